@@ -1,5 +1,6 @@
 //! Infrastructure to serialize and recover data
 
+pub mod delta;
 pub mod dynamic;
 pub mod serde;
 
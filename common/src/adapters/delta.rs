@@ -0,0 +1,173 @@
+//! Delta encoding for serialized values, so a small change to a large component doesn't resend
+//! the whole thing
+//!
+//! Deltas are taken between the serialized bytes rather than the fields, which covers every
+//! replicated type including ones that can't be reflected like `Thrusters`. bincode lays out the
+//! unchanged fields of a value identically, so changing one field only touches a few bytes.
+
+use std::sync::Arc;
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use super::{options, BackingType};
+
+/// Values smaller than this are always sent whole
+pub const MIN_DELTA_SIZE: usize = 16;
+
+/// Unchanged bytes between two changes are sent anyway when there are fewer than this many, each
+/// run costs a couple bytes of overhead
+const MERGE_GAP: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ByteDelta {
+    /// The length of the new value
+    len: u32,
+    /// The changed bytes, each run starts the given number of bytes after the previous one ended
+    runs: Vec<(u32, Vec<u8>)>,
+}
+
+impl ByteDelta {
+    /// Describes `new` as changes to `old`, `None` when that wouldn't be smaller than `new`
+    pub fn encode(old: &[u8], new: &[u8]) -> Option<Self> {
+        let mut runs = Vec::new();
+        let mut last_end = 0;
+        let mut idx = 0;
+
+        while idx < new.len() {
+            if old.get(idx) == Some(&new[idx]) {
+                idx += 1;
+                continue;
+            }
+
+            let start = idx;
+            let mut end = idx;
+            let mut unchanged = 0;
+
+            while idx < new.len() && unchanged < MERGE_GAP {
+                if old.get(idx) == Some(&new[idx]) {
+                    unchanged += 1;
+                } else {
+                    unchanged = 0;
+                    end = idx + 1;
+                }
+
+                idx += 1;
+            }
+
+            runs.push(((start - last_end) as u32, new[start..end].to_vec()));
+            last_end = end;
+            idx = end;
+        }
+
+        let delta = Self {
+            len: new.len() as u32,
+            runs,
+        };
+
+        let size = options().serialized_size(&delta).ok()?;
+        (size < new.len() as u64).then_some(delta)
+    }
+
+    /// Rebuilds the new value, `None` if the delta doesn't fit `old`
+    pub fn apply(&self, old: &[u8]) -> Option<BackingType> {
+        let mut new = old.to_vec();
+        new.resize(self.len as usize, 0);
+
+        let mut idx = 0;
+        for (skip, bytes) in &self.runs {
+            idx += *skip as usize;
+            new.get_mut(idx..idx + bytes.len())?.copy_from_slice(bytes);
+            idx += bytes.len();
+        }
+
+        Some(Arc::new(new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ptr::Ptr;
+    use motor_math::{
+        glam::ThrusterGlam, solve::reverse::Axis, x3d::X3dMotorId, Direction, FloatType,
+        MotorConfig,
+    };
+    use nalgebra::Vector3;
+    use stable_hashmap::StableHashMap;
+
+    use crate::{
+        adapters::serde::SerdeAdapter,
+        components::{MovementAxisMaximums, Thrusters},
+        types::units::Newtons,
+    };
+
+    use super::*;
+
+    fn serialize<T: SerdeAdapter>(value: &T) -> BackingType {
+        // SAFETY: The pointer is to a `T`
+        unsafe { T::serialize(Ptr::from(value)).unwrap() }
+    }
+
+    fn assert_round_trip(old: &[u8], new: &[u8]) -> usize {
+        let delta = ByteDelta::encode(old, new).expect("Delta should be smaller");
+        assert_eq!(delta.apply(old).unwrap().as_slice(), new);
+
+        options().serialized_size(&delta).unwrap() as usize
+    }
+
+    #[test]
+    fn thruster_direction() {
+        let thruster = ThrusterGlam {
+            position: glam::vec3a(0.2, 0.3, 0.1),
+            orientation: glam::vec3a(-0.5, 0.8, -0.3),
+            direction: Direction::Clockwise,
+        };
+        let config = MotorConfig::<X3dMotorId, FloatType>::new(thruster.into(), Vector3::zeros())
+            .erase_lossy();
+
+        let old = serialize(&Thrusters(config.clone()));
+        let mut new = config;
+        new.motors[3].1.direction = Direction::CounterClockwise;
+        let new = serialize(&Thrusters(new));
+
+        let size = assert_round_trip(&old, &new);
+        assert!(size * 10 < new.len(), "{size} bytes of {}", new.len());
+    }
+
+    #[test]
+    fn axis_maximum() {
+        let maximums = [
+            Axis::X,
+            Axis::Y,
+            Axis::Z,
+            Axis::XRot,
+            Axis::YRot,
+            Axis::ZRot,
+        ]
+        .into_iter()
+        .map(|axis| (axis, Newtons(40.0)))
+        .collect::<StableHashMap<_, _>>();
+
+        let old = serialize(&MovementAxisMaximums(maximums.clone()));
+        let mut new = maximums;
+        new.insert(Axis::Z, Newtons(25.0));
+        let new = serialize(&MovementAxisMaximums(new));
+
+        let size = assert_round_trip(&old, &new);
+        assert!(size * 2 < new.len(), "{size} bytes of {}", new.len());
+    }
+
+    #[test]
+    fn length_changes() {
+        let old = (0..100).collect::<Vec<u8>>();
+
+        let mut longer = old.clone();
+        longer.extend([1, 2, 3]);
+        assert_round_trip(&old, &longer);
+
+        let shorter = &old[..90];
+        assert_round_trip(&old, shorter);
+
+        assert!(ByteDelta::encode(&old, &[7; 100]).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+//! Attitude math shared by the robot and the surfaces
+//!
+//! The world frame is +Z up. The robot's body frame has +Y forward, +X to the right and +Z up,
+//! so yaw is the angle of the body's forward axis counter clockwise about world +Z.
+
+use std::f32::consts::{PI, TAU};
+
+use glam::{vec3a, Quat, Vec3A};
+
+/// Yaw of `orientation` in radians, in the range `(-PI, PI]`
+///
+/// Taken from the direction the body's forward axis points when projected onto the horizontal
+/// plane, so it stays correct when the robot is inverted. Falls back to the right axis when
+/// the robot points straight up or down.
+pub fn yaw(orientation: Quat) -> f32 {
+    let forward = orientation * Vec3A::Y;
+    let right = orientation * Vec3A::X;
+
+    if forward.truncate().length_squared() >= right.truncate().length_squared() {
+        (-forward.x).atan2(forward.y)
+    } else {
+        right.y.atan2(right.x)
+    }
+}
+
+/// The rotation about world +Z with the same yaw as `orientation`, with pitch and roll removed
+pub fn yaw_rotation(orientation: Quat) -> Quat {
+    Quat::from_rotation_z(yaw(orientation))
+}
+
+pub fn body_to_world(orientation: Quat, body: Vec3A) -> Vec3A {
+    orientation * body
+}
+
+pub fn world_to_body(orientation: Quat, world: Vec3A) -> Vec3A {
+    orientation.inverse() * world
+}
+
+/// Rotates a vector given relative to the robot's heading, where +Z is always world up, into the
+/// body frame. Lets the pilot's heave stay vertical while the robot is pitched or rolled
+pub fn heading_to_body(orientation: Quat, heading_relative: Vec3A) -> Vec3A {
+    world_to_body(orientation, yaw_rotation(orientation) * heading_relative)
+}
+
+/// Wraps an angle in radians into the range `(-PI, PI]`
+pub fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = angle.rem_euclid(TAU);
+
+    if wrapped > PI {
+        wrapped - TAU
+    } else {
+        wrapped
+    }
+}
+
+/// Signed shortest turn in degrees from the heading `from` to the heading `to`, positive when
+/// `to` is clockwise of `from`
+pub fn heading_error(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Angle in radians of the part of `rotation` that twists about `axis`, in the range `(-PI, PI]`
+///
+/// This is the twist half of a swing-twist decomposition, `axis` must be normalized.
+pub fn twist_angle(rotation: Quat, axis: Vec3A) -> f32 {
+    let rotation_axis = vec3a(rotation.x, rotation.y, rotation.z);
+
+    let sign = rotation_axis.dot(axis).signum();
+    let projected = rotation_axis.project_onto(axis);
+    let twist =
+        Quat::from_xyzw(projected.x, projected.y, projected.z, rotation.w).normalize() * sign;
+
+    wrap_angle(twist.w.acos() * 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::EulerRot;
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < EPSILON, "{a} != {b}");
+    }
+
+    #[test]
+    fn yaw_ignores_pitch_and_roll() {
+        let yaw_angle = 40f32.to_radians();
+        let orientation = Quat::from_euler(
+            EulerRot::ZXY,
+            yaw_angle,
+            25f32.to_radians(),
+            -30f32.to_radians(),
+        );
+
+        assert_close(yaw(orientation), yaw_angle);
+        assert!(
+            yaw_rotation(orientation).angle_between(Quat::from_rotation_z(yaw_angle)) < EPSILON
+        );
+    }
+
+    #[test]
+    fn yaw_while_inverted() {
+        let yaw_angle = -120f32.to_radians();
+        let inverted = Quat::from_rotation_z(yaw_angle) * Quat::from_rotation_y(PI);
+
+        assert_close(yaw(inverted), yaw_angle);
+    }
+
+    #[test]
+    fn yaw_while_pointing_down() {
+        let yaw_angle = 70f32.to_radians();
+        let pointing_down = Quat::from_rotation_z(yaw_angle) * Quat::from_rotation_x(-PI / 2.0);
+
+        assert_close(yaw(pointing_down), yaw_angle);
+    }
+
+    #[test]
+    fn heading_relative_heave_stays_vertical() {
+        let orientation =
+            Quat::from_euler(EulerRot::ZXY, 1.0, 20f32.to_radians(), 10f32.to_radians());
+
+        let body = heading_to_body(orientation, Vec3A::Z);
+        assert!((body_to_world(orientation, body) - Vec3A::Z).length() < EPSILON);
+
+        let level = Quat::from_rotation_z(1.0);
+        assert!((heading_to_body(level, Vec3A::Y) - Vec3A::Y).length() < EPSILON);
+    }
+
+    #[test]
+    fn angles_wrap() {
+        assert_close(wrap_angle(3.0 * PI / 2.0), -PI / 2.0);
+        assert_close(wrap_angle(-3.0 * PI / 2.0), PI / 2.0);
+        assert_close(wrap_angle(PI), PI);
+
+        assert_close(heading_error(350.0, 10.0), 20.0);
+        assert_close(heading_error(10.0, 350.0), -20.0);
+        assert_close(heading_error(90.0, 90.0), 0.0);
+    }
+
+    #[test]
+    fn twist_about_axis() {
+        let rotation = Quat::from_rotation_z(0.5) * Quat::from_rotation_x(0.2);
+
+        assert_close(twist_angle(Quat::from_rotation_z(0.5), Vec3A::Z), 0.5);
+        assert_close(twist_angle(Quat::from_rotation_z(-2.5), Vec3A::Z), -2.5);
+        assert!((twist_angle(rotation, Vec3A::Z) - 0.5).abs() < 0.05);
+    }
+}
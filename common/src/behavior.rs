@@ -0,0 +1,351 @@
+//! Behavior trees for autonomy, shared by every app
+//!
+//! A tree is a `BehaviorTree` and a `BehaviorStatus` on an entity and is run by the app that
+//! spawned it. The composite nodes, `Hold`'s timing and `WaitFor` are ticked here, everything else
+//! an action does is up to the app: it watches `BehaviorStatus::running_actions` after
+//! `TickBehaviors` and reports back with `BehaviorStatus::finish`. Both components are replicated
+//! so trees spawned with `Replicate` can be watched from any app
+
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{
+    app::{App, Plugin, Update},
+    prelude::{IntoSystemConfigs, Query, Ref, Res, Resource, SystemSet, Without},
+    time::Time,
+};
+use tracing::warn;
+
+use crate::{
+    components::{BehaviorStatus, BehaviorTree},
+    ecs_sync::ForignOwned,
+    types::behavior::{BehaviorAction, NodeKind, NodeState},
+};
+
+pub struct BehaviorPlugin;
+
+impl Plugin for BehaviorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BehaviorConditions>()
+            .add_systems(Update, tick_behaviors.in_set(TickBehaviors));
+    }
+}
+
+/// Systems carrying out actions should run after this
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TickBehaviors;
+
+/// Named flags `WaitFor` actions wait on, set by whatever knows about them
+#[derive(Resource, Debug, Clone, Default)]
+pub struct BehaviorConditions(pub HashMap<String, bool>);
+
+impl BehaviorConditions {
+    pub fn set(&mut self, name: impl Into<String>, value: bool) {
+        self.0.insert(name.into(), value);
+    }
+
+    /// Unknown conditions are false
+    pub fn get(&self, name: &str) -> bool {
+        self.0.get(name).copied().unwrap_or(false)
+    }
+}
+
+fn tick_behaviors(
+    time: Res<Time>,
+    conditions: Res<BehaviorConditions>,
+    mut trees: Query<(Ref<BehaviorTree>, &mut BehaviorStatus), Without<ForignOwned>>,
+) {
+    let now = time.elapsed();
+
+    for (tree, mut status) in &mut trees {
+        // New and edited trees start over, so does inserting an empty status
+        if tree.is_changed() || status.0.len() != tree.nodes.len() {
+            *status = BehaviorStatus::new(&tree);
+
+            if let Err(err) = tree.validate() {
+                warn!("Behavior tree {} is invalid: {err:?}", tree.name);
+
+                if let Some(root) = status.0.get_mut(tree.root) {
+                    root.state = NodeState::Failed(format!("Invalid tree: {err}"));
+                }
+            }
+        }
+
+        if status.is_done(&tree) || tree.validate().is_err() {
+            continue;
+        }
+
+        // Only write when something changed so the status isn't replicated every frame
+        let mut new_status = status.clone();
+        tick(&tree, &mut new_status, tree.root, now, &conditions);
+        if new_status != *status {
+            *status = new_status;
+        }
+    }
+}
+
+/// Advances `node` and everything under it, the tree must be valid
+pub fn tick(
+    tree: &BehaviorTree,
+    status: &mut BehaviorStatus,
+    node: usize,
+    now: Duration,
+    conditions: &BehaviorConditions,
+) -> NodeState {
+    let current = status.0[node].state.clone();
+    if current.is_done() {
+        return current;
+    }
+    if current == NodeState::Idle {
+        let node = &mut status.0[node];
+        node.state = NodeState::Running;
+        node.started = now;
+        node.iterations = 0;
+    }
+
+    let elapsed = now.saturating_sub(status.0[node].started);
+    let children = &tree.nodes[node].children;
+
+    let state = match &tree.nodes[node].kind {
+        NodeKind::Sequence => {
+            let mut state = NodeState::Succeeded;
+            for &child in children {
+                let child_state = tick(tree, status, child, now, conditions);
+                if child_state != NodeState::Succeeded {
+                    state = child_state;
+                    break;
+                }
+            }
+
+            state
+        }
+        NodeKind::Fallback => {
+            let mut state = NodeState::Failed("Every option failed".to_owned());
+            for &child in children {
+                let child_state = tick(tree, status, child, now, conditions);
+                if !matches!(child_state, NodeState::Failed(_)) {
+                    state = child_state;
+                    break;
+                }
+            }
+
+            state
+        }
+        NodeKind::Parallel { required } => {
+            let states = children
+                .iter()
+                .map(|&child| tick(tree, status, child, now, conditions))
+                .collect::<Vec<_>>();
+
+            let succeeded = states
+                .iter()
+                .filter(|it| **it == NodeState::Succeeded)
+                .count();
+            let failed = states
+                .iter()
+                .filter(|it| matches!(it, NodeState::Failed(_)))
+                .count();
+
+            if succeeded >= *required {
+                NodeState::Succeeded
+            } else if children.len() - failed < *required {
+                states
+                    .into_iter()
+                    .find(|it| matches!(it, NodeState::Failed(_)))
+                    .unwrap_or_else(|| NodeState::Failed("Too many children failed".to_owned()))
+            } else {
+                NodeState::Running
+            }
+        }
+        NodeKind::Repeat { count } => {
+            let child = children[0];
+
+            match tick(tree, status, child, now, conditions) {
+                NodeState::Succeeded => {
+                    status.0[node].iterations += 1;
+
+                    if count.is_some_and(|it| status.0[node].iterations >= it) {
+                        NodeState::Succeeded
+                    } else {
+                        reset(tree, status, child);
+                        NodeState::Running
+                    }
+                }
+                state => state,
+            }
+        }
+        NodeKind::Invert => match tick(tree, status, children[0], now, conditions) {
+            NodeState::Succeeded => NodeState::Failed("Inverted success".to_owned()),
+            NodeState::Failed(_) => NodeState::Succeeded,
+            state => state,
+        },
+        NodeKind::Timeout { seconds } => {
+            if elapsed.as_secs_f32() >= *seconds {
+                NodeState::Failed(format!("Timed out after {seconds}s"))
+            } else {
+                tick(tree, status, children[0], now, conditions)
+            }
+        }
+        NodeKind::Action(BehaviorAction::Hold { seconds }) => {
+            if elapsed.as_secs_f32() >= *seconds {
+                NodeState::Succeeded
+            } else {
+                NodeState::Running
+            }
+        }
+        NodeKind::Action(BehaviorAction::WaitFor { condition }) => {
+            if conditions.get(condition) {
+                NodeState::Succeeded
+            } else {
+                NodeState::Running
+            }
+        }
+        // Finished by the app through `BehaviorStatus::finish`
+        NodeKind::Action(_) => NodeState::Running,
+    };
+
+    // Stop whatever is still running below a node that is done
+    if state.is_done() {
+        for &child in children {
+            halt(tree, status, child);
+        }
+    }
+
+    status.0[node].state = state.clone();
+    state
+}
+
+/// Puts a node and everything under it back to idle so it runs again from the start
+fn reset(tree: &BehaviorTree, status: &mut BehaviorStatus, node: usize) {
+    status.0[node] = Default::default();
+
+    for &child in &tree.nodes[node].children {
+        reset(tree, status, child);
+    }
+}
+
+/// Puts running nodes back to idle, finished ones keep their result to be looked at
+fn halt(tree: &BehaviorTree, status: &mut BehaviorStatus, node: usize) {
+    if status.0[node].state == NodeState::Running {
+        status.0[node].state = NodeState::Idle;
+    }
+
+    for &child in &tree.nodes[node].children {
+        halt(tree, status, child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hold(tree: &mut BehaviorTree, seconds: f32) -> usize {
+        tree.action("Hold", BehaviorAction::Hold { seconds })
+    }
+
+    fn run_pipeline(tree: &mut BehaviorTree) -> usize {
+        tree.action(
+            "Pipeline",
+            BehaviorAction::RunPipeline {
+                pipeline: "Squares".to_owned(),
+                camera: "Front".to_owned(),
+                seconds: None,
+            },
+        )
+    }
+
+    fn tick_at(tree: &BehaviorTree, status: &mut BehaviorStatus, seconds: f32) -> NodeState {
+        let conditions = BehaviorConditions::default();
+        tick(
+            tree,
+            status,
+            tree.root,
+            Duration::from_secs_f32(seconds),
+            &conditions,
+        )
+    }
+
+    #[test]
+    fn sequence_runs_in_order() {
+        let mut tree = BehaviorTree::new("Test");
+        let first = hold(&mut tree, 1.0);
+        let second = hold(&mut tree, 1.0);
+        tree.root = tree.add("Sequence", NodeKind::Sequence, vec![first, second]);
+        tree.validate().unwrap();
+
+        let mut status = BehaviorStatus::new(&tree);
+        assert_eq!(tick_at(&tree, &mut status, 0.0), NodeState::Running);
+        assert_eq!(*status.state(second), NodeState::Idle);
+
+        assert_eq!(tick_at(&tree, &mut status, 1.0), NodeState::Running);
+        assert_eq!(*status.state(first), NodeState::Succeeded);
+        assert_eq!(*status.state(second), NodeState::Running);
+
+        assert_eq!(tick_at(&tree, &mut status, 2.0), NodeState::Succeeded);
+    }
+
+    #[test]
+    fn app_actions_finish_through_status() {
+        let mut tree = BehaviorTree::new("Test");
+        let pipeline = run_pipeline(&mut tree);
+        let fallback = hold(&mut tree, 0.0);
+        tree.root = tree.add("Fallback", NodeKind::Fallback, vec![pipeline, fallback]);
+
+        let mut status = BehaviorStatus::new(&tree);
+        tick_at(&tree, &mut status, 0.0);
+        assert_eq!(
+            status
+                .running_actions(&tree)
+                .map(|it| it.0)
+                .collect::<Vec<_>>(),
+            vec![pipeline]
+        );
+
+        status.finish(pipeline, Err("No camera".to_owned()));
+        assert_eq!(tick_at(&tree, &mut status, 0.5), NodeState::Succeeded);
+        assert_eq!(*status.state(fallback), NodeState::Succeeded);
+    }
+
+    #[test]
+    fn repeat_and_timeout() {
+        let mut tree = BehaviorTree::new("Test");
+        let step = hold(&mut tree, 1.0);
+        let repeat = tree.add("Repeat", NodeKind::Repeat { count: None }, vec![step]);
+        tree.root = tree.add("Timeout", NodeKind::Timeout { seconds: 3.5 }, vec![repeat]);
+
+        let mut status = BehaviorStatus::new(&tree);
+        for second in 0..=3 {
+            assert_eq!(
+                tick_at(&tree, &mut status, second as f32),
+                NodeState::Running
+            );
+        }
+        // The child starts over on the tick after it succeeds
+        assert_eq!(status.0[repeat].iterations, 2);
+
+        assert!(matches!(
+            tick_at(&tree, &mut status, 4.0),
+            NodeState::Failed(_)
+        ));
+        // The repeat was cut off rather than finishing
+        assert_eq!(*status.state(repeat), NodeState::Idle);
+    }
+
+    #[test]
+    fn validate_rejects_shared_nodes() {
+        let mut tree = BehaviorTree::new("Test");
+        let step = hold(&mut tree, 1.0);
+        tree.root = tree.add("Sequence", NodeKind::Sequence, vec![step, step]);
+
+        assert!(tree.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_bad_durations() {
+        for seconds in [-1.0, f32::NAN, f32::INFINITY] {
+            let mut tree = BehaviorTree::new("Test");
+            tree.root = hold(&mut tree, seconds);
+
+            assert!(tree.validate().is_err());
+        }
+    }
+}
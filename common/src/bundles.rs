@@ -1,14 +1,14 @@
 use bevy::{core::Name, ecs::bundle::Bundle, transform::components::Transform};
 
 use crate::components::{
-    AccelerometerMeasurement, ActualForce, ActualMovement, Armed, CameraCalibration,
-    CameraDefinition, CameraInputRotation, CenterOfMass, CurrentDraw, DepthMeasurement,
-    GenericMotorId, GyroMeasurement, Leak, MagnetometerMeasurement, MeasuredVoltage,
-    MotorContributionMode, MotorRawSignalRange, MotorSignal, MotorSignalType, MovementAxisMaximums,
-    MovementContribution, MovementCurrentCap, Orientation, Robot, RobotId, SystemCores,
-    SystemCpuTotal, SystemDisks, SystemLoadAverage, SystemMemory, SystemNetworks, SystemOs,
-    SystemProcesses, SystemTemperatures, SystemUptime, TargetForce, TargetMovement,
-    TempertureMeasurement, ThrusterDefinition, Thrusters,
+    AccelerometerMeasurement, ActualForce, ActualMovement, ActuatorIdentity, Armed,
+    CameraCalibration, CameraCapabilities, CameraDefinition, CameraInputRotation, CameraStream,
+    CenterOfMass, CurrentDraw, DepthMeasurement, GenericMotorId, GyroMeasurement, Leak,
+    MagnetometerMeasurement, MeasuredVoltage, MotorContributionMode, MotorRawSignalRange,
+    MotorSignal, MotorSignalType, MovementAxisMaximums, MovementContribution, MovementCurrentCap,
+    Orientation, Robot, RobotId, SystemCores, SystemCpuTotal, SystemDisks, SystemLoadAverage,
+    SystemMemory, SystemNetworks, SystemOs, SystemProcesses, SystemTemperatures, SystemUptime,
+    TargetForce, TargetMovement, TempertureMeasurement, ThrusterDefinition, Thrusters,
 };
 
 #[derive(Bundle, PartialEq)]
@@ -86,6 +86,8 @@ pub struct RobotPowerBundle {
 pub struct CameraBundle {
     pub name: Name,
     pub camera: CameraDefinition,
+    pub stream: CameraStream,
+    pub capabilities: CameraCapabilities,
     pub input_rotation: CameraInputRotation,
     // FIXME: This should be optional
     pub calib: CameraCalibration,
@@ -117,6 +119,7 @@ pub struct MotorBundle {
 // #[deprecated]
 pub struct ActuatorBundle {
     pub name: Name,
+    pub identity: ActuatorIdentity,
     pub channel: GenericMotorId,
     pub signal: MotorSignal,
     pub signal_type: MotorSignalType,
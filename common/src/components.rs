@@ -2,105 +2,171 @@ use crate::ecs_sync::AppReplicateExt;
 use bevy::prelude::*;
 
 macro_rules! components {
-    ($($mod:ident :: { $($name:ident),* $(,)? }),* $(,)?) => {
-        pub fn register_components(app: &mut App) {
-            $(
+    ($($group:ident => { $($mod:ident :: { $($name:ident),* $(,)? }),* $(,)? }),* $(,)?) => {
+        $(
+            pub fn $group(app: &mut App) {
                 $(
-                    app.replicate::<$name>();
+                    $(
+                        app.replicate::<$name>();
+                    )*
                 )*
-            )*
-        }
+            }
 
-        $(
-            mod $mod;
             $(
-                pub use $mod::$name;
+                mod $mod;
+                $(
+                    pub use $mod::$name;
+                )*
             )*
         )*
     }
 }
 
 components! {
-    core::{
-        Singleton,
-        Robot,
-        Surface,
-        Armed,
-        RobotId,
-    },
+    register_core_components => {
+        core::{
+            Singleton,
+            Robot,
+            Surface,
+            SurfaceRole,
+            Stations,
+            Armed,
+            RobotId,
+        },
 
-    control::{
-        DepthTarget,
-        OrientationTarget,
+        params::{
+            Parameters,
+        },
     },
 
-    motor::{
-        MotorCameraReference,
-        Motors,
-        MotorSignal,
-        MotorSignalType,
-        MotorRawSignalRange,
-        MotorContributionMode,
-        MotorTargets,
-        MotorSlewRate,
-        MotorContribution,
-        GenericMotorId,
-    },
+    register_actuator_components => {
+        motor::{
+            MotorCameraReference,
+            Motors,
+            MotorSignal,
+            MotorSignalType,
+            MotorRawSignalRange,
+            MotorContributionMode,
+            MotorTargets,
+            MotorSlewRate,
+            MotorContribution,
+            MotorMixing,
+            ServoPresets,
+            ActiveServoPreset,
+            GenericMotorId,
+            ActuatorIdentity,
+        },
 
-    pid::{
-        PidConfig,
-        PidResult,
-        PidController,
-    },
+        maintenance::{
+            Maintenance,
+        },
+
+        thruster::{
+            // Movement Api
+            TargetMovement,
+            ActualMovement,
+            MovementContribution,
+            MovementAxisMaximums,
+            MovementCurrentCap,
+            DisableMovementApi,
+            CenterOfMass,
+
+            // Thruster Api
+            TargetForce,
+            ActualForce,
+            ThrusterDefinition,
+            Thrusters,
+            ThrustContribution,
+            JerkLimit,
+            ThrusterDiagnostics,
+        },
+
+        pid::{
+            PidConfig,
+            PidResult,
+            PidController,
+            ActuationLatency,
+            LatencyIdentification,
+        },
 
-    power::{
-        MeasuredVoltage,
-        CurrentDraw,
+        power::{
+            MeasuredVoltage,
+            CurrentDraw,
+            BatteryStatus,
+            BatteryFault,
+            ThermalDerate,
+        },
     },
 
-    sensor::{
-        Orientation,
-        GyroMeasurement,
-        AccelerometerMeasurement,
-        MagnetometerMeasurement,
-        DepthMeasurement,
-        DepthSettings,
-        TempertureMeasurement,
-        Leak,
-        CameraDefinition,
-        CameraInputRotation,
-        CameraCalibration,
+    register_sensor_components => {
+        sensor::{
+            Orientation,
+            Heading,
+            LevelTrim,
+            TetherTurns,
+            GyroMeasurement,
+            AccelerometerMeasurement,
+            MagnetometerMeasurement,
+            DepthMeasurement,
+            DepthSettings,
+            DepthSensorHealth,
+            WaterTemperature,
+            Salinity,
+            Altimeter,
+            VisualOdometry,
+            PositionEstimate,
+            TransectLine,
+            TempertureMeasurement,
+            Leak,
+            CameraDefinition,
+        },
+
+        system_monitor::{
+            SystemProcesses,
+            SystemLoadAverage,
+            SystemNetworks,
+            SystemCpuTotal,
+            SystemCores,
+            SystemMemory,
+            SystemTemperatures,
+            SystemDisks,
+            SystemUptime,
+            SystemOs,
+        },
     },
 
-    system_monitor::{
-        SystemProcesses,
-        SystemLoadAverage,
-        SystemNetworks,
-        SystemCpuTotal,
-        SystemCores,
-        SystemMemory,
-        SystemTemperatures,
-        SystemDisks,
-        SystemUptime,
-        SystemOs,
+    register_video_components => {
+        video::{
+            CameraStream,
+            CameraCapabilities,
+            CameraControls,
+            CameraInputRotation,
+            CameraCalibration,
+        },
     },
 
-    thruster::{
-        // Movement Api
-        TargetMovement,
-        ActualMovement,
-        MovementContribution,
-        MovementAxisMaximums,
-        MovementCurrentCap,
-        DisableMovementApi,
-        CenterOfMass,
-
-        // Thruster Api
-        TargetForce,
-        ActualForce,
-        ThrusterDefinition,
-        Thrusters,
-        ThrustContribution,
-        JerkLimit,
+    register_autonomy_components => {
+        behavior::{
+            BehaviorTree,
+            BehaviorStatus,
+        },
+
+        control::{
+            DepthTarget,
+            OrientationTarget,
+            HeadingTarget,
+            BottomLock,
+            BottomLockStatus,
+            Geofence,
+            PilotModes,
+        },
     },
 }
+
+// Types used by the components above that aren't replicated on their own
+pub use self::core::{Capabilities, Station};
+pub use control::{GeofenceBreach, LockState};
+pub use maintenance::{MaintenanceFinding, MaintenanceProgress, MaintenanceRoutine};
+pub use motor::{ActuatorChannelType, ActuatorKind, ServoPreset};
+pub use params::{ParamEntry, ParamValue};
+pub use thruster::ThrusterDiagnostic;
@@ -0,0 +1,162 @@
+use anyhow::bail;
+use bevy::{
+    ecs::component::Component,
+    reflect::{Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::adapters::serde::ReflectSerdeAdapter;
+use crate::types::behavior::{BehaviorAction, BehaviorNode, NodeKind, NodeState, NodeStatus};
+
+/// A behavior for the app that spawned it to run, see `behavior::BehaviorPlugin`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BehaviorTree {
+    pub name: String,
+    pub nodes: Vec<BehaviorNode>,
+    pub root: usize,
+}
+
+impl BehaviorTree {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            nodes: Vec::new(),
+            root: 0,
+        }
+    }
+
+    /// Adds a node and returns its index, children have to be added first
+    pub fn add(&mut self, name: impl Into<String>, kind: NodeKind, children: Vec<usize>) -> usize {
+        self.nodes.push(BehaviorNode {
+            name: name.into(),
+            kind,
+            children,
+        });
+
+        self.nodes.len() - 1
+    }
+
+    pub fn action(&mut self, name: impl Into<String>, action: BehaviorAction) -> usize {
+        self.add(name, NodeKind::Action(action), Vec::new())
+    }
+
+    /// Checks that the nodes form a tree under `root`, that every node has as many children as its
+    /// kind needs and that every duration is a valid number of seconds
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.root >= self.nodes.len() {
+            bail!("Root {} is not a node", self.root);
+        }
+
+        let mut parent = vec![None; self.nodes.len()];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            for &child in &node.children {
+                if child >= self.nodes.len() {
+                    bail!("{} has a child that is not a node", node.name);
+                }
+                if child == self.root || parent[child].is_some() {
+                    bail!("{} is used more than once", self.nodes[child].name);
+                }
+                parent[child] = Some(idx);
+            }
+
+            let children = node.children.len();
+            match node.kind {
+                NodeKind::Sequence | NodeKind::Fallback if children == 0 => {
+                    bail!("{} has no children", node.name);
+                }
+                NodeKind::Parallel { required } if required > children => {
+                    bail!("{} requires more children than it has", node.name);
+                }
+                NodeKind::Repeat { .. } | NodeKind::Invert | NodeKind::Timeout { .. }
+                    if children != 1 =>
+                {
+                    bail!("{} needs exactly one child", node.name);
+                }
+                NodeKind::Action(_) if children != 0 => {
+                    bail!("{} is an action and can't have children", node.name);
+                }
+                _ => {}
+            }
+
+            let seconds = match &node.kind {
+                NodeKind::Timeout { seconds }
+                | NodeKind::Action(BehaviorAction::Hold { seconds }) => Some(*seconds),
+                NodeKind::Action(BehaviorAction::RunPipeline { seconds, .. }) => *seconds,
+                _ => None,
+            };
+            if seconds.is_some_and(|it| !it.is_finite() || it < 0.0) {
+                bail!("{} has an invalid duration", node.name);
+            }
+        }
+
+        // Every node has one parent at most, so a node that can't reach the root is in a cycle
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let mut current = idx;
+            for _ in 0..self.nodes.len() {
+                match parent[current] {
+                    Some(next) => current = next,
+                    None => break,
+                }
+            }
+            if current != self.root {
+                bail!("{} is not reachable from the root", node.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// State of every node of the `BehaviorTree` on the same entity, the tree runs while this is
+/// present and its root isn't done
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BehaviorStatus(pub Vec<NodeStatus>);
+
+impl BehaviorStatus {
+    pub fn new(tree: &BehaviorTree) -> Self {
+        Self(vec![NodeStatus::default(); tree.nodes.len()])
+    }
+
+    pub fn state(&self, node: usize) -> &NodeState {
+        self.0
+            .get(node)
+            .map(|it| &it.state)
+            .unwrap_or(&NodeState::Idle)
+    }
+
+    pub fn is_done(&self, tree: &BehaviorTree) -> bool {
+        self.state(tree.root).is_done()
+    }
+
+    /// Reports the result of an action the app carries out, ignored unless it is running
+    pub fn finish(&mut self, node: usize, result: Result<(), String>) {
+        let Some(status) = self.0.get_mut(node) else {
+            return;
+        };
+        if status.state != NodeState::Running {
+            return;
+        }
+
+        status.state = match result {
+            Ok(()) => NodeState::Succeeded,
+            Err(reason) => NodeState::Failed(reason),
+        };
+    }
+
+    /// Actions that are currently running along with their index
+    pub fn running_actions<'a>(
+        &'a self,
+        tree: &'a BehaviorTree,
+    ) -> impl Iterator<Item = (usize, &'a BehaviorAction)> + 'a {
+        tree.nodes
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *self.state(*idx) == NodeState::Running)
+            .filter_map(|(idx, node)| match &node.kind {
+                NodeKind::Action(action) => Some((idx, action)),
+                _ => None,
+            })
+    }
+}
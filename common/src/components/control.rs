@@ -2,11 +2,14 @@ use bevy::{
     ecs::component::Component,
     reflect::{Reflect, ReflectDeserialize, ReflectSerialize},
 };
-use glam::Quat;
+use glam::{Quat, Vec3A};
 use serde::{Deserialize, Serialize};
 
 use crate::adapters::serde::ReflectSerdeAdapter;
-use crate::types::units::Meters;
+use crate::types::{
+    pilot::PilotMode,
+    units::{Degrees, Meters},
+};
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
@@ -16,3 +19,143 @@ pub struct DepthTarget(pub Meters);
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct OrientationTarget(pub Quat);
+
+/// Compass heading the robot holds by yawing alone, clockwise from north. Ignored while an
+/// `OrientationTarget` is set, since that already holds the heading
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct HeadingTarget(pub Degrees);
+
+/// Hovers over the bottom, holding the altitude from the altimeter, the position from the
+/// `PositionEstimate` and the heading. A hold is `None` when its sensor had no reading when the
+/// lock was engaged
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BottomLock {
+    pub altitude: Option<Meters>,
+    pub position: Option<Vec3A>,
+    pub heading: Degrees,
+}
+
+/// How each hold of the `BottomLock` is doing, published by the robot while it is engaged
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct BottomLockStatus {
+    pub altitude: LockState,
+    pub position: LockState,
+    pub heading: LockState,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockState {
+    /// Not part of the lock
+    #[default]
+    Off,
+    Active,
+    /// The sensor behind the hold dropped out. Altitude falls back to holding depth, position
+    /// stops correcting until the sensor recovers
+    Degraded,
+}
+
+impl LockState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LockState::Off => "Off",
+            LockState::Active => "Active",
+            LockState::Degraded => "Degraded",
+        }
+    }
+}
+
+/// The robot's hard depth and distance limits, enforced by the robot regardless of pilot input
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Geofence {
+    pub max_depth: Option<Meters>,
+    /// Horizontal distance from home, only enforced while a `PositionEstimate` is available
+    pub max_distance: Option<Meters>,
+    /// Where the robot was armed
+    pub home: Option<Vec3A>,
+    pub breach: Option<GeofenceBreach>,
+    /// Has to be echoed back in `GeofenceOverrideStep::Confirm` to override the limits
+    pub override_challenge: Option<u32>,
+    /// The limits are not enforced until the robot is disarmed
+    pub overridden: bool,
+}
+
+impl Geofence {
+    /// Pilot input is ignored while the robot brings itself back inside the limits
+    pub fn enforcing(&self) -> bool {
+        self.breach.is_some() && !self.overridden
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeofenceBreach {
+    /// Deeper than `max_depth`, the robot ascends to just above it
+    Depth,
+    /// Further than `max_distance` from home, the robot surfaces
+    Distance,
+}
+
+impl GeofenceBreach {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeofenceBreach::Depth => "Depth Limit",
+            GeofenceBreach::Distance => "Distance Limit",
+        }
+    }
+}
+
+/// Named control profiles the pilot can select or cycle through, lives on the surface entity
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PilotModes(pub Vec<PilotMode>);
+
+impl PilotModes {
+    pub fn get(&self, name: &str) -> Option<&PilotMode> {
+        self.0.iter().find(|it| it.name == name)
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &PilotMode> {
+        self.0.iter().filter(|it| it.enabled)
+    }
+
+    /// The enabled mode following `name`, wrapping around to the first enabled mode
+    pub fn next_after(&self, name: &str) -> Option<&PilotMode> {
+        let enabled = self.enabled().collect::<Vec<_>>();
+        let idx = enabled.iter().position(|it| it.name == name);
+
+        match idx {
+            Some(idx) => enabled.get((idx + 1) % enabled.len()).copied(),
+            None => enabled.first().copied(),
+        }
+    }
+
+    /// The enabled mode before `name`, wrapping around to the last enabled mode
+    pub fn previous_before(&self, name: &str) -> Option<&PilotMode> {
+        let enabled = self.enabled().collect::<Vec<_>>();
+        let idx = enabled.iter().position(|it| it.name == name);
+
+        match idx {
+            Some(idx) => enabled.get((idx + enabled.len() - 1) % enabled.len()).copied(),
+            None => enabled.last().copied(),
+        }
+    }
+}
+
+impl Default for PilotModes {
+    fn default() -> Self {
+        Self(vec![
+            PilotMode::normal(),
+            PilotMode::slow(),
+            PilotMode::precision(),
+            PilotMode::vertical_transect(),
+            PilotMode {
+                name: "Custom".to_owned(),
+                enabled: false,
+                ..PilotMode::normal()
+            },
+        ])
+    }
+}
@@ -18,6 +18,65 @@ pub struct Robot;
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Surface;
 
+/// The role a surface asks for when it connects, the robot only grants one pilot at a time
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Default,
+)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum SurfaceRole {
+    #[default]
+    Pilot,
+    /// Receives all telemetry and video but only controls what the pilot allows
+    CoPilot,
+}
+
+impl SurfaceRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SurfaceRole::Pilot => "Pilot",
+            SurfaceRole::CoPilot => "Co-Pilot",
+        }
+    }
+}
+
+/// The classes of commands a surface may send to the robot
+#[derive(Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    pub arming: bool,
+    pub movement: bool,
+}
+
+impl Capabilities {
+    pub const ALL: Self = Self {
+        arming: true,
+        movement: true,
+    };
+    pub const NONE: Self = Self {
+        arming: false,
+        movement: false,
+    };
+}
+
+/// Every surface connected to the robot, kept on the robot entity
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Stations(pub Vec<Station>);
+
+impl Stations {
+    pub fn get(&self, surface: NetId) -> Option<&Station> {
+        self.0.iter().find(|it| it.surface == surface)
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+pub struct Station {
+    pub surface: NetId,
+    pub name: String,
+    /// The role the robot granted, not necessarily the one that was asked for
+    pub role: SurfaceRole,
+    pub capabilities: Capabilities,
+}
+
 // TODO: This could be changed to a unit struct that is added and removed from the robot entity
 #[derive(
     Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Default,
@@ -0,0 +1,64 @@
+//! Actuator test routines the robot runs while disarmed
+
+use bevy::{
+    ecs::component::Component,
+    reflect::{prelude::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::adapters::serde::ReflectSerdeAdapter;
+
+/// The robot's maintenance routines, they only run while the robot is disarmed and after the
+/// pilot confirms an override
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Maintenance {
+    /// Has to be echoed back in `MaintenanceStep::Confirm` to start the routine
+    pub override_challenge: Option<(MaintenanceRoutine, u32)>,
+    pub running: Option<MaintenanceProgress>,
+    /// From the last routine to stop, cleared when the next one starts
+    pub findings: Vec<MaintenanceFinding>,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceRoutine {
+    /// Pulses each thruster's esc in channel order, once for the first thruster, twice for the
+    /// second and so on, to check the wiring
+    EscBeacon,
+    /// Sweeps each servo through its full range and watches the current draw for binding
+    ServoSweep,
+    /// Flashes the lights
+    LightFlash,
+}
+
+impl MaintenanceRoutine {
+    pub const ALL: [MaintenanceRoutine; 3] = [
+        MaintenanceRoutine::EscBeacon,
+        MaintenanceRoutine::ServoSweep,
+        MaintenanceRoutine::LightFlash,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceRoutine::EscBeacon => "ESC Beacon",
+            MaintenanceRoutine::ServoSweep => "Servo Sweep",
+            MaintenanceRoutine::LightFlash => "Light Flash Test",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+pub struct MaintenanceProgress {
+    pub routine: MaintenanceRoutine,
+    /// The actuator being exercised, empty between actuators
+    pub actuator: String,
+    /// From zero to one over the whole routine
+    pub progress: f32,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+pub struct MaintenanceFinding {
+    pub actuator: String,
+    pub passed: bool,
+    pub detail: String,
+}
@@ -1,5 +1,5 @@
 //! Servo and dc motor api
-use std::{borrow::Cow, cmp::Ordering};
+use std::{borrow::Cow, cmp::Ordering, fmt::Display};
 
 use bevy::{
     ecs::component::Component,
@@ -31,6 +31,44 @@ pub enum MotorSignal {
     Raw(i32),
 }
 
+impl MotorSignal {
+    pub fn to_raw(&self, range: &MotorRawSignalRange) -> i32 {
+        match *self {
+            MotorSignal::Percent(pct) => range.raw_from_percent(pct),
+            MotorSignal::Raw(raw) => raw,
+        }
+    }
+
+    pub fn to_percent(&self, range: &MotorRawSignalRange) -> f32 {
+        match *self {
+            MotorSignal::Percent(pct) => pct,
+            MotorSignal::Raw(raw) => range.percent_from_raw(raw),
+        }
+    }
+
+    /// Whether the signal can be output without being clamped
+    pub fn in_range(&self, range: &MotorRawSignalRange) -> bool {
+        match *self {
+            MotorSignal::Percent(pct) => {
+                let (min, max) = range.percent_limits();
+                (min..=max).contains(&pct)
+            }
+            MotorSignal::Raw(raw) => range.clamp_raw(raw) == raw,
+        }
+    }
+
+    /// The closest signal to this one that `range` allows, in the same representation
+    pub fn clamped(&self, range: &MotorRawSignalRange) -> MotorSignal {
+        match *self {
+            MotorSignal::Percent(pct) => {
+                let (min, max) = range.percent_limits();
+                MotorSignal::Percent(pct.clamp(min, max))
+            }
+            MotorSignal::Raw(raw) => MotorSignal::Raw(range.clamp_raw(raw)),
+        }
+    }
+}
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub enum MotorSignalType {
@@ -51,6 +89,14 @@ impl MotorRawSignalRange {
         raw.clamp(self.min.min(self.max), self.min.max(self.max))
     }
 
+    /// Lowest and highest percent signals, ranges that can't go below their center stop at zero
+    pub fn percent_limits(&self) -> (f32, f32) {
+        let min = if self.min == self.center { 0.0 } else { -1.0 };
+        let max = if self.max == self.center { 0.0 } else { 1.0 };
+
+        (min, max)
+    }
+
     pub fn percent_from_raw(&self, raw: i32) -> f32 {
         match raw.cmp(&self.center) {
             Ordering::Greater => (raw - self.center) as f32 / (self.max - self.center) as f32,
@@ -97,6 +143,55 @@ pub struct MotorSlewRate(pub MotorSignal);
 #[reflect(from_reflect = false)]
 pub struct MotorContribution(pub StableHashMap<GenericMotorId, f32>);
 
+/// How the robot mixes the `MotorContribution` on the same entity with the others driving the same
+/// motors. Contributions without one use the default
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct MotorMixing {
+    /// A motor is only driven by the highest priority contributions that aren't zero for it, lower
+    /// ones are ignored until those let go
+    pub priority: i32,
+    /// The largest magnitude of this contribution to any motor
+    pub limit: Option<f32>,
+    /// How fast this contribution to a motor may change, per second
+    pub rate_limit: Option<f32>,
+}
+
+impl MotorMixing {
+    /// The limit as a magnitude, ignored if it isn't a finite number since any surface can set it
+    pub fn limit(&self) -> Option<f32> {
+        self.limit.filter(|it| it.is_finite()).map(f32::abs)
+    }
+
+    /// The rate limit as a magnitude, ignored if it isn't a finite number
+    pub fn rate_limit(&self) -> Option<f32> {
+        self.rate_limit.filter(|it| it.is_finite()).map(f32::abs)
+    }
+}
+
+/// The named servo positions from the robot's config, in the order they were defined
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, /*Serialize, Deserialize,*/ Debug, PartialEq, Default)]
+#[reflect(from_reflect = false)]
+pub struct ServoPresets(pub Vec<ServoPreset>);
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(from_reflect = false)]
+pub struct ServoPreset {
+    pub name: String,
+    pub positions: StableHashMap<GenericMotorId, f32>,
+}
+
+/// The preset the robot's servos are following, removed once any of them is moved by hand
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ActiveServoPreset {
+    pub name: String,
+    /// Whether every servo in the preset has been driven to its position, servos with a slew rate
+    /// may still be catching up
+    pub arrived: bool,
+}
+
 #[derive(
     Component,
     Serialize,
@@ -113,3 +208,158 @@ pub struct MotorContribution(pub StableHashMap<GenericMotorId, f32>);
 )]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct GenericMotorId(pub u8);
+
+impl GenericMotorId {
+    const DC_FLAG: u8 = 0x80;
+
+    pub fn new(channel_type: ActuatorChannelType, index: u8) -> Self {
+        match channel_type {
+            ActuatorChannelType::Pwm => Self(index & !Self::DC_FLAG),
+            ActuatorChannelType::Dc => Self(index | Self::DC_FLAG),
+        }
+    }
+
+    pub fn channel_type(&self) -> ActuatorChannelType {
+        if self.0 & Self::DC_FLAG != 0 {
+            ActuatorChannelType::Dc
+        } else {
+            ActuatorChannelType::Pwm
+        }
+    }
+
+    pub fn index(&self) -> u8 {
+        self.0 & !Self::DC_FLAG
+    }
+
+    /// Whether the robot's hardware has this channel
+    pub fn exists(&self) -> bool {
+        self.index() < self.channel_type().channels()
+    }
+}
+
+impl Display for GenericMotorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.channel_type().name(), self.index())
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActuatorChannelType {
+    Pwm,
+    Dc,
+}
+
+impl ActuatorChannelType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActuatorChannelType::Pwm => "PWM",
+            ActuatorChannelType::Dc => "DC",
+        }
+    }
+
+    /// Number of channels of this type on the robot's hardware
+    pub fn channels(&self) -> u8 {
+        match self {
+            ActuatorChannelType::Pwm => 16,
+            ActuatorChannelType::Dc => 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActuatorKind {
+    Thruster,
+    Servo,
+}
+
+/// What an actuator is and what it can do
+///
+/// Defined once by the robot from its config so surfaces don't have to reconstruct it from the
+/// channel number or the actuator's `Name`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ActuatorIdentity {
+    pub channel_type: ActuatorChannelType,
+    pub index: u8,
+    /// Name from the config, without any decoration
+    pub name: String,
+    pub kind: ActuatorKind,
+    /// Can be driven to either side of its center
+    pub reversible: bool,
+    /// The signal commands a position rather than a speed
+    pub positional: bool,
+}
+
+impl ActuatorIdentity {
+    pub fn new(
+        name: impl Into<String>,
+        kind: ActuatorKind,
+        channel: GenericMotorId,
+        signal_type: MotorSignalType,
+        signal_range: &MotorRawSignalRange,
+    ) -> Self {
+        Self {
+            channel_type: channel.channel_type(),
+            index: channel.index(),
+            name: name.into(),
+            kind,
+            reversible: signal_range.min != signal_range.center
+                && signal_range.max != signal_range.center,
+            positional: signal_type == MotorSignalType::Position,
+        }
+    }
+
+    pub fn channel(&self) -> GenericMotorId {
+        GenericMotorId::new(self.channel_type, self.index)
+    }
+
+    /// The name and the channel it is wired to, for listing actuators
+    pub fn label(&self) -> String {
+        format!("{} ({})", self.name, self.channel())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PWM: MotorRawSignalRange = MotorRawSignalRange {
+        min: 1100,
+        center: 1500,
+        max: 1900,
+    };
+
+    #[test]
+    fn signal_conversions() {
+        assert_eq!(MotorSignal::Percent(0.5).to_raw(&PWM), 1700);
+        assert_eq!(MotorSignal::Percent(-1.0).to_raw(&PWM), 1100);
+        assert_eq!(MotorSignal::Raw(1300).to_percent(&PWM), -0.5);
+        assert_eq!(MotorSignal::Raw(1500).to_percent(&PWM), 0.0);
+    }
+
+    #[test]
+    fn signal_clamping() {
+        let one_sided = MotorRawSignalRange {
+            min: 1500,
+            ..PWM
+        };
+
+        assert!(MotorSignal::Percent(1.0).in_range(&PWM));
+        assert!(!MotorSignal::Percent(1.2).in_range(&PWM));
+        assert!(!MotorSignal::Raw(2000).in_range(&PWM));
+        assert!(!MotorSignal::Percent(-0.5).in_range(&one_sided));
+
+        assert_eq!(
+            MotorSignal::Percent(1.2).clamped(&PWM),
+            MotorSignal::Percent(1.0)
+        );
+        assert_eq!(
+            MotorSignal::Raw(900).clamped(&PWM),
+            MotorSignal::Raw(1100)
+        );
+        assert_eq!(
+            MotorSignal::Percent(-0.5).clamped(&one_sided),
+            MotorSignal::Percent(0.0)
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::{
+    ecs::component::Component,
+    reflect::{Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::adapters::serde::ReflectSerdeAdapter;
+
+/// Every parameter registered on a peer, see `params`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Parameters(pub Vec<ParamEntry>);
+
+impl Parameters {
+    pub fn get(&self, key: &str) -> Option<&ParamEntry> {
+        self.0.iter().find(|it| it.key == key)
+    }
+}
+
+/// A parameter with everything needed to show and edit it without knowing its type
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ParamEntry {
+    pub key: String,
+    /// The subsystem the parameter belongs to, used to group the tuning panel
+    pub group: String,
+    pub description: String,
+    pub value: ParamValue,
+    pub default: ParamValue,
+    pub min: ParamValue,
+    pub max: ParamValue,
+}
+
+impl ParamEntry {
+    pub fn is_default(&self) -> bool {
+        self.value == self.default
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub enum ParamValue {
+    Float(f32),
+    Int(i64),
+    Bool(bool),
+}
+
+impl ParamValue {
+    pub fn same_kind(&self, other: &ParamValue) -> bool {
+        matches!(
+            (self, other),
+            (ParamValue::Float(_), ParamValue::Float(_))
+                | (ParamValue::Int(_), ParamValue::Int(_))
+                | (ParamValue::Bool(_), ParamValue::Bool(_))
+        )
+    }
+
+    /// Whether this can be used as a parameter value, NaN and infinite floats can't
+    pub fn is_finite(&self) -> bool {
+        match self {
+            ParamValue::Float(value) => value.is_finite(),
+            _ => true,
+        }
+    }
+
+    /// Clamps numeric values into `min..=max`, both of which must be the same kind as `self`.
+    /// NaN clamps to `min`
+    pub fn clamp(self, min: ParamValue, max: ParamValue) -> ParamValue {
+        match (self, min, max) {
+            (ParamValue::Float(value), ParamValue::Float(min), _) if value.is_nan() => {
+                ParamValue::Float(min)
+            }
+            (ParamValue::Float(value), ParamValue::Float(min), ParamValue::Float(max)) => {
+                ParamValue::Float(value.clamp(min, max))
+            }
+            (ParamValue::Int(value), ParamValue::Int(min), ParamValue::Int(max)) => {
+                ParamValue::Int(value.clamp(min, max))
+            }
+            (value, ..) => value,
+        }
+    }
+}
@@ -106,3 +106,18 @@ impl PidController {
         self.integral
     }
 }
+
+/// Delay between a pid controller commanding movement and the robot's motion responding to it,
+/// measured by chirping the controller's axis
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ActuationLatency(pub Duration);
+
+/// Present on a pid controller while its actuation latency is being identified, the controller
+/// does not stabilize during this time
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct LatencyIdentification {
+    pub elapsed: Duration,
+    pub duration: Duration,
+}
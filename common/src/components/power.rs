@@ -5,7 +5,7 @@ use bevy::{
 use serde::{Deserialize, Serialize};
 
 use crate::adapters::serde::ReflectSerdeAdapter;
-use crate::types::units::{Amperes, Volts};
+use crate::types::units::{Amperes, Celsius, Volts};
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
@@ -14,3 +14,46 @@ pub struct MeasuredVoltage(pub Volts);
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct CurrentDraw(pub Amperes);
+
+/// Readings from the battery's management system
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BatteryStatus {
+    /// In series order
+    pub cell_voltages: Vec<Volts>,
+    pub temperature: Celsius,
+    /// Percent of full charge
+    pub state_of_charge: f32,
+    pub cycle_count: u16,
+}
+
+impl BatteryStatus {
+    pub fn min_cell(&self) -> Option<Volts> {
+        self.cell_voltages.iter().copied().reduce(|a, b| if b < a { b } else { a })
+    }
+
+    pub fn max_cell(&self) -> Option<Volts> {
+        self.cell_voltages.iter().copied().reduce(|a, b| if b > a { b } else { a })
+    }
+
+    /// Difference between the highest and lowest cell
+    pub fn imbalance(&self) -> Option<Volts> {
+        Some(self.max_cell()? - self.min_cell()?)
+    }
+}
+
+/// On the robot while its battery is unsafe to arm on, with the reason
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BatteryFault(pub String);
+
+/// How far the robot has scaled back its thruster current budget because its electronics are hot
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ThermalDerate {
+    /// Fraction of the configured budget available, 1 when not derated
+    pub factor: f32,
+    /// The hottest monitored sensor
+    pub sensor: String,
+    pub temperature: Celsius,
+}
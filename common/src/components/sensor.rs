@@ -4,17 +4,63 @@ use bevy::{
     ecs::component::Component,
     reflect::{prelude::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
 };
-use glam::{Mat3A, Quat};
+use glam::{EulerRot, Quat, Vec3A};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     adapters::serde::ReflectSerdeAdapter,
-    types::units::{Celsius, Dps, GForce, Gauss, Mbar, Meters},
+    attitude,
+    types::units::{Celsius, Degrees, Dps, GForce, Gauss, Mbar, Meters, Radians},
 };
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct Orientation(pub Quat);
 
+impl Orientation {
+    /// Yaw, pitch and roll in degrees, yaw is counter clockwise about +Z
+    pub fn yaw_pitch_roll(&self) -> (f32, f32, f32) {
+        let (yaw, pitch, roll) = self.0.to_euler(EulerRot::ZXY);
+
+        (yaw.to_degrees(), pitch.to_degrees(), roll.to_degrees())
+    }
+
+    pub fn heading(&self) -> Heading {
+        let yaw = attitude::yaw(self.0).to_degrees();
+
+        Heading(Degrees((-yaw).rem_euclid(360.0)))
+    }
+}
+
+/// Compass heading of the robot's forward axis, clockwise from north in the range `[0, 360)`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Heading(pub Degrees);
+
+/// Pitch and roll removed from the robot's orientation so it reads level at the attitude recorded
+/// by level calibration
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct LevelTrim {
+    pub pitch: Degrees,
+    pub roll: Degrees,
+}
+
+/// Net turns the robot has made since the counter was last reset, how twisted the tether is
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct TetherTurns {
+    /// Positive when the robot has turned clockwise, the same direction as `Heading`
+    pub turns: f32,
+    /// Turns in either direction before the tether is considered over twisted
+    pub budget: f32,
+}
+
+impl TetherTurns {
+    pub fn over_budget(&self) -> bool {
+        self.turns.abs() > self.budget
+    }
+}
+
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct GyroMeasurement {
@@ -54,7 +100,79 @@ pub struct DepthMeasurement {
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct DepthSettings {
     pub sea_level: Mbar,
+    /// Kilograms per cubic meter
     pub fluid_density: f32,
+    /// Practical salinity units, when set `fluid_density` follows the salinity and the water
+    /// temperature
+    pub salinity: Option<f32>,
+}
+
+/// Measured by the depth sensor
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct WaterTemperature(pub Celsius);
+
+/// Measured by the conductivity sensor, in practical salinity units
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Salinity(pub f32);
+
+/// The state of the pressure sensor behind `DepthMeasurement`
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct DepthSensorHealth {
+    /// The model of the sensor, ie "Bar30"
+    pub sensor: String,
+    /// Reads that failed since the last good one
+    pub failed_reads: u32,
+    /// No reading has arrived for a while, `DepthMeasurement` is out of date
+    pub stale: bool,
+}
+
+/// Distance to the bottom measured by the downward echosounder, along the robot's down axis
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Altimeter {
+    pub altitude: Meters,
+    /// From 0 to 1, how sure the echosounder is that it picked the bottom's echo
+    pub confidence: f32,
+}
+
+/// Motion of the robot estimated by a visual odometry pipeline, in the robot's frame
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct VisualOdometry {
+    /// Meters per second
+    pub velocity: Vec3A,
+    /// Radians per second
+    pub angular_velocity: Vec3A,
+    /// Fraction of the tracked features consistent with the estimated motion
+    pub quality: f32,
+    /// Whether the scale came from fiducial tags rather than an assumed distance to the scene
+    pub metric: bool,
+}
+
+/// Position of the robot estimated from acoustic fixes by the waterlinked pilot, meters from the
+/// calibrated origin with +Z up
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct PositionEstimate {
+    pub position: Vec3A,
+    pub horizontal_std: Meters,
+    /// Rotates the robot's `Orientation` into the frame of `position`
+    pub frame_rotation: Quat,
+}
+
+/// A line on the seafloor found by a transect pipeline, relative to the downward camera's image
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct TransectLine {
+    /// From the image's vertical axis, positive when the top of the line leans right
+    pub angle: Radians,
+    /// Where the line crosses the middle row of the image, -1 at the left edge and 1 at the right
+    pub offset: f32,
+    /// How much of the image the line was seen along, from 0 to 1
+    pub confidence: f32,
 }
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Default)]
@@ -75,13 +193,3 @@ pub struct CameraDefinition {
     #[reflect(ignore)]
     pub location: SocketAddr,
 }
-
-#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
-pub struct CameraInputRotation(pub Quat);
-
-#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
-pub struct CameraCalibration {
-    pub camera_matrix: Mat3A,
-    // TODO: Figure out if this is always 5 long
-    pub distortion_coefficients: Vec<f32>,
-}
@@ -85,6 +85,25 @@ mod thruster_api {
     #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
     pub struct JerkLimit(pub f32);
 
+    /// What the thrust allocator did with each thruster, published at a reduced rate
+    #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+    #[reflect(SerdeAdapter, /*Serialize, Deserialize,*/ Debug, PartialEq, Default)]
+    #[reflect(from_reflect = false)]
+    pub struct ThrusterDiagnostics(pub StableHashMap<ErasedMotorId, ThrusterDiagnostic>);
+
+    #[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Default)]
+    pub struct ThrusterDiagnostic {
+        /// The sum of every thrust contribution
+        pub requested: Newtons,
+        /// What the thruster was given after the current budget was applied
+        pub clamped: Newtons,
+        /// The fraction of the movement current budget used by this thruster
+        pub current_share: f32,
+        /// Whether the thruster fell short of the requested force at any point since the last
+        /// update
+        pub saturated: bool,
+    }
+
     // Not Implemented
     // #[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
     // #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
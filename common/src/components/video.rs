@@ -0,0 +1,61 @@
+use bevy::{
+    ecs::component::Component,
+    reflect::{prelude::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use glam::{Mat3A, Quat};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapters::serde::ReflectSerdeAdapter,
+    types::video::{CameraControlRanges, CameraFormat, CameraQuality, StreamTransport, VideoCodec},
+};
+
+/// The encoding and transport the robot uses for a camera's video
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Eq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct CameraStream {
+    pub codec: VideoCodec,
+    pub transport: StreamTransport,
+    /// Whether the robot is encoding the stream with a hardware encoder rather than passing
+    /// through the camera's own encoding or using a software encoder
+    pub hardware_encoded: bool,
+    pub quality: CameraQuality,
+}
+
+/// What the camera's V4L2 device reports
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct CameraCapabilities {
+    pub device: String,
+    pub card: String,
+    pub formats: Vec<CameraFormat>,
+    /// The format the camera is currently capturing in
+    pub active: Option<CameraFormat>,
+    pub controls: CameraControlRanges,
+}
+
+/// Image controls set from the surface and applied by the robot through V4L2, `None` leaves the
+/// camera's automatic mode in charge
+#[derive(
+    Component, Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct CameraControls {
+    /// In the camera's units, usually 100µs
+    pub exposure: Option<i32>,
+    /// Kelvin
+    pub white_balance: Option<i32>,
+    pub focus: Option<i32>,
+    /// Gain has no automatic mode, `None` is the camera's default gain
+    pub gain: Option<i32>,
+}
+
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+pub struct CameraInputRotation(pub Quat);
+
+#[derive(Component, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+pub struct CameraCalibration {
+    pub camera_matrix: Mat3A,
+    // TODO: Figure out if this is always 5 long
+    pub distortion_coefficients: Vec<f32>,
+}
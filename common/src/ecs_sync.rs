@@ -1,7 +1,9 @@
 pub mod apply_changes;
 pub mod detect_changes;
+pub mod verify;
 
 use std::any::Any;
+use std::mem;
 use std::sync::Arc;
 use std::{any::TypeId, borrow::Cow, marker::PhantomData};
 
@@ -22,6 +24,7 @@ use bevy::{
 };
 use networking::Token;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use crate::{
     adapters::{
@@ -44,6 +47,43 @@ impl NetId {
     pub fn random() -> Self {
         Self(rand::random())
     }
+
+    /// The allocation domain of the peer that made this id, see `NetIdAllocator`
+    pub fn domain(&self) -> u64 {
+        (self.0 >> 64) as u64
+    }
+}
+
+/// Hands out the `NetId`s of entities this peer replicates. The upper half of each id is a domain
+/// picked at random on startup and the lower half counts up, so a peer never repeats an id and
+/// ids from two peers can only collide if their domains do
+#[derive(Resource, Debug)]
+pub struct NetIdAllocator {
+    domain: u64,
+    next: u64,
+}
+
+impl Default for NetIdAllocator {
+    fn default() -> Self {
+        Self {
+            // Zero is left out so `NetId::invalid` is never in a domain
+            domain: rand::random::<u64>().max(1),
+            next: 1,
+        }
+    }
+}
+
+impl NetIdAllocator {
+    pub fn domain(&self) -> u64 {
+        self.domain
+    }
+
+    pub fn allocate(&mut self) -> NetId {
+        let id = NetId(((self.domain as u128) << 64) | self.next as u128);
+        self.next += 1;
+
+        id
+    }
 }
 
 #[derive(Component, Serialize, Deserialize, Reflect, Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -61,8 +101,9 @@ pub enum SerializedChange {
 
 #[derive(Event, Debug)]
 pub struct SerializedChangeInEvent(pub SerializedChange, pub Token);
+/// The token is set when forwarding a change made by that peer, it isn't sent back to them
 #[derive(Event, Debug)]
-pub struct SerializedChangeOutEvent(pub SerializedChange);
+pub struct SerializedChangeOutEvent(pub SerializedChange, pub Option<Token>);
 
 #[derive(Resource, Default)]
 pub struct EntityMap {
@@ -72,6 +113,250 @@ pub struct EntityMap {
     pub(crate) forign_owned: HashMap<Token, HashSet<Entity>>,
 
     pub(crate) local_modified: HashMap<Entity, Tick>,
+
+    /// Ids a peer spawned that were already taken, they are replaced with ids from our allocator
+    /// in everything sent to or received from that peer. Ids inside component data, like
+    /// `RobotId`, aren't rewritten
+    pub(crate) remapped: HashMap<Token, RemappedIds>,
+    /// Spawns that have been received but not applied yet
+    pub(crate) reserved: HashSet<NetId>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RemappedIds {
+    /// The peer's id to ours
+    inbound: HashMap<NetId, NetId>,
+    /// Our id to the peer's
+    outbound: HashMap<NetId, NetId>,
+}
+
+impl EntityMap {
+    /// Rewrites the ids in a change from `peer` to the ones used locally. A spawn with an id that
+    /// is already in use by another peer or is in our own domain is given a new id
+    pub(crate) fn remap_inbound(
+        &mut self,
+        peer: Token,
+        change: SerializedChange,
+        ids: &mut NetIdAllocator,
+    ) -> SerializedChange {
+        match change {
+            SerializedChange::EntitySpawned(forign) => {
+                let taken_by_other = self.forign_to_local.get(&forign).is_some_and(|entity| {
+                    !self
+                        .forign_owned
+                        .get(&peer)
+                        .is_some_and(|owned| owned.contains(entity))
+                });
+                let collides = taken_by_other
+                    || self.reserved.contains(&forign)
+                    || forign.domain() == ids.domain();
+
+                let local = if collides {
+                    let local = ids.allocate();
+                    warn!("NetId collision, {forign:?} from {peer:?} was remapped to {local:?}");
+
+                    let remapped = self.remapped.entry(peer).or_default();
+                    remapped.inbound.insert(forign, local);
+                    remapped.outbound.insert(local, forign);
+
+                    local
+                } else {
+                    forign
+                };
+
+                self.reserved.insert(local);
+                SerializedChange::EntitySpawned(local)
+            }
+            SerializedChange::EntityDespawned(forign) => {
+                let local = match self.remapped.get_mut(&peer) {
+                    Some(remapped) => {
+                        let local = remapped.inbound.remove(&forign);
+                        if let Some(local) = local {
+                            remapped.outbound.remove(&local);
+                        }
+
+                        local.unwrap_or(forign)
+                    }
+                    None => forign,
+                };
+
+                SerializedChange::EntityDespawned(local)
+            }
+            SerializedChange::ComponentUpdated(forign, token, raw) => {
+                let local = self.remap(peer, forign, |it| &it.inbound);
+                SerializedChange::ComponentUpdated(local, token, raw)
+            }
+            change @ SerializedChange::EventEmitted(..) => change,
+        }
+    }
+
+    /// Rewrites our ids in a change to the ones `peer` knows them by
+    pub(crate) fn remap_outbound(
+        &self,
+        peer: Token,
+        change: &SerializedChange,
+    ) -> SerializedChange {
+        let remap = |local| self.remap(peer, local, |it| &it.outbound);
+
+        match change {
+            SerializedChange::EntitySpawned(local) => {
+                SerializedChange::EntitySpawned(remap(*local))
+            }
+            SerializedChange::EntityDespawned(local) => {
+                SerializedChange::EntityDespawned(remap(*local))
+            }
+            SerializedChange::ComponentUpdated(local, token, raw) => {
+                SerializedChange::ComponentUpdated(remap(*local), token.clone(), raw.clone())
+            }
+            SerializedChange::EventEmitted(..) => change.clone(),
+        }
+    }
+
+    fn remap(
+        &self,
+        peer: Token,
+        id: NetId,
+        direction: impl Fn(&RemappedIds) -> &HashMap<NetId, NetId>,
+    ) -> NetId {
+        self.remapped
+            .get(&peer)
+            .and_then(|it| direction(it).get(&id))
+            .copied()
+            .unwrap_or(id)
+    }
+
+    /// Whether any ids have to be rewritten for `peer`
+    pub(crate) fn has_remapped(&self, peer: Token) -> bool {
+        self.remapped
+            .get(&peer)
+            .is_some_and(|it| !it.outbound.is_empty())
+    }
+
+    /// Forgets the entities `peer` replicated to us, they are returned so they can be despawned
+    pub fn forget_peer(&mut self, peer: Token) -> HashSet<Entity> {
+        self.remapped.remove(&peer);
+
+        let owned = self.forign_owned.remove(&peer).unwrap_or_default();
+        for entity in &owned {
+            if let Some(forign) = self.local_to_forign.remove(entity) {
+                self.forign_to_local.remove(&forign);
+            }
+
+            self.local_modified.remove(entity);
+        }
+
+        owned
+    }
+}
+
+/// What a peer may replicate to us
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Permitted {
+    #[default]
+    All,
+    /// Changes to any other component or event are dropped
+    Only(HashSet<NetTypeId>),
+}
+
+/// Changes held for a single peer before the rest are dropped
+const MAX_HELD_CHANGES: usize = 10_000;
+
+/// Components and events peers may replicate to us, changes they make to anything else are
+/// dropped before they are applied. Peers without an entry get the default, which is unrestricted
+/// unless set otherwise
+#[derive(Resource, Default)]
+pub struct ReplicationPermissions {
+    default: Permitted,
+    peers: HashMap<Token, Permitted>,
+    /// Owned by us, no peer may replicate these
+    denied: HashSet<NetTypeId>,
+
+    /// Whether changes the default doesn't permit are held until the peer's own permissions are
+    /// set, instead of being dropped
+    hold_default: bool,
+    held: HashMap<Token, Vec<SerializedChange>>,
+    /// Held changes from peers whose permissions have since been set, in the order they arrived
+    released: Vec<(SerializedChange, Token)>,
+}
+
+impl ReplicationPermissions {
+    pub fn set_default(&mut self, permitted: Permitted) {
+        self.default = permitted;
+    }
+
+    /// Holds what the default doesn't permit until the peer gets its own permissions, so a peer
+    /// that is still being identified doesn't lose the state it sent first
+    pub fn set_hold_default(&mut self, hold: bool) {
+        self.hold_default = hold;
+    }
+
+    pub fn set(&mut self, peer: Token, permitted: Permitted) {
+        self.peers.insert(peer, permitted);
+
+        if let Some(held) = self.held.remove(&peer) {
+            self.released
+                .extend(held.into_iter().map(|change| (change, peer)));
+        }
+    }
+
+    /// Returns `peer` to the default, anything held for it is dropped
+    pub fn remove(&mut self, peer: Token) {
+        self.peers.remove(&peer);
+        self.held.remove(&peer);
+    }
+
+    pub fn set_denied(&mut self, denied: HashSet<NetTypeId>) {
+        self.denied = denied;
+    }
+
+    pub fn permits(&self, peer: Token, type_name: &NetTypeId) -> bool {
+        if self.denied.contains(type_name) {
+            return false;
+        }
+
+        match self.peers.get(&peer).unwrap_or(&self.default) {
+            Permitted::All => true,
+            Permitted::Only(allowed) => allowed.contains(type_name),
+        }
+    }
+
+    pub(crate) fn permits_change(&self, peer: Token, change: &SerializedChange) -> bool {
+        match change {
+            SerializedChange::ComponentUpdated(_, type_name, _)
+            | SerializedChange::EventEmitted(type_name, _) => self.permits(peer, type_name),
+            _ => true,
+        }
+    }
+
+    /// Keeps a change that wasn't permitted if `peer` is still on the default and it is held,
+    /// returns false if the change should be dropped instead
+    pub(crate) fn hold(&mut self, peer: Token, change: &SerializedChange) -> bool {
+        if !self.hold_default || self.peers.contains_key(&peer) {
+            return false;
+        }
+
+        let held = self.held.entry(peer).or_default();
+        if held.len() >= MAX_HELD_CHANGES {
+            return false;
+        }
+        if held.len() + 1 == MAX_HELD_CHANGES {
+            warn!("Holding too many changes from {peer:?}, dropping the rest");
+        }
+
+        held.push(change.clone());
+        true
+    }
+
+    /// Held changes that can now be applied
+    pub(crate) fn take_released(&mut self) -> Vec<(SerializedChange, Token)> {
+        mem::take(&mut self.released)
+    }
+
+    /// Forgets what was held for peers that disconnected
+    pub(crate) fn retain_held(&mut self, mut keep: impl FnMut(Token) -> bool) {
+        self.held.retain(|peer, _| keep(*peer));
+        self.released.retain(|(_, peer)| keep(*peer));
+    }
 }
 
 #[derive(Resource)]
@@ -340,3 +625,98 @@ impl ErasedManualEventReader {
 //         panic!()
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use networking::Token;
+
+    use ahash::HashSet;
+
+    use super::{EntityMap, NetIdAllocator, Permitted, ReplicationPermissions, SerializedChange};
+
+    #[test]
+    fn colliding_spawns_are_remapped() {
+        let mut entity_map = EntityMap::default();
+        let mut ids = NetIdAllocator::default();
+        let mut other_ids = NetIdAllocator::default();
+
+        let (a, b) = (Token(1), Token(2));
+        let forign = other_ids.allocate();
+
+        let SerializedChange::EntitySpawned(first) =
+            entity_map.remap_inbound(a, SerializedChange::EntitySpawned(forign), &mut ids)
+        else {
+            unreachable!()
+        };
+        assert_eq!(first, forign);
+
+        // Another peer reusing the id while the first spawn is still pending
+        let SerializedChange::EntitySpawned(second) =
+            entity_map.remap_inbound(b, SerializedChange::EntitySpawned(forign), &mut ids)
+        else {
+            unreachable!()
+        };
+        assert_ne!(second, forign);
+        assert_eq!(second.domain(), ids.domain());
+
+        let update = SerializedChange::ComponentUpdated(forign, "test".into(), None);
+        assert_eq!(
+            entity_map.remap_inbound(b, update.clone(), &mut ids),
+            SerializedChange::ComponentUpdated(second, "test".into(), None)
+        );
+        assert_eq!(
+            entity_map.remap_inbound(a, update.clone(), &mut ids),
+            update
+        );
+
+        let outbound = SerializedChange::ComponentUpdated(second, "test".into(), None);
+        assert_eq!(entity_map.remap_outbound(b, &outbound), update);
+        assert!(entity_map.has_remapped(b));
+
+        entity_map.remap_inbound(b, SerializedChange::EntityDespawned(forign), &mut ids);
+        assert!(!entity_map.has_remapped(b));
+    }
+
+    #[test]
+    fn permissions_default_to_allowlist() {
+        let mut permissions = ReplicationPermissions::default();
+        let (a, b) = (Token(1), Token(2));
+
+        assert!(permissions.permits(a, &"anything".into()));
+
+        permissions.set_default(Permitted::Only(["allowed".into()].into_iter().collect()));
+        permissions.set(b, Permitted::All);
+        permissions.set_denied(["owned".into()].into_iter().collect());
+
+        assert!(permissions.permits(a, &"allowed".into()));
+        assert!(!permissions.permits(a, &"anything".into()));
+        assert!(permissions.permits(b, &"anything".into()));
+        assert!(!permissions.permits(b, &"owned".into()));
+
+        permissions.remove(b);
+        assert!(!permissions.permits(b, &"anything".into()));
+    }
+
+    #[test]
+    fn held_changes_are_released_once_set() {
+        let mut permissions = ReplicationPermissions::default();
+        let (a, b) = (Token(1), Token(2));
+        let change = SerializedChange::EventEmitted("anything".into(), Default::default());
+
+        permissions.set_default(Permitted::Only(HashSet::default()));
+        assert!(!permissions.hold(a, &change));
+
+        permissions.set_hold_default(true);
+        assert!(permissions.hold(a, &change));
+        assert!(permissions.hold(b, &change));
+        assert!(permissions.take_released().is_empty());
+
+        permissions.set(a, Permitted::All);
+        assert!(!permissions.hold(a, &change));
+        assert_eq!(permissions.take_released(), vec![(change.clone(), a)]);
+
+        permissions.remove(b);
+        permissions.set(b, Permitted::All);
+        assert!(permissions.take_released().is_empty());
+    }
+}
@@ -1,14 +1,15 @@
+use ahash::HashSet;
 use bevy::{
     app::{App, Plugin, PreUpdate},
     ecs::{
         event::EventReader,
         reflect::AppTypeRegistry,
         schedule::{IntoSystemConfigs, SystemSet},
-        system::{Commands, Res, ResMut, SystemChangeTick},
+        system::{Commands, Local, Res, ResMut, SystemChangeTick},
         world::{Mut, World},
     },
 };
-use tracing::error;
+use tracing::{debug, error, info, warn};
 
 use crate::{
     adapters::{dynamic::DynamicAdapter, ComponentTypeAdapter, EventTypeAdapter},
@@ -16,8 +17,8 @@ use crate::{
 };
 
 use super::{
-    EntityMap, ForignOwned, Replicate, SerializationSettings, SerializedChange,
-    SerializedChangeInEvent,
+    EntityMap, ForignOwned, NetTypeId, Replicate, ReplicationPermissions, SerializationSettings,
+    SerializedChange, SerializedChangeInEvent,
 };
 
 pub struct ChangeApplicationPlugin;
@@ -38,16 +39,54 @@ fn apply_changes(
     settings: Res<SerializationSettings>,
     mut entity_map: ResMut<EntityMap>,
     peers: Res<Peers>,
+    mut permissions: ResMut<ReplicationPermissions>,
     mut reader: EventReader<SerializedChangeInEvent>,
+    mut unknown_types: Local<HashSet<NetTypeId>>,
 ) {
-    for SerializedChangeInEvent(change, token) in reader.read() {
-        if !peers.valid_tokens.contains(token) {
+    // Peers only register the type groups they use, so updates for the rest are expected
+    let mut unknown_type = |token: &NetTypeId| {
+        if unknown_types.insert(token.clone()) {
+            info!("Ignoring {token}, its type isn't registered");
+        }
+    };
+
+    // Changes held for peers that have since been given permissions go first, they arrived first
+    permissions.retain_held(|peer| peers.is_valid(peer));
+    let released = permissions.take_released();
+    let changes = released
+        .iter()
+        .map(|(change, token)| (change, token))
+        .chain(
+            reader
+                .read()
+                .map(|SerializedChangeInEvent(change, token)| (change, token)),
+        );
+
+    for (change, token) in changes {
+        if let SerializedChange::EntitySpawned(forign) = change {
+            entity_map.reserved.remove(forign);
+        }
+
+        if !peers.is_valid(*token) {
             // The peer disconnected and has already been cleaned up
             continue;
         }
 
+        if !permissions.permits_change(*token, change) {
+            if !permissions.hold(*token, change) {
+                debug!("Dropped {change:?} from {token:?}, not permitted");
+            }
+
+            continue;
+        }
+
         match change {
             SerializedChange::EntitySpawned(forign) => {
+                if entity_map.forign_to_local.contains_key(forign) {
+                    warn!("Dropped duplicate spawn of {forign:?} from {token:?}");
+                    continue;
+                }
+
                 let local = cmds.spawn((Replicate, *forign, ForignOwned(token.0))).id();
 
                 entity_map.local_to_forign.insert(local, *forign);
@@ -84,7 +123,7 @@ fn apply_changes(
                 };
 
                 let Some(sync_info) = settings.component_by_token.get(token) else {
-                    error!("Got update for unknown entity token");
+                    unknown_type(token);
                     continue;
                 };
 
@@ -145,7 +184,7 @@ fn apply_changes(
                 };
 
                 let Some(sync_info) = settings.component_by_token.get(token) else {
-                    error!("Got update for unknown entity token");
+                    unknown_type(token);
                     continue;
                 };
 
@@ -162,7 +201,7 @@ fn apply_changes(
             }
             SerializedChange::EventEmitted(token, serialized) => {
                 let Some(sync_info) = settings.event_by_token.get(token) else {
-                    error!("Got unknown event");
+                    unknown_type(token);
                     continue;
                 };
 
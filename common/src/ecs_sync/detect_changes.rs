@@ -19,14 +19,15 @@ use bevy::ecs::{
     system::{Commands, Query, Res, ResMut, SystemChangeTick},
     world::{EntityRef, World},
 };
-use bevy::utils::HashSet;
+use bevy::utils::HashMap;
+use tracing::warn;
 
 use crate::adapters::dynamic::DynamicAdapter;
 use crate::adapters::{ComponentTypeAdapter, EventTypeAdapter};
 
 use super::{
-    EntityMap, ErasedManualEventReader, EventInfo, NetId, Replicate, SerializationSettings,
-    SerializedChange, SerializedChangeInEvent, SerializedChangeOutEvent,
+    EntityMap, ErasedManualEventReader, EventInfo, NetId, NetIdAllocator, Replicate,
+    SerializationSettings, SerializedChange, SerializedChangeInEvent, SerializedChangeOutEvent,
 };
 
 // TODO(mid): Events as RPC
@@ -62,13 +63,25 @@ fn detect_new_entities(
     mut cmds: Commands,
     new_entities: Query<(Entity, Option<&NetId>), Added<Replicate>>,
     mut entity_map: ResMut<EntityMap>,
+    mut ids: ResMut<NetIdAllocator>,
     mut events: EventWriter<SerializedChangeOutRawEvent>,
 ) {
     for (entity_id, net_id) in &new_entities {
         let remote_entity = net_id
             .or_else(|| entity_map.local_to_forign.get(&entity_id))
             .copied()
-            .unwrap_or_else(NetId::random);
+            .filter(|net_id| {
+                let taken = entity_map
+                    .forign_to_local
+                    .get(net_id)
+                    .is_some_and(|it| *it != entity_id);
+                if taken {
+                    warn!("NetId collision, {net_id:?} on {entity_id} is already in use");
+                }
+
+                !taken
+            })
+            .unwrap_or_else(|| ids.allocate());
 
         entity_map.local_to_forign.insert(entity_id, remote_entity);
         entity_map.forign_to_local.insert(remote_entity, entity_id);
@@ -303,16 +316,31 @@ fn detect_despawns(
 }
 
 fn filter_detections(
+    entity_map: Res<EntityMap>,
     mut raw: EventReader<SerializedChangeOutRawEvent>,
     mut inbound: EventReader<SerializedChangeInEvent>,
     mut events: EventWriter<SerializedChangeOutEvent>,
 ) {
-    let inbound = inbound.read().map(|it| &it.0).collect::<HashSet<_>>();
-
-    events.send_batch(
-        raw.read()
-            .map(|it| it.0.clone())
-            .filter(|it| !inbound.contains(it))
-            .map(SerializedChangeOutEvent),
-    );
+    let inbound = inbound
+        .read()
+        .map(|it| (&it.0, it.1))
+        .collect::<HashMap<_, _>>();
+
+    events.send_batch(raw.read().filter_map(|it| {
+        let Some(&origin) = inbound.get(&it.0) else {
+            return Some(SerializedChangeOutEvent(it.0.clone(), None));
+        };
+
+        // Changes a peer made to one of our entities still need to reach the other peers
+        let SerializedChange::ComponentUpdated(net_id, ..) = &it.0 else {
+            return None;
+        };
+        let entity = entity_map.forign_to_local.get(net_id)?;
+        let forign_owned = entity_map
+            .forign_owned
+            .values()
+            .any(|forign_set| forign_set.contains(entity));
+
+        (!forign_owned).then(|| SerializedChangeOutEvent(it.0.clone(), Some(origin)))
+    }));
 }
@@ -0,0 +1,78 @@
+//! Debug checks that every replicated entity has a `NetId` nothing else in the session uses
+
+use ahash::{HashMap, HashSet};
+use bevy::{
+    app::{App, Last, Plugin},
+    ecs::{
+        entity::Entity,
+        query::With,
+        schedule::IntoSystemConfigs,
+        system::{Local, Query, Res, Resource},
+    },
+};
+use tracing::error;
+
+use super::{EntityMap, ForignOwned, NetId, Replicate};
+
+pub struct NetIdVerificationPlugin;
+
+impl Plugin for NetIdVerificationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetIdVerification>().add_systems(
+            Last,
+            verify_net_ids.run_if(|verification: Res<NetIdVerification>| verification.0),
+        );
+    }
+}
+
+/// Walks every replicated entity each frame, on by default in debug builds
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct NetIdVerification(pub bool);
+
+impl Default for NetIdVerification {
+    fn default() -> Self {
+        Self(cfg!(debug_assertions))
+    }
+}
+
+fn verify_net_ids(
+    entity_map: Res<EntityMap>,
+    entities: Query<(Entity, &NetId, Option<&ForignOwned>), With<Replicate>>,
+    // Whether each id seen this session belonged to a local entity
+    mut history: Local<HashMap<NetId, bool>>,
+    mut reported: Local<HashSet<NetId>>,
+) {
+    let mut live = HashMap::<NetId, Entity>::default();
+
+    for (entity, &net_id, owner) in &entities {
+        if let Some(other) = live.insert(net_id, entity) {
+            if reported.insert(net_id) {
+                error!("NetId {net_id:?} is used by both {other} and {entity}");
+            }
+        }
+
+        let local = owner.is_none();
+        let was_local = *history.entry(net_id).or_insert(local);
+        if was_local != local && reported.insert(net_id) {
+            error!(
+                "NetId {net_id:?} was reused, it belonged to a {} entity and is now on {entity}",
+                if was_local { "local" } else { "remote" }
+            );
+        }
+
+        match entity_map.local_to_forign.get(&entity) {
+            Some(mapped) if mapped != &net_id => {
+                if reported.insert(net_id) {
+                    error!("{entity} has NetId {net_id:?} but is mapped to {mapped:?}");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (local, forign) in &entity_map.local_to_forign {
+        if entity_map.forign_to_local.get(forign) != Some(local) && reported.insert(*forign) {
+            error!("The entity map is inconsistent for {local} and {forign:?}");
+        }
+    }
+}
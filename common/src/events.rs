@@ -1,30 +1,73 @@
+use std::time::Duration;
+
 use bevy::{
     app::App,
     ecs::event::Event,
     reflect::{Reflect, ReflectDeserialize, ReflectSerialize},
 };
+use motor_math::glam::ThrusterGlam;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    adapters::serde::ReflectSerdeAdapter, components::GenericMotorId, ecs_sync::AppReplicateExt,
+    adapters::serde::ReflectSerdeAdapter,
+    components::{Capabilities, GenericMotorId, MaintenanceRoutine, ParamValue, PidConfig},
+    ecs_sync::{AppReplicateExt, NetId},
+    types::video::CameraQuality,
 };
 
 macro_rules! events {
-    ($($name:ident),*) => {
-        pub fn register_events(app: &mut App) {
-            $(
-                app.replicate_event::<$name>();
-            )*
-        }
+    ($($group:ident => { $($name:ident),* $(,)? }),* $(,)?) => {
+        $(
+            pub fn $group(app: &mut App) {
+                $(
+                    app.replicate_event::<$name>();
+                )*
+            }
+        )*
     }
 }
 
 events! {
-    ResyncCameras,
-    CalibrateSeaLevel,
-    ResetYaw,
-    ResetServos,
-    ResetServo
+    register_core_events => {
+        FetchLogs,
+        LogLines,
+        Alarm,
+        SetCapabilities,
+        BeginUpdate,
+        UpdateChunk,
+        CancelUpdate,
+        UpdateStatus,
+        PowerCommand,
+        SetParameter,
+    },
+
+    register_actuator_events => {
+        ResetServos,
+        ResetServo,
+        GoToServoPreset,
+        SetThrusterLayout,
+        SetPidConfig,
+        IdentifyActuationLatency,
+        MaintenanceCommand,
+    },
+
+    register_sensor_events => {
+        CalibrateSeaLevel,
+        ResetYaw,
+        CalibrateLevel,
+        ClearLevelTrim,
+        ResetTetherTurns,
+    },
+
+    register_video_events => {
+        ResyncCameras,
+        SetCameraQuality,
+        RecoverCamera,
+    },
+
+    register_autonomy_events => {
+        GeofenceOverride,
+    },
 }
 
 #[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
@@ -39,10 +82,305 @@ pub struct CalibrateSeaLevel;
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ResetYaw;
 
+/// Records the robot's current pitch and roll as level, trimming out imu mounting error
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CalibrateLevel;
+
+/// Removes the trim recorded by `CalibrateLevel`
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClearLevelTrim;
+
 #[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ResetServos;
 
+/// Zeroes the robot's tether turn counter, sent once the tether has been untwisted
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ResetTetherTurns;
+
 #[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
 #[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ResetServo(pub GenericMotorId);
+
+/// Moves the robot's servos to the named entry of its `ServoPresets`
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct GoToServoPreset {
+    pub preset: String,
+    /// How far each servo moves towards its position per second. `None` jumps straight there,
+    /// still limited by the servo's slew rate
+    pub rate: Option<f32>,
+}
+
+/// Asks the robot for every persisted log line written at or after `since_ms`
+/// (milliseconds since the unix epoch)
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FetchLogs {
+    pub since_ms: u64,
+}
+
+/// A batch of json log lines sent in response to `FetchLogs`
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LogLines {
+    pub lines: Vec<String>,
+    /// Set on the final batch of a response
+    pub last: bool,
+}
+
+/// Restarts a camera's stream at a different quality, `device` is from its `CameraCapabilities`
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SetCameraQuality {
+    pub device: String,
+    pub quality: CameraQuality,
+}
+
+/// Asks the robot to reset a camera whose stream stalled and restart its stream, `device` is from
+/// its `CameraCapabilities`
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RecoverCamera {
+    pub device: String,
+}
+
+/// Replaces the robot's thrusters with a custom layout and saves it to the robot's config, only
+/// accepted while the robot is disarmed
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SetThrusterLayout {
+    pub thrusters: Vec<ThrusterLayout>,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+pub struct ThrusterLayout {
+    pub name: String,
+    pub channel: GenericMotorId,
+    pub thruster: ThrusterGlam,
+}
+
+/// A condition on the robot the operators should be told about
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlarmKind {
+    /// Any error reported through `Errors` on the robot
+    Error,
+    Leak,
+    LowVoltage,
+    InactivityDisarm,
+    /// The measured current draw disagrees with the actuators' predicted draw
+    CurrentAnomaly,
+    /// The tether has been twisted past the configured number of turns
+    TetherTwist,
+    /// The robot went past its depth or distance limit and took control from the pilot
+    Geofence,
+    /// The depth sensor stopped reporting
+    DepthSensor,
+}
+
+impl AlarmKind {
+    /// Critical alarms stay on screen until acknowledged, the rest are shown as toasts
+    pub fn is_critical(&self) -> bool {
+        !matches!(self, AlarmKind::Error)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlarmKind::Error => "Error",
+            AlarmKind::Leak => "Leak",
+            AlarmKind::LowVoltage => "Low Voltage",
+            AlarmKind::InactivityDisarm => "Disarmed Due To Inactivity",
+            AlarmKind::CurrentAnomaly => "Current Anomaly",
+            AlarmKind::TetherTwist => "Tether Twist",
+            AlarmKind::Geofence => "Geofence",
+            AlarmKind::DepthSensor => "Depth Sensor",
+        }
+    }
+}
+
+/// Replaces the `PidConfig` of the robot's pid controller named `controller` and saves it to the
+/// robot's config
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SetPidConfig {
+    pub controller: String,
+    pub config: PidConfig,
+}
+
+/// Chirps the axis of the robot's pid controller named `controller` to measure its
+/// `ActuationLatency`, which is saved to the robot's config
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct IdentifyActuationLatency {
+    pub controller: String,
+    /// Peak of the chirp, in newton meters
+    pub amplitude: f32,
+    pub duration: Duration,
+}
+
+/// Grants or revokes what a co-pilot station may control, only accepted from the pilot
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SetCapabilities {
+    pub surface: NetId,
+    pub capabilities: Capabilities,
+}
+
+/// Starts pushing a new robot binary, followed by `UpdateChunk`s covering `size` bytes. Only
+/// accepted while the robot is disarmed
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BeginUpdate {
+    pub size: u64,
+    /// Hex checksum from `update::checksum`, keyed with the robot's update key when it has one
+    pub checksum: String,
+    /// Install even if the robot already runs the same version
+    pub force: bool,
+}
+
+/// Part of the binary announced by `BeginUpdate`, chunks must arrive in order
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct UpdateChunk {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// Abandons the update being received, the running binary is left untouched
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CancelUpdate;
+
+/// Progress of an update, sent by the robot as it advances and to surfaces that connect while a
+/// new binary is being self tested
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct UpdateStatus {
+    pub state: UpdateState,
+    /// Bytes of the binary received so far
+    pub received: u64,
+    pub total: u64,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateState {
+    Receiving,
+    /// The checksum matched, the binary is being checked and swapped in
+    Installing,
+    /// Waiting to restart into the new binary, postponed while armed
+    Restarting,
+    /// The new binary is running but has not been up for long enough to be kept
+    SelfTest,
+    Installed,
+    /// The new binary failed its self test and the previous one was restored
+    RolledBack,
+    Failed,
+    Cancelled,
+}
+
+impl UpdateState {
+    /// No more progress will be reported for this update
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            UpdateState::Installed
+                | UpdateState::RolledBack
+                | UpdateState::Failed
+                | UpdateState::Cancelled
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpdateState::Receiving => "Receiving",
+            UpdateState::Installing => "Installing",
+            UpdateState::Restarting => "Restarting",
+            UpdateState::SelfTest => "Self Test",
+            UpdateState::Installed => "Installed",
+            UpdateState::RolledBack => "Rolled Back",
+            UpdateState::Failed => "Failed",
+            UpdateState::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// Disarms the robot, stops its hardware threads and flushes its logs before acting
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PowerCommand(pub PowerAction);
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    /// Exits the robot process, systemd starts it again
+    RestartProcess,
+    RebootOs,
+    /// Powers the robot's computer off, it stays off until power is cycled
+    Shutdown,
+}
+
+impl PowerAction {
+    pub const ALL: [PowerAction; 3] = [
+        PowerAction::RestartProcess,
+        PowerAction::RebootOs,
+        PowerAction::Shutdown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerAction::RestartProcess => "Restart Robot Process",
+            PowerAction::RebootOs => "Reboot Robot OS",
+            PowerAction::Shutdown => "Safe Shutdown",
+        }
+    }
+}
+
+/// Overrides the robot's geofence in two steps so it can't be done with a stray click
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct GeofenceOverride(pub GeofenceOverrideStep);
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeofenceOverrideStep {
+    /// The robot answers by publishing `Geofence::override_challenge`
+    Request,
+    /// Echoes the challenge back, the override starts if it matches
+    Confirm(u32),
+    /// Drops a pending challenge or ends the override
+    Cancel,
+}
+
+/// Starts or stops one of the robot's maintenance routines, starting takes two steps so it can't be
+/// done with a stray click
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MaintenanceCommand(pub MaintenanceStep);
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceStep {
+    /// The robot answers by publishing `Maintenance::override_challenge`, only while disarmed
+    Request(MaintenanceRoutine),
+    /// Echoes the challenge back, the routine starts if it matches
+    Confirm(u32),
+    /// Drops a pending challenge or stops the running routine
+    Cancel,
+}
+
+/// Changes a parameter on every peer that registered `key` and saves it as an override, `None`
+/// goes back to the default
+#[derive(Event, Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(SerdeAdapter, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SetParameter {
+    pub key: String,
+    pub value: Option<ParamValue>,
+}
@@ -32,4 +32,13 @@ impl GitMetadata {
             dirty,
         })
     }
+
+    /// The commit hash, suffixed with `-dirty` when built with uncommitted changes
+    pub fn version(&self) -> String {
+        if self.dirty == "true" {
+            format!("{}-dirty", self.commit_hash)
+        } else {
+            self.commit_hash.to_string()
+        }
+    }
 }
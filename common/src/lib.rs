@@ -10,8 +10,8 @@ use bevy::{
     transform::components::Transform,
 };
 use ecs_sync::{
-    apply_changes::ChangeApplicationPlugin, detect_changes::ChangeDetectionPlugin, AppReplicateExt,
-    NetId, Replicate,
+    apply_changes::ChangeApplicationPlugin, detect_changes::ChangeDetectionPlugin,
+    verify::NetIdVerificationPlugin, AppReplicateExt, NetId, Replicate,
 };
 use error::ErrorPlugin;
 use git::GitMetadata;
@@ -20,6 +20,8 @@ use signal_handler::SignalPlugin;
 use sync::{Latency, SyncPlugin, SyncRole};
 
 pub mod adapters;
+pub mod attitude;
+pub mod behavior;
 pub mod bundles;
 pub mod components;
 pub mod ecs_sync;
@@ -27,19 +29,25 @@ pub mod error;
 pub mod events;
 pub mod git;
 pub mod over_run;
+pub mod params;
 pub mod protocol;
 pub mod reflect;
 pub mod signal_handler;
 pub mod sync;
 pub mod types;
+pub mod update;
+#[cfg(feature = "rerun")]
+pub mod visualization;
 
+/// The types every peer needs to replicate with any other, the rest are split into groups so a
+/// peer only registers what it uses. Updates for types from a group a peer left out are dropped
 pub struct CommunicationTypes;
 
 impl Plugin for CommunicationTypes {
     fn build(&self, app: &mut App) {
-        types::register_types(app);
-        components::register_components(app);
-        events::register_events(app);
+        types::register_core_types(app);
+        components::register_core_components(app);
+        events::register_core_events(app);
 
         app.register_type::<NetId>()
             .register_type::<Replicate>()
@@ -51,6 +59,50 @@ impl Plugin for CommunicationTypes {
     }
 }
 
+/// Motors, thrusters, pid controllers and power
+pub struct ActuatorTypes;
+
+impl Plugin for ActuatorTypes {
+    fn build(&self, app: &mut App) {
+        components::register_actuator_components(app);
+        events::register_actuator_events(app);
+    }
+}
+
+/// Sensor readings, camera definitions and system monitoring
+pub struct SensorTypes;
+
+impl Plugin for SensorTypes {
+    fn build(&self, app: &mut App) {
+        types::register_sensor_types(app);
+        components::register_sensor_components(app);
+        events::register_sensor_events(app);
+    }
+}
+
+/// The video pipeline, how each camera is streamed and calibrated
+pub struct VideoTypes;
+
+impl Plugin for VideoTypes {
+    fn build(&self, app: &mut App) {
+        types::register_video_types(app);
+        components::register_video_components(app);
+        events::register_video_events(app);
+    }
+}
+
+/// Control targets, pilot modes, the geofence and behavior trees
+pub struct AutonomyTypes;
+
+impl Plugin for AutonomyTypes {
+    fn build(&self, app: &mut App) {
+        types::register_autonomy_types(app);
+        components::register_autonomy_components(app);
+        events::register_autonomy_events(app);
+    }
+}
+
+/// Every type group is enabled, apps that don't need one can disable it when building the group
 pub struct CommonPlugins {
     pub name: String,
     pub role: SyncRole,
@@ -69,8 +121,13 @@ impl PluginGroup for CommonPlugins {
             })
             .add(SyncPlugin(self.role))
             .add(CommunicationTypes)
+            .add(ActuatorTypes)
+            .add(SensorTypes)
+            .add(VideoTypes)
+            .add(AutonomyTypes)
             .add(ChangeDetectionPlugin)
             .add(ChangeApplicationPlugin)
+            .add(NetIdVerificationPlugin)
             .add(SignalPlugin)
             .add(ErrorPlugin)
             .add(OverRunPligin)
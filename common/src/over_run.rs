@@ -1,7 +1,32 @@
-use std::time::{Duration, Instant};
+//! Detects ticks and schedules that take longer than their budget
+//!
+//! The whole tick, `Update`, each run of `FixedUpdate` and the time between ticks each get their
+//! own budget. Every over run is reported as an error and recorded in `OverRunStats`, along with
+//! the systems that ran during it when `system_timing_layer` is installed.
 
+use std::{
+    fmt::Debug,
+    mem,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ahash::HashMap;
 use anyhow::anyhow;
-use bevy::prelude::*;
+use bevy::{
+    app::{FixedMainScheduleOrder, MainScheduleOrder},
+    ecs::schedule::ScheduleLabel,
+    log::{
+        tracing_subscriber::{layer::Context, registry::LookupSpan, Layer},
+        BoxedLayer,
+    },
+    prelude::*,
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Subscriber,
+};
 
 use crate::error::ErrorEvent;
 
@@ -9,16 +34,43 @@ pub struct OverRunPligin;
 
 impl Plugin for OverRunPligin {
     fn build(&self, app: &mut App) {
+        let mut order = app.world_mut().resource_mut::<MainScheduleOrder>();
+        order.insert_after(RunFixedMainLoop, BeginUpdate);
+        order.insert_after(Update, EndUpdate);
+
+        let mut order = app.world_mut().resource_mut::<FixedMainScheduleOrder>();
+        order.insert_after(FixedPreUpdate, BeginFixedUpdate);
+        order.insert_after(FixedUpdate, EndFixedUpdate);
+
         app.init_resource::<OverRunSettings>()
+            .init_resource::<OverRunStats>()
+            .init_resource::<ScheduleStarts>()
             .add_systems(First, begin_tick)
+            .add_systems(BeginUpdate, begin_update)
+            .add_systems(EndUpdate, end_update)
+            .add_systems(BeginFixedUpdate, begin_fixed_update)
+            .add_systems(EndFixedUpdate, end_fixed_update)
             // TODO(low): run before error system
             .add_systems(Last, detect_overrun);
+
+        #[cfg(feature = "window")]
+        app.add_systems(First, track_focus.before(begin_tick));
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Debug, Clone)]
 pub struct OverRunSettings {
+    /// Budget for a whole tick, from the start of `First` to the end of `Last`
     pub max_time: Duration,
+    /// Budget for a single run of `Update`
+    pub update_max_time: Option<Duration>,
+    /// Budget for a single run of `FixedUpdate`, which can run several times per tick
+    pub fixed_update_max_time: Option<Duration>,
+    /// Budget for the time between the start of consecutive ticks. Unlike `max_time` this
+    /// includes presenting and waiting on the render world
+    pub render_max_time: Option<Duration>,
+    /// How the budgets change while none of the app's windows are focused
+    pub unfocused: UnfocusedBudget,
     pub tracy_frame_mark: bool,
 }
 
@@ -26,37 +78,283 @@ impl Default for OverRunSettings {
     fn default() -> Self {
         Self {
             max_time: Duration::from_secs_f32(1.0 / 100.0),
+            update_max_time: None,
+            fixed_update_max_time: None,
+            render_max_time: None,
+            unfocused: UnfocusedBudget::Unchanged,
             tracy_frame_mark: true,
         }
     }
 }
 
+impl OverRunSettings {
+    /// The limit currently in effect for `budget`, `None` when it isn't checked
+    pub fn limit(&self, budget: Budget, focused: bool) -> Option<Duration> {
+        let limit = match budget {
+            Budget::Tick => Some(self.max_time),
+            Budget::Update => self.update_max_time,
+            Budget::FixedUpdate => self.fixed_update_max_time,
+            Budget::Render => self.render_max_time,
+        }?;
+
+        if focused {
+            return Some(limit);
+        }
+
+        match self.unfocused {
+            UnfocusedBudget::Unchanged => Some(limit),
+            UnfocusedBudget::Scaled(scale) => Some(limit.mul_f32(scale)),
+            UnfocusedBudget::Unchecked => None,
+        }
+    }
+}
+
+/// Windowed apps get throttled by the OS and compositor while in the background
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnfocusedBudget {
+    #[default]
+    Unchanged,
+    /// Multiplies every budget
+    Scaled(f32),
+    /// Stops checking budgets until focus returns
+    Unchecked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Budget {
+    Tick,
+    Update,
+    FixedUpdate,
+    Render,
+}
+
+impl Budget {
+    pub const ALL: [Budget; 4] = [
+        Budget::Tick,
+        Budget::Update,
+        Budget::FixedUpdate,
+        Budget::Render,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Budget::Tick => "Tick",
+            Budget::Update => "Update",
+            Budget::FixedUpdate => "Fixed Update",
+            Budget::Render => "Render",
+        }
+    }
+}
+
+/// Over run history since startup or the last reset
+#[derive(Resource, Debug, Default)]
+pub struct OverRunStats {
+    pub budgets: HashMap<Budget, BudgetStats>,
+    /// Only filled in when `system_timing_layer` is installed and bevy is built with `trace`
+    pub systems: HashMap<String, SystemStats>,
+    /// Set while none of the app's windows are focused
+    pub unfocused: bool,
+
+    overran: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BudgetStats {
+    pub runs: u64,
+    pub overruns: u64,
+    pub last: Duration,
+    pub worst: Duration,
+}
+
+/// How a system behaved during the ticks that over ran
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemStats {
+    pub overruns: u64,
+    /// Total time spent in the system across all of those ticks
+    pub total: Duration,
+    /// Longest time spent in the system in one of those ticks
+    pub worst: Duration,
+}
+
+impl OverRunStats {
+    pub fn reset(&mut self) {
+        self.budgets.clear();
+        self.systems.clear();
+    }
+
+    /// The systems that took the most time across all over runs, slowest first
+    pub fn worst_systems(&self, count: usize) -> Vec<(&str, &SystemStats)> {
+        let mut systems = self
+            .systems
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+            .collect::<Vec<_>>();
+        systems.sort_by(|a, b| b.1.total.cmp(&a.1.total));
+        systems.truncate(count);
+
+        systems
+    }
+}
+
 #[derive(Resource)]
 pub struct TickStart(Instant);
 
-fn begin_tick(mut cmds: Commands) {
-    cmds.insert_resource(TickStart(Instant::now()))
+#[derive(Resource, Default)]
+struct ScheduleStarts {
+    update: Option<Instant>,
+    fixed_update: Option<Instant>,
 }
 
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct BeginUpdate;
+
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct EndUpdate;
+
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct BeginFixedUpdate;
+
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct EndFixedUpdate;
+
 const TOLERANCE: Duration = Duration::from_micros(300);
 
+fn check_budget(
+    budget: Budget,
+    elapsed: Duration,
+    settings: &OverRunSettings,
+    stats: &mut OverRunStats,
+    errors: &mut EventWriter<ErrorEvent>,
+) {
+    let limit = settings.limit(budget, !stats.unfocused);
+    let overran = limit.filter(|limit| elapsed > *limit + TOLERANCE);
+
+    let budget_stats = stats.budgets.entry(budget).or_default();
+    budget_stats.runs += 1;
+    budget_stats.last = elapsed;
+    budget_stats.worst = budget_stats.worst.max(elapsed);
+
+    if let Some(limit) = overran {
+        budget_stats.overruns += 1;
+        stats.overran = true;
+
+        errors.send(
+            anyhow!(
+                "{} time over run. Last run took {:.4}, exceeding limit of {:.4}",
+                budget.name(),
+                elapsed.as_secs_f32(),
+                limit.as_secs_f32()
+            )
+            .into(),
+        );
+    }
+}
+
+#[cfg(feature = "window")]
+fn track_focus(windows: Query<&Window>, mut stats: ResMut<OverRunStats>) {
+    // Headless apps are always treated as focused
+    let unfocused = !windows.is_empty() && !windows.iter().any(|it| it.focused);
+
+    if stats.unfocused != unfocused {
+        stats.unfocused = unfocused;
+    }
+}
+
+fn begin_tick(
+    mut cmds: Commands,
+    mut last_start: Local<Option<Instant>>,
+
+    settings: Res<OverRunSettings>,
+    mut stats: ResMut<OverRunStats>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let now = Instant::now();
+
+    if let Some(last_start) = last_start.replace(now) {
+        check_budget(
+            Budget::Render,
+            now - last_start,
+            &settings,
+            &mut stats,
+            &mut errors,
+        );
+    }
+
+    cmds.insert_resource(TickStart(now))
+}
+
+fn begin_update(mut starts: ResMut<ScheduleStarts>) {
+    starts.update = Some(Instant::now());
+}
+
+fn end_update(
+    mut starts: ResMut<ScheduleStarts>,
+    settings: Res<OverRunSettings>,
+    mut stats: ResMut<OverRunStats>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    if let Some(start) = starts.update.take() {
+        check_budget(
+            Budget::Update,
+            start.elapsed(),
+            &settings,
+            &mut stats,
+            &mut errors,
+        );
+    }
+}
+
+fn begin_fixed_update(mut starts: ResMut<ScheduleStarts>) {
+    starts.fixed_update = Some(Instant::now());
+}
+
+fn end_fixed_update(
+    mut starts: ResMut<ScheduleStarts>,
+    settings: Res<OverRunSettings>,
+    mut stats: ResMut<OverRunStats>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    if let Some(start) = starts.fixed_update.take() {
+        check_budget(
+            Budget::FixedUpdate,
+            start.elapsed(),
+            &settings,
+            &mut stats,
+            &mut errors,
+        );
+    }
+}
+
 fn detect_overrun(
     settings: Res<OverRunSettings>,
     start: Option<Res<TickStart>>,
+    timings: Option<Res<SystemTimings>>,
+    mut stats: ResMut<OverRunStats>,
     mut errors: EventWriter<ErrorEvent>,
 ) {
     if let Some(start) = start {
-        let frame_time = start.0.elapsed();
-
-        if frame_time > settings.max_time + TOLERANCE {
-            errors.send(
-                anyhow!(
-                    "Max loop time over run. Last tick took {:.4}, exceeding limit of {:.4}",
-                    frame_time.as_secs_f32(),
-                    settings.max_time.as_secs_f32()
-                )
-                .into(),
-            );
+        check_budget(
+            Budget::Tick,
+            start.0.elapsed(),
+            &settings,
+            &mut stats,
+            &mut errors,
+        );
+    }
+
+    let overran = mem::take(&mut stats.overran);
+
+    if let Some(timings) = timings {
+        // Always drained so the next tick starts from zero
+        let timings = mem::take(&mut *timings.0.lock().unwrap());
+
+        if overran {
+            for (name, time) in timings {
+                let system = stats.systems.entry(name).or_default();
+                system.overruns += 1;
+                system.total += time;
+                system.worst = system.worst.max(time);
+            }
         }
     }
 
@@ -65,3 +363,124 @@ fn detect_overrun(
         info!(message = "finished frame", tracy.frame_mark = true);
     }
 }
+
+/// Time spent in each system since the start of the tick
+#[derive(Resource, Clone, Default)]
+struct SystemTimings(Arc<Mutex<HashMap<String, Duration>>>);
+
+/// Passed to `LogPlugin::custom_layer` to attribute over runs to the systems that ran during
+/// them. Bevy only creates spans for systems when built with the `trace` feature
+pub fn system_timing_layer(app: &mut App) -> Option<BoxedLayer> {
+    let timings = SystemTimings::default();
+    app.insert_resource(timings.clone());
+
+    Some(Box::new(SystemTimingLayer(timings)))
+}
+
+struct SystemTimingLayer(SystemTimings);
+
+/// Stored in the extensions of a system's span
+struct SystemSpan {
+    name: String,
+    entered: Option<Instant>,
+}
+
+impl<S> Layer<S> for SystemTimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != "system" {
+            return;
+        }
+
+        let mut visitor = NameVisitor(None);
+        attrs.record(&mut visitor);
+
+        if let (Some(name), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SystemSpan {
+                name,
+                entered: None,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        if let Some(system) = span.extensions_mut().get_mut::<SystemSpan>() {
+            system.entered = Some(Instant::now());
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(system) = extensions.get_mut::<SystemSpan>() else {
+            return;
+        };
+        let Some(entered) = system.entered.take() else {
+            return;
+        };
+
+        let elapsed = entered.elapsed();
+        let mut timings = self.0 .0.lock().unwrap();
+        match timings.get_mut(&system.name) {
+            Some(time) => *time += elapsed,
+            None => {
+                timings.insert(system.name.clone(), elapsed);
+            }
+        }
+    }
+}
+
+struct NameVisitor(Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unfocused_budgets() {
+        let settings = OverRunSettings {
+            max_time: Duration::from_millis(10),
+            update_max_time: Some(Duration::from_millis(4)),
+            unfocused: UnfocusedBudget::Scaled(3.0),
+            ..default()
+        };
+
+        assert_eq!(
+            settings.limit(Budget::Tick, true),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            settings.limit(Budget::Update, false),
+            Some(Duration::from_millis(12))
+        );
+        assert_eq!(settings.limit(Budget::Render, false), None);
+
+        let settings = OverRunSettings {
+            unfocused: UnfocusedBudget::Unchecked,
+            ..settings
+        };
+        assert_eq!(settings.limit(Budget::Tick, false), None);
+    }
+}
@@ -0,0 +1,348 @@
+//! Parameters that can be tuned while running instead of being compile time constants
+//!
+//! Each parameter is declared as a `Param` constant next to the code that reads it and registered
+//! with `AppParamExt::register_param`. Values live in the `Params` resource and are changed by the
+//! `SetParameter` event from any peer. Anything that differs from the default is saved to the
+//! file given to `ParamsPlugin` so it survives restarts. The robot publishes its parameters as the
+//! replicated `Parameters` component, letting the surface tune parameters it doesn't know about.
+
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+
+use crate::{
+    components::{ParamEntry, ParamValue},
+    error::ErrorEvent,
+    events::SetParameter,
+};
+
+pub struct ParamsPlugin {
+    pub path: PathBuf,
+}
+
+impl Plugin for ParamsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Params>()
+            .insert_resource(ParamsFile(self.path.clone()))
+            .add_systems(PreStartup, load_overrides)
+            .add_systems(
+                Update,
+                (
+                    apply_parameter_events,
+                    save_overrides.after(apply_parameter_events),
+                ),
+            );
+    }
+}
+
+/// Declares a tunable parameter, meant to be used as a constant
+#[derive(Debug, Clone, Copy)]
+pub struct Param<T> {
+    /// Unique across every peer, by convention `<app>.<subsystem>.<name>`
+    pub key: &'static str,
+    pub group: &'static str,
+    pub description: &'static str,
+    pub default: T,
+    pub min: T,
+    pub max: T,
+}
+
+impl<T: ParamType> Param<T> {
+    pub const fn new(
+        key: &'static str,
+        group: &'static str,
+        description: &'static str,
+        default: T,
+        min: T,
+        max: T,
+    ) -> Self {
+        Self {
+            key,
+            group,
+            description,
+            default,
+            min,
+            max,
+        }
+    }
+
+    pub fn entry(&self) -> ParamEntry {
+        ParamEntry {
+            key: self.key.to_owned(),
+            group: self.group.to_owned(),
+            description: self.description.to_owned(),
+            value: self.default.to_value(),
+            default: self.default.to_value(),
+            min: self.min.to_value(),
+            max: self.max.to_value(),
+        }
+    }
+}
+
+/// Types that can be stored in a `Param`
+pub trait ParamType: Copy + Send + Sync + 'static {
+    fn to_value(self) -> ParamValue;
+    fn from_value(value: ParamValue) -> Option<Self>;
+}
+
+impl ParamType for f32 {
+    fn to_value(self) -> ParamValue {
+        ParamValue::Float(self)
+    }
+
+    fn from_value(value: ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl ParamType for i64 {
+    fn to_value(self) -> ParamValue {
+        ParamValue::Int(self)
+    }
+
+    fn from_value(value: ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl ParamType for bool {
+    fn to_value(self) -> ParamValue {
+        ParamValue::Bool(self)
+    }
+
+    fn from_value(value: ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Stored as seconds
+impl ParamType for Duration {
+    fn to_value(self) -> ParamValue {
+        ParamValue::Float(self.as_secs_f32())
+    }
+
+    fn from_value(value: ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::Float(value) => Duration::try_from_secs_f32(value).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The current value of every registered parameter
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Params {
+    /// Sorted by key
+    entries: Vec<ParamEntry>,
+    /// Everything that differs from its default, including parameters this peer never registered
+    overrides: BTreeMap<String, ParamValue>,
+}
+
+impl Params {
+    pub fn get<T: ParamType>(&self, param: &Param<T>) -> T {
+        self.entry(param.key)
+            .and_then(|it| T::from_value(it.value))
+            .unwrap_or(param.default)
+    }
+
+    pub fn entry(&self, key: &str) -> Option<&ParamEntry> {
+        self.entries
+            .binary_search_by(|it| it.key.as_str().cmp(key))
+            .ok()
+            .map(|idx| &self.entries[idx])
+    }
+
+    pub fn entries(&self) -> &[ParamEntry] {
+        &self.entries
+    }
+
+    pub fn register(&mut self, mut entry: ParamEntry) {
+        if let Some(value) = self.overrides.get(&entry.key) {
+            if value.same_kind(&entry.default) {
+                entry.value = value.clamp(entry.min, entry.max);
+            }
+        }
+
+        match self
+            .entries
+            .binary_search_by(|it| it.key.as_str().cmp(&entry.key))
+        {
+            Ok(idx) => self.entries[idx] = entry,
+            Err(idx) => self.entries.insert(idx, entry),
+        }
+    }
+
+    /// Sets a registered parameter, clamped to its range. `None` goes back to the default
+    pub fn set(&mut self, key: &str, value: Option<ParamValue>) -> anyhow::Result<ParamValue> {
+        let Ok(idx) = self.entries.binary_search_by(|it| it.key.as_str().cmp(key)) else {
+            bail!("No parameter named {key}");
+        };
+        let entry = &mut self.entries[idx];
+
+        let value = match value {
+            Some(value) if !value.same_kind(&entry.default) => {
+                bail!(
+                    "{key} expects a value like {:?}, got {value:?}",
+                    entry.default
+                );
+            }
+            Some(value) if !value.is_finite() => {
+                bail!("{key} must be a finite number, got {value:?}");
+            }
+            Some(value) => value.clamp(entry.min, entry.max),
+            None => entry.default,
+        };
+
+        entry.value = value;
+        if entry.is_default() {
+            self.overrides.remove(key);
+        } else {
+            self.overrides.insert(key.to_owned(), value);
+        }
+
+        Ok(value)
+    }
+
+    fn load(&mut self, mut overrides: BTreeMap<String, ParamValue>) {
+        overrides.retain(|key, value| {
+            if !value.is_finite() {
+                warn!("Ignoring saved parameter {key}, {value:?} is not a finite number");
+            }
+
+            value.is_finite()
+        });
+        self.overrides = overrides;
+
+        let entries = self.entries.drain(..).collect::<Vec<_>>();
+        for mut entry in entries {
+            entry.value = entry.default;
+            self.register(entry);
+        }
+    }
+}
+
+pub trait AppParamExt {
+    fn register_param<T: ParamType>(&mut self, param: &Param<T>) -> &mut Self;
+}
+
+impl AppParamExt for App {
+    fn register_param<T: ParamType>(&mut self, param: &Param<T>) -> &mut Self {
+        self.init_resource::<Params>();
+        self.world_mut()
+            .resource_mut::<Params>()
+            .register(param.entry());
+
+        self
+    }
+}
+
+#[derive(Resource)]
+struct ParamsFile(PathBuf);
+
+fn load_overrides(file: Res<ParamsFile>, mut params: ResMut<Params>) {
+    let res: anyhow::Result<BTreeMap<String, ParamValue>> = try {
+        let overrides = fs::read_to_string(&file.0).context("Read parameters")?;
+        toml::from_str(&overrides).context("Parse parameters")?
+    };
+
+    match res {
+        Ok(overrides) => {
+            info!("Loaded {} parameter overrides", overrides.len());
+            params.load(overrides);
+        }
+        Err(err) => {
+            info!("Using default parameters: {err:?}");
+        }
+    }
+}
+
+fn apply_parameter_events(
+    mut events: EventReader<SetParameter>,
+    mut params: ResMut<Params>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for SetParameter { key, value } in events.read() {
+        // Every peer sees the event, only the ones that registered the parameter apply it
+        if params.entry(key).is_none() {
+            continue;
+        }
+
+        match params.set(key, *value) {
+            Ok(value) => info!("Set parameter {key} to {value:?}"),
+            Err(err) => {
+                errors.send(err.context("Set parameter").into());
+            }
+        }
+    }
+}
+
+fn save_overrides(file: Res<ParamsFile>, params: Res<Params>) {
+    if !params.is_changed() || params.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&params.overrides) else {
+        error!("Could not serialize parameters");
+        return;
+    };
+
+    let res = fs::write(&file.0, &str);
+    if let Err(err) = res {
+        error!("Could not write parameters: {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAIN: Param<f32> = Param::new("test.gain", "Test", "", 1.0, 0.0, 2.0);
+    const ENABLED: Param<bool> = Param::new("test.enabled", "Test", "", true, false, true);
+
+    #[test]
+    fn set_and_reset() {
+        let mut params = Params::default();
+        params.register(GAIN.entry());
+        params.register(ENABLED.entry());
+
+        assert_eq!(params.get(&GAIN), 1.0);
+
+        params.set(GAIN.key, Some(ParamValue::Float(5.0))).unwrap();
+        assert_eq!(params.get(&GAIN), 2.0);
+        assert!(params.set(GAIN.key, Some(ParamValue::Bool(false))).is_err());
+        assert!(params
+            .set(GAIN.key, Some(ParamValue::Float(f32::NAN)))
+            .is_err());
+        assert!(params
+            .set(GAIN.key, Some(ParamValue::Float(f32::INFINITY)))
+            .is_err());
+        assert_eq!(params.get(&GAIN), 2.0);
+        assert!(params.set("test.missing", None).is_err());
+
+        params.set(GAIN.key, None).unwrap();
+        assert_eq!(params.get(&GAIN), 1.0);
+        assert!(params.overrides.is_empty());
+    }
+
+    #[test]
+    fn overrides_apply_to_later_registrations() {
+        let mut params = Params::default();
+        params.load(BTreeMap::from([(
+            ENABLED.key.to_owned(),
+            ParamValue::Bool(false),
+        )]));
+        params.register(ENABLED.entry());
+
+        assert!(!params.get(&ENABLED));
+    }
+}
@@ -5,13 +5,23 @@ use bincode::{DefaultOptions, Options};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::{ecs_sync::SerializedChange, git::GitMetadata};
+use crate::{
+    adapters::delta::ByteDelta,
+    ecs_sync::{NetId, NetTypeId, SerializedChange},
+    git::GitMetadata,
+    sync::PeerKind,
+};
 
 /// Representation of all messages that can be communicated between peers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Protocol {
+    /// Sent first on every connection, says what the peer is
+    Hello(PeerKind),
     GitMetadata(GitMetadata),
     EcsUpdate(SerializedChange),
+    /// A `SerializedChange::ComponentUpdated` sent as the changes to the last value of the
+    /// component exchanged with this peer
+    EcsDelta(NetId, NetTypeId, ByteDelta),
     /// Asks the peer to reply with a Pong, used to measure communication latency
     Ping {
         payload: u32,
@@ -1,15 +1,20 @@
 use std::{
+    collections::VecDeque,
     net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
     thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    adapters,
-    components::Singleton,
+    adapters::{
+        self,
+        delta::{ByteDelta, MIN_DELTA_SIZE},
+    },
+    components::{Singleton, SurfaceRole},
     ecs_sync::{
         apply_changes::ChangeApplicationSet, detect_changes::ChangeDetectionSet, EntityMap,
-        ForignOwned, NetId, NetTypeId, SerializationSettings, SerializedChange,
-        SerializedChangeInEvent, SerializedChangeOutEvent,
+        ForignOwned, NetId, NetIdAllocator, NetTypeId, ReplicationPermissions,
+        SerializationSettings, SerializedChange, SerializedChangeInEvent, SerializedChangeOutEvent,
     },
     git::GitMetadata,
     protocol::Protocol,
@@ -21,11 +26,15 @@ use bevy::{app::AppExit, core::FrameCount, prelude::*};
 use crossbeam::channel::{self, Receiver};
 use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
 use networking::{Event as NetEvent, Messenger, Networking, Token as NetToken};
+use serde::{Deserialize, Serialize};
 
 use crate::error::{self, ErrorEvent, Errors};
 
 const SERVICE_TYPE: &str = "_bevy_ecs_sync._tcp.local.";
 
+/// Changes replayed from a recording are attributed to this token, networking never hands it out
+pub const REPLAY_TOKEN: NetToken = NetToken(usize::MAX);
+
 pub struct SyncPlugin(pub SyncRole);
 
 #[derive(Resource, Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -40,8 +49,12 @@ impl Plugin for SyncPlugin {
             .add_event::<SerializedChangeOutEvent>()
             .init_resource::<SerializationSettings>()
             .init_resource::<EntityMap>()
+            .init_resource::<NetIdAllocator>()
             .init_resource::<Deltas>()
+            .init_resource::<DeltaBaselines>()
             .init_resource::<Peers>()
+            .init_resource::<ReplicationPermissions>()
+            .init_resource::<LocalPeerKind>()
             .insert_resource(self.0)
             .add_event::<ConnectToPeer>()
             .add_event::<DisconnectPeer>()
@@ -73,19 +86,62 @@ impl Plugin for SyncPlugin {
     }
 }
 
+/// What a peer is, from the `Protocol::Hello` it opens the connection with. Inserted on the peer's
+/// entity, peers that never say are `Unknown`
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeerKind {
+    #[default]
+    Unknown,
+    Robot,
+    Surface(SurfaceRole),
+    /// Drives the robot on a surface's behalf, like the waterlinked autonomy
+    Bridge,
+}
+
+/// What this app tells its peers it is
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct LocalPeerKind(pub PeerKind);
+
 #[derive(Resource)]
 struct Net(Messenger<Protocol>, Receiver<NetEvent<Protocol>>);
 
+/// A connection whose peer entity hasn't been set up yet
+struct PendingPeer {
+    addrs: SocketAddr,
+    /// In frames
+    connected_at: u32,
+    git_meta: Option<GitMetadata>,
+    kind: PeerKind,
+}
+
 #[derive(Resource, Default)]
 pub struct Peers {
     by_token: HashMap<NetToken, Entity>,
     by_addrs: HashMap<SocketAddr, Entity>,
 
-    // In frames
-    pending: HashMap<NetToken, (SocketAddr, u32, Option<GitMetadata>)>,
+    pending: HashMap<NetToken, PendingPeer>,
 
     // TODO: This is kinda bad
     pub(crate) valid_tokens: HashSet<NetToken>,
+    /// Changes from `REPLAY_TOKEN` are only applied while set
+    replaying: bool,
+}
+
+impl Peers {
+    /// Whether changes from `token` should be applied
+    pub fn is_valid(&self, token: NetToken) -> bool {
+        self.valid_tokens.contains(&token) || (self.replaying && token == REPLAY_TOKEN)
+    }
+
+    /// Whether any peer is connected, replayed state is never sent to peers so a replay can only
+    /// run while this is false
+    pub fn is_connected(&self) -> bool {
+        !self.valid_tokens.is_empty()
+    }
+
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
 }
 
 #[derive(Component, Debug)]
@@ -100,6 +156,42 @@ pub struct Latency {
     pub last_ping_sent: Option<u32>,
     pub last_acknowledged: Option<u32>,
     pub ping: Option<u32>,
+
+    /// Wall clock round trip time of the last answered ping
+    pub rtt: Option<Duration>,
+    /// Smoothed difference between consecutive round trip times
+    pub jitter: Duration,
+    /// Pings left unanswered for longer than `HEARTBEAT_TIMEOUT` since the peer connected
+    pub missed_heartbeats: u32,
+    /// Whether each of the recent pings was answered in time, newest last
+    #[reflect(ignore)]
+    pub recent: VecDeque<bool>,
+
+    #[reflect(ignore)]
+    ping_sent_at: Option<Instant>,
+    /// The outstanding ping was already counted as missed
+    #[reflect(ignore)]
+    late: bool,
+}
+
+impl Latency {
+    /// Fraction of the recent pings that were not answered in time
+    pub fn loss(&self) -> f32 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+
+        let missed = self.recent.iter().filter(|it| !**it).count();
+        missed as f32 / self.recent.len() as f32
+    }
+
+    fn record_heartbeat(&mut self, on_time: bool) {
+        self.recent.push_back(on_time);
+
+        while self.recent.len() > HEARTBEAT_HISTORY {
+            self.recent.pop_front();
+        }
+    }
 }
 
 #[derive(Resource)]
@@ -276,6 +368,8 @@ fn net_read(
 
     mut peers: ResMut<Peers>,
     mut entity_map: ResMut<EntityMap>,
+    mut ids: ResMut<NetIdAllocator>,
+    mut baselines: ResMut<DeltaBaselines>,
     mut changes: EventWriter<SerializedChangeInEvent>,
     mut new_peers: EventWriter<SyncPeer>,
 
@@ -289,12 +383,32 @@ fn net_read(
                 info!(?token, ?addrs, "Peer connected");
 
                 new_peers.send(SyncPeer(token));
-                peers.pending.insert(token, (addrs, frame.0, None));
+                peers.pending.insert(
+                    token,
+                    PendingPeer {
+                        addrs,
+                        connected_at: frame.0,
+                        git_meta: None,
+                        kind: PeerKind::Unknown,
+                    },
+                );
 
                 peers.valid_tokens.insert(token);
             }
             NetEvent::Data(token, packet) => match packet {
                 Protocol::EcsUpdate(update) => {
+                    baselines.received(token, &update);
+
+                    let update = entity_map.remap_inbound(token, update, &mut ids);
+                    changes.send(SerializedChangeInEvent(update, token));
+                }
+                Protocol::EcsDelta(net_id, type_name, delta) => {
+                    let Some(update) = baselines.decode(token, net_id, type_name, &delta) else {
+                        errors.send(anyhow!("Got a delta without a base from {token:?}").into());
+                        continue;
+                    };
+
+                    let update = entity_map.remap_inbound(token, update, &mut ids);
                     changes.send(SerializedChangeInEvent(update, token));
                 }
                 Protocol::Ping { payload } => {
@@ -322,6 +436,37 @@ fn net_read(
 
                     latency.last_acknowledged = sent.into();
                     latency.ping = Some(frame.wrapping_sub(sent));
+
+                    if let Some(sent_at) = latency.ping_sent_at.take() {
+                        let rtt = sent_at.elapsed();
+
+                        // Same smoothing as RFC 3550's interarrival jitter
+                        if let Some(last_rtt) = latency.rtt {
+                            let delta = rtt.abs_diff(last_rtt);
+                            let jitter = latency.jitter;
+                            latency.jitter = if delta > jitter {
+                                jitter + (delta - jitter) / 16
+                            } else {
+                                jitter - (jitter - delta) / 16
+                            };
+                        }
+                        latency.rtt = Some(rtt);
+
+                        if !latency.late {
+                            latency.record_heartbeat(true);
+                        }
+                    }
+                }
+                Protocol::Hello(kind) => {
+                    info!(?token, ?kind, "Peer identified");
+
+                    if let Some(pending_peer) = peers.pending.get_mut(&token) {
+                        pending_peer.kind = kind;
+                    } else if let Some(&entity) = peers.by_token.get(&token) {
+                        cmds.entity(entity).insert(kind);
+                    } else {
+                        error!("Got hello from an unknown peer");
+                    }
                 }
                 Protocol::GitMetadata(git_metadata) => {
                     if Some(&git_metadata) != GitMetadata::new().as_ref() {
@@ -338,7 +483,7 @@ fn net_read(
                         error!("Got git metadata for a peer that is not pending");
                         continue;
                     };
-                    pending_peer.2 = Some(git_metadata);
+                    pending_peer.git_meta = Some(git_metadata);
                 }
             },
             NetEvent::Error(token, error) => {
@@ -361,23 +506,15 @@ fn net_read(
                 };
 
                 peers.by_addrs.remove(&peer.addrs);
+                baselines.remove(token);
 
                 // cmds.entity(entity).despawn();
-                if let Some(owned_entities) = entity_map.forign_owned.remove(&token) {
-                    for entity in owned_entities {
-                        let forign = entity_map.local_to_forign.remove(&entity);
-                        if let Some(forign) = forign {
-                            entity_map.forign_to_local.remove(&forign);
-                        };
-
-                        entity_map.local_modified.remove(&entity);
-
-                        let Some(mut entity) = cmds.get_entity(entity) else {
-                            continue;
-                        };
+                for entity in entity_map.forget_peer(token) {
+                    let Some(mut entity) = cmds.get_entity(entity) else {
+                        continue;
+                    };
 
-                        entity.despawn();
-                    }
+                    entity.despawn();
                 }
 
                 info!("Peer ({token:?}) at {} disconnected", peer.addrs);
@@ -387,11 +524,40 @@ fn net_read(
 }
 fn net_write(
     net: Res<Net>,
+    peers: Res<Peers>,
+    entity_map: Res<EntityMap>,
+    mut baselines: ResMut<DeltaBaselines>,
     mut changes: EventReader<SerializedChangeOutEvent>,
     mut errors: EventWriter<ErrorEvent>,
 ) {
-    for change in changes.read() {
-        let rst = net.0.brodcast_packet(Protocol::EcsUpdate(change.0.clone()));
+    let remapped = peers
+        .valid_tokens
+        .iter()
+        .any(|&peer| entity_map.has_remapped(peer));
+
+    for SerializedChangeOutEvent(change, origin) in changes.read() {
+        let rst = if origin.is_some() || remapped || DeltaBaselines::is_large(change) {
+            peers
+                .valid_tokens
+                .iter()
+                .filter(|&peer| Some(peer) != origin.as_ref())
+                .try_for_each(|&peer| {
+                    let change = entity_map.remap_outbound(peer, change);
+                    let rst = net.0.send_packet(peer, baselines.encode(peer, change));
+                    if rst.is_err() {
+                        // The peer may not have gotten the new base, what it sends us is unaffected
+                        baselines.reset_sent(peer);
+                    }
+
+                    rst
+                })
+        } else {
+            for &peer in &peers.valid_tokens {
+                baselines.sent(peer, change);
+            }
+
+            net.0.brodcast_packet(Protocol::EcsUpdate(change.clone()))
+        };
 
         if rst.is_err() {
             errors.send(anyhow!("Could not brodcast ECS update").into());
@@ -412,6 +578,7 @@ fn spawn_peer_entities(
     mut peers: ResMut<Peers>,
     mut entity_map: ResMut<EntityMap>,
     query: Query<(Entity, &ForignOwned), Added<Singleton>>,
+    fallbacks: Query<(&Peer, &PeerKind, Option<&GitMetadata>), Without<Singleton>>,
 ) {
     let peers = &mut *peers;
 
@@ -419,28 +586,57 @@ fn spawn_peer_entities(
         let token = NetToken(owner.0);
         let data = peers.pending.remove(&token);
 
-        if let Some((addrs, _, git_meta)) = data {
+        if let Some(pending) = data {
+            let addrs = pending.addrs;
             let mut entity_cmds = cmds.entity(entity);
-            entity_cmds.insert((Peer { addrs, token }, Latency::default()));
+            entity_cmds.insert((Peer { addrs, token }, pending.kind, Latency::default()));
 
-            if let Some(git_meta) = git_meta {
+            if let Some(git_meta) = pending.git_meta {
                 entity_cmds.insert(git_meta);
             }
 
             peers.by_token.insert(token, entity);
             peers.by_addrs.insert(addrs, entity);
+        } else if let Some(&fallback) = peers.by_token.get(&token) {
+            // The singleton arrived after the deadline, move the peer onto it so it is identified
+            // the same as any other
+            let Ok((peer, kind, git_meta)) = fallbacks.get(fallback) else {
+                continue;
+            };
+
+            let mut entity_cmds = cmds.entity(entity);
+            entity_cmds.insert((
+                Peer {
+                    addrs: peer.addrs,
+                    token,
+                },
+                *kind,
+                Latency::default(),
+            ));
+            if let Some(git_meta) = git_meta {
+                entity_cmds.insert(git_meta.clone());
+            }
+            cmds.entity(fallback).despawn();
+
+            if let Some(owned) = entity_map.forign_owned.get_mut(&token) {
+                owned.remove(&fallback);
+            }
+            peers.by_token.insert(token, entity);
+            peers.by_addrs.insert(peer.addrs, entity);
         }
     }
 
     let frame = frame.0;
     peers
         .pending
-        .extract_if(|_, (_, time, _)| frame.wrapping_sub(*time) > SINGLETON_DEADLINE)
-        .for_each(|(token, (addrs, _, git_meta))| {
-            let mut entity_cmds = cmds.spawn((Peer { addrs, token }, Latency::default()));
+        .extract_if(|_, pending| frame.wrapping_sub(pending.connected_at) > SINGLETON_DEADLINE)
+        .for_each(|(token, pending)| {
+            let addrs = pending.addrs;
+            let mut entity_cmds =
+                cmds.spawn((Peer { addrs, token }, pending.kind, Latency::default()));
             let entity = entity_cmds.id();
 
-            if let Some(git_meta) = git_meta {
+            if let Some(git_meta) = pending.git_meta {
                 entity_cmds.insert(git_meta);
             }
 
@@ -489,6 +685,10 @@ fn shutdown(
 
 const PING_INTERVAL: u32 = 50;
 const MAX_LATENCY: u32 = 15;
+/// A ping that goes unanswered for this long counts as a missed heartbeat
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_millis(150);
+/// Number of pings `Latency::loss` is computed over
+const HEARTBEAT_HISTORY: usize = 20;
 
 // TODO(high): Auto Reconnect
 fn ping(
@@ -500,6 +700,15 @@ fn ping(
     let frame = frame.0;
 
     for (peer, mut latency) in &mut query {
+        let overdue = latency
+            .ping_sent_at
+            .is_some_and(|it| it.elapsed() > HEARTBEAT_TIMEOUT);
+        if overdue && !latency.late {
+            latency.late = true;
+            latency.missed_heartbeats += 1;
+            latency.record_heartbeat(false);
+        }
+
         let should_disconnect = match (
             latency.last_ping_sent,
             latency.last_acknowledged,
@@ -548,10 +757,100 @@ fn ping(
             }
 
             latency.last_ping_sent = frame.into();
+            latency.ping_sent_at = Some(Instant::now());
+            latency.late = false;
         }
     }
 }
 
+type Baseline = HashMap<(NetId, NetTypeId), adapters::BackingType>;
+
+/// The last value of each large component sent to and received from each peer, deltas are taken
+/// against these. Each direction of a connection is ordered, so both ends agree on the base
+#[derive(Resource, Default, Debug)]
+struct DeltaBaselines {
+    sent: HashMap<NetToken, Baseline>,
+    received: HashMap<NetToken, Baseline>,
+}
+
+impl DeltaBaselines {
+    fn is_large(change: &SerializedChange) -> bool {
+        matches!(
+            change,
+            SerializedChange::ComponentUpdated(_, _, Some(raw)) if raw.len() >= MIN_DELTA_SIZE
+        )
+    }
+
+    /// Packs `change` for `peer`, as a delta when the peer already has an earlier value
+    fn encode(&mut self, peer: NetToken, change: SerializedChange) -> Protocol {
+        let delta = match &change {
+            SerializedChange::ComponentUpdated(net_id, type_name, Some(raw)) => self
+                .sent
+                .get(&peer)
+                .and_then(|it| it.get(&(*net_id, type_name.clone())))
+                .and_then(|old| ByteDelta::encode(old, raw))
+                .map(|delta| Protocol::EcsDelta(*net_id, type_name.clone(), delta)),
+            _ => None,
+        };
+
+        self.sent(peer, &change);
+        delta.unwrap_or(Protocol::EcsUpdate(change))
+    }
+
+    /// Rebuilds the update a delta from `peer` describes
+    fn decode(
+        &mut self,
+        peer: NetToken,
+        net_id: NetId,
+        type_name: NetTypeId,
+        delta: &ByteDelta,
+    ) -> Option<SerializedChange> {
+        let old = self
+            .received
+            .get(&peer)?
+            .get(&(net_id, type_name.clone()))?;
+        let change = SerializedChange::ComponentUpdated(net_id, type_name, Some(delta.apply(old)?));
+
+        self.received(peer, &change);
+        Some(change)
+    }
+
+    fn sent(&mut self, peer: NetToken, change: &SerializedChange) {
+        track_baseline(self.sent.entry(peer).or_default(), change);
+    }
+
+    fn received(&mut self, peer: NetToken, change: &SerializedChange) {
+        track_baseline(self.received.entry(peer).or_default(), change);
+    }
+
+    /// Sends full updates to `peer` until new bases are established
+    fn reset_sent(&mut self, peer: NetToken) {
+        self.sent.remove(&peer);
+    }
+
+    fn remove(&mut self, peer: NetToken) {
+        self.sent.remove(&peer);
+        self.received.remove(&peer);
+    }
+}
+
+fn track_baseline(baseline: &mut Baseline, change: &SerializedChange) {
+    match change {
+        SerializedChange::ComponentUpdated(net_id, type_name, Some(raw))
+            if raw.len() >= MIN_DELTA_SIZE =>
+        {
+            baseline.insert((*net_id, type_name.clone()), raw.clone());
+        }
+        SerializedChange::ComponentUpdated(net_id, type_name, _) => {
+            baseline.remove(&(*net_id, type_name.clone()));
+        }
+        SerializedChange::EntityDespawned(net_id) => {
+            baseline.retain(|(it, _), _| it != net_id);
+        }
+        _ => {}
+    }
+}
+
 #[derive(Resource, Default, Debug)]
 struct Deltas {
     entities: HashMap<NetId, HashMap<NetTypeId, adapters::BackingType>>,
@@ -560,6 +859,7 @@ struct Deltas {
 fn flatten_deltas(
     mut deltas: ResMut<Deltas>,
     entity_map: Res<EntityMap>,
+    permissions: Res<ReplicationPermissions>,
 
     mut inbound: EventReader<SerializedChangeInEvent>,
     mut outbound: EventReader<SerializedChangeOutEvent>,
@@ -568,7 +868,10 @@ fn flatten_deltas(
 ) {
     let iter = Iterator::chain(
         outbound.read().map(|it| &it.0),
-        inbound.read().map(|it| &it.0),
+        inbound
+            .read()
+            .filter(|it| permissions.permits_change(it.1, &it.0))
+            .map(|it| &it.0),
     );
 
     for change in iter {
@@ -620,10 +923,18 @@ fn flatten_deltas(
 fn sync_new_peers(
     net: Res<Net>,
     deltas: Res<Deltas>,
+    kind: Res<LocalPeerKind>,
     mut new_peers: EventReader<SyncPeer>,
     mut errors: EventWriter<ErrorEvent>,
 ) {
     'outer: for &SyncPeer(peer) in new_peers.read() {
+        // The peer decides what we may replicate from this, so it goes before any changes
+        let rst = net.0.send_packet(peer, Protocol::Hello(kind.0));
+        if rst.is_err() {
+            errors.send(anyhow!("Could not send sync packet").into());
+            continue 'outer;
+        }
+
         if let Some(git_meta) = GitMetadata::new() {
             let rst = net.0.send_packet(peer, Protocol::GitMetadata(git_meta));
 
@@ -1,9 +1,24 @@
 use bevy::app::App;
 
+pub mod behavior;
+pub mod pilot;
 pub mod system;
 pub mod units;
+pub mod video;
 
-pub fn register_types(app: &mut App) {
-    system::register_types(app);
+pub fn register_core_types(app: &mut App) {
     units::register_types(app);
 }
+
+pub fn register_sensor_types(app: &mut App) {
+    system::register_types(app);
+}
+
+pub fn register_video_types(app: &mut App) {
+    video::register_types(app);
+}
+
+pub fn register_autonomy_types(app: &mut App) {
+    behavior::register_types(app);
+    pilot::register_types(app);
+}
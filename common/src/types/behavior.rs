@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use bevy::{
+    app::App,
+    reflect::{prelude::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+/// A node of a `BehaviorTree`, children are indices into the tree's nodes
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BehaviorNode {
+    pub name: String,
+    pub kind: NodeKind,
+    pub children: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub enum NodeKind {
+    /// Runs the children in order, fails as soon as one of them fails
+    Sequence,
+    /// Runs the children in order until one of them succeeds
+    Fallback,
+    /// Runs every child at once, succeeds once `required` of them have
+    Parallel {
+        required: usize,
+    },
+    /// Runs its only child again each time it succeeds, forever if `count` is unset
+    Repeat {
+        count: Option<u32>,
+    },
+    /// Succeeds when its only child fails and the other way around
+    Invert,
+    /// Fails its only child if it runs for longer than this many seconds
+    Timeout {
+        seconds: f32,
+    },
+    Action(BehaviorAction),
+}
+
+impl NodeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeKind::Sequence => "Sequence",
+            NodeKind::Fallback => "Fallback",
+            NodeKind::Parallel { .. } => "Parallel",
+            NodeKind::Repeat { .. } => "Repeat",
+            NodeKind::Invert => "Invert",
+            NodeKind::Timeout { .. } => "Timeout",
+            NodeKind::Action(action) => action.name(),
+        }
+    }
+}
+
+/// The leaves of a tree, these are what actually do something
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub enum BehaviorAction {
+    /// Drives to a position in the positioning system's frame, done once within
+    /// `acceptance_radius` meters of it
+    GoToPose {
+        x: f32,
+        y: f32,
+        /// Meters below the surface, unset leaves the depth alone
+        depth: Option<f32>,
+        /// Compass heading in degrees, unset leaves the heading alone
+        heading: Option<f32>,
+        /// In m/s
+        speed: f32,
+        acceptance_radius: f32,
+    },
+    /// Stays where the robot is for this many seconds
+    Hold { seconds: f32 },
+    /// Done once the named condition in `BehaviorConditions` is true
+    WaitFor { condition: String },
+    /// Runs a video pipeline on the named camera, done after `seconds` if set or otherwise once
+    /// the pipeline is stopped
+    RunPipeline {
+        pipeline: String,
+        camera: String,
+        seconds: Option<f32>,
+    },
+}
+
+impl BehaviorAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BehaviorAction::GoToPose { .. } => "Go To Pose",
+            BehaviorAction::Hold { .. } => "Hold",
+            BehaviorAction::WaitFor { .. } => "Wait For",
+            BehaviorAction::RunPipeline { .. } => "Run Pipeline",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum NodeState {
+    /// Not started yet, or reset by its parent
+    #[default]
+    Idle,
+    Running,
+    Succeeded,
+    Failed(String),
+}
+
+impl NodeState {
+    pub fn is_done(&self) -> bool {
+        matches!(self, NodeState::Succeeded | NodeState::Failed(_))
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct NodeStatus {
+    pub state: NodeState,
+    /// When the node last started running, on the clock of the app running the tree
+    pub started: Duration,
+    /// Times a `Repeat` node's child has succeeded
+    pub iterations: u32,
+}
+
+pub fn register_types(app: &mut App) {
+    app.register_type::<BehaviorNode>()
+        .register_type::<NodeKind>()
+        .register_type::<BehaviorAction>()
+        .register_type::<NodeState>()
+        .register_type::<NodeStatus>();
+}
@@ -0,0 +1,93 @@
+use bevy::{
+    app::App,
+    math::{vec3a, Vec3A},
+    reflect::{Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PilotMode {
+    pub name: String,
+    /// Disabled modes are skipped when cycling modes
+    pub enabled: bool,
+
+    pub depth_mps: f32,
+    pub trim_dps: Vec3A,
+    pub servo_rate: f32,
+
+    pub scale: f32,
+    /// Replaces the expo of every axis in the binding profile's input shaping while this mode is
+    /// active
+    #[serde(default)]
+    pub expo: Option<f32>,
+
+    pub translate_gain: Vec3A,
+    pub translate_gain_depth_hold: Vec3A,
+    pub torque_gain: Vec3A,
+    pub torque_gain_stabalize: Vec3A,
+}
+
+impl PilotMode {
+    pub fn normal() -> Self {
+        Self {
+            name: "Normal".to_owned(),
+            enabled: true,
+            depth_mps: 0.3,
+            trim_dps: vec3a(35.0, 35.0, 100.0),
+            servo_rate: 1.5,
+            scale: 0.8,
+            expo: None,
+            translate_gain: vec3a(1.0, 1.0, 1.0),
+            translate_gain_depth_hold: vec3a(1.0, 1.0, 0.1),
+            torque_gain: vec3a(1.0, 1.0, 0.5),
+            torque_gain_stabalize: vec3a(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn slow() -> Self {
+        Self {
+            name: "Slow".to_owned(),
+            scale: 0.4,
+            ..Self::normal()
+        }
+    }
+
+    pub fn precision() -> Self {
+        Self {
+            name: "Precision".to_owned(),
+            enabled: true,
+            depth_mps: 0.3,
+            trim_dps: vec3a(25.0, 25.0, 60.0),
+            servo_rate: 1.0,
+            scale: 0.2,
+            expo: None,
+            translate_gain: vec3a(1.0, 1.0, 1.0),
+            translate_gain_depth_hold: vec3a(2.0, 1.0, 0.0),
+            torque_gain: vec3a(1.0, 1.0, 0.5),
+            torque_gain_stabalize: vec3a(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Heave dominant mode for vertical transects, horizontal and rotational inputs are softened
+    /// so the robot holds its line while the pilot changes depth
+    pub fn vertical_transect() -> Self {
+        Self {
+            name: "Vertical Transect".to_owned(),
+            enabled: true,
+            depth_mps: 0.5,
+            trim_dps: vec3a(25.0, 25.0, 60.0),
+            servo_rate: 1.0,
+            scale: 0.6,
+            expo: Some(0.5),
+            translate_gain: vec3a(0.4, 0.4, 1.0),
+            translate_gain_depth_hold: vec3a(0.4, 0.4, 0.3),
+            torque_gain: vec3a(0.5, 0.5, 0.3),
+            torque_gain_stabalize: vec3a(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+pub fn register_types(app: &mut App) {
+    app.register_type::<PilotMode>();
+}
@@ -1,14 +1,20 @@
 use std::{
-    fmt::{Display, Formatter},
+    fmt::{Alignment, Display, Formatter},
+    iter::Sum,
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+    time::Duration,
 };
 
 use bevy::app::App;
+use bevy::ecs::system::Resource;
 use bevy::reflect::{std_traits::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize};
 use serde::{Deserialize, Serialize};
 
+/// Decimal places shown when a unit is displayed without a precision, ie `{}` rather than `{:.1}`
+const DEFAULT_PRECISION: usize = 2;
+
 macro_rules! unit {
-    ($name:ident, $repr:ty, $fmt:expr) => {
+    ($name:ident, $repr:ty, $symbol:expr) => {
         #[derive(
             Debug, Copy, Clone, Default, Serialize, Deserialize, Reflect, PartialOrd, PartialEq,
         )]
@@ -17,11 +23,21 @@ macro_rules! unit {
 
         impl $name {
             pub const ZERO: $name = $name(0.0);
+            pub const SYMBOL: &'static str = $symbol;
         }
 
         impl Display for $name {
             fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-                f.pad(&format!($fmt, self.0))
+                let precision = f.precision().unwrap_or(DEFAULT_PRECISION);
+                let text = format!("{:.*}{}", precision, self.0, $symbol);
+
+                // `pad` would treat the precision as a maximum length
+                let width = f.width().unwrap_or(0);
+                match f.align() {
+                    Some(Alignment::Right) => write!(f, "{text:>width$}"),
+                    Some(Alignment::Center) => write!(f, "{text:^width$}"),
+                    _ => write!(f, "{text:<width$}"),
+                }
             }
         }
 
@@ -81,6 +97,28 @@ macro_rules! unit {
             }
         }
 
+        impl Mul<$repr> for $name {
+            type Output = $name;
+
+            fn mul(self, rhs: $repr) -> Self::Output {
+                Self(self.0 * rhs)
+            }
+        }
+
+        impl Div<$repr> for $name {
+            type Output = $name;
+
+            fn div(self, rhs: $repr) -> Self::Output {
+                Self(self.0 / rhs)
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold(Self::ZERO, |acc, it| acc + it)
+            }
+        }
+
         impl Neg for $name {
             type Output = $name;
 
@@ -104,9 +142,9 @@ macro_rules! unit {
 }
 
 macro_rules! units {
-    ($($name:ident, $fmt:expr);* ) => {
+    ($($name:ident, $symbol:expr);* ) => {
         $(
-            unit!($name, Repr, $fmt);
+            unit!($name, Repr, $symbol);
         )*
 
         pub fn register_types(app: &mut App) {
@@ -120,15 +158,272 @@ macro_rules! units {
 type Repr = f32;
 
 units! {
-    Meters, "{:.2}M";
-    Mbar, "{:.2}mbar";
-    Celsius, "{:.2}°C";
-    GForce, "{:.2}g";
-    Radians, "{:.2}rad";
-    Degrees, "{:.2}°";
-    Dps, "{:.2}°/s";
-    Gauss, "{:.2}Gs";
-    Newtons, "{:.2}N";
-    Volts, "{:.2}V";
-    Amperes, "{:.2}A"
+    Meters, "M";
+    Mbar, "mbar";
+    Celsius, "°C";
+    GForce, "g";
+    Radians, "rad";
+    Degrees, "°";
+    Dps, "°/s";
+    Gauss, "Gs";
+    Newtons, "N";
+    Volts, "V";
+    Amperes, "A";
+    Watts, "W";
+    Seconds, "s"
+}
+
+/// Implements `$lhs * $rhs = $out` along with both ways of dividing it back out
+macro_rules! product {
+    ($lhs:ident * $rhs:ident = $out:ident) => {
+        impl Mul<$rhs> for $lhs {
+            type Output = $out;
+
+            fn mul(self, rhs: $rhs) -> Self::Output {
+                $out(self.0 * rhs.0)
+            }
+        }
+
+        impl Mul<$lhs> for $rhs {
+            type Output = $out;
+
+            fn mul(self, rhs: $lhs) -> Self::Output {
+                $out(self.0 * rhs.0)
+            }
+        }
+
+        impl Div<$rhs> for $out {
+            type Output = $lhs;
+
+            fn div(self, rhs: $rhs) -> Self::Output {
+                $lhs(self.0 / rhs.0)
+            }
+        }
+
+        impl Div<$lhs> for $out {
+            type Output = $rhs;
+
+            fn div(self, rhs: $lhs) -> Self::Output {
+                $rhs(self.0 / rhs.0)
+            }
+        }
+    };
+}
+
+product!(Volts * Amperes = Watts);
+
+impl From<Degrees> for Radians {
+    fn from(value: Degrees) -> Self {
+        Radians(value.0.to_radians())
+    }
+}
+
+impl From<Radians> for Degrees {
+    fn from(value: Radians) -> Self {
+        Degrees(value.0.to_degrees())
+    }
+}
+
+impl From<Duration> for Seconds {
+    fn from(value: Duration) -> Self {
+        Seconds(value.as_secs_f32())
+    }
+}
+
+impl From<Seconds> for Duration {
+    fn from(value: Seconds) -> Self {
+        Duration::from_secs_f32(value.0.max(0.0))
+    }
+}
+
+impl Celsius {
+    pub fn fahrenheit(&self) -> f32 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+}
+
+/// How quantities are shown to the pilot, values are always stored and replicated in SI units
+#[derive(Resource, Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct UnitPreferences {
+    pub length: LengthUnit,
+    pub temperature: TemperatureUnit,
+    pub electrical: ElectricalUnit,
+}
+
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LengthUnit {
+    #[default]
+    Meters,
+    Feet,
+}
+
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+/// Current draw can be shown directly or as the power drawn at the bus voltage
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ElectricalUnit {
+    #[default]
+    Amperes,
+    Watts,
+}
+
+const FEET_PER_METER: f32 = 3.28084;
+
+impl LengthUnit {
+    pub const ALL: [LengthUnit; 2] = [LengthUnit::Meters, LengthUnit::Feet];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "Meters",
+            LengthUnit::Feet => "Feet",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "M",
+            LengthUnit::Feet => "ft",
+        }
+    }
+
+    pub fn from_meters(&self, length: Meters) -> f32 {
+        match self {
+            LengthUnit::Meters => length.0,
+            LengthUnit::Feet => length.0 * FEET_PER_METER,
+        }
+    }
+
+    pub fn to_meters(&self, length: f32) -> Meters {
+        match self {
+            LengthUnit::Meters => Meters(length),
+            LengthUnit::Feet => Meters(length / FEET_PER_METER),
+        }
+    }
+}
+
+impl TemperatureUnit {
+    pub const ALL: [TemperatureUnit; 2] = [TemperatureUnit::Celsius, TemperatureUnit::Fahrenheit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "Celsius",
+            TemperatureUnit::Fahrenheit => "Fahrenheit",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+
+    pub fn from_celsius(&self, temperature: Celsius) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => temperature.0,
+            TemperatureUnit::Fahrenheit => temperature.fahrenheit(),
+        }
+    }
+}
+
+impl ElectricalUnit {
+    pub const ALL: [ElectricalUnit; 2] = [ElectricalUnit::Amperes, ElectricalUnit::Watts];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ElectricalUnit::Amperes => "Amperes",
+            ElectricalUnit::Watts => "Watts",
+        }
+    }
+}
+
+impl UnitPreferences {
+    pub fn length(&self, length: Meters) -> String {
+        format!(
+            "{:.2}{}",
+            self.length.from_meters(length),
+            self.length.symbol()
+        )
+    }
+
+    pub fn temperature(&self, temperature: Celsius) -> String {
+        format!(
+            "{:.2}{}",
+            self.temperature.from_celsius(temperature),
+            self.temperature.symbol()
+        )
+    }
+
+    /// Falls back to amperes when the bus voltage isn't known
+    pub fn current(&self, current: Amperes, voltage: Option<Volts>) -> String {
+        match (self.electrical, voltage) {
+            (ElectricalUnit::Watts, Some(voltage)) => format!("{:.1}", current * voltage),
+            _ => format!("{current}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_round_trip() {
+        let unit = LengthUnit::Feet;
+
+        assert!((unit.from_meters(Meters(1.0)) - 3.28084).abs() < 1e-4);
+        assert!((unit.to_meters(unit.from_meters(Meters(3.2))).0 - 3.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn temperature_conversion() {
+        let unit = TemperatureUnit::Fahrenheit;
+
+        assert_eq!(unit.from_celsius(Celsius(0.0)), 32.0);
+        assert_eq!(unit.from_celsius(Celsius(100.0)), 212.0);
+    }
+
+    #[test]
+    fn unit_arithmetic() {
+        let power = Volts(12.0) * Amperes(2.5);
+
+        assert_eq!(power, Watts(30.0));
+        assert_eq!(power / Volts(12.0), Amperes(2.5));
+        assert_eq!(power / Amperes(2.5), Volts(12.0));
+        assert_eq!(Meters(1.5) * 2.0, Meters(3.0));
+        assert_eq!(
+            [Amperes(1.0), Amperes(2.0)].into_iter().sum::<Amperes>(),
+            Amperes(3.0)
+        );
+        assert!((Radians::from(Degrees(180.0)).0 - std::f32::consts::PI).abs() < 1e-6);
+    }
+
+    #[test]
+    fn unit_display() {
+        assert_eq!(format!("{}", Volts(12.345)), "12.35V");
+        assert_eq!(format!("{:.1}", Volts(12.345)), "12.3V");
+        assert_eq!(format!("{:.0}", Celsius(71.6)), "72°C");
+        assert_eq!(format!("{:7}|", Meters(1.0)), "1.00M  |");
+        assert_eq!(format!("{:>7}", Meters(1.0)), "  1.00M");
+    }
+
+    #[test]
+    fn watts_need_voltage() {
+        let preferences = UnitPreferences {
+            electrical: ElectricalUnit::Watts,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            preferences.current(Amperes(2.0), Some(Volts(12.0))),
+            "24.0W"
+        );
+        assert_eq!(preferences.current(Amperes(2.0), None), "2.00A");
+    }
 }
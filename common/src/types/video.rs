@@ -0,0 +1,189 @@
+use std::fmt::{self, Display};
+
+use bevy::{
+    app::App,
+    reflect::{prelude::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    H265,
+}
+
+impl Display for VideoCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoCodec::H264 => write!(f, "H.264"),
+            VideoCodec::H265 => write!(f, "H.265"),
+        }
+    }
+}
+
+/// How the encoded video gets from the robot to the surface
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq, Eq, Default)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum StreamTransport {
+    /// RTP over UDP pushed to the address in the camera's `CameraDefinition`
+    #[default]
+    Rtp,
+    /// Served by the robot over RTSP
+    Rtsp { url: String },
+}
+
+impl Display for StreamTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamTransport::Rtp => write!(f, "RTP"),
+            StreamTransport::Rtsp { url } => write!(f, "RTSP ({url})"),
+        }
+    }
+}
+
+/// Steps the robot takes to reduce the bandwidth a camera uses, lower qualities cap the resolution,
+/// framerate and bitrate further
+#[derive(
+    Serialize,
+    Deserialize,
+    Reflect,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum CameraQuality {
+    Minimal,
+    Low,
+    Medium,
+    High,
+    #[default]
+    Full,
+}
+
+impl CameraQuality {
+    pub const ALL: [CameraQuality; 5] = [
+        CameraQuality::Full,
+        CameraQuality::High,
+        CameraQuality::Medium,
+        CameraQuality::Low,
+        CameraQuality::Minimal,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraQuality::Full => "Full",
+            CameraQuality::High => "High",
+            CameraQuality::Medium => "Medium",
+            CameraQuality::Low => "Low",
+            CameraQuality::Minimal => "Minimal",
+        }
+    }
+
+    pub fn lower(&self) -> Option<Self> {
+        match self {
+            CameraQuality::Full => Some(CameraQuality::High),
+            CameraQuality::High => Some(CameraQuality::Medium),
+            CameraQuality::Medium => Some(CameraQuality::Low),
+            CameraQuality::Low => Some(CameraQuality::Minimal),
+            CameraQuality::Minimal => None,
+        }
+    }
+
+    pub fn higher(&self) -> Option<Self> {
+        match self {
+            CameraQuality::Full => None,
+            CameraQuality::High => Some(CameraQuality::Full),
+            CameraQuality::Medium => Some(CameraQuality::High),
+            CameraQuality::Low => Some(CameraQuality::Medium),
+            CameraQuality::Minimal => Some(CameraQuality::Low),
+        }
+    }
+
+    /// Largest width and height to capture at, `None` uses the robot's configured limit
+    pub fn max_size(&self) -> Option<(u32, u32)> {
+        match self {
+            CameraQuality::Full | CameraQuality::High => None,
+            CameraQuality::Medium => Some((1280, 720)),
+            CameraQuality::Low => Some((640, 480)),
+            CameraQuality::Minimal => Some((320, 240)),
+        }
+    }
+
+    pub fn max_fps(&self) -> Option<f32> {
+        match self {
+            CameraQuality::Full | CameraQuality::High | CameraQuality::Medium => None,
+            CameraQuality::Low => Some(15.0),
+            CameraQuality::Minimal => Some(10.0),
+        }
+    }
+
+    /// Fraction of the configured bitrate to encode with
+    pub fn bitrate_scale(&self) -> f32 {
+        match self {
+            CameraQuality::Full => 1.0,
+            CameraQuality::High => 0.6,
+            CameraQuality::Medium => 0.4,
+            CameraQuality::Low => 0.2,
+            CameraQuality::Minimal => 0.1,
+        }
+    }
+}
+
+/// A single mode a V4L2 device can capture in
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CameraFormat {
+    /// The V4L2 fourcc such as `H264`, `MJPG` or `YUYV`
+    pub fourcc: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+}
+
+impl Display for CameraFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}x{} @ {:.0}fps",
+            self.fourcc, self.width, self.height, self.fps
+        )
+    }
+}
+
+/// The range of a V4L2 integer control
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ControlRange {
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+    pub default: i32,
+}
+
+/// The image controls a camera supports, `None` when the camera doesn't have the control
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[reflect(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct CameraControlRanges {
+    pub exposure: Option<ControlRange>,
+    pub white_balance: Option<ControlRange>,
+    pub focus: Option<ControlRange>,
+    pub gain: Option<ControlRange>,
+}
+
+pub fn register_types(app: &mut App) {
+    app.register_type::<VideoCodec>()
+        .register_type::<StreamTransport>()
+        .register_type::<CameraQuality>()
+        .register_type::<CameraFormat>()
+        .register_type::<ControlRange>()
+        .register_type::<CameraControlRanges>();
+}
@@ -0,0 +1,52 @@
+//! Checksums shared by the surface and robot halves of the robot update protocol
+
+pub use blake3::Hasher;
+
+/// Bytes of the binary carried by each `UpdateChunk`
+pub const CHUNK_SIZE: usize = 32 * 1024;
+/// Chunks the surface may send ahead of the last progress reported by the robot
+pub const CHUNK_WINDOW: u64 = 16;
+
+const KEY_CONTEXT: &str = "robocode 2025 robot update checksum";
+
+/// Hashes a binary the same way on both ends, a shared `key` turns the checksum into a signature
+/// only holders of the key can produce
+pub fn hasher(key: Option<&str>) -> Hasher {
+    match key {
+        Some(key) => Hasher::new_keyed(&blake3::derive_key(KEY_CONTEXT, key.as_bytes())),
+        None => Hasher::new(),
+    }
+}
+
+pub fn checksum(data: &[u8], key: Option<&str>) -> String {
+    hasher(key).update(data).finalize().to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyed_checksums_differ() {
+        let data = b"robot binary";
+
+        assert_eq!(checksum(data, None), checksum(data, None));
+        assert_ne!(checksum(data, None), checksum(data, Some("pool")));
+        assert_ne!(checksum(data, Some("pool")), checksum(data, Some("lab")));
+    }
+
+    #[test]
+    fn incremental_checksum_matches() {
+        let data = vec![7; CHUNK_SIZE * 3 + 5];
+
+        let mut hasher = hasher(Some("pool"));
+        for chunk in data.chunks(CHUNK_SIZE) {
+            hasher.update(chunk);
+        }
+
+        assert_eq!(
+            hasher.finalize().to_hex().to_string(),
+            checksum(&data, Some("pool"))
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Streams robot state to a rerun viewer for 3D timeline debugging
+//!
+//! This logs what every app knows about, the robot's attitude, its thrusters and the movement
+//! allocation. Apps log their own state into the same stream through the `Rerun` resource, robot
+//! relative things go under `robot/` so they follow the robot's transform
+
+use std::net::SocketAddr;
+
+use bevy::{
+    app::{App, First, Plugin, PostUpdate},
+    core::Name,
+    math::{Quat, Vec3A},
+    prelude::{resource_exists, IntoSystemConfigs, Query, Res, Resource, With},
+    time::{Real, Time},
+};
+use rerun::{Arrows3D, Quaternion, RecordingStream, RecordingStreamBuilder, Transform3D};
+use tracing::{info, warn};
+
+use crate::components::{
+    ActualForce, ActualMovement, DepthMeasurement, Orientation, Robot, TargetMovement,
+    ThrusterDefinition,
+};
+
+/// Overrides the address of the viewer to stream to
+pub const ADDRESS_VARIABLE: &str = "RERUN_ADDR";
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9876";
+
+/// Length in meters of the arrow for one newton of force
+const FORCE_SCALE: f32 = 0.02;
+
+pub struct RerunPlugin {
+    /// The name the viewer lists the stream under
+    pub app_id: &'static str,
+}
+
+impl Plugin for RerunPlugin {
+    fn build(&self, app: &mut App) {
+        let address = std::env::var(ADDRESS_VARIABLE).unwrap_or_else(|_| DEFAULT_ADDRESS.into());
+
+        let stream: anyhow::Result<RecordingStream> = try {
+            let address = address.parse::<SocketAddr>()?;
+
+            RecordingStreamBuilder::new(self.app_id).connect_tcp_opts(address, None)?
+        };
+
+        match stream {
+            Ok(stream) => {
+                info!("Streaming to rerun viewer at {address}");
+                app.insert_resource(Rerun(stream));
+            }
+            Err(err) => {
+                warn!("Could not connect to rerun viewer at {address}: {err:?}");
+            }
+        }
+
+        app.add_systems(First, set_time.run_if(resource_exists::<Rerun>))
+            .add_systems(
+                PostUpdate,
+                (log_robot, log_thrusters, log_movement).run_if(resource_exists::<Rerun>),
+            );
+    }
+}
+
+/// The stream to the viewer, only present while connected
+#[derive(Resource, Clone)]
+pub struct Rerun(pub RecordingStream);
+
+impl Rerun {
+    /// Logs `archetype` at `path` on the current frame's timeline, failures only mean the viewer
+    /// misses a sample so they are ignored
+    pub fn log(&self, path: impl Into<rerun::EntityPath>, archetype: &impl rerun::AsComponents) {
+        let _ = self.0.log(path, archetype);
+    }
+}
+
+pub fn transform(translation: Vec3A, rotation: Quat) -> Transform3D {
+    Transform3D::from_translation_rotation(
+        translation.to_array(),
+        Quaternion::from_xyzw(rotation.to_array()),
+    )
+}
+
+fn set_time(rerun: Res<Rerun>, time: Res<Time<Real>>) {
+    rerun.0.set_time_seconds("time", time.elapsed_secs_f64());
+}
+
+fn log_robot(
+    rerun: Res<Rerun>,
+    // TODO(low): Support multiple robots
+    robot: Query<(&Orientation, Option<&DepthMeasurement>), With<Robot>>,
+) {
+    let Ok((orientation, depth)) = robot.get_single() else {
+        return;
+    };
+
+    let depth = depth.map(|it| it.depth.0).unwrap_or_default();
+    rerun.log(
+        "robot",
+        &transform(Vec3A::new(0.0, 0.0, -depth), orientation.0),
+    );
+}
+
+fn log_thrusters(
+    rerun: Res<Rerun>,
+    thrusters: Query<(&Name, &ThrusterDefinition, Option<&ActualForce>)>,
+) {
+    if thrusters.is_empty() {
+        return;
+    }
+
+    let mut origins = Vec::new();
+    let mut vectors = Vec::new();
+    let mut labels = Vec::new();
+
+    for (name, ThrusterDefinition(_, thruster), force) in &thrusters {
+        let force = force.map(|it| it.0 .0).unwrap_or_default();
+
+        origins.push(thruster.position.to_array());
+        vectors.push((thruster.orientation * force * FORCE_SCALE).to_array());
+        labels.push(name.to_string());
+    }
+
+    rerun.log(
+        "robot/thrusters",
+        &Arrows3D::from_vectors(vectors)
+            .with_origins(origins)
+            .with_labels(labels),
+    );
+}
+
+fn log_movement(
+    rerun: Res<Rerun>,
+    robot: Query<(Option<&TargetMovement>, Option<&ActualMovement>), With<Robot>>,
+) {
+    let Ok((target, actual)) = robot.get_single() else {
+        return;
+    };
+
+    let movements = [
+        ("Target", target.map(|it| it.0)),
+        ("Actual", actual.map(|it| it.0)),
+    ];
+    let (labels, vectors): (Vec<_>, Vec<_>) = movements
+        .into_iter()
+        .filter_map(|(label, movement)| Some((label, movement?)))
+        .map(|(label, movement)| (label, (movement.force * FORCE_SCALE).to_array()))
+        .unzip();
+
+    if vectors.is_empty() {
+        return;
+    }
+
+    rerun.log(
+        "robot/movement",
+        &Arrows3D::from_vectors(vectors).with_labels(labels),
+    );
+}
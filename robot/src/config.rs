@@ -1,7 +1,14 @@
-use ahash::HashMap;
+use std::fs;
+
+use ahash::{HashMap, HashSet};
+use anyhow::{bail, Context};
 use bevy::{ecs::system::Resource, transform::components::Transform};
-use common::components::{
-    CameraCalibration, MotorContributionMode, MotorSignalType, MotorSlewRate, PidConfig,
+use common::{
+    components::{
+        CameraCalibration, MotorContributionMode, MotorSignalType, MotorSlewRate, PidConfig,
+    },
+    events::ThrusterLayout,
+    types::{units::Celsius, video::VideoCodec},
 };
 use glam::{vec3a, EulerRot, Quat, Vec3A};
 use motor_math::{
@@ -11,7 +18,13 @@ use motor_math::{
 use nalgebra::vector;
 use serde::{Deserialize, Serialize};
 
-use crate::plugins::actuators::{hardware::motor_id_map::LocalMotorId, stabilize::PidAxis};
+use crate::{
+    peripheral::{ping1d::Ping1d, pressure},
+    plugins::actuators::{hardware::motor_id_map::LocalMotorId, stabilize::PidAxis},
+};
+
+pub const CONFIG_PATH: &str = "robot.toml";
+const BACKUP_PATH: &str = "robot.toml.bak";
 
 #[derive(Resource, Debug, Clone, Serialize, Deserialize)]
 pub struct RobotConfig {
@@ -30,12 +43,349 @@ pub struct RobotConfig {
 
     #[serde(default)]
     pub imu_offset: ConfigRotation,
+    /// Applied on top of `imu_offset`, recorded by level calibration
+    #[serde(default)]
+    pub level_trim: ConfigRotation,
+
+    #[serde(default)]
+    pub depth_sensor: DepthSensorConfig,
+    /// Only set on robots with a conductivity sensor
+    #[serde(default)]
+    pub conductivity: Option<ConductivityConfig>,
+    /// Only set on robots with a downward echosounder
+    #[serde(default)]
+    pub altimeter: Option<AltimeterConfig>,
 
     #[serde(default)]
     pub cameras: HashMap<String, CameraDefinition>,
+    #[serde(default)]
+    pub camera_streaming: CameraStreamingConfig,
 
     #[serde(default)]
     pub pid_configs: HashMap<PidAxis, PidConfig>,
+    /// Seconds, measured by latency identification and compensated for by the stabilize module
+    #[serde(default)]
+    pub actuation_latency: HashMap<PidAxis, f32>,
+    /// Newtons per meter of horizontal error while bottom lock holds position, only needed on
+    /// robots with acoustic positioning
+    #[serde(default)]
+    pub position_hold: Option<PidConfig>,
+
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    #[serde(default)]
+    pub current_monitor: CurrentMonitorConfig,
+
+    #[serde(default)]
+    pub tether: TetherConfig,
+
+    /// Only set on robots with a smart battery
+    #[serde(default)]
+    pub battery: Option<BatteryConfig>,
+
+    #[serde(default)]
+    pub update: UpdateConfig,
+
+    #[serde(default)]
+    pub geofence: GeofenceConfig,
+
+    #[serde(default)]
+    pub thermal_derate: ThermalDerateConfig,
+}
+
+impl RobotConfig {
+    pub fn load() -> anyhow::Result<Self> {
+        let config = fs::read_to_string(CONFIG_PATH).context("Read config")?;
+        toml::from_str(&config).context("Parse config")
+    }
+
+    /// Rotation from the imu's frame to the robot's, including the level trim
+    pub fn imu_frame(&self) -> Quat {
+        self.level_trim.flatten() * self.imu_offset.flatten()
+    }
+
+    /// Overwrites the config file, the previous one is kept as a backup
+    pub fn save(&self) -> anyhow::Result<()> {
+        let config = toml::to_string_pretty(self).context("Serialize config")?;
+
+        fs::copy(CONFIG_PATH, BACKUP_PATH).context("Back up config")?;
+        fs::write(CONFIG_PATH, config).context("Write config")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentMonitorConfig {
+    /// Amperes of unaccounted current draw that count as an anomaly
+    pub threshold: f32,
+    /// Seconds the threshold must be exceeded for before alarming
+    pub duration: f32,
+    /// Amperes drawn by electronics without a predicted current draw
+    pub baseline: f32,
+}
+
+impl Default for CurrentMonitorConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 4.0,
+            duration: 3.0,
+            baseline: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TetherConfig {
+    /// Net turns in either direction before warning the pilot to unwind the tether
+    pub twist_budget: f32,
+}
+
+impl Default for TetherConfig {
+    fn default() -> Self {
+        Self { twist_budget: 3.0 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Shared with the surfaces allowed to push binaries, unkeyed checksums are accepted when unset
+    pub key: Option<String>,
+    /// Seconds a new binary must stay up for before the previous binary is discarded
+    pub self_test: f32,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            self_test: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalDerateConfig {
+    /// Degrees celsius where the thruster current budget starts being scaled back
+    pub knee: f32,
+    /// Degrees celsius where the budget reaches `min_factor`
+    pub limit: f32,
+    /// Fraction of `motor_amperage_budget` left at `limit` and above
+    pub min_factor: f32,
+    /// Labels of the temperature sensors to watch, all of them when empty
+    pub sensors: Vec<String>,
+}
+
+impl Default for ThermalDerateConfig {
+    fn default() -> Self {
+        Self {
+            knee: 70.0,
+            limit: 85.0,
+            min_factor: 0.4,
+            sensors: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeofenceConfig {
+    /// Meters, depth targets are clamped to this
+    pub max_depth: Option<f32>,
+    /// Meters horizontally from where the robot was armed, needs a position estimate
+    pub max_distance: Option<f32>,
+    /// Meters past a limit the robot may drift before pilot input is taken away
+    pub tolerance: f32,
+    /// Meters above the depth limit the robot ascends to after breaching it
+    pub recovery_margin: f32,
+}
+
+impl Default for GeofenceConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_distance: None,
+            tolerance: 0.3,
+            recovery_margin: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DepthSensorConfig {
+    pub sensor: DepthSensorKind,
+    /// Overrides the sensor's default i2c bus
+    pub i2c_bus: Option<u8>,
+    /// Overrides the sensor's default i2c address
+    pub i2c_address: Option<u8>,
+    pub fluid: Fluid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum DepthSensorKind {
+    /// MS5837-30BA
+    #[default]
+    Bar30,
+    /// Keller 10LD
+    Bar100,
+    /// Any other sensor of Keller's LD line
+    KellerLd,
+}
+
+/// What the robot is submerged in, sets the density used to turn pressure into depth
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Fluid {
+    #[default]
+    Fresh,
+    /// Average sea water, 35 practical salinity units
+    Salt,
+    /// Practical salinity units
+    Salinity(f32),
+    /// Kilograms per cubic meter, used as is without temperature compensation
+    Custom(f32),
+}
+
+impl Fluid {
+    /// Practical salinity units, the density follows the water temperature when this is known
+    pub fn salinity(&self) -> Option<f32> {
+        match self {
+            Fluid::Fresh => Some(0.0),
+            Fluid::Salt => Some(35.0),
+            Fluid::Salinity(salinity) => Some(*salinity),
+            Fluid::Custom(_) => None,
+        }
+    }
+
+    /// Kilograms per cubic meter, at 20C when the density follows the water temperature
+    pub fn density(&self) -> f32 {
+        match self {
+            Fluid::Custom(density) => *density,
+            fluid => pressure::water_density(Celsius(20.0), fluid.salinity().unwrap_or(0.0)),
+        }
+    }
+}
+
+/// An Atlas Scientific EZO-EC conductivity circuit, its salinity replaces the configured fluid's
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConductivityConfig {
+    pub i2c_bus: u8,
+    pub i2c_address: u8,
+}
+
+impl Default for ConductivityConfig {
+    fn default() -> Self {
+        Self {
+            i2c_bus: 6,
+            i2c_address: 0x64,
+        }
+    }
+}
+
+/// A Blue Robotics Ping sonar looking down, used as an altimeter by bottom lock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AltimeterConfig {
+    pub uart: String,
+    pub baud_rate: u32,
+}
+
+impl Default for AltimeterConfig {
+    fn default() -> Self {
+        Self {
+            uart: "/dev/ttyAMA1".to_owned(),
+            baud_rate: Ping1d::BAUD_RATE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    /// Number of cells in series
+    pub cells: u8,
+    pub i2c_address: u8,
+    /// Volts, arming is blocked when any cell is below this
+    pub min_cell_voltage: f32,
+    /// Volts between the highest and lowest cell that block arming
+    pub max_cell_imbalance: f32,
+    /// Degrees celsius that block arming
+    pub max_temperature: f32,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            cells: 4,
+            i2c_address: 0x0B,
+            min_cell_voltage: 3.3,
+            max_cell_imbalance: 0.15,
+            max_temperature: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    pub directory: String,
+    pub rotation: LogRotation,
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            directory: "logs".to_owned(),
+            rotation: LogRotation::Hourly,
+            max_files: 72,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraStreamingConfig {
+    pub codec: VideoCodec,
+    pub transport: CameraTransport,
+    /// Port of the RTSP server the streams are published to when using `CameraTransport::Rtsp`,
+    /// the server itself (such as mediamtx) is run separately
+    pub rtsp_port: u16,
+    /// Only used when the robot has to encode the stream itself
+    pub bitrate_kbps: u32,
+    pub max_width: u32,
+    pub max_height: u32,
+}
+
+impl Default for CameraStreamingConfig {
+    fn default() -> Self {
+        Self {
+            codec: VideoCodec::H264,
+            transport: CameraTransport::Rtp,
+            rtsp_port: 8554,
+            bitrate_kbps: 4000,
+            max_width: 1920,
+            max_height: 1080,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraTransport {
+    /// Pushes RTP over UDP to the surface
+    Rtp,
+    /// Publishes to an RTSP server on the robot that the surface pulls from
+    Rtsp,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,6 +470,43 @@ impl HeavyDefinition {
 }
 
 impl CustomDefinition {
+    /// Checks a layout sent by the surface before it replaces the configured thrusters
+    pub fn from_layout(layout: &[ThrusterLayout]) -> anyhow::Result<Self> {
+        if layout.is_empty() {
+            bail!("Layout has no thrusters");
+        }
+
+        let mut channels = HashSet::default();
+        let mut motors = HashMap::default();
+
+        for ThrusterLayout {
+            name,
+            channel,
+            thruster,
+        } in layout
+        {
+            if !channel.exists() {
+                bail!("{name} uses channel {channel}, which doesn't exist");
+            }
+            if !channels.insert(*channel) {
+                bail!("{name} uses a channel used by another thruster");
+            }
+            if thruster.orientation.length() < 0.1 {
+                bail!("{name} has no orientation");
+            }
+
+            let motor = CustomThruster {
+                channel: (*channel).into(),
+                motor: *thruster,
+            };
+            if motors.insert(name.clone(), motor).is_some() {
+                bail!("More than one thruster is named {name}");
+            }
+        }
+
+        Ok(Self { motors })
+    }
+
     fn to_motor_config(&self, center_mass: Vec3A) -> MotorConfig<String, motor_math::FloatType> {
         MotorConfig::<String, motor_math::FloatType>::new_raw(
             self.motors
@@ -246,6 +633,19 @@ impl MotorConfigDefinition {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ServoConfigDefinition {
     pub servos: HashMap<String, Servo>,
+    #[serde(default)]
+    pub presets: Vec<ServoPresetDefinition>,
+    /// Names of the servos that drive lights
+    #[serde(default)]
+    pub lights: Vec<String>,
+}
+
+/// A named set of positions for some of the servos, such as "Claw Open" or "Camera Down"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServoPresetDefinition {
+    pub name: String,
+    /// Keyed by servo name, servos left out keep their position
+    pub positions: HashMap<String, f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -273,6 +673,9 @@ pub struct CameraDefinition {
     pub movement_rotation: ConfigRotation,
     #[serde(default)]
     pub calib: CameraCalibration,
+    /// Overrides `CameraStreamingConfig::codec` for this camera
+    #[serde(default)]
+    pub codec: Option<VideoCodec>,
 }
 
 #[derive(Resource, Debug, Clone, Serialize, Deserialize)]
@@ -321,4 +724,14 @@ impl ConfigRotation {
             roll.to_radians(),
         )
     }
+
+    pub fn from_quat(rotation: Quat) -> Self {
+        let (yaw, pitch, roll) = rotation.to_euler(EulerRot::ZXY);
+
+        ConfigRotation {
+            yaw: yaw.to_degrees(),
+            pitch: pitch.to_degrees(),
+            roll: roll.to_degrees(),
+        }
+    }
 }
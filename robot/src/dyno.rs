@@ -0,0 +1,385 @@
+//! Bench tool that sweeps one thruster through its PWM range to characterize a new propeller
+//!
+//! Run as `robot dyno --channel <pwm channel>` with the robot service stopped, it drives the PWM
+//! controller directly. Each step is held until the thruster settles, then the current and
+//! voltage from the power sense module and the force from an optional serial load cell are
+//! averaged. The result is written in the format of `motor_data.csv`.
+//!
+//! The idle current measured at neutral before the sweep is subtracted from every step and the
+//! load cell is tared at the same time. RPM isn't measured and is written as 0, nothing reads it.
+//! Without a load cell the force and efficiency columns are left empty and have to be filled in
+//! before the file can be used as motor data.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::{
+    peripheral::{
+        ads1115::{Ads1115, AnalogChannel},
+        load_cell::LoadCell,
+        pca9685::Pca9685,
+    },
+    plugins::sensors::power,
+};
+
+const USAGE: &str = "\
+Usage: robot dyno --channel <0-15> [options]
+
+Options:
+    --start <us>           First PWM value of the sweep [default: 1100]
+    --end <us>             Last PWM value of the sweep [default: 1900]
+    --step <us>            PWM increment between steps [default: 4]
+    --settle <s>           Time each step is held before measuring [default: 1.0]
+    --sample <s>           Time each step is measured for [default: 0.5]
+    --max-current <A>      Stops the sweep once a step draws more than this [default: 25]
+    --load-cell <path>     Serial port of a load cell amplifier
+    --load-cell-baud <n>   Baud rate of the load cell [default: 115200]
+    --load-cell-scale <n>  Newtons per unit the load cell reports [default: 1.0]
+    --output <path>        Where the CSV is written [default: dyno.csv]
+";
+
+const NEUTRAL: u16 = 1500;
+/// ESCs arm after seeing neutral for a while, the idle current and tare are measured meanwhile
+const ARMING_DURATION: Duration = Duration::from_secs(2);
+/// Moving to and from the start of the sweep is spread out so the thruster doesn't jerk
+const RAMP_STEP: u16 = 10;
+const RAMP_INTERVAL: Duration = Duration::from_millis(20);
+const PWM_PERIOD: Duration = Duration::from_millis(10);
+const STANDARD_GRAVITY: f32 = 9.80665;
+
+const HEADER: &str = "pwm,rpm,current,voltage,power,force,efficiency\n";
+
+#[derive(Debug, Clone)]
+struct DynoOptions {
+    channel: u8,
+    start: u16,
+    end: u16,
+    step: u16,
+    settle: Duration,
+    sample: Duration,
+    max_current: f32,
+    load_cell: Option<String>,
+    load_cell_baud: u32,
+    load_cell_scale: f32,
+    output: String,
+}
+
+impl DynoOptions {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut options = Self {
+            channel: u8::MAX,
+            start: 1100,
+            end: 1900,
+            step: 4,
+            settle: Duration::from_secs(1),
+            sample: Duration::from_millis(500),
+            max_current: 25.0,
+            load_cell: None,
+            load_cell_baud: 115200,
+            load_cell_scale: 1.0,
+            output: "dyno.csv".to_owned(),
+        };
+
+        let seconds = |value: &str| -> anyhow::Result<Duration> {
+            Duration::try_from_secs_f32(value.parse()?).context("Invalid duration")
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| anyhow!("{flag} needs a value"))?;
+
+            match flag.as_str() {
+                "--channel" => options.channel = value.parse()?,
+                "--start" => options.start = value.parse()?,
+                "--end" => options.end = value.parse()?,
+                "--step" => options.step = value.parse()?,
+                "--settle" => options.settle = seconds(&value)?,
+                "--sample" => options.sample = seconds(&value)?,
+                "--max-current" => options.max_current = value.parse()?,
+                "--load-cell" => options.load_cell = Some(value),
+                "--load-cell-baud" => options.load_cell_baud = value.parse()?,
+                "--load-cell-scale" => options.load_cell_scale = value.parse()?,
+                "--output" => options.output = value,
+                _ => bail!("Unknown option {flag}"),
+            }
+        }
+
+        if options.channel >= 16 {
+            bail!("--channel must be a PWM channel from 0 to 15");
+        }
+        if options.start >= options.end || options.step == 0 {
+            bail!("The sweep needs --start below --end and a non zero --step");
+        }
+        if options.start < 1000 || options.end > 2000 {
+            bail!("The sweep has to stay within 1000us to 2000us");
+        }
+
+        Ok(options)
+    }
+}
+
+/// One step of the sweep, averaged over the sample time
+#[derive(Debug, Clone, Copy)]
+struct DynoRecord {
+    pwm: u16,
+    current: f32,
+    voltage: f32,
+    force: Option<f32>,
+}
+
+impl DynoRecord {
+    fn write_row(&self, csv: &mut String) {
+        let power = self.voltage * self.current;
+
+        let (force, efficiency) = match self.force {
+            Some(force) => {
+                // Grams of thrust per watt, like the manufacturer's data
+                let efficiency = if power > 0.1 {
+                    force.abs() / STANDARD_GRAVITY * 1000.0 / power
+                } else {
+                    0.0
+                };
+
+                (format!("{force:.4}"), format!("{efficiency:.1}"))
+            }
+            None => Default::default(),
+        };
+
+        let _ = writeln!(
+            csv,
+            "{},0,{:.2},{:.2},{:.1},{force},{efficiency}",
+            self.pwm, self.current, self.voltage, power
+        );
+    }
+}
+
+/// Everything read from the sensors over some time
+#[derive(Debug, Default)]
+struct Samples {
+    current: Vec<f32>,
+    voltage: Vec<f32>,
+    force: Vec<f32>,
+}
+
+impl Samples {
+    fn mean(values: &[f32]) -> Option<f32> {
+        (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+    }
+}
+
+struct Dyno {
+    options: DynoOptions,
+    pwm: Pca9685,
+    adc: Ads1115,
+    load_cell: Option<LoadCell>,
+    stop: Arc<AtomicBool>,
+    output: u16,
+}
+
+impl Dyno {
+    fn set_pwm(&mut self, pwm: u16) -> anyhow::Result<()> {
+        self.pwm
+            .set_pwm(self.options.channel, Duration::from_micros(pwm as u64))
+            .context("Set pwm")?;
+        self.output = pwm;
+
+        Ok(())
+    }
+
+    fn ramp_to(&mut self, target: u16) -> anyhow::Result<()> {
+        while self.output != target {
+            let next = if self.output < target {
+                self.output.saturating_add(RAMP_STEP).min(target)
+            } else {
+                self.output.saturating_sub(RAMP_STEP).max(target)
+            };
+
+            self.set_pwm(next)?;
+            thread::sleep(RAMP_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    fn wait(&self, duration: Duration) -> anyhow::Result<()> {
+        let end = Instant::now() + duration;
+
+        while Instant::now() < end {
+            if self.stop.load(Ordering::Relaxed) {
+                bail!("Stopped");
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(())
+    }
+
+    fn sample(&mut self, duration: Duration) -> anyhow::Result<Samples> {
+        let mut samples = Samples::default();
+
+        if let Some(load_cell) = &mut self.load_cell {
+            // Readings from while the thruster was settling
+            load_cell.read_forces().context("Flush load cell")?;
+        }
+
+        let end = Instant::now() + duration;
+        while Instant::now() < end {
+            if self.stop.load(Ordering::Relaxed) {
+                bail!("Stopped");
+            }
+
+            let voltage = self
+                .adc
+                .read_channel(AnalogChannel::Ch3)
+                .context("Read voltage")?;
+            let current = self
+                .adc
+                .read_channel(AnalogChannel::Ch2)
+                .context("Read current")?;
+            samples.voltage.push(power::volts(voltage));
+            samples.current.push(power::amps(current));
+
+            if let Some(load_cell) = &mut self.load_cell {
+                let forces = load_cell.read_forces().context("Read load cell")?;
+                samples.force.extend(forces);
+            }
+        }
+
+        Ok(samples)
+    }
+
+    fn sweep(&mut self, records: &mut Vec<DynoRecord>) -> anyhow::Result<()> {
+        self.set_pwm(NEUTRAL)?;
+        self.pwm.output_enable();
+
+        println!("Arming and measuring the idle current");
+        let idle = self.sample(ARMING_DURATION)?;
+        let idle_current = Samples::mean(&idle.current).context("No current readings")?;
+        let tare = Samples::mean(&idle.force);
+        if self.load_cell.is_some() && tare.is_none() {
+            bail!("The load cell did not report any readings");
+        }
+
+        println!("Idle current {idle_current:.2}A, sweeping");
+        self.ramp_to(self.options.start)?;
+
+        let DynoOptions {
+            start, end, step, ..
+        } = self.options;
+        for pwm in (start..=end).step_by(step as usize) {
+            self.set_pwm(pwm)?;
+            self.wait(self.options.settle)?;
+
+            let samples = self.sample(self.options.sample)?;
+            let current = Samples::mean(&samples.current).context("No current readings")?;
+            let voltage = Samples::mean(&samples.voltage).context("No voltage readings")?;
+            let force = tare.zip(Samples::mean(&samples.force));
+
+            let record = DynoRecord {
+                pwm,
+                current: (current - idle_current).max(0.0),
+                voltage,
+                force: force.map(|(tare, force)| force - tare),
+            };
+            records.push(record);
+
+            match record.force {
+                Some(force) => println!(
+                    "{pwm}us: {:.2}A at {voltage:.2}V, {force:.3}N",
+                    record.current
+                ),
+                None => println!("{pwm}us: {:.2}A at {voltage:.2}V", record.current),
+            }
+
+            if current > self.options.max_current {
+                bail!(
+                    "Drew {current:.2}A at {pwm}us, over the {}A limit",
+                    self.options.max_current
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the sweep and writes the CSV, `args` are everything after `dyno`
+pub fn run(args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let options = match DynoOptions::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprint!("{USAGE}");
+            return Err(err);
+        }
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGINT, stop.clone()).context("Register SIGINT")?;
+    signal_hook::flag::register(SIGTERM, stop.clone()).context("Register SIGTERM")?;
+
+    let mut pwm =
+        Pca9685::new(Pca9685::I2C_BUS, Pca9685::I2C_ADDRESS, PWM_PERIOD).context("PCA9685")?;
+    pwm.set_pwms([Duration::from_micros(NEUTRAL as u64); 16])
+        .context("Set initial pwms")?;
+
+    let adc = Ads1115::new(Ads1115::I2C_BUS, Ads1115::I2C_ADDRESS)
+        .context("Analog to Digital converter (Ads1115)")?;
+    let load_cell = options
+        .load_cell
+        .as_deref()
+        .map(|path| LoadCell::new(path, options.load_cell_baud, options.load_cell_scale))
+        .transpose()
+        .context("Load cell")?;
+
+    if load_cell.is_none() {
+        println!("No load cell, force will have to be filled in by hand");
+    }
+
+    let mut dyno = Dyno {
+        options,
+        pwm,
+        adc,
+        load_cell,
+        stop,
+        output: NEUTRAL,
+    };
+
+    let mut records = Vec::new();
+    let res = dyno.sweep(&mut records);
+
+    // Always try to bring the thruster back down gently, dropping the controller cuts the output
+    dyno.stop.store(false, Ordering::Relaxed);
+    let ramp = dyno.ramp_to(NEUTRAL);
+    let output = dyno.options.output.clone();
+    drop(dyno);
+
+    if let Err(err) = &res {
+        eprintln!("Sweep ended early: {err:?}");
+    }
+    ramp.context("Return to neutral")?;
+
+    if records.is_empty() {
+        return res;
+    }
+
+    let mut csv = HEADER.to_owned();
+    for record in &records {
+        record.write_row(&mut csv);
+    }
+    fs::write(&output, csv).context("Write dyno data")?;
+
+    println!("Wrote {} steps to {output}", records.len());
+
+    res
+}
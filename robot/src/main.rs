@@ -2,13 +2,13 @@
 #![allow(private_interfaces, clippy::redundant_pattern_matching)]
 
 pub mod config;
+pub mod dyno;
 pub mod peripheral;
 pub mod plugins;
 pub mod utils;
 
-use std::{fs, time::Duration};
+use std::time::Duration;
 
-use anyhow::Context;
 use bevy::{
     app::ScheduleRunnerPlugin,
     diagnostic::{DiagnosticsPlugin, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
@@ -16,25 +16,47 @@ use bevy::{
     prelude::*,
 };
 use bevy_tokio_tasks::TokioTasksPlugin;
-use common::{sync::SyncRole, CommonPlugins};
+use common::{git::GitMetadata, params::ParamsPlugin, sync::SyncRole, CommonPlugins};
 use config::RobotConfig;
 use plugins::{
-    actuators::MovementPlugins, core::CorePlugins, monitor::MonitorPlugins, sensors::SensorPlugins,
+    actuators::MovementPlugins,
+    core::{logging, power, update, CorePlugins},
+    monitor::MonitorPlugins,
+    sensors::SensorPlugins,
 };
 
-// TODO: LogPlugin now exposes a way to play with the tracing subscriber
+/// Parameters tuned from the surface, see `common::params`
+const PARAMS_PATH: &str = "params.toml";
+
 fn main() -> anyhow::Result<()> {
+    // Used by the updater to check staged binaries
+    if std::env::args().any(|it| it == "--version") {
+        let version = GitMetadata::new().map(|it| it.version());
+        println!("{}", version.as_deref().unwrap_or("unknown"));
+
+        return Ok(());
+    }
+
+    // Bench tool for characterizing thrusters, runs instead of the robot
+    if std::env::args().nth(1).as_deref() == Some("dyno") {
+        return dyno::run(std::env::args().skip(2));
+    }
+
     info!("---------- Starting Robot Code ----------");
 
+    if update::check_pending_update()? {
+        // systemd starts the restored binary
+        return Ok(());
+    }
+
     info!("Reading config");
-    let config = fs::read_to_string("robot.toml").context("Read config")?;
-    let config: RobotConfig = toml::from_str(&config).context("Parse config")?;
+    let config = RobotConfig::load()?;
 
     let name = config.name.clone();
     let port = config.port;
 
     info!("Starting bevy");
-    App::new()
+    let exit = App::new()
         .insert_resource(config)
         .add_plugins((
             MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
@@ -54,7 +76,10 @@ fn main() -> anyhow::Result<()> {
             //     },
             // })
             // Logging
-            LogPlugin::default(),
+            LogPlugin {
+                custom_layer: logging::log_layer,
+                ..default()
+            },
             // Tokio
             TokioTasksPlugin::default(),
             // Diagnostics
@@ -69,6 +94,9 @@ fn main() -> anyhow::Result<()> {
                     role: SyncRole::Server { port },
                     name,
                 },
+                ParamsPlugin {
+                    path: PARAMS_PATH.into(),
+                },
                 CorePlugins,
                 MovementPlugins,
                 SensorPlugins,
@@ -79,5 +107,5 @@ fn main() -> anyhow::Result<()> {
 
     info!("---------- Robot Code Exited Cleanly ----------");
 
-    Ok(())
+    power::perform_exit_action(&exit)
 }
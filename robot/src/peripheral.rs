@@ -1,6 +1,12 @@
 pub mod ads1115;
+pub mod ezo_ec;
 pub mod icm20602;
+pub mod keller_ld;
+pub mod load_cell;
 pub mod mmc5983;
 pub mod ms5937;
 pub mod neopixel;
 pub mod pca9685;
+pub mod ping1d;
+pub mod pressure;
+pub mod smart_battery;
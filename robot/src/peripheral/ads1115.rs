@@ -1,3 +1,5 @@
+use std::{thread, time::Duration};
+
 use rppal::i2c::I2c;
 use tracing::{info, instrument};
 
@@ -84,4 +86,15 @@ impl Ads1115 {
 
         Ok(value as f32 / 0xffff as f32 * 2.0 * 4.096)
     }
+
+    /// Converts a channel and waits for the result
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_channel(&mut self, channel: AnalogChannel) -> anyhow::Result<f32> {
+        self.request_conversion(channel)?;
+        thread::sleep(Duration::from_secs_f64(1.0 / 860.0));
+
+        while !self.ready()? {}
+
+        self.read()
+    }
 }
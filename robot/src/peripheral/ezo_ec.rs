@@ -0,0 +1,98 @@
+use std::{thread, time::Duration};
+
+use anyhow::{bail, Context};
+use common::types::units::Celsius;
+use rppal::i2c::I2c;
+use tracing::{debug, info, instrument};
+
+/// Atlas Scientific's EZO-EC conductivity circuit, set up to report salinity
+pub struct EzoEc {
+    i2c: I2c,
+}
+
+impl EzoEc {
+    pub const I2C_BUS: u8 = 6;
+    pub const I2C_ADDRESS: u8 = 0x64;
+
+    const RESPONSE_SUCCESS: u8 = 1;
+    const RESPONSE_SYNTAX_ERROR: u8 = 2;
+    const RESPONSE_PENDING: u8 = 254;
+    const RESPONSE_NO_DATA: u8 = 255;
+
+    const OUTPUT_DELAY: Duration = Duration::from_millis(300);
+    const READ_DELAY: Duration = Duration::from_millis(600);
+    const COMPENSATED_READ_DELAY: Duration = Duration::from_millis(900);
+
+    #[instrument(level = "debug")]
+    pub fn new(bus: u8, address: u8) -> anyhow::Result<Self> {
+        info!("Setting up EZO-EC (Conductivity Sensor)");
+
+        let mut i2c = I2c::with_bus(bus).context("Open i2c")?;
+
+        i2c.set_slave_address(address as u16)
+            .context("Set address for EZO-EC")?;
+
+        let mut this = Self { i2c };
+
+        this.initialize().context("Init EZO-EC")?;
+
+        Ok(this)
+    }
+
+    /// Practical salinity units, compensated for `temperature` when it is known. The circuit
+    /// assumes 25C otherwise
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_salinity(&mut self, temperature: Option<Celsius>) -> anyhow::Result<f32> {
+        let response = match temperature {
+            Some(temperature) => self.command(
+                &format!("RT,{:.2}", temperature.0),
+                Self::COMPENSATED_READ_DELAY,
+            )?,
+            None => self.command("R", Self::READ_DELAY)?,
+        };
+
+        response
+            .trim()
+            .parse()
+            .with_context(|| format!("Parse salinity from {response:?}"))
+    }
+}
+
+impl EzoEc {
+    fn initialize(&mut self) -> anyhow::Result<()> {
+        debug!("Initializing EZO-EC (conductivity sensor)");
+
+        // Only report salinity so reads don't need to be split
+        for command in ["O,EC,0", "O,TDS,0", "O,S,1", "O,SG,0"] {
+            self.command(command, Self::OUTPUT_DELAY)
+                .with_context(|| format!("Set output ({command})"))?;
+        }
+
+        debug!("Initializing EZO-EC complete");
+
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str, delay: Duration) -> anyhow::Result<String> {
+        self.i2c
+            .write(command.as_bytes())
+            .context("Write command")?;
+        thread::sleep(delay);
+
+        let mut buffer = [0; 32];
+        self.i2c.read(&mut buffer).context("Read response")?;
+
+        match buffer[0] {
+            Self::RESPONSE_SUCCESS => {}
+            Self::RESPONSE_SYNTAX_ERROR => bail!("Syntax error for {command}"),
+            Self::RESPONSE_PENDING => bail!("{command} is still processing"),
+            Self::RESPONSE_NO_DATA => bail!("No data for {command}"),
+            code => bail!("Unknown response code {code} for {command}"),
+        }
+
+        let data = &buffer[1..];
+        let end = data.iter().position(|&it| it == 0).unwrap_or(data.len());
+
+        Ok(String::from_utf8_lossy(&data[..end]).into_owned())
+    }
+}
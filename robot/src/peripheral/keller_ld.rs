@@ -0,0 +1,149 @@
+use std::{thread, time::Duration};
+
+use anyhow::{bail, Context};
+use common::types::units::{Celsius, Mbar};
+use rppal::i2c::I2c;
+use tracing::{debug, info, instrument};
+
+use super::pressure::PressureSensor;
+
+/// Keller's LD line of pressure sensors, the Blue Robotics Bar100 is a Keller 10LD
+pub struct KellerLd {
+    i2c: I2c,
+    name: &'static str,
+
+    /// Bar, added to readings so they are absolute
+    mode_offset: f32,
+    /// Bar, the pressure range of the sensor
+    min_pressure: f32,
+    max_pressure: f32,
+}
+
+impl KellerLd {
+    pub const I2C_BUS: u8 = 6;
+    pub const I2C_ADDRESS: u8 = 0x40;
+
+    const CMD_REQUEST_MEASUREMENT: u8 = 0xAC;
+    const ADDR_SCALING_0: u8 = 0x12;
+    const ADDR_P_MIN_HIGH: u8 = 0x13;
+    const ADDR_P_MIN_LOW: u8 = 0x14;
+    const ADDR_P_MAX_HIGH: u8 = 0x15;
+    const ADDR_P_MAX_LOW: u8 = 0x16;
+
+    const STATUS_BUSY: u8 = 1 << 5;
+    const STATUS_MODE: u8 = 0b11 << 3;
+    const STATUS_CHECKSUM: u8 = 1 << 2;
+
+    /// Vented gauge, sealed gauge and absolute, what a reading of zero means in each mode
+    const MODE_OFFSETS: [f32; 3] = [1.01325, 1.0, 0.0];
+
+    #[instrument(level = "debug")]
+    pub fn new(bus: u8, address: u8, name: &'static str) -> anyhow::Result<Self> {
+        info!("Setting up Keller LD ({name})");
+
+        let mut i2c = I2c::with_bus(bus).context("Open i2c")?;
+
+        i2c.set_slave_address(address as u16)
+            .context("Set address for Keller LD")?;
+
+        let mut this = Self {
+            i2c,
+            name,
+            mode_offset: 0.0,
+            min_pressure: 0.0,
+            max_pressure: 0.0,
+        };
+
+        this.initialize().context("Init Keller LD")?;
+
+        Ok(this)
+    }
+}
+
+impl PressureSensor for KellerLd {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[instrument(level = "trace", skip(self), ret)]
+    fn read(&mut self) -> anyhow::Result<(Mbar, Celsius)> {
+        self.i2c
+            .write(&[Self::CMD_REQUEST_MEASUREMENT])
+            .context("Request measurement")?;
+        thread::sleep(Duration::from_millis(9));
+
+        let mut buffer = [0; 5];
+        self.i2c.read(&mut buffer).context("Read measurement")?;
+
+        let status = buffer[0];
+        if status & Self::STATUS_BUSY != 0 {
+            bail!("Measurement not ready");
+        }
+        if status & Self::STATUS_MODE != 0 {
+            bail!("Invalid mode, status {status:#04x}");
+        }
+        if status & Self::STATUS_CHECKSUM != 0 {
+            bail!("Memory checksum error");
+        }
+
+        let pressure_raw = (buffer[1] as u16) << 8 | buffer[2] as u16;
+        let temperature_raw = (buffer[3] as u16) << 8 | buffer[4] as u16;
+
+        let range = self.max_pressure - self.min_pressure;
+        let bar = (pressure_raw as f32 - 16384.0) * range / 32768.0
+            + self.min_pressure
+            + self.mode_offset;
+        let temperature = ((temperature_raw >> 4) as f32 - 24.0) * 0.05 - 50.0;
+
+        Ok((Mbar(bar * 1000.0), Celsius(temperature)))
+    }
+}
+
+impl KellerLd {
+    fn initialize(&mut self) -> anyhow::Result<()> {
+        debug!("Initializing Keller LD (depth sensor)");
+
+        let scaling_0 = self.read_memory(Self::ADDR_SCALING_0)?;
+        let mode = (scaling_0 & 0b11) as usize;
+        let Some(&mode_offset) = Self::MODE_OFFSETS.get(mode) else {
+            bail!("Unknown pressure mode {mode}");
+        };
+        self.mode_offset = mode_offset;
+
+        self.min_pressure = self.read_float(Self::ADDR_P_MIN_HIGH, Self::ADDR_P_MIN_LOW)?;
+        self.max_pressure = self.read_float(Self::ADDR_P_MAX_HIGH, Self::ADDR_P_MAX_LOW)?;
+
+        if self.max_pressure <= self.min_pressure {
+            bail!(
+                "Got bad pressure range, {} to {} bar",
+                self.min_pressure,
+                self.max_pressure
+            );
+        }
+
+        debug!(
+            "Initializing Keller LD complete, mode {mode}, {} to {} bar",
+            self.min_pressure, self.max_pressure
+        );
+
+        Ok(())
+    }
+
+    fn read_memory(&mut self, address: u8) -> anyhow::Result<u16> {
+        let mut buffer = [0; 3];
+
+        self.i2c.write(&[address]).context("Request memory")?;
+        thread::sleep(Duration::from_millis(1));
+        self.i2c.read(&mut buffer).context("Read memory")?;
+
+        Ok((buffer[1] as u16) << 8 | buffer[2] as u16)
+    }
+
+    /// Floats are stored big endian across two words
+    fn read_float(&mut self, high: u8, low: u8) -> anyhow::Result<f32> {
+        let high = self.read_memory(high)? as u32;
+        let low = self.read_memory(low)? as u32;
+
+        Ok(f32::from_bits(high << 16 | low))
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use rppal::uart::{Parity, Queue, Uart};
+use tracing::{info, instrument};
+
+/// A load cell amplifier that prints one reading per line over serial, like an HX711 breakout
+/// running the usual serial sketch
+///
+/// The last number on each line is taken as the reading, so labels and units around it are fine.
+pub struct LoadCell {
+    uart: Uart,
+    /// Newtons per unit the amplifier reports
+    scale: f32,
+    line: String,
+}
+
+impl LoadCell {
+    #[instrument(level = "debug")]
+    pub fn new(path: &str, baud_rate: u32, scale: f32) -> anyhow::Result<Self> {
+        info!("Setting up load cell");
+
+        let mut uart = Uart::with_path(path, baud_rate, Parity::None, 8, 1).context("Open uart")?;
+        // Reads return whatever has arrived without waiting
+        uart.set_read_mode(0, Duration::ZERO)
+            .context("Set read mode")?;
+        uart.flush(Queue::Both).context("Flush uart")?;
+
+        Ok(Self {
+            uart,
+            scale,
+            line: String::new(),
+        })
+    }
+
+    /// Every complete reading that arrived since the last call, in newtons
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_forces(&mut self) -> anyhow::Result<Vec<f32>> {
+        let mut buffer = [0u8; 256];
+        let mut forces = Vec::new();
+
+        loop {
+            let read = self.uart.read(&mut buffer).context("Read uart")?;
+            if read == 0 {
+                break;
+            }
+
+            for &byte in &buffer[..read] {
+                if byte != b'\n' {
+                    self.line.push(byte as char);
+                    continue;
+                }
+
+                let reading = self
+                    .line
+                    .split(|it: char| it.is_whitespace() || it == ',' || it == ':')
+                    .filter_map(|it| it.parse::<f32>().ok())
+                    .last();
+                if let Some(reading) = reading {
+                    forces.push(reading * self.scale);
+                }
+
+                self.line.clear();
+            }
+        }
+
+        Ok(forces)
+    }
+}
@@ -1,24 +1,16 @@
 use std::{thread, time::Duration};
 
 use anyhow::{bail, Context};
-use common::{
-    components::{DepthMeasurement, TempertureMeasurement},
-    types::units::{Celsius, Mbar, Meters},
-};
+use common::types::units::{Celsius, Mbar};
 use rppal::i2c::I2c;
 use tracing::{debug, info, instrument};
 
-use crate::utils::average::RunningAverage;
+use super::pressure::PressureSensor;
 
+/// The MS5837-30BA used by the Blue Robotics Bar30
 pub struct Ms5837 {
     i2c: I2c,
     calibration: [u16; 8],
-
-    // FIXME: I think id rather move this to a higher level of abstraction
-    filter: RunningAverage<10>,
-
-    pub fluid_density: f32,
-    pub sea_level: Mbar,
 }
 
 impl Ms5837 {
@@ -37,34 +29,24 @@ impl Ms5837 {
         let mut this = Self {
             i2c,
             calibration: [0; 8],
-            filter: RunningAverage::new(),
-            fluid_density: 1000.0,
-            sea_level: Mbar(1013.25),
         };
 
         this.initialize().context("Init MS5837")?;
 
         Ok(this)
     }
+}
+
+impl PressureSensor for Ms5837 {
+    fn name(&self) -> &'static str {
+        "Bar30"
+    }
 
     #[instrument(level = "trace", skip(self), ret)]
-    pub fn read_frame(&mut self) -> anyhow::Result<(DepthMeasurement, TempertureMeasurement)> {
+    fn read(&mut self) -> anyhow::Result<(Mbar, Celsius)> {
         let raw = self.read_raw().context("Read raw frame")?;
 
-        let (pressure, temperature) = calculate_pressure_and_temperature(raw, &self.calibration);
-        let pressure = Mbar(self.filter.add_reading(pressure.0));
-
-        let altitude = pressure_to_altitude(pressure, self.sea_level.0);
-        let depth = pressure_to_depth(pressure, self.fluid_density, self.sea_level.0);
-
-        Ok((
-            DepthMeasurement {
-                depth,
-                altitude,
-                pressure,
-            },
-            TempertureMeasurement { temperature },
-        ))
+        Ok(calculate_pressure_and_temperature(raw, &self.calibration))
     }
 }
 
@@ -123,7 +105,7 @@ impl Ms5837 {
             .context("Begin d1 read")?;
         self.i2c.read(&mut buffer).context("D1 read")?;
 
-        let d1 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[0] as u32;
+        let d1 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[2] as u32;
 
         self.i2c
             .write(&[Self::CMD_CONVERT_D2_OSR1024])
@@ -135,7 +117,7 @@ impl Ms5837 {
             .context("Begin d2 read")?;
         self.i2c.read(&mut buffer).context("D2 read")?;
 
-        let d2 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[0] as u32;
+        let d2 = (buffer[0] as u32) << 16 | (buffer[1] as u32) << 8 | buffer[2] as u32;
 
         Ok((d1, d2))
     }
@@ -189,14 +171,6 @@ fn calculate_pressure_and_temperature(raw: (u32, u32), calibration: &[u16; 8]) -
     (pressure, temperature)
 }
 
-fn pressure_to_depth(pressure: Mbar, density: f32, sea_level: f32) -> Meters {
-    Meters(((pressure.0 - sea_level) * 100.0) / (density * 9.80665))
-}
-
-fn pressure_to_altitude(pressure: Mbar, sea_level: f32) -> Meters {
-    Meters((1.0 - f32::powf(pressure.0 / sea_level, 0.190284)) * 145366.45 * 0.3048)
-}
-
 fn crc4(mut data: [u16; 8]) -> u8 {
     let mut n_rem = 0u16;
 
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use common::types::units::Meters;
+use rppal::uart::{Parity, Queue, Uart};
+use tracing::{debug, info, instrument};
+
+/// Blue Robotics' Ping sonar, a single beam echosounder used as an altimeter
+pub struct Ping1d {
+    uart: Uart,
+}
+
+impl Ping1d {
+    pub const BAUD_RATE: u32 = 115200;
+
+    const HEADER: [u8; 2] = *b"BR";
+    /// Header, payload length, message id and the two device ids
+    const FRAME_HEADER_LENGTH: usize = 8;
+    const CHECKSUM_LENGTH: usize = 2;
+
+    const MESSAGE_GENERAL_REQUEST: u16 = 6;
+    const MESSAGE_PROTOCOL_VERSION: u16 = 5;
+    const MESSAGE_DISTANCE_SIMPLE: u16 = 1211;
+
+    const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+    #[instrument(level = "debug")]
+    pub fn new(path: &str, baud_rate: u32) -> anyhow::Result<Self> {
+        info!("Setting up Ping1d (Altimeter)");
+
+        let mut uart = Uart::with_path(path, baud_rate, Parity::None, 8, 1).context("Open uart")?;
+        uart.set_read_mode(0, Self::READ_TIMEOUT)
+            .context("Set read mode")?;
+
+        let mut this = Self { uart };
+
+        this.initialize().context("Init Ping1d")?;
+
+        Ok(this)
+    }
+
+    /// Distance to the bottom and the sonar's confidence in it, from 0 to 1
+    #[instrument(level = "trace", skip(self), ret)]
+    pub fn read_distance(&mut self) -> anyhow::Result<(Meters, f32)> {
+        let payload = self
+            .request(Self::MESSAGE_DISTANCE_SIMPLE)
+            .context("Request distance")?;
+
+        let [d0, d1, d2, d3, confidence, ..] = payload[..] else {
+            bail!("Distance payload too short: {payload:?}");
+        };
+
+        let distance = u32::from_le_bytes([d0, d1, d2, d3]);
+
+        Ok((Meters(distance as f32 / 1000.0), confidence as f32 / 100.0))
+    }
+}
+
+impl Ping1d {
+    fn initialize(&mut self) -> anyhow::Result<()> {
+        debug!("Initializing Ping1d (altimeter)");
+
+        self.uart.flush(Queue::Both).context("Flush uart")?;
+
+        // Confirms something speaking the ping protocol is on the other end
+        let version = self
+            .request(Self::MESSAGE_PROTOCOL_VERSION)
+            .context("Request protocol version")?;
+        debug!(?version, "Ping protocol version");
+
+        debug!("Initializing Ping1d complete");
+
+        Ok(())
+    }
+
+    fn request(&mut self, id: u16) -> anyhow::Result<Vec<u8>> {
+        let frame = Self::frame(Self::MESSAGE_GENERAL_REQUEST, &id.to_le_bytes());
+        self.uart.write(&frame).context("Write request")?;
+
+        self.read_message(id)
+    }
+
+    /// Reads frames until one with `id` arrives, returning its payload
+    fn read_message(&mut self, id: u16) -> anyhow::Result<Vec<u8>> {
+        // The sonar may still be streaming replies to older requests
+        for _ in 0..5 {
+            let mut header = [0; Self::FRAME_HEADER_LENGTH];
+            self.read_exact(&mut header).context("Read header")?;
+
+            if header[..2] != Self::HEADER {
+                self.uart.flush(Queue::Input).context("Flush uart")?;
+                bail!("Bad frame header: {header:?}");
+            }
+
+            let payload_length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            let message_id = u16::from_le_bytes([header[4], header[5]]);
+
+            let mut rest = vec![0; payload_length + Self::CHECKSUM_LENGTH];
+            self.read_exact(&mut rest).context("Read payload")?;
+
+            let (payload, checksum) = rest.split_at(payload_length);
+            let checksum = u16::from_le_bytes([checksum[0], checksum[1]]);
+            let expected = Self::checksum(&header).wrapping_add(Self::checksum(payload));
+            if checksum != expected {
+                bail!("Bad checksum on message {message_id}, got {checksum} expected {expected}");
+            }
+
+            if message_id == id {
+                return Ok(payload.to_vec());
+            }
+        }
+
+        bail!("No reply to message {id}");
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> anyhow::Result<()> {
+        let mut read = 0;
+
+        while read < buffer.len() {
+            let count = self.uart.read(&mut buffer[read..]).context("Read uart")?;
+            if count == 0 {
+                bail!("Timed out");
+            }
+
+            read += count;
+        }
+
+        Ok(())
+    }
+
+    fn frame(id: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame =
+            Vec::with_capacity(Self::FRAME_HEADER_LENGTH + payload.len() + Self::CHECKSUM_LENGTH);
+
+        frame.extend_from_slice(&Self::HEADER);
+        frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        frame.extend_from_slice(&id.to_le_bytes());
+        // Source and destination device ids, zero is the host and the sonar's default
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+
+        let checksum = Self::checksum(&frame);
+        frame.extend_from_slice(&checksum.to_le_bytes());
+
+        frame
+    }
+
+    fn checksum(bytes: &[u8]) -> u16 {
+        bytes
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16))
+    }
+}
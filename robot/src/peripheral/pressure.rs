@@ -0,0 +1,41 @@
+//! Common interface for the pressure sensors used to measure depth
+
+use common::types::units::{Celsius, Mbar, Meters};
+
+/// Standard gravity, in meters per second squared
+const GRAVITY: f32 = 9.80665;
+
+pub trait PressureSensor: Send {
+    /// The model of the sensor, ie "Bar30"
+    fn name(&self) -> &'static str;
+
+    /// Reads the absolute pressure and the temperature of the sensor
+    fn read(&mut self) -> anyhow::Result<(Mbar, Celsius)>;
+}
+
+/// Depth below the surface, `density` is in kilograms per cubic meter
+pub fn pressure_to_depth(pressure: Mbar, density: f32, sea_level: Mbar) -> Meters {
+    Meters(((pressure.0 - sea_level.0) * 100.0) / (density * GRAVITY))
+}
+
+pub fn pressure_to_altitude(pressure: Mbar, sea_level: Mbar) -> Meters {
+    Meters((1.0 - f32::powf(pressure.0 / sea_level.0, 0.190284)) * 145366.45 * 0.3048)
+}
+
+/// Density of water at atmospheric pressure in kilograms per cubic meter, from the UNESCO (EOS-80)
+/// equation of state. `salinity` is in practical salinity units, zero for fresh water
+pub fn water_density(temperature: Celsius, salinity: f32) -> f32 {
+    let t = temperature.0 as f64;
+    let s = salinity.max(0.0) as f64;
+
+    let pure = 999.842594 + 6.793952e-2 * t - 9.095290e-3 * t.powi(2) + 1.001685e-4 * t.powi(3)
+        - 1.120083e-6 * t.powi(4)
+        + 6.536332e-9 * t.powi(5);
+
+    let a = 0.824493 - 4.0899e-3 * t + 7.6438e-5 * t.powi(2) - 8.2467e-7 * t.powi(3)
+        + 5.3875e-9 * t.powi(4);
+    let b = -5.72466e-3 + 1.0227e-4 * t - 1.6546e-6 * t.powi(2);
+    let c = 4.8314e-4;
+
+    (pure + a * s + b * s.powf(1.5) + c * s.powi(2)) as f32
+}
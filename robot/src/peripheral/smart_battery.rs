@@ -0,0 +1,80 @@
+use rppal::i2c::I2c;
+use tracing::{info, instrument};
+
+use anyhow::{bail, Context};
+
+/// A battery management system speaking the Smart Battery Data Specification over SMBus
+pub struct SmartBattery {
+    i2c: I2c,
+}
+
+impl SmartBattery {
+    pub const I2C_BUS: u8 = 1;
+    pub const I2C_ADDRESS: u8 = 0x0B;
+
+    /// The most cells `cell_voltages` can read
+    pub const MAX_CELLS: u8 = 4;
+
+    #[instrument(level = "debug")]
+    pub fn new(bus: u8, address: u8) -> anyhow::Result<Self> {
+        info!("Setting up Smart Battery (BMS)");
+
+        let mut i2c = I2c::with_bus(bus).context("Open i2c")?;
+
+        i2c.set_slave_address(address as u16)
+            .context("Set address for Smart Battery")?;
+
+        Ok(Self { i2c })
+    }
+}
+
+// Commands from the Smart Battery Data Specification v1.1
+impl SmartBattery {
+    const COMMAND_TEMPERATURE: u8 = 0x08;
+    const COMMAND_VOLTAGE: u8 = 0x09;
+    const COMMAND_RELATIVE_STATE_OF_CHARGE: u8 = 0x0D;
+    const COMMAND_CYCLE_COUNT: u8 = 0x17;
+    // Manufacturer specific, used by the common TI gas gauges, counting down from cell 1
+    const COMMAND_CELL_VOLTAGE_1: u8 = 0x3F;
+
+    #[instrument(level = "trace", skip(self), ret)]
+    fn read_word(&mut self, command: u8) -> anyhow::Result<u16> {
+        self.i2c
+            .smbus_read_word(command)
+            .with_context(|| format!("Read smart battery command {command:#04x}"))
+    }
+
+    /// Pack voltage in volts
+    pub fn voltage(&mut self) -> anyhow::Result<f32> {
+        Ok(self.read_word(Self::COMMAND_VOLTAGE)? as f32 / 1000.0)
+    }
+
+    /// Pack temperature in degrees celsius
+    pub fn temperature(&mut self) -> anyhow::Result<f32> {
+        // Reported in tenths of a kelvin
+        Ok(self.read_word(Self::COMMAND_TEMPERATURE)? as f32 / 10.0 - 273.15)
+    }
+
+    /// Remaining capacity in percent of full charge
+    pub fn state_of_charge(&mut self) -> anyhow::Result<f32> {
+        Ok(self.read_word(Self::COMMAND_RELATIVE_STATE_OF_CHARGE)? as f32)
+    }
+
+    pub fn cycle_count(&mut self) -> anyhow::Result<u16> {
+        self.read_word(Self::COMMAND_CYCLE_COUNT)
+    }
+
+    /// Voltages of the first `cells` cells in volts
+    pub fn cell_voltages(&mut self, cells: u8) -> anyhow::Result<Vec<f32>> {
+        if cells > Self::MAX_CELLS {
+            bail!("Smart battery can only report {} cells", Self::MAX_CELLS);
+        }
+
+        (0..cells)
+            .map(|cell| {
+                let voltage = self.read_word(Self::COMMAND_CELL_VOLTAGE_1 - cell)?;
+                Ok(voltage as f32 / 1000.0)
+            })
+            .collect()
+    }
+}
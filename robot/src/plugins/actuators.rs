@@ -1,6 +1,12 @@
+pub mod bottom_lock;
+pub mod derate;
+pub mod geofence;
 pub mod hardware;
 pub mod leds;
+pub mod maintenance;
+pub mod mixer;
 pub mod servo;
+pub mod signal;
 pub mod stabilize;
 pub mod thruster;
 
@@ -11,9 +17,15 @@ pub struct MovementPlugins;
 impl PluginGroup for MovementPlugins {
     fn build(self) -> PluginGroupBuilder {
         let plugins = PluginGroupBuilder::start::<Self>()
+            .add(mixer::MotorMixerPlugin)
             .add(servo::ServoPlugin)
             .add(thruster::ThrusterPlugin)
-            .add(stabilize::StabilizePlugin);
+            .add(stabilize::StabilizePlugin)
+            .add(geofence::GeofencePlugin)
+            .add(derate::ThermalDeratePlugin)
+            .add(signal::MotorSignalValidationPlugin)
+            .add(maintenance::MaintenancePlugin)
+            .add(bottom_lock::BottomLockPlugin);
 
         #[cfg(rpi)]
         let plugins = plugins
@@ -0,0 +1,268 @@
+//! Hovers over the bottom while the surface has set a `BottomLock`
+//!
+//! The lock is made of three holds. Altitude is held by moving the depth target so the altimeter
+//! reads the locked altitude, position by pushing back toward the locked `PositionEstimate` and
+//! heading through the `HeadingTarget`. When a sensor drops out its hold is reported as degraded:
+//! altitude falls back to holding the last depth target and position stops correcting until the
+//! sensor recovers.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use common::{
+    bundles::MovementContributionBundle,
+    components::{
+        Altimeter, Armed, BottomLock, BottomLockStatus, DepthMeasurement, DepthTarget, Geofence,
+        HeadingTarget, LockState, MovementContribution, Orientation, OrientationTarget,
+        PidController, PositionEstimate, RobotId,
+    },
+    ecs_sync::Replicate,
+    types::units::Meters,
+};
+use glam::Vec3A;
+use motor_math::glam::MovementGlam;
+
+use crate::{
+    config::RobotConfig,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
+
+/// Altimeter readings older than this are treated as a dropout
+const ALTIMETER_TIMEOUT: Duration = Duration::from_secs(1);
+/// Acoustic fixes arrive slower than the other sensors
+const POSITION_TIMEOUT: Duration = Duration::from_secs(3);
+/// Altimeter readings the sonar is less sure of than this are ignored
+const MIN_ALTIMETER_CONFIDENCE: f32 = 0.5;
+/// Position estimates less certain than this, in meters, are too noisy to hold on
+const MAX_POSITION_STD: f32 = 1.0;
+/// Depth target changes smaller than this, in meters, aren't worth replicating
+const DEPTH_TARGET_TOLERANCE: f32 = 0.01;
+
+pub struct BottomLockPlugin;
+
+impl Plugin for BottomLockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_bottom_lock)
+            .add_systems(Update, bottom_lock);
+    }
+}
+
+/// The movement contribution of the position hold
+#[derive(Component, Default)]
+struct PositionHold {
+    x: PidController,
+    y: PidController,
+    last_fix: Option<Duration>,
+}
+
+fn setup_bottom_lock(mut cmds: Commands, robot: Res<LocalRobot>) {
+    cmds.spawn((
+        MovementContributionBundle {
+            name: Name::new("Bottom Lock"),
+            contribution: MovementContribution(MovementGlam::default()),
+            robot: RobotId(robot.net_id),
+        },
+        PositionHold::default(),
+        Replicate,
+    ));
+}
+
+fn bottom_lock(
+    mut cmds: Commands,
+    // When each sensor last produced a usable reading
+    mut last_altitude: Local<Option<Duration>>,
+    mut last_position: Local<Option<Duration>>,
+    mut was_locked: Local<bool>,
+    robot: Query<
+        (
+            Entity,
+            &Armed,
+            Option<&BottomLock>,
+            Option<&BottomLockStatus>,
+            (Option<&DepthMeasurement>, Option<&DepthTarget>),
+            Option<Ref<Altimeter>>,
+            Option<Ref<PositionEstimate>>,
+            (
+                Option<&Orientation>,
+                Option<&OrientationTarget>,
+                Option<&HeadingTarget>,
+            ),
+            Option<&Geofence>,
+        ),
+        With<LocalRobotMarker>,
+    >,
+    mut hold: Query<(Entity, &mut PositionHold)>,
+    config: Res<RobotConfig>,
+    time: Res<Time<Real>>,
+) {
+    let Ok((
+        robot,
+        armed,
+        lock,
+        status,
+        (depth, depth_target),
+        altimeter,
+        position,
+        (orientation, orientation_target, heading_target),
+        geofence,
+    )) = robot.get_single()
+    else {
+        return;
+    };
+    let Ok((hold_entity, mut hold)) = hold.get_single_mut() else {
+        return;
+    };
+
+    let now = time.elapsed();
+
+    let altimeter = altimeter.filter(|it| it.confidence >= MIN_ALTIMETER_CONFIDENCE);
+    if altimeter.as_ref().is_some_and(|it| it.is_changed()) {
+        *last_altitude = Some(now);
+    }
+    let altimeter = altimeter
+        .filter(|_| last_altitude.is_some_and(|it| now - it < ALTIMETER_TIMEOUT))
+        .map(|it| *it);
+
+    let position = position.filter(|it| it.horizontal_std.0 <= MAX_POSITION_STD);
+    let new_fix = position.as_ref().is_some_and(|it| it.is_changed());
+    if new_fix {
+        *last_position = Some(now);
+    }
+    let position = position
+        .filter(|_| last_position.is_some_and(|it| now - it < POSITION_TIMEOUT))
+        .map(|it| *it);
+
+    let Some(lock) = lock else {
+        if *was_locked {
+            info!("Bottom lock released");
+
+            // The depth target is left in place so the robot keeps holding its depth
+            cmds.entity(robot)
+                .remove::<(HeadingTarget, BottomLockStatus)>();
+            cmds.entity(hold_entity).remove::<MovementContribution>();
+            *hold = PositionHold::default();
+            *was_locked = false;
+        }
+
+        return;
+    };
+
+    if !*was_locked {
+        info!("Bottom lock engaged: {lock:?}");
+        *was_locked = true;
+    }
+
+    // The geofence takes control while it recovers from a breach
+    let enforcing = geofence.is_some_and(|it| it.enforcing());
+
+    let altitude_state = match (lock.altitude, depth) {
+        (None, _) => LockState::Off,
+        (Some(target), Some(depth)) => match (altimeter, orientation) {
+            (Some(altimeter), Some(orientation)) if !enforcing => {
+                // The sonar looks along the robot's down axis, only the vertical part of the
+                // range is altitude
+                let down = orientation.0 * Vec3A::NEG_Z;
+                let altitude = altimeter.altitude.0 * -down.z;
+
+                // Too high above the bottom means going deeper
+                let new_target = (depth.depth.0 + altitude - target.0).max(0.0);
+                let changed = depth_target
+                    .is_none_or(|it| (it.0 .0 - new_target).abs() > DEPTH_TARGET_TOLERANCE);
+                if changed {
+                    cmds.entity(robot).insert(DepthTarget(Meters(new_target)));
+                }
+
+                LockState::Active
+            }
+            _ => {
+                if depth_target.is_none() && !enforcing {
+                    cmds.entity(robot).insert(DepthTarget(depth.depth));
+                }
+
+                LockState::Degraded
+            }
+        },
+        (Some(_), None) => LockState::Degraded,
+    };
+
+    let heading_state = match orientation {
+        Some(_) => {
+            // Leveling already holds the heading it was engaged at
+            if orientation_target.is_none() && heading_target != Some(&HeadingTarget(lock.heading))
+            {
+                cmds.entity(robot).insert(HeadingTarget(lock.heading));
+            }
+
+            LockState::Active
+        }
+        None => LockState::Degraded,
+    };
+
+    let correction = match (lock.position, position, orientation, &config.position_hold) {
+        (Some(target), Some(position), Some(orientation), Some(pid_config))
+            if *armed == Armed::Armed && !enforcing =>
+        {
+            // Only correct on new fixes, the estimate doesn't move between them
+            if new_fix {
+                let interval = hold
+                    .last_fix
+                    .map(|it| now - it)
+                    .unwrap_or(Duration::from_secs_f32(0.1));
+                hold.last_fix = Some(now);
+
+                let error = target - position.position;
+                let x = hold.x.update(error.x, pid_config, interval).correction;
+                let y = hold.y.update(error.y, pid_config, interval).correction;
+
+                let frame = position.frame_rotation * orientation.0;
+                Some(Some(frame.inverse() * Vec3A::new(x, y, 0.0)))
+            } else {
+                Some(None)
+            }
+        }
+        _ => None,
+    };
+
+    let position_state = match (lock.position, &correction) {
+        (None, _) => LockState::Off,
+        (Some(_), Some(_)) => LockState::Active,
+        (Some(_), None) => LockState::Degraded,
+    };
+
+    match correction {
+        Some(Some(force)) => {
+            cmds.entity(hold_entity)
+                .insert(MovementContribution(MovementGlam {
+                    force,
+                    torque: Vec3A::ZERO,
+                }));
+        }
+        // Keeps the last correction until the next fix
+        Some(None) => {}
+        None => {
+            cmds.entity(hold_entity).remove::<MovementContribution>();
+            *hold = PositionHold::default();
+        }
+    }
+
+    let new_status = BottomLockStatus {
+        altitude: altitude_state,
+        position: position_state,
+        heading: heading_state,
+    };
+    if status != Some(&new_status) {
+        if let Some(status) = status {
+            for (name, old, new) in [
+                ("Altitude", status.altitude, new_status.altitude),
+                ("Position", status.position, new_status.position),
+                ("Heading", status.heading, new_status.heading),
+            ] {
+                if old != new && new == LockState::Degraded {
+                    warn!("Bottom lock {name} hold degraded");
+                }
+            }
+        }
+
+        cmds.entity(robot).insert(new_status);
+    }
+}
@@ -0,0 +1,121 @@
+//! Scales back the thruster current budget as the robot's electronics heat up
+//!
+//! The budget falls linearly from the full `motor_amperage_budget` at the knee to `min_factor` of
+//! it at the limit, and recovers the same way as things cool. The factor moves in steps with a
+//! little hysteresis since every change re-solves the axis maximums.
+
+use bevy::prelude::*;
+use common::{
+    components::{MovementCurrentCap, SystemTemperatures, ThermalDerate},
+    params::{AppParamExt, Param, Params},
+    types::units::Amperes,
+};
+
+use crate::{
+    config::{RobotConfig, ThermalDerateConfig},
+    plugins::core::robot::LocalRobotMarker,
+};
+
+const FACTOR_STEP: Param<f32> = Param::new(
+    "robot.derate.factor_step",
+    "Thermal Derate",
+    "Fraction of the budget the factor moves by at a time",
+    0.05,
+    0.01,
+    0.5,
+);
+const HYSTERESIS: Param<f32> = Param::new(
+    "robot.derate.hysteresis",
+    "Thermal Derate",
+    "Degrees celsius the temperature has to drop past a step before the budget is restored",
+    2.0,
+    0.0,
+    10.0,
+);
+
+pub struct ThermalDeratePlugin;
+
+impl Plugin for ThermalDeratePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_param(&FACTOR_STEP)
+            .register_param(&HYSTERESIS)
+            .add_systems(Update, derate_thrusters);
+    }
+}
+
+fn derate_factor(temperature: f32, config: &ThermalDerateConfig, step: f32) -> f32 {
+    let span = (config.limit - config.knee).max(f32::EPSILON);
+    let progress = ((temperature - config.knee) / span).clamp(0.0, 1.0);
+    let factor = 1.0 - progress * (1.0 - config.min_factor);
+
+    // Rounded up so readings just past the knee don't derate yet
+    ((factor / step).ceil() * step).min(1.0)
+}
+
+fn derate_thrusters(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    params: Res<Params>,
+    robot: Query<
+        (Entity, &SystemTemperatures, Option<&ThermalDerate>),
+        (With<LocalRobotMarker>, Changed<SystemTemperatures>),
+    >,
+) {
+    let Ok((entity, temperatures, last)) = robot.get_single() else {
+        return;
+    };
+    let derate_config = &config.thermal_derate;
+
+    let Some(hottest) = temperatures
+        .0
+        .iter()
+        .filter(|it| derate_config.sensors.is_empty() || derate_config.sensors.contains(&it.name))
+        .max_by(|a, b| a.tempature.0.total_cmp(&b.tempature.0))
+    else {
+        return;
+    };
+    let temperature = hottest.tempature.0;
+
+    let last_factor = last.map(|it| it.factor).unwrap_or(1.0);
+    let step = params.get(&FACTOR_STEP);
+    let factor = derate_factor(temperature, derate_config, step);
+    let recovered = derate_factor(temperature + params.get(&HYSTERESIS), derate_config, step);
+
+    let factor = if factor < last_factor {
+        factor
+    } else if recovered > last_factor {
+        recovered
+    } else {
+        last_factor
+    };
+
+    if factor != last_factor {
+        if factor < last_factor {
+            warn!(
+                "Derating thrusters to {:.0}%, {} at {}",
+                factor * 100.0,
+                hottest.name,
+                hottest.tempature
+            );
+        } else {
+            info!(
+                "Restoring thrusters to {:.0}%, {} at {}",
+                factor * 100.0,
+                hottest.name,
+                hottest.tempature
+            );
+        }
+
+        let current_cap = Amperes(config.motor_amperage_budget * factor);
+        cmds.entity(entity).insert(MovementCurrentCap(current_cap));
+    }
+
+    let derate = ThermalDerate {
+        factor,
+        sensor: hottest.name.clone(),
+        temperature: hottest.tempature,
+    };
+    if last != Some(&derate) {
+        cmds.entity(entity).insert(derate);
+    }
+}
@@ -0,0 +1,243 @@
+//! Hard depth and distance limits enforced on the robot
+//!
+//! Depth targets are clamped to the limits. Drifting past a limit anyway takes control away from
+//! the pilot until the robot is back inside: past the depth limit it ascends to just above the
+//! limit, outside the distance limit it surfaces. The pilot can override the limits with a two
+//! step confirmation from the surface, the override lasts until the robot is disarmed.
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use common::{
+    attitude,
+    components::{
+        Armed, DepthMeasurement, DepthTarget, Geofence, GeofenceBreach, Orientation,
+        OrientationTarget, PositionEstimate,
+    },
+    events::{GeofenceOverride, GeofenceOverrideStep},
+    types::units::Meters,
+};
+
+use crate::{
+    config::RobotConfig,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
+
+/// How long the surface has to confirm an override
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct GeofencePlugin;
+
+impl Plugin for GeofencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_geofence).add_systems(
+            Update,
+            (record_home, handle_overrides, enforce_geofence).chain(),
+        );
+    }
+}
+
+fn setup_geofence(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotConfig>) {
+    cmds.entity(robot.entity).insert(Geofence {
+        max_depth: config.geofence.max_depth.map(Meters),
+        max_distance: config.geofence.max_distance.map(Meters),
+        ..default()
+    });
+}
+
+fn record_home(
+    mut robot: Query<
+        (Ref<Armed>, Option<&PositionEstimate>, &mut Geofence),
+        With<LocalRobotMarker>,
+    >,
+) {
+    let Ok((armed, position, mut geofence)) = robot.get_single_mut() else {
+        return;
+    };
+
+    let mut new = geofence.clone();
+
+    match *armed {
+        Armed::Armed => {
+            // Also covers arming before there was a position
+            if armed.is_changed() || new.home.is_none() {
+                if let Some(position) = position {
+                    new.home = Some(position.position);
+                }
+            }
+        }
+        Armed::Disarmed => {
+            new.home = None;
+            new.overridden = false;
+        }
+    }
+
+    if new.home != geofence.home && new.home.is_some() {
+        info!("Geofence home at {:?}", new.home);
+    }
+    if new.overridden != geofence.overridden {
+        info!("Geofence override ended by disarming");
+    }
+
+    geofence.set_if_neq(new);
+}
+
+fn handle_overrides(
+    mut next_challenge: Local<u32>,
+    mut issued: Local<Option<Instant>>,
+
+    mut events: EventReader<GeofenceOverride>,
+    mut robot: Query<&mut Geofence, With<LocalRobotMarker>>,
+) {
+    let Ok(mut geofence) = robot.get_single_mut() else {
+        return;
+    };
+
+    let mut new = geofence.clone();
+
+    for GeofenceOverride(step) in events.read() {
+        match *step {
+            GeofenceOverrideStep::Request => {
+                *next_challenge = next_challenge.wrapping_add(1);
+                new.override_challenge = Some(*next_challenge);
+                *issued = Some(Instant::now());
+
+                warn!("Geofence override requested, waiting for confirmation");
+            }
+            GeofenceOverrideStep::Confirm(challenge) => {
+                if new.override_challenge == Some(challenge) {
+                    warn!("Geofence overridden until disarmed");
+                    new.overridden = true;
+                } else {
+                    warn!("Ignoring geofence override with a stale challenge");
+                }
+
+                new.override_challenge = None;
+            }
+            GeofenceOverrideStep::Cancel => {
+                if new.overridden {
+                    info!("Geofence override ended");
+                }
+
+                new.override_challenge = None;
+                new.overridden = false;
+            }
+        }
+    }
+
+    if issued.is_some_and(|it| it.elapsed() > CHALLENGE_TIMEOUT) {
+        if new.override_challenge.take().is_some() {
+            info!("Geofence override was not confirmed in time");
+        }
+        *issued = None;
+    }
+
+    geofence.set_if_neq(new);
+}
+
+fn enforce_geofence(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    mut robot: Query<
+        (
+            Entity,
+            &Armed,
+            &mut Geofence,
+            Option<&DepthMeasurement>,
+            Option<&DepthTarget>,
+            (Option<&Orientation>, Option<&OrientationTarget>),
+            Option<&PositionEstimate>,
+        ),
+        With<LocalRobotMarker>,
+    >,
+) {
+    let Ok((
+        entity,
+        armed,
+        mut geofence,
+        depth,
+        depth_target,
+        (orientation, orientation_target),
+        position,
+    )) = robot.get_single_mut()
+    else {
+        return;
+    };
+
+    let outside = geofence
+        .max_distance
+        .zip(geofence.home)
+        .zip(position)
+        .is_some_and(|((max_distance, home), position)| {
+            (position.position - home).truncate().length() > max_distance.0
+        });
+
+    // Outside the distance limit the robot may only be at the surface
+    let allowed = if outside {
+        Some(0.0)
+    } else {
+        geofence.max_depth.map(|it| it.0)
+    };
+
+    let Some(allowed) = allowed.filter(|_| *armed == Armed::Armed && !geofence.overridden) else {
+        geofence.set_if_neq(Geofence {
+            breach: None,
+            ..geofence.clone()
+        });
+        return;
+    };
+
+    let tolerance = config.geofence.tolerance;
+    let mut breach = geofence.breach;
+
+    if let Some(depth) = depth.map(|it| it.depth.0) {
+        if breach.is_none() && depth > allowed + tolerance {
+            let kind = if outside {
+                GeofenceBreach::Distance
+            } else {
+                GeofenceBreach::Depth
+            };
+
+            warn!("{} breached at {depth:.2}m, taking control", kind.name());
+            breach = Some(kind);
+        } else if breach.is_some() && depth <= allowed + tolerance / 2.0 {
+            info!("Back inside the geofence, returning control");
+            breach = None;
+        }
+    }
+
+    let target_limit = match breach {
+        Some(_) if !outside => (allowed - config.geofence.recovery_margin).max(0.0),
+        _ => allowed,
+    };
+
+    match (breach, depth_target) {
+        // Holds depth even if the pilot had turned depth hold off
+        (Some(_), Some(&DepthTarget(Meters(target)))) if target > target_limit => {
+            cmds.entity(entity)
+                .insert(DepthTarget(Meters(target_limit)));
+        }
+        (Some(_), None) => {
+            cmds.entity(entity)
+                .insert(DepthTarget(Meters(target_limit)));
+        }
+        (None, Some(&DepthTarget(Meters(target)))) if target > allowed => {
+            debug!("Clamping depth target {target:.2}m to {allowed:.2}m");
+            cmds.entity(entity).insert(DepthTarget(Meters(allowed)));
+        }
+        _ => {}
+    }
+
+    // Without input the robot would drift, holding it level keeps the ascent straight
+    if breach.is_some() && orientation_target.is_none() {
+        if let Some(&Orientation(orientation)) = orientation {
+            cmds.entity(entity)
+                .insert(OrientationTarget(attitude::yaw_rotation(orientation)));
+        }
+    }
+
+    geofence.set_if_neq(Geofence {
+        breach,
+        ..geofence.clone()
+    });
+}
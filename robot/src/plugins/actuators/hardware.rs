@@ -3,3 +3,8 @@ pub mod motor_id_map;
 pub mod pwm;
 
 pub enum MotorReference {}
+
+/// Reported when the motor outputs stop because the surface stopped re-arming them in time
+#[derive(Debug, thiserror::Error)]
+#[error("Motors disarmed due to inactivity")]
+pub struct InactivityDisarm;
@@ -27,8 +27,14 @@ use tokio::{
     time,
 };
 
-use super::motor_id_map::{DcChannel, LocalMotorId};
-use crate::plugins::core::robot::{LocalRobot, LocalRobotMarker};
+use super::{
+    motor_id_map::{DcChannel, LocalMotorId},
+    InactivityDisarm,
+};
+use crate::plugins::{
+    actuators::maintenance::MaintenanceOutputs,
+    core::robot::{LocalRobot, LocalRobotMarker},
+};
 
 const NUM_CHANNELS: usize = 4;
 // fraction of output
@@ -250,7 +256,7 @@ fn start_dc_motor_thread(
                 if matches!(armed, Armed::Armed) && last_arm_timestamp.elapsed() > max_inactive {
                     warn!("Time since last arm exceeded max_inactive, disarming");
 
-                    let _ = errors.send(anyhow!("Motors disarmed due to inactivity"));
+                    let _ = errors.send(InactivityDisarm.into());
                     armed = Armed::Disarmed;
                     channel_signals = STOP_SIGNALS;
                 }
@@ -338,6 +344,7 @@ fn start_dc_motor_thread(
 
 fn listen_to_dc_motors(
     channels: Res<DcMotorChannels>,
+    maintenance: Res<MaintenanceOutputs>,
     robot: Query<(&NetId, &Armed), With<LocalRobotMarker>>,
     pwms: Query<(
         &RobotId,
@@ -348,9 +355,17 @@ fn listen_to_dc_motors(
 ) -> anyhow::Result<()> {
     let (net_id, armed) = robot.single();
 
+    // Maintenance routines drive the outputs while the robot is disarmed
+    let maintenance = maintenance.0.as_ref().filter(|_| *armed == Armed::Disarmed);
+    let armed = if maintenance.is_some() {
+        Armed::Armed
+    } else {
+        *armed
+    };
+
     channels
         .0
-        .blocking_send(DcMotorEvent::Arm(*armed))
+        .blocking_send(DcMotorEvent::Arm(armed))
         .context("Send data to dc motor thread")?;
 
     let mut channel_batch = STOP_SIGNALS;
@@ -359,15 +374,19 @@ fn listen_to_dc_motors(
             continue;
         }
 
+        let signal = match maintenance {
+            Some(outputs) => outputs
+                .get(&channel)
+                .copied()
+                .unwrap_or(MotorSignal::Percent(0.0)),
+            None => signal,
+        };
+
         let LocalMotorId::DcChannel(channel) = channel.into() else {
             continue;
         };
 
-        let output = match signal {
-            MotorSignal::Percent(pct) => raw_range.raw_from_percent(pct),
-            MotorSignal::Raw(raw) => raw,
-        };
-        let output = raw_range.clamp_raw(output) as i16;
+        let output = signal.clamped(raw_range).to_raw(raw_range) as i16;
 
         let id = channel.id() as usize;
         if id < NUM_CHANNELS {
@@ -1,4 +1,4 @@
-use common::components::{GenericMotorId, MotorRawSignalRange};
+use common::components::{ActuatorChannelType, GenericMotorId, MotorRawSignalRange};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -64,22 +64,22 @@ impl DcChannel {
 
 impl From<LocalMotorId> for GenericMotorId {
     fn from(value: LocalMotorId) -> Self {
-        GenericMotorId(match value {
-            LocalMotorId::PwmChannel(pwm_channel) => pwm_channel.id(),
-            LocalMotorId::DcChannel(dc_channel) => dc_channel.id() | 0x80,
-        })
+        match value {
+            LocalMotorId::PwmChannel(pwm_channel) => {
+                GenericMotorId::new(ActuatorChannelType::Pwm, pwm_channel.id())
+            }
+            LocalMotorId::DcChannel(dc_channel) => {
+                GenericMotorId::new(ActuatorChannelType::Dc, dc_channel.id())
+            }
+        }
     }
 }
 
 impl From<GenericMotorId> for LocalMotorId {
     fn from(value: GenericMotorId) -> Self {
-        let motor_type = value.0 >> 7;
-        let id = value.0 & 0x7F;
-
-        if motor_type == 0 {
-            LocalMotorId::PwmChannel(PwmChannel::new(id))
-        } else {
-            LocalMotorId::DcChannel(DcChannel::new(id))
+        match value.channel_type() {
+            ActuatorChannelType::Pwm => LocalMotorId::PwmChannel(PwmChannel::new(value.index())),
+            ActuatorChannelType::Dc => LocalMotorId::DcChannel(DcChannel::new(value.index())),
         }
     }
 }
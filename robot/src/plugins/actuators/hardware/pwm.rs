@@ -3,7 +3,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use bevy::{app::AppExit, prelude::*};
 use common::{
     components::{Armed, GenericMotorId, MotorRawSignalRange, MotorSignal, RobotId},
@@ -13,8 +13,11 @@ use common::{
 use crossbeam::channel::{self, Sender};
 use tracing::{span, Level};
 
-use super::motor_id_map::LocalMotorId;
-use crate::{peripheral::pca9685::Pca9685, plugins::core::robot::LocalRobotMarker};
+use super::{motor_id_map::LocalMotorId, InactivityDisarm};
+use crate::{
+    peripheral::pca9685::Pca9685,
+    plugins::{actuators::maintenance::MaintenanceOutputs, core::robot::LocalRobotMarker},
+};
 
 const NUM_CHANNELS: usize = 16;
 // microseconds
@@ -124,7 +127,7 @@ fn start_pwm_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result<(
                 if matches!(armed, Armed::Armed) && last_arm_timestamp.elapsed() > max_inactive {
                     warn!("Time since last arm exceeded max_inactive, disarming");
 
-                    let _ = errors.send(anyhow!("Motors disarmed due to inactivity"));
+                    let _ = errors.send(InactivityDisarm.into());
                     armed = Armed::Disarmed;
                     channel_pwms = STOP_PWMS;
                 }
@@ -180,6 +183,7 @@ fn start_pwm_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result<(
 
 fn listen_to_pwms(
     channels: Res<GenericMotorIds>,
+    maintenance: Res<MaintenanceOutputs>,
     robot: Query<(&NetId, &Armed), With<LocalRobotMarker>>,
     pwms: Query<(
         &RobotId,
@@ -190,9 +194,17 @@ fn listen_to_pwms(
 ) -> anyhow::Result<()> {
     let (net_id, armed) = robot.single();
 
+    // Maintenance routines drive the outputs while the robot is disarmed
+    let maintenance = maintenance.0.as_ref().filter(|_| *armed == Armed::Disarmed);
+    let armed = if maintenance.is_some() {
+        Armed::Armed
+    } else {
+        *armed
+    };
+
     channels
         .0
-        .send(PwmEvent::Arm(*armed))
+        .send(PwmEvent::Arm(armed))
         .context("Send data to pwm thread")?;
 
     let mut channel_batch = STOP_SIGNALS;
@@ -201,15 +213,19 @@ fn listen_to_pwms(
             continue;
         }
 
+        let signal = match maintenance {
+            Some(outputs) => outputs
+                .get(&channel)
+                .copied()
+                .unwrap_or(MotorSignal::Percent(0.0)),
+            None => signal,
+        };
+
         let LocalMotorId::PwmChannel(channel) = channel.into() else {
             continue;
         };
 
-        let pwm = match signal {
-            MotorSignal::Percent(pct) => raw_range.raw_from_percent(pct),
-            MotorSignal::Raw(raw) => raw,
-        };
-        let pwm = raw_range.clamp_raw(pwm) as u16;
+        let pwm = signal.clamped(raw_range).to_raw(raw_range) as u16;
 
         let id = channel.id() as usize;
         if id < NUM_CHANNELS {
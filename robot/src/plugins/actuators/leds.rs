@@ -181,10 +181,7 @@ fn update_leds(
                 let signal = thrusters.get(&GenericMotorId(id));
 
                 if let Some((signal, raw_range)) = signal {
-                    let pct = match signal {
-                        MotorSignal::Percent(pct) => *pct,
-                        MotorSignal::Raw(signal) => raw_range.percent_from_raw(*signal),
-                    };
+                    let pct = signal.to_percent(raw_range);
 
                     if pct > 0.0 {
                         // Forward
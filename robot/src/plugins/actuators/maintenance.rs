@@ -0,0 +1,484 @@
+//! Actuator test routines run from the surface's Maintenance menu
+//!
+//! A routine only starts while the robot is disarmed and after a two step confirmation from the
+//! surface. While it runs the output plugins drive the signals in `MaintenanceOutputs` instead of
+//! the actuators' own, holding every other channel at neutral, and keep the outputs enabled even
+//! though the robot is disarmed. Arming the robot or cancelling from the surface stops it.
+
+use std::{
+    f32::consts::TAU,
+    time::{Duration, Instant},
+};
+
+use ahash::HashMap;
+use anyhow::anyhow;
+use bevy::prelude::*;
+use common::{
+    components::{
+        ActuatorIdentity, ActuatorKind, Armed, CurrentDraw, GenericMotorId, Maintenance,
+        MaintenanceFinding, MaintenanceProgress, MaintenanceRoutine, MotorSignal, RobotId,
+    },
+    error,
+    events::{MaintenanceCommand, MaintenanceStep},
+    params::{AppParamExt, Param, Params},
+};
+
+use crate::{
+    config::RobotConfig,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
+
+/// How long the surface has to confirm a routine
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Neutral signal held before the first thruster so the escs finish arming, a little longer than
+/// the pwm plugin holds it for
+const ESC_ARMING: Duration = Duration::from_secs(2);
+/// Enough for the motor to tick without spinning up
+const BEACON_SIGNAL: f32 = 0.05;
+const BEACON_PULSE: Duration = Duration::from_millis(150);
+const BEACON_GAP: Duration = Duration::from_millis(350);
+/// Pause after each thruster's pulses so the counts are easy to tell apart
+const BEACON_SPACING: Duration = Duration::from_secs(1);
+
+const SWEEP_DURATION: Duration = Duration::from_secs(4);
+/// Velocity servos have no range to sweep, they are run each way at this speed instead
+const SWEEP_SPEED: f32 = 0.5;
+/// Start of a sweep the idle current is taken from, before the servo has moved much
+const SWEEP_SETTLE: Duration = Duration::from_millis(250);
+
+const FLASHES: u32 = 3;
+const FLASH_PERIOD: Duration = Duration::from_millis(600);
+
+const BINDING_CURRENT: Param<f32> = Param::new(
+    "robot.maintenance.binding_current",
+    "Maintenance",
+    "Amperes above idle a servo may draw during a sweep before it is reported as binding",
+    1.0,
+    0.1,
+    10.0,
+);
+
+pub struct MaintenancePlugin;
+
+impl Plugin for MaintenancePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_param(&BINDING_CURRENT)
+            .init_resource::<MaintenanceOutputs>()
+            .init_resource::<MaintenanceRun>()
+            .add_systems(Startup, setup_maintenance)
+            .add_systems(
+                Update,
+                (handle_commands.pipe(error::handle_errors), run_maintenance).chain(),
+            );
+    }
+}
+
+/// Signals the output plugins drive while a routine runs, channels left out are held at neutral.
+/// `None` when no routine is running
+#[derive(Resource, Default)]
+pub struct MaintenanceOutputs(pub Option<HashMap<GenericMotorId, MotorSignal>>);
+
+#[derive(Resource, Default)]
+struct MaintenanceRun(Option<Run>);
+
+struct Run {
+    routine: MaintenanceRoutine,
+    steps: Vec<Step>,
+    step: usize,
+    step_started: Instant,
+
+    /// Amperes, taken at the start of a sweep
+    idle_current: Option<f32>,
+    peak_current: Option<f32>,
+
+    findings: Vec<MaintenanceFinding>,
+}
+
+struct Step {
+    /// `None` holds every channel at neutral
+    actuator: Option<(Entity, ActuatorIdentity)>,
+    duration: Duration,
+    pattern: Pattern,
+}
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Hold,
+    Beacon { pulses: u32 },
+    Sweep { amplitude: f32 },
+    Flash,
+}
+
+impl Pattern {
+    fn signal(&self, elapsed: Duration, duration: Duration) -> MotorSignal {
+        match *self {
+            Pattern::Hold => MotorSignal::Percent(0.0),
+            Pattern::Beacon { pulses } => {
+                let period = (BEACON_PULSE + BEACON_GAP).as_nanos();
+                let pulse = elapsed.as_nanos() / period;
+                let in_pulse = elapsed.as_nanos() % period < BEACON_PULSE.as_nanos();
+
+                if pulse < pulses as u128 && in_pulse {
+                    MotorSignal::Percent(BEACON_SIGNAL)
+                } else {
+                    MotorSignal::Percent(0.0)
+                }
+            }
+            Pattern::Sweep { amplitude } => {
+                // Out to one end, back through center to the other and back to center
+                let fraction = elapsed.as_secs_f32() / duration.as_secs_f32();
+                MotorSignal::Percent(amplitude * (TAU * fraction).sin())
+            }
+            Pattern::Flash => {
+                let period = FLASH_PERIOD.as_nanos();
+                let on = elapsed.as_nanos() % period < period / 2;
+
+                // Clamped to the light's range by the output plugins
+                MotorSignal::Percent(if on { 1.0 } else { 0.0 })
+            }
+        }
+    }
+}
+
+fn setup_maintenance(mut cmds: Commands, robot: Res<LocalRobot>) {
+    cmds.entity(robot.entity).insert(Maintenance::default());
+}
+
+/// Orders the actuators a routine exercises by channel so the pilot can follow along
+fn plan(
+    routine: MaintenanceRoutine,
+    mut actuators: Vec<(Entity, ActuatorIdentity)>,
+    lights: &[String],
+) -> Vec<Step> {
+    actuators.sort_by_key(|(_, identity)| identity.channel());
+
+    let is_light = |identity: &ActuatorIdentity| lights.contains(&identity.name);
+
+    match routine {
+        MaintenanceRoutine::EscBeacon => {
+            let thrusters = actuators
+                .into_iter()
+                .filter(|(_, identity)| identity.kind == ActuatorKind::Thruster)
+                .enumerate()
+                .map(|(idx, actuator)| {
+                    let pulses = idx as u32 + 1;
+
+                    Step {
+                        actuator: Some(actuator),
+                        duration: (BEACON_PULSE + BEACON_GAP) * pulses + BEACON_SPACING,
+                        pattern: Pattern::Beacon { pulses },
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if thrusters.is_empty() {
+                return thrusters;
+            }
+
+            let arming = Step {
+                actuator: None,
+                duration: ESC_ARMING,
+                pattern: Pattern::Hold,
+            };
+
+            [arming].into_iter().chain(thrusters).collect()
+        }
+        MaintenanceRoutine::ServoSweep => actuators
+            .into_iter()
+            .filter(|(_, identity)| identity.kind == ActuatorKind::Servo && !is_light(identity))
+            .map(|actuator| {
+                let amplitude = if actuator.1.positional {
+                    1.0
+                } else {
+                    SWEEP_SPEED
+                };
+
+                Step {
+                    actuator: Some(actuator),
+                    duration: SWEEP_DURATION,
+                    pattern: Pattern::Sweep { amplitude },
+                }
+            })
+            .collect(),
+        MaintenanceRoutine::LightFlash => actuators
+            .into_iter()
+            .filter(|(_, identity)| identity.kind == ActuatorKind::Servo && is_light(identity))
+            .map(|actuator| Step {
+                actuator: Some(actuator),
+                duration: FLASH_PERIOD * FLASHES,
+                pattern: Pattern::Flash,
+            })
+            .collect(),
+    }
+}
+
+fn handle_commands(
+    mut next_challenge: Local<u32>,
+    mut issued: Local<Option<Instant>>,
+
+    mut events: EventReader<MaintenanceCommand>,
+    mut run: ResMut<MaintenanceRun>,
+    config: Res<RobotConfig>,
+    local_robot: Res<LocalRobot>,
+    mut robot: Query<(&Armed, &mut Maintenance), With<LocalRobotMarker>>,
+    actuators: Query<(Entity, &ActuatorIdentity, &RobotId)>,
+) -> anyhow::Result<()> {
+    let Ok((armed, mut maintenance)) = robot.get_single_mut() else {
+        events.clear();
+        return Ok(());
+    };
+
+    let mut new = maintenance.clone();
+    let mut rst = Ok(());
+
+    for MaintenanceCommand(step) in events.read() {
+        match *step {
+            MaintenanceStep::Request(routine) => {
+                if *armed == Armed::Armed {
+                    rst = Err(anyhow!("Cannot run {} while armed", routine.name()));
+                    continue;
+                }
+                if let Some(run) = &run.0 {
+                    rst = Err(anyhow!("{} is already running", run.routine.name()));
+                    continue;
+                }
+
+                *next_challenge = next_challenge.wrapping_add(1);
+                new.override_challenge = Some((routine, *next_challenge));
+                *issued = Some(Instant::now());
+
+                warn!("{} requested, waiting for confirmation", routine.name());
+            }
+            MaintenanceStep::Confirm(challenge) => {
+                let Some((routine, _)) = new
+                    .override_challenge
+                    .take()
+                    .filter(|&(_, expected)| expected == challenge)
+                else {
+                    warn!("Ignoring maintenance confirmation with a stale challenge");
+                    continue;
+                };
+
+                if *armed == Armed::Armed {
+                    rst = Err(anyhow!("Cannot run {} while armed", routine.name()));
+                    continue;
+                }
+
+                let actuators = actuators
+                    .iter()
+                    .filter(|(_, _, robot)| robot.0 == local_robot.net_id)
+                    .map(|(entity, identity, _)| (entity, identity.clone()))
+                    .collect();
+                let steps = plan(routine, actuators, &config.servo_config.lights);
+
+                if steps.is_empty() {
+                    rst = Err(anyhow!("No actuators for {}", routine.name()));
+                    continue;
+                }
+
+                warn!("Running {} while disarmed", routine.name());
+
+                new.findings.clear();
+                run.0 = Some(Run {
+                    routine,
+                    steps,
+                    step: 0,
+                    step_started: Instant::now(),
+                    idle_current: None,
+                    peak_current: None,
+                    findings: Vec::new(),
+                });
+            }
+            MaintenanceStep::Cancel => {
+                new.override_challenge = None;
+
+                if let Some(run) = run.0.take() {
+                    info!("{} cancelled", run.routine.name());
+
+                    new.findings = run.findings;
+                    new.running = None;
+                }
+            }
+        }
+    }
+
+    if issued.is_some_and(|it| it.elapsed() > CHALLENGE_TIMEOUT) {
+        if new.override_challenge.take().is_some() {
+            info!("Maintenance routine was not confirmed in time");
+        }
+        *issued = None;
+    }
+
+    maintenance.set_if_neq(new);
+
+    rst
+}
+
+fn run_maintenance(
+    mut run: ResMut<MaintenanceRun>,
+    mut outputs: ResMut<MaintenanceOutputs>,
+    params: Res<Params>,
+    mut robot: Query<(&Armed, Option<&CurrentDraw>, &mut Maintenance), With<LocalRobotMarker>>,
+    currents: Query<&CurrentDraw>,
+) {
+    let Ok((armed, robot_current, mut maintenance)) = robot.get_single_mut() else {
+        return;
+    };
+
+    let Some(active) = &mut run.0 else {
+        outputs.0 = None;
+        if maintenance.running.is_some() {
+            maintenance.running = None;
+        }
+        return;
+    };
+
+    if *armed == Armed::Armed {
+        warn!("{} stopped, the robot was armed", active.routine.name());
+
+        let mut new = maintenance.clone();
+        new.running = None;
+        new.findings = run.0.take().map(|it| it.findings).unwrap_or_default();
+        maintenance.set_if_neq(new);
+
+        outputs.0 = None;
+        return;
+    }
+
+    // Moves on to the next step once this one is over, a step always lasts at least a frame
+    let mut elapsed = active.step_started.elapsed();
+    if elapsed >= active.steps[active.step].duration {
+        let step = &active.steps[active.step];
+        if let Some(finding) = finding(
+            step,
+            active.idle_current,
+            active.peak_current,
+            params.get(&BINDING_CURRENT),
+        ) {
+            active.findings.push(finding);
+        }
+
+        active.step += 1;
+        active.step_started = Instant::now();
+        active.idle_current = None;
+        active.peak_current = None;
+        elapsed = Duration::ZERO;
+
+        if active.step == active.steps.len() {
+            info!("{} finished", active.routine.name());
+
+            let mut new = maintenance.clone();
+            new.running = None;
+            new.findings = run.0.take().map(|it| it.findings).unwrap_or_default();
+            maintenance.set_if_neq(new);
+
+            outputs.0 = None;
+            return;
+        }
+    }
+
+    let step = &active.steps[active.step];
+
+    let mut signals = HashMap::default();
+    if let Some((entity, identity)) = &step.actuator {
+        signals.insert(
+            identity.channel(),
+            step.pattern.signal(elapsed, step.duration),
+        );
+
+        // Servos on the dc channels report their own current, the rest only show up in the
+        // robot's total
+        if let Pattern::Sweep { .. } = step.pattern {
+            let current = currents
+                .get(*entity)
+                .ok()
+                .or(robot_current)
+                .map(|it| it.0 .0);
+
+            if let Some(current) = current {
+                if elapsed < SWEEP_SETTLE {
+                    active.idle_current = Some(current);
+                } else {
+                    let peak = active.peak_current.unwrap_or(current);
+                    active.peak_current = Some(peak.max(current));
+                }
+            }
+        }
+    }
+    outputs.0 = Some(signals);
+
+    let total: Duration = active.steps.iter().map(|it| it.duration).sum();
+    let done: Duration = active.steps[..active.step]
+        .iter()
+        .map(|it| it.duration)
+        .sum::<Duration>()
+        + elapsed;
+    // Whole percents so the progress isn't replicated every frame
+    let progress = (done.as_secs_f32() / total.as_secs_f32() * 100.0).floor() / 100.0;
+
+    let running = MaintenanceProgress {
+        routine: active.routine,
+        actuator: step
+            .actuator
+            .as_ref()
+            .map(|(_, identity)| identity.label())
+            .unwrap_or_default(),
+        progress,
+    };
+
+    if maintenance.running.as_ref() != Some(&running) {
+        maintenance.running = Some(running);
+    }
+}
+
+fn finding(
+    step: &Step,
+    idle_current: Option<f32>,
+    peak_current: Option<f32>,
+    binding_current: f32,
+) -> Option<MaintenanceFinding> {
+    let (_, identity) = step.actuator.as_ref()?;
+    let actuator = identity.label();
+
+    let finding = match step.pattern {
+        Pattern::Hold => return None,
+        Pattern::Beacon { pulses } => MaintenanceFinding {
+            actuator,
+            passed: true,
+            detail: format!("Pulsed {pulses} times"),
+        },
+        Pattern::Sweep { .. } => match idle_current.zip(peak_current) {
+            Some((idle, peak)) => {
+                let rise = peak - idle;
+                let passed = rise <= binding_current;
+
+                if !passed {
+                    warn!("{actuator} may be binding, drew {rise:.2}A above idle");
+                }
+
+                MaintenanceFinding {
+                    actuator,
+                    passed,
+                    detail: if passed {
+                        format!("Swept, peaked {rise:.2}A above idle")
+                    } else {
+                        format!("Binding, drew {rise:.2}A above idle")
+                    },
+                }
+            }
+            None => MaintenanceFinding {
+                actuator,
+                passed: true,
+                detail: "Swept, no current measurement to check for binding".to_owned(),
+            },
+        },
+        Pattern::Flash => MaintenanceFinding {
+            actuator,
+            passed: true,
+            detail: format!("Flashed {FLASHES} times"),
+        },
+    };
+
+    Some(finding)
+}
@@ -0,0 +1,205 @@
+//! Mixes the `MotorContribution`s of every source driving the robot's servos
+//!
+//! Like movement, each source keeps its contribution on its own entity and the robot sums them.
+//! `MotorMixing` on a source sets its priority, limit and rate limit. Each motor is only driven by
+//! the highest priority sources asking for it, so a camera follow or a scripted sequence can take
+//! a servo from the pilot without the two fighting over it.
+
+use ahash::HashMap;
+use bevy::prelude::*;
+use common::{
+    components::{GenericMotorId, Geofence, MotorContribution, MotorMixing, RobotId},
+    ecs_sync::NetId,
+};
+
+use crate::plugins::core::robot::LocalRobotMarker;
+
+pub struct MotorMixerPlugin;
+
+impl Plugin for MotorMixerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MixedMotorInputs>()
+            .add_systems(Update, mix_motor_contributions);
+    }
+}
+
+/// The mixed contribution to each motor, driven by the servo plugin
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MixedMotorInputs(pub HashMap<GenericMotorId, f32>);
+
+pub fn mix_motor_contributions(
+    robot: Query<(&NetId, Option<&Geofence>), With<LocalRobotMarker>>,
+    sources: Query<(Entity, &RobotId, &MotorContribution, Option<&MotorMixing>)>,
+    mut mixed: ResMut<MixedMotorInputs>,
+    // What each source contributed to each motor last frame, after its limits
+    mut last: Local<HashMap<(Entity, GenericMotorId), f32>>,
+    time: Res<Time<Real>>,
+) {
+    mixed.0.clear();
+
+    let Ok((&net_id, geofence)) = robot.get_single() else {
+        last.clear();
+        return;
+    };
+
+    // Motors may also move the robot, so none are driven while it recovers from a geofence breach
+    if geofence.is_some_and(Geofence::enforcing) {
+        last.clear();
+        return;
+    }
+
+    let sources = sources
+        .iter()
+        .filter(|(_, robot, ..)| robot.0 == net_id)
+        .map(|(source, _, contribution, mixing)| {
+            (source, contribution, mixing.copied().unwrap_or_default())
+        });
+
+    let contributed = mix(sources, &last, time.delta_secs(), &mut mixed.0);
+    *last = contributed;
+}
+
+/// Mixes one frame of contributions into `mixed`, returns what each source contributed to each
+/// motor after its limits
+fn mix<'a>(
+    sources: impl Iterator<Item = (Entity, &'a MotorContribution, MotorMixing)>,
+    last: &HashMap<(Entity, GenericMotorId), f32>,
+    delta_secs: f32,
+    mixed: &mut HashMap<GenericMotorId, f32>,
+) -> HashMap<(Entity, GenericMotorId), f32> {
+    let mut contributed = HashMap::default();
+    // The highest priority asking for each motor and the sum of the contributions at it
+    let mut motors = HashMap::<GenericMotorId, (i32, f32)>::default();
+
+    for (source, contribution, mixing) in sources {
+        for (&motor, &input) in &contribution.0 {
+            let target = match mixing.limit() {
+                Some(limit) => input.clamp(-limit, limit),
+                None => input,
+            };
+            let value = match mixing.rate_limit() {
+                Some(rate_limit) => {
+                    let last = last.get(&(source, motor)).copied().unwrap_or(0.0);
+                    let step = rate_limit * delta_secs;
+
+                    last + (target - last).clamp(-step, step)
+                }
+                None => target,
+            };
+            contributed.insert((source, motor), value);
+
+            // A source still asking for zero keeps driving the motor, just without a say in it
+            let (priority, sum) = motors.entry(motor).or_insert((i32::MIN, 0.0));
+            if value == 0.0 {
+                continue;
+            }
+
+            if mixing.priority > *priority {
+                *priority = mixing.priority;
+                *sum = value;
+            } else if mixing.priority == *priority {
+                *sum += value;
+            }
+        }
+    }
+
+    mixed.extend(motors.into_iter().map(|(motor, (_, sum))| (motor, sum)));
+
+    contributed
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::HashMap;
+    use bevy::ecs::entity::Entity;
+    use common::components::{GenericMotorId, MotorContribution, MotorMixing};
+
+    use super::mix;
+
+    const MOTOR: GenericMotorId = GenericMotorId(0);
+
+    fn contribution(value: f32) -> MotorContribution {
+        MotorContribution([(MOTOR, value)].into())
+    }
+
+    /// Mixes one frame, returns the value for `MOTOR` and what each source contributed
+    fn mix_once(
+        sources: &[(Entity, MotorContribution, MotorMixing)],
+        last: &HashMap<(Entity, GenericMotorId), f32>,
+        delta_secs: f32,
+    ) -> (f32, HashMap<(Entity, GenericMotorId), f32>) {
+        let mut mixed = HashMap::default();
+        let sources = sources
+            .iter()
+            .map(|(source, contribution, mixing)| (*source, contribution, *mixing));
+        let contributed = mix(sources, last, delta_secs, &mut mixed);
+
+        (mixed.get(&MOTOR).copied().unwrap_or_default(), contributed)
+    }
+
+    fn mix_single(value: f32, mixing: MotorMixing) -> f32 {
+        let sources = [(Entity::from_raw(0), contribution(value), mixing)];
+        mix_once(&sources, &HashMap::default(), 0.25).0
+    }
+
+    #[test]
+    fn highest_priority_wins() {
+        let (pilot, camera, script) = (
+            Entity::from_raw(0),
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+        );
+        let low = MotorMixing::default();
+        let high = MotorMixing {
+            priority: 1,
+            ..Default::default()
+        };
+
+        let sources = [
+            (pilot, contribution(0.5), low),
+            (camera, contribution(0.25), high),
+            (script, contribution(0.125), high),
+        ];
+        assert_eq!(mix_once(&sources, &HashMap::default(), 0.1).0, 0.375);
+
+        // Letting go hands the motor back to the lower priority
+        let sources = [
+            (pilot, contribution(0.5), low),
+            (camera, contribution(0.0), high),
+        ];
+        assert_eq!(mix_once(&sources, &HashMap::default(), 0.1).0, 0.5);
+    }
+
+    #[test]
+    fn limit_clamps() {
+        let limit = |limit| MotorMixing {
+            limit: Some(limit),
+            ..Default::default()
+        };
+
+        assert_eq!(mix_single(0.75, limit(0.5)), 0.5);
+        assert_eq!(mix_single(-0.75, limit(0.5)), -0.5);
+
+        // Any surface can set these, bad values must not panic the robot
+        assert_eq!(mix_single(0.75, limit(-0.5)), 0.5);
+        assert_eq!(mix_single(0.75, limit(f32::NAN)), 0.75);
+    }
+
+    #[test]
+    fn rate_limit_ramps() {
+        let rate_limit = |rate_limit| MotorMixing {
+            rate_limit: Some(rate_limit),
+            ..Default::default()
+        };
+
+        let sources = [(Entity::from_raw(0), contribution(1.0), rate_limit(1.0))];
+        let (mixed, last) = mix_once(&sources, &HashMap::default(), 0.25);
+        assert_eq!(mixed, 0.25);
+        let (mixed, _) = mix_once(&sources, &last, 0.25);
+        assert_eq!(mixed, 0.5);
+
+        assert_eq!(mix_single(1.0, rate_limit(-1.0)), 0.25);
+        assert_eq!(mix_single(1.0, rate_limit(f32::NAN)), 1.0);
+        assert_eq!(mix_single(1.0, rate_limit(f32::INFINITY)), 1.0);
+    }
+}
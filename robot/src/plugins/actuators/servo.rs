@@ -3,15 +3,16 @@ use bevy::prelude::*;
 use common::{
     bundles::{ActuatorBundle, MotorBundle},
     components::{
-        DisableMovementApi, GenericMotorId, MotorCameraReference, MotorContribution,
-        MotorContributionMode, MotorRawSignalRange, MotorSignal, MotorSignalType, MotorSlewRate,
-        MotorTargets, Motors, RobotId,
+        ActiveServoPreset, ActuatorIdentity, ActuatorKind, DisableMovementApi, GenericMotorId,
+        MotorCameraReference, MotorContributionMode, MotorRawSignalRange, MotorSignal,
+        MotorSignalType, MotorSlewRate, MotorTargets, Motors, RobotId, ServoPreset, ServoPresets,
     },
-    ecs_sync::{NetId, Replicate},
-    events::{ResetServo, ResetServos},
+    ecs_sync::Replicate,
+    events::{GoToServoPreset, ResetServo, ResetServos},
 };
 use motor_math::motor_preformance::MotorData;
 
+use super::mixer::{mix_motor_contributions, MixedMotorInputs};
 use crate::{
     config::{RobotConfig, Servo},
     plugins::core::robot::{LocalRobot, LocalRobotMarker},
@@ -22,14 +23,31 @@ pub struct ServoPlugin;
 impl Plugin for ServoPlugin {
     fn build(&self, app: &mut App) {
         // TODO(mid): Update motor config when motor definitions change
-        app.add_systems(Startup, create_servos)
-            .add_systems(Update, handle_servo_input);
+        app.init_resource::<ServoPresetMotion>()
+            .add_systems(Startup, create_servos)
+            .add_systems(
+                Update,
+                (start_servo_presets, handle_servo_input)
+                    .chain()
+                    .after(mix_motor_contributions),
+            );
     }
 }
 
 #[derive(Resource)]
 pub struct MotorDataRes(pub MotorData);
 
+/// The preset the servos are moving to or holding
+#[derive(Resource, Default)]
+struct ServoPresetMotion(Option<PresetMotion>);
+
+struct PresetMotion {
+    name: String,
+    targets: HashMap<GenericMotorId, f32>,
+    rate: Option<f32>,
+    arrived: bool,
+}
+
 fn create_servos(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotConfig>) {
     let servos = &config.servo_config.servos;
 
@@ -42,6 +60,28 @@ fn create_servos(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
                 .collect(),
         },
         MotorTargets::default(),
+        ServoPresets(
+            config
+                .servo_config
+                .presets
+                .iter()
+                .map(|preset| ServoPreset {
+                    name: preset.name.clone(),
+                    positions: preset
+                        .positions
+                        .iter()
+                        .filter_map(|(servo, &position)| {
+                            let Some(definition) = servos.get(servo) else {
+                                warn!("Servo preset {} uses unknown servo {servo}", preset.name);
+                                return None;
+                            };
+
+                            Some((definition.channel.into(), position.clamp(-1.0, 1.0)))
+                        })
+                        .collect(),
+                })
+                .collect(),
+        ),
     ));
 
     for (
@@ -70,11 +110,19 @@ fn create_servos(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
             default_signal_range
         };
         let mode = control_mode.unwrap_or_default();
+        let identity = ActuatorIdentity::new(
+            name.clone(),
+            ActuatorKind::Servo,
+            channel.into(),
+            signal_type,
+            &signal_range,
+        );
 
         let mut entity = cmds.spawn((
             MotorBundle {
                 actuator: ActuatorBundle {
                     name: Name::new(name.clone()),
+                    identity,
                     channel: channel.into(),
                     signal: MotorSignal::Percent(0.0),
                     robot: RobotId(robot.net_id),
@@ -99,15 +147,51 @@ fn create_servos(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
     }
 }
 
+fn start_servo_presets(
+    mut cmds: Commands,
+    robot: Query<(Entity, &ServoPresets), With<LocalRobotMarker>>,
+    mut events: EventReader<GoToServoPreset>,
+    mut motion: ResMut<ServoPresetMotion>,
+) {
+    let Ok((robot, presets)) = robot.get_single() else {
+        events.clear();
+        return;
+    };
+
+    for GoToServoPreset { preset, rate } in events.read() {
+        let Some(definition) = presets.0.iter().find(|it| it.name == *preset) else {
+            warn!("No servo preset named {preset}");
+            continue;
+        };
+
+        info!("Going to servo preset {preset}");
+        motion.0 = Some(PresetMotion {
+            name: preset.clone(),
+            targets: definition
+                .positions
+                .iter()
+                .map(|(&id, &it)| (id, it))
+                .collect(),
+            rate: rate.map(f32::abs),
+            arrived: false,
+        });
+        cmds.entity(robot).insert(ActiveServoPreset {
+            name: preset.clone(),
+            arrived: false,
+        });
+    }
+}
+
 fn handle_servo_input(
     mut cmds: Commands,
 
     robot: Query<
-        (Entity, &NetId, &MotorTargets),
+        (Entity, &MotorTargets),
         // FIXME: Should this really be `Without<DisableMovementApi>`
         (With<LocalRobotMarker>, Without<DisableMovementApi>),
     >,
-    servo_inputs: Query<(&RobotId, &MotorContribution)>,
+    inputs: Res<MixedMotorInputs>,
+    mut motion: ResMut<ServoPresetMotion>,
     // TODO
     servos: Query<(
         Entity,
@@ -125,22 +209,10 @@ fn handle_servo_input(
 
     time: Res<Time<Real>>,
 ) {
-    let Ok((robot, &net_id, last_positions)) = robot.get_single() else {
+    let Ok((robot, last_positions)) = robot.get_single() else {
         return;
     };
 
-    let mut all_inputs = HashMap::<_, f32>::default();
-
-    for (&RobotId(robot_net_id), servo_contribution) in &servo_inputs {
-        if robot_net_id != net_id {
-            continue;
-        }
-
-        for (motor, input) in &servo_contribution.0 {
-            *all_inputs.entry(motor).or_default() += *input;
-        }
-    }
-
     let servos_by_id = servos
         .iter()
         .map(|it| (*it.6, it))
@@ -161,7 +233,7 @@ fn handle_servo_input(
         should_reset.insert(event.0);
     }
 
-    new_positions.extend(all_inputs.into_iter().flat_map(|(id, input)| {
+    new_positions.extend(inputs.0.iter().flat_map(|(id, &input)| {
         // This is terrifying
         let (_, _, _, _, _, mode, _, _) = servos_by_id.get(id)?;
 
@@ -182,6 +254,44 @@ fn handle_servo_input(
         }
     }));
 
+    if let Some(preset) = &mut motion.0 {
+        // Moving any of the preset's servos by hand takes them back from it
+        let overridden = full_reset
+            || preset.targets.keys().any(|id| {
+                should_reset.contains(id) || inputs.0.get(id).is_some_and(|&it| it != 0.0)
+            });
+
+        if overridden {
+            info!("Servo preset {} was overridden", preset.name);
+            motion.0 = None;
+            cmds.entity(robot).remove::<ActiveServoPreset>();
+        } else {
+            let mut arrived = true;
+
+            for (&id, &target) in &preset.targets {
+                let last_position = last_positions.0.get(&id).copied().unwrap_or(0.0);
+                let position = match preset.rate {
+                    Some(rate) => {
+                        let step = rate * time.delta_secs();
+                        last_position + (target - last_position).clamp(-step, step)
+                    }
+                    None => target,
+                };
+
+                arrived &= position == target;
+                new_positions.insert(id, position);
+            }
+
+            if arrived != preset.arrived {
+                preset.arrived = arrived;
+                cmds.entity(robot).insert(ActiveServoPreset {
+                    name: preset.name.clone(),
+                    arrived,
+                });
+            }
+        }
+    }
+
     for (id, &position) in &new_positions {
         let Some((servo, _, last_signal, _, slew_rate, ..)) = servos_by_id.get(id) else {
             continue;
@@ -0,0 +1,51 @@
+//! Catches motor signals outside of their actuator's configured range
+//!
+//! The output plugins clamp every signal before it reaches the hardware, so this only exists to
+//! make the clamping visible instead of silently ignoring part of a command.
+
+use ahash::HashSet;
+use bevy::prelude::*;
+use common::components::{MotorRawSignalRange, MotorSignal, RobotId};
+
+use crate::plugins::core::robot::LocalRobot;
+
+pub struct MotorSignalValidationPlugin;
+
+impl Plugin for MotorSignalValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, validate_signals);
+    }
+}
+
+fn validate_signals(
+    // Entities currently out of range, so each excursion is only reported once
+    mut out_of_range: Local<HashSet<Entity>>,
+
+    robot: Res<LocalRobot>,
+    motors: Query<
+        (Entity, &Name, &MotorSignal, &MotorRawSignalRange, &RobotId),
+        Or<(Changed<MotorSignal>, Changed<MotorRawSignalRange>)>,
+    >,
+    mut removed: RemovedComponents<MotorSignal>,
+) {
+    for entity in removed.read() {
+        out_of_range.remove(&entity);
+    }
+
+    for (entity, name, signal, range, &RobotId(robot_id)) in &motors {
+        if robot_id != robot.net_id {
+            continue;
+        }
+
+        if signal.in_range(range) {
+            out_of_range.remove(&entity);
+        } else if out_of_range.insert(entity) {
+            warn!(
+                "{name} was sent {signal:?}, outside of its range {}..{}, clamping to {:?}",
+                range.min,
+                range.max,
+                signal.clamped(range)
+            );
+        }
+    }
+}
@@ -1,13 +1,18 @@
-use std::f32::consts::{PI, TAU};
+use std::{f32::consts::TAU, mem, time::Duration};
 
+use anyhow::{bail, Context};
 use bevy::prelude::*;
 use common::{
+    attitude,
     bundles::MovementContributionBundle,
     components::{
-        Armed, DepthMeasurement, DepthTarget, MovementContribution, Orientation, OrientationTarget,
-        PidConfig, PidController, PidResult, RobotId,
+        ActuationLatency, Armed, DepthMeasurement, DepthTarget, GyroMeasurement, HeadingTarget,
+        LatencyIdentification, MovementContribution, Orientation, OrientationTarget, PidConfig,
+        PidController, PidResult, RobotId,
     },
     ecs_sync::Replicate,
+    error,
+    events::{IdentifyActuationLatency, SetPidConfig},
 };
 use glam::{vec3a, Vec3A};
 use motor_math::glam::MovementGlam;
@@ -18,12 +23,39 @@ use crate::{
     plugins::core::robot::{LocalRobot, LocalRobotMarker},
 };
 
+/// Frequency the identification chirp sweeps from, in hertz
+const CHIRP_START_HZ: f32 = 0.5;
+/// Frequency the identification chirp sweeps to, in hertz
+const CHIRP_END_HZ: f32 = 3.0;
+
+/// Identification samples are resampled to this interval before being correlated, in seconds
+const SAMPLE_INTERVAL: f32 = 0.01;
+/// Longest latency identification searches for
+const MAX_LATENCY: Duration = Duration::from_millis(500);
+/// Normalized correlation below which the response is too weak to trust the measured latency
+const MIN_CORRELATION: f32 = 0.3;
+/// Largest chirp peak accepted from a surface, in newton meters
+const MAX_CHIRP_AMPLITUDE: f32 = 10.0;
+/// Longest chirp accepted from a surface
+const MAX_CHIRP_DURATION: Duration = Duration::from_secs(60);
+
 pub struct StabilizePlugin;
 
 impl Plugin for StabilizePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_stabalize);
-        app.add_systems(Update, stabalize_system);
+        app.add_systems(
+            Update,
+            (
+                (
+                    start_latency_identification.pipe(error::handle_errors),
+                    stabalize_system,
+                    run_latency_identification.pipe(error::handle_errors),
+                )
+                    .chain(),
+                apply_pid_configs.pipe(error::handle_errors),
+            ),
+        );
     }
 }
 
@@ -57,6 +89,15 @@ impl PidAxis {
         }
     }
 
+    /// Degrees per second the robot is turning about this axis, positive when it is reducing a
+    /// positive error
+    fn angular_rate(&self, gyro: &GyroMeasurement, imu_frame: Quat, orientation: Quat) -> f32 {
+        // The gyro reads in the imu's frame
+        let rate = imu_frame * vec3a(gyro.x.0, gyro.y.0, gyro.z.0);
+
+        rate.dot(self.get_unit_local_movement(orientation).torque)
+    }
+
     fn get_unit_global_movement(&self, orientation: Quat) -> MovementGlam {
         match self {
             PidAxis::Depth => MovementGlam {
@@ -79,9 +120,27 @@ impl PidAxis {
     }
 }
 
+/// Robot side state of a running latency identification
+#[derive(Component)]
+struct Identification {
+    amplitude: f32,
+    duration: Duration,
+    elapsed: Duration,
+    samples: Vec<IdentificationSample>,
+}
+
+struct IdentificationSample {
+    /// Seconds since the start of the chirp
+    time: f32,
+    /// Newton meters
+    commanded: f32,
+    /// Degrees per second about the axis
+    rate: f32,
+}
+
 fn setup_stabalize(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotConfig>) {
     for (axis, pid_config) in &config.pid_configs {
-        cmds.spawn((
+        let mut controller = cmds.spawn((
             MovementContributionBundle {
                 name: Name::new(format!("Stabalize {axis:?}")),
                 contribution: MovementContribution(MovementGlam::default()),
@@ -92,6 +151,17 @@ fn setup_stabalize(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<Robot
             PidController::default(),
             Replicate,
         ));
+
+        if let Some(&latency) = config.actuation_latency.get(axis) {
+            match Duration::try_from_secs_f32(latency) {
+                Ok(latency) => {
+                    controller.insert(ActuationLatency(latency));
+                }
+                Err(err) => {
+                    warn!("Ignoring actuation latency of {latency}s for {axis:?}: {err}");
+                }
+            }
+        }
     }
 }
 
@@ -102,29 +172,54 @@ fn stabalize_system(
             &Armed,
             Option<&Orientation>,
             Option<&OrientationTarget>,
+            Option<&HeadingTarget>,
             Option<&DepthMeasurement>,
             Option<&DepthTarget>,
+            Option<&GyroMeasurement>,
         ),
         With<LocalRobotMarker>,
     >,
-    mut conntroller_query: Query<(Entity, &PidConfig, &PidAxis, &mut PidController)>,
+    mut conntroller_query: Query<
+        (
+            Entity,
+            &PidConfig,
+            &PidAxis,
+            &mut PidController,
+            Option<&ActuationLatency>,
+        ),
+        Without<Identification>,
+    >,
+    config: Res<RobotConfig>,
     time: Res<Time<Real>>,
 ) {
-    let (armed, orientation, orientation_target, depth, depth_target) = robot_query.single();
+    let (armed, orientation, orientation_target, heading_target, depth, depth_target, gyro) =
+        robot_query.single();
 
     let mut orientation_error = orientation_target
         .zip(orientation)
         .map(|(orientation_target, orientation)| orientation_target.0 * orientation.0.inverse());
+    // Heading hold only drives yaw, in degrees with the same sign as the yaw component of the
+    // orientation error
+    let mut heading_error = heading_target
+        .filter(|_| orientation_target.is_none())
+        .zip(orientation)
+        .map(|(heading_target, orientation)| {
+            // Headings increase clockwise, the opposite of yaw
+            -attitude::heading_error(orientation.heading().0 .0, heading_target.0 .0)
+        });
     let mut depth_error = depth_target
         .zip(depth)
         .map(|(depth_target, depth)| depth_target.0 - depth.depth);
 
     if *armed != Armed::Armed {
         orientation_error = None;
+        heading_error = None;
         depth_error = None;
     }
 
-    for (entity, config, axis, mut state) in conntroller_query.iter_mut() {
+    let imu_frame = config.imu_frame();
+
+    for (entity, config, axis, mut state, latency) in conntroller_query.iter_mut() {
         let needs_remove = 'pid_result: {
             let Some(orientation) = orientation else {
                 break 'pid_result true;
@@ -135,12 +230,27 @@ fn stabalize_system(
                     depth_error.map(|depth_error| state.update(depth_error.0, config, time.delta()))
                 }
                 PidAxis::Yaw | PidAxis::Pitch | PidAxis::Roll => {
-                    orientation_error.map(|orientation_error| {
-                        let error = instant_twist(
-                            orientation_error,
-                            axis.get_unit_global_movement(orientation.0).torque,
-                        )
-                        .to_degrees();
+                    let error = orientation_error
+                        .map(|orientation_error| {
+                            attitude::twist_angle(
+                                orientation_error,
+                                axis.get_unit_global_movement(orientation.0).torque,
+                            )
+                            .to_degrees()
+                        })
+                        .or(heading_error.filter(|_| *axis == PidAxis::Yaw));
+
+                    error.map(|error| {
+                        // Smith predictor style compensation, acts on where the robot will be
+                        // once the correction takes effect rather than where it is now. This
+                        // also leads the derivative path by the latency
+                        let error = match latency.zip(gyro) {
+                            Some((latency, gyro)) => {
+                                let rate = axis.angular_rate(gyro, imu_frame, orientation.0);
+                                error - rate * latency.0.as_secs_f32()
+                            }
+                            None => error,
+                        };
 
                         state.update(error, config, time.delta())
                     })
@@ -164,26 +274,245 @@ fn stabalize_system(
         }
     }
 }
-fn instant_twist(q: Quat, twist_axis: Vec3A) -> f32 {
-    let rotation_axis = vec3a(q.x, q.y, q.z);
 
-    let sign = rotation_axis.dot(twist_axis).signum();
-    let projected = rotation_axis.project_onto(twist_axis);
-    let twist = Quat::from_xyzw(projected.x, projected.y, projected.z, q.w).normalize() * sign;
+fn start_latency_identification(
+    mut cmds: Commands,
+    mut events: EventReader<IdentifyActuationLatency>,
+    robot: Query<&Armed, With<LocalRobotMarker>>,
+    mut controllers: Query<(Entity, &Name, &PidAxis, &mut PidController)>,
+) -> anyhow::Result<()> {
+    for IdentifyActuationLatency {
+        controller,
+        amplitude,
+        duration,
+    } in events.read()
+    {
+        let Some((entity, _, axis, mut state)) = controllers
+            .iter_mut()
+            .find(|(_, name, _, _)| name.as_str() == controller)
+        else {
+            bail!("No pid controller named {controller}");
+        };
+
+        if *axis == PidAxis::Depth {
+            bail!("Latency identification measures the gyro, {controller} is not rotational");
+        }
+
+        if !matches!(robot.get_single(), Ok(Armed::Armed)) {
+            bail!("Cannot identify the latency of {controller}, the robot is not armed");
+        }
+
+        if !amplitude.is_finite() {
+            bail!("Invalid chirp amplitude for {controller}: {amplitude}");
+        }
+
+        let amplitude = amplitude.abs().min(MAX_CHIRP_AMPLITUDE);
+        let duration = (*duration).min(MAX_CHIRP_DURATION);
+
+        info!("Identifying actuation latency of {controller}");
+
+        state.reset();
+        cmds.entity(entity).remove::<PidResult>().insert((
+            Identification {
+                amplitude,
+                duration,
+                elapsed: Duration::ZERO,
+                samples: Vec::new(),
+            },
+            LatencyIdentification {
+                elapsed: Duration::ZERO,
+                duration,
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+/// Sweeps the axis' torque through a chirp and records the gyro's response, the latency is
+/// measured once the chirp finishes
+fn run_latency_identification(
+    mut cmds: Commands,
+    robot: Query<(&Armed, Option<&Orientation>, Option<&GyroMeasurement>), With<LocalRobotMarker>>,
+    mut identifications: Query<(Entity, &Name, &PidAxis, &mut Identification)>,
+    mut config: ResMut<RobotConfig>,
+    time: Res<Time<Real>>,
+) -> anyhow::Result<()> {
+    let Ok((armed, orientation, gyro)) = robot.get_single() else {
+        return Ok(());
+    };
+
+    for (entity, name, axis, mut identification) in &mut identifications {
+        let cancel = if *armed != Armed::Armed {
+            Some("the robot was disarmed")
+        } else if orientation.is_none() || gyro.is_none() {
+            Some("the imu stopped reporting")
+        } else {
+            None
+        };
+
+        if let Some(reason) = cancel {
+            cmds.entity(entity)
+                .remove::<(Identification, LatencyIdentification, MovementContribution)>();
+            bail!("Latency identification of {name} canceled, {reason}");
+        }
+
+        let (Some(orientation), Some(gyro)) = (orientation, gyro) else {
+            continue;
+        };
+
+        identification.elapsed += time.delta();
+
+        if identification.elapsed < identification.duration {
+            let time = identification.elapsed.as_secs_f32();
+            let duration = identification.duration.as_secs_f32();
+
+            // Linear chirp, the phase is the integral of the swept frequency
+            let phase = TAU
+                * (CHIRP_START_HZ * time
+                    + (CHIRP_END_HZ - CHIRP_START_HZ) * time * time / (2.0 * duration));
+            let commanded = identification.amplitude * phase.sin();
+            let rate = axis.angular_rate(gyro, config.imu_frame(), orientation.0);
+
+            identification.samples.push(IdentificationSample {
+                time,
+                commanded,
+                rate,
+            });
+
+            let movement = axis.get_unit_local_movement(orientation.0) * commanded;
+            cmds.entity(entity).insert((
+                MovementContribution(movement),
+                LatencyIdentification {
+                    elapsed: identification.elapsed,
+                    duration: identification.duration,
+                },
+            ));
+
+            continue;
+        }
+
+        let samples = mem::take(&mut identification.samples);
+        cmds.entity(entity)
+            .remove::<(Identification, LatencyIdentification, MovementContribution)>();
+
+        let (latency, correlation) = estimate_latency(&samples)
+            .with_context(|| format!("Identify actuation latency of {name}"))?;
+
+        cmds.entity(entity).insert(ActuationLatency(latency));
+
+        let mut new_config = config.clone();
+        new_config
+            .actuation_latency
+            .insert(*axis, latency.as_secs_f32());
+        new_config.save()?;
+        *config = new_config;
+
+        info!(
+            "Saved actuation latency for {name}: {}ms, correlation {correlation:.2}",
+            latency.as_millis()
+        );
+    }
 
-    let angle = twist.w.acos() * 2.0;
-    normalize_angle(angle)
+    Ok(())
 }
 
-fn normalize_angle(angle: f32) -> f32 {
-    let wrapped_angle = modf(angle, TAU);
-    if wrapped_angle > PI {
-        wrapped_angle - TAU
-    } else {
-        wrapped_angle
+/// Finds the lag that best correlates the commanded torque with the angular acceleration it
+/// causes. The angular rate trails the torque by an extra quarter period from integrating, so it
+/// is differentiated first to leave only the actuation latency
+fn estimate_latency(samples: &[IdentificationSample]) -> anyhow::Result<(Duration, f32)> {
+    let Some(last) = samples.last() else {
+        bail!("No samples were recorded");
+    };
+
+    let count = (last.time / SAMPLE_INTERVAL) as usize;
+    let max_lag = (MAX_LATENCY.as_secs_f32() / SAMPLE_INTERVAL) as usize;
+    if count < max_lag * 4 {
+        bail!("Not enough samples, the chirp was too short");
+    }
+
+    // Frames aren't evenly spaced, linearly interpolate onto a fixed interval
+    let resample = |value: fn(&IdentificationSample) -> f32| -> Vec<f32> {
+        let mut idx = 0;
+
+        (0..count)
+            .map(|it| {
+                let time = it as f32 * SAMPLE_INTERVAL;
+                while idx + 1 < samples.len() && samples[idx + 1].time <= time {
+                    idx += 1;
+                }
+
+                let a = &samples[idx];
+                let b = samples.get(idx + 1).unwrap_or(a);
+                if b.time > a.time {
+                    let t = ((time - a.time) / (b.time - a.time)).clamp(0.0, 1.0);
+                    value(a) + (value(b) - value(a)) * t
+                } else {
+                    value(a)
+                }
+            })
+            .collect()
+    };
+
+    let commanded = resample(|it| it.commanded);
+    let acceleration: Vec<f32> = resample(|it| it.rate)
+        .windows(2)
+        .map(|it| (it[1] - it[0]) / SAMPLE_INTERVAL)
+        .collect();
+
+    let (lag, correlation) = (0..=max_lag)
+        .map(|lag| {
+            let commanded = &commanded[..acceleration.len() - lag];
+            let acceleration = &acceleration[lag..];
+
+            let dot: f32 = commanded.iter().zip(acceleration).map(|(a, b)| a * b).sum();
+            let energy_commanded: f32 = commanded.iter().map(|it| it * it).sum();
+            let energy_acceleration: f32 = acceleration.iter().map(|it| it * it).sum();
+
+            let energy = (energy_commanded * energy_acceleration).sqrt();
+            (lag, if energy > 0.0 { dot / energy } else { 0.0 })
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap_or_default();
+
+    if correlation < MIN_CORRELATION {
+        bail!("Response too weak to measure, correlation {correlation:.2}, try a larger amplitude");
     }
+
+    Ok((
+        Duration::from_secs_f32(lag as f32 * SAMPLE_INTERVAL),
+        correlation,
+    ))
 }
 
-fn modf(a: f32, b: f32) -> f32 {
-    (a % b + b) % b
+/// Applies gains tuned on the surface and saves them to the config
+fn apply_pid_configs(
+    mut cmds: Commands,
+    mut events: EventReader<SetPidConfig>,
+    mut config: ResMut<RobotConfig>,
+    controllers: Query<(Entity, &Name, &PidAxis)>,
+) -> anyhow::Result<()> {
+    for SetPidConfig {
+        controller,
+        config: pid_config,
+    } in events.read()
+    {
+        let Some((entity, _, axis)) = controllers
+            .iter()
+            .find(|(_, name, _)| name.as_str() == controller)
+        else {
+            bail!("No pid controller named {controller}");
+        };
+
+        cmds.entity(entity).insert(pid_config.clone());
+
+        let mut new_config = config.clone();
+        new_config.pid_configs.insert(*axis, pid_config.clone());
+        new_config.save()?;
+        *config = new_config;
+
+        info!("Saved pid config for {controller}: {pid_config:?}");
+    }
+
+    Ok(())
 }
@@ -1,16 +1,21 @@
-use std::time::Duration;
+use std::{mem, time::Duration};
+
+use anyhow::{bail, Context};
 
 use ahash::HashMap;
 use bevy::prelude::*;
 use common::{
     bundles::{ActuatorBundle, RobotThrusterBundle, ThrusterBundle},
     components::{
-        ActualForce, ActualMovement, Armed, CenterOfMass, CurrentDraw, DisableMovementApi,
-        GenericMotorId, JerkLimit, MotorRawSignalRange, MotorSignal, MotorSignalType,
-        MovementAxisMaximums, MovementContribution, MovementCurrentCap, RobotId, TargetForce,
-        TargetMovement, ThrustContribution, ThrusterDefinition, Thrusters,
+        ActualForce, ActualMovement, ActuatorIdentity, ActuatorKind, Armed, CenterOfMass,
+        CurrentDraw, DisableMovementApi, GenericMotorId, Geofence, JerkLimit, MotorRawSignalRange,
+        MotorSignal, MotorSignalType, MovementAxisMaximums, MovementContribution,
+        MovementCurrentCap, RobotId, TargetForce, TargetMovement, ThrustContribution,
+        ThrusterDefinition, ThrusterDiagnostic, ThrusterDiagnostics, Thrusters,
     },
     ecs_sync::{NetId, Replicate},
+    error,
+    events::SetThrusterLayout,
     types::units::{Amperes, Newtons},
 };
 use motor_math::{
@@ -25,10 +30,19 @@ use motor_math::{
 use stable_hashmap::StableHashMap;
 
 use crate::{
-    config::{MotorConfigDefinition, RobotConfig},
-    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+    config::{CustomDefinition, MotorConfigDefinition, RobotConfig},
+    plugins::{
+        actuators::stabilize::PidAxis,
+        core::robot::{LocalRobot, LocalRobotMarker},
+    },
 };
 
+/// How often the thrust allocator's diagnostics are replicated
+const DIAGNOSTICS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// A thruster given less than this fraction of its requested force is saturated
+const SATURATION_RATIO: f32 = 0.98;
+
 pub struct ThrusterPlugin;
 
 impl Plugin for ThrusterPlugin {
@@ -37,17 +51,19 @@ impl Plugin for ThrusterPlugin {
         let motor_data = motor_preformance::read_motor_data_from_path("motor_data.csv")
             .expect("Read motor data");
 
-        // TODO(mid): Update motor config when motor definitions change
         app.add_systems(Startup, (create_motors, setup_motor_math))
             .add_systems(
                 Update,
                 (
+                    apply_thruster_layout.pipe(error::handle_errors),
                     update_axis_maximums,
                     update_center_of_mass,
                     accumulate_movements,
                     accumulate_motor_forces.after(accumulate_movements),
+                    publish_thruster_diagnostics.after(accumulate_motor_forces),
                 ),
             )
+            .init_resource::<PendingDiagnostics>()
             .insert_resource(MotorDataRes(motor_data));
     }
 }
@@ -55,7 +71,21 @@ impl Plugin for ThrusterPlugin {
 #[derive(Resource)]
 pub struct MotorDataRes(pub MotorData);
 
+/// Diagnostics collected since they were last published
+#[derive(Resource, Default)]
+struct PendingDiagnostics(StableHashMap<ErasedMotorId, ThrusterDiagnostic>);
+
 fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotConfig>) {
+    spawn_motors(&mut cmds, &robot, &config, None);
+}
+
+/// `current_cap` keeps the robot's existing cap, which may be derated, instead of the full budget
+fn spawn_motors(
+    cmds: &mut Commands,
+    robot: &LocalRobot,
+    config: &RobotConfig,
+    current_cap: Option<MovementCurrentCap>,
+) {
     let (motors, motor_config) = config.motor_config.flatten(config.center_of_mass);
 
     info!("Generating motor config");
@@ -65,7 +95,8 @@ fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
         movement_actual: ActualMovement(Default::default()),
         thruster_config: Thrusters(motor_config),
         axis_maximums: MovementAxisMaximums(Default::default()),
-        current_cap: MovementCurrentCap(config.motor_amperage_budget.into()),
+        current_cap: current_cap
+            .unwrap_or_else(|| MovementCurrentCap(config.motor_amperage_budget.into())),
         armed: Armed::Disarmed,
         center_of_mass: CenterOfMass(config.center_of_mass),
     });
@@ -73,37 +104,49 @@ fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
     for (motor_id, motor, channel) in motors {
         let name = match config.motor_config {
             MotorConfigDefinition::X3d(_) => {
-                format!(
-                    "{:?} ({motor_id})",
-                    X3dMotorId::try_from(motor_id).expect("Bad motor id for config")
-                )
+                let id = X3dMotorId::try_from(motor_id).expect("Bad motor id for config");
+                format!("{id:?}")
             }
             MotorConfigDefinition::BlueRov(_) => {
-                format!(
-                    "{:?} ({motor_id})",
-                    BlueRovMotorId::try_from(motor_id).expect("Bad motor id for config")
-                )
+                let id = BlueRovMotorId::try_from(motor_id).expect("Bad motor id for config");
+                format!("{id:?}")
             }
             MotorConfigDefinition::Heavy(_) => {
-                format!(
-                    "{:?} ({motor_id})",
-                    HeavyMotorId::try_from(motor_id).expect("Bad motor id for config")
-                )
+                let id = HeavyMotorId::try_from(motor_id).expect("Bad motor id for config");
+                format!("{id:?}")
+            }
+            MotorConfigDefinition::Custom(ref custom) => {
+                // Erased ids follow the order of the names
+                let mut names = custom.motors.keys().collect::<Vec<_>>();
+                names.sort();
+
+                match names.get(motor_id as usize) {
+                    Some(name) => name.to_string(),
+                    None => format!("Motor {motor_id}"),
+                }
             }
-            MotorConfigDefinition::Custom(_) => format!("Motor {motor_id}"),
         };
+        let signal_range = channel.default_signal_range();
+        let identity = ActuatorIdentity::new(
+            name.clone(),
+            ActuatorKind::Thruster,
+            channel.into(),
+            MotorSignalType::Velocity,
+            &signal_range,
+        );
 
         cmds.spawn((
             ThrusterBundle {
                 actuator: ActuatorBundle {
-                    name: Name::new(name),
+                    name: Name::new(format!("{name} ({motor_id})")),
+                    identity,
                     channel: channel.into(),
                     signal: MotorSignal::Percent(0.0),
                     robot: RobotId(robot.net_id),
                     signal_type: MotorSignalType::Velocity,
                     // TODO:  Come up with a better way to do this
                     // FIXME:
-                    signal_range: channel.default_signal_range(),
+                    signal_range,
                 },
                 motor: ThrusterDefinition(motor_id, motor),
                 target_force: TargetForce(0.0f32.into()),
@@ -115,6 +158,45 @@ fn create_motors(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotCo
     }
 }
 
+/// Replaces the thrusters with a layout from the surface and saves it to the config
+fn apply_thruster_layout(
+    mut cmds: Commands,
+    mut events: EventReader<SetThrusterLayout>,
+    mut config: ResMut<RobotConfig>,
+    local_robot: Res<LocalRobot>,
+    robot: Query<(&Armed, Option<&MovementCurrentCap>), With<LocalRobotMarker>>,
+    thrusters: Query<Entity, With<ThrusterDefinition>>,
+) -> anyhow::Result<()> {
+    // Only the latest layout matters
+    let Some(SetThrusterLayout { thrusters: layout }) = events.read().last() else {
+        return Ok(());
+    };
+
+    let robot = robot.get_single().ok();
+    if let Some((Armed::Armed, _)) = robot {
+        bail!("Cannot change the thruster layout while armed");
+    }
+
+    let custom = CustomDefinition::from_layout(layout).context("Invalid thruster layout")?;
+
+    let mut new_config = config.clone();
+    new_config.motor_config = MotorConfigDefinition::Custom(custom);
+    new_config.save().context("Save thruster layout")?;
+    *config = new_config;
+
+    // The cap may be derated for temperature, which is only reapplied when the derate changes
+    let current_cap = robot.and_then(|(_, it)| it.cloned());
+
+    for entity in &thrusters {
+        cmds.entity(entity).despawn();
+    }
+    spawn_motors(&mut cmds, &local_robot, &config, current_cap);
+
+    info!("Applied thruster layout with {} thrusters", layout.len());
+
+    Ok(())
+}
+
 fn setup_motor_math(mut cmds: Commands, config: Res<RobotConfig>, robot: Res<LocalRobot>) {
     if let Some(jerk_limit) = config.jerk_limit {
         cmds.entity(robot.entity).insert(JerkLimit(jerk_limit));
@@ -162,22 +244,25 @@ fn update_center_of_mass(
 fn accumulate_movements(
     mut cmds: Commands,
     robot: Query<
-        (Entity, &NetId, &Thrusters),
+        (Entity, &NetId, &Thrusters, Option<&Geofence>),
         (With<LocalRobotMarker>, Without<DisableMovementApi>),
     >,
-    movements: Query<(&RobotId, &MovementContribution)>,
+    movements: Query<(&RobotId, &MovementContribution, Has<PidAxis>)>,
 
     motor_data: Res<MotorDataRes>,
 ) {
-    let Ok((entity, net_id, Thrusters(thruster_config))) = robot.get_single() else {
+    let Ok((entity, net_id, Thrusters(thruster_config), geofence)) = robot.get_single() else {
         return;
     };
     let mut robot = cmds.entity(entity);
 
+    // Only the robot's own stabilization may move it while it recovers from a geofence breach
+    let enforcing = geofence.is_some_and(Geofence::enforcing);
+
     let mut total_movement = MovementGlam::default();
 
-    for (RobotId(robot_net_id), movement) in &movements {
-        if robot_net_id == net_id {
+    for (RobotId(robot_net_id), movement, stabilize) in &movements {
+        if robot_net_id == net_id && (stabilize || !enforcing) {
             total_movement += movement.0;
         }
     }
@@ -204,14 +289,16 @@ fn accumulate_motor_forces(
             &Thrusters,
             &MovementCurrentCap,
             Option<&JerkLimit>,
+            Option<&Geofence>,
         ),
         (With<LocalRobotMarker>, Without<DisableMovementApi>),
     >,
-    thruster_forces: Query<(&RobotId, &ThrustContribution)>,
+    thruster_forces: Query<(Entity, &RobotId, &ThrustContribution)>,
     thrusters: Query<(Entity, &ThrusterDefinition, &RobotId)>,
 
     time: Res<Time<Real>>,
     motor_data: Res<MotorDataRes>,
+    mut diagnostics: ResMut<PendingDiagnostics>,
 ) {
     let Ok((
         entity,
@@ -219,16 +306,20 @@ fn accumulate_motor_forces(
         Thrusters(thruster_config),
         &MovementCurrentCap(current_cap),
         jerk_limit,
+        geofence,
     )) = robot.get_single()
     else {
         return;
     };
     let mut robot = cmds.entity(entity);
 
+    // Direct thrust from the surface is ignored like pilot movement during a geofence breach
+    let enforcing = geofence.is_some_and(Geofence::enforcing);
+
     let mut all_forces = StableHashMap::default();
 
-    for (&RobotId(robot_net_id), motor_force_contributions) in &thruster_forces {
-        if robot_net_id == net_id {
+    for (contributor, &RobotId(robot_net_id), motor_force_contributions) in &thruster_forces {
+        if robot_net_id == net_id && (contributor == entity || !enforcing) {
             for (motor, force) in &motor_force_contributions.0 {
                 *all_forces.entry(*motor).or_default() += force.0 as motor_math::FloatType;
             }
@@ -265,6 +356,26 @@ fn accumulate_motor_forces(
         0.01,
     );
 
+    for (motor, &requested) in &all_forces {
+        let record = motor_cmds.get(motor);
+        let clamped = record.map(|it| it.force as f32).unwrap_or(0.0);
+        let current = record.map(|it| it.current.abs() as f32).unwrap_or(0.0);
+        let requested = requested as f32;
+
+        let diagnostic = diagnostics.0.entry(*motor).or_default();
+        *diagnostic = ThrusterDiagnostic {
+            requested: Newtons(requested),
+            clamped: Newtons(clamped),
+            current_share: if current_cap.0 > 0.0 {
+                current / current_cap.0
+            } else {
+                0.0
+            },
+            // Sticks until published so a short saturation isn't missed
+            saturated: diagnostic.saturated || clamped.abs() < requested.abs() * SATURATION_RATIO,
+        };
+    }
+
     // Implement slew rate limiting
     let motor_cmds = if let Some(JerkLimit(jerk_limit)) = jerk_limit {
         let slew_motor_cmds = motor_cmds
@@ -347,3 +458,25 @@ fn accumulate_motor_forces(
 
     *last_movement = motor_cmds;
 }
+
+fn publish_thruster_diagnostics(
+    mut cmds: Commands,
+    mut timer: Local<Option<Timer>>,
+    robot: Query<Entity, (With<LocalRobotMarker>, Without<DisableMovementApi>)>,
+    mut diagnostics: ResMut<PendingDiagnostics>,
+    time: Res<Time<Real>>,
+) {
+    let timer = timer.get_or_insert_with(|| Timer::new(DIAGNOSTICS_INTERVAL, TimerMode::Repeating));
+    timer.tick(time.delta());
+
+    if !timer.just_finished() {
+        return;
+    }
+
+    let Ok(robot) = robot.get_single() else {
+        return;
+    };
+
+    cmds.entity(robot)
+        .insert(ThrusterDiagnostics(mem::take(&mut diagnostics.0)));
+}
@@ -1,16 +1,24 @@
 use bevy::{app::PluginGroupBuilder, prelude::PluginGroup};
 
+pub mod logging;
+pub mod power;
 pub mod robot;
 pub mod state;
+pub mod stations;
 pub mod stats;
+pub mod update;
 
 pub struct CorePlugins;
 
 impl PluginGroup for CorePlugins {
     fn build(self) -> PluginGroupBuilder {
         PluginGroupBuilder::start::<Self>()
+            .add(logging::LoggingPlugin)
+            .add(power::PowerPlugin)
             .add(robot::RobotPlugin)
             .add(state::StatePlugin)
+            .add(stations::StationsPlugin)
             .add(stats::StatisticsPlugin)
+            .add(update::UpdatePlugin)
     }
 }
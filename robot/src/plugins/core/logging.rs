@@ -0,0 +1,196 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use bevy::{log::BoxedLayer, prelude::*};
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::{
+    error::Errors,
+    events::{FetchLogs, LogLines},
+};
+use serde::Deserialize;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    fmt::{format::Writer, time::FormatTime},
+    Layer,
+};
+
+use crate::config::{LogRotation, RobotConfig};
+
+const LOG_FILE_PREFIX: &str = "robot";
+const LOG_FILE_SUFFIX: &str = "log";
+const LINES_PER_BATCH: usize = 250;
+
+pub struct LoggingPlugin;
+
+impl Plugin for LoggingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, fetch_logs);
+    }
+}
+
+/// Keeps the background log writer alive, dropping this flushes any buffered lines
+#[derive(Resource)]
+struct LogWriterGuard(#[allow(dead_code)] WorkerGuard);
+
+/// Passed to `LogPlugin::custom_layer`, adds a json layer writing to the rotating log files
+/// described by the `logging` section of the robot config
+pub fn log_layer(app: &mut App) -> Option<BoxedLayer> {
+    let config = app.world().get_resource::<RobotConfig>()?.logging.clone();
+
+    let rotation = match config.rotation {
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(config.max_files)
+        .build(&config.directory);
+
+    let appender = match appender {
+        Ok(appender) => appender,
+        Err(err) => {
+            // The tracing subscriber isnt installed yet
+            eprintln!(
+                "Could not open log directory {:?}: {err:?}",
+                config.directory
+            );
+            return None;
+        }
+    };
+
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    app.insert_resource(LogWriterGuard(guard));
+
+    Some(
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_timer(UnixMillis)
+            .with_ansi(false)
+            .with_writer(writer)
+            .boxed(),
+    )
+}
+
+/// Timestamps log lines with milliseconds since the unix epoch so they are trivial to filter
+struct UnixMillis;
+
+impl FormatTime for UnixMillis {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "{}", unix_millis(SystemTime::now()))
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|it| it.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn fetch_logs(
+    mut events: EventReader<FetchLogs>,
+    config: Res<RobotConfig>,
+    runtime: ResMut<TokioTasksRuntime>,
+    errors: Res<Errors>,
+) {
+    for &FetchLogs { since_ms } in events.read() {
+        info!("Fetching logs since {since_ms}");
+
+        let directory = PathBuf::from(&config.logging.directory);
+        let errors = errors.0.clone();
+
+        runtime.spawn_background_task(async move |mut ctx| {
+            let res =
+                tokio::task::spawn_blocking(move || read_logs_since(&directory, since_ms)).await;
+
+            let lines = match res.context("Join log reader") {
+                Ok(Ok(lines)) => lines,
+                Ok(Err(err)) | Err(err) => {
+                    let _ = errors.send(err);
+                    Vec::new()
+                }
+            };
+
+            ctx.run_on_main_thread(move |ctx| {
+                let mut batches = lines.chunks(LINES_PER_BATCH).peekable();
+
+                if batches.peek().is_none() {
+                    ctx.world.send_event(LogLines {
+                        lines: Vec::new(),
+                        last: true,
+                    });
+                }
+
+                while let Some(batch) = batches.next() {
+                    ctx.world.send_event(LogLines {
+                        lines: batch.to_vec(),
+                        last: batches.peek().is_none(),
+                    });
+                }
+            })
+            .await;
+        });
+    }
+}
+
+#[derive(Deserialize)]
+struct LogTimestamp {
+    timestamp: String,
+}
+
+fn read_logs_since(directory: &Path, since_ms: u64) -> anyhow::Result<Vec<String>> {
+    let mut files = fs::read_dir(directory)
+        .context("Read log directory")?
+        .filter_map(|it| it.ok())
+        .map(|it| it.path())
+        .filter(|it| {
+            it.file_name()
+                .and_then(|it| it.to_str())
+                .is_some_and(|it| it.starts_with(LOG_FILE_PREFIX))
+        })
+        .collect::<Vec<_>>();
+
+    // Rotated files are suffixed with their creation date so this is chronological
+    files.sort();
+
+    let mut lines = Vec::new();
+
+    for path in files {
+        let modified = fs::metadata(&path)
+            .and_then(|it| it.modified())
+            .map(unix_millis)
+            .unwrap_or(u64::MAX);
+
+        if modified < since_ms {
+            continue;
+        }
+
+        let file = File::open(&path).with_context(|| format!("Open log file {path:?}"))?;
+
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("Read log file {path:?}"))?;
+
+            let timestamp = serde_json::from_str::<LogTimestamp>(&line)
+                .ok()
+                .and_then(|it| it.timestamp.parse::<u64>().ok());
+
+            if timestamp.is_some_and(|it| it >= since_ms) {
+                lines.push(line);
+            }
+        }
+    }
+
+    Ok(lines)
+}
@@ -0,0 +1,107 @@
+//! Restarts or powers off the robot on request from the surface
+//!
+//! The robot is disarmed first, then the app exits through `AppExit` like it would on SIGTERM so
+//! the hardware threads stop and the logs are flushed. OS level actions are carried out by
+//! `main` once the app has been torn down, the requested action is passed along as the exit code.
+
+use std::{process::Command, time::Duration};
+
+use anyhow::{bail, Context};
+use bevy::{app::AppExit, prelude::*};
+use common::{
+    components::Armed,
+    events::{PowerAction, PowerCommand},
+};
+
+use super::robot::LocalRobotMarker;
+
+/// Gives the actuators time to settle at neutral after disarming
+const DISARM_DELAY: Duration = Duration::from_millis(500);
+
+const REBOOT_EXIT_CODE: u8 = 64;
+const SHUTDOWN_EXIT_CODE: u8 = 65;
+
+pub struct PowerPlugin;
+
+impl Plugin for PowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (power_commands, act).chain());
+    }
+}
+
+#[derive(Resource)]
+struct PendingPowerAction {
+    action: PowerAction,
+    timer: Timer,
+}
+
+fn power_commands(
+    mut cmds: Commands,
+    mut events: EventReader<PowerCommand>,
+    robot: Query<(Entity, &Armed), With<LocalRobotMarker>>,
+    pending: Option<Res<PendingPowerAction>>,
+) {
+    let Some(&PowerCommand(action)) = events.read().last() else {
+        return;
+    };
+
+    if pending.is_some() {
+        warn!("Ignoring {}, already powering down", action.name());
+        return;
+    }
+
+    warn!("{} requested", action.name());
+
+    if let Ok((entity, Armed::Armed)) = robot.get_single() {
+        info!("Disarming before {}", action.name());
+        cmds.entity(entity).insert(Armed::Disarmed);
+    }
+
+    cmds.insert_resource(PendingPowerAction {
+        action,
+        timer: Timer::new(DISARM_DELAY, TimerMode::Once),
+    });
+}
+
+fn act(
+    pending: Option<ResMut<PendingPowerAction>>,
+    time: Res<Time>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+
+    if !pending.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    info!("Exiting for {}", pending.action.name());
+
+    exit.send(match pending.action {
+        PowerAction::RestartProcess => AppExit::Success,
+        PowerAction::RebootOs => AppExit::from_code(REBOOT_EXIT_CODE),
+        PowerAction::Shutdown => AppExit::from_code(SHUTDOWN_EXIT_CODE),
+    });
+}
+
+/// Carries out the OS level action requested before the app exited, if any
+pub fn perform_exit_action(exit: &AppExit) -> anyhow::Result<()> {
+    let command = match exit {
+        AppExit::Error(code) if code.get() == REBOOT_EXIT_CODE => "reboot",
+        AppExit::Error(code) if code.get() == SHUTDOWN_EXIT_CODE => "poweroff",
+        _ => return Ok(()),
+    };
+
+    info!("Running systemctl {command}");
+
+    let status = Command::new("systemctl")
+        .arg(command)
+        .status()
+        .with_context(|| format!("Run systemctl {command}"))?;
+    if !status.success() {
+        bail!("systemctl {command} exited with {status}");
+    }
+
+    Ok(())
+}
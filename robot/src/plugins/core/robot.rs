@@ -1,8 +1,10 @@
 use bevy::prelude::*;
 use common::{
     bundles::RobotCoreBundle,
-    components::{Robot, RobotId, Singleton},
-    ecs_sync::{NetId, Replicate},
+    components::{Parameters, Robot, RobotId, Singleton},
+    ecs_sync::{NetId, NetIdAllocator, Replicate},
+    params::Params,
+    sync::{LocalPeerKind, PeerKind},
     InstanceName,
 };
 
@@ -21,12 +23,17 @@ pub struct LocalRobot {
 
 impl Plugin for RobotPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, setup_robot);
+        app.insert_resource(LocalPeerKind(PeerKind::Robot))
+            .add_systems(PreStartup, setup_robot)
+            .add_systems(
+                Update,
+                publish_parameters.run_if(resource_exists_and_changed::<Params>),
+            );
     }
 }
 
-fn setup_robot(mut cmds: Commands, name: Res<InstanceName>) {
-    let net_id = NetId::random();
+fn setup_robot(mut cmds: Commands, name: Res<InstanceName>, mut ids: ResMut<NetIdAllocator>) {
+    let net_id = ids.allocate();
 
     let robot = cmds
         .spawn((
@@ -49,3 +56,9 @@ fn setup_robot(mut cmds: Commands, name: Res<InstanceName>) {
         net_id,
     })
 }
+
+/// Lets the surface build a tuning panel for the robot's parameters
+fn publish_parameters(mut cmds: Commands, robot: Res<LocalRobot>, params: Res<Params>) {
+    cmds.entity(robot.entity)
+        .insert(Parameters(params.entries().to_vec()));
+}
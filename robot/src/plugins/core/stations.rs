@@ -0,0 +1,248 @@
+use ahash::HashSet;
+use bevy::prelude::*;
+use common::{
+    components::{
+        Armed, BehaviorStatus, BehaviorTree, BottomLock, Capabilities, DepthTarget, Geofence,
+        HeadingTarget, MotorContribution, MotorMixing, MovementContribution, OrientationTarget,
+        PositionEstimate, RobotId, Singleton, Station, Stations, Surface, SurfaceRole,
+        ThrustContribution,
+    },
+    ecs_sync::{NetId, NetTypeId, Permitted, ReplicationPermissions},
+    events::{FetchLogs, GoToServoPreset, ResyncCameras, SetCapabilities},
+    sync::{Peer, PeerKind},
+};
+use networking::Token;
+
+use super::robot::LocalRobotMarker;
+
+/// Tracks the connected surfaces, grants the first one to ask the pilot role and restricts what
+/// the others can replicate to the robot
+pub struct StationsPlugin;
+
+impl Plugin for StationsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, restrict_peers).add_systems(
+            Update,
+            (
+                track_stations,
+                set_capabilities,
+                apply_permissions.run_if(peers_changed),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn track_stations(
+    mut cmds: Commands,
+    robot: Query<(Entity, Option<&Stations>), With<LocalRobotMarker>>,
+    peers: Query<(&NetId, Option<&Name>, &PeerKind), With<Peer>>,
+) {
+    let Ok((entity, stations)) = robot.get_single() else {
+        return;
+    };
+    let last = stations.map(|it| it.0.as_slice()).unwrap_or_default();
+
+    // The role comes from the peer's handshake, not from anything it replicates
+    let surfaces = peers
+        .iter()
+        .filter_map(|(net_id, name, kind)| match kind {
+            PeerKind::Surface(role) => Some((net_id, name, *role)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Kept in the order they connected
+    let mut updated = last
+        .iter()
+        .filter(|station| {
+            surfaces
+                .iter()
+                .any(|(net_id, ..)| *net_id == station.surface)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for (&net_id, name, requested) in surfaces {
+        if updated.iter().any(|it| it.surface == net_id) {
+            continue;
+        }
+
+        let name = name.map(|it| it.as_str()).unwrap_or("Unknown").to_owned();
+
+        let pilot_taken = updated.iter().any(|it| it.role == SurfaceRole::Pilot);
+        let role = if requested == SurfaceRole::Pilot && pilot_taken {
+            warn!("{name} asked to pilot but a pilot is already connected, joining as a co-pilot");
+            SurfaceRole::CoPilot
+        } else {
+            requested
+        };
+
+        let capabilities = match role {
+            SurfaceRole::Pilot => Capabilities::ALL,
+            SurfaceRole::CoPilot => Capabilities::NONE,
+        };
+
+        info!("{name} connected as {}", role.name());
+        updated.push(Station {
+            surface: net_id,
+            name,
+            role,
+            capabilities,
+        });
+    }
+
+    if updated != last {
+        cmds.entity(entity).insert(Stations(updated));
+    }
+}
+
+fn set_capabilities(
+    mut robot: Query<&mut Stations, With<LocalRobotMarker>>,
+    mut events: EventReader<SetCapabilities>,
+) {
+    let Ok(mut stations) = robot.get_single_mut() else {
+        return;
+    };
+
+    for event in events.read() {
+        let station = stations.0.iter_mut().find(|it| it.surface == event.surface);
+
+        match station {
+            Some(station) if station.role == SurfaceRole::CoPilot => {
+                info!("{} can now control {:?}", station.name, event.capabilities);
+                station.capabilities = event.capabilities;
+            }
+            Some(station) => {
+                warn!("Cannot restrict the pilot station {}", station.name);
+            }
+            None => {
+                warn!("Got capabilities for an unknown station");
+            }
+        }
+    }
+}
+
+/// No peer may replicate these, the robot owns them
+fn restrict_peers(mut permissions: ResMut<ReplicationPermissions>) {
+    permissions.set_denied(
+        [Stations::type_path(), Geofence::type_path()]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    );
+
+    // Until the robot knows what a new peer is, it gets no more than a co-pilot without
+    // capabilities, this applies from the first change it sends. The rest is held so a pilot
+    // doesn't lose what it sent before its station was set up
+    permissions.set_default(Permitted::Only(allowed(Capabilities::NONE)));
+    permissions.set_hold_default(true);
+}
+
+/// What a co-pilot with `capabilities` may replicate to the robot
+fn allowed(capabilities: Capabilities) -> HashSet<NetTypeId> {
+    // Enough to identify itself, drive its own entities and fetch logs
+    let mut allowed: HashSet<NetTypeId> = [
+        Singleton::type_path(),
+        Surface::type_path(),
+        SurfaceRole::type_path(),
+        Name::type_path(),
+        RobotId::type_path(),
+        FetchLogs::type_path(),
+        ResyncCameras::type_path(),
+    ]
+    .into_iter()
+    .map(Into::into)
+    .collect();
+
+    if capabilities.arming {
+        allowed.insert(Armed::type_path().into());
+    }
+    if capabilities.movement {
+        allowed.extend(
+            [
+                MovementContribution::type_path(),
+                ThrustContribution::type_path(),
+                MotorContribution::type_path(),
+                MotorMixing::type_path(),
+                GoToServoPreset::type_path(),
+                DepthTarget::type_path(),
+                OrientationTarget::type_path(),
+                HeadingTarget::type_path(),
+                BottomLock::type_path(),
+            ]
+            .into_iter()
+            .map(Into::into),
+        );
+    }
+
+    allowed
+}
+
+/// What the waterlinked bridge may replicate to the robot, enough to drive it autonomously
+fn bridge_allowed() -> HashSet<NetTypeId> {
+    let mut allowed = allowed(Capabilities::NONE);
+    allowed.extend(
+        [
+            PositionEstimate::type_path(),
+            MovementContribution::type_path(),
+            DepthTarget::type_path(),
+            OrientationTarget::type_path(),
+            BehaviorTree::type_path(),
+            BehaviorStatus::type_path(),
+        ]
+        .into_iter()
+        .map(Into::into),
+    );
+
+    allowed
+}
+
+fn peers_changed(
+    peers: Query<(), Or<(Added<Peer>, Changed<PeerKind>)>>,
+    stations: Query<(), (With<LocalRobotMarker>, Changed<Stations>)>,
+) -> bool {
+    !peers.is_empty() || !stations.is_empty()
+}
+
+fn apply_permissions(
+    mut assigned: Local<HashSet<Token>>,
+    mut permissions: ResMut<ReplicationPermissions>,
+
+    robot: Query<&Stations, With<LocalRobotMarker>>,
+    peers: Query<(&Peer, Option<&NetId>, &PeerKind)>,
+) {
+    let stations = robot.get_single().ok();
+    let mut current = HashSet::default();
+
+    for (peer, net_id, kind) in &peers {
+        let station = stations
+            .zip(net_id)
+            .and_then(|(stations, net_id)| stations.get(*net_id));
+
+        let permitted = match (kind, station) {
+            // Only the pilot reconfigures, updates or powers off the robot, overrides its limits
+            // or hands out control
+            (PeerKind::Surface(_), Some(station)) if station.role == SurfaceRole::Pilot => {
+                Permitted::All
+            }
+            (PeerKind::Surface(_), Some(station)) => Permitted::Only(allowed(station.capabilities)),
+            // Keeps the default until its station is set up, what it sends until then is held
+            (PeerKind::Surface(_), None) => continue,
+            (PeerKind::Bridge, _) => Permitted::Only(bridge_allowed()),
+            // Peers that didn't say what they are get the default, what was held for them is
+            // dropped
+            (PeerKind::Robot | PeerKind::Unknown, _) => {
+                Permitted::Only(allowed(Capabilities::NONE))
+            }
+        };
+
+        permissions.set(peer.token, permitted);
+        current.insert(peer.token);
+    }
+
+    for &token in assigned.difference(&current) {
+        permissions.remove(token);
+    }
+    *assigned = current;
+}
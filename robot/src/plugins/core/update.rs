@@ -0,0 +1,500 @@
+//! Installs robot binaries pushed from the surface
+//!
+//! Chunks are written to `<binary>.staged` and hashed as they arrive. Once the checksum matches,
+//! the staged binary is asked for its version, the running binary is copied to
+//! `<binary>.previous` and the staged one is renamed over it. The robot then exits through
+//! `AppExit` like it would on SIGTERM and systemd starts the new binary.
+//!
+//! A `<binary>.pending` marker counts the starts of the new binary until it has stayed up for the
+//! configured self test duration. A binary that keeps failing before then is replaced by the
+//! previous one by `check_pending_update`, which runs before anything else in `main`.
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use bevy::{app::AppExit, prelude::*};
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::{
+    components::{Armed, Surface},
+    error::{self, Errors},
+    events::{BeginUpdate, CancelUpdate, UpdateChunk, UpdateState, UpdateStatus},
+    git::GitMetadata,
+    update::{self, Hasher},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::RobotConfig;
+
+use super::robot::LocalRobotMarker;
+
+/// Starts a new binary gets to pass its self test before the previous binary is restored
+const MAX_STARTS: u32 = 2;
+/// Gives the final status a chance to reach the surface before exiting
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+pub struct UpdatePlugin;
+
+impl Plugin for UpdatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_updates.pipe(error::handle_errors))
+            .add_systems(
+                Update,
+                (
+                    begin_update.pipe(error::handle_errors),
+                    receive_chunks.pipe(error::handle_errors),
+                    cancel_update,
+                    self_test.pipe(error::handle_errors),
+                    report_status,
+                    restart,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UpdatePaths {
+    binary: PathBuf,
+    staged: PathBuf,
+    previous: PathBuf,
+    pending: PathBuf,
+    rolled_back: PathBuf,
+}
+
+impl UpdatePaths {
+    fn new() -> anyhow::Result<Self> {
+        let binary = std::env::current_exe().context("Locate robot binary")?;
+
+        Ok(Self {
+            staged: binary.with_extension("staged"),
+            previous: binary.with_extension("previous"),
+            pending: binary.with_extension("pending"),
+            rolled_back: binary.with_extension("rolled-back"),
+            binary,
+        })
+    }
+}
+
+/// Written next to the binary while a new binary has not passed its self test
+#[derive(Serialize, Deserialize, Debug)]
+struct PendingUpdate {
+    version: String,
+    starts: u32,
+}
+
+/// Counts a start of a freshly installed binary and restores the previous binary once it has
+/// failed too many times. Returns true after restoring, the process should exit so systemd
+/// starts the previous binary
+pub fn check_pending_update() -> anyhow::Result<bool> {
+    let paths = UpdatePaths::new()?;
+
+    let Ok(pending) = fs::read_to_string(&paths.pending) else {
+        return Ok(false);
+    };
+    let mut pending: PendingUpdate = toml::from_str(&pending).context("Parse pending update")?;
+    pending.starts += 1;
+
+    if pending.starts > MAX_STARTS {
+        error!(
+            "{} failed its self test {MAX_STARTS} times, restoring the previous binary",
+            pending.version
+        );
+
+        fs::rename(&paths.previous, &paths.binary).context("Restore previous binary")?;
+        fs::write(&paths.rolled_back, &pending.version).context("Record rollback")?;
+        fs::remove_file(&paths.pending).context("Remove pending update")?;
+
+        return Ok(true);
+    }
+
+    info!(
+        "Starting {} ({}/{MAX_STARTS}), self test pending",
+        pending.version, pending.starts
+    );
+    let pending = toml::to_string(&pending).context("Serialize pending update")?;
+    fs::write(&paths.pending, pending).context("Write pending update")?;
+
+    Ok(false)
+}
+
+#[derive(Resource)]
+struct Updater {
+    paths: UpdatePaths,
+    /// Version of the running binary while it is being self tested
+    self_testing: Option<String>,
+    /// Sent to surfaces as they connect
+    report: Option<UpdateStatus>,
+}
+
+/// The binary being received
+#[derive(Resource)]
+struct Transfer {
+    file: File,
+    hasher: Hasher,
+    received: u64,
+    size: u64,
+    checksum: String,
+    force: bool,
+}
+
+#[derive(Resource)]
+struct Restart(Timer);
+
+fn status(state: UpdateState, message: String) -> UpdateStatus {
+    UpdateStatus {
+        state,
+        received: 0,
+        total: 0,
+        message,
+    }
+}
+
+fn setup_updates(mut cmds: Commands) -> anyhow::Result<()> {
+    let paths = UpdatePaths::new()?;
+    let mut report = None;
+
+    let self_testing = fs::read_to_string(&paths.pending)
+        .ok()
+        .and_then(|it| toml::from_str::<PendingUpdate>(&it).ok())
+        .map(|it| it.version);
+
+    if let Some(ref version) = self_testing {
+        report = Some(status(
+            UpdateState::SelfTest,
+            format!("Running {version}, self test pending"),
+        ));
+    }
+
+    if let Ok(version) = fs::read_to_string(&paths.rolled_back) {
+        warn!("Running the previous binary, {version} failed its self test");
+        report = Some(status(
+            UpdateState::RolledBack,
+            format!("{version} failed its self test, the previous binary was restored"),
+        ));
+
+        fs::remove_file(&paths.rolled_back).context("Remove rollback record")?;
+    }
+
+    // Left behind by an interrupted transfer
+    let _ = fs::remove_file(&paths.staged);
+
+    cmds.insert_resource(Updater {
+        paths,
+        self_testing,
+        report,
+    });
+
+    Ok(())
+}
+
+fn begin_update(
+    mut cmds: Commands,
+    mut events: EventReader<BeginUpdate>,
+    updater: Res<Updater>,
+    restart: Option<Res<Restart>>,
+    config: Res<RobotConfig>,
+    robot: Query<&Armed, With<LocalRobotMarker>>,
+    mut status_events: EventWriter<UpdateStatus>,
+) -> anyhow::Result<()> {
+    let Some(begin) = events.read().last() else {
+        return Ok(());
+    };
+
+    let res: anyhow::Result<Transfer> = try {
+        if let Ok(Armed::Armed) = robot.get_single() {
+            Err(anyhow!("Cannot update while armed"))?;
+        }
+        if restart.is_some() {
+            Err(anyhow!("Already restarting into a new binary"))?;
+        }
+        if let Some(ref version) = updater.self_testing {
+            Err(anyhow!("{version} has not passed its self test yet"))?;
+        }
+
+        let file = File::create(&updater.paths.staged).context("Create staged binary")?;
+
+        Transfer {
+            file,
+            hasher: update::hasher(config.update.key.as_deref()),
+            received: 0,
+            size: begin.size,
+            checksum: begin.checksum.clone(),
+            force: begin.force,
+        }
+    };
+
+    match res {
+        Ok(transfer) => {
+            info!("Receiving a {} byte update", begin.size);
+
+            status_events.send(UpdateStatus {
+                state: UpdateState::Receiving,
+                received: 0,
+                total: begin.size,
+                message: "Receiving binary".to_owned(),
+            });
+            cmds.insert_resource(transfer);
+
+            Ok(())
+        }
+        Err(err) => {
+            status_events.send(status(UpdateState::Failed, format!("{err:#}")));
+            cmds.remove_resource::<Transfer>();
+
+            Err(err.context("Begin update"))
+        }
+    }
+}
+
+fn receive_chunks(
+    mut cmds: Commands,
+    mut events: EventReader<UpdateChunk>,
+    transfer: Option<ResMut<Transfer>>,
+    updater: Res<Updater>,
+    robot: Query<&Armed, With<LocalRobotMarker>>,
+    runtime: ResMut<TokioTasksRuntime>,
+    errors: Res<Errors>,
+    mut status_events: EventWriter<UpdateStatus>,
+) -> anyhow::Result<()> {
+    let Some(mut transfer) = transfer else {
+        events.clear();
+        return Ok(());
+    };
+
+    let mut received_any = false;
+    let res: anyhow::Result<()> = try {
+        for UpdateChunk { offset, data } in events.read() {
+            if *offset != transfer.received {
+                Err(anyhow!(
+                    "Expected a chunk at {}, got {offset}",
+                    transfer.received
+                ))?;
+            }
+            if transfer.received + data.len() as u64 > transfer.size {
+                Err(anyhow!("Received more than {} bytes", transfer.size))?;
+            }
+
+            transfer
+                .file
+                .write_all(data)
+                .context("Write staged binary")?;
+            transfer.hasher.update(data);
+            transfer.received += data.len() as u64;
+            received_any = true;
+        }
+
+        if transfer.received == transfer.size {
+            transfer.file.sync_all().context("Sync staged binary")?;
+
+            let checksum = transfer.hasher.finalize().to_hex();
+            if checksum.as_str() != transfer.checksum {
+                Err(anyhow!(
+                    "Checksum mismatch, corrupted or signed with another key"
+                ))?;
+            }
+            if let Ok(Armed::Armed) = robot.get_single() {
+                Err(anyhow!("Armed while receiving the update"))?;
+            }
+        }
+    };
+
+    if let Err(err) = res {
+        cmds.remove_resource::<Transfer>();
+        let _ = fs::remove_file(&updater.paths.staged);
+
+        status_events.send(status(UpdateState::Failed, format!("{err:#}")));
+
+        return Err(err.context("Receive update"));
+    }
+
+    if transfer.received < transfer.size {
+        if received_any {
+            status_events.send(UpdateStatus {
+                state: UpdateState::Receiving,
+                received: transfer.received,
+                total: transfer.size,
+                message: "Receiving binary".to_owned(),
+            });
+        }
+
+        return Ok(());
+    }
+
+    cmds.remove_resource::<Transfer>();
+    status_events.send(UpdateStatus {
+        state: UpdateState::Installing,
+        received: transfer.received,
+        total: transfer.size,
+        message: "Checksum verified, installing".to_owned(),
+    });
+
+    let paths = updater.paths.clone();
+    let force = transfer.force;
+    let errors = errors.0.clone();
+
+    runtime.spawn_background_task(async move |mut ctx| {
+        let res = tokio::task::spawn_blocking(move || {
+            let res = install(&paths, force);
+            if res.is_err() {
+                let _ = fs::remove_file(&paths.staged);
+            }
+
+            res
+        })
+        .await;
+
+        let res = match res.context("Join installer") {
+            Ok(Ok(version)) => Ok(version),
+            Ok(Err(err)) | Err(err) => Err(err),
+        };
+
+        ctx.run_on_main_thread(move |ctx| match res {
+            Ok(version) => {
+                info!("Installed {version}, restarting");
+
+                ctx.world.send_event(status(
+                    UpdateState::Restarting,
+                    format!("Installed {version}, restarting once disarmed"),
+                ));
+                ctx.world
+                    .insert_resource(Restart(Timer::new(RESTART_DELAY, TimerMode::Once)));
+            }
+            Err(err) => {
+                ctx.world
+                    .send_event(status(UpdateState::Failed, format!("{err:#}")));
+                let _ = errors.send(err.context("Install update"));
+            }
+        })
+        .await;
+    });
+
+    Ok(())
+}
+
+/// Swaps the staged binary in, returning its version
+fn install(paths: &UpdatePaths, force: bool) -> anyhow::Result<String> {
+    fs::set_permissions(&paths.staged, fs::Permissions::from_mode(0o755))
+        .context("Mark staged binary executable")?;
+
+    // Also catches binaries built for the wrong target
+    let output = Command::new(&paths.staged)
+        .arg("--version")
+        .output()
+        .context("Run staged binary")?;
+    if !output.status.success() {
+        bail!("Staged binary exited with {}", output.status);
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    let running = GitMetadata::new().map(|it| it.version());
+
+    if !force && !version.ends_with("-dirty") && running.as_ref() == Some(&version) {
+        bail!("The robot already runs {version}");
+    }
+
+    fs::copy(&paths.binary, &paths.previous).context("Back up running binary")?;
+
+    let pending = PendingUpdate {
+        version: version.clone(),
+        starts: 0,
+    };
+    let pending = toml::to_string(&pending).context("Serialize pending update")?;
+    fs::write(&paths.pending, pending).context("Write pending update")?;
+
+    // Atomic since both live in the same directory
+    if let Err(err) = fs::rename(&paths.staged, &paths.binary) {
+        let _ = fs::remove_file(&paths.pending);
+        return Err(err).context("Swap binaries");
+    }
+
+    Ok(version)
+}
+
+fn cancel_update(
+    mut cmds: Commands,
+    mut events: EventReader<CancelUpdate>,
+    transfer: Option<Res<Transfer>>,
+    updater: Res<Updater>,
+    mut status_events: EventWriter<UpdateStatus>,
+) {
+    if events.read().count() == 0 || transfer.is_none() {
+        return;
+    }
+
+    info!("Update cancelled");
+
+    cmds.remove_resource::<Transfer>();
+    let _ = fs::remove_file(&updater.paths.staged);
+
+    status_events.send(status(
+        UpdateState::Cancelled,
+        "Update cancelled".to_owned(),
+    ));
+}
+
+/// Keeps a new binary once it has stayed up for long enough
+fn self_test(
+    mut updater: ResMut<Updater>,
+    config: Res<RobotConfig>,
+    time: Res<Time>,
+    mut status_events: EventWriter<UpdateStatus>,
+) -> anyhow::Result<()> {
+    if updater.self_testing.is_none() || time.elapsed_secs() < config.update.self_test {
+        return Ok(());
+    }
+    let Some(version) = updater.self_testing.take() else {
+        return Ok(());
+    };
+
+    fs::remove_file(&updater.paths.pending).context("Remove pending update")?;
+    info!("{version} passed its self test");
+
+    let report = status(
+        UpdateState::Installed,
+        format!("{version} passed its self test"),
+    );
+    status_events.send(report.clone());
+    updater.report = Some(report);
+
+    Ok(())
+}
+
+fn report_status(
+    updater: Res<Updater>,
+    surfaces: Query<(), Added<Surface>>,
+    mut status_events: EventWriter<UpdateStatus>,
+) {
+    if surfaces.is_empty() {
+        return;
+    }
+
+    if let Some(ref report) = updater.report {
+        status_events.send(report.clone());
+    }
+}
+
+fn restart(
+    restart: Option<ResMut<Restart>>,
+    time: Res<Time>,
+    robot: Query<&Armed, With<LocalRobotMarker>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(mut restart) = restart else {
+        return;
+    };
+
+    restart.0.tick(time.delta());
+
+    // Never restart out from under the pilot
+    if restart.0.finished() && !matches!(robot.get_single(), Ok(Armed::Armed)) {
+        info!("Restarting into the new binary");
+        exit.send(AppExit::Success);
+    }
+}
@@ -1,13 +1,20 @@
 use bevy::{app::PluginGroupBuilder, prelude::PluginGroup};
 
+pub mod alarms;
+pub mod current;
 pub mod hw_stat;
+pub mod tether;
 pub mod voltage;
 
 pub struct MonitorPlugins;
 
 impl PluginGroup for MonitorPlugins {
     fn build(self) -> PluginGroupBuilder {
-        let builder = PluginGroupBuilder::start::<Self>().add(hw_stat::HwStatPlugin);
+        let builder = PluginGroupBuilder::start::<Self>()
+            .add(alarms::AlarmPlugin)
+            .add(current::CurrentMonitorPlugin)
+            .add(hw_stat::HwStatPlugin)
+            .add(tether::TetherPlugin);
 
         #[cfg(rpi)]
         let builder = builder.add(voltage::VoltagePlugin);
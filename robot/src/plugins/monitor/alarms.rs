@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use common::{
+    components::{DepthSensorHealth, Geofence, Leak, MeasuredVoltage, TetherTurns},
+    error::{self, ErrorEvent},
+    events::{Alarm, AlarmKind},
+};
+
+use super::{current::CurrentAnomaly, tether::TetherTwisted, voltage::BrownedOut};
+use crate::plugins::{actuators::hardware::InactivityDisarm, core::robot::LocalRobotMarker};
+
+/// Identical errors reported within this window are only forwarded once
+const REPEAT_SUPPRESSION: Duration = Duration::from_secs(1);
+
+/// Forwards errors and critical conditions to the surface as `Alarm` events
+pub struct AlarmPlugin;
+
+impl Plugin for AlarmPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                leak_alarm,
+                brown_out_alarm,
+                current_anomaly_alarm,
+                tether_twist_alarm,
+                geofence_alarm,
+                depth_sensor_alarm,
+            ),
+        )
+        .add_systems(Last, forward_errors.after(error::error_channel));
+    }
+}
+
+fn forward_errors(
+    mut errors: EventReader<ErrorEvent>,
+    mut alarms: EventWriter<Alarm>,
+    mut last_sent: Local<Option<(String, Instant)>>,
+) {
+    for ErrorEvent(error) in errors.read() {
+        let kind = if error.downcast_ref::<InactivityDisarm>().is_some() {
+            AlarmKind::InactivityDisarm
+        } else {
+            AlarmKind::Error
+        };
+        let message = format!("{error:#}");
+
+        let repeated = last_sent
+            .as_ref()
+            .is_some_and(|(last, at)| *last == message && at.elapsed() < REPEAT_SUPPRESSION);
+        if repeated {
+            continue;
+        }
+
+        *last_sent = Some((message.clone(), Instant::now()));
+        alarms.send(Alarm { kind, message });
+    }
+}
+
+fn leak_alarm(
+    robot: Query<&Leak, (With<LocalRobotMarker>, Changed<Leak>)>,
+    mut alarms: EventWriter<Alarm>,
+) {
+    for leak in &robot {
+        if leak.0 {
+            alarms.send(Alarm {
+                kind: AlarmKind::Leak,
+                message: "Leak detected".to_owned(),
+            });
+        }
+    }
+}
+
+fn brown_out_alarm(
+    robot: Query<&MeasuredVoltage, (With<LocalRobotMarker>, Added<BrownedOut>)>,
+    mut alarms: EventWriter<Alarm>,
+) {
+    for voltage in &robot {
+        alarms.send(Alarm {
+            kind: AlarmKind::LowVoltage,
+            message: format!("Browned out at {}", voltage.0),
+        });
+    }
+}
+
+fn current_anomaly_alarm(
+    robot: Query<&CurrentAnomaly, (With<LocalRobotMarker>, Added<CurrentAnomaly>)>,
+    mut alarms: EventWriter<Alarm>,
+) {
+    for anomaly in &robot {
+        alarms.send(Alarm {
+            kind: AlarmKind::CurrentAnomaly,
+            message: format!("{} of current draw is unaccounted for", anomaly.residual),
+        });
+    }
+}
+
+fn tether_twist_alarm(
+    robot: Query<&TetherTurns, (With<LocalRobotMarker>, Added<TetherTwisted>)>,
+    mut alarms: EventWriter<Alarm>,
+) {
+    for turns in &robot {
+        let direction = if turns.turns > 0.0 {
+            "clockwise"
+        } else {
+            "counter clockwise"
+        };
+
+        alarms.send(Alarm {
+            kind: AlarmKind::TetherTwist,
+            message: format!(
+                "Tether twisted {:.1} turns {direction}, unwind it",
+                turns.turns.abs()
+            ),
+        });
+    }
+}
+
+fn geofence_alarm(
+    mut was_enforcing: Local<bool>,
+    robot: Query<&Geofence, (With<LocalRobotMarker>, Changed<Geofence>)>,
+    mut alarms: EventWriter<Alarm>,
+) {
+    for geofence in &robot {
+        let enforcing = geofence.enforcing();
+
+        if let Some(breach) = geofence.breach.filter(|_| enforcing && !*was_enforcing) {
+            alarms.send(Alarm {
+                kind: AlarmKind::Geofence,
+                message: format!("{} breached, the robot has taken control", breach.name()),
+            });
+        }
+
+        *was_enforcing = enforcing;
+    }
+}
+
+fn depth_sensor_alarm(
+    mut was_stale: Local<bool>,
+    robot: Query<&DepthSensorHealth, (With<LocalRobotMarker>, Changed<DepthSensorHealth>)>,
+    mut alarms: EventWriter<Alarm>,
+) {
+    for health in &robot {
+        if health.stale && !*was_stale {
+            alarms.send(Alarm {
+                kind: AlarmKind::DepthSensor,
+                message: format!(
+                    "{} depth sensor stopped reporting after {} failed reads",
+                    health.sensor, health.failed_reads
+                ),
+            });
+        }
+
+        *was_stale = health.stale;
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use common::{
+    components::{CurrentDraw, RobotId},
+    types::units::Amperes,
+};
+
+use crate::{
+    config::RobotConfig,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
+
+/// Compares the measured current draw to the sum of every actuator's predicted draw, what the
+/// Current Draw Debugger calls unaccounted current. A large residual points at a shorted tether,
+/// a fouled prop or a failing ESC
+pub struct CurrentMonitorPlugin;
+
+impl Plugin for CurrentMonitorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, check_current);
+    }
+}
+
+/// On the robot while the unaccounted current has been over the threshold for long enough
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CurrentAnomaly {
+    /// Measured minus predicted current draw
+    pub residual: Amperes,
+}
+
+fn check_current(
+    mut cmds: Commands,
+    mut exceeded_since: Local<Option<Instant>>,
+
+    config: Res<RobotConfig>,
+    local_robot: Res<LocalRobot>,
+    robot: Query<(Entity, &CurrentDraw, Option<&CurrentAnomaly>), With<LocalRobotMarker>>,
+    actuators: Query<(&RobotId, &CurrentDraw), Without<LocalRobotMarker>>,
+) {
+    let Ok((entity, measured, anomaly)) = robot.get_single() else {
+        return;
+    };
+    let monitor = &config.current_monitor;
+
+    let predicted = actuators
+        .iter()
+        .filter(|(robot_id, _)| robot_id.0 == local_robot.net_id)
+        .map(|(_, current)| current.0)
+        .fold(Amperes::ZERO, |acc, it| acc + it);
+    let residual = measured.0 - predicted - Amperes(monitor.baseline);
+
+    if residual.0.abs() < monitor.threshold {
+        *exceeded_since = None;
+
+        if anomaly.is_some() {
+            info!("Current draw anomaly cleared, {residual} unaccounted");
+            cmds.entity(entity).remove::<CurrentAnomaly>();
+        }
+
+        return;
+    }
+
+    let since = *exceeded_since.get_or_insert_with(Instant::now);
+    if anomaly.is_none() && since.elapsed() >= Duration::from_secs_f32(monitor.duration) {
+        warn!(
+            "Current draw anomaly, measured {} but predicted {predicted}, {residual} unaccounted",
+            measured.0
+        );
+        cmds.entity(entity).insert(CurrentAnomaly { residual });
+    }
+}
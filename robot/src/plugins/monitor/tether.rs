@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use common::{
+    attitude,
+    components::{Heading, TetherTurns},
+    events::{ResetTetherTurns, ResetYaw},
+};
+
+use crate::{config::RobotConfig, plugins::core::robot::LocalRobotMarker};
+
+/// Integrates the robot's heading into the net number of turns it has made, pilots lose track of
+/// this quickly and a twisted tether eventually kinks
+pub struct TetherPlugin;
+
+impl Plugin for TetherPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (track_turns, check_twist).chain());
+    }
+}
+
+/// On the robot while the tether is twisted past the budget
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TetherTwisted;
+
+fn track_turns(
+    mut cmds: Commands,
+    mut last_heading: Local<Option<f32>>,
+    mut turns: Local<f32>,
+
+    config: Res<RobotConfig>,
+    robot: Query<(Entity, Ref<Heading>, Option<&TetherTurns>), With<LocalRobotMarker>>,
+    mut reset_turns: EventReader<ResetTetherTurns>,
+    mut reset_yaw: EventReader<ResetYaw>,
+) {
+    let Ok((entity, heading, last_turns)) = robot.get_single() else {
+        return;
+    };
+
+    if heading.is_changed() {
+        let heading = heading.0 .0;
+
+        if let Some(last_heading) = *last_heading {
+            // Shortest way around, the robot can't turn half a revolution between readings
+            let delta = attitude::heading_error(last_heading, heading);
+            *turns += delta / 360.0;
+        }
+
+        *last_heading = Some(heading);
+    }
+
+    if reset_turns.read().count() > 0 {
+        info!("Resetting tether turns, was at {:.2}", *turns);
+        *turns = 0.0;
+    }
+
+    // Resetting yaw makes the heading jump, that jump isn't a real turn
+    if reset_yaw.read().count() > 0 {
+        *last_heading = None;
+    }
+
+    let tether_turns = TetherTurns {
+        turns: *turns,
+        budget: config.tether.twist_budget,
+    };
+    if last_turns != Some(&tether_turns) {
+        cmds.entity(entity).insert(tether_turns);
+    }
+}
+
+fn check_twist(
+    mut cmds: Commands,
+    robot: Query<
+        (Entity, &TetherTurns, Has<TetherTwisted>),
+        (With<LocalRobotMarker>, Changed<TetherTurns>),
+    >,
+) {
+    for (entity, turns, twisted) in &robot {
+        if turns.over_budget() && !twisted {
+            warn!("Tether twisted {:.1} turns, unwind it", turns.turns);
+            cmds.entity(entity).insert(TetherTwisted);
+        } else if !turns.over_budget() && twisted {
+            info!("Tether twist back within budget");
+            cmds.entity(entity).remove::<TetherTwisted>();
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use bevy::{app::PluginGroupBuilder, prelude::PluginGroup};
 
+pub mod altimeter;
+pub mod battery;
 pub mod cameras;
+pub mod conductivity;
 pub mod depth;
 pub mod leak;
 pub mod orientation;
@@ -18,7 +21,10 @@ impl PluginGroup for SensorPlugins {
             .add(orientation::OrientationPlugin)
             .add(power::PowerPlugin)
             .add(depth::DepthPlugin)
-            .add(leak::LeakPlugin);
+            .add(conductivity::ConductivityPlugin)
+            .add(altimeter::AltimeterPlugin)
+            .add(leak::LeakPlugin)
+            .add(battery::BatteryPlugin);
 
         builder
     }
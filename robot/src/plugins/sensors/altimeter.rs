@@ -0,0 +1,98 @@
+use std::{thread, time::Duration};
+
+use anyhow::Context;
+use bevy::{app::AppExit, prelude::*};
+use common::{
+    components::Altimeter,
+    error::{self, Errors},
+};
+use crossbeam::channel::{self, Receiver, Sender};
+use tracing::{span, Level};
+
+use crate::{config::RobotConfig, peripheral::ping1d::Ping1d, plugins::core::robot::LocalRobot};
+
+/// The sonar pings at roughly this rate on its own
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reads the distance to the bottom from an optional downward echosounder, for bottom lock
+pub struct AltimeterPlugin;
+
+impl Plugin for AltimeterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, start_altimeter_thread.pipe(error::handle_errors));
+        app.add_systems(
+            PreUpdate,
+            read_new_data.run_if(resource_exists::<AltimeterChannels>),
+        );
+        app.add_systems(Last, shutdown.run_if(resource_exists::<AltimeterChannels>));
+    }
+}
+
+#[derive(Resource)]
+struct AltimeterChannels(Receiver<Altimeter>, Sender<()>);
+
+fn start_altimeter_thread(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    errors: Res<Errors>,
+) -> anyhow::Result<()> {
+    let Some(config) = config.altimeter.clone() else {
+        return Ok(());
+    };
+
+    let (tx_data, rx_data) = channel::bounded(5);
+    let (tx_exit, rx_exit) = channel::bounded(1);
+
+    let mut sensor = Ping1d::new(&config.uart, config.baud_rate).context("Altimeter (Ping1d)")?;
+
+    cmds.insert_resource(AltimeterChannels(rx_data, tx_exit));
+
+    let errors = errors.0.clone();
+    thread::Builder::new()
+        .name("Altimeter Thread".to_owned())
+        .spawn(move || {
+            let _span = span!(Level::INFO, "Altimeter thread").entered();
+
+            while rx_exit.try_recv().is_err() {
+                let span = span!(Level::INFO, "Altimeter cycle").entered();
+
+                let rst = sensor.read_distance().context("Read altitude");
+
+                match rst {
+                    Ok((altitude, confidence)) => {
+                        let reading = Altimeter {
+                            altitude,
+                            confidence,
+                        };
+
+                        if tx_data.send(reading).is_err() {
+                            // Peer disconected
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = errors.send(err);
+                    }
+                }
+
+                span.exit();
+
+                thread::sleep(INTERVAL);
+            }
+        })
+        .context("Start thread")?;
+
+    Ok(())
+}
+
+fn read_new_data(mut cmds: Commands, channels: Res<AltimeterChannels>, robot: Res<LocalRobot>) {
+    if let Some(reading) = channels.0.try_iter().last() {
+        cmds.entity(robot.entity).insert(reading);
+    }
+}
+
+fn shutdown(channels: Res<AltimeterChannels>, mut exit: EventReader<AppExit>) {
+    for _event in exit.read() {
+        let _ = channels.1.send(());
+    }
+}
@@ -0,0 +1,190 @@
+use std::{thread, time::Duration};
+
+use anyhow::Context;
+use bevy::{app::AppExit, prelude::*};
+use common::{
+    components::{Armed, BatteryFault, BatteryStatus},
+    error::{self, ErrorEvent, Errors},
+};
+use crossbeam::channel::{self, Receiver, Sender};
+use tracing::{span, Level};
+
+use crate::{
+    config::{BatteryConfig, RobotConfig},
+    peripheral::smart_battery::SmartBattery,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct BatteryPlugin;
+
+impl Plugin for BatteryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, start_battery_thread.pipe(error::handle_errors));
+        app.add_systems(
+            PreUpdate,
+            read_new_data.run_if(resource_exists::<BatteryChannels>),
+        );
+        app.add_systems(
+            Update,
+            (check_battery, gate_arming)
+                .chain()
+                .run_if(resource_exists::<BatteryChannels>),
+        );
+        app.add_systems(Last, shutdown.run_if(resource_exists::<BatteryChannels>));
+    }
+}
+
+#[derive(Resource)]
+struct BatteryChannels(Receiver<BatteryStatus>, Sender<()>);
+
+fn start_battery_thread(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    errors: Res<Errors>,
+) -> anyhow::Result<()> {
+    let Some(config) = config.battery.clone() else {
+        return Ok(());
+    };
+
+    let (tx_data, rx_data) = channel::bounded(5);
+    let (tx_exit, rx_exit) = channel::bounded(1);
+
+    let mut battery = SmartBattery::new(SmartBattery::I2C_BUS, config.i2c_address)
+        .context("Battery management system (Smart Battery)")?;
+
+    cmds.insert_resource(BatteryChannels(rx_data, tx_exit));
+
+    let errors = errors.0.clone();
+    thread::Builder::new()
+        .name("Battery Thread".to_owned())
+        .spawn(move || {
+            let _span = span!(Level::INFO, "Battery thread").entered();
+
+            loop {
+                let span = span!(Level::INFO, "Battery cycle").entered();
+
+                let status: anyhow::Result<BatteryStatus> = try {
+                    BatteryStatus {
+                        cell_voltages: battery
+                            .cell_voltages(config.cells)?
+                            .into_iter()
+                            .map(Into::into)
+                            .collect(),
+                        temperature: battery.temperature()?.into(),
+                        state_of_charge: battery.state_of_charge()?,
+                        cycle_count: battery.cycle_count()?,
+                    }
+                };
+
+                match status {
+                    Ok(status) => {
+                        if tx_data.send(status).is_err() {
+                            // Peer disconected
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = errors.send(err);
+                    }
+                }
+
+                if let Ok(()) = rx_exit.try_recv() {
+                    return;
+                }
+
+                span.exit();
+
+                thread::sleep(INTERVAL);
+            }
+        })
+        .context("Start thread")?;
+
+    Ok(())
+}
+
+fn read_new_data(mut cmds: Commands, channels: Res<BatteryChannels>, robot: Res<LocalRobot>) {
+    if let Some(status) = channels.0.try_iter().last() {
+        cmds.entity(robot.entity).insert(status);
+    }
+}
+
+fn fault(status: &BatteryStatus, config: &BatteryConfig) -> Option<String> {
+    if let Some(min) = status.min_cell() {
+        if min.0 < config.min_cell_voltage {
+            return Some(format!("Battery cell at {min}"));
+        }
+    }
+
+    if let Some(imbalance) = status.imbalance() {
+        if imbalance.0 > config.max_cell_imbalance {
+            return Some(format!("Battery cells imbalanced by {imbalance}"));
+        }
+    }
+
+    if status.temperature.0 > config.max_temperature {
+        return Some(format!("Battery at {}", status.temperature));
+    }
+
+    None
+}
+
+fn check_battery(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    robot: Query<
+        (Entity, &BatteryStatus, Option<&BatteryFault>),
+        (With<LocalRobotMarker>, Changed<BatteryStatus>),
+    >,
+) {
+    let Some(config) = &config.battery else {
+        return;
+    };
+
+    for (entity, status, last_fault) in &robot {
+        match fault(status, config) {
+            Some(fault) => {
+                if last_fault.map(|it| &it.0) != Some(&fault) {
+                    warn!("Battery fault: {fault}");
+                    cmds.entity(entity).insert(BatteryFault(fault));
+                }
+            }
+            None => {
+                if last_fault.is_some() {
+                    info!("Battery fault cleared");
+                    cmds.entity(entity).remove::<BatteryFault>();
+                }
+            }
+        }
+    }
+}
+
+/// Refuses to arm on a faulted battery, a robot that is already armed stays armed
+fn gate_arming(
+    mut cmds: Commands,
+    mut last_armed: Local<Option<Armed>>,
+    robot: Query<(Entity, &Armed, Option<&BatteryFault>), With<LocalRobotMarker>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let Ok((entity, &armed, fault)) = robot.get_single() else {
+        return;
+    };
+
+    let arming = armed == Armed::Armed && *last_armed != Some(Armed::Armed);
+    if let Some(BatteryFault(fault)) = fault.filter(|_| arming) {
+        cmds.entity(entity).insert(Armed::Disarmed);
+        errors.send(anyhow::anyhow!("Arming blocked: {fault}").into());
+
+        *last_armed = Some(Armed::Disarmed);
+        return;
+    }
+
+    *last_armed = Some(armed);
+}
+
+fn shutdown(channels: Res<BatteryChannels>, mut exit: EventReader<AppExit>) {
+    for _event in exit.read() {
+        let _ = channels.1.send(());
+    }
+}
@@ -1,10 +1,12 @@
+mod v4l2;
+
 use core::str;
 use std::{
     io,
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
     process::{Child, Command},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use ahash::{HashMap, HashSet};
@@ -12,20 +14,30 @@ use anyhow::{anyhow, bail, Context};
 use bevy::{app::AppExit, prelude::*};
 use common::{
     bundles::CameraBundle,
-    components::{CameraCalibration, CameraDefinition, CameraInputRotation, RobotId},
+    components::{
+        CameraCalibration, CameraCapabilities, CameraControls, CameraDefinition,
+        CameraInputRotation, CameraStream, RobotId,
+    },
     ecs_sync::{NetId, Replicate},
     error::{self, Errors},
-    events::ResyncCameras,
+    events::{RecoverCamera, ResyncCameras, SetCameraQuality},
     sync::Peer,
+    types::video::{CameraFormat, CameraQuality, StreamTransport, VideoCodec},
 };
 use crossbeam::channel::{self, Receiver, Sender};
 use tracing::{span, Level};
 
+use self::v4l2::HardwareEncoders;
 use crate::{
-    config::RobotConfig,
+    config::{CameraStreamingConfig, CameraTransport, RobotConfig},
     plugins::core::robot::{LocalRobot, LocalRobotMarker},
 };
 
+/// How often the camera thread checks for gstreamers that died
+const STREAM_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Minimum time between recoveries of the same camera, so a dead camera isn't reset in a loop
+const RECOVERY_COOLDOWN: Duration = Duration::from_secs(10);
+
 // TODO(low): Use multicast udp
 pub struct CameraPlugin;
 
@@ -33,7 +45,7 @@ impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, start_camera_thread.pipe(error::handle_errors));
         app.add_systems(PreUpdate, read_new_data);
-        app.add_systems(Update, handle_peers);
+        app.add_systems(Update, (handle_peers, check_streams, handle_controls));
         app.add_systems(Last, shutdown);
     }
 }
@@ -46,6 +58,14 @@ enum CameraEvent {
     LostPeer,
     // TODO(low): Some way to trigger this from the surface or on an interval
     Resync,
+    /// Restarts a single camera's gstreamer with new limits
+    SetQuality(String, CameraQuality),
+    /// Applies image controls to a camera, they are reapplied whenever the camera restarts
+    SetControls(String, CameraControls),
+    /// Recovers any camera whose gstreamer exited
+    CheckStreams,
+    /// Resets a camera the surface stopped receiving frames from and restarts its gstreamer
+    Recover(String),
     Shutdown,
 }
 
@@ -73,8 +93,14 @@ fn start_camera_thread(
         .spawn(move || {
             let _span = span!(Level::INFO, "Camera manager").entered();
 
+            let encoders = HardwareEncoders::detect();
+            info!("Hardware encoders: {encoders:?}");
+
             let mut last_cameras: HashSet<String> = HashSet::default();
-            let mut cameras: HashMap<String, (Child, SocketAddr)> = HashMap::default();
+            let mut cameras: HashMap<String, RunningCamera> = HashMap::default();
+            let mut qualities: HashMap<String, CameraQuality> = HashMap::default();
+            let mut recovered_at: HashMap<String, Instant> = HashMap::default();
+            let mut controls: HashMap<String, CameraControls> = HashMap::default();
             let mut target_ip = None;
             let mut port = 1024u16;
 
@@ -86,8 +112,8 @@ fn start_camera_thread(
 
                         target_ip = Some(addrs.ip());
 
-                        for (camera, (mut child, _)) in cameras.drain() {
-                            let rst = child.kill();
+                        for (camera, mut running) in cameras.drain() {
+                            let rst = running.child.kill();
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -95,7 +121,7 @@ fn start_camera_thread(
                                 );
                             }
 
-                            let rst = child.wait();
+                            let rst = running.child.wait();
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -107,7 +133,16 @@ fn start_camera_thread(
                         thread::sleep(Duration::from_millis(500));
 
                         for camera in &last_cameras {
-                            let rst = add_camera(camera, addrs.ip(), &mut cameras, &mut port);
+                            let rst = add_camera(
+                                camera,
+                                addrs.ip(),
+                                &mut cameras,
+                                &mut port,
+                                &config,
+                                encoders,
+                                qualities.get(camera).copied().unwrap_or_default(),
+                                controls.get(camera),
+                            );
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -129,8 +164,8 @@ fn start_camera_thread(
 
                         target_ip = None;
 
-                        for (camera, (mut child, _)) in cameras.drain() {
-                            let rst = child.kill();
+                        for (camera, mut running) in cameras.drain() {
+                            let rst = running.child.kill();
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -138,7 +173,7 @@ fn start_camera_thread(
                                 );
                             }
 
-                            let rst = child.wait();
+                            let rst = running.child.wait();
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -174,8 +209,8 @@ fn start_camera_thread(
                                             data.lines().map(ToOwned::to_owned).collect();
 
                                         for old_camera in last_cameras.difference(&next_cameras) {
-                                            if let Some(mut child) = cameras.remove(old_camera) {
-                                                let rst = child.0.kill();
+                                            if let Some(mut running) = cameras.remove(old_camera) {
+                                                let rst = running.child.kill();
 
                                                 if let Err(err) = rst {
                                                     let _ = errors.send(anyhow!(err).context(
@@ -183,7 +218,7 @@ fn start_camera_thread(
                                                     ));
                                                 }
 
-                                                let rst = child.0.wait();
+                                                let rst = running.child.wait();
 
                                                 if let Err(err) = rst {
                                                     let _ = errors.send(anyhow!(err).context(
@@ -202,6 +237,13 @@ fn start_camera_thread(
                                                     ip,
                                                     &mut cameras,
                                                     &mut port,
+                                                    &config,
+                                                    encoders,
+                                                    qualities
+                                                        .get(new_camera)
+                                                        .copied()
+                                                        .unwrap_or_default(),
+                                                    controls.get(new_camera),
                                                 );
 
                                                 if let Err(err) = rst {
@@ -234,9 +276,152 @@ fn start_camera_thread(
                             }
                         }
                     }
+                    // Restarts only the affected camera so the other streams keep running
+                    CameraEvent::SetQuality(camera, quality) => {
+                        if qualities.get(&camera).copied().unwrap_or_default() == quality {
+                            continue;
+                        }
+
+                        info!("Setting quality of {camera} to {}", quality.name());
+                        qualities.insert(camera.clone(), quality);
+
+                        let (Some(ip), Some(mut running)) = (target_ip, cameras.remove(&camera))
+                        else {
+                            continue;
+                        };
+
+                        let rst = running.child.kill();
+
+                        if let Err(err) = rst {
+                            let _ = errors
+                                .send(anyhow!(err).context(format!("Kill gstreamer for {camera}")));
+                        }
+
+                        let rst = running.child.wait();
+
+                        if let Err(err) = rst {
+                            let _ = errors
+                                .send(anyhow!(err).context(format!("Wait gstreamer for {camera}")));
+                        }
+
+                        let rst = add_camera(
+                            &camera,
+                            ip,
+                            &mut cameras,
+                            &mut port,
+                            &config,
+                            encoders,
+                            quality,
+                            controls.get(&camera),
+                        );
+
+                        if let Err(err) = rst {
+                            let _ = errors.send(
+                                anyhow!(err).context(format!("Start gstreamer for {camera}")),
+                            );
+                        }
+
+                        let camera_list = camera_list(&cameras, robot, &config);
+                        let res = tx_camreas.send(camera_list);
+                        if res.is_err() {
+                            // Peer disconected
+                            return;
+                        }
+                    }
+                    CameraEvent::SetControls(camera, new_controls) => {
+                        info!("Setting controls of {camera} to {new_controls:?}");
+                        controls.insert(camera.clone(), new_controls);
+
+                        // Cameras that aren't running get them when they start
+                        if cameras.contains_key(&camera) {
+                            let rst = v4l2::set_controls(&camera, &new_controls);
+
+                            if let Err(err) = rst {
+                                let _ = errors.send(
+                                    anyhow!(err).context(format!("Set controls of {camera}")),
+                                );
+                            }
+                        }
+                    }
+                    CameraEvent::CheckStreams | CameraEvent::Recover(_) => {
+                        let Some(ip) = target_ip else {
+                            continue;
+                        };
+
+                        let stalled = match event {
+                            CameraEvent::Recover(camera) => vec![camera],
+                            _ => exited_cameras(&mut cameras, &errors),
+                        };
+
+                        let now = Instant::now();
+                        let mut changed = false;
+                        for camera in stalled {
+                            if !cameras.contains_key(&camera) {
+                                continue;
+                            }
+
+                            let cooling_down = recovered_at
+                                .get(&camera)
+                                .is_some_and(|it| now - *it < RECOVERY_COOLDOWN);
+                            if cooling_down {
+                                continue;
+                            }
+                            recovered_at.insert(camera.clone(), now);
+
+                            warn!("Recovering stalled camera {camera}");
+
+                            if let Some(mut running) = cameras.remove(&camera) {
+                                // Errors when the gstreamer already exited on its own
+                                let _ = running.child.kill();
+
+                                let rst = running.child.wait();
+
+                                if let Err(err) = rst {
+                                    let _ = errors.send(
+                                        anyhow!(err)
+                                            .context(format!("Wait gstreamer for {camera}")),
+                                    );
+                                }
+                            }
+
+                            // Reopening the device is sometimes enough, so a failed reset
+                            // doesn't stop the restart
+                            if let Err(err) = v4l2::reset(&camera) {
+                                warn!("Could not reset {camera}: {err:?}");
+                            }
+
+                            let rst = add_camera(
+                                &camera,
+                                ip,
+                                &mut cameras,
+                                &mut port,
+                                &config,
+                                encoders,
+                                qualities.get(&camera).copied().unwrap_or_default(),
+                                controls.get(&camera),
+                            );
+
+                            if let Err(err) = rst {
+                                let _ = errors.send(
+                                    anyhow!(err).context(format!("Restart gstreamer for {camera}")),
+                                );
+                            }
+
+                            changed = true;
+                        }
+
+                        if changed {
+                            let camera_list = camera_list(&cameras, robot, &config);
+                            let res = tx_camreas.send(camera_list);
+                            if res.is_err() {
+                                // Peer disconected
+                                return;
+                            }
+                        }
+                    }
                     CameraEvent::Shutdown => {
-                        for (camera, (mut child, _)) in cameras.drain() {
-                            let rst = child.kill();
+                        for (camera, mut running) in cameras.drain() {
+                            let rst = running.child.kill();
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -244,7 +429,7 @@ fn start_camera_thread(
                                 );
                             }
 
-                            let rst = child.wait();
+                            let rst = running.child.wait();
 
                             if let Err(err) = rst {
                                 let _ = errors.send(
@@ -275,6 +460,8 @@ fn handle_peers(
     connected: Query<&Peer, Changed<Peer>>,
     connected_all: Query<&Peer>,
     mut resync_events: EventReader<ResyncCameras>,
+    mut quality_events: EventReader<SetCameraQuality>,
+    mut recover_events: EventReader<RecoverCamera>,
 ) {
     let res: Result<(), crossbeam::channel::SendError<_>> = try {
         for _resync in resync_events.read() {
@@ -287,6 +474,16 @@ fn handle_peers(
             channels.0.send(CameraEvent::NewPeer(peer.addrs))?;
         }
 
+        for SetCameraQuality { device, quality } in quality_events.read() {
+            channels
+                .0
+                .send(CameraEvent::SetQuality(device.clone(), *quality))?;
+        }
+
+        for RecoverCamera { device } in recover_events.read() {
+            channels.0.send(CameraEvent::Recover(device.clone()))?;
+        }
+
         for _disconnection in disconnected.read() {
             channels.0.send(CameraEvent::LostPeer)?;
         }
@@ -301,12 +498,50 @@ fn handle_peers(
     }
 }
 
-// TODO(low): Only update the cameras that changed
+fn handle_controls(
+    channels: Res<CameraChannels>,
+    cameras: Query<(&CameraCapabilities, &CameraControls), Changed<CameraControls>>,
+) {
+    for (capabilities, controls) in &cameras {
+        let res = channels.0.send(CameraEvent::SetControls(
+            capabilities.device.clone(),
+            *controls,
+        ));
+
+        if res.is_err() {
+            error!("Camera thread dead");
+        }
+    }
+}
+
+fn check_streams(
+    channels: Res<CameraChannels>,
+    mut timer: Local<Option<Timer>>,
+    time: Res<Time<Real>>,
+) {
+    let timer =
+        timer.get_or_insert_with(|| Timer::new(STREAM_CHECK_INTERVAL, TimerMode::Repeating));
+    timer.tick(time.delta());
+
+    if timer.just_finished() {
+        // The camera thread may be busy restarting cameras, skipping a check is harmless
+        let _ = channels.0.try_send(CameraEvent::CheckStreams);
+    }
+}
+
+/// Updates the camera entities in place so the surface only restarts the streams that changed
 fn read_new_data(
     mut cmds: Commands,
     channels: Res<CameraChannels>,
     robot: Query<(Entity, &NetId), With<LocalRobotMarker>>,
-    cameras: Query<(Entity, &RobotId), With<CameraDefinition>>,
+    cameras: Query<(
+        Entity,
+        &RobotId,
+        &Name,
+        &CameraDefinition,
+        &CameraStream,
+        &CameraCapabilities,
+    )>,
 ) {
     let mut new_cameras = None;
     for camera_update in channels.1.try_iter() {
@@ -316,14 +551,32 @@ fn read_new_data(
     if let Some(new_cameras) = new_cameras {
         let (_robot, id) = robot.single();
 
-        for (entity, camera_robot) in &cameras {
-            if camera_robot.0 == *id {
-                cmds.entity(entity).despawn();
+        let mut existing: HashMap<&str, _> = cameras
+            .iter()
+            .filter(|(_, camera_robot, ..)| camera_robot.0 == *id)
+            .map(|(entity, _, name, definition, stream, capabilities)| {
+                (name.as_str(), (entity, definition, stream, capabilities))
+            })
+            .collect();
+
+        for camera in new_cameras {
+            match existing.remove(camera.name.as_str()) {
+                Some((entity, definition, stream, capabilities)) => {
+                    if *definition != camera.camera
+                        || *stream != camera.stream
+                        || *capabilities != camera.capabilities
+                    {
+                        cmds.entity(entity).insert(camera);
+                    }
+                }
+                None => {
+                    cmds.spawn((camera, Replicate));
+                }
             }
         }
 
-        for camera in new_cameras {
-            cmds.spawn((camera, Replicate));
+        for (entity, ..) in existing.into_values() {
+            cmds.entity(entity).despawn();
         }
     }
 }
@@ -334,26 +587,188 @@ fn shutdown(channels: Res<CameraChannels>, mut exit: EventReader<AppExit>) {
     }
 }
 
+/// A camera with a running instance of gstreamer
+struct RunningCamera {
+    child: Child,
+    location: SocketAddr,
+    stream: CameraStream,
+    capabilities: CameraCapabilities,
+}
+
+/// The part of a gstreamer pipeline that captures from the camera and ends with encoded video
+struct EncodedSource {
+    pipeline: String,
+    hardware_encoded: bool,
+    format: Option<CameraFormat>,
+}
+
+/// Picks how to get `codec` out of a camera, preferring the camera's own encoder, then the hardware
+/// encoder, then a software encoder
+fn negotiate_source(
+    device: &str,
+    capabilities: &CameraCapabilities,
+    codec: VideoCodec,
+    encoders: HardwareEncoders,
+    settings: &CameraStreamingConfig,
+    quality: CameraQuality,
+) -> anyhow::Result<EncodedSource> {
+    let (max_width, max_height) = match quality.max_size() {
+        Some((width, height)) => (
+            width.min(settings.max_width),
+            height.min(settings.max_height),
+        ),
+        None => (settings.max_width, settings.max_height),
+    };
+    let bitrate_kbps = (settings.bitrate_kbps as f32 * quality.bitrate_scale()) as u32;
+
+    let best = |fourcc: &str| {
+        capabilities
+            .formats
+            .iter()
+            .filter(|it| it.fourcc == fourcc)
+            .filter(|it| it.width <= max_width && it.height <= max_height)
+            .max_by(|a, b| {
+                (a.width * a.height)
+                    .cmp(&(b.width * b.height))
+                    .then(a.fps.total_cmp(&b.fps))
+            })
+            .cloned()
+            .map(|mut it| {
+                if let Some(max_fps) = quality.max_fps() {
+                    it.fps = it.fps.min(max_fps);
+                }
+
+                it
+            })
+    };
+    let caps = |format: &CameraFormat| {
+        format!(
+            "width={},height={},framerate={:.0}/1",
+            format.width, format.height, format.fps
+        )
+    };
+    let src = format!("v4l2src device={device} do-timestamp=true");
+
+    let (native, parse, caps_name, hardware, hardware_encoder, software_encoder) = match codec {
+        VideoCodec::H264 => (
+            "H264",
+            "h264parse",
+            "video/x-h264,stream-format=avc,alignment=au",
+            encoders.h264,
+            "v4l2h264enc",
+            "x264enc",
+        ),
+        VideoCodec::H265 => (
+            "HEVC",
+            "h265parse",
+            "video/x-h265,stream-format=hvc1,alignment=au",
+            encoders.h265,
+            "v4l2h265enc",
+            "x265enc",
+        ),
+    };
+
+    if let Some(format) = best(native) {
+        return Ok(EncodedSource {
+            pipeline: format!("{src} ! {parse} ! {caps_name},{}", caps(&format)),
+            hardware_encoded: false,
+            format: Some(format),
+        });
+    }
+
+    // Cameras that could not be queried are assumed to be the H.264 cameras this always used, these
+    // only support a few sizes so pick the closest one
+    if capabilities.formats.is_empty() && codec == VideoCodec::H264 {
+        let (width, height) = match quality.max_size() {
+            None => (1920, 1080),
+            Some((width, _)) if width >= 1280 => (1280, 720),
+            Some(_) => (640, 480),
+        };
+        let fps = quality.max_fps().unwrap_or(30.0);
+
+        return Ok(EncodedSource {
+            pipeline: format!(
+                "{src} ! {parse} ! {caps_name},width={width},height={height},framerate={fps:.0}/1"
+            ),
+            hardware_encoded: false,
+            format: None,
+        });
+    }
+
+    let (format, decode) = if let Some(format) = best("MJPG") {
+        let decode = format!("image/jpeg,{} ! jpegdec ! videoconvert", caps(&format));
+        (format, decode)
+    } else if let Some(format) = best("YUYV") {
+        let decode = format!("video/x-raw,format=YUY2,{} ! videoconvert", caps(&format));
+        (format, decode)
+    } else {
+        bail!("{device} has no format that can be encoded as {codec}");
+    };
+
+    // One keyframe a second so the surface can join the stream quickly
+    let keyframe_interval = format.fps.round().max(1.0) as u32;
+    let encode = if hardware {
+        let mut controls = format!("video_bitrate={}", bitrate_kbps * 1000);
+        if codec == VideoCodec::H264 {
+            controls += &format!(",h264_i_frame_period={keyframe_interval}");
+        }
+
+        format!("{hardware_encoder} extra-controls=controls,{controls}")
+    } else {
+        format!(
+            "{software_encoder} tune=zerolatency speed-preset=ultrafast bitrate={bitrate_kbps} key-int-max={keyframe_interval}"
+        )
+    };
+
+    Ok(EncodedSource {
+        pipeline: format!("{src} ! {decode} ! {encode} ! {parse} ! {caps_name}"),
+        hardware_encoded: hardware,
+        format: Some(format),
+    })
+}
+
+/// The address this machine uses to reach `peer`, no packets are sent
+fn local_ip_towards(peer: IpAddr) -> io::Result<IpAddr> {
+    let unspecified: IpAddr = match peer {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+
+    let socket = UdpSocket::bind((unspecified, 0))?;
+    socket.connect((peer, 9))?;
+
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Cameras whose gstreamer exited, usually because the device stopped producing frames
+fn exited_cameras(
+    cameras: &mut HashMap<String, RunningCamera>,
+    errors: &Sender<anyhow::Error>,
+) -> Vec<String> {
+    let mut exited = Vec::new();
+
+    for (camera, running) in cameras {
+        match running.child.try_wait() {
+            Ok(Some(status)) => {
+                warn!("Gstreamer for {camera} exited: {status}");
+                exited.push(camera.clone());
+            }
+            Ok(None) => {}
+            Err(err) => {
+                let _ = errors.send(anyhow!(err).context(format!("Check gstreamer for {camera}")));
+            }
+        }
+    }
+
+    exited
+}
+
 /// Spawns a gstreamer with the args necessary
-fn start_gstreamer(camera: &str, addrs: SocketAddr) -> io::Result<Child> {
+fn start_gstreamer(pipeline: &str) -> io::Result<Child> {
+    debug!("Starting gstreamer: {pipeline}");
+
     Command::new("gst-launch-1.0")
-        .arg("v4l2src")
-        .arg(format!("device={camera}"))
-        .arg("do-timestamp=true")
-        .arg("!")
-        .arg("h264parse")
-        .arg("!")
-        .arg("video/x-h264,stream-format=avc,alignment=au,width=1920,height=1080,framerate=30/1")
-        .arg("!")
-        .arg("rtph264pay")
-        .arg("aggregate-mode=zero-latency")
-        .arg("config-interval=10")
-        .arg("pt=96")
-        .arg("!")
-        .arg("udpsink")
-        .arg("sync=false")
-        .arg(format!("host={}", addrs.ip()))
-        .arg(format!("port={}", addrs.port()))
+        .args(pipeline.split_whitespace())
         .spawn()
 }
 
@@ -361,8 +776,12 @@ fn start_gstreamer(camera: &str, addrs: SocketAddr) -> io::Result<Child> {
 fn add_camera(
     camera: &str,
     ip: IpAddr,
-    cameras: &mut HashMap<String, (Child, SocketAddr)>,
+    cameras: &mut HashMap<String, RunningCamera>,
     port: &mut u16,
+    config: &RobotConfig,
+    encoders: HardwareEncoders,
+    quality: CameraQuality,
+    controls: Option<&CameraControls>,
 ) -> anyhow::Result<()> {
     let setup_exit = Command::new("/home/pi/mate/setup_camera.sh")
         .arg(camera)
@@ -374,25 +793,97 @@ fn add_camera(
         bail!("Could not setup cameras");
     }
 
+    // After the setup script so the surface's controls override its defaults
+    if let Some(controls) = controls {
+        if let Err(err) = v4l2::set_controls(camera, controls) {
+            warn!("Could not set the controls of {camera}: {err:?}");
+        }
+    }
+
+    let settings = &config.camera_streaming;
+    let codec = config
+        .cameras
+        .get(camera)
+        .and_then(|it| it.codec)
+        .unwrap_or(settings.codec);
+
+    let mut capabilities = match v4l2::capabilities(camera) {
+        Ok(capabilities) => capabilities,
+        Err(err) => {
+            warn!("Could not query capabilities of {camera}: {err:?}");
+
+            CameraCapabilities {
+                device: camera.to_owned(),
+                card: camera.to_owned(),
+                ..default()
+            }
+        }
+    };
+
+    let source = negotiate_source(camera, &capabilities, codec, encoders, settings, quality)?;
+    capabilities.active = source.format;
+
     let bind = (ip, *port).into();
-    let child =
-        start_gstreamer(camera, bind).with_context(|| format!("Spawn gstreamer for {camera}"))?;
+    let pay = match codec {
+        VideoCodec::H264 => "rtph264pay",
+        VideoCodec::H265 => "rtph265pay",
+    };
+
+    let (sink, transport) = match settings.transport {
+        CameraTransport::Rtp => (
+            format!(
+                "{pay} aggregate-mode=zero-latency config-interval=10 pt=96 ! udpsink sync=false host={} port={}",
+                ip, *port
+            ),
+            StreamTransport::Rtp,
+        ),
+        CameraTransport::Rtsp => {
+            let path = camera.trim_start_matches("/dev/").replace('/', "_");
+            let robot_ip = local_ip_towards(ip).context("Find robot address")?;
+
+            (
+                format!(
+                    "rtspclientsink location=rtsp://127.0.0.1:{}/{path} protocols=tcp",
+                    settings.rtsp_port
+                ),
+                StreamTransport::Rtsp {
+                    url: format!("rtsp://{robot_ip}:{}/{path}", settings.rtsp_port),
+                },
+            )
+        }
+    };
+
+    let child = start_gstreamer(&format!("{} ! {sink}", source.pipeline))
+        .with_context(|| format!("Spawn gstreamer for {camera}"))?;
     *port += 1;
 
-    cameras.insert((*camera).to_owned(), (child, bind));
+    cameras.insert(
+        (*camera).to_owned(),
+        RunningCamera {
+            child,
+            location: bind,
+            stream: CameraStream {
+                codec,
+                transport,
+                hardware_encoded: source.hardware_encoded,
+                quality,
+            },
+            capabilities,
+        },
+    );
 
     Ok(())
 }
 
 /// Converts internal repersentation of cameras to what the protocol calls for
 fn camera_list(
-    cameras: &HashMap<String, (Child, SocketAddr)>,
+    cameras: &HashMap<String, RunningCamera>,
     robot: RobotId,
     config: &RobotConfig,
 ) -> Vec<CameraBundle> {
     let mut list = Vec::new();
 
-    for (name, &(_, location)) in cameras {
+    for (name, running) in cameras {
         let (name, transform, input_rotation, calib) = match config.cameras.get(name) {
             Some(definition) => (
                 format!("{} ({})", definition.name, name),
@@ -410,7 +901,11 @@ fn camera_list(
 
         list.push(CameraBundle {
             name: Name::new(name),
-            camera: CameraDefinition { location },
+            camera: CameraDefinition {
+                location: running.location,
+            },
+            stream: running.stream.clone(),
+            capabilities: running.capabilities.clone(),
             robot,
             transform,
             input_rotation,
@@ -0,0 +1,306 @@
+use core::str;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ahash::HashMap;
+use anyhow::{anyhow, bail, Context};
+use common::{
+    components::{CameraCapabilities, CameraControls},
+    types::video::{CameraControlRanges, CameraFormat, ControlRange},
+};
+use tracing::debug;
+
+/// Queries the name and capture formats of a V4L2 device with `v4l2-ctl`
+pub fn capabilities(device: &str) -> anyhow::Result<CameraCapabilities> {
+    let info = v4l2_ctl(device, "--info").context("Read device info")?;
+    let formats = v4l2_ctl(device, "--list-formats-ext").context("Read device formats")?;
+    let controls = v4l2_ctl(device, "--list-ctrls").context("Read device controls")?;
+    let controls = parse_controls(&controls);
+
+    let range = |names: &[&str]| names.iter().find_map(|it| controls.get(*it)).copied();
+
+    Ok(CameraCapabilities {
+        device: device.to_owned(),
+        card: parse_card(&info).unwrap_or_else(|| device.to_owned()),
+        formats: parse_formats(&formats),
+        active: None,
+        controls: CameraControlRanges {
+            exposure: range(&EXPOSURE),
+            white_balance: range(&WHITE_BALANCE),
+            focus: range(&FOCUS),
+            gain: range(&GAIN),
+        },
+    })
+}
+
+// UVC controls were renamed in linux 5.x, the old names are kept for older kernels
+const AUTO_EXPOSURE: [&str; 2] = ["auto_exposure", "exposure_auto"];
+const EXPOSURE: [&str; 2] = ["exposure_time_absolute", "exposure_absolute"];
+const AUTO_WHITE_BALANCE: [&str; 2] = ["white_balance_automatic", "white_balance_temperature_auto"];
+const WHITE_BALANCE: [&str; 1] = ["white_balance_temperature"];
+const AUTO_FOCUS: [&str; 2] = ["focus_automatic_continuous", "focus_auto"];
+const FOCUS: [&str; 1] = ["focus_absolute"];
+const GAIN: [&str; 1] = ["gain"];
+
+/// Values of the `auto_exposure` menu
+const EXPOSURE_MANUAL: i32 = 1;
+const EXPOSURE_APERTURE_PRIORITY: i32 = 3;
+
+/// Applies the surface's image controls, automatic modes are switched before the manual values
+/// are written since cameras ignore manual values while their automatic mode is on
+pub fn set_controls(device: &str, controls: &CameraControls) -> anyhow::Result<()> {
+    let available =
+        parse_controls(&v4l2_ctl(device, "--list-ctrls").context("Read device controls")?);
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|it| available.get_key_value(*it))
+            .map(|(name, range)| (name.as_str(), *range))
+    };
+
+    let mut modes = Vec::new();
+    let mut values = Vec::new();
+
+    let manual_controls = [
+        (&AUTO_EXPOSURE[..], &EXPOSURE[..], controls.exposure),
+        (
+            &AUTO_WHITE_BALANCE[..],
+            &WHITE_BALANCE[..],
+            controls.white_balance,
+        ),
+        (&AUTO_FOCUS[..], &FOCUS[..], controls.focus),
+    ];
+    for (auto, manual, value) in manual_controls {
+        if let Some((name, _)) = find(auto) {
+            let mode = if AUTO_EXPOSURE.contains(&name) {
+                match value {
+                    Some(_) => EXPOSURE_MANUAL,
+                    None => EXPOSURE_APERTURE_PRIORITY,
+                }
+            } else {
+                // The other automatic modes are booleans
+                value.is_none() as i32
+            };
+            modes.push(format!("{name}={mode}"));
+        }
+
+        if let (Some((name, _)), Some(value)) = (find(manual), value) {
+            values.push(format!("{name}={value}"));
+        }
+    }
+
+    // Gain has no automatic mode, clearing it goes back to the default
+    if let Some((name, range)) = find(&GAIN) {
+        values.push(format!("{name}={}", controls.gain.unwrap_or(range.default)));
+    }
+
+    for set in [modes, values] {
+        if !set.is_empty() {
+            v4l2_ctl(device, &format!("--set-ctrl={}", set.join(",")))
+                .context("Set device controls")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn v4l2_ctl(device: &str, arg: &str) -> anyhow::Result<String> {
+    let output = Command::new("v4l2-ctl")
+        .arg("-d")
+        .arg(device)
+        .arg(arg)
+        .output()
+        .context("Run v4l2-ctl")?;
+
+    if !output.status.success() {
+        bail!("v4l2-ctl {arg}: {}", output.status);
+    }
+
+    Ok(str::from_utf8(&output.stdout)
+        .context("Decode v4l2-ctl output")?
+        .to_owned())
+}
+
+/// Finds `Card type : <name>` in the output of `v4l2-ctl --info`
+fn parse_card(info: &str) -> Option<String> {
+    info.lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix("Card type"))
+        .and_then(|it| it.split_once(':'))
+        .map(|(_, card)| card.trim().to_owned())
+}
+
+/// Parses the output of `v4l2-ctl --list-ctrls`, which looks like
+///
+/// ```text
+/// exposure_time_absolute 0x009a0902 (int)    : min=3 max=2047 step=1 default=250 value=250
+/// ```
+fn parse_controls(list: &str) -> HashMap<String, ControlRange> {
+    let mut controls = HashMap::default();
+
+    for line in list.lines() {
+        let Some((name, values)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(name) = name.split_whitespace().next() else {
+            continue;
+        };
+
+        let value = |key: &str| {
+            values
+                .split_whitespace()
+                .find_map(|it| it.strip_prefix(key)?.strip_prefix('='))
+                .and_then(|it| it.parse().ok())
+        };
+
+        let (Some(min), Some(max), Some(default)) = (value("min"), value("max"), value("default"))
+        else {
+            continue;
+        };
+
+        controls.insert(
+            name.to_owned(),
+            ControlRange {
+                min,
+                max,
+                // Menus and booleans don't list a step
+                step: value("step").unwrap_or(1),
+                default,
+            },
+        );
+    }
+
+    controls
+}
+
+/// Parses the output of `v4l2-ctl --list-formats-ext`, which looks like
+///
+/// ```text
+/// [0]: 'MJPG' (Motion-JPEG, compressed)
+///     Size: Discrete 1920x1080
+///         Interval: Discrete 0.033s (30.000 fps)
+/// ```
+///
+/// Only the fastest interval of each size is kept
+fn parse_formats(list: &str) -> Vec<CameraFormat> {
+    let mut formats: Vec<CameraFormat> = Vec::new();
+    let mut fourcc = None;
+    let mut size = None;
+
+    for line in list.lines().map(str::trim) {
+        if line.starts_with('[') {
+            fourcc = line.split('\'').nth(1).map(ToOwned::to_owned);
+            size = None;
+        } else if let Some(rest) = line.strip_prefix("Size:") {
+            size = rest
+                .split_whitespace()
+                .last()
+                .and_then(|it| it.split_once('x'))
+                .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+        } else if line.starts_with("Interval:") {
+            let fps = line
+                .split_once('(')
+                .and_then(|(_, it)| it.split_whitespace().next())
+                .and_then(|it| it.parse::<f32>().ok());
+
+            let (Some(fourcc), Some((width, height)), Some(fps)) = (&fourcc, size, fps) else {
+                continue;
+            };
+
+            let existing = formats
+                .iter_mut()
+                .find(|it| it.fourcc == *fourcc && it.width == width && it.height == height);
+
+            match existing {
+                Some(existing) => existing.fps = existing.fps.max(fps),
+                None => formats.push(CameraFormat {
+                    fourcc: fourcc.clone(),
+                    width,
+                    height,
+                    fps,
+                }),
+            }
+        }
+    }
+
+    formats
+}
+
+/// Time the kernel gets to drop the device before it is authorized again
+const DEAUTHORIZE_DELAY: Duration = Duration::from_millis(500);
+/// Time a reset device gets to show up again
+const REENUMERATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resets the USB device behind a V4L2 device by deauthorizing and reauthorizing it, which makes
+/// the kernel drop and rebind its driver. Cameras that aren't on USB can't be reset this way
+pub fn reset(device: &str) -> anyhow::Result<()> {
+    let usb_device = usb_device(device).context("Find usb device")?;
+    let authorized = usb_device.join("authorized");
+
+    debug!("Resetting {device} through {}", usb_device.display());
+
+    fs::write(&authorized, "0").context("Deauthorize device")?;
+    thread::sleep(DEAUTHORIZE_DELAY);
+    fs::write(&authorized, "1").context("Authorize device")?;
+
+    let start = Instant::now();
+    while !Path::new(device).exists() {
+        if start.elapsed() > REENUMERATE_TIMEOUT {
+            bail!("{device} did not come back after reset");
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    Ok(())
+}
+
+/// Walks up sysfs from the video device to the USB device it belongs to
+fn usb_device(device: &str) -> anyhow::Result<PathBuf> {
+    // Devices are often udev symlinks to the real node
+    let node = fs::canonicalize(device).context("Resolve device")?;
+    let name = node
+        .file_name()
+        .ok_or_else(|| anyhow!("{device} has no name"))?;
+
+    let sysfs = Path::new("/sys/class/video4linux")
+        .join(name)
+        .join("device");
+    let sysfs = fs::canonicalize(&sysfs).context("Resolve sysfs device")?;
+
+    // Only USB devices (not their interfaces) have a vendor id
+    sysfs
+        .ancestors()
+        .find(|it| it.join("idVendor").exists())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| anyhow!("{device} is not a usb device"))
+}
+
+/// The hardware encoders gstreamer has available, the Pi exposes its encoder through V4L2
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HardwareEncoders {
+    pub h264: bool,
+    pub h265: bool,
+}
+
+impl HardwareEncoders {
+    pub fn detect() -> Self {
+        let exists = |element: &str| {
+            Command::new("gst-inspect-1.0")
+                .arg("--exists")
+                .arg(element)
+                .status()
+                .is_ok_and(|it| it.success())
+        };
+
+        Self {
+            h264: exists("v4l2h264enc"),
+            h265: exists("v4l2h265enc"),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{thread, time::Duration};
+
+use anyhow::Context;
+use bevy::{app::AppExit, prelude::*};
+use common::{
+    components::{DepthSettings, Salinity, WaterTemperature},
+    error::{self, Errors},
+    types::units::Celsius,
+};
+use crossbeam::channel::{self, Receiver, Sender};
+use tracing::{span, Level};
+
+use crate::{
+    config::RobotConfig,
+    peripheral::ezo_ec::EzoEc,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
+};
+
+const INTERVAL: Duration = Duration::from_secs(2);
+
+/// Practical salinity units the measured salinity must change by before the depth calculation
+/// picks it up
+const SALINITY_TOLERANCE: f32 = 0.1;
+
+/// Reads the salinity of the water from an optional conductivity sensor and uses it for the depth
+/// calculation in place of the configured fluid
+pub struct ConductivityPlugin;
+
+impl Plugin for ConductivityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            start_conductivity_thread.pipe(error::handle_errors),
+        );
+        app.add_systems(
+            PreUpdate,
+            read_new_data.run_if(resource_exists::<ConductivityChannels>),
+        );
+        app.add_systems(
+            Update,
+            send_water_temperature.run_if(resource_exists::<ConductivityChannels>),
+        );
+        app.add_systems(
+            Last,
+            shutdown.run_if(resource_exists::<ConductivityChannels>),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct ConductivityChannels(Receiver<Salinity>, Sender<Message>);
+
+enum Message {
+    /// Used for temperature compensation
+    WaterTemperature(Celsius),
+    Shutdown,
+}
+
+fn start_conductivity_thread(
+    mut cmds: Commands,
+    config: Res<RobotConfig>,
+    errors: Res<Errors>,
+) -> anyhow::Result<()> {
+    let Some(config) = config.conductivity.clone() else {
+        return Ok(());
+    };
+
+    let (tx_data, rx_data) = channel::bounded(5);
+    let (tx_msg, rx_msg) = channel::bounded(5);
+
+    let mut sensor =
+        EzoEc::new(config.i2c_bus, config.i2c_address).context("Conductivity sensor (EZO-EC)")?;
+
+    cmds.insert_resource(ConductivityChannels(rx_data, tx_msg));
+
+    let errors = errors.0.clone();
+    thread::Builder::new()
+        .name("Conductivity Thread".to_owned())
+        .spawn(move || {
+            let _span = span!(Level::INFO, "Conductivity thread").entered();
+
+            let mut temperature = None;
+
+            loop {
+                let span = span!(Level::INFO, "Conductivity cycle").entered();
+
+                for msg in rx_msg.try_iter() {
+                    match msg {
+                        Message::WaterTemperature(new_temperature) => {
+                            temperature = Some(new_temperature);
+                        }
+                        Message::Shutdown => return,
+                    }
+                }
+
+                let rst = sensor.read_salinity(temperature).context("Read salinity");
+
+                match rst {
+                    Ok(salinity) => {
+                        if tx_data.send(Salinity(salinity)).is_err() {
+                            // Peer disconected
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = errors.send(err);
+                    }
+                }
+
+                span.exit();
+
+                thread::sleep(INTERVAL);
+            }
+        })
+        .context("Start thread")?;
+
+    Ok(())
+}
+
+fn read_new_data(
+    mut cmds: Commands,
+    channels: Res<ConductivityChannels>,
+    robot: Res<LocalRobot>,
+    mut settings: Query<&mut DepthSettings, With<LocalRobotMarker>>,
+) {
+    let Some(salinity) = channels.0.try_iter().last() else {
+        return;
+    };
+
+    for mut settings in &mut settings {
+        let changed = settings
+            .salinity
+            .is_none_or(|it| (it - salinity.0).abs() > SALINITY_TOLERANCE);
+
+        if changed {
+            settings.salinity = Some(salinity.0);
+        }
+    }
+
+    cmds.entity(robot.entity).insert(salinity);
+}
+
+fn send_water_temperature(
+    channels: Res<ConductivityChannels>,
+    robot: Query<&WaterTemperature, With<LocalRobotMarker>>,
+    mut last_sent: Local<Option<Celsius>>,
+) {
+    let Ok(&WaterTemperature(temperature)) = robot.get_single() else {
+        return;
+    };
+
+    // The sensor only reads every couple seconds, small changes aren't worth sending
+    if last_sent.is_some_and(|it| (it.0 - temperature.0).abs() < 0.1) {
+        return;
+    }
+
+    if channels
+        .1
+        .try_send(Message::WaterTemperature(temperature))
+        .is_ok()
+    {
+        *last_sent = Some(temperature);
+    }
+}
+
+fn shutdown(channels: Res<ConductivityChannels>, mut exit: EventReader<AppExit>) {
+    for _event in exit.read() {
+        let _ = channels.1.send(Message::Shutdown);
+    }
+}
@@ -3,25 +3,42 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use bevy::{app::AppExit, prelude::*};
 use common::{
-    components::{DepthMeasurement, DepthSettings, TempertureMeasurement},
+    components::{DepthMeasurement, DepthSensorHealth, DepthSettings, WaterTemperature},
     error::{self, Errors},
     events::CalibrateSeaLevel,
+    types::units::Mbar,
 };
 use crossbeam::channel::{self, Receiver, Sender};
 use tracing::{span, Level};
 
 use crate::{
-    peripheral::ms5937::Ms5837,
+    config::{DepthSensorConfig, DepthSensorKind, RobotConfig},
+    peripheral::{
+        keller_ld::KellerLd,
+        ms5937::Ms5837,
+        pressure::{self, PressureSensor},
+    },
     plugins::core::robot::{LocalRobot, LocalRobotMarker},
+    utils::average::RunningAverage,
 };
 
+/// Without a reading for this long the depth measurement is removed so nothing acts on it
+const STALE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Readings averaged into the sea level pressure when calibrating
+const CALIBRATION_SAMPLES: usize = 50;
+
+/// Kilograms per cubic meter the density in use may drift from the replicated one
+const DENSITY_TOLERANCE: f32 = 0.1;
+
 pub struct DepthPlugin;
 
 impl Plugin for DepthPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<SeaLevelCalibration>();
         app.add_systems(Startup, start_depth_thread.pipe(error::handle_errors));
         app.add_systems(
             PreUpdate,
@@ -30,7 +47,11 @@ impl Plugin for DepthPlugin {
         app.add_systems(
             Update,
             (
-                calibrate_sea_level.run_if(resource_exists::<DepthChannels>),
+                update_health.run_if(resource_exists::<DepthChannels>),
+                calibrate_sea_level
+                    .pipe(error::handle_errors)
+                    .run_if(resource_exists::<DepthChannels>)
+                    .after(update_health),
                 listen_for_settings
                     .pipe(error::handle_errors)
                     .run_if(resource_exists::<DepthChannels>)
@@ -42,36 +63,93 @@ impl Plugin for DepthPlugin {
 }
 
 #[derive(Resource)]
-struct DepthChannels(
-    Receiver<(DepthMeasurement, TempertureMeasurement)>,
-    Sender<Message>,
-);
+struct DepthChannels(Receiver<Reading>, Sender<Message>);
+
+enum Reading {
+    /// The measurement, the water temperature and the density the depth was calculated with
+    Frame(DepthMeasurement, WaterTemperature, f32),
+    Failed,
+}
 
 enum Message {
     Settings(DepthSettings),
     Shutdown,
 }
 
+#[derive(Resource)]
+struct DepthSensorState {
+    sensor: &'static str,
+    last_reading: Instant,
+    failed_reads: u32,
+}
+
+/// The pressures read since a sea level calibration was started
+#[derive(Resource, Default)]
+struct SeaLevelCalibration(Option<Vec<Mbar>>);
+
+fn open_sensor(config: &DepthSensorConfig) -> anyhow::Result<Box<dyn PressureSensor>> {
+    let sensor: Box<dyn PressureSensor> = match config.sensor {
+        DepthSensorKind::Bar30 => Box::new(
+            Ms5837::new(
+                config.i2c_bus.unwrap_or(Ms5837::I2C_BUS),
+                config.i2c_address.unwrap_or(Ms5837::I2C_ADDRESS),
+            )
+            .context("Depth sensor (Ms5837)")?,
+        ),
+        DepthSensorKind::Bar100 | DepthSensorKind::KellerLd => {
+            let name = if config.sensor == DepthSensorKind::Bar100 {
+                "Bar100"
+            } else {
+                "Keller LD"
+            };
+
+            Box::new(
+                KellerLd::new(
+                    config.i2c_bus.unwrap_or(KellerLd::I2C_BUS),
+                    config.i2c_address.unwrap_or(KellerLd::I2C_ADDRESS),
+                    name,
+                )
+                .context("Depth sensor (Keller LD)")?,
+            )
+        }
+    };
+
+    Ok(sensor)
+}
+
 fn start_depth_thread(
     mut cmds: Commands,
     robot: Res<LocalRobot>,
+    config: Res<RobotConfig>,
     errors: Res<Errors>,
 ) -> anyhow::Result<()> {
     let (tx_data, rx_data) = channel::bounded(5);
     let (tx_exit, rx_msg) = channel::bounded(1);
 
-    let mut depth =
-        Ms5837::new(Ms5837::I2C_BUS, Ms5837::I2C_ADDRESS).context("Depth sensor (Ms5837)")?;
+    let mut sensor = open_sensor(&config.depth_sensor)?;
+    sensor.read().context("Initial depth read")?;
 
     cmds.insert_resource(DepthChannels(rx_data, tx_exit));
+    cmds.insert_resource(DepthSensorState {
+        sensor: sensor.name(),
+        last_reading: Instant::now(),
+        failed_reads: 0,
+    });
 
-    let (sea_level, _) = depth.read_frame().context("Read Sea Level")?;
-    // depth.sea_level = sea_level.pressure;
+    let fluid = config.depth_sensor.fluid;
+    let mut settings = DepthSettings {
+        sea_level: Mbar(1013.25),
+        fluid_density: fluid.density(),
+        salinity: fluid.salinity(),
+    };
 
-    cmds.entity(robot.entity).insert(DepthSettings {
-        sea_level: depth.sea_level,
-        fluid_density: depth.fluid_density,
-    });
+    cmds.entity(robot.entity).insert((
+        settings,
+        DepthSensorHealth {
+            sensor: sensor.name().to_owned(),
+            ..default()
+        },
+    ));
 
     let errors = errors.0.clone();
     thread::Builder::new()
@@ -81,31 +159,54 @@ fn start_depth_thread(
 
             let interval = Duration::from_secs_f64(1.0 / 100.0);
             let mut deadline = Instant::now();
+            let mut filter = RunningAverage::<10>::new();
 
             loop {
                 let span = span!(Level::INFO, "Depth sensor cycle").entered();
 
-                let rst = depth.read_frame().context("Read depth frame");
+                let rst = sensor.read().context("Read depth frame");
 
-                match rst {
-                    Ok(frame) => {
-                        let res = tx_data.send(frame);
+                let reading = match rst {
+                    Ok((pressure, temperature)) => {
+                        let pressure = Mbar(filter.add_reading(pressure.0));
+                        let density = match settings.salinity {
+                            Some(salinity) => pressure::water_density(temperature, salinity),
+                            None => settings.fluid_density,
+                        };
 
-                        if res.is_err() {
-                            // Peer disconected
-                            return;
-                        }
+                        Reading::Frame(
+                            DepthMeasurement {
+                                depth: pressure::pressure_to_depth(
+                                    pressure,
+                                    density,
+                                    settings.sea_level,
+                                ),
+                                altitude: pressure::pressure_to_altitude(
+                                    pressure,
+                                    settings.sea_level,
+                                ),
+                                pressure,
+                            },
+                            WaterTemperature(temperature),
+                            density,
+                        )
                     }
                     Err(err) => {
                         let _ = errors.send(err);
+                        Reading::Failed
                     }
+                };
+
+                let res = tx_data.send(reading);
+                if res.is_err() {
+                    // Peer disconected
+                    return;
                 }
 
                 if let Ok(msg) = rx_msg.try_recv() {
                     match msg {
-                        Message::Settings(settings) => {
-                            depth.fluid_density = settings.fluid_density;
-                            depth.sea_level = settings.sea_level;
+                        Message::Settings(new_settings) => {
+                            settings = new_settings;
                         }
                         Message::Shutdown => return,
                     }
@@ -123,27 +224,106 @@ fn start_depth_thread(
     Ok(())
 }
 
-fn read_new_data(mut cmds: Commands, channels: Res<DepthChannels>, robot: Res<LocalRobot>) {
-    for (depth, temp) in channels.0.try_iter() {
-        // TODO: when we move this to a child entity, we will add the temperature measurement to
-        // that
-        cmds.entity(robot.entity).insert(depth);
-        let _ = temp;
+fn read_new_data(
+    mut cmds: Commands,
+    channels: Res<DepthChannels>,
+    robot: Res<LocalRobot>,
+    mut state: ResMut<DepthSensorState>,
+    mut calibration: ResMut<SeaLevelCalibration>,
+    mut settings: Query<&mut DepthSettings, With<LocalRobotMarker>>,
+) {
+    for reading in channels.0.try_iter() {
+        match reading {
+            Reading::Frame(depth, temperature, density) => {
+                state.last_reading = Instant::now();
+                state.failed_reads = 0;
+
+                if let Some(samples) = &mut calibration.0 {
+                    samples.push(depth.pressure);
+                }
+
+                // Replicates the density that follows the water temperature
+                for mut settings in &mut settings {
+                    if (settings.fluid_density - density).abs() > DENSITY_TOLERANCE {
+                        settings.fluid_density = density;
+                    }
+                }
+
+                cmds.entity(robot.entity).insert((depth, temperature));
+            }
+            Reading::Failed => {
+                state.failed_reads += 1;
+            }
+        }
+    }
+}
+
+fn update_health(
+    mut cmds: Commands,
+    state: Res<DepthSensorState>,
+    robot: Query<
+        (Entity, Option<&DepthSensorHealth>, Has<DepthMeasurement>),
+        With<LocalRobotMarker>,
+    >,
+) {
+    let Ok((robot, last_health, has_measurement)) = robot.get_single() else {
+        return;
+    };
+
+    let health = DepthSensorHealth {
+        sensor: state.sensor.to_owned(),
+        failed_reads: state.failed_reads,
+        stale: state.last_reading.elapsed() > STALE_TIMEOUT,
+    };
+
+    if health.stale && has_measurement {
+        warn!("Depth sensor stopped reporting");
+        cmds.entity(robot).remove::<DepthMeasurement>();
+    }
+
+    if last_health != Some(&health) {
+        cmds.entity(robot).insert(health);
     }
 }
 
 fn calibrate_sea_level(
-    mut cmds: Commands,
     mut events: EventReader<CalibrateSeaLevel>,
-    mut robot: Query<(&DepthMeasurement, &mut DepthSettings), With<LocalRobotMarker>>,
-) {
-    for _ in events.read() {
-        info!("Calibrating Sea Level");
+    mut robot: Query<(&mut DepthSettings, &DepthSensorHealth), With<LocalRobotMarker>>,
+    mut calibration: ResMut<SeaLevelCalibration>,
+) -> anyhow::Result<()> {
+    let Ok((mut settings, health)) = robot.get_single_mut() else {
+        events.clear();
+        return Ok(());
+    };
 
-        for (depth, mut settings) in &mut robot {
-            settings.sea_level = depth.pressure;
+    if !events.is_empty() {
+        events.clear();
+
+        if health.stale {
+            calibration.0 = None;
+            bail!("Cannot calibrate sea level, the depth sensor is not reporting");
         }
+
+        info!("Calibrating Sea Level");
+        calibration.0 = Some(Vec::with_capacity(CALIBRATION_SAMPLES));
     }
+
+    if health.stale && calibration.0.is_some() {
+        calibration.0 = None;
+        bail!("Sea level calibration failed, the depth sensor stopped reporting");
+    }
+
+    if let Some(samples) = calibration
+        .0
+        .take_if(|samples| samples.len() >= CALIBRATION_SAMPLES)
+    {
+        let sea_level = samples.iter().map(|it| it.0).sum::<f32>() / samples.len() as f32;
+        settings.sea_level = Mbar(sea_level);
+
+        info!("Calibrated sea level to {}", settings.sea_level);
+    }
+
+    Ok(())
 }
 
 fn listen_for_settings(
@@ -4,24 +4,26 @@ use std::{
 };
 
 use ahrs::{Ahrs, Madgwick};
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use bevy::{app::AppExit, prelude::*};
 use common::{
+    attitude,
     components::{
-        AccelerometerMeasurement, GyroMeasurement, MagnetometerMeasurement, Orientation,
-        TempertureMeasurement,
+        AccelerometerMeasurement, GyroMeasurement, LevelTrim, MagnetometerMeasurement, Orientation,
+        OrientationTarget, TempertureMeasurement,
     },
     error::{self, ErrorEvent, Errors},
-    events::ResetYaw,
+    events::{CalibrateLevel, ClearLevelTrim, ResetYaw},
+    types::units::Degrees,
 };
 use crossbeam::channel::{self, Receiver, Sender};
 use nalgebra::Vector3;
 use tracing::{span, Level};
 
 use crate::{
-    config::RobotConfig,
+    config::{ConfigRotation, RobotConfig},
     peripheral::{icm20602::Icm20602, mmc5983::Mcc5983},
-    plugins::core::robot::LocalRobot,
+    plugins::core::robot::{LocalRobot, LocalRobotMarker},
 };
 
 pub struct OrientationPlugin;
@@ -29,14 +31,20 @@ pub struct OrientationPlugin;
 impl Plugin for OrientationPlugin {
     fn build(&self, app: &mut App) {
         // let orientation_offset = Quat::from_euler(EulerRot::YXZ, 180.0f32.to_radians(), 0.0, 0.0);
-        let orientation_offset = app.world().resource::<RobotConfig>().imu_offset.flatten();
+        let orientation_offset = app.world().resource::<RobotConfig>().imu_frame();
         let mut madgwick = Madgwick::new(1.0 / 1000.0, 0.041);
         madgwick.quat = orientation_offset.into();
 
         app.insert_resource(OrientationOffset(orientation_offset));
         app.insert_resource(MadgwickFilter(madgwick));
 
-        app.add_systems(Startup, start_inertial_thread.pipe(error::handle_errors));
+        app.add_systems(
+            Startup,
+            (
+                start_inertial_thread.pipe(error::handle_errors),
+                setup_level_trim,
+            ),
+        );
         app.add_systems(
             PreUpdate,
             (
@@ -44,6 +52,7 @@ impl Plugin for OrientationPlugin {
                 read_new_data.run_if(resource_exists::<InertialChannels>),
             ),
         );
+        app.add_systems(Update, calibrate_level.pipe(error::handle_errors));
         app.add_systems(Last, shutdown.run_if(resource_exists::<InertialChannels>));
     }
 }
@@ -64,6 +73,7 @@ struct InertialChannels(
 #[derive(Resource)]
 struct MadgwickFilter(Madgwick<f32>);
 
+/// Rotation from the imu's frame to the robot's, see `RobotConfig::imu_frame`
 #[derive(Resource)]
 struct OrientationOffset(Quat);
 
@@ -186,12 +196,14 @@ fn read_new_data(
 
         let quat: glam::Quat = madgwick_filter.0.quat.into();
         let orientation = Orientation(quat * orientation_offset.0.inverse());
+        // The compass is not fused yet so this is relative to the last yaw reset
+        let heading = orientation.heading();
 
         let inertial = *inertial.last().unwrap();
         let magnetic = *magnetic.last().unwrap();
 
         cmds.entity(robot.entity)
-            .insert((orientation, inertial, magnetic));
+            .insert((orientation, heading, inertial, magnetic));
     }
 }
 
@@ -207,6 +219,79 @@ fn reset_yaw_handler(
     }
 }
 
+fn setup_level_trim(mut cmds: Commands, robot: Res<LocalRobot>, config: Res<RobotConfig>) {
+    cmds.entity(robot.entity)
+        .insert(level_trim(config.level_trim.flatten()));
+}
+
+/// Trims the robot's current pitch and roll out of its orientation and saves the trim to the config
+fn calibrate_level(
+    mut cmds: Commands,
+    mut calibrate_events: EventReader<CalibrateLevel>,
+    mut clear_events: EventReader<ClearLevelTrim>,
+    mut orientation_offset: ResMut<OrientationOffset>,
+    mut config: ResMut<RobotConfig>,
+    robot: Query<
+        (Entity, Option<&Orientation>, Option<&OrientationTarget>),
+        With<LocalRobotMarker>,
+    >,
+) -> anyhow::Result<()> {
+    let calibrate = !calibrate_events.is_empty();
+    let clear = !clear_events.is_empty();
+    calibrate_events.clear();
+    clear_events.clear();
+
+    if !calibrate && !clear {
+        return Ok(());
+    }
+
+    let Ok((robot, orientation, orientation_target)) = robot.get_single() else {
+        return Ok(());
+    };
+
+    let old_trim = config.level_trim.flatten();
+    let new_trim = if calibrate {
+        let Some(&Orientation(orientation)) = orientation else {
+            bail!("Cannot calibrate level, no orientation");
+        };
+
+        // The pitch and roll left once yaw is removed is what reads as level from now on
+        let tilt = attitude::yaw_rotation(orientation).inverse() * orientation;
+        (tilt * old_trim).normalize()
+    } else {
+        Quat::IDENTITY
+    };
+
+    let mut new_config = config.clone();
+    new_config.level_trim = ConfigRotation::from_quat(new_trim);
+    new_config.save()?;
+    *config = new_config;
+
+    orientation_offset.0 = config.imu_frame();
+
+    // Keeps holding the same physical attitude now that the body frame moved
+    if let Some(&OrientationTarget(target)) = orientation_target {
+        let target = (target * old_trim * new_trim.inverse()).normalize();
+        cmds.entity(robot).insert(OrientationTarget(target));
+    }
+
+    let trim = level_trim(new_trim);
+    cmds.entity(robot).insert(trim);
+
+    info!("Trimmed level to {} pitch, {} roll", trim.pitch, trim.roll);
+
+    Ok(())
+}
+
+fn level_trim(trim: Quat) -> LevelTrim {
+    let (_, pitch, roll) = trim.to_euler(EulerRot::ZXY);
+
+    LevelTrim {
+        pitch: Degrees(pitch.to_degrees()),
+        roll: Degrees(roll.to_degrees()),
+    }
+}
+
 fn shutdown(channels: Res<InertialChannels>, mut exit: EventReader<AppExit>) {
     for _event in exit.read() {
         let _ = channels.1.send(());
@@ -72,7 +72,7 @@ fn start_power_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result
 
                 match rst {
                     Ok(value) => {
-                        let value = 11.0 * value;
+                        let value = volts(value);
                         let res = tx_data.send(PowerEvent::Voltage(value));
 
                         if res.is_err() {
@@ -98,7 +98,7 @@ fn start_power_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result
 
                 match rst {
                     Ok(value) => {
-                        let value = 37.8788 * (value - 0.33);
+                        let value = amps(value);
                         let res = tx_data.send(PowerEvent::Amperage(value));
 
                         if res.is_err() {
@@ -127,6 +127,16 @@ fn start_power_thread(mut cmds: Commands, errors: Res<Errors>) -> anyhow::Result
     Ok(())
 }
 
+/// Converts a reading of the power sense module's voltage channel to volts
+pub fn volts(value: f32) -> f32 {
+    11.0 * value
+}
+
+/// Converts a reading of the power sense module's current channel to amps
+pub fn amps(value: f32) -> f32 {
+    37.8788 * (value - 0.33)
+}
+
 fn read_new_data(mut cmds: Commands, channels: Res<PowerChannels>, robot: Res<LocalRobot>) {
     for event in channels.0.try_iter() {
         match event {
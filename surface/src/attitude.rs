@@ -15,7 +15,7 @@ use common::components::{Orientation, OrientationTarget, Robot, Thrusters};
 use egui::TextureId;
 use motor_math::{glam::ThrusterGlam, x3d::X3dMotorId, Direction, ErasedMotorId, MotorConfig};
 
-use crate::DARK_MODE;
+use crate::settings::SurfaceSettings;
 
 const RENDER_LAYERS: RenderLayers = RenderLayers::layer(1);
 
@@ -24,7 +24,14 @@ pub struct AttitudePlugin;
 impl Plugin for AttitudePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup)
-            .add_systems(Update, (update_motor_conf, rotator_system))
+            .add_systems(
+                Update,
+                (
+                    update_motor_conf,
+                    rotator_system,
+                    apply_theme_lighting.run_if(resource_changed::<SurfaceSettings>),
+                ),
+            )
             .insert_gizmo_config(
                 AttitudeGizmo,
                 GizmoConfig {
@@ -44,14 +51,14 @@ pub struct OrientationDisplay(pub Handle<Image>, pub TextureId);
 struct OrientationDisplayMarker;
 #[derive(Component)]
 struct MotorMarker(ErasedMotorId);
+#[derive(Component)]
+struct AttitudeLight;
 
 fn setup(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut egui_context: EguiContexts,
 
-    mut ambient_light: ResMut<AmbientLight>,
-
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
@@ -88,20 +95,16 @@ fn setup(
 
     let image_handle = images.add(image);
 
-    // light
+    // light, brightness is set by `apply_theme_lighting`
     commands.spawn((
         PointLight {
             shadows_enabled: true,
-            intensity: if DARK_MODE { 1_000_000.0 } else { 4_000_000.0 },
             ..default()
         },
         Transform::from_xyz(4.0, 4.0, 8.0),
+        AttitudeLight,
         RENDER_LAYERS,
     ));
-    // FIXME: This absolutelly should not be here
-    if !DARK_MODE {
-        ambient_light.brightness *= 7.0;
-    }
 
     // camera
     commands.spawn((
@@ -292,3 +295,19 @@ fn rotator_system(
         }
     }
 }
+
+fn apply_theme_lighting(
+    mut base_ambient: Local<Option<f32>>,
+    settings: Res<SurfaceSettings>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut lights: Query<&mut PointLight, With<AttitudeLight>>,
+) {
+    let palette = settings.theme.palette();
+    // FIXME: The ambient light is global, this absolutelly should not be here
+    let base = *base_ambient.get_or_insert(ambient_light.brightness);
+
+    ambient_light.brightness = base * palette.ambient_scale;
+    for mut light in &mut lights {
+        light.intensity = palette.light_intensity;
+    }
+}
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use ahash::HashMap;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    behavior::{BehaviorPlugin, TickBehaviors},
+    components::{BehaviorStatus, BehaviorTree, CameraDefinition},
+    ecs_sync::ForignOwned,
+    types::behavior::{BehaviorAction, NodeState},
+};
+use egui::{CollapsingHeader, Color32, RichText};
+
+use crate::{
+    layout::DockableWindow, ui::BehaviorsUi, video_pipelines::VideoPipelines,
+    video_stream::VideoProcessorFactory,
+};
+
+pub struct AutonomyPlugin;
+
+impl Plugin for AutonomyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(BehaviorPlugin).add_systems(
+            Update,
+            (
+                run_pipelines.after(TickBehaviors),
+                behaviors_ui.run_if(resource_exists::<BehaviorsUi>),
+            ),
+        );
+    }
+}
+
+/// A `RunPipeline` action that has a camera
+struct RunningPipeline {
+    camera: Entity,
+    pipeline: String,
+    started: Duration,
+}
+
+fn run_pipelines(
+    mut running: Local<HashMap<(Entity, usize), RunningPipeline>>,
+
+    mut cmds: Commands,
+    time: Res<Time>,
+    pipelines: Res<VideoPipelines>,
+    mut trees: Query<(Entity, &BehaviorTree, &mut BehaviorStatus), Without<ForignOwned>>,
+    cameras: Query<(Entity, &Name, Option<&VideoProcessorFactory>), With<CameraDefinition>>,
+) {
+    let now = time.elapsed();
+
+    // Stop the pipelines of actions that were halted
+    running.retain(|(tree, node), pipeline| {
+        let still_running = trees
+            .get(*tree)
+            .is_ok_and(|(_, _, status)| *status.state(*node) == NodeState::Running);
+
+        if !still_running {
+            let owned = cameras.get(pipeline.camera).is_ok_and(|(_, _, factory)| {
+                factory.is_some_and(|it| it.name == pipeline.pipeline)
+            });
+            if owned {
+                cmds.entity(pipeline.camera)
+                    .remove::<VideoProcessorFactory>();
+            }
+        }
+
+        still_running
+    });
+
+    for (entity, tree, mut status) in &mut trees {
+        let actions = status
+            .running_actions(tree)
+            .map(|(node, action)| (node, action.clone()))
+            .collect::<Vec<_>>();
+
+        for (node, action) in actions {
+            let (name, camera_name, seconds) = match action {
+                BehaviorAction::RunPipeline {
+                    pipeline,
+                    camera,
+                    seconds,
+                } => (pipeline, camera, seconds),
+                // Motion is up to whichever app drives the robot
+                BehaviorAction::GoToPose { .. } => {
+                    status.finish(node, Err("The surface can't drive the robot".to_owned()));
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if let Some(pipeline) = running.get(&(entity, node)) {
+                let factory = cameras.get(pipeline.camera).ok().and_then(|it| it.2);
+
+                if factory.is_none_or(|it| it.name != pipeline.pipeline) {
+                    // Stopped by the pilot or replaced with another pipeline
+                    status.finish(node, Ok(()));
+                } else if seconds
+                    .and_then(|it| Duration::try_from_secs_f32(it).ok())
+                    .is_some_and(|it| now - pipeline.started >= it)
+                {
+                    cmds.entity(pipeline.camera)
+                        .remove::<VideoProcessorFactory>();
+                    status.finish(node, Ok(()));
+                }
+
+                continue;
+            }
+
+            let Some((camera, ..)) = cameras.iter().find(|it| it.1.as_str() == camera_name) else {
+                status.finish(node, Err(format!("No camera named {camera_name}")));
+                continue;
+            };
+            let Some(pipeline) = pipelines.0.iter().find(|it| it.name == name) else {
+                status.finish(node, Err(format!("No pipeline named {name}")));
+                continue;
+            };
+
+            cmds.entity(camera).insert(pipeline.factory.clone());
+            running.insert(
+                (entity, node),
+                RunningPipeline {
+                    camera,
+                    pipeline: name,
+                    started: now,
+                },
+            );
+        }
+    }
+}
+
+fn behaviors_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    trees: Query<(
+        Entity,
+        &BehaviorTree,
+        Option<&BehaviorStatus>,
+        Has<ForignOwned>,
+    )>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Behaviors")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Behaviors"), |ui| {
+            if trees.is_empty() {
+                ui.label("No behavior trees");
+            }
+
+            for (entity, tree, status, remote) in &trees {
+                let owner = if remote { "Remote" } else { "Local" };
+
+                CollapsingHeader::new(format!("{} ({owner})", tree.name))
+                    .id_salt(entity)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        // Invalid trees can have cycles
+                        if let Err(err) = tree.validate() {
+                            ui.label(RichText::new(format!("Invalid: {err}")).color(Color32::RED));
+                        } else {
+                            behavior_node(ui, entity, tree, status, tree.root);
+                        }
+
+                        // Trees are only controlled by the app running them
+                        if remote {
+                            return;
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Restart").clicked() {
+                                cmds.entity(entity).insert(BehaviorStatus::default());
+                            }
+                            if status.is_some() && ui.button("Stop").clicked() {
+                                cmds.entity(entity).remove::<BehaviorStatus>();
+                            }
+                        });
+                    });
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<BehaviorsUi>();
+    }
+}
+
+fn behavior_node(
+    ui: &mut egui::Ui,
+    entity: Entity,
+    tree: &BehaviorTree,
+    status: Option<&BehaviorStatus>,
+    node: usize,
+) {
+    let definition = &tree.nodes[node];
+    let state = status.map(|it| it.state(node)).unwrap_or(&NodeState::Idle);
+
+    let (state, color) = match state {
+        NodeState::Idle => ("Idle".to_owned(), Color32::GRAY),
+        NodeState::Running => ("Running".to_owned(), Color32::YELLOW),
+        NodeState::Succeeded => ("Succeeded".to_owned(), Color32::GREEN),
+        NodeState::Failed(reason) => (format!("Failed: {reason}"), Color32::RED),
+    };
+    let text = RichText::new(format!(
+        "{} [{}] {state}",
+        definition.name,
+        definition.kind.name()
+    ))
+    .color(color);
+
+    if definition.children.is_empty() {
+        ui.label(text);
+    } else {
+        CollapsingHeader::new(text)
+            .id_salt((entity, node))
+            .default_open(true)
+            .show(ui, |ui| {
+                for &child in &definition.children {
+                    behavior_node(ui, entity, tree, status, child);
+                }
+            });
+    }
+}
@@ -0,0 +1,477 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use leafwing_input_manager::{
+    input_map::InputMap,
+    prelude::{
+        AxislikeChord, MouseMoveAxis, MouseScrollAxis, VirtualAxis, WithAxisProcessingPipelineExt,
+    },
+    Actionlike, InputControlKind,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hid::HidPanel,
+    input::{Action, LevelingType, MacroSlot},
+    input_shaping::InputShaping,
+    macros::InputMacro,
+    settings::config_path,
+};
+
+pub const BINDINGS_FILE: &str = "bindings.toml";
+pub const DEFAULT_PROFILE: &str = "Default";
+
+/// Axes checked when capturing a new axis binding
+const CAPTURE_AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::LeftZ,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+    GamepadAxis::RightZ,
+];
+const CAPTURE_AXIS_THRESHOLD: f32 = 0.6;
+
+/// Scales mouse movement in pixels to roughly match a full stick deflection
+const MOUSE_DRAG_SENSITIVITY: f32 = 0.05;
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BindingCapture>()
+            .init_resource::<KeyboardPiloting>()
+            .add_systems(PreStartup, load_profiles)
+            .add_systems(
+                Update,
+                (
+                    update_keyboard_piloting,
+                    capture_binding,
+                    save_profiles.after(capture_binding),
+                ),
+            );
+    }
+}
+
+/// All binding profiles known to the control station, persisted to `bindings.toml`
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct BindingProfiles {
+    pub active: String,
+    pub profiles: BTreeMap<String, BindingProfile>,
+    /// Robot name to profile name, takes priority over `active`
+    #[serde(default)]
+    pub robot_overrides: BTreeMap<String, String>,
+}
+
+impl Default for BindingProfiles {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_PROFILE.to_owned(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_owned(), BindingProfile::default())]),
+            robot_overrides: BTreeMap::new(),
+        }
+    }
+}
+
+impl BindingProfiles {
+    /// The name of the profile used for the robot named `robot`
+    pub fn profile_name_for(&self, robot: &str) -> &str {
+        self.robot_overrides
+            .get(robot)
+            .filter(|it| self.profiles.contains_key(*it))
+            .unwrap_or(&self.active)
+    }
+
+    pub fn profile_for(&self, robot: &str) -> BindingProfile {
+        self.profiles
+            .get(self.profile_name_for(robot))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BindingProfile {
+    pub bindings: Vec<Binding>,
+    #[serde(default)]
+    pub shaping: InputShaping,
+    #[serde(default)]
+    pub macros: Vec<InputMacro>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub action: Action,
+    pub input: BindingInput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BindingInput {
+    Button(GamepadButton),
+    Axis(GamepadAxis),
+    Key(KeyCode),
+    KeyAxis {
+        negative: KeyCode,
+        positive: KeyCode,
+    },
+    MouseScroll,
+    MouseDragX(MouseButton),
+    MouseDragY(MouseButton),
+}
+
+impl BindingInput {
+    pub fn name(&self) -> String {
+        match self {
+            BindingInput::Button(button) => format!("{button:?}"),
+            BindingInput::Axis(axis) => format!("{axis:?}"),
+            BindingInput::Key(key) => format!("Key {key:?}"),
+            BindingInput::KeyAxis { negative, positive } => {
+                format!("Keys {negative:?}/{positive:?}")
+            }
+            BindingInput::MouseScroll => "Mouse Scroll".to_owned(),
+            BindingInput::MouseDragX(button) => format!("{button:?} Mouse Drag X"),
+            BindingInput::MouseDragY(button) => format!("{button:?} Mouse Drag Y"),
+        }
+    }
+}
+
+impl Binding {
+    const fn new(action: Action, input: BindingInput) -> Self {
+        Self { action, input }
+    }
+}
+
+impl Default for BindingProfile {
+    fn default() -> Self {
+        use BindingInput::*;
+
+        Self {
+            bindings: vec![
+                Binding::new(Action::Disarm, Button(GamepadButton::Select)),
+                Binding::new(Action::Arm, Button(GamepadButton::Start)),
+                Binding::new(Action::Disarm, Key(KeyCode::Space)),
+                Binding::new(Action::Arm, Key(KeyCode::Enter)),
+                Binding::new(Action::CycleCameraLayout, Key(KeyCode::F1)),
+                Binding::new(Action::CycleMasterCamera, Key(KeyCode::F2)),
+                Binding::new(Action::CaptureStill, Key(KeyCode::F3)),
+                Binding::new(Action::CaptureBundle, Key(KeyCode::F4)),
+                Binding::new(
+                    Action::ToggleLeveling(LevelingType::Upright),
+                    Button(GamepadButton::North),
+                ),
+                Binding::new(
+                    Action::ToggleLeveling(LevelingType::Inverted),
+                    Button(GamepadButton::South),
+                ),
+                Binding::new(Action::ToggleDepthHold, Button(GamepadButton::East)),
+                Binding::new(Action::TakePhotoSphereImage, Button(GamepadButton::West)),
+                Binding::new(Action::Yaw, Axis(GamepadAxis::LeftStickX)),
+                Binding::new(Action::Surge, Axis(GamepadAxis::LeftStickY)),
+                Binding::new(Action::Sway, Axis(GamepadAxis::RightStickX)),
+                Binding::new(Action::Heave, Axis(GamepadAxis::RightStickY)),
+                Binding::new(Action::ServoInverted, Button(GamepadButton::LeftTrigger)),
+                Binding::new(Action::Servo, Button(GamepadButton::RightTrigger)),
+                Binding::new(Action::Pitch, Button(GamepadButton::RightTrigger2)),
+                Binding::new(Action::PitchInverted, Button(GamepadButton::LeftTrigger2)),
+                Binding::new(Action::ServoCenter, Button(GamepadButton::DPadUp)),
+                Binding::new(Action::SwitchServo, Button(GamepadButton::DPadRight)),
+                Binding::new(Action::SwitchServoInverted, Button(GamepadButton::DPadLeft)),
+                Binding::new(Action::ToggleRobotMode, Button(GamepadButton::DPadDown)),
+                Binding::new(Action::ToggleRobotMode, Button(GamepadButton::Mode)),
+                Binding::new(
+                    Action::RunMacro(MacroSlot::One),
+                    Button(GamepadButton::LeftThumb),
+                ),
+                Binding::new(
+                    Action::ToggleHeadingAssist,
+                    Button(GamepadButton::RightThumb),
+                ),
+            ],
+            shaping: InputShaping::default(),
+            macros: vec![InputMacro::level_and_hold()],
+        }
+    }
+}
+
+impl BindingProfile {
+    /// The keyboard and mouse scheme layered on top of the pilot's bindings when keyboard
+    /// piloting is active
+    pub fn keyboard() -> Self {
+        use BindingInput::*;
+
+        Self {
+            bindings: vec![
+                Binding::new(
+                    Action::Surge,
+                    KeyAxis {
+                        negative: KeyCode::KeyS,
+                        positive: KeyCode::KeyW,
+                    },
+                ),
+                Binding::new(
+                    Action::Sway,
+                    KeyAxis {
+                        negative: KeyCode::KeyA,
+                        positive: KeyCode::KeyD,
+                    },
+                ),
+                Binding::new(
+                    Action::Heave,
+                    KeyAxis {
+                        negative: KeyCode::KeyQ,
+                        positive: KeyCode::KeyE,
+                    },
+                ),
+                Binding::new(
+                    Action::Yaw,
+                    KeyAxis {
+                        negative: KeyCode::ArrowLeft,
+                        positive: KeyCode::ArrowRight,
+                    },
+                ),
+                Binding::new(Action::Pitch, Key(KeyCode::ArrowUp)),
+                Binding::new(Action::PitchInverted, Key(KeyCode::ArrowDown)),
+                Binding::new(Action::Yaw, MouseDragX(MouseButton::Right)),
+                Binding::new(Action::PitchAxis, MouseDragY(MouseButton::Right)),
+                Binding::new(Action::DepthTargetAxis, MouseScroll),
+                Binding::new(Action::ToggleDepthHold, Key(KeyCode::KeyH)),
+                Binding::new(Action::ToggleHeadingAssist, Key(KeyCode::KeyG)),
+                Binding::new(Action::ToggleBottomLock, Key(KeyCode::KeyB)),
+                Binding::new(
+                    Action::ToggleLeveling(LevelingType::Upright),
+                    Key(KeyCode::KeyL),
+                ),
+                Binding::new(Action::ToggleRobotMode, Key(KeyCode::KeyM)),
+                Binding::new(Action::PreviousRobotMode, Key(KeyCode::KeyN)),
+                Binding::new(Action::ServoInverted, Key(KeyCode::KeyZ)),
+                Binding::new(Action::Servo, Key(KeyCode::KeyX)),
+                Binding::new(Action::SwitchServo, Key(KeyCode::KeyC)),
+            ],
+            shaping: InputShaping::default(),
+            macros: Vec::new(),
+        }
+    }
+
+    pub fn to_input_map(&self) -> InputMap<Action> {
+        let mut input_map = InputMap::default();
+
+        for binding in &self.bindings {
+            match (binding.action.input_control_kind(), binding.input) {
+                (InputControlKind::Axis, BindingInput::Axis(axis)) => {
+                    input_map.insert_axis(binding.action, axis);
+                }
+                (InputControlKind::Button, BindingInput::Button(button)) => {
+                    input_map.insert(binding.action, button);
+                }
+                (InputControlKind::Button, BindingInput::Key(key)) => {
+                    input_map.insert(binding.action, key);
+                }
+                (InputControlKind::Axis, BindingInput::KeyAxis { negative, positive }) => {
+                    input_map.insert_axis(binding.action, VirtualAxis::new(negative, positive));
+                }
+                (InputControlKind::Axis, BindingInput::MouseScroll) => {
+                    input_map.insert_axis(binding.action, MouseScrollAxis::Y);
+                }
+                (InputControlKind::Axis, BindingInput::MouseDragX(button)) => {
+                    input_map.insert_axis(
+                        binding.action,
+                        AxislikeChord::new(
+                            button,
+                            MouseMoveAxis::X.sensitivity(MOUSE_DRAG_SENSITIVITY),
+                        ),
+                    );
+                }
+                (InputControlKind::Axis, BindingInput::MouseDragY(button)) => {
+                    input_map.insert_axis(
+                        binding.action,
+                        AxislikeChord::new(
+                            button,
+                            MouseMoveAxis::Y.sensitivity(MOUSE_DRAG_SENSITIVITY),
+                        ),
+                    );
+                }
+                (kind, input) => {
+                    warn!(
+                        "Cannot bind {input:?} to {:?} which expects {kind:?} input",
+                        binding.action
+                    );
+                }
+            }
+        }
+
+        input_map
+    }
+
+    pub fn bindings_for(&self, action: Action) -> impl Iterator<Item = (usize, &Binding)> {
+        self.bindings
+            .iter()
+            .enumerate()
+            .filter(move |(_, it)| it.action == action)
+    }
+}
+
+/// Whether the keyboard and mouse scheme is layered onto the pilot's bindings
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyboardPiloting {
+    pub mode: KeyboardPilotingMode,
+    /// Updated every frame from `mode` and the connected gamepads
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyboardPilotingMode {
+    /// Enabled while no gamepad is connected
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+impl KeyboardPilotingMode {
+    pub const ALL: [KeyboardPilotingMode; 3] = [
+        KeyboardPilotingMode::Auto,
+        KeyboardPilotingMode::On,
+        KeyboardPilotingMode::Off,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyboardPilotingMode::Auto => "Auto",
+            KeyboardPilotingMode::On => "On",
+            KeyboardPilotingMode::Off => "Off",
+        }
+    }
+}
+
+pub fn update_keyboard_piloting(
+    mut piloting: ResMut<KeyboardPiloting>,
+    gamepads: Query<(), (With<Gamepad>, Without<HidPanel>)>,
+) {
+    let active = match piloting.mode {
+        KeyboardPilotingMode::Auto => gamepads.is_empty(),
+        KeyboardPilotingMode::On => true,
+        KeyboardPilotingMode::Off => false,
+    };
+
+    if piloting.active != active {
+        info!("Keyboard piloting active: {active}");
+        piloting.active = active;
+    }
+}
+
+/// Set by the bindings window to bind the next pressed input to `action` in `profile`
+#[derive(Resource, Debug, Clone, Default)]
+pub struct BindingCapture(pub Option<(String, Action)>);
+
+fn load_profiles(mut cmds: Commands) {
+    let res: anyhow::Result<BindingProfiles> = try {
+        let profiles = fs::read_to_string(config_path(BINDINGS_FILE)).context("Read bindings")?;
+        toml::from_str(&profiles).context("Parse bindings")?
+    };
+
+    let mut profiles = match res {
+        Ok(profiles) => profiles,
+        Err(err) => {
+            warn!("Using default bindings: {err:?}");
+            BindingProfiles::default()
+        }
+    };
+
+    // The file may have been edited by hand
+    if !profiles.profiles.contains_key(&profiles.active) {
+        let active = profiles
+            .profiles
+            .keys()
+            .next()
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_owned());
+        warn!(
+            "Active binding profile {:?} does not exist, using {active:?}",
+            profiles.active
+        );
+
+        profiles.profiles.entry(active.clone()).or_default();
+        profiles.active = active;
+    }
+
+    cmds.insert_resource(profiles);
+}
+
+fn save_profiles(profiles: Res<BindingProfiles>) {
+    if !profiles.is_changed() || profiles.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*profiles) else {
+        error!("Could not serialize bindings");
+        return;
+    };
+
+    let res = fs::write(config_path(BINDINGS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write bindings: {err:?}");
+    }
+}
+
+fn capture_binding(
+    mut capture: ResMut<BindingCapture>,
+    mut profiles: ResMut<BindingProfiles>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+) {
+    let Some((profile, action)) = capture.0.clone() else {
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        capture.0 = None;
+        return;
+    }
+
+    let input = match action.input_control_kind() {
+        InputControlKind::Axis => gamepads
+            .iter()
+            .flat_map(|gamepad| {
+                CAPTURE_AXES.into_iter().filter(|axis| {
+                    gamepad
+                        .get(*axis)
+                        .is_some_and(|it| it.abs() > CAPTURE_AXIS_THRESHOLD)
+                })
+            })
+            .map(BindingInput::Axis)
+            .next(),
+        InputControlKind::Button => keys
+            .get_just_pressed()
+            .map(|key| BindingInput::Key(*key))
+            .chain(
+                gamepads
+                    .iter()
+                    .flat_map(|gamepad| gamepad.get_just_pressed())
+                    .map(|button| BindingInput::Button(*button)),
+            )
+            .next(),
+        kind => {
+            error!("Capturing {kind:?} bindings is not supported");
+            capture.0 = None;
+            return;
+        }
+    };
+
+    let Some(input) = input else {
+        return;
+    };
+
+    capture.0 = None;
+
+    let Some(profile) = profiles.profiles.get_mut(&profile) else {
+        warn!("Binding profile {profile} no longer exists");
+        return;
+    };
+
+    let binding = Binding { action, input };
+    if !profile.bindings.contains(&binding) {
+        info!("Bound {} to {action:?}", input.name());
+        profile.bindings.push(binding);
+    }
+}
@@ -0,0 +1,484 @@
+//! Alert sounds and spoken callouts for key events so the pilot doesn't have to watch the HUD
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::{audio::Pitch, prelude::*};
+use bevy_egui::EguiContexts;
+use common::{
+    components::{Armed, DepthTarget, Robot},
+    events::{Alarm, AlarmKind},
+};
+use crossbeam::channel::{self, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+
+use crate::{layout::DockableWindow, settings::config_path, ui::CalloutsUi};
+
+pub const CALLOUTS_FILE: &str = "callouts.toml";
+
+/// Gap between the tones of a sound
+const TONE_GAP: Duration = Duration::from_millis(60);
+/// Scrolling the depth target changes it every frame, only the value it settles on is spoken
+const DEPTH_SETTLE_TIME: Duration = Duration::from_millis(1500);
+/// Callouts waiting to be spoken, new callouts are dropped once this many are queued
+const MAX_QUEUED_SPEECH: usize = 3;
+
+pub struct CalloutPlugin;
+
+impl Plugin for CalloutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Callout>()
+            .init_resource::<TonePlayer>()
+            .add_systems(PreStartup, (load_settings, start_speech))
+            .add_systems(
+                Update,
+                (
+                    (arming_callouts, depth_hold_callouts, alarm_callouts),
+                    play_callouts,
+                    play_tones,
+                )
+                    .chain(),
+            )
+            .add_systems(Update, callouts_ui.run_if(resource_exists::<CalloutsUi>))
+            .add_systems(Last, write_settings);
+    }
+}
+
+/// Each class of event has its own sound and can be spoken or not
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CalloutClass {
+    Arming,
+    DepthHold,
+    LowBattery,
+    /// Critical alarms other than low battery
+    Alarm,
+    /// Errors reported by the robot
+    Error,
+}
+
+impl CalloutClass {
+    pub const ALL: [CalloutClass; 5] = [
+        CalloutClass::Arming,
+        CalloutClass::DepthHold,
+        CalloutClass::LowBattery,
+        CalloutClass::Alarm,
+        CalloutClass::Error,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CalloutClass::Arming => "Arming",
+            CalloutClass::DepthHold => "Depth Hold",
+            CalloutClass::LowBattery => "Low Battery",
+            CalloutClass::Alarm => "Alarms",
+            CalloutClass::Error => "Errors",
+        }
+    }
+}
+
+/// The built in alert sounds, generated as tones so no audio assets need to be shipped
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlertSound {
+    None,
+    #[default]
+    Beep,
+    DoubleBeep,
+    Chime,
+    Falling,
+    Siren,
+}
+
+impl AlertSound {
+    pub const ALL: [AlertSound; 6] = [
+        AlertSound::None,
+        AlertSound::Beep,
+        AlertSound::DoubleBeep,
+        AlertSound::Chime,
+        AlertSound::Falling,
+        AlertSound::Siren,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertSound::None => "None",
+            AlertSound::Beep => "Beep",
+            AlertSound::DoubleBeep => "Double Beep",
+            AlertSound::Chime => "Chime",
+            AlertSound::Falling => "Falling",
+            AlertSound::Siren => "Siren",
+        }
+    }
+
+    /// Frequency in hz and length in ms of each tone, played one after another
+    pub fn tones(&self) -> &'static [(f32, u64)] {
+        match self {
+            AlertSound::None => &[],
+            AlertSound::Beep => &[(880.0, 150)],
+            AlertSound::DoubleBeep => &[(880.0, 120), (880.0, 120)],
+            AlertSound::Chime => &[(660.0, 150), (990.0, 250)],
+            AlertSound::Falling => &[(990.0, 150), (660.0, 250)],
+            AlertSound::Siren => &[(880.0, 200), (660.0, 200), (880.0, 200), (660.0, 200)],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClassCallout {
+    pub sound: AlertSound,
+    /// Speak the callout's message after the sound
+    pub speak: bool,
+}
+
+/// Persisted to `callouts.toml`
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CalloutSettings {
+    /// Nothing is played while this is off
+    pub enabled: bool,
+    /// Program and arguments the callout's text is appended to
+    pub speech_command: Vec<String>,
+    pub classes: BTreeMap<CalloutClass, ClassCallout>,
+}
+
+impl Default for CalloutSettings {
+    fn default() -> Self {
+        let speech_command = if cfg!(target_os = "macos") {
+            vec!["say".to_owned()]
+        } else {
+            vec!["espeak-ng".to_owned()]
+        };
+
+        let callout = |sound, speak| ClassCallout { sound, speak };
+
+        Self {
+            enabled: false,
+            speech_command,
+            classes: BTreeMap::from([
+                (CalloutClass::Arming, callout(AlertSound::Chime, true)),
+                (CalloutClass::DepthHold, callout(AlertSound::Beep, true)),
+                (CalloutClass::LowBattery, callout(AlertSound::Siren, true)),
+                (CalloutClass::Alarm, callout(AlertSound::Siren, true)),
+                // The robot can report errors in bursts
+                (CalloutClass::Error, callout(AlertSound::None, false)),
+            ]),
+        }
+    }
+}
+
+impl CalloutSettings {
+    pub fn class(&self, class: CalloutClass) -> ClassCallout {
+        self.classes.get(&class).copied().unwrap_or_default()
+    }
+}
+
+/// Plays the class's sound and speaks `message` if the class is configured to
+#[derive(Event, Debug, Clone)]
+pub struct Callout {
+    pub class: CalloutClass,
+    pub message: String,
+}
+
+/// Tones waiting for their turn
+#[derive(Resource, Default)]
+struct TonePlayer {
+    queue: Vec<(Instant, Pitch)>,
+}
+
+/// Runs the speech command off the main thread, callouts are spoken one at a time
+#[derive(Resource)]
+struct Speech(Sender<(Vec<String>, String)>);
+
+fn load_settings(mut cmds: Commands) {
+    let res: anyhow::Result<CalloutSettings> = try {
+        let settings =
+            fs::read_to_string(config_path(CALLOUTS_FILE)).context("Read callout settings")?;
+        toml::from_str(&settings).context("Parse callout settings")?
+    };
+
+    let settings = match res {
+        Ok(settings) => settings,
+        Err(err) => {
+            warn!("Using default callout settings: {err:?}");
+            CalloutSettings::default()
+        }
+    };
+
+    cmds.insert_resource(settings);
+}
+
+fn write_settings(settings: Res<CalloutSettings>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*settings) else {
+        error!("Could not serialize callout settings");
+        return;
+    };
+
+    let res = fs::write(config_path(CALLOUTS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write callout settings: {err:?}");
+    }
+}
+
+fn start_speech(mut cmds: Commands) {
+    let (tx, rx) = channel::bounded::<(Vec<String>, String)>(MAX_QUEUED_SPEECH);
+
+    thread::spawn(move || {
+        for (command, text) in rx {
+            let Some((program, args)) = command.split_first() else {
+                continue;
+            };
+
+            let res = Command::new(program).args(args).arg(&text).status();
+            match res {
+                Ok(status) if !status.success() => warn!("Speech command exited with {status}"),
+                Ok(_) => {}
+                Err(err) => warn!("Could not run speech command {program}: {err}"),
+            }
+        }
+    });
+
+    cmds.insert_resource(Speech(tx));
+}
+
+fn arming_callouts(robots: Query<Ref<Armed>, With<Robot>>, mut callouts: EventWriter<Callout>) {
+    for armed in &robots {
+        // Don't announce the state the robot connected in
+        if !armed.is_changed() || armed.is_added() {
+            continue;
+        }
+
+        let message = match *armed {
+            Armed::Armed => "Armed",
+            Armed::Disarmed => "Disarmed",
+        };
+
+        callouts.send(Callout {
+            class: CalloutClass::Arming,
+            message: message.to_owned(),
+        });
+    }
+}
+
+fn depth_hold_callouts(
+    mut announced: Local<Option<Option<f32>>>,
+    mut pending: Local<Option<(Option<f32>, Instant)>>,
+
+    robot: Query<Option<&DepthTarget>, With<Robot>>,
+    mut callouts: EventWriter<Callout>,
+) {
+    // TODO(low): Support multiple robots
+    let Ok(target) = robot.get_single() else {
+        *announced = None;
+        *pending = None;
+        return;
+    };
+    let target = target.map(|it| it.0 .0);
+
+    let Some(last) = *announced else {
+        // Don't announce the target the robot connected with
+        *announced = Some(target);
+        return;
+    };
+
+    match *pending {
+        Some((pending_target, _)) if pending_target != target => {
+            *pending = Some((target, Instant::now()));
+        }
+        None if last != target => {
+            *pending = Some((target, Instant::now()));
+        }
+        _ => {}
+    }
+
+    let Some((target, since)) = *pending else {
+        return;
+    };
+    if since.elapsed() < DEPTH_SETTLE_TIME {
+        return;
+    }
+
+    *pending = None;
+    if last == target {
+        return;
+    }
+    *announced = Some(target);
+
+    let message = match target {
+        Some(target) => format!("Depth hold set {target:.1} meters"),
+        None => "Depth hold off".to_owned(),
+    };
+
+    callouts.send(Callout {
+        class: CalloutClass::DepthHold,
+        message,
+    });
+}
+
+fn alarm_callouts(mut alarms: EventReader<Alarm>, mut callouts: EventWriter<Callout>) {
+    for Alarm { kind, .. } in alarms.read() {
+        let (class, message) = match kind {
+            AlarmKind::LowVoltage => (CalloutClass::LowBattery, "Low battery"),
+            AlarmKind::Error => (CalloutClass::Error, "Robot error"),
+            kind => (CalloutClass::Alarm, kind.name()),
+        };
+
+        callouts.send(Callout {
+            class,
+            message: message.to_owned(),
+        });
+    }
+}
+
+fn play_callouts(
+    settings: Res<CalloutSettings>,
+    speech: Res<Speech>,
+    mut player: ResMut<TonePlayer>,
+    mut callouts: EventReader<Callout>,
+) {
+    if !settings.enabled {
+        callouts.clear();
+        return;
+    }
+
+    for Callout { class, message } in callouts.read() {
+        let callout = settings.class(*class);
+        debug!("Callout {}: {message}", class.name());
+
+        // Queue after any sound that is still playing
+        let mut start = player
+            .queue
+            .last()
+            .map(|(start, pitch)| *start + pitch.duration + TONE_GAP)
+            .unwrap_or_else(Instant::now)
+            .max(Instant::now());
+
+        for &(frequency, length) in callout.sound.tones() {
+            let pitch = Pitch::new(frequency, Duration::from_millis(length));
+            player.queue.push((start, pitch.clone()));
+            start += pitch.duration + TONE_GAP;
+        }
+
+        if callout.speak {
+            let res = speech
+                .0
+                .try_send((settings.speech_command.clone(), message.clone()));
+
+            if let Err(TrySendError::Full(_)) = res {
+                warn!("Too many queued callouts, dropping {message:?}");
+            }
+        }
+    }
+}
+
+fn play_tones(
+    mut cmds: Commands,
+    mut player: ResMut<TonePlayer>,
+    pitches: Option<ResMut<Assets<Pitch>>>,
+) {
+    if player.queue.is_empty() {
+        return;
+    }
+
+    let Some(mut pitches) = pitches else {
+        warn!("Audio is not available, dropping alert sounds");
+        player.queue.clear();
+        return;
+    };
+
+    let now = Instant::now();
+    let due = player
+        .queue
+        .iter()
+        .take_while(|(start, _)| *start <= now)
+        .count();
+
+    for (_, pitch) in player.queue.drain(..due) {
+        cmds.spawn((AudioPlayer(pitches.add(pitch)), PlaybackSettings::DESPAWN));
+    }
+}
+
+fn callouts_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<CalloutSettings>,
+    mut callouts: EventWriter<Callout>,
+) {
+    let mut open = true;
+    let mut edited = settings.clone();
+
+    egui::Window::new("Callouts").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        egui::Id::new("Callouts"),
+        |ui| {
+            ui.checkbox(&mut edited.enabled, "Enable Sounds and Callouts");
+
+            ui.add_enabled_ui(edited.enabled, |ui| {
+                egui::Grid::new("Callout Classes")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Event");
+                        ui.label("Sound");
+                        ui.label("Speak");
+                        ui.label("");
+                        ui.end_row();
+
+                        for class in CalloutClass::ALL {
+                            let callout = edited.classes.entry(class).or_default();
+
+                            ui.label(class.name());
+                            egui::ComboBox::from_id_salt(class.name())
+                                .selected_text(callout.sound.name())
+                                .show_ui(ui, |ui| {
+                                    for sound in AlertSound::ALL {
+                                        ui.selectable_value(
+                                            &mut callout.sound,
+                                            sound,
+                                            sound.name(),
+                                        );
+                                    }
+                                });
+                            ui.checkbox(&mut callout.speak, "");
+
+                            if ui.button("Test").clicked() {
+                                callouts.send(Callout {
+                                    class,
+                                    message: format!("{} test", class.name()),
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Speech Command");
+
+                    let mut command = edited.speech_command.join(" ");
+                    if ui.text_edit_singleline(&mut command).changed() {
+                        edited.speech_command =
+                            command.split_whitespace().map(ToOwned::to_owned).collect();
+                    }
+                });
+            });
+        },
+    );
+
+    if edited != *settings {
+        *settings = edited;
+    }
+
+    if !open {
+        cmds.remove_resource::<CalloutsUi>();
+    }
+}
@@ -0,0 +1,251 @@
+//! Exposure, white balance, focus and gain of the robot's cameras, set from the surface so they
+//! can be adjusted mid-dive
+//!
+//! Controls saved as a camera's default are stored by camera name and applied whenever that camera
+//! shows up.
+
+use std::{collections::BTreeMap, fs};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{CameraCapabilities, CameraControls, CameraDefinition},
+    types::video::ControlRange,
+};
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+
+use crate::{layout::DockableWindow, settings::config_path, ui::CameraControlsUi};
+
+pub const CAMERA_CONTROLS_FILE: &str = "camera_controls.toml";
+
+pub struct CameraControlsPlugin;
+
+impl Plugin for CameraControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, load_defaults)
+            .add_systems(
+                Update,
+                (
+                    apply_defaults,
+                    camera_controls_ui.run_if(resource_exists::<CameraControlsUi>),
+                ),
+            )
+            .add_systems(Last, write_defaults);
+    }
+}
+
+/// Controls saved per camera name, applied when the camera connects
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CameraControlDefaults(pub BTreeMap<String, CameraControls>);
+
+fn load_defaults(mut cmds: Commands) {
+    let res: anyhow::Result<CameraControlDefaults> = try {
+        let defaults = fs::read_to_string(config_path(CAMERA_CONTROLS_FILE))
+            .context("Read camera control defaults")?;
+        toml::from_str(&defaults).context("Parse camera control defaults")?
+    };
+
+    let defaults = match res {
+        Ok(defaults) => defaults,
+        Err(err) => {
+            warn!("No camera control defaults: {err:?}");
+            CameraControlDefaults::default()
+        }
+    };
+
+    cmds.insert_resource(defaults);
+}
+
+fn write_defaults(defaults: Res<CameraControlDefaults>) {
+    if !defaults.is_changed() || defaults.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*defaults) else {
+        error!("Could not serialize camera control defaults");
+        return;
+    };
+
+    let res = fs::write(config_path(CAMERA_CONTROLS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write camera control defaults: {err:?}");
+    }
+}
+
+fn apply_defaults(
+    mut cmds: Commands,
+    cameras: Query<
+        (Entity, &Name),
+        (
+            With<CameraDefinition>,
+            Added<CameraCapabilities>,
+            Without<CameraControls>,
+        ),
+    >,
+    defaults: Res<CameraControlDefaults>,
+) {
+    for (entity, name) in &cameras {
+        if let Some(controls) = defaults.0.get(name.as_str()) {
+            info!("Applying default controls to {name}");
+            cmds.entity(entity).insert(*controls);
+        }
+    }
+}
+
+fn camera_controls_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut state: ResMut<CameraControlsUi>,
+    mut defaults: ResMut<CameraControlDefaults>,
+    cameras: Query<
+        (Entity, &Name, &CameraCapabilities, Option<&CameraControls>),
+        With<CameraDefinition>,
+    >,
+) {
+    let mut open = true;
+
+    let mut sorted = cameras.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|(_, name, ..)| name.as_str());
+
+    egui::Window::new("Camera Controls")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Camera Controls"), |ui| {
+            let selected = state
+                .camera
+                .and_then(|it| sorted.iter().find(|(entity, ..)| *entity == it))
+                .or(sorted.first());
+            let Some(&(camera, name, capabilities, current)) = selected else {
+                ui.label("No cameras");
+                return;
+            };
+
+            egui::ComboBox::from_id_salt("Camera Controls Camera")
+                .selected_text(name.as_str())
+                .show_ui(ui, |ui| {
+                    for (entity, name, ..) in &sorted {
+                        if ui
+                            .selectable_label(*entity == camera, name.as_str())
+                            .clicked()
+                        {
+                            state.camera = Some(*entity);
+                            state.pending = None;
+                        }
+                    }
+                });
+
+            ui.separator();
+
+            // Slider drags are held back until they end, each change runs v4l2-ctl on the robot
+            let current = current.copied().unwrap_or_default();
+            let mut controls = state
+                .pending
+                .filter(|(it, _)| *it == camera)
+                .map(|(_, it)| it)
+                .unwrap_or(current);
+            let ranges = capabilities.controls;
+            let mut finished = false;
+
+            egui::Grid::new("Camera Controls Grid")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    finished |= control_row(
+                        ui,
+                        "Exposure",
+                        &mut controls.exposure,
+                        ranges.exposure,
+                        "Auto",
+                    );
+                    finished |= control_row(
+                        ui,
+                        "White Balance",
+                        &mut controls.white_balance,
+                        ranges.white_balance,
+                        "Auto",
+                    );
+                    finished |= control_row(ui, "Focus", &mut controls.focus, ranges.focus, "Auto");
+                    finished |= control_row(ui, "Gain", &mut controls.gain, ranges.gain, "Default");
+                });
+
+            if finished {
+                state.pending = None;
+
+                if controls != current {
+                    cmds.entity(camera).insert(controls);
+                }
+            } else if controls != current {
+                state.pending = Some((camera, controls));
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                let saved = defaults.0.get(name.as_str()).copied();
+
+                if ui
+                    .add_enabled(saved != Some(current), egui::Button::new("Save as Default"))
+                    .clicked()
+                {
+                    defaults.0.insert(name.to_string(), current);
+                }
+
+                if ui
+                    .add_enabled(saved.is_some(), egui::Button::new("Forget Default"))
+                    .clicked()
+                {
+                    defaults.0.remove(name.as_str());
+                }
+
+                if ui.button("All Auto").clicked() {
+                    state.pending = None;
+                    cmds.entity(camera).insert(CameraControls::default());
+                }
+            });
+        });
+
+    if !open {
+        cmds.remove_resource::<CameraControlsUi>();
+    }
+}
+
+/// A control with its automatic mode toggle, returns true once an edit is complete
+fn control_row(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<i32>,
+    range: Option<ControlRange>,
+    auto_label: &str,
+) -> bool {
+    ui.label(label);
+
+    let Some(range) = range else {
+        ui.label(RichText::new("Not supported").weak());
+        ui.end_row();
+
+        return false;
+    };
+
+    let mut finished = false;
+
+    let mut auto = value.is_none();
+    if ui.checkbox(&mut auto, auto_label).changed() {
+        *value = (!auto).then_some(range.default);
+        finished = true;
+    }
+
+    let mut manual = value.unwrap_or(range.default);
+    let slider = ui.add_enabled(
+        !auto,
+        egui::Slider::new(&mut manual, range.min..=range.max).step_by(range.step.max(1) as f64),
+    );
+    if slider.changed() {
+        *value = Some(manual);
+    }
+    finished |= slider.drag_stopped() || (slider.changed() && !slider.dragged());
+
+    ui.end_row();
+
+    finished
+}
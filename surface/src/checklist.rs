@@ -0,0 +1,303 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use common::{
+    components::{Armed, CameraDefinition, DepthMeasurement, DepthTarget, Orientation, Robot},
+    error::ErrorEvent,
+};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::{
+    session::{artifact_directory, ActiveSession},
+    video_stream::VideoThread,
+    wall_clock::{format_time, now},
+};
+
+pub const CHECKLIST_FILE: &str = "checklists.toml";
+pub const CHECKLIST_LOG_DIRECTORY: &str = "checklist_logs";
+
+pub struct ChecklistPlugin;
+
+impl Plugin for ChecklistPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportChecklist>()
+            .add_systems(PreStartup, load_checklists)
+            .add_systems(
+                Update,
+                (
+                    auto_check.run_if(resource_exists::<ChecklistRun>),
+                    export_checklist.after(auto_check),
+                ),
+            );
+    }
+}
+
+/// The procedures loaded from `checklists.toml`
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct Checklists {
+    pub checklists: Vec<ChecklistDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecklistDefinition {
+    pub name: String,
+    pub items: Vec<ChecklistItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub label: String,
+    /// Checks the item off once the condition is observed, otherwise the item is manual
+    #[serde(default)]
+    pub auto: Option<AutoCheck>,
+}
+
+/// Conditions that can be observed from the ECS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutoCheck {
+    RobotConnected,
+    DepthReporting,
+    OrientationReporting,
+    CamerasStreaming,
+    DepthHold,
+    Armed,
+    Disarmed,
+}
+
+impl AutoCheck {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutoCheck::RobotConnected => "Robot connected",
+            AutoCheck::DepthReporting => "Depth sensor reporting",
+            AutoCheck::OrientationReporting => "Orientation reporting",
+            AutoCheck::CamerasStreaming => "Cameras streaming",
+            AutoCheck::DepthHold => "Depth hold active",
+            AutoCheck::Armed => "Robot armed",
+            AutoCheck::Disarmed => "Robot disarmed",
+        }
+    }
+}
+
+impl ChecklistItem {
+    fn manual(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            auto: None,
+        }
+    }
+
+    fn auto(label: &str, check: AutoCheck) -> Self {
+        Self {
+            label: label.to_owned(),
+            auto: Some(check),
+        }
+    }
+}
+
+impl Default for Checklists {
+    fn default() -> Self {
+        Self {
+            checklists: vec![ChecklistDefinition {
+                name: "Pre Dive".to_owned(),
+                items: vec![
+                    ChecklistItem::manual("Tether connected and strain relieved"),
+                    ChecklistItem::manual("Power on"),
+                    ChecklistItem::auto("Robot connected", AutoCheck::RobotConnected),
+                    ChecklistItem::auto("Depth sensor reporting", AutoCheck::DepthReporting),
+                    ChecklistItem::auto("IMU reporting", AutoCheck::OrientationReporting),
+                    ChecklistItem::manual("Thruster self test"),
+                    ChecklistItem::auto("Camera check", AutoCheck::CamerasStreaming),
+                    ChecklistItem::manual("Area clear of people"),
+                    ChecklistItem::auto("Arm", AutoCheck::Armed),
+                ],
+            }],
+        }
+    }
+}
+
+/// A checklist in progress
+#[derive(Resource, Debug, Clone)]
+pub struct ChecklistRun {
+    pub definition: ChecklistDefinition,
+    pub started: OffsetDateTime,
+    pub started_instant: Instant,
+    pub completed: Vec<Option<CompletedItem>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompletedItem {
+    pub at: OffsetDateTime,
+    pub elapsed: Duration,
+    /// Checked off by `auto_check` rather than the operator
+    pub automatic: bool,
+}
+
+impl ChecklistRun {
+    pub fn new(definition: ChecklistDefinition) -> Self {
+        Self {
+            completed: vec![None; definition.items.len()],
+            definition,
+            started: now(),
+            started_instant: Instant::now(),
+        }
+    }
+
+    pub fn check(&mut self, idx: usize, automatic: bool) {
+        let elapsed = self.started_instant.elapsed();
+
+        if let Some(item) = self.completed.get_mut(idx) {
+            if item.is_none() {
+                *item = Some(CompletedItem {
+                    at: now(),
+                    elapsed,
+                    automatic,
+                });
+            }
+        }
+    }
+
+    pub fn uncheck(&mut self, idx: usize) {
+        if let Some(item) = self.completed.get_mut(idx) {
+            *item = None;
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed.iter().all(Option::is_some)
+    }
+
+    pub fn to_log(&self) -> String {
+        let mut log = String::new();
+
+        let _ = writeln!(
+            log,
+            "{} started {}",
+            self.definition.name,
+            format_time(self.started)
+        );
+
+        for (item, completed) in self.definition.items.iter().zip(&self.completed) {
+            match completed {
+                Some(completed) => {
+                    let _ = writeln!(
+                        log,
+                        "[x] {} - {} (+{:.1}s{})",
+                        item.label,
+                        format_time(completed.at),
+                        completed.elapsed.as_secs_f32(),
+                        if completed.automatic { ", auto" } else { "" }
+                    );
+                }
+                None => {
+                    let _ = writeln!(log, "[ ] {}", item.label);
+                }
+            }
+        }
+
+        log
+    }
+}
+
+/// Writes the current `ChecklistRun` to the checklist log directory
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ExportChecklist;
+
+fn load_checklists(mut cmds: Commands) {
+    let res: anyhow::Result<Checklists> = try {
+        let checklists = fs::read_to_string(CHECKLIST_FILE).context("Read checklists")?;
+        toml::from_str(&checklists).context("Parse checklists")?
+    };
+
+    let checklists = match res {
+        Ok(checklists) => checklists,
+        Err(err) => {
+            warn!("Using default checklists: {err:?}");
+            Checklists::default()
+        }
+    };
+
+    cmds.insert_resource(checklists);
+}
+
+fn auto_check(
+    mut run: ResMut<ChecklistRun>,
+    robots: Query<
+        (
+            &Armed,
+            Option<&DepthMeasurement>,
+            Option<&Orientation>,
+            Option<&DepthTarget>,
+        ),
+        With<Robot>,
+    >,
+    cameras: Query<(), With<CameraDefinition>>,
+    streams: Query<(), With<VideoThread>>,
+) {
+    let observed = |check: AutoCheck| match check {
+        AutoCheck::RobotConnected => !robots.is_empty(),
+        AutoCheck::DepthReporting => robots.iter().any(|(_, depth, ..)| depth.is_some()),
+        AutoCheck::OrientationReporting => robots
+            .iter()
+            .any(|(_, _, orientation, _)| orientation.is_some()),
+        AutoCheck::CamerasStreaming => !cameras.is_empty() && !streams.is_empty(),
+        AutoCheck::DepthHold => robots.iter().any(|(.., target)| target.is_some()),
+        AutoCheck::Armed => robots.iter().any(|(armed, ..)| *armed == Armed::Armed),
+        AutoCheck::Disarmed => {
+            !robots.is_empty() && robots.iter().all(|(armed, ..)| *armed == Armed::Disarmed)
+        }
+    };
+
+    let pending = run
+        .definition
+        .items
+        .iter()
+        .zip(&run.completed)
+        .enumerate()
+        .filter(|(_, (_, completed))| completed.is_none())
+        .filter_map(|(idx, (item, _))| item.auto.map(|check| (idx, check)))
+        .filter(|(_, check)| observed(*check))
+        .collect::<Vec<_>>();
+
+    for (idx, check) in pending {
+        info!("Checklist: {}", check.name());
+        run.check(idx, true);
+    }
+}
+
+fn export_checklist(
+    mut events: EventReader<ExportChecklist>,
+    run: Option<Res<ChecklistRun>>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for _ in events.read() {
+        let Some(ref run) = run else {
+            warn!("No checklist to export");
+            continue;
+        };
+
+        let res: anyhow::Result<()> = try {
+            let directory = artifact_directory(session.as_deref(), CHECKLIST_LOG_DIRECTORY);
+            fs::create_dir_all(&directory).context("Create checklist directory")?;
+
+            let file_name = run
+                .started
+                .format(&Iso8601::DATE_TIME)
+                .context("Format time")?;
+            let path = directory.join(format!("checklist_{file_name}.txt"));
+
+            fs::write(&path, run.to_log()).context("Write checklist log")?;
+            info!("Saved checklist to {path:?}");
+        };
+
+        if let Err(err) = res {
+            errors.send(err.into());
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! A read-only web page with the robot's telemetry and camera thumbnails, for watching a run from
+//! devices without the control station
+//!
+//! `/` serves the page, `/telemetry` the latest `Telemetry` as json, `/ws` streams it over a
+//! websocket as it changes and `/cameras/{name}` serves a jpeg thumbnail of a camera
+
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::{
+    components::{
+        Armed, CameraDefinition, CurrentDraw, DepthMeasurement, DepthTarget, Heading, Leak,
+        MeasuredVoltage, Orientation, Robot,
+    },
+    error::ErrorEvent,
+};
+use opencv::{
+    core::{AlgorithmHint, Size, Vector},
+    imgcodecs, imgproc,
+    prelude::*,
+};
+use serde::Serialize;
+use tokio::{
+    net::TcpListener,
+    sync::{oneshot, watch},
+};
+
+use crate::{layout::DockableWindow, ui::DashboardUi, video_stream::ImageHandle};
+
+pub const DEFAULT_PORT: u16 = 8080;
+
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(100);
+const THUMBNAIL_INTERVAL: Duration = Duration::from_secs(1);
+const THUMBNAIL_WIDTH: i32 = 320;
+
+const INDEX: &str = include_str!("dashboard.html");
+
+pub struct DashboardPlugin;
+
+impl Plugin for DashboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Dashboard>()
+            .add_event::<SetDashboard>()
+            .add_systems(
+                Update,
+                (
+                    handle_set_dashboard,
+                    update_telemetry.run_if(|it: Res<Dashboard>| it.is_running()),
+                    update_thumbnails.run_if(|it: Res<Dashboard>| it.is_running()),
+                    dashboard_ui.run_if(resource_exists::<DashboardUi>),
+                ),
+            );
+    }
+}
+
+/// What the page shows, unknown values are null
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Telemetry {
+    pub robot: Option<String>,
+    pub armed: Option<bool>,
+    pub depth_m: Option<f32>,
+    pub depth_target_m: Option<f32>,
+    pub heading_deg: Option<f32>,
+    pub pitch_deg: Option<f32>,
+    pub roll_deg: Option<f32>,
+    pub voltage_v: Option<f32>,
+    pub current_a: Option<f32>,
+    pub leak: Option<bool>,
+    /// Names of the cameras with a thumbnail
+    pub cameras: Vec<String>,
+}
+
+/// Shared between the app and the server
+struct DashboardState {
+    telemetry: watch::Sender<Telemetry>,
+    /// Jpeg encoded, by camera name
+    thumbnails: RwLock<HashMap<String, Vec<u8>>>,
+    viewers: AtomicUsize,
+}
+
+#[derive(Resource)]
+pub struct Dashboard {
+    pub port: u16,
+
+    state: Arc<DashboardState>,
+    /// Dropping this stops the server
+    server: Option<(SocketAddr, oneshot::Sender<()>)>,
+    last_telemetry: Option<Instant>,
+    last_thumbnails: Option<Instant>,
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            state: Arc::new(DashboardState {
+                telemetry: watch::Sender::new(Telemetry::default()),
+                thumbnails: RwLock::default(),
+                viewers: AtomicUsize::new(0),
+            }),
+            server: None,
+            last_telemetry: None,
+            last_thumbnails: None,
+        }
+    }
+}
+
+impl Dashboard {
+    pub fn is_running(&self) -> bool {
+        self.server.is_some()
+    }
+
+    /// The address the server is listening on
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.server.as_ref().map(|it| it.0)
+    }
+
+    /// Number of pages connected to the live telemetry
+    pub fn viewers(&self) -> usize {
+        self.state.viewers.load(Ordering::Relaxed)
+    }
+}
+
+/// Starts or stops the server
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SetDashboard(pub bool);
+
+fn handle_set_dashboard(
+    mut events: EventReader<SetDashboard>,
+    mut dashboard: ResMut<Dashboard>,
+    runtime: ResMut<TokioTasksRuntime>,
+) {
+    for &SetDashboard(enabled) in events.read() {
+        if !enabled {
+            if let Some((address, _)) = dashboard.server.take() {
+                info!("Stopped dashboard on {address}");
+            }
+
+            continue;
+        }
+
+        if dashboard.is_running() {
+            continue;
+        }
+
+        let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, dashboard.port));
+        let (stop, stopped) = oneshot::channel();
+        dashboard.server = Some((address, stop));
+
+        let router = Router::new()
+            .route("/", get(|| async { Html(INDEX) }))
+            .route("/telemetry", get(telemetry))
+            .route("/ws", get(websocket))
+            .route("/cameras/{name}", get(thumbnail))
+            .with_state(dashboard.state.clone());
+
+        runtime.spawn_background_task(move |mut ctx| async move {
+            let res: anyhow::Result<()> = try {
+                let listener = TcpListener::bind(address)
+                    .await
+                    .with_context(|| format!("Bind dashboard to {address}"))?;
+                info!("Serving dashboard on {address}");
+
+                axum::serve(listener, router)
+                    .with_graceful_shutdown(async {
+                        let _ = stopped.await;
+                    })
+                    .await
+                    .context("Serve dashboard")?;
+            };
+
+            if let Err(err) = res {
+                ctx.run_on_main_thread(move |ctx| {
+                    let world = ctx.world;
+
+                    let mut dashboard = world.resource_mut::<Dashboard>();
+                    if dashboard.address() == Some(address) {
+                        dashboard.server = None;
+                    }
+
+                    world.send_event(ErrorEvent::from(err));
+                })
+                .await;
+            }
+        });
+    }
+}
+
+async fn telemetry(State(state): State<Arc<DashboardState>>) -> Json<Telemetry> {
+    Json(state.telemetry.borrow().clone())
+}
+
+async fn websocket(
+    upgrade: WebSocketUpgrade,
+    State(state): State<Arc<DashboardState>>,
+) -> Response {
+    upgrade.on_upgrade(move |socket| stream_telemetry(socket, state))
+}
+
+async fn stream_telemetry(mut socket: WebSocket, state: Arc<DashboardState>) {
+    state.viewers.fetch_add(1, Ordering::Relaxed);
+
+    let mut updates = state.telemetry.subscribe();
+    loop {
+        let json = serde_json::to_string(&*updates.borrow_and_update());
+        let Ok(json) = json else {
+            break;
+        };
+
+        if socket.send(Message::Text(json.into())).await.is_err() {
+            break;
+        }
+        if updates.changed().await.is_err() {
+            break;
+        }
+    }
+
+    state.viewers.fetch_sub(1, Ordering::Relaxed);
+}
+
+async fn thumbnail(Path(name): Path<String>, State(state): State<Arc<DashboardState>>) -> Response {
+    let jpeg = state
+        .thumbnails
+        .read()
+        .ok()
+        .and_then(|it| it.get(&name).cloned());
+
+    match jpeg {
+        Some(jpeg) => (
+            [
+                (header::CONTENT_TYPE, "image/jpeg"),
+                (header::CACHE_CONTROL, "no-store"),
+            ],
+            jpeg,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn update_telemetry(
+    mut dashboard: ResMut<Dashboard>,
+    // TODO(low): Support multiple robots
+    robot: Query<
+        (
+            &Name,
+            Option<&Armed>,
+            (Option<&DepthMeasurement>, Option<&DepthTarget>),
+            (Option<&Orientation>, Option<&Heading>),
+            (Option<&MeasuredVoltage>, Option<&CurrentDraw>),
+            Option<&Leak>,
+        ),
+        With<Robot>,
+    >,
+) {
+    if dashboard
+        .last_telemetry
+        .is_some_and(|it| it.elapsed() < TELEMETRY_INTERVAL)
+    {
+        return;
+    }
+    dashboard.last_telemetry = Some(Instant::now());
+
+    let mut telemetry = Telemetry::default();
+
+    if let Ok((name, armed, (depth, target), (orientation, heading), (voltage, current), leak)) =
+        robot.get_single()
+    {
+        let (pitch, roll) = orientation
+            .map(|it| it.yaw_pitch_roll())
+            .map(|(_, pitch, roll)| (Some(pitch), Some(roll)))
+            .unwrap_or_default();
+
+        telemetry = Telemetry {
+            robot: Some(name.to_string()),
+            armed: armed.map(|it| *it == Armed::Armed),
+            depth_m: depth.map(|it| it.depth.0),
+            depth_target_m: target.map(|it| it.0 .0),
+            heading_deg: heading
+                .copied()
+                .or_else(|| orientation.map(|it| it.heading()))
+                .map(|it| it.0 .0),
+            pitch_deg: pitch,
+            roll_deg: roll,
+            voltage_v: voltage.map(|it| it.0 .0),
+            current_a: current.map(|it| it.0 .0),
+            leak: leak.map(|it| it.0),
+            cameras: Vec::new(),
+        };
+    }
+
+    if let Ok(thumbnails) = dashboard.state.thumbnails.read() {
+        telemetry.cameras = thumbnails.keys().cloned().collect();
+        telemetry.cameras.sort();
+    }
+
+    // Only wakes the websockets when something changed
+    dashboard.state.telemetry.send_if_modified(|it| {
+        let modified = *it != telemetry;
+        *it = telemetry;
+        modified
+    });
+}
+
+fn update_thumbnails(
+    mut dashboard: ResMut<Dashboard>,
+    cameras: Query<(&Name, &ImageHandle), With<CameraDefinition>>,
+    images: Res<Assets<Image>>,
+) {
+    if dashboard
+        .last_thumbnails
+        .is_some_and(|it| it.elapsed() < THUMBNAIL_INTERVAL)
+    {
+        return;
+    }
+    dashboard.last_thumbnails = Some(Instant::now());
+
+    let thumbnails = cameras
+        .iter()
+        .filter_map(|(name, handle)| {
+            let image = images.get(&handle.0).filter(|it| !it.data.is_empty())?;
+
+            match encode_thumbnail(image) {
+                Ok(jpeg) => Some((name.to_string(), jpeg)),
+                Err(err) => {
+                    warn!("Could not encode thumbnail of {name}: {err:?}");
+                    None
+                }
+            }
+        })
+        .collect();
+
+    if let Ok(mut old) = dashboard.state.thumbnails.write() {
+        *old = thumbnails;
+    }
+}
+
+fn encode_thumbnail(image: &Image) -> anyhow::Result<Vec<u8>> {
+    let size = image.size();
+
+    let rgba = Mat::from_slice(&image.data).context("Wrap image")?;
+    let rgba = rgba.reshape(4, size.y as i32).context("Reshape image")?;
+
+    let mut bgr = Mat::default();
+    imgproc::cvt_color(
+        &rgba,
+        &mut bgr,
+        imgproc::COLOR_RGBA2BGR,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )
+    .context("Convert colors")?;
+
+    let height = THUMBNAIL_WIDTH * size.y as i32 / (size.x as i32).max(1);
+    let mut small = Mat::default();
+    imgproc::resize(
+        &bgr,
+        &mut small,
+        Size::new(THUMBNAIL_WIDTH, height.max(1)),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )
+    .context("Resize image")?;
+
+    let mut jpeg = Vector::<u8>::new();
+    imgcodecs::imencode_def(".jpg", &small, &mut jpeg).context("Encode jpeg")?;
+
+    Ok(jpeg.to_vec())
+}
+
+fn dashboard_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut dashboard: ResMut<Dashboard>,
+    mut set_dashboard: EventWriter<SetDashboard>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Web Dashboard")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Web Dashboard"), |ui| {
+            let running = dashboard.is_running();
+
+            ui.add_enabled_ui(!running, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Port");
+                    ui.add(egui::DragValue::new(&mut dashboard.port).range(1024..=u16::MAX));
+                });
+            });
+
+            if let Some(address) = dashboard.address() {
+                ui.label(format!("Serving on port {}", address.port()));
+                ui.label(format!("{} live viewers", dashboard.viewers()));
+            }
+
+            let label = if running {
+                "Stop Server"
+            } else {
+                "Start Server"
+            };
+            if ui.button(label).clicked() {
+                set_dashboard.send(SetDashboard(!running));
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<DashboardUi>();
+    }
+}
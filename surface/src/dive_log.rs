@@ -0,0 +1,251 @@
+use std::{fmt::Write as _, fs};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use common::{
+    components::{Armed, CurrentDraw, DepthMeasurement, DepthTarget, Robot},
+    error::ErrorEvent,
+    events::Alarm,
+    types::units::{Amperes, Meters},
+};
+use egui_plot::PlotPoint;
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::{
+    session::{artifact_directory, ActiveSession},
+    wall_clock::{format_time, now},
+};
+
+pub const DIVE_LOG_DIRECTORY: &str = "dive_logs";
+
+/// Depth is recorded at 10hz, enough for a profile without growing too large over a long session
+const SAMPLE_INTERVAL: f64 = 0.1;
+
+pub struct DiveLogPlugin;
+
+impl Plugin for DiveLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DiveLog>()
+            .add_event::<ExportDiveLog>()
+            .add_systems(
+                Update,
+                (
+                    record_dive,
+                    record_alarms,
+                    export_dive_log.after(record_dive),
+                ),
+            );
+    }
+}
+
+/// Depth and notable events for the whole session, times are seconds since the robot was first seen
+#[derive(Resource, Debug, Clone)]
+pub struct DiveLog {
+    pub started: OffsetDateTime,
+    started_secs: Option<f64>,
+    pub depth: Vec<PlotPoint>,
+    pub events: Vec<DiveEvent>,
+    pub max_depth: Meters,
+    /// Time spent armed
+    pub dive_secs: f64,
+    current_sum: f64,
+    current_samples: u32,
+
+    last_sample: f64,
+    last_armed: Option<Armed>,
+    last_target: Option<Meters>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiveEvent {
+    pub time: f64,
+    pub kind: DiveEventKind,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiveEventKind {
+    Armed,
+    Disarmed,
+    DepthTarget,
+    Alarm,
+}
+
+impl Default for DiveLog {
+    fn default() -> Self {
+        Self {
+            started: now(),
+            started_secs: None,
+            depth: Vec::new(),
+            events: Vec::new(),
+            max_depth: Meters::ZERO,
+            dive_secs: 0.0,
+            current_sum: 0.0,
+            current_samples: 0,
+            last_sample: f64::NEG_INFINITY,
+            last_armed: None,
+            last_target: None,
+        }
+    }
+}
+
+impl DiveLog {
+    /// Mean current draw while armed
+    pub fn average_current(&self) -> Option<Amperes> {
+        if self.current_samples == 0 {
+            return None;
+        }
+
+        Some(Amperes(
+            (self.current_sum / self.current_samples as f64) as f32,
+        ))
+    }
+
+    fn elapsed(&mut self, now: f64) -> f64 {
+        now - *self.started_secs.get_or_insert(now)
+    }
+
+    fn push_event(&mut self, time: f64, kind: DiveEventKind, label: String) {
+        info!("Dive log: {label}");
+        self.events.push(DiveEvent { time, kind, label });
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+
+        let _ = writeln!(summary, "Dive started {}", format_time(self.started));
+        let _ = writeln!(summary, "Max depth: {}", self.max_depth);
+        let _ = writeln!(summary, "Dive duration: {}", format_secs(self.dive_secs));
+        match self.average_current() {
+            Some(current) => {
+                let _ = writeln!(summary, "Average current: {current}");
+            }
+            None => {
+                let _ = writeln!(summary, "Average current: Unknown");
+            }
+        }
+
+        if !self.events.is_empty() {
+            let _ = writeln!(summary);
+            let _ = writeln!(summary, "Events:");
+
+            for event in &self.events {
+                let _ = writeln!(summary, "+{} {}", format_secs(event.time), event.label);
+            }
+        }
+
+        summary
+    }
+}
+
+/// Writes the summary of the current `DiveLog` to the dive log directory
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ExportDiveLog;
+
+pub fn format_secs(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn record_dive(
+    mut log: ResMut<DiveLog>,
+    time: Res<Time<Real>>,
+    // TODO(low): Support multiple robots
+    robot: Query<
+        (
+            &Armed,
+            Option<&DepthMeasurement>,
+            Option<&DepthTarget>,
+            Option<&CurrentDraw>,
+        ),
+        With<Robot>,
+    >,
+) {
+    let Ok((&armed, depth, target, current)) = robot.get_single() else {
+        return;
+    };
+
+    let now = log.elapsed(time.elapsed_secs_f64());
+    let log = &mut *log;
+
+    if log.last_armed != Some(armed) {
+        let (kind, label) = match armed {
+            Armed::Armed => (DiveEventKind::Armed, "Armed"),
+            Armed::Disarmed => (DiveEventKind::Disarmed, "Disarmed"),
+        };
+
+        // The first observation is the state the robot connected in, not a transition
+        if log.last_armed.is_some() || armed == Armed::Armed {
+            log.push_event(now, kind, label.to_owned());
+        }
+        log.last_armed = Some(armed);
+    }
+
+    let target = target.map(|it| it.0);
+    if log.last_target != target {
+        let label = match target {
+            Some(target) => format!("Depth target {target}"),
+            None => "Depth hold off".to_owned(),
+        };
+
+        log.push_event(now, DiveEventKind::DepthTarget, label);
+        log.last_target = target;
+    }
+
+    if armed == Armed::Armed {
+        log.dive_secs += time.delta_secs_f64();
+
+        if let Some(current) = current {
+            log.current_sum += current.0 .0 as f64;
+            log.current_samples += 1;
+        }
+    }
+
+    if let Some(depth) = depth {
+        if depth.depth > log.max_depth {
+            log.max_depth = depth.depth;
+        }
+
+        if now - log.last_sample >= SAMPLE_INTERVAL {
+            log.depth.push(PlotPoint::new(now, depth.depth.0));
+            log.last_sample = now;
+        }
+    }
+}
+
+fn record_alarms(mut log: ResMut<DiveLog>, time: Res<Time<Real>>, mut alarms: EventReader<Alarm>) {
+    for alarm in alarms.read() {
+        let now = log.elapsed(time.elapsed_secs_f64());
+        let label = format!("{}: {}", alarm.kind.name(), alarm.message);
+
+        log.push_event(now, DiveEventKind::Alarm, label);
+    }
+}
+
+fn export_dive_log(
+    mut events: EventReader<ExportDiveLog>,
+    log: Res<DiveLog>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for _ in events.read() {
+        let res: anyhow::Result<()> = try {
+            let directory = artifact_directory(session.as_deref(), DIVE_LOG_DIRECTORY);
+            fs::create_dir_all(&directory).context("Create dive log directory")?;
+
+            let file_name = log
+                .started
+                .format(&Iso8601::DATE_TIME)
+                .context("Format time")?;
+            let path = directory.join(format!("dive_{file_name}.txt"));
+
+            fs::write(&path, log.summary()).context("Write dive log")?;
+            info!("Saved dive log to {path:?}");
+        };
+
+        if let Err(err) = res {
+            errors.send(err.into());
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Primary flight display widgets for the HUD, drawn with the egui painter so they stay sharp at
+//! any size
+
+use egui::{
+    emath::Rot2, pos2, vec2, Align2, Color32, FontId, Painter, Pos2, Rect, Response, Sense, Shape,
+    Stroke, StrokeKind, Ui, Vec2,
+};
+
+const SKY: Color32 = Color32::from_rgb(40, 110, 190);
+const GROUND: Color32 = Color32::from_rgb(125, 80, 40);
+const WATER: Color32 = Color32::from_rgb(20, 45, 80);
+const TAPE_BACKGROUND: Color32 = Color32::from_rgba_premultiplied(10, 10, 10, 200);
+const MARKINGS: Color32 = Color32::WHITE;
+const REFERENCE: Color32 = Color32::YELLOW;
+const TARGET: Color32 = Color32::from_rgb(255, 0, 255);
+
+/// Degrees of pitch visible between the center and the edge of the horizon
+const HORIZON_PITCH_RANGE: f32 = 30.0;
+/// Degrees of heading visible between the center and the edge of the tape
+const HEADING_RANGE: f32 = 45.0;
+/// Depth visible between the center and the edge of the depth tape, in the pilot's length unit
+const DEPTH_RANGE: f32 = 2.0;
+
+const ROLL_MARKS: [f32; 11] = [
+    -60.0, -45.0, -30.0, -20.0, -10.0, 0.0, 10.0, 20.0, 30.0, 45.0, 60.0,
+];
+
+/// Attitude indicator with a pitch ladder and roll scale, angles are in degrees
+pub fn artificial_horizon(ui: &mut Ui, size: f32, pitch: f32, roll: f32) -> Response {
+    let (response, painter) = ui.allocate_painter(Vec2::splat(size), Sense::hover());
+    let rect = response.rect;
+    let center = rect.center();
+
+    let px_per_deg = size / 2.0 / HORIZON_PITCH_RANGE;
+    let font = FontId::monospace(size / 18.0);
+    let stroke = Stroke::new((size / 150.0).max(1.0), MARKINGS);
+
+    // Rolling right tilts the horizon counter clockwise
+    let rot = Rot2::from_angle(-roll.to_radians());
+    let horizon = center + rot * vec2(0.0, pitch * px_per_deg);
+    let local = |x: f32, y: f32| horizon + rot * vec2(x, y);
+
+    let extent = size * 2.0;
+    painter.add(Shape::convex_polygon(
+        vec![
+            local(-extent, -extent),
+            local(extent, -extent),
+            local(extent, 0.0),
+            local(-extent, 0.0),
+        ],
+        SKY,
+        Stroke::NONE,
+    ));
+    painter.add(Shape::convex_polygon(
+        vec![
+            local(-extent, 0.0),
+            local(extent, 0.0),
+            local(extent, extent),
+            local(-extent, extent),
+        ],
+        GROUND,
+        Stroke::NONE,
+    ));
+    painter.line_segment([local(-extent, 0.0), local(extent, 0.0)], stroke);
+
+    // Pitch ladder
+    for mark in (-90..=90).step_by(5) {
+        let mark = mark as f32;
+        if mark == 0.0 || (mark - pitch).abs() > HORIZON_PITCH_RANGE {
+            continue;
+        }
+
+        let major = mark % 10.0 == 0.0;
+        let half_width = if major { size * 0.15 } else { size * 0.07 };
+        let y = -mark * px_per_deg;
+
+        painter.line_segment([local(-half_width, y), local(half_width, y)], stroke);
+
+        if major {
+            let label = format!("{mark:.0}");
+            let offset = size * 0.05;
+            painter.text(
+                local(-half_width - offset, y),
+                Align2::CENTER_CENTER,
+                &label,
+                font.clone(),
+                MARKINGS,
+            );
+            painter.text(
+                local(half_width + offset, y),
+                Align2::CENTER_CENTER,
+                &label,
+                font.clone(),
+                MARKINGS,
+            );
+        }
+    }
+
+    // Roll scale, fixed to the display with a pointer that follows the horizon
+    let radius = size * 0.42;
+    for mark in ROLL_MARKS {
+        let direction = Rot2::from_angle(mark.to_radians()) * vec2(0.0, -1.0);
+        let length = if mark % 30.0 == 0.0 {
+            size * 0.05
+        } else {
+            size * 0.03
+        };
+
+        painter.line_segment(
+            [
+                center + direction * radius,
+                center + direction * (radius + length),
+            ],
+            stroke,
+        );
+    }
+
+    let pointer = |x: f32, y: f32| center + rot * vec2(x, y);
+    let tip = -radius + size * 0.005;
+    let base = tip + size * 0.04;
+    painter.add(Shape::convex_polygon(
+        vec![
+            pointer(0.0, tip),
+            pointer(size * 0.025, base),
+            pointer(-size * 0.025, base),
+        ],
+        REFERENCE,
+        Stroke::NONE,
+    ));
+
+    // Fixed reference symbol
+    let reference = Stroke::new(stroke.width * 2.0, REFERENCE);
+    let wing = size * 0.12;
+    let gap = size * 0.04;
+    painter.line_segment(
+        [center + vec2(-wing - gap, 0.0), center + vec2(-gap, 0.0)],
+        reference,
+    );
+    painter.line_segment(
+        [center + vec2(gap, 0.0), center + vec2(wing + gap, 0.0)],
+        reference,
+    );
+    painter.circle_filled(center, stroke.width * 2.0, REFERENCE);
+
+    let readout = FontId::monospace(size / 15.0);
+    painter.text(
+        rect.left_bottom() + vec2(size * 0.03, -size * 0.03),
+        Align2::LEFT_BOTTOM,
+        format!("P {pitch:+.0}°"),
+        readout.clone(),
+        MARKINGS,
+    );
+    painter.text(
+        rect.right_bottom() + vec2(-size * 0.03, -size * 0.03),
+        Align2::RIGHT_BOTTOM,
+        format!("R {roll:+.0}°"),
+        readout,
+        MARKINGS,
+    );
+
+    response
+}
+
+/// Horizontal compass tape, `heading` is in degrees clockwise from north
+pub fn heading_tape(ui: &mut Ui, size: Vec2, heading: f32) -> Response {
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let rect = response.rect;
+    let center = rect.center();
+
+    let px_per_deg = size.x / 2.0 / HEADING_RANGE;
+    let font = FontId::monospace(size.y * 0.3);
+    let stroke = Stroke::new((size.y / 30.0).max(1.0), MARKINGS);
+
+    painter.rect_filled(rect, 0.0, TAPE_BACKGROUND);
+
+    let first = ((heading - HEADING_RANGE) / 5.0).floor() as i32 * 5;
+    let last = ((heading + HEADING_RANGE) / 5.0).ceil() as i32 * 5;
+
+    for mark in (first..=last).step_by(5) {
+        let x = center.x + (mark as f32 - heading) * px_per_deg;
+        let major = mark % 10 == 0;
+        let length = if major { size.y * 0.3 } else { size.y * 0.15 };
+
+        painter.line_segment(
+            [pos2(x, rect.bottom()), pos2(x, rect.bottom() - length)],
+            stroke,
+        );
+
+        let mark = mark.rem_euclid(360);
+        if mark % 30 == 0 {
+            let label = match mark {
+                0 => "N".to_owned(),
+                90 => "E".to_owned(),
+                180 => "S".to_owned(),
+                270 => "W".to_owned(),
+                _ => format!("{mark}"),
+            };
+
+            painter.text(
+                pos2(x, rect.top() + size.y * 0.05),
+                Align2::CENTER_TOP,
+                label,
+                font.clone(),
+                MARKINGS,
+            );
+        }
+    }
+
+    readout_box(
+        &painter,
+        pos2(center.x, rect.top()),
+        Align2::CENTER_TOP,
+        format!("{:03.0}", heading.rem_euclid(360.0)),
+        font,
+    );
+
+    response
+}
+
+/// Vertical depth tape, deeper is lower on the tape. The target is drawn as a marker that sticks to
+/// the edge of the tape when it is out of view
+pub fn depth_tape(ui: &mut Ui, size: Vec2, depth: f32, target: Option<f32>) -> Response {
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let rect = response.rect;
+    let center = rect.center();
+
+    let px_per_m = size.y / 2.0 / DEPTH_RANGE;
+    let font = FontId::monospace(size.x * 0.2);
+    let stroke = Stroke::new((size.x / 40.0).max(1.0), MARKINGS);
+    let depth_to_y = |it: f32| center.y + (it - depth) * px_per_m;
+
+    painter.rect_filled(rect, 0.0, TAPE_BACKGROUND);
+
+    // Water below the surface
+    let surface = depth_to_y(0.0).clamp(rect.top(), rect.bottom());
+    painter.rect_filled(
+        Rect::from_min_max(pos2(rect.left(), surface), rect.right_bottom()),
+        0.0,
+        WATER,
+    );
+
+    // Ticks every 10cm
+    let first = ((depth - DEPTH_RANGE) * 10.0).floor() as i32;
+    let last = ((depth + DEPTH_RANGE) * 10.0).ceil() as i32;
+
+    for mark in first..=last {
+        let y = depth_to_y(mark as f32 / 10.0);
+        let major = mark % 5 == 0;
+        let length = if major { size.x * 0.3 } else { size.x * 0.15 };
+
+        painter.line_segment(
+            [pos2(rect.left(), y), pos2(rect.left() + length, y)],
+            stroke,
+        );
+
+        if major {
+            painter.text(
+                pos2(rect.right() - size.x * 0.05, y),
+                Align2::RIGHT_CENTER,
+                format!("{:.1}", mark as f32 / 10.0),
+                font.clone(),
+                MARKINGS,
+            );
+        }
+    }
+
+    if let Some(target) = target {
+        let half = size.x * 0.1;
+        let y = depth_to_y(target).clamp(rect.top() + half, rect.bottom() - half);
+        let x = rect.left();
+
+        painter.add(Shape::convex_polygon(
+            vec![
+                pos2(x, y),
+                pos2(x + half * 1.5, y - half),
+                pos2(x + half * 1.5, y + half),
+            ],
+            TARGET,
+            Stroke::NONE,
+        ));
+    }
+
+    readout_box(
+        &painter,
+        pos2(rect.right(), center.y),
+        Align2::RIGHT_CENTER,
+        format!("{depth:.2}"),
+        font,
+    );
+
+    response
+}
+
+fn readout_box(painter: &Painter, pos: Pos2, anchor: Align2, text: String, font: FontId) {
+    let galley = painter.layout_no_wrap(text, font, REFERENCE);
+    let padding = vec2(4.0, 2.0);
+    let rect = anchor.anchor_size(pos, galley.size() + padding * 2.0);
+
+    painter.rect_filled(rect, 2.0, Color32::BLACK);
+    painter.rect_stroke(rect, 2.0, Stroke::new(1.0, REFERENCE), StrokeKind::Inside);
+    painter.galley(rect.min + padding, galley, REFERENCE);
+}
+
+/// Signal strength style bars, `bars` of the 3 are filled with `color`
+pub fn signal_bars(ui: &mut Ui, height: f32, bars: u8, color: Color32) -> Response {
+    let bar_width = height / 4.0;
+    let gap = bar_width / 2.0;
+    let size = vec2(bar_width * 3.0 + gap * 2.0, height);
+
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let rect = response.rect;
+
+    for idx in 0..3u8 {
+        let bar_height = height * (idx + 1) as f32 / 3.0;
+        let left = rect.left() + (bar_width + gap) * idx as f32;
+        let bar = Rect::from_min_max(
+            pos2(left, rect.bottom() - bar_height),
+            pos2(left + bar_width, rect.bottom()),
+        );
+
+        if idx < bars {
+            painter.rect_filled(bar, 1.0, color);
+        } else {
+            painter.rect_stroke(bar, 1.0, Stroke::new(1.0, color), StrokeKind::Inside);
+        }
+    }
+
+    response
+}
@@ -0,0 +1,117 @@
+//! Shows how the surface is doing against its over run budgets and which systems are to blame
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::over_run::{Budget, OverRunSettings, OverRunStats};
+use egui::{Color32, RichText};
+
+use crate::{layout::DockableWindow, ui::FrameBudgetUi};
+
+const WORST_SYSTEMS: usize = 15;
+
+pub struct FrameBudgetPlugin;
+
+impl Plugin for FrameBudgetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            frame_budget_ui.run_if(resource_exists::<FrameBudgetUi>),
+        );
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn frame_budget_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    settings: Res<OverRunSettings>,
+    mut stats: ResMut<OverRunStats>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Frame Budget")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Frame Budget"), |ui| {
+            let focused = !stats.unfocused;
+            if !focused {
+                ui.colored_label(Color32::YELLOW, "Unfocused, budgets are relaxed");
+            }
+
+            egui::Grid::new("Budgets")
+                .num_columns(5)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label(RichText::new("Budget").strong());
+                    ui.label(RichText::new("Limit").strong());
+                    ui.label(RichText::new("Last").strong());
+                    ui.label(RichText::new("Worst").strong());
+                    ui.label(RichText::new("Over Runs").strong());
+                    ui.end_row();
+
+                    for budget in Budget::ALL {
+                        let budget_stats = stats.budgets.get(&budget).copied().unwrap_or_default();
+
+                        ui.label(budget.name());
+                        match settings.limit(budget, focused) {
+                            Some(limit) => ui.label(millis(limit)),
+                            None => ui.weak("Unchecked"),
+                        };
+                        ui.label(millis(budget_stats.last));
+                        ui.label(millis(budget_stats.worst));
+
+                        let overruns = format!("{} / {}", budget_stats.overruns, budget_stats.runs);
+                        if budget_stats.overruns > 0 {
+                            ui.colored_label(Color32::RED, overruns);
+                        } else {
+                            ui.label(overruns);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+            ui.separator();
+
+            ui.label(RichText::new("Worst Offenders").strong());
+
+            let systems = stats.worst_systems(WORST_SYSTEMS);
+            if systems.is_empty() {
+                ui.weak("No system timings, build with bevy's trace feature to collect them");
+            } else {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("Worst Offenders")
+                        .num_columns(4)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label(RichText::new("System").strong());
+                            ui.label(RichText::new("Total").strong());
+                            ui.label(RichText::new("Worst").strong());
+                            ui.label(RichText::new("Over Runs").strong());
+                            ui.end_row();
+
+                            for (name, system) in systems {
+                                ui.label(name);
+                                ui.label(millis(system.total));
+                                ui.label(millis(system.worst));
+                                ui.label(system.overruns.to_string());
+                                ui.end_row();
+                            }
+                        });
+                });
+            }
+
+            ui.separator();
+
+            if ui.button("Reset").clicked() {
+                stats.reset();
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<FrameBudgetUi>();
+    }
+}
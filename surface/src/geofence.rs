@@ -0,0 +1,78 @@
+//! Shows when the robot's geofence has taken control and walks the pilot through overriding it.
+//! The limits themselves are enforced by the robot's `geofence` plugin
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{Geofence, GeofenceBreach, Robot},
+    events::{GeofenceOverride, GeofenceOverrideStep},
+};
+use egui::{Align2, Color32, RichText};
+
+pub struct GeofencePlugin;
+
+impl Plugin for GeofencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, geofence_ui);
+    }
+}
+
+fn geofence_ui(
+    mut contexts: EguiContexts,
+    // TODO(low): Support multiple robots
+    robot: Query<&Geofence, With<Robot>>,
+    mut overrides: EventWriter<GeofenceOverride>,
+) {
+    let Ok(geofence) = robot.get_single() else {
+        return;
+    };
+
+    if !geofence.enforcing() && geofence.override_challenge.is_none() && !geofence.overridden {
+        return;
+    }
+
+    egui::Window::new("Geofence")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .show(contexts.ctx_mut(), |ui| {
+            if let Some(challenge) = geofence.override_challenge {
+                ui.label(RichText::new("Override the geofence?").strong());
+                ui.label(
+                    "The robot will not enforce its depth or distance limits until it is disarmed.",
+                );
+
+                ui.horizontal(|ui| {
+                    if ui.button("Confirm Override").clicked() {
+                        warn!("Confirming geofence override");
+                        overrides.send(GeofenceOverride(GeofenceOverrideStep::Confirm(challenge)));
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        overrides.send(GeofenceOverride(GeofenceOverrideStep::Cancel));
+                    }
+                });
+            } else if geofence.overridden {
+                ui.colored_label(Color32::YELLOW, "Geofence overridden until disarmed");
+
+                if ui.button("Restore Limits").clicked() {
+                    overrides.send(GeofenceOverride(GeofenceOverrideStep::Cancel));
+                }
+            } else if let Some(breach) = geofence.breach {
+                let recovery = match breach {
+                    GeofenceBreach::Depth => "ascending above the depth limit",
+                    GeofenceBreach::Distance => "surfacing outside the distance limit",
+                };
+
+                ui.colored_label(
+                    Color32::RED,
+                    RichText::new(format!("{} breached", breach.name())).strong(),
+                );
+                ui.label(format!("The robot has taken control and is {recovery}."));
+
+                if ui.button("Override Limits").clicked() {
+                    overrides.send(GeofenceOverride(GeofenceOverrideStep::Request));
+                }
+            }
+        });
+}
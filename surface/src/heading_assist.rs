@@ -0,0 +1,170 @@
+//! Holds the robot's heading while the pilot translates with the yaw stick centered, so the yaw
+//! asymmetric drag adds to lateral moves doesn't need constant stick corrections
+//!
+//! The assist sets the robot's `HeadingTarget`, it stands aside while leveling or bottom lock
+//! holds the heading. Yawing hands control straight back to the pilot and the heading is only
+//! captured again once the stick has been centered for a moment, so the robot never snaps back to
+//! an old heading.
+
+use std::time::Duration;
+
+use ahash::HashMap;
+use bevy::prelude::*;
+use common::{
+    components::{
+        Armed, BottomLock, Heading, HeadingTarget, Orientation, OrientationTarget, Robot, RobotId,
+    },
+    error,
+    params::{AppParamExt, Param, ParamType, Params},
+};
+use leafwing_input_manager::action_state::ActionState;
+
+use crate::{
+    input::{Action, InputInterpolation, InputMarker},
+    input_shaping::{AxisInputs, InputShaping},
+};
+
+pub struct HeadingAssistPlugin;
+
+impl Plugin for HeadingAssistPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_param(&HEADING_ASSIST)
+            .register_param(&HEADING_ASSIST_DELAY)
+            .register_param(&HEADING_ASSIST_DEADBAND)
+            .add_systems(
+                Update,
+                (
+                    toggle_heading_assist.pipe(error::handle_errors),
+                    heading_assist.after(toggle_heading_assist),
+                ),
+            );
+    }
+}
+
+pub const HEADING_ASSIST: Param<bool> = Param::new(
+    "surface.input.heading_assist",
+    "Input",
+    "Holds the heading while translating with the yaw stick centered",
+    false,
+    false,
+    true,
+);
+
+const HEADING_ASSIST_DELAY: Param<Duration> = Param::new(
+    "surface.input.heading_assist_delay",
+    "Input",
+    "Time the yaw stick must be centered before the heading is captured",
+    Duration::from_millis(500),
+    Duration::ZERO,
+    Duration::from_secs(3),
+);
+
+const HEADING_ASSIST_DEADBAND: Param<f32> = Param::new(
+    "surface.input.heading_assist_deadband",
+    "Input",
+    "Shaped stick magnitude below which an axis counts as centered",
+    0.05,
+    0.0,
+    0.5,
+);
+
+/// Turns the assist on or off, shared by the binding and the hud
+pub fn set_heading_assist(params: &mut Params, enabled: bool) -> anyhow::Result<()> {
+    params.set(HEADING_ASSIST.key, Some(enabled.to_value()))?;
+    info!(
+        "Heading assist {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+
+    Ok(())
+}
+
+fn toggle_heading_assist(
+    inputs: Query<&ActionState<Action>, With<InputMarker>>,
+    mut params: ResMut<Params>,
+) -> anyhow::Result<()> {
+    let toggle = inputs
+        .iter()
+        .any(|action_state| action_state.just_pressed(&Action::ToggleHeadingAssist));
+
+    if toggle {
+        let enabled = !params.get(&HEADING_ASSIST);
+        set_heading_assist(&mut params, enabled)?;
+    }
+
+    Ok(())
+}
+
+fn heading_assist(
+    mut cmds: Commands,
+    // When the yaw stick was last centered, per robot
+    mut centered_since: Local<HashMap<Entity, Duration>>,
+    inputs: Query<
+        (
+            &RobotId,
+            &ActionState<Action>,
+            &InputInterpolation,
+            &InputShaping,
+        ),
+        With<InputMarker>,
+    >,
+    robots: Query<
+        (
+            Entity,
+            Option<&Armed>,
+            Option<&Orientation>,
+            Option<&OrientationTarget>,
+            Option<&HeadingTarget>,
+            &RobotId,
+        ),
+        (With<Robot>, Without<BottomLock>),
+    >,
+    params: Res<Params>,
+    time: Res<Time<Real>>,
+) {
+    let enabled = params.get(&HEADING_ASSIST);
+    let delay = params.get(&HEADING_ASSIST_DELAY);
+    let deadband = params.get(&HEADING_ASSIST_DEADBAND);
+
+    for (robot, armed, orientation, orientation_target, heading_target, robot_id) in &robots {
+        let mut yawing = false;
+        let mut translating = false;
+
+        // Every role's input is considered, whichever gamepad owns the movement axes
+        for (_, action_state, interpolation, shaping) in inputs
+            .iter()
+            .filter(|(other_robot, ..)| *other_robot == robot_id)
+        {
+            let inputs = shaping
+                .with_expo(interpolation.expo)
+                .apply(AxisInputs::from_action_state(action_state));
+
+            yawing |= inputs.yaw.abs() > deadband;
+            translating |= inputs.surge.abs() > deadband || inputs.sway.abs() > deadband;
+        }
+
+        let active =
+            enabled && armed == Some(&Armed::Armed) && orientation_target.is_none() && !yawing;
+        if !active {
+            centered_since.remove(&robot);
+
+            if heading_target.is_some() {
+                cmds.entity(robot).remove::<HeadingTarget>();
+            }
+
+            continue;
+        }
+
+        let centered_since = *centered_since.entry(robot).or_insert(time.elapsed());
+        if heading_target.is_some() || !translating || time.elapsed() - centered_since < delay {
+            continue;
+        }
+
+        if let Some(orientation) = orientation {
+            let Heading(heading) = orientation.heading();
+
+            debug!("Heading assist holding {heading}");
+            cmds.entity(robot).insert(HeadingTarget(heading));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Maps input devices that aren't gamepads, like throttle quadrants and button boxes, onto
+//! actions using a table loaded from `hid.toml`
+//!
+//! These devices still show up through gilrs as gamepads, but their axes and buttons land on
+//! whichever gamepad inputs the driver picks. A matched device is never given a role. Instead its
+//! mapped inputs are merged into the `ActionState` of the input entity that owns each action, the
+//! same way the touch controls are. For example
+//!
+//! ```toml
+//! [[devices]]
+//! name = "Thrustmaster TWCS Throttle"
+//!
+//! [[devices.axes]]
+//! axis = "LeftZ"
+//! action = "Heave"
+//! lever = true
+//!
+//! [[devices.buttons]]
+//! button = { Other = 4 }
+//! action = "ToggleDepthHold"
+//! ```
+
+use std::fs;
+
+use ahash::HashSet;
+use anyhow::Context;
+use bevy::{input::InputSystem, prelude::*};
+use leafwing_input_manager::{
+    action_state::ActionState, plugin::InputManagerSystem, Actionlike, InputControlKind,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{Action, GamepadRoles, InputMarker, InputRole},
+    settings::config_path,
+};
+
+pub const HID_FILE: &str = "hid.toml";
+
+pub struct HidPlugin;
+
+impl Plugin for HidPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, load_mappings).add_systems(
+            PreUpdate,
+            (
+                claim_devices.after(InputSystem),
+                apply_hid_input
+                    .after(claim_devices)
+                    .in_set(InputManagerSystem::ManualControl),
+            ),
+        );
+    }
+}
+
+/// Every device with a mapping, loaded once at startup
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HidMappings {
+    #[serde(default)]
+    pub devices: Vec<HidDevice>,
+}
+
+impl HidMappings {
+    /// The mapping for `gamepad`, if it is one of the configured devices
+    pub fn device_for(&self, gamepad: &Gamepad, name: Option<&Name>) -> Option<&HidDevice> {
+        self.devices.iter().find(|it| it.matches(gamepad, name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HidDevice {
+    /// The name reported by the driver
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub vendor_id: Option<u16>,
+    #[serde(default)]
+    pub product_id: Option<u16>,
+
+    #[serde(default)]
+    pub axes: Vec<HidAxis>,
+    #[serde(default)]
+    pub buttons: Vec<HidButton>,
+}
+
+impl HidDevice {
+    /// Every identifier given has to match, a device without any never matches
+    pub fn matches(&self, gamepad: &Gamepad, name: Option<&Name>) -> bool {
+        if self.name.is_none() && self.vendor_id.is_none() && self.product_id.is_none() {
+            return false;
+        }
+
+        let name_matches = self
+            .name
+            .as_ref()
+            .is_none_or(|it| name.is_some_and(|name| name.as_str() == it));
+        let vendor_matches = self
+            .vendor_id
+            .is_none_or(|it| gamepad.vendor_id() == Some(it));
+        let product_matches = self
+            .product_id
+            .is_none_or(|it| gamepad.product_id() == Some(it));
+
+        name_matches && vendor_matches && product_matches
+    }
+
+    pub fn label(&self) -> String {
+        match (&self.name, self.vendor_id, self.product_id) {
+            (Some(name), ..) => name.clone(),
+            (None, vendor, product) => format!(
+                "{:04x}:{:04x}",
+                vendor.unwrap_or_default(),
+                product.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HidAxis {
+    pub axis: GamepadAxis,
+    pub action: Action,
+    #[serde(default)]
+    pub invert: bool,
+    /// The axis is a lever resting at one end of its travel, its full travel maps onto `0..1`
+    #[serde(default)]
+    pub lever: bool,
+    #[serde(default)]
+    pub deadzone: f32,
+    #[serde(default = "unit_scale")]
+    pub scale: f32,
+}
+
+fn unit_scale() -> f32 {
+    1.0
+}
+
+impl HidAxis {
+    pub fn value(&self, raw: f32) -> f32 {
+        let value = if self.invert { -raw } else { raw };
+        let value = if self.lever {
+            (value + 1.0) / 2.0
+        } else {
+            value
+        };
+
+        if value.abs() < self.deadzone {
+            0.0
+        } else {
+            (value * self.scale).clamp(-1.0, 1.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HidButton {
+    pub button: GamepadButton,
+    pub action: Action,
+    /// Value set while held when `action` is an axis, lets a button box drive movement
+    #[serde(default = "unit_scale")]
+    pub value: f32,
+}
+
+/// Marks a gamepad entity that was matched to a `HidDevice`
+#[derive(Component, Debug, Clone)]
+pub struct HidPanel(pub HidDevice);
+
+fn load_mappings(mut cmds: Commands) {
+    let res: anyhow::Result<HidMappings> = try {
+        let mappings = fs::read_to_string(config_path(HID_FILE)).context("Read HID mappings")?;
+        toml::from_str(&mappings).context("Parse HID mappings")?
+    };
+
+    let mappings = match res {
+        Ok(mappings) => mappings,
+        Err(err) => {
+            info!("No HID devices mapped: {err:?}");
+            HidMappings::default()
+        }
+    };
+
+    for device in &mappings.devices {
+        for axis in &device.axes {
+            if axis.action.input_control_kind() != InputControlKind::Axis {
+                warn!(
+                    "{}: {:?} is mapped to {:?} which expects a button",
+                    device.label(),
+                    axis.axis,
+                    axis.action
+                );
+            }
+        }
+    }
+
+    cmds.insert_resource(mappings);
+}
+
+fn claim_devices(
+    mut cmds: Commands,
+    mappings: Res<HidMappings>,
+    gamepads: Query<(Entity, &Gamepad, Option<&Name>), Added<Gamepad>>,
+) {
+    for (entity, gamepad, name) in &gamepads {
+        if let Some(device) = mappings.device_for(gamepad, name) {
+            info!("Using {entity} as the HID device {}", device.label());
+            cmds.entity(entity).insert(HidPanel(device.clone()));
+        }
+    }
+}
+
+fn apply_hid_input(
+    roles: Res<GamepadRoles>,
+    panels: Query<(&Gamepad, &HidPanel)>,
+    mut inputs: Query<(&InputRole, &mut ActionState<Action>), With<InputMarker>>,
+    mut last_axes: Local<HashSet<Action>>,
+    mut last_buttons: Local<HashSet<Action>>,
+) {
+    let mut axes = Vec::new();
+    let mut buttons = HashSet::default();
+
+    for (gamepad, HidPanel(device)) in &panels {
+        for axis in &device.axes {
+            let value = axis.value(gamepad.get(axis.axis).unwrap_or_default());
+            if value != 0.0 {
+                axes.push((axis.action, value));
+            }
+        }
+
+        for button in &device.buttons {
+            if !gamepad.pressed(button.button) {
+                continue;
+            }
+
+            match button.action.input_control_kind() {
+                InputControlKind::Axis => axes.push((button.action, button.value)),
+                _ => {
+                    buttons.insert(button.action);
+                }
+            }
+        }
+    }
+
+    for (role, mut action_state) in &mut inputs {
+        let owned = |action: &Action| roles.owner(action) == *role;
+
+        // Like the touch sticks, a deflected axis overrides the gamepad
+        for action in last_axes.iter().filter(|it| owned(it)) {
+            if !axes.iter().any(|(other, _)| other == action) {
+                action_state.set_value(action, 0.0);
+            }
+        }
+        for (action, value) in axes.iter().filter(|(action, _)| owned(action)) {
+            action_state.set_value(action, *value);
+        }
+
+        for action in last_buttons.difference(&buttons).filter(|it| owned(it)) {
+            action_state.release(action);
+        }
+        for action in buttons.iter().filter(|it| owned(it)) {
+            action_state.press(action);
+        }
+    }
+
+    *last_axes = axes.into_iter().map(|(action, _)| action).collect();
+    *last_buttons = buttons;
+}
@@ -1,50 +1,80 @@
 use std::borrow::Cow;
 
-use ahash::HashSet;
+use ahash::{HashMap, HashSet};
 use bevy::{
-    math::{vec3a, Vec3A},
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    math::vec3a,
     prelude::*,
 };
+use bevy_egui::EguiContexts;
 use common::{
+    attitude,
     bundles::MovementContributionBundle,
     components::{
-        Armed, CameraInputRotation, DepthMeasurement, DepthTarget, GenericMotorId,
-        MotorContribution, Motors, MovementAxisMaximums, MovementContribution, Orientation,
-        OrientationTarget, Robot, RobotId,
+        ActuatorIdentity, Altimeter, Armed, BottomLock, CameraInputRotation, DepthMeasurement,
+        DepthTarget, GenericMotorId, Heading, MotorContribution, Motors, MovementAxisMaximums,
+        MovementContribution, Orientation, OrientationTarget, PilotModes, PositionEstimate, Robot,
+        RobotId, ServoPresets, Stations,
     },
     ecs_sync::{NetId, Replicate},
-    events::ResetServo,
-    types::units::Meters,
+    events::{GoToServoPreset, ResetServo},
+    params::{AppParamExt, Param, Params},
+    types::{pilot::PilotMode, units::Meters},
 };
 use leafwing_input_manager::{
-    action_state::ActionState, input_map::InputMap, plugin::InputManagerPlugin, Actionlike,
-    InputManagerBundle,
+    action_state::ActionState,
+    input_map::InputMap,
+    plugin::{InputManagerPlugin, InputManagerSystem},
+    Actionlike, InputManagerBundle,
 };
 use motor_math::{glam::MovementGlam, solve::reverse::Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bindings::{update_keyboard_piloting, BindingProfile, BindingProfiles, KeyboardPiloting},
+    hid::HidMappings,
+    input_shaping::{AxisInputs, InputShaping},
+    macros::InputMacros,
+    photosphere::TakePhotoSphereImage,
+    surface::{local_capabilities, LocalSurfaceMarker},
+    video_display_2d_master::VideoMasterMarker,
+};
 
-use crate::{photosphere::TakePhotoSphereImage, video_display_2d_master::VideoMasterMarker};
-
-// TODO(low): Handle multiple gamepads better
 pub struct InputPlugin;
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<InputInterpolation>()
-            .register_type::<SelectedServo>();
+            .register_type::<SelectedServo>()
+            .register_type::<InputRole>()
+            .init_resource::<GamepadRoles>()
+            .register_param(&DEPTH_TARGET_NUDGE)
+            .register_param(&HEADING_TARGET_NUDGE);
 
         app.add_plugins(InputManagerPlugin::<Action>::default())
+            .add_systems(
+                PreUpdate,
+                filter_captured_input.in_set(InputManagerSystem::Filter),
+            )
             .add_systems(
                 Update,
                 (
-                    attach_to_new_robots,
+                    assign_new_gamepads,
+                    attach_to_robots
+                        .after(assign_new_gamepads)
+                        .after(update_keyboard_piloting),
                     handle_disconnected_robots,
                     movement,
                     arm,
                     depth_hold,
+                    bottom_lock,
                     leveling,
                     trim_orientation,
                     trim_depth,
+                    nudge_depth_target,
+                    nudge_heading_target,
                     servos,
+                    servo_presets,
                     robot_mode,
                     take_photo_sphere_image,
                     // switch_pitch_roll,
@@ -53,68 +83,40 @@ impl Plugin for InputPlugin {
     }
 }
 
-#[derive(Component, Debug, Clone, Default, Reflect)]
-pub struct SelectedServo {
-    pub servo: Option<(GenericMotorId, Cow<'static, str>)>,
-}
-
-#[derive(Component, Debug, Clone, Copy, Reflect, PartialEq)]
-pub struct InputInterpolation {
-    depth_mps: f32,
-    trim_dps: Vec3A,
-    servo_rate: f32,
-
-    power: f32,
-    scale: f32,
-
-    translate_gain: Vec3A,
-    translate_gain_depth_hold: Vec3A,
-    torque_gain: Vec3A,
-    torque_gain_stabalize: Vec3A,
-}
-
-impl InputInterpolation {
-    pub fn interpolate_input(&self, input: f32) -> f32 {
-        input.powf(self.power).copysign(input) * self.scale
-    }
+/// Drops the keyboard and mouse input egui is using before it reaches the action states, so typing
+/// into a text field or scrolling a panel doesn't also fly the robot
+fn filter_captured_input(
+    mut contexts: EguiContexts,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut mouse_buttons: ResMut<ButtonInput<MouseButton>>,
+    mut mouse_scroll: ResMut<AccumulatedMouseScroll>,
+    mut mouse_motion: ResMut<AccumulatedMouseMotion>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
 
-    pub const fn normal() -> Self {
-        Self {
-            depth_mps: 0.3,
-            trim_dps: vec3a(35.0, 35.0, 100.0),
-            servo_rate: 1.5,
-            power: 3.0,
-            scale: 0.8,
-            translate_gain: vec3a(1.0, 1.0, 1.0),
-            translate_gain_depth_hold: vec3a(1.0, 1.0, 0.1),
-            torque_gain: vec3a(1.0, 1.0, 0.5),
-            torque_gain_stabalize: vec3a(0.0, 0.0, 0.0),
-        }
+    if ctx.wants_keyboard_input() {
+        keys.reset_all();
     }
 
-    pub const fn slow() -> Self {
-        Self {
-            scale: 0.4,
-            ..Self::normal()
-        }
+    if ctx.wants_pointer_input() {
+        mouse_buttons.reset_all();
+        mouse_scroll.delta = Vec2::ZERO;
+        mouse_motion.delta = Vec2::ZERO;
     }
+}
 
-    pub const fn precision() -> Self {
-        Self {
-            depth_mps: 0.3,
-            trim_dps: vec3a(25.0, 25.0, 60.0),
-            servo_rate: 1.0,
-            power: 3.0,
-            scale: 0.2,
-            translate_gain: vec3a(1.0, 1.0, 1.0),
-            translate_gain_depth_hold: vec3a(2.0, 1.0, 0.0),
-            torque_gain: vec3a(1.0, 1.0, 0.5),
-            torque_gain_stabalize: vec3a(0.0, 0.0, 0.0),
-        }
-    }
+#[derive(Component, Debug, Clone, Default, Reflect)]
+pub struct SelectedServo {
+    pub servo: Option<(GenericMotorId, Cow<'static, str>)>,
 }
 
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+/// The pilot mode currently applied to an input entity
+#[derive(Component, Debug, Clone, Reflect, PartialEq, Deref)]
+pub struct InputInterpolation(pub PilotMode);
+
+#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Serialize, Deserialize)]
 pub enum Action {
     Arm,
     Disarm,
@@ -124,8 +126,14 @@ pub enum Action {
     // ResetGain,
     ToggleDepthHold,
     ToggleLeveling(LevelingType),
+    /// Holds the heading while translating with the yaw stick centered
+    ToggleHeadingAssist,
+    /// Holds altitude above the bottom, position and heading together
+    ToggleBottomLock,
 
     ToggleRobotMode,
+    /// Cycles the pilot modes backwards
+    PreviousRobotMode,
 
     #[actionlike(Axis)]
     Surge,
@@ -144,6 +152,9 @@ pub enum Action {
     Pitch,
     // #[actionlike(Axis)]
     PitchInverted,
+    /// Analog pitch for inputs like mouse drag, combined with `Pitch`
+    #[actionlike(Axis)]
+    PitchAxis,
     // #[actionlike(Axis)]
     Roll,
     // #[actionlike(Axis)]
@@ -163,114 +174,336 @@ pub enum Action {
     SwitchPitchRoll,
 
     TakePhotoSphereImage,
+
+    /// Nudges the depth target while depth hold is active
+    #[actionlike(Axis)]
+    DepthTargetAxis,
+    /// Turns the heading of the orientation target while leveling is active, positive is clockwise
+    #[actionlike(Axis)]
+    HeadingTargetAxis,
+
+    /// Runs the macro assigned to this slot in the binding profile
+    RunMacro(MacroSlot),
+    /// Moves the servos to the robot's preset in this slot, in the order the robot defines them
+    ServoPreset(PresetSlot),
+
+    CycleCameraLayout,
+    /// Makes the next shown camera the master camera
+    CycleMasterCamera,
+    /// Saves the current frame of the master camera and opens it for annotation
+    CaptureStill,
+    /// Saves a time synchronized frame from every camera with the robot's pose for photogrammetry
+    CaptureBundle,
 }
 
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Default)]
+impl Action {
+    pub const ALL: [Action; 44] = [
+        Action::Arm,
+        Action::Disarm,
+        Action::ToggleDepthHold,
+        Action::ToggleLeveling(LevelingType::Upright),
+        Action::ToggleLeveling(LevelingType::Inverted),
+        Action::ToggleHeadingAssist,
+        Action::ToggleBottomLock,
+        Action::ToggleRobotMode,
+        Action::PreviousRobotMode,
+        Action::Surge,
+        Action::SurgeInverted,
+        Action::Heave,
+        Action::HeaveInverted,
+        Action::Sway,
+        Action::SwayInverted,
+        Action::Pitch,
+        Action::PitchInverted,
+        Action::PitchAxis,
+        Action::Roll,
+        Action::RollInverted,
+        Action::Yaw,
+        Action::YawInverted,
+        Action::Servo,
+        Action::ServoCenter,
+        Action::ServoInverted,
+        Action::SwitchServo,
+        Action::SwitchServoInverted,
+        Action::SelectImportantServo,
+        Action::SwitchPitchRoll,
+        Action::TakePhotoSphereImage,
+        Action::DepthTargetAxis,
+        Action::HeadingTargetAxis,
+        Action::RunMacro(MacroSlot::One),
+        Action::RunMacro(MacroSlot::Two),
+        Action::RunMacro(MacroSlot::Three),
+        Action::RunMacro(MacroSlot::Four),
+        Action::ServoPreset(PresetSlot::One),
+        Action::ServoPreset(PresetSlot::Two),
+        Action::ServoPreset(PresetSlot::Three),
+        Action::ServoPreset(PresetSlot::Four),
+        Action::CycleCameraLayout,
+        Action::CycleMasterCamera,
+        Action::CaptureStill,
+        Action::CaptureBundle,
+    ];
+}
+
+#[derive(
+    Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Default, Serialize, Deserialize,
+)]
 pub enum LevelingType {
     #[default]
     Upright,
     Inverted,
 }
 
+#[derive(
+    Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Default, Serialize, Deserialize,
+)]
+pub enum MacroSlot {
+    #[default]
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+impl MacroSlot {
+    pub const ALL: [MacroSlot; 4] = [
+        MacroSlot::One,
+        MacroSlot::Two,
+        MacroSlot::Three,
+        MacroSlot::Four,
+    ];
+}
+
+#[derive(
+    Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Default, Serialize, Deserialize,
+)]
+pub enum PresetSlot {
+    #[default]
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+impl PresetSlot {
+    pub const ALL: [PresetSlot; 4] = [
+        PresetSlot::One,
+        PresetSlot::Two,
+        PresetSlot::Three,
+        PresetSlot::Four,
+    ];
+
+    /// The index of the robot's preset bound to this slot
+    pub fn index(&self) -> usize {
+        match self {
+            PresetSlot::One => 0,
+            PresetSlot::Two => 1,
+            PresetSlot::Three => 2,
+            PresetSlot::Four => 3,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct InputMarker;
 
-fn attach_to_new_robots(mut cmds: Commands, new_robots: Query<(&NetId, &Name), Added<Robot>>) {
-    for (robot, name) in &new_robots {
-        let mut input_map = InputMap::default();
-
-        input_map.insert(Action::Disarm, GamepadButton::Select);
-        input_map.insert(Action::Arm, GamepadButton::Start);
-
-        input_map.insert(Action::Disarm, KeyCode::Space);
-        input_map.insert(Action::Arm, KeyCode::Enter);
-
-        input_map.insert(
-            Action::ToggleLeveling(LevelingType::Upright),
-            GamepadButton::North,
-        );
-        input_map.insert(
-            Action::ToggleLeveling(LevelingType::Inverted),
-            GamepadButton::South,
-        );
-        input_map.insert(Action::ToggleDepthHold, GamepadButton::East);
-        // input_map.insert(Action::ToggleDepthHold, GamepadButton::North);
-        // input_map.insert(Action::ToggleDepthHold, GamepadButton::South);
-        // input_map.insert(Action::SwitchPitchRoll, GamepadButton::West);
-        input_map.insert(Action::TakePhotoSphereImage, GamepadButton::West);
-
-        input_map.insert_axis(Action::Yaw, GamepadAxis::LeftStickX);
-        input_map.insert_axis(Action::Surge, GamepadAxis::LeftStickY);
-
-        input_map.insert_axis(Action::Sway, GamepadAxis::RightStickX);
-        input_map.insert_axis(Action::Heave, GamepadAxis::RightStickY);
-
-        input_map.insert(Action::ServoInverted, GamepadButton::LeftTrigger);
-        input_map.insert(Action::Servo, GamepadButton::RightTrigger);
-        // input_map.insert(Action::ServoInverted, GamepadButton::RightTrigger2);
-        // input_map.insert(Action::Servo, GamepadButton::LeftTrigger2);
-
-        // input_map.insert(Action::Pitch, GamepadButton::RightTrigger);
-        // input_map.insert(Action::PitchInverted, GamepadButton::LeftTrigger);
-
-        // input_map.insert(Action::Roll, GamepadButton::RightTrigger2);
-        // input_map.insert(Action::RollInverted, GamepadButton::LeftTrigger2);
-        input_map.insert(Action::Pitch, GamepadButton::RightTrigger2);
-        input_map.insert(Action::PitchInverted, GamepadButton::LeftTrigger2);
-
-        input_map.insert(Action::ServoCenter, GamepadButton::DPadUp);
-        // input_map.insert(Action::Servo, GamepadButton::DPadRight);
-        // input_map.insert(Action::ServoInverted, GamepadButton::DPadLeft);
-        input_map.insert(Action::SwitchServo, GamepadButton::DPadRight);
-        input_map.insert(Action::SwitchServoInverted, GamepadButton::DPadLeft);
-        // input_map.insert(Action::SelectImportantServo, GamepadButton::DPadDown);
-        input_map.insert(Action::ToggleRobotMode, GamepadButton::DPadDown);
-
-        input_map.insert(Action::ToggleRobotMode, GamepadButton::Mode);
-        // input_map.insert(Action::ToggleRobotMode, GamepadButton::West);
-
-        // input_map.insert(
-        //     Action::Yaw,
-        //     SingleAxis::symmetric(GamepadAxis::LeftStickX, 0.05),
-        // );
-        // input_map.insert(
-        //     Action::Pitch,
-        //     SingleAxis::symmetric(GamepadAxis::LeftStickY, 0.05),
-        // );
-        //
-        // input_map.insert(
-        //     Action::Sway,
-        //     SingleAxis::symmetric(GamepadAxis::RightStickX, 0.05),
-        // );
-        // input_map.insert(
-        //     Action::Heave,
-        //     SingleAxis::symmetric(GamepadAxis::RightStickY, 0.05),
-        // );
-        //
-        // input_map.insert(Action::Roll, GamepadButton::RightTrigger);
-        // input_map.insert(Action::RollInverted, GamepadButton::LeftTrigger);
-        //
-        // input_map.insert(Action::Surge, GamepadButton::RightTrigger2);
-        // input_map.insert(Action::SurgeInverted, GamepadButton::LeftTrigger2);
-
-        cmds.spawn((
-            SelectedServo::default(),
-            InputManagerBundle::<Action> {
-                // Stores "which actions are currently pressed"
-                action_state: ActionState::default(),
-                // Describes how to convert from player inputs into those actions
-                input_map,
-            },
-            MovementContributionBundle {
-                name: Name::new(format!("HID {name}")),
-                contribution: MovementContribution(MovementGlam::default()),
-                robot: RobotId(*robot),
-            },
-            MotorContribution(Default::default()),
-            InputInterpolation::normal(),
-            InputMarker,
-            Replicate,
-        ));
+/// The operator an input entity belongs to, each role gets its own `InputMarker` entity per robot
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Default)]
+pub enum InputRole {
+    #[default]
+    Pilot,
+    /// Operates the servos
+    CoPilot,
+    Camera,
+}
+
+impl InputRole {
+    pub const ALL: [InputRole; 3] = [InputRole::Pilot, InputRole::CoPilot, InputRole::Camera];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputRole::Pilot => "Pilot",
+            InputRole::CoPilot => "Co-Pilot",
+            InputRole::Camera => "Camera Operator",
+        }
+    }
+
+    /// The role that would like to handle `action`
+    fn preferred_for(action: &Action) -> Self {
+        match action {
+            Action::Servo
+            | Action::ServoCenter
+            | Action::ServoInverted
+            | Action::SwitchServo
+            | Action::SwitchServoInverted
+            | Action::SelectImportantServo
+            | Action::ServoPreset(_) => InputRole::CoPilot,
+            Action::TakePhotoSphereImage
+            | Action::CycleCameraLayout
+            | Action::CycleMasterCamera
+            | Action::CaptureStill
+            | Action::CaptureBundle => InputRole::Camera,
+            _ => InputRole::Pilot,
+        }
+    }
+}
+
+/// Which role each connected gamepad has been given
+#[derive(Resource, Debug, Clone, Default)]
+pub struct GamepadRoles {
+    pub assignments: HashMap<Entity, InputRole>,
+}
+
+impl GamepadRoles {
+    pub fn gamepad(&self, role: InputRole) -> Option<Entity> {
+        self.assignments
+            .iter()
+            .find(|(_, other)| **other == role)
+            .map(|(gamepad, _)| *gamepad)
+    }
+
+    /// The role that handles `action`, the pilot picks up the actions of any role that has no
+    /// gamepad assigned
+    pub fn owner(&self, action: &Action) -> InputRole {
+        let role = InputRole::preferred_for(action);
+
+        if role == InputRole::Pilot || self.gamepad(role).is_some() {
+            role
+        } else {
+            InputRole::Pilot
+        }
+    }
+
+    /// The pilot always has an input entity so keyboard controls keep working
+    fn is_active(&self, role: InputRole) -> bool {
+        role == InputRole::Pilot || self.gamepad(role).is_some()
+    }
+
+    /// Builds the input map for `role` from `profile`, only containing the actions the role owns
+    pub fn input_map(&self, role: InputRole, profile: &BindingProfile) -> InputMap<Action> {
+        let mut input_map = profile.to_input_map();
+
+        for action in Action::ALL {
+            if self.owner(&action) != role {
+                input_map.clear_action(&action);
+            }
+        }
+
+        if let Some(gamepad) = self.gamepad(role) {
+            input_map.set_gamepad(gamepad);
+        }
+
+        input_map
+    }
+}
+
+/// Gives the first connected gamepad to the pilot, devices mapped in `hid.toml` never get a role
+fn assign_new_gamepads(
+    mut roles: ResMut<GamepadRoles>,
+    hid: Res<HidMappings>,
+    new_gamepads: Query<(Entity, &Gamepad, Option<&Name>), Added<Gamepad>>,
+    mut removed_gamepads: RemovedComponents<Gamepad>,
+) {
+    for gamepad in removed_gamepads.read() {
+        if let Some(role) = roles.assignments.remove(&gamepad) {
+            warn!("{} gamepad disconnected", role.name());
+        }
+    }
+
+    for (gamepad, device, name) in &new_gamepads {
+        if hid.device_for(device, name).is_some() {
+            continue;
+        }
+
+        if roles.gamepad(InputRole::Pilot).is_none() {
+            info!("Assigning new gamepad to pilot");
+            roles.assignments.insert(gamepad, InputRole::Pilot);
+        }
+    }
+}
+
+/// Keeps one input entity per active role for every robot
+fn attach_to_robots(
+    mut cmds: Commands,
+    roles: Res<GamepadRoles>,
+    profiles: Res<BindingProfiles>,
+    keyboard: Res<KeyboardPiloting>,
+    robots: Query<(&NetId, &Name), With<Robot>>,
+    new_robots: Query<(), Added<Robot>>,
+    inputs: Query<(Entity, &RobotId, &InputRole), With<InputMarker>>,
+    modes: Query<&PilotModes, With<LocalSurfaceMarker>>,
+) {
+    if !roles.is_changed()
+        && !profiles.is_changed()
+        && !keyboard.is_changed()
+        && new_robots.is_empty()
+    {
+        return;
+    }
+
+    let initial_mode = modes
+        .get_single()
+        .ok()
+        .and_then(|it| it.enabled().next())
+        .cloned()
+        .unwrap_or_else(PilotMode::normal);
+
+    for (robot, name) in &robots {
+        let mut profile = profiles.profile_for(name.as_str());
+
+        if keyboard.active {
+            profile.bindings.extend(BindingProfile::keyboard().bindings);
+        }
+
+        for role in InputRole::ALL {
+            let existing = inputs
+                .iter()
+                .find(|(_, robot_id, other_role)| robot_id.0 == *robot && **other_role == role)
+                .map(|(entity, ..)| entity);
+
+            match (existing, roles.is_active(role)) {
+                (Some(entity), true) => {
+                    cmds.entity(entity).insert((
+                        roles.input_map(role, &profile),
+                        profile.shaping,
+                        InputMacros(profile.macros.clone()),
+                    ));
+                }
+                (Some(entity), false) => {
+                    cmds.entity(entity).despawn();
+                }
+                (None, true) => {
+                    let name = if role == InputRole::Pilot {
+                        format!("HID {name}")
+                    } else {
+                        format!("HID {name} ({})", role.name())
+                    };
+
+                    cmds.spawn((
+                        SelectedServo::default(),
+                        InputManagerBundle::<Action> {
+                            // Stores "which actions are currently pressed"
+                            action_state: ActionState::default(),
+                            // Describes how to convert from player inputs into those actions
+                            input_map: roles.input_map(role, &profile),
+                        },
+                        MovementContributionBundle {
+                            name: Name::new(name),
+                            contribution: MovementContribution(MovementGlam::default()),
+                            robot: RobotId(*robot),
+                        },
+                        MotorContribution(Default::default()),
+                        InputInterpolation(initial_mode.clone()),
+                        profile.shaping,
+                        InputMacros(profile.macros.clone()),
+                        role,
+                        InputMarker,
+                        Replicate,
+                    ));
+                }
+                (None, false) => {}
+            }
+        }
     }
 }
 
@@ -290,10 +523,18 @@ fn handle_disconnected_robots(
     }
 }
 
-// TODO(mid): Remap sticks to square. See http://theinstructionlimit.com/squaring-the-thumbsticks
 fn movement(
     mut cmds: Commands,
-    inputs: Query<(Entity, &RobotId, &ActionState<Action>, &InputInterpolation), With<InputMarker>>,
+    inputs: Query<
+        (
+            Entity,
+            &RobotId,
+            &ActionState<Action>,
+            &InputInterpolation,
+            &InputShaping,
+        ),
+        With<InputMarker>,
+    >,
     robots: Query<
         (
             &MovementAxisMaximums,
@@ -306,7 +547,7 @@ fn movement(
     >,
     selected_camera: Query<(&CameraInputRotation, &RobotId), With<VideoMasterMarker>>,
 ) {
-    for (entity, robot, action_state, interpolation) in &inputs {
+    for (entity, robot, action_state, interpolation, shaping) in &inputs {
         let Some((
             MovementAxisMaximums(maximums),
             depth_target,
@@ -341,17 +582,11 @@ fn movement(
             interpolation.torque_gain
         };
 
-        let force = vec3a(
-            interpolation.interpolate_input(
-                action_state.value(&Action::Sway) - action_state.value(&Action::SwayInverted),
-            ),
-            interpolation.interpolate_input(
-                action_state.value(&Action::Surge) - action_state.value(&Action::SurgeInverted),
-            ),
-            interpolation.interpolate_input(
-                action_state.value(&Action::Heave) - action_state.value(&Action::HeaveInverted),
-            ),
-        );
+        let inputs = shaping
+            .with_expo(interpolation.expo)
+            .apply(AxisInputs::from_action_state(action_state));
+
+        let force = vec3a(inputs.sway, inputs.surge, inputs.heave) * interpolation.scale;
         let force = input_rotation * force;
         let force = force
             * vec3a(
@@ -361,19 +596,7 @@ fn movement(
             )
             * translate_gain;
 
-        let torque = vec3a(
-            interpolation.interpolate_input(
-                action_state.button_value(&Action::Pitch)
-                    - action_state.button_value(&Action::PitchInverted),
-            ),
-            interpolation.interpolate_input(
-                action_state.button_value(&Action::Roll)
-                    - action_state.button_value(&Action::RollInverted),
-            ),
-            interpolation.interpolate_input(
-                -(action_state.value(&Action::Yaw) - action_state.value(&Action::YawInverted)),
-            ),
-        );
+        let torque = vec3a(inputs.pitch, inputs.roll, -inputs.yaw) * interpolation.scale;
         let torque = input_rotation * torque;
         let torque = torque
             * vec3a(
@@ -387,24 +610,7 @@ fn movement(
         // interperting z as local vs global
         let force = if depth_target.is_some() {
             if let Some(orientation) = orientation {
-                // TODO: Validate this actually works, and make it into a helper function, also
-                // used for heading display
-                let mut yaw = orientation.0;
-                if yaw.z.abs() * yaw.z.abs() + yaw.w.abs() * yaw.w.abs() > 0.1 {
-                    yaw.x = 0.0;
-                    yaw.y = 0.0;
-                    yaw = yaw.normalize()
-                } else {
-                    yaw *= Quat::from_rotation_y(180f32.to_radians());
-                    yaw.x = 0.0;
-                    yaw.y = 0.0;
-                    yaw = -yaw.normalize();
-                    // yaw *= Quat::from_rotation_y(180f32.to_radians()).inverse();
-                }
-
-                let world_force = yaw * force;
-
-                orientation.0.inverse() * world_force
+                attitude::heading_to_body(orientation.0, force)
             } else {
                 force
             }
@@ -428,16 +634,23 @@ fn movement(
 fn arm(
     mut cmds: Commands,
     inputs: Query<(&RobotId, &ActionState<Action>), With<InputMarker>>,
-    robots: Query<(Entity, &RobotId), With<Robot>>,
+    robots: Query<(Entity, &RobotId, Option<&Stations>), With<Robot>>,
+    surface: Query<&NetId, With<LocalSurfaceMarker>>,
 ) {
     for (robot, action_state) in &inputs {
         let disarm = action_state.just_pressed(&Action::Disarm);
         let arm = action_state.just_pressed(&Action::Arm);
 
-        let robot = robots.iter().find(|&(_, other_robot)| robot == other_robot);
+        let robot = robots
+            .iter()
+            .find(|&(_, other_robot, _)| robot == other_robot);
 
-        if let Some((robot, _)) = robot {
-            if disarm {
+        if let Some((robot, _, stations)) = robot {
+            let capabilities = local_capabilities(stations, surface.get_single().ok());
+
+            if (arm || disarm) && !capabilities.arming {
+                warn!("This station is not permitted to arm or disarm");
+            } else if disarm {
                 info!("Disarming");
                 cmds.entity(robot).insert(Armed::Disarmed);
             } else if arm {
@@ -483,6 +696,67 @@ fn depth_hold(
     }
 }
 
+/// Altimeter readings the sonar is less sure of than this aren't locked onto
+const MIN_ALTIMETER_CONFIDENCE: f32 = 0.5;
+
+fn bottom_lock(
+    mut cmds: Commands,
+    inputs: Query<(&RobotId, &ActionState<Action>), With<InputMarker>>,
+    robots: Query<
+        (
+            Entity,
+            Option<&BottomLock>,
+            Option<&Orientation>,
+            Option<&Altimeter>,
+            Option<&PositionEstimate>,
+            &RobotId,
+        ),
+        With<Robot>,
+    >,
+) {
+    for (robot, action_state) in &inputs {
+        let toggle = action_state.just_pressed(&Action::ToggleBottomLock);
+
+        let robot = robots
+            .iter()
+            .find(|&(.., other_robot)| robot == other_robot);
+
+        if let Some((robot, lock, orientation, altimeter, position, _)) = robot {
+            if toggle {
+                if lock.is_some() {
+                    info!("Clear Bottom Lock");
+                    cmds.entity(robot).remove::<BottomLock>();
+                    continue;
+                }
+
+                let Some(orientation) = orientation else {
+                    warn!("Cannot Bottom Lock without an orientation");
+                    continue;
+                };
+
+                // Sub locks whose sensor isn't available are left off, the rest still engage
+                let Heading(heading) = orientation.heading();
+                let lock = BottomLock {
+                    altitude: altimeter
+                        .filter(|it| it.confidence >= MIN_ALTIMETER_CONFIDENCE)
+                        .map(|it| {
+                            // Matches the robot's tilt correction of the slant range
+                            let down = orientation.0 * vec3a(0.0, 0.0, -1.0);
+                            Meters(it.altitude.0 * -down.z)
+                        }),
+                    position: position.map(|it| it.position),
+                    heading,
+                };
+
+                info!("Set Bottom Lock: {lock:?}");
+                cmds.entity(robot).insert(lock);
+            }
+        } else if toggle {
+            warn!("No ROV attached");
+        }
+    }
+}
+
 fn leveling(
     mut cmds: Commands,
     inputs: Query<(&RobotId, &ActionState<Action>), With<InputMarker>>,
@@ -500,19 +774,12 @@ fn leveling(
 
         if let Some((robot, orientation, orientation_target, _)) = robot {
             if toggle_upright || toggle_inverted {
-                let mut new_target = orientation.0;
-
-                // Only keep yaw component
-                new_target.x = 0.0;
-                new_target.y = 0.0;
-                let new_target = new_target.normalize();
-
-                // Flip if inverted is selected
-                let new_target = if toggle_upright {
-                    new_target
+                let leveling = if toggle_upright {
+                    LevelingType::Upright
                 } else {
-                    new_target * Quat::from_rotation_y(180f32.to_radians())
+                    LevelingType::Inverted
                 };
+                let new_target = leveling_target(orientation.0, leveling);
 
                 match orientation_target {
                     // FIXME: Make switching from upright to inverted easier
@@ -538,14 +805,33 @@ fn leveling(
     }
 }
 
+/// The orientation target that levels the robot while keeping its current heading
+pub fn leveling_target(orientation: Quat, leveling: LevelingType) -> Quat {
+    let new_target = attitude::yaw_rotation(orientation);
+
+    // Flip if inverted is selected
+    match leveling {
+        LevelingType::Upright => new_target,
+        LevelingType::Inverted => new_target * Quat::from_rotation_y(180f32.to_radians()),
+    }
+}
+
 fn trim_orientation(
     mut cmds: Commands,
-    inputs: Query<(&RobotId, &ActionState<Action>, &InputInterpolation), With<InputMarker>>,
+    inputs: Query<
+        (
+            &RobotId,
+            &ActionState<Action>,
+            &InputInterpolation,
+            &InputShaping,
+        ),
+        With<InputMarker>,
+    >,
     robots: Query<(Entity, &Orientation, Option<&OrientationTarget>, &RobotId), With<Robot>>,
     selected_camera: Query<(&CameraInputRotation, &RobotId), With<VideoMasterMarker>>,
     time: Res<Time<Real>>,
 ) {
-    for (robot, action_state, interpolation) in &inputs {
+    for (robot, action_state, interpolation, shaping) in &inputs {
         let input_rotation = selected_camera
             .iter()
             .filter(|(_, robot_id)| robot_id.0 == robot.0)
@@ -553,19 +839,10 @@ fn trim_orientation(
             .next()
             .unwrap_or_default();
 
-        let torque = vec3a(
-            interpolation.interpolate_input(
-                action_state.button_value(&Action::Pitch)
-                    - action_state.button_value(&Action::PitchInverted),
-            ),
-            interpolation.interpolate_input(
-                action_state.button_value(&Action::Roll)
-                    - action_state.button_value(&Action::RollInverted),
-            ),
-            interpolation.interpolate_input(
-                -(action_state.value(&Action::Yaw) - action_state.value(&Action::YawInverted)),
-            ),
-        );
+        let inputs = shaping
+            .with_expo(interpolation.expo)
+            .apply(AxisInputs::from_action_state(action_state));
+        let torque = vec3a(inputs.pitch, inputs.roll, -inputs.yaw) * interpolation.scale;
         let torque = input_rotation * torque;
         let torque = torque * interpolation.trim_dps;
 
@@ -605,14 +882,24 @@ fn trim_orientation(
 
 fn trim_depth(
     mut cmds: Commands,
-    inputs: Query<(&RobotId, &ActionState<Action>, &InputInterpolation), With<InputMarker>>,
+    inputs: Query<
+        (
+            &RobotId,
+            &ActionState<Action>,
+            &InputInterpolation,
+            &InputShaping,
+        ),
+        With<InputMarker>,
+    >,
     robots: Query<(Entity, Option<&DepthTarget>, Option<&Orientation>, &RobotId), With<Robot>>,
     time: Res<Time<Real>>,
 ) {
-    for (robot, action_state, interpolation) in &inputs {
-        let z = interpolation.interpolate_input(
-            action_state.value(&Action::Heave) - action_state.value(&Action::HeaveInverted),
-        );
+    for (robot, action_state, interpolation, shaping) in &inputs {
+        let z = shaping
+            .with_expo(interpolation.expo)
+            .apply(AxisInputs::from_action_state(action_state))
+            .heave
+            * interpolation.scale;
 
         let robot = robots
             .iter()
@@ -643,6 +930,75 @@ fn trim_depth(
     }
 }
 
+const DEPTH_TARGET_NUDGE: Param<f32> = Param::new(
+    "surface.input.depth_target_nudge",
+    "Input",
+    "Meters the depth target moves per line scrolled",
+    0.05,
+    0.005,
+    0.5,
+);
+
+fn nudge_depth_target(
+    mut cmds: Commands,
+    inputs: Query<(&RobotId, &ActionState<Action>), With<InputMarker>>,
+    robots: Query<(Entity, &DepthTarget, &RobotId), With<Robot>>,
+    params: Res<Params>,
+) {
+    for (robot, action_state) in &inputs {
+        let nudge = action_state.value(&Action::DepthTargetAxis);
+        if nudge == 0.0 {
+            continue;
+        }
+
+        let Some((robot, &DepthTarget(Meters(depth_target)), _)) = robots
+            .iter()
+            .find(|&(_, _, other_robot)| robot == other_robot)
+        else {
+            continue;
+        };
+
+        // Scrolling up should cause upward movement, ie depth should decrease
+        let depth_target = (depth_target - nudge * params.get(&DEPTH_TARGET_NUDGE)).max(0.0);
+        cmds.entity(robot).insert(DepthTarget(depth_target.into()));
+    }
+}
+
+const HEADING_TARGET_NUDGE: Param<f32> = Param::new(
+    "surface.input.heading_target_nudge",
+    "Input",
+    "Degrees the heading target turns per unit of the heading target axis",
+    5.0,
+    0.5,
+    45.0,
+);
+
+fn nudge_heading_target(
+    mut cmds: Commands,
+    inputs: Query<(&RobotId, &ActionState<Action>), With<InputMarker>>,
+    robots: Query<(Entity, &OrientationTarget, &RobotId), With<Robot>>,
+    params: Res<Params>,
+) {
+    for (robot, action_state) in &inputs {
+        let nudge = action_state.value(&Action::HeadingTargetAxis);
+        if nudge == 0.0 {
+            continue;
+        }
+
+        let Some((robot, &OrientationTarget(orientation_target), _)) = robots
+            .iter()
+            .find(|&(_, _, other_robot)| robot == other_robot)
+        else {
+            continue;
+        };
+
+        // Headings increase clockwise, the opposite of yaw
+        let turn = Quat::from_rotation_z(-(nudge * params.get(&HEADING_TARGET_NUDGE)).to_radians());
+        cmds.entity(robot)
+            .insert(OrientationTarget(turn * orientation_target));
+    }
+}
+
 fn servos(
     mut cmds: Commands,
     mut inputs: Query<
@@ -658,7 +1014,7 @@ fn servos(
     >,
     mut writer: EventWriter<ResetServo>,
     robots: Query<(&Motors, &RobotId), With<Robot>>,
-    servos: Query<(&GenericMotorId, &Name, &RobotId)>,
+    servos: Query<(&ActuatorIdentity, &RobotId)>,
 ) {
     for (entity, robot_id, action_state, interpolation, mut selected_servo) in &mut inputs {
         let center = action_state.just_pressed(&Action::ServoCenter);
@@ -708,10 +1064,9 @@ fn servos(
                 let servo_id = motors.ids[idx];
                 let servo_name = servos
                     .iter()
-                    .filter(|(_, _, servo_robot_id)| **servo_robot_id == *robot_id)
-                    .filter(|(servo_channel, _, _)| **servo_channel == servo_id)
-                    .map(|(_, name, _)| Cow::from(name.as_str().to_owned()))
-                    .next();
+                    .filter(|(_, servo_robot_id)| **servo_robot_id == *robot_id)
+                    .find(|(identity, _)| identity.channel() == servo_id)
+                    .map(|(identity, _)| Cow::from(identity.name.clone()));
                 selected_servo.servo =
                     Some((servo_id, servo_name.unwrap_or("Unknown Servo".into())));
             }
@@ -734,20 +1089,57 @@ fn servos(
     }
 }
 
+fn servo_presets(
+    inputs: Query<(&RobotId, &ActionState<Action>, &InputInterpolation), With<InputMarker>>,
+    robots: Query<(&ServoPresets, &RobotId), With<Robot>>,
+    mut writer: EventWriter<GoToServoPreset>,
+) {
+    for (robot_id, action_state, interpolation) in &inputs {
+        let Some((presets, _)) = robots
+            .iter()
+            .find(|&(_, other_robot_id)| robot_id == other_robot_id)
+        else {
+            continue;
+        };
+
+        for slot in PresetSlot::ALL {
+            if !action_state.just_pressed(&Action::ServoPreset(slot)) {
+                continue;
+            }
+
+            let Some(preset) = presets.0.get(slot.index()) else {
+                warn!("No servo preset in slot {slot:?}");
+                continue;
+            };
+
+            // Presets move at the same speed the servos are driven by hand
+            writer.send(GoToServoPreset {
+                preset: preset.name.clone(),
+                rate: Some(interpolation.servo_rate),
+            });
+        }
+    }
+}
+
 fn robot_mode(
     mut inputs: Query<(&ActionState<Action>, &mut InputInterpolation), With<InputMarker>>,
+    modes: Query<&PilotModes, With<LocalSurfaceMarker>>,
 ) {
+    let Ok(modes) = modes.get_single() else {
+        return;
+    };
+
     for (action_state, mut interpolation) in &mut inputs {
-        let toggle = action_state.just_pressed(&Action::ToggleRobotMode);
+        let mode = if action_state.just_pressed(&Action::ToggleRobotMode) {
+            modes.next_after(&interpolation.name)
+        } else if action_state.just_pressed(&Action::PreviousRobotMode) {
+            modes.previous_before(&interpolation.name)
+        } else {
+            None
+        };
 
-        if toggle {
-            if *interpolation == InputInterpolation::normal() {
-                *interpolation = InputInterpolation::slow()
-            } else if *interpolation == InputInterpolation::slow() {
-                *interpolation = InputInterpolation::precision()
-            } else {
-                *interpolation = InputInterpolation::normal()
-            }
+        if let Some(mode) = mode {
+            interpolation.0 = mode.clone();
         }
     }
 }
@@ -0,0 +1,132 @@
+//! Headless mode that only reads gamepads and pilots the robot, for setups where the pilot
+//! station and the video wall are separate computers
+//!
+//! Started with `--input-client <robot host>`. The input entities are replicated to the robot
+//! exactly like the full control station's, so the video wall should be started with
+//! `--co-pilot` to leave the pilot role to this machine.
+
+use std::time::Duration;
+
+use bevy::{
+    app::ScheduleRunnerPlugin, gilrs::GilrsPlugin, input::InputPlugin as BevyInputPlugin,
+    log::LogPlugin, prelude::*,
+};
+use bevy_tokio_tasks::{TokioTasksPlugin, TokioTasksRuntime};
+use common::{
+    components::{Robot, Stations, SurfaceRole},
+    ecs_sync::NetId,
+    sync::SyncRole,
+    CommonPlugins,
+};
+
+use crate::{
+    bindings::BindingsPlugin,
+    heading_assist::HeadingAssistPlugin,
+    hid::HidPlugin,
+    input::InputPlugin,
+    macros::InputMacroPlugin,
+    pilot_modes::PilotModesPlugin,
+    settings::connect_to_host,
+    surface::{LocalSurfaceMarker, SurfacePlugin},
+};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Runs the input client until it is interrupted
+pub fn run(host: String) -> anyhow::Result<()> {
+    info!("---------- Starting Input Client ----------");
+
+    App::new()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / 100.0,
+            ))),
+            LogPlugin::default(),
+            BevyInputPlugin,
+            GilrsPlugin,
+            TokioTasksPlugin::default(),
+            CommonPlugins {
+                name: "Remote Pilot".to_owned(),
+                role: SyncRole::Client,
+            },
+            SurfacePlugin(SurfaceRole::Pilot),
+            InputPlugin,
+            InputMacroPlugin,
+            BindingsPlugin,
+            HidPlugin,
+            PilotModesPlugin,
+            HeadingAssistPlugin,
+            InputClientPlugin { host },
+        ))
+        .run();
+
+    info!("---------- Input Client Exited Cleanly ----------");
+
+    Ok(())
+}
+
+struct InputClientPlugin {
+    host: String,
+}
+
+impl Plugin for InputClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputClientHost(self.host.clone()))
+            .add_systems(Update, (reconnect, report_role));
+    }
+}
+
+#[derive(Resource)]
+struct InputClientHost(String);
+
+/// Keeps trying the robot until it is connected, and again after it drops
+fn reconnect(
+    mut last_attempt: Local<Option<Duration>>,
+    host: Res<InputClientHost>,
+    time: Res<Time<Real>>,
+    runtime: Res<TokioTasksRuntime>,
+    robots: Query<(), With<Robot>>,
+) {
+    if !robots.is_empty() {
+        *last_attempt = None;
+        return;
+    }
+
+    let now = time.elapsed();
+    if last_attempt.is_some_and(|it| now - it < RECONNECT_INTERVAL) {
+        return;
+    }
+    *last_attempt = Some(now);
+
+    info!("Connecting to {}", host.0);
+    connect_to_host(&runtime, host.0.clone());
+}
+
+/// Another station may already hold the pilot role, in which case the gamepad does nothing
+fn report_role(
+    mut last_role: Local<Option<SurfaceRole>>,
+    robots: Query<&Stations, With<Robot>>,
+    surface: Query<&NetId, With<LocalSurfaceMarker>>,
+) {
+    let Ok(net_id) = surface.get_single() else {
+        return;
+    };
+    let role = robots
+        .iter()
+        .find_map(|it| it.get(*net_id))
+        .map(|it| it.role);
+
+    if role == *last_role {
+        return;
+    }
+    *last_role = role;
+
+    match role {
+        Some(SurfaceRole::Pilot) => info!("Piloting the robot"),
+        Some(role) => warn!(
+            "Joined as a {}, start the video wall's control station with --co-pilot",
+            role.name()
+        ),
+        None => {}
+    }
+}
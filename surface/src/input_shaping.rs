@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use leafwing_input_manager::action_state::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::input::Action;
+
+/// Shaping applied to the raw stick values before they are scaled by the robot mode
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct InputShaping {
+    /// Remaps each stick's circular range onto a square so diagonals can reach full output.
+    /// See http://theinstructionlimit.com/squaring-the-thumbsticks
+    pub square_sticks: bool,
+
+    pub surge: AxisShaping,
+    pub sway: AxisShaping,
+    pub heave: AxisShaping,
+    pub pitch: AxisShaping,
+    pub roll: AxisShaping,
+    pub yaw: AxisShaping,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisShaping {
+    /// Inputs with a magnitude below this are ignored, the remaining range is rescaled to 0..1
+    pub deadzone: f32,
+    /// Blend between a linear (0.0) and cubic (1.0) response
+    pub expo: f32,
+}
+
+impl Default for AxisShaping {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.0,
+            expo: 1.0,
+        }
+    }
+}
+
+impl AxisShaping {
+    pub fn apply(&self, input: f32) -> f32 {
+        let magnitude = input.abs();
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+
+        let x = ((magnitude - self.deadzone) / (1.0 - self.deadzone)).min(1.0);
+        let shaped = (1.0 - self.expo) * x + self.expo * x.powi(3);
+
+        shaped.copysign(input)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShapedAxis {
+    Surge,
+    Sway,
+    Heave,
+    Pitch,
+    Roll,
+    Yaw,
+}
+
+impl ShapedAxis {
+    pub const ALL: [ShapedAxis; 6] = [
+        ShapedAxis::Surge,
+        ShapedAxis::Sway,
+        ShapedAxis::Heave,
+        ShapedAxis::Pitch,
+        ShapedAxis::Roll,
+        ShapedAxis::Yaw,
+    ];
+}
+
+/// Movement inputs in the range -1..1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AxisInputs {
+    pub surge: f32,
+    pub sway: f32,
+    pub heave: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub yaw: f32,
+}
+
+impl AxisInputs {
+    pub fn from_action_state(action_state: &ActionState<Action>) -> Self {
+        let axis = |positive, negative| {
+            (action_state.value(&positive) - action_state.value(&negative)).clamp(-1.0, 1.0)
+        };
+        let button = |positive, negative| {
+            action_state.button_value(&positive) - action_state.button_value(&negative)
+        };
+
+        Self {
+            surge: axis(Action::Surge, Action::SurgeInverted),
+            sway: axis(Action::Sway, Action::SwayInverted),
+            heave: axis(Action::Heave, Action::HeaveInverted),
+            pitch: (button(Action::Pitch, Action::PitchInverted)
+                + action_state.value(&Action::PitchAxis))
+            .clamp(-1.0, 1.0),
+            roll: button(Action::Roll, Action::RollInverted),
+            yaw: axis(Action::Yaw, Action::YawInverted),
+        }
+    }
+
+    pub fn get(&self, axis: ShapedAxis) -> f32 {
+        match axis {
+            ShapedAxis::Surge => self.surge,
+            ShapedAxis::Sway => self.sway,
+            ShapedAxis::Heave => self.heave,
+            ShapedAxis::Pitch => self.pitch,
+            ShapedAxis::Roll => self.roll,
+            ShapedAxis::Yaw => self.yaw,
+        }
+    }
+}
+
+impl InputShaping {
+    pub fn axis(&self, axis: ShapedAxis) -> &AxisShaping {
+        match axis {
+            ShapedAxis::Surge => &self.surge,
+            ShapedAxis::Sway => &self.sway,
+            ShapedAxis::Heave => &self.heave,
+            ShapedAxis::Pitch => &self.pitch,
+            ShapedAxis::Roll => &self.roll,
+            ShapedAxis::Yaw => &self.yaw,
+        }
+    }
+
+    pub fn axis_mut(&mut self, axis: ShapedAxis) -> &mut AxisShaping {
+        match axis {
+            ShapedAxis::Surge => &mut self.surge,
+            ShapedAxis::Sway => &mut self.sway,
+            ShapedAxis::Heave => &mut self.heave,
+            ShapedAxis::Pitch => &mut self.pitch,
+            ShapedAxis::Roll => &mut self.roll,
+            ShapedAxis::Yaw => &mut self.yaw,
+        }
+    }
+
+    /// Replaces the expo of every axis, used by pilot modes that change the feel of the sticks
+    pub fn with_expo(mut self, expo: Option<f32>) -> Self {
+        if let Some(expo) = expo {
+            for axis in ShapedAxis::ALL {
+                self.axis_mut(axis).expo = expo.clamp(0.0, 1.0);
+            }
+        }
+
+        self
+    }
+
+    pub fn apply(&self, inputs: AxisInputs) -> AxisInputs {
+        let mut inputs = inputs;
+
+        if self.square_sticks {
+            // Matches the default bindings, yaw and surge share the left stick while sway and
+            // heave share the right stick
+            (inputs.yaw, inputs.surge) = square_stick(inputs.yaw, inputs.surge);
+            (inputs.sway, inputs.heave) = square_stick(inputs.sway, inputs.heave);
+        }
+
+        AxisInputs {
+            surge: self.surge.apply(inputs.surge),
+            sway: self.sway.apply(inputs.sway),
+            heave: self.heave.apply(inputs.heave),
+            pitch: self.pitch.apply(inputs.pitch),
+            roll: self.roll.apply(inputs.roll),
+            yaw: self.yaw.apply(inputs.yaw),
+        }
+    }
+}
+
+fn square_stick(x: f32, y: f32) -> (f32, f32) {
+    let max = x.abs().max(y.abs());
+    if max <= f32::EPSILON {
+        return (x, y);
+    }
+
+    let scale = Vec2::new(x, y).length() / max;
+
+    ((x * scale).clamp(-1.0, 1.0), (y * scale).clamp(-1.0, 1.0))
+}
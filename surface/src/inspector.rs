@@ -0,0 +1,344 @@
+//! ECS inspector for bring-up, replaces the stock world inspector's flat entity dump
+//!
+//! Entities are grouped by the robot they belong to and can be filtered by where they came from
+//! and searched by name or component. Components can be pinned to watch their values while
+//! browsing elsewhere.
+
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::EguiContext;
+use bevy_inspector_egui::{bevy_inspector, DefaultInspectorConfigPlugin};
+use common::{
+    components::{Robot, RobotId},
+    ecs_sync::{ForignOwned, NetId, Replicate},
+};
+use egui::{CollapsingHeader, Color32, RichText, ScrollArea};
+
+use crate::ui::ShowInspector;
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(DefaultInspectorConfigPlugin)
+            .init_resource::<InspectorState>()
+            .add_systems(
+                Update,
+                inspector_ui.run_if(resource_exists::<ShowInspector>),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct InspectorState {
+    search: String,
+    origin: Origin,
+    selected: Option<Entity>,
+    pinned: Vec<Pin>,
+}
+
+/// Where an entity came from, as far as replication is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Origin {
+    #[default]
+    All,
+    /// Never replicated
+    Local,
+    /// Replicated from this surface
+    Owned,
+    /// Replicated from a peer
+    Remote,
+}
+
+impl Origin {
+    const ALL: [Origin; 4] = [Origin::All, Origin::Local, Origin::Owned, Origin::Remote];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Origin::All => "All",
+            Origin::Local => "Local",
+            Origin::Owned => "Owned",
+            Origin::Remote => "Remote",
+        }
+    }
+}
+
+struct Pin {
+    entity: Entity,
+    /// Type path of the component
+    component: String,
+}
+
+struct EntityRow {
+    entity: Entity,
+    label: String,
+    origin: Origin,
+    components: Vec<ComponentRow>,
+}
+
+struct ComponentRow {
+    /// Type path when the component is reflected, otherwise its type name
+    name: String,
+    reflected: bool,
+}
+
+/// The last segment of a type path, keeping generics
+fn short_name(type_path: &str) -> &str {
+    let base = type_path.split('<').next().unwrap_or(type_path);
+    let start = base.rfind("::").map_or(0, |it| it + 2);
+
+    &type_path[start..]
+}
+
+fn entity_label(world: &World, entity: Entity) -> String {
+    match world.get::<Name>(entity) {
+        Some(name) => format!("{name} ({entity})"),
+        None => format!("Entity {entity}"),
+    }
+}
+
+fn collect_rows(world: &mut World) -> BTreeMap<Option<String>, Vec<EntityRow>> {
+    let mut robots = world.query_filtered::<(&NetId, &Name), With<Robot>>();
+    let robots = robots
+        .iter(world)
+        .map(|(net_id, name)| (*net_id, name.to_string()))
+        .collect::<Vec<_>>();
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    let mut groups = BTreeMap::<_, Vec<_>>::new();
+
+    for entity_ref in world.iter_entities() {
+        let entity = entity_ref.id();
+
+        let origin = if entity_ref.contains::<ForignOwned>() {
+            Origin::Remote
+        } else if entity_ref.contains::<Replicate>() {
+            Origin::Owned
+        } else {
+            Origin::Local
+        };
+
+        // Robots are grouped with the entities that belong to them
+        let robot_id = entity_ref
+            .get::<RobotId>()
+            .map(|it| it.0)
+            .or_else(|| entity_ref.get::<NetId>().copied())
+            .filter(|it| *it != NetId::invalid());
+        let group = robot_id
+            .and_then(|robot_id| robots.iter().find(|(net_id, _)| *net_id == robot_id))
+            .map(|(_, name)| name.clone());
+
+        let mut components = entity_ref
+            .archetype()
+            .components()
+            .filter_map(|id| world.components().get_info(id))
+            .map(|info| {
+                let registration = info
+                    .type_id()
+                    .and_then(|it| registry.get(it))
+                    .filter(|it| it.data::<ReflectComponent>().is_some());
+
+                match registration {
+                    Some(registration) => ComponentRow {
+                        name: registration.type_info().type_path().to_owned(),
+                        reflected: true,
+                    },
+                    None => ComponentRow {
+                        name: info.name().to_owned(),
+                        reflected: false,
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        components.sort_by(|a, b| short_name(&a.name).cmp(short_name(&b.name)));
+
+        groups.entry(group).or_default().push(EntityRow {
+            entity,
+            label: entity_label(world, entity),
+            origin,
+            components,
+        });
+    }
+
+    for rows in groups.values_mut() {
+        rows.sort_by(|a, b| a.label.cmp(&b.label));
+    }
+
+    groups
+}
+
+fn matches(row: &EntityRow, search: &str, origin: Origin) -> bool {
+    if origin != Origin::All && row.origin != origin {
+        return false;
+    }
+
+    if search.is_empty() {
+        return true;
+    }
+
+    let search = search.to_lowercase();
+    row.label.to_lowercase().contains(&search)
+        || row
+            .components
+            .iter()
+            .any(|it| short_name(&it.name).to_lowercase().contains(&search))
+}
+
+/// Current value of a pinned component, `None` once the entity or component is gone
+fn pinned_value(world: &World, pin: &Pin) -> Option<String> {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    let entity = world.get_entity(pin.entity).ok()?;
+    let registration = registry.get_with_type_path(&pin.component)?;
+    let component = registration.data::<ReflectComponent>()?.reflect(entity)?;
+
+    Some(format!("{component:#?}"))
+}
+
+fn inspector_ui(world: &mut World) {
+    let mut contexts = world.query_filtered::<&mut EguiContext, With<PrimaryWindow>>();
+    let Ok(mut context) = contexts.get_single_mut(world) else {
+        return;
+    };
+    let context = context.get_mut().clone();
+
+    let groups = collect_rows(world);
+
+    world.resource_scope(|world, mut state: Mut<InspectorState>| {
+        let state = &mut *state;
+        let mut open = true;
+
+        egui::Window::new("Inspector")
+            .open(&mut open)
+            .default_size((420.0, 600.0))
+            .show(&context, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Search:");
+                    ui.text_edit_singleline(&mut state.search)
+                        .on_hover_text("Matches entity names and component types");
+                });
+
+                ui.horizontal(|ui| {
+                    for origin in Origin::ALL {
+                        ui.selectable_value(&mut state.origin, origin, origin.name());
+                    }
+                });
+
+                ui.separator();
+
+                if !state.pinned.is_empty() {
+                    CollapsingHeader::new("Pinned")
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            let mut unpin = None;
+
+                            for (idx, pin) in state.pinned.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "{}: {}",
+                                            entity_label(world, pin.entity),
+                                            short_name(&pin.component)
+                                        ))
+                                        .strong(),
+                                    );
+
+                                    if ui.small_button("Unpin").clicked() {
+                                        unpin = Some(idx);
+                                    }
+                                });
+
+                                match pinned_value(world, pin) {
+                                    Some(value) => {
+                                        ui.monospace(value);
+                                    }
+                                    None => {
+                                        ui.colored_label(Color32::GRAY, "Missing");
+                                    }
+                                }
+                            }
+
+                            if let Some(idx) = unpin {
+                                state.pinned.remove(idx);
+                            }
+                        });
+
+                    ui.separator();
+                }
+
+                ScrollArea::vertical()
+                    .id_salt("Entities")
+                    .max_height(ui.available_height() / 2.0)
+                    .show(ui, |ui| {
+                        for (group, rows) in &groups {
+                            let rows = rows
+                                .iter()
+                                .filter(|it| matches(it, &state.search, state.origin))
+                                .collect::<Vec<_>>();
+                            if rows.is_empty() {
+                                continue;
+                            }
+
+                            let title = group.as_deref().unwrap_or("Not On A Robot");
+                            CollapsingHeader::new(format!("{title} ({})", rows.len()))
+                                .id_salt(("Inspector Group", group))
+                                .default_open(group.is_some())
+                                .show(ui, |ui| {
+                                    for row in rows {
+                                        let selected = state.selected == Some(row.entity);
+                                        if ui.selectable_label(selected, &row.label).clicked() {
+                                            state.selected = Some(row.entity);
+                                        }
+                                    }
+                                });
+                        }
+                    });
+
+                ui.separator();
+
+                let Some(selected) = state.selected.filter(|it| world.get_entity(*it).is_ok())
+                else {
+                    ui.label("Select an entity");
+                    return;
+                };
+
+                ScrollArea::vertical().id_salt("Selected").show(ui, |ui| {
+                    ui.heading(entity_label(world, selected));
+
+                    CollapsingHeader::new("Pin Components").show(ui, |ui| {
+                        let row = groups.values().flatten().find(|it| it.entity == selected);
+
+                        for component in row.iter().flat_map(|it| &it.components) {
+                            let pinned = state
+                                .pinned
+                                .iter()
+                                .any(|it| it.entity == selected && it.component == component.name);
+
+                            ui.horizontal(|ui| {
+                                ui.label(short_name(&component.name));
+
+                                // Values are read back through reflection
+                                let can_pin = component.reflected && !pinned;
+                                if can_pin && ui.small_button("Pin").clicked() {
+                                    state.pinned.push(Pin {
+                                        entity: selected,
+                                        component: component.name.clone(),
+                                    });
+                                }
+                            });
+                        }
+                    });
+
+                    bevy_inspector::ui_for_entity(world, selected, ui);
+                });
+            });
+
+        if !open {
+            world.remove_resource::<ShowInspector>();
+        }
+    });
+}
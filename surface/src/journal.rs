@@ -0,0 +1,505 @@
+//! Who changed what and when, with undo for the changes that can safely be reverted
+//!
+//! Changes are found by comparing snapshots of the state rather than by hooking every system
+//! that sets a target. One snapshot is taken right after the changes replicated from the robot
+//! are applied and another after `Update`, so anything that changed between them was done by
+//! this station.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display, Write as _},
+    fs::OpenOptions,
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_egui::EguiContexts;
+use common::{
+    components::{Armed, CameraDefinition, DepthTarget, Orientation, OrientationTarget, Robot},
+    ecs_sync::apply_changes::ChangeApplicationSet,
+    error::ErrorEvent,
+    types::units::Meters,
+    InstanceName,
+};
+use egui::RichText;
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::{
+    layout::DockableWindow, session::ActiveSession, ui::HistoryUi,
+    video_display_2d_master::VideoMasterMarker, video_stream::VideoProcessorFactory, wall_clock,
+};
+
+const JOURNAL_FILE: &str = "journal.txt";
+
+/// Changes to the same thing closer together than this are one entry, so holding a trim for a
+/// few seconds is undone in one step
+const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+const MAX_ENTRIES: usize = 2000;
+
+pub struct JournalPlugin;
+
+impl Plugin for JournalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandJournal>()
+            .add_event::<UndoCommand>()
+            .add_systems(PreUpdate, observe_remote.after(ChangeApplicationSet))
+            .add_systems(
+                Update,
+                (undo, history_ui.run_if(resource_exists::<HistoryUi>)),
+            )
+            .add_systems(PostUpdate, (observe_local, write_session_log).chain());
+    }
+}
+
+/// Reverts the newest change made by this station that can be undone
+#[derive(Event, Debug, Clone, Copy)]
+pub struct UndoCommand;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subject {
+    DepthTarget(Entity),
+    OrientationTarget(Entity),
+    Armed(Entity),
+    MasterCamera,
+    Pipeline(Entity),
+}
+
+impl Subject {
+    /// Reverting arming or a pipeline could surprise the pilot, targets and cameras are cheap to
+    /// set again
+    pub fn reversible(&self) -> bool {
+        matches!(
+            self,
+            Subject::DepthTarget(_) | Subject::OrientationTarget(_) | Subject::MasterCamera
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Depth(Meters),
+    Orientation(Quat),
+    Armed(Armed),
+    Camera(Entity, String),
+    Pipeline(String),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Depth(depth) => write!(f, "{depth}"),
+            Value::Orientation(orientation) => {
+                write!(f, "heading {}", Orientation(*orientation).heading().0)
+            }
+            Value::Armed(Armed::Armed) => write!(f, "Armed"),
+            Value::Armed(Armed::Disarmed) => write!(f, "Disarmed"),
+            Value::Camera(_, name) | Value::Pipeline(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub time: OffsetDateTime,
+    pub who: String,
+    pub label: String,
+    pub subject: Subject,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    /// Made by this station, only these can be undone
+    pub local: bool,
+    /// Records an undo rather than a change
+    pub undo: bool,
+    pub undone: bool,
+
+    updated: Instant,
+    logged: bool,
+}
+
+impl JournalEntry {
+    pub fn description(&self) -> String {
+        let value = |it: &Option<Value>| match it {
+            Some(value) => value.to_string(),
+            None => "Off".to_owned(),
+        };
+        let prefix = if self.undo { "Undo " } else { "" };
+
+        format!(
+            "{prefix}{}: {} -> {}",
+            self.label,
+            value(&self.before),
+            value(&self.after)
+        )
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.local && !self.undo && !self.undone && self.subject.reversible()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CommandJournal {
+    pub entries: VecDeque<JournalEntry>,
+    /// The state at the last snapshot
+    state: HashMap<Subject, Option<Value>>,
+    /// Session the entries are currently written to
+    log_directory: Option<PathBuf>,
+}
+
+impl CommandJournal {
+    /// The entry `UndoCommand` would revert
+    pub fn next_undo(&self) -> Option<&JournalEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|it| self.state.contains_key(&it.subject))
+            .find(|it| it.can_undo())
+    }
+
+    fn record(&mut self, entry: JournalEntry) {
+        let last = self
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|it| it.subject == entry.subject)
+            .filter(|it| {
+                it.who == entry.who
+                    && !it.undo
+                    && !it.undone
+                    && !entry.undo
+                    && it.updated.elapsed() < COALESCE_WINDOW
+            });
+
+        match last {
+            Some(last) => {
+                last.after = entry.after;
+                last.updated = entry.updated;
+            }
+            None => {
+                info!("Journal: {} by {}", entry.description(), entry.who);
+                self.entries.push_back(entry);
+
+                if self.entries.len() > MAX_ENTRIES {
+                    self.entries.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Records the differences between the last snapshot and this one
+    fn observe(
+        &mut self,
+        current: HashMap<Subject, (String, Option<Value>)>,
+        who: &str,
+        local: bool,
+    ) {
+        let mut state = HashMap::with_capacity(current.len());
+
+        for (subject, (label, value)) in current {
+            // Things that just appeared have no history to record
+            if let Some(before) = self.state.get(&subject).cloned() {
+                if before != value {
+                    self.record(JournalEntry {
+                        time: wall_clock::now(),
+                        who: who.to_owned(),
+                        label,
+                        subject,
+                        before,
+                        after: value.clone(),
+                        local,
+                        undo: false,
+                        undone: false,
+                        updated: Instant::now(),
+                        logged: false,
+                    });
+                }
+            }
+
+            state.insert(subject, value);
+        }
+
+        self.state = state;
+    }
+}
+
+#[derive(SystemParam)]
+struct ObservedState<'w, 's> {
+    robots: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Name,
+            &'static Armed,
+            Option<&'static DepthTarget>,
+            Option<&'static OrientationTarget>,
+        ),
+        With<Robot>,
+    >,
+    cameras: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Name,
+            Has<VideoMasterMarker>,
+            Option<&'static VideoProcessorFactory>,
+        ),
+        With<CameraDefinition>,
+    >,
+}
+
+impl ObservedState<'_, '_> {
+    fn snapshot(&self) -> HashMap<Subject, (String, Option<Value>)> {
+        let mut snapshot = HashMap::new();
+
+        for (robot, name, armed, depth_target, orientation_target) in &self.robots {
+            snapshot.insert(
+                Subject::DepthTarget(robot),
+                (
+                    format!("{name} depth target"),
+                    depth_target.map(|it| Value::Depth(it.0)),
+                ),
+            );
+            snapshot.insert(
+                Subject::OrientationTarget(robot),
+                (
+                    format!("{name} orientation target"),
+                    orientation_target.map(|it| Value::Orientation(it.0)),
+                ),
+            );
+            snapshot.insert(
+                Subject::Armed(robot),
+                (name.to_string(), Some(Value::Armed(*armed))),
+            );
+        }
+
+        let mut master = None;
+        for (camera, name, is_master, processor) in &self.cameras {
+            if is_master {
+                master = Some(Value::Camera(camera, name.to_string()));
+            }
+
+            snapshot.insert(
+                Subject::Pipeline(camera),
+                (
+                    format!("{name} pipeline"),
+                    processor.map(|it| Value::Pipeline(it.name.to_string())),
+                ),
+            );
+        }
+        snapshot.insert(Subject::MasterCamera, ("Camera".to_owned(), master));
+
+        snapshot
+    }
+}
+
+fn observe_remote(mut journal: ResMut<CommandJournal>, state: ObservedState) {
+    journal.observe(state.snapshot(), "Remote", false);
+}
+
+fn observe_local(
+    mut journal: ResMut<CommandJournal>,
+    state: ObservedState,
+    name: Res<InstanceName>,
+) {
+    journal.observe(state.snapshot(), &name.0, true);
+}
+
+fn undo(
+    mut cmds: Commands,
+    mut events: EventReader<UndoCommand>,
+    mut journal: ResMut<CommandJournal>,
+    name: Res<InstanceName>,
+) {
+    for _ in events.read() {
+        let Some(entry) = journal.next_undo().cloned() else {
+            warn!("Nothing to undo");
+            continue;
+        };
+
+        match (entry.subject, &entry.before) {
+            (Subject::DepthTarget(robot), Some(&Value::Depth(depth))) => {
+                cmds.entity(robot).insert(DepthTarget(depth));
+            }
+            (Subject::DepthTarget(robot), None) => {
+                cmds.entity(robot).remove::<DepthTarget>();
+            }
+            (Subject::OrientationTarget(robot), Some(&Value::Orientation(orientation))) => {
+                cmds.entity(robot).insert(OrientationTarget(orientation));
+            }
+            (Subject::OrientationTarget(robot), None) => {
+                cmds.entity(robot).remove::<OrientationTarget>();
+            }
+            (Subject::MasterCamera, Some(&Value::Camera(camera, _))) => {
+                cmds.entity(camera).insert(VideoMasterMarker);
+            }
+            (Subject::MasterCamera, None) => {
+                if let Some(Some(Value::Camera(camera, _))) =
+                    journal.state.get(&Subject::MasterCamera)
+                {
+                    cmds.entity(*camera).remove::<VideoMasterMarker>();
+                }
+            }
+            (subject, before) => {
+                error!("Cannot undo {subject:?} to {before:?}");
+                continue;
+            }
+        }
+
+        let subject = entry.subject;
+        let reverted = entry.before.clone();
+
+        if let Some(undone) = journal
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|it| it.can_undo() && it.subject == subject)
+        {
+            undone.undone = true;
+        }
+
+        let current = journal.state.get(&subject).cloned().flatten();
+        journal.record(JournalEntry {
+            time: wall_clock::now(),
+            who: name.0.clone(),
+            before: current,
+            after: reverted.clone(),
+            undo: true,
+            updated: Instant::now(),
+            logged: false,
+            ..entry
+        });
+
+        // The reverted state is already accounted for by the undo entry
+        journal.state.insert(subject, reverted);
+    }
+}
+
+fn write_session_log(
+    mut journal: ResMut<CommandJournal>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let directory = session.map(|it| it.directory.clone());
+
+    let res = if directory != journal.log_directory {
+        // Whatever is left belongs to the session that just ended
+        let res = match journal.log_directory.take() {
+            Some(old) => append_entries(&old, &mut journal, |_| true),
+            None => Ok(()),
+        };
+
+        // Entries from before the session started are not part of it
+        for entry in &mut journal.entries {
+            entry.logged = true;
+        }
+        journal.log_directory = directory;
+
+        res
+    } else if let Some(directory) = directory {
+        // Entries are only written once they can no longer be coalesced
+        append_entries(&directory, &mut journal, |it| {
+            it.updated.elapsed() >= COALESCE_WINDOW
+        })
+    } else {
+        Ok(())
+    };
+
+    if let Err(err) = res {
+        errors.send(err.context("Write journal").into());
+    }
+}
+
+fn append_entries(
+    directory: &Path,
+    journal: &mut CommandJournal,
+    settled: impl Fn(&JournalEntry) -> bool,
+) -> anyhow::Result<()> {
+    let mut lines = String::new();
+    for entry in journal.entries.iter_mut().filter(|it| !it.logged) {
+        if !settled(entry) {
+            continue;
+        }
+
+        let time = entry
+            .time
+            .format(&Iso8601::DATE_TIME)
+            .context("Format time")?;
+        let _ = writeln!(lines, "{time} {}: {}", entry.who, entry.description());
+        entry.logged = true;
+    }
+
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(directory.join(JOURNAL_FILE))
+        .context("Open journal")?
+        .write_all(lines.as_bytes())
+        .context("Append journal")
+}
+
+fn history_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    journal: Res<CommandJournal>,
+    mut undo: EventWriter<UndoCommand>,
+) {
+    let mut open = true;
+
+    egui::Window::new("History").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        egui::Id::new("History"),
+        |ui| {
+            let next_undo = journal.next_undo();
+
+            let button = ui.add_enabled(next_undo.is_some(), egui::Button::new("Undo"));
+            let button = match next_undo {
+                Some(entry) => button.on_hover_text(entry.description()),
+                None => button,
+            };
+            if button.clicked() {
+                undo.send(UndoCommand);
+            }
+
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("History Entries")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for entry in journal.entries.iter().rev() {
+                            let time = entry.time;
+                            ui.label(format!(
+                                "{:02}:{:02}:{:02}",
+                                time.hour(),
+                                time.minute(),
+                                time.second()
+                            ));
+                            ui.label(&entry.who);
+
+                            let text = RichText::new(entry.description());
+                            if entry.undone {
+                                ui.label(text.strikethrough().weak());
+                            } else {
+                                ui.label(text);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        },
+    );
+
+    if !open {
+        cmds.remove_resource::<HistoryUi>();
+    }
+}
@@ -0,0 +1,729 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::Context;
+use bevy::{app::AppExit, ecs::event::EventCursor, prelude::*, window::PrimaryWindow};
+use bevy_egui::{EguiContext, EguiPreUpdateSet};
+use egui::{Align2, Id, InnerResponse, LayerId, Order, Pos2, Rect, Sense, Ui};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    settings::config_path,
+    tool_windows::{self, ToolKind, ToolWindow},
+    ui::LAYOUT_WINDOWS,
+};
+
+pub const LAYOUTS_FILE: &str = "layouts.toml";
+
+const DEFAULT_PANE_WIDTH: f32 = 320.0;
+const DEFAULT_PANE_HEIGHT: f32 = 240.0;
+
+pub struct UiLayoutPlugin;
+
+impl Plugin for UiLayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ApplyLayout>()
+            .add_event::<SaveLayout>()
+            .add_event::<DockWindow>()
+            .init_resource::<UiDock>()
+            .add_systems(PreStartup, load_layouts)
+            .add_systems(Startup, apply_active_layout)
+            .add_systems(
+                PreUpdate,
+                apply_layout.after(EguiPreUpdateSet::InitContexts),
+            )
+            // Panels take space in the order they are shown, the top bar goes first so the dock
+            // panes sit below it
+            .add_systems(Update, show_dock.after(crate::ui::topbar))
+            .add_systems(Last, (save_layout, save_on_exit, write_layouts).chain());
+    }
+}
+
+/// A window that can be placed by a layout
+pub struct LayoutWindow {
+    /// The window's title, egui derives the window's id from this
+    pub title: &'static str,
+    pub set_open: fn(&mut World, bool),
+    pub is_open: fn(&World) -> bool,
+}
+
+/// Named window arrangements, persisted to `layouts.toml`
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct UiLayouts {
+    /// Saved when the control station exits and restored on launch
+    pub active: String,
+    pub layouts: BTreeMap<String, UiLayout>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UiLayout {
+    /// Window title to placement
+    pub windows: BTreeMap<String, WindowPlacement>,
+    /// Tool windows open in this layout, see `tool_windows`
+    #[serde(default)]
+    pub tools: Vec<ToolPlacement>,
+    /// Windows placed in the dock panes, every other window floats
+    #[serde(default)]
+    pub dock: DockLayout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowPlacement {
+    pub open: bool,
+    /// Top left corner of the window, `None` leaves the window where egui puts it
+    #[serde(default)]
+    pub pos: Option<[f32; 2]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ToolPlacement {
+    pub kind: ToolKind,
+    pub instance: u32,
+    #[serde(default)]
+    pub pos: Option<[f32; 2]>,
+}
+
+impl UiLayout {
+    fn with_open(windows: &[&str]) -> Self {
+        let windows = LAYOUT_WINDOWS
+            .iter()
+            .map(|it| {
+                let placement = WindowPlacement {
+                    open: windows.contains(&it.title),
+                    pos: None,
+                };
+
+                (it.title.to_owned(), placement)
+            })
+            .collect();
+
+        Self {
+            windows,
+            tools: Vec::new(),
+            dock: DockLayout::default(),
+        }
+    }
+
+    fn docked(mut self, side: DockSide, windows: &[&str]) -> Self {
+        let pane = self.dock.pane_mut(side);
+        pane.tabs
+            .extend(windows.iter().map(|&it| DockTab::window(it)));
+
+        self
+    }
+}
+
+impl Default for UiLayouts {
+    fn default() -> Self {
+        Self {
+            active: "Piloting".to_owned(),
+            layouts: BTreeMap::from([
+                ("Piloting".to_owned(), UiLayout::with_open(&["Timer"])),
+                (
+                    "Tuning".to_owned(),
+                    UiLayout::with_open(&["PWM Control", "Input Shaping", "Pilot Modes"])
+                        .docked(DockSide::Right, &["Input Shaping", "Pilot Modes"]),
+                ),
+                (
+                    "Debugging".to_owned(),
+                    UiLayout::with_open(&["Gamepads", "Bindings", "Checklist", "Frame Budget"])
+                        .docked(DockSide::Right, &["Gamepads", "Bindings"])
+                        .docked(DockSide::Bottom, &["Frame Budget"]),
+                ),
+            ]),
+        }
+    }
+}
+
+/// Opens, closes and moves windows to match the named layout
+#[derive(Event, Debug, Clone)]
+pub struct ApplyLayout(pub String);
+
+/// Records the current window placement under the given name
+#[derive(Event, Debug, Clone)]
+pub struct SaveLayout(pub String);
+
+/// Moves a window into a dock pane, or out of the dock when `side` is `None`
+#[derive(Event, Debug, Clone)]
+pub struct DockWindow {
+    pub tab: DockTab,
+    pub side: Option<DockSide>,
+}
+
+/// A window that can be docked, identified the same way `UiLayout` identifies it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum DockTab {
+    Window { title: String },
+    Tool { kind: ToolKind, instance: u32 },
+}
+
+impl DockTab {
+    pub fn window(title: &str) -> Self {
+        DockTab::Window {
+            title: title.to_owned(),
+        }
+    }
+
+    pub fn tool(tool: ToolWindow) -> Self {
+        DockTab::Tool {
+            kind: tool.kind,
+            instance: tool.instance,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            DockTab::Window { title } => title.clone(),
+            DockTab::Tool { kind, instance } => ToolWindow {
+                kind: *kind,
+                instance: *instance,
+            }
+            .title(),
+        }
+    }
+
+    /// The id egui gives the floating window
+    pub fn id(&self) -> Id {
+        match self {
+            DockTab::Window { title } => Id::new(title.as_str()),
+            DockTab::Tool { kind, instance } => ToolWindow {
+                kind: *kind,
+                instance: *instance,
+            }
+            .id(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DockSide {
+    Left,
+    Right,
+    Bottom,
+}
+
+impl DockSide {
+    pub const ALL: [DockSide; 3] = [DockSide::Left, DockSide::Right, DockSide::Bottom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DockSide::Left => "Left",
+            DockSide::Right => "Right",
+            DockSide::Bottom => "Bottom",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DockLayout {
+    #[serde(default)]
+    pub left: DockPane,
+    #[serde(default)]
+    pub right: DockPane,
+    #[serde(default)]
+    pub bottom: DockPane,
+}
+
+/// Tabs sharing one side of the screen, only the active tab is drawn
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DockPane {
+    /// Closed windows keep their tab so they return to the pane when reopened
+    #[serde(default)]
+    pub tabs: Vec<DockTab>,
+    #[serde(default)]
+    pub active: Option<DockTab>,
+    /// Width of the side panes or height of the bottom pane, `None` uses the default size
+    #[serde(default)]
+    pub size: Option<f32>,
+}
+
+impl DockLayout {
+    pub fn pane(&self, side: DockSide) -> &DockPane {
+        match side {
+            DockSide::Left => &self.left,
+            DockSide::Right => &self.right,
+            DockSide::Bottom => &self.bottom,
+        }
+    }
+
+    pub fn pane_mut(&mut self, side: DockSide) -> &mut DockPane {
+        match side {
+            DockSide::Left => &mut self.left,
+            DockSide::Right => &mut self.right,
+            DockSide::Bottom => &mut self.bottom,
+        }
+    }
+
+    /// The pane `tab` is docked in, `None` if it floats
+    pub fn side(&self, tab: &DockTab) -> Option<DockSide> {
+        DockSide::ALL
+            .into_iter()
+            .find(|&side| self.pane(side).tabs.contains(tab))
+    }
+
+    fn dock(&mut self, tab: DockTab, side: Option<DockSide>) {
+        for side in DockSide::ALL {
+            let pane = self.pane_mut(side);
+            pane.tabs.retain(|it| *it != tab);
+
+            if pane.active.as_ref() == Some(&tab) {
+                pane.active = None;
+            }
+        }
+
+        if let Some(side) = side {
+            let pane = self.pane_mut(side);
+            pane.tabs.push(tab.clone());
+            pane.active = Some(tab);
+        }
+    }
+}
+
+/// The live dock placement, copied to and from `UiLayout::dock`
+#[derive(Resource, Debug, Clone, Default)]
+pub struct UiDock {
+    pub layout: DockLayout,
+    /// Windows that are currently open, docked or not
+    pub open: Vec<DockTab>,
+    /// Bumped when a layout is applied so the panes are recreated at the layout's sizes
+    generation: u32,
+}
+
+/// Where a docked window's contents go this frame, kept in egui's memory under the window's id
+#[derive(Debug, Clone, Copy)]
+enum DockSlot {
+    Visible(Rect),
+    /// Docked behind another tab
+    Hidden,
+}
+
+/// Lets a window be drawn into its dock tab, see `UiDock`
+pub trait DockableWindow {
+    /// Same as `egui::Window::show` while the window floats. Once docked, the contents are drawn
+    /// into the window's tab instead and nothing is drawn while another tab is active
+    ///
+    /// `id` must be the id egui gives the floating window, see `DockTab::id`
+    fn show_dockable<R>(
+        self,
+        ctx: &egui::Context,
+        id: Id,
+        add_contents: impl FnOnce(&mut Ui) -> R,
+    ) -> Option<InnerResponse<Option<R>>>;
+}
+
+impl DockableWindow for egui::Window<'_> {
+    fn show_dockable<R>(
+        self,
+        ctx: &egui::Context,
+        id: Id,
+        add_contents: impl FnOnce(&mut Ui) -> R,
+    ) -> Option<InnerResponse<Option<R>>> {
+        match ctx.data(|data| data.get_temp::<DockSlot>(id)) {
+            None => self.show(ctx, add_contents),
+            Some(DockSlot::Hidden) => None,
+            Some(DockSlot::Visible(rect)) => {
+                let response = egui::Area::new(id.with("Docked"))
+                    .order(Order::Middle)
+                    .fixed_pos(rect.min)
+                    .constrain_to(rect)
+                    .show(ctx, |ui| {
+                        ui.set_clip_rect(rect);
+                        ui.set_min_size(rect.size());
+                        ui.set_max_size(rect.size());
+
+                        add_contents(ui)
+                    });
+
+                Some(InnerResponse::new(Some(response.inner), response.response))
+            }
+        }
+    }
+}
+
+fn load_layouts(mut cmds: Commands) {
+    let res: anyhow::Result<UiLayouts> = try {
+        let layouts = fs::read_to_string(config_path(LAYOUTS_FILE)).context("Read layouts")?;
+        toml::from_str(&layouts).context("Parse layouts")?
+    };
+
+    let layouts = match res {
+        Ok(layouts) => layouts,
+        Err(err) => {
+            warn!("Using default layouts: {err:?}");
+            UiLayouts::default()
+        }
+    };
+
+    cmds.insert_resource(layouts);
+}
+
+fn apply_active_layout(layouts: Res<UiLayouts>, mut apply: EventWriter<ApplyLayout>) {
+    apply.send(ApplyLayout(layouts.active.clone()));
+}
+
+fn primary_context(world: &mut World) -> Option<egui::Context> {
+    let mut contexts = world.query_filtered::<&mut EguiContext, With<PrimaryWindow>>();
+    let mut context = contexts.get_single_mut(world).ok()?;
+
+    Some(context.get_mut().clone())
+}
+
+fn apply_layout(world: &mut World, mut cursor: Local<EventCursor<ApplyLayout>>) {
+    let events = world.resource::<Events<ApplyLayout>>();
+    let Some(ApplyLayout(name)) = cursor.read(events).last().cloned() else {
+        return;
+    };
+
+    let mut layouts = world.resource_mut::<UiLayouts>();
+    let Some(layout) = layouts.layouts.get(&name).cloned() else {
+        warn!("Unknown layout {name}");
+        return;
+    };
+
+    info!("Applying layout {name}");
+    if layouts.active != name {
+        layouts.active = name;
+    }
+
+    let mut dock = world.resource_mut::<UiDock>();
+    dock.layout = layout.dock.clone();
+    dock.generation += 1;
+
+    let context = primary_context(world);
+
+    for window in LAYOUT_WINDOWS {
+        let Some(placement) = layout.windows.get(window.title) else {
+            continue;
+        };
+
+        if (window.is_open)(world) != placement.open {
+            (window.set_open)(world, placement.open);
+        }
+
+        if let (Some(context), Some(pos)) = (&context, placement.pos) {
+            place_window(context, Id::new(window.title), pos);
+        }
+    }
+
+    // Tools not in the layout are closed, the rest are reopened under the same instance
+    let mut tools = world.query::<(Entity, &ToolWindow)>();
+    let open = tools
+        .iter(world)
+        .map(|(entity, tool)| (entity, *tool))
+        .collect::<Vec<_>>();
+
+    for (entity, tool) in &open {
+        let keep = layout
+            .tools
+            .iter()
+            .any(|it| it.kind == tool.kind && it.instance == tool.instance);
+
+        if !keep {
+            world.entity_mut(*entity).despawn_recursive();
+        }
+    }
+
+    for placement in &layout.tools {
+        let tool = ToolWindow {
+            kind: placement.kind,
+            instance: placement.instance,
+        };
+
+        if !open.iter().any(|(_, it)| *it == tool) {
+            tool_windows::open_tool_instance(world, tool);
+        }
+
+        if let (Some(context), Some(pos)) = (&context, placement.pos) {
+            place_window(context, tool.id(), pos);
+        }
+    }
+}
+
+fn place_window(context: &egui::Context, id: Id, [x, y]: [f32; 2]) {
+    let layer = LayerId::new(Order::Middle, id);
+
+    context.memory_mut(|memory| {
+        let areas = memory.areas_mut();
+        let mut state = areas.get(layer.id).copied().unwrap_or(egui::AreaState {
+            pivot_pos: None,
+            pivot: Align2::LEFT_TOP,
+            size: None,
+            interactable: true,
+            last_became_visible_at: None,
+        });
+
+        state.pivot_pos = Some(Pos2::new(x, y));
+        areas.set_state(layer, state);
+    });
+}
+
+fn window_pos(context: Option<&egui::Context>, id: Id) -> Option<[f32; 2]> {
+    context
+        .and_then(|it| it.memory(|memory| memory.area_rect(id)))
+        .map(|it| [it.min.x, it.min.y])
+}
+
+enum DockAction {
+    Select(DockSide, DockTab),
+    Move(DockTab, Option<DockSide>),
+    Close(DockTab),
+}
+
+/// Draws the dock panes and tells each docked window where to draw its contents
+fn show_dock(world: &mut World, mut cursor: Local<EventCursor<DockWindow>>) {
+    let events = world.resource::<Events<DockWindow>>();
+    let requests = cursor.read(events).cloned().collect::<Vec<_>>();
+
+    let mut open = LAYOUT_WINDOWS
+        .iter()
+        .filter(|window| (window.is_open)(world))
+        .map(|window| DockTab::window(window.title))
+        .collect::<Vec<_>>();
+
+    let mut tools = world
+        .query::<&ToolWindow>()
+        .iter(world)
+        .copied()
+        .collect::<Vec<_>>();
+    tools.sort_by_key(|it| (it.kind, it.instance));
+    open.extend(tools.into_iter().map(DockTab::tool));
+
+    let Some(context) = primary_context(world) else {
+        return;
+    };
+
+    let mut dock = world.resource_mut::<UiDock>();
+    for DockWindow { tab, side } in requests {
+        dock.layout.dock(tab, side);
+    }
+
+    let generation = dock.generation;
+    let mut actions = Vec::new();
+
+    for side in DockSide::ALL {
+        let pane = dock.layout.pane_mut(side);
+        let tabs = pane
+            .tabs
+            .iter()
+            .filter(|it| open.contains(it))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let Some(first) = tabs.first() else {
+            continue;
+        };
+        let active = pane
+            .active
+            .clone()
+            .filter(|it| tabs.contains(it))
+            .unwrap_or_else(|| first.clone());
+
+        let id = Id::new(("Dock", side, generation));
+        let add_contents = |ui: &mut Ui| show_pane(ui, side, &tabs, &active, &mut actions);
+
+        let response = match side {
+            DockSide::Left => egui::SidePanel::left(id)
+                .default_width(pane.size.unwrap_or(DEFAULT_PANE_WIDTH))
+                .show(&context, add_contents),
+            DockSide::Right => egui::SidePanel::right(id)
+                .default_width(pane.size.unwrap_or(DEFAULT_PANE_WIDTH))
+                .show(&context, add_contents),
+            DockSide::Bottom => egui::TopBottomPanel::bottom(id)
+                .resizable(true)
+                .default_height(pane.size.unwrap_or(DEFAULT_PANE_HEIGHT))
+                .show(&context, add_contents),
+        };
+
+        let size = match side {
+            DockSide::Left | DockSide::Right => response.response.rect.width(),
+            DockSide::Bottom => response.response.rect.height(),
+        };
+        if pane.size != Some(size) {
+            pane.size = Some(size);
+        }
+
+        let contents = response.inner;
+        context.data_mut(|data| {
+            for tab in &tabs {
+                let slot = if *tab == active {
+                    DockSlot::Visible(contents)
+                } else {
+                    DockSlot::Hidden
+                };
+
+                data.insert_temp(tab.id(), slot);
+            }
+        });
+    }
+
+    // Floating windows draw themselves
+    context.data_mut(|data| {
+        for tab in &open {
+            if dock.layout.side(tab).is_none() {
+                data.remove::<DockSlot>(tab.id());
+            }
+        }
+    });
+
+    dock.open = open;
+
+    let mut closed = Vec::new();
+    for action in actions {
+        match action {
+            DockAction::Select(side, tab) => dock.layout.pane_mut(side).active = Some(tab),
+            DockAction::Move(tab, side) => dock.layout.dock(tab, side),
+            DockAction::Close(tab) => closed.push(tab),
+        }
+    }
+
+    for tab in closed {
+        close_window(world, &tab);
+    }
+}
+
+/// Draws a pane's tab bar, returns the space left for the active tab's contents
+fn show_pane(
+    ui: &mut Ui,
+    side: DockSide,
+    tabs: &[DockTab],
+    active: &DockTab,
+    actions: &mut Vec<DockAction>,
+) -> Rect {
+    ui.horizontal_wrapped(|ui| {
+        for tab in tabs {
+            let response = ui.selectable_label(tab == active, tab.title());
+            if response.clicked() {
+                actions.push(DockAction::Select(side, tab.clone()));
+            }
+
+            response.context_menu(|ui| {
+                for other in DockSide::ALL {
+                    if other != side && ui.button(format!("Move {}", other.name())).clicked() {
+                        actions.push(DockAction::Move(tab.clone(), Some(other)));
+                        ui.close_menu();
+                    }
+                }
+
+                if ui.button("Float").clicked() {
+                    actions.push(DockAction::Move(tab.clone(), None));
+                    ui.close_menu();
+                }
+
+                if ui.button("Close").clicked() {
+                    actions.push(DockAction::Close(tab.clone()));
+                    ui.close_menu();
+                }
+            });
+        }
+    });
+
+    ui.separator();
+
+    let rect = ui.available_rect_before_wrap();
+    ui.allocate_rect(rect, Sense::hover());
+
+    rect
+}
+
+fn close_window(world: &mut World, tab: &DockTab) {
+    match tab {
+        DockTab::Window { title } => {
+            if let Some(window) = LAYOUT_WINDOWS.iter().find(|it| it.title == title.as_str()) {
+                (window.set_open)(world, false);
+            }
+        }
+        DockTab::Tool { kind, instance } => {
+            let mut tools = world.query::<(Entity, &ToolWindow)>();
+            let entity = tools
+                .iter(world)
+                .find(|(_, tool)| tool.kind == *kind && tool.instance == *instance)
+                .map(|(entity, _)| entity);
+
+            if let Some(entity) = entity {
+                world.entity_mut(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn capture_layout(world: &mut World) -> UiLayout {
+    let context = primary_context(world);
+
+    let windows = LAYOUT_WINDOWS
+        .iter()
+        .map(|window| {
+            let open = (window.is_open)(world);
+            let pos = window_pos(context.as_ref(), Id::new(window.title));
+
+            (window.title.to_owned(), WindowPlacement { open, pos })
+        })
+        .collect();
+
+    let mut tools = world
+        .query::<&ToolWindow>()
+        .iter(world)
+        .map(|tool| ToolPlacement {
+            kind: tool.kind,
+            instance: tool.instance,
+            pos: window_pos(context.as_ref(), tool.id()),
+        })
+        .collect::<Vec<_>>();
+    tools.sort_by_key(|it| (it.kind, it.instance));
+
+    let dock = world.resource::<UiDock>().layout.clone();
+
+    UiLayout {
+        windows,
+        tools,
+        dock,
+    }
+}
+
+fn save_layout(world: &mut World, mut cursor: Local<EventCursor<SaveLayout>>) {
+    let events = world.resource::<Events<SaveLayout>>();
+    let names = cursor
+        .read(events)
+        .map(|SaveLayout(name)| name.clone())
+        .collect::<Vec<_>>();
+
+    for name in names {
+        info!("Saving layout {name}");
+
+        let layout = capture_layout(world);
+        let mut layouts = world.resource_mut::<UiLayouts>();
+        layouts.layouts.insert(name.clone(), layout);
+        layouts.active = name;
+    }
+}
+
+/// Remembers where windows were left so the next launch starts with the same placement
+fn save_on_exit(world: &mut World, mut cursor: Local<EventCursor<AppExit>>) {
+    let events = world.resource::<Events<AppExit>>();
+    if cursor.read(events).next().is_none() {
+        return;
+    }
+
+    let layout = capture_layout(world);
+    let mut layouts = world.resource_mut::<UiLayouts>();
+    let active = layouts.active.clone();
+
+    if layouts.layouts.get(&active) != Some(&layout) {
+        layouts.layouts.insert(active, layout);
+    }
+}
+
+fn write_layouts(layouts: Res<UiLayouts>) {
+    if !layouts.is_changed() || layouts.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*layouts) else {
+        error!("Could not serialize layouts");
+        return;
+    };
+
+    let res = fs::write(config_path(LAYOUTS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write layouts: {err:?}");
+    }
+}
@@ -0,0 +1,289 @@
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::anyhow;
+use bevy::prelude::*;
+use common::{
+    components::{
+        Armed, DepthMeasurement, DepthTarget, Orientation, OrientationTarget, PilotModes, Robot,
+        RobotId,
+    },
+    error::ErrorEvent,
+    events::{CalibrateSeaLevel, ResetServos, ResetYaw},
+};
+use leafwing_input_manager::action_state::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{leveling_target, Action, InputInterpolation, InputMarker, LevelingType, MacroSlot},
+    surface::LocalSurfaceMarker,
+};
+
+pub struct InputMacroPlugin;
+
+impl Plugin for InputMacroPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (start_macros, run_macros.after(start_macros)));
+    }
+}
+
+/// A named sequence of steps run when `Action::RunMacro(slot)` is pressed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputMacro {
+    pub slot: MacroSlot,
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step")]
+pub enum MacroStep {
+    Arm,
+    /// Also ends the macro, the remaining steps are skipped
+    Disarm,
+    /// Holds the robot's current depth
+    SetDepthHold,
+    ClearDepthHold,
+    Level {
+        leveling: LevelingType,
+    },
+    ClearLeveling,
+    PilotMode {
+        name: String,
+    },
+    ResetServos,
+    ResetYaw,
+    CalibrateSeaLevel,
+    /// Delays the following steps, gives the robot time to react to the previous steps
+    Wait {
+        seconds: f32,
+    },
+}
+
+impl MacroStep {
+    pub fn name(&self) -> String {
+        match self {
+            MacroStep::Level { leveling } => format!("Level {leveling:?}"),
+            MacroStep::PilotMode { name } => format!("Pilot Mode {name}"),
+            MacroStep::Wait { seconds } => format!("Wait {seconds:.1}s"),
+            other => format!("{other:?}"),
+        }
+    }
+}
+
+impl InputMacro {
+    /// Levels the robot, holds depth and switches to the precision pilot mode
+    pub fn level_and_hold() -> Self {
+        Self {
+            slot: MacroSlot::One,
+            name: "Level and Hold".to_owned(),
+            steps: vec![
+                MacroStep::Level {
+                    leveling: LevelingType::Upright,
+                },
+                MacroStep::SetDepthHold,
+                MacroStep::PilotMode {
+                    name: "Precision".to_owned(),
+                },
+            ],
+        }
+    }
+}
+
+/// The macros from the binding profile an input entity was created from
+#[derive(Component, Debug, Clone, Default)]
+pub struct InputMacros(pub Vec<InputMacro>);
+
+/// The remaining steps of the macro an input entity is currently running
+#[derive(Component, Debug, Clone)]
+pub struct RunningMacro {
+    pub name: String,
+    steps: VecDeque<MacroStep>,
+    resume_at: Option<Duration>,
+    /// Set once the robot has been seen armed, any disarm after this aborts the macro
+    armed: bool,
+}
+
+impl RunningMacro {
+    fn new(input_macro: &InputMacro) -> Self {
+        Self {
+            name: input_macro.name.clone(),
+            steps: input_macro.steps.iter().cloned().collect(),
+            resume_at: None,
+            armed: false,
+        }
+    }
+}
+
+fn start_macros(
+    mut cmds: Commands,
+    inputs: Query<
+        (
+            Entity,
+            &ActionState<Action>,
+            &InputMacros,
+            Option<&RunningMacro>,
+        ),
+        With<InputMarker>,
+    >,
+) {
+    for (entity, action_state, macros, running) in &inputs {
+        for slot in MacroSlot::ALL {
+            if !action_state.just_pressed(&Action::RunMacro(slot)) {
+                continue;
+            }
+
+            let Some(input_macro) = macros.0.iter().find(|it| it.slot == slot) else {
+                warn!("No macro assigned to slot {slot:?}");
+                continue;
+            };
+
+            if let Some(running) = running {
+                info!("Replacing macro {}", running.name);
+            }
+
+            info!("Running macro {}", input_macro.name);
+            cmds.entity(entity).insert(RunningMacro::new(input_macro));
+        }
+    }
+}
+
+/// Runs macro steps in order until the macro finishes or reaches a wait step
+///
+/// Every step goes through `Commands` so inserts and events are applied in the order the steps
+/// are listed
+fn run_macros(
+    mut cmds: Commands,
+    mut inputs: Query<
+        (
+            Entity,
+            &RobotId,
+            &ActionState<Action>,
+            &mut RunningMacro,
+            &mut InputInterpolation,
+        ),
+        With<InputMarker>,
+    >,
+    robots: Query<
+        (
+            Entity,
+            &RobotId,
+            &Armed,
+            Option<&DepthMeasurement>,
+            Option<&Orientation>,
+        ),
+        With<Robot>,
+    >,
+    modes: Query<&PilotModes, With<LocalSurfaceMarker>>,
+    time: Res<Time<Real>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let now = time.elapsed();
+
+    'macros: for (entity, robot_id, action_state, mut running, mut interpolation) in &mut inputs {
+        let robot = robots
+            .iter()
+            .find(|&(_, other_robot, ..)| robot_id == other_robot);
+
+        let Some((robot, _, armed, depth, orientation)) = robot else {
+            warn!("Aborting macro {}, no ROV attached", running.name);
+            cmds.entity(entity).remove::<RunningMacro>();
+            continue;
+        };
+
+        let disarmed = *armed == Armed::Disarmed;
+        if action_state.just_pressed(&Action::Disarm) || (running.armed && disarmed) {
+            warn!("Aborting macro {}, robot disarmed", running.name);
+            cmds.entity(entity).remove::<RunningMacro>();
+            continue;
+        }
+        running.armed |= !disarmed;
+
+        if running.resume_at.is_some_and(|it| now < it) {
+            continue;
+        }
+        running.resume_at = None;
+
+        while let Some(step) = running.steps.pop_front() {
+            debug!("Macro {}: {}", running.name, step.name());
+
+            match step {
+                MacroStep::Arm => {
+                    cmds.entity(robot).insert(Armed::Armed);
+                }
+                MacroStep::Disarm => {
+                    cmds.entity(robot).insert(Armed::Disarmed);
+                    running.steps.clear();
+                }
+                MacroStep::SetDepthHold => {
+                    if let Some(depth) = depth {
+                        cmds.entity(robot).insert(DepthTarget(depth.depth));
+                    } else {
+                        warn!("Macro {}: No depth measurement", running.name);
+                    }
+                }
+                MacroStep::ClearDepthHold => {
+                    cmds.entity(robot).remove::<DepthTarget>();
+                }
+                MacroStep::Level { leveling } => {
+                    if let Some(orientation) = orientation {
+                        let target = leveling_target(orientation.0, leveling);
+                        cmds.entity(robot).insert(OrientationTarget(target));
+                    } else {
+                        warn!("Macro {}: No orientation", running.name);
+                    }
+                }
+                MacroStep::ClearLeveling => {
+                    cmds.entity(robot).remove::<OrientationTarget>();
+                }
+                MacroStep::PilotMode { name } => {
+                    let mode = modes.get_single().ok().and_then(|it| it.get(&name));
+
+                    if let Some(mode) = mode {
+                        interpolation.0 = mode.clone();
+                    } else {
+                        warn!("Macro {}: Unknown pilot mode {name}", running.name);
+                    }
+                }
+                MacroStep::ResetServos => {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(ResetServos);
+                    });
+                }
+                MacroStep::ResetYaw => {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(ResetYaw);
+                    });
+                }
+                MacroStep::CalibrateSeaLevel => {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(CalibrateSeaLevel);
+                    });
+                }
+                MacroStep::Wait { seconds } => {
+                    let resume_at = Duration::try_from_secs_f32(seconds.max(0.0))
+                        .ok()
+                        .and_then(|it| now.checked_add(it));
+                    let Some(resume_at) = resume_at else {
+                        errors.send(
+                            anyhow!(
+                                "Aborting macro {}, invalid wait of {seconds}s",
+                                running.name
+                            )
+                            .into(),
+                        );
+                        cmds.entity(entity).remove::<RunningMacro>();
+                        continue 'macros;
+                    };
+
+                    running.resume_at = Some(resume_at);
+                    break;
+                }
+            }
+        }
+
+        if running.steps.is_empty() && running.resume_at.is_none() {
+            info!("Finished macro {}", running.name);
+            cmds.entity(entity).remove::<RunningMacro>();
+        }
+    }
+}
@@ -1,24 +1,73 @@
-#![feature(iter_intersperse, try_blocks)]
+#![feature(iter_intersperse, try_blocks, test)]
 
 pub mod attitude;
+pub mod autonomy;
+pub mod bindings;
+pub mod callouts;
+pub mod camera_controls;
+pub mod checklist;
+pub mod dashboard;
+pub mod dive_log;
+pub mod flight_display;
+pub mod frame_budget;
+pub mod geofence;
+pub mod heading_assist;
+pub mod hid;
 pub mod input;
+pub mod input_client;
+pub mod input_shaping;
+pub mod inspector;
+pub mod journal;
 pub mod layer_allocator;
+pub mod layout;
+pub mod macros;
+pub mod maintenance;
+pub mod measurement;
+pub mod motor_editor;
+pub mod notifications;
+pub mod parameters;
+pub mod photogrammetry;
 pub mod photosphere;
-pub mod shipwreck;
+pub mod pid_tuning;
+pub mod pilot_modes;
+pub mod plotting;
+pub mod preferences;
+pub mod replay;
+pub mod robot_logs;
+pub mod robot_power;
+pub mod robot_update;
+pub mod robot_view;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod session;
+pub mod settings;
+pub mod snapshot;
+pub mod stations;
+pub mod stereo;
 pub mod surface;
+pub mod telemetry_export;
+pub mod thruster_allocation;
+pub mod tool_windows;
+pub mod touch;
 pub mod ui;
 pub mod video_display_2d_master;
 // pub mod video_display_2d_tile;
 // pub mod video_display_3d;
 pub mod video_pipelines;
 pub mod video_stream;
+#[cfg(feature = "rerun")]
+pub mod visualization;
+pub mod wall_clock;
 
-use std::time::Duration;
+use std::{env, time::Duration};
 
 use anyhow::Context;
 use attitude::AttitudePlugin;
+use autonomy::AutonomyPlugin;
 use bevy::{
+    app::PluginGroupBuilder,
     diagnostic::{EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
+    log::LogPlugin,
     pbr::wireframe::WireframePlugin,
     prelude::*,
     render::{
@@ -26,115 +75,223 @@ use bevy::{
         RenderPlugin,
     },
 };
-use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_panorbit_camera::PanOrbitCameraPlugin;
 use bevy_tokio_tasks::TokioTasksPlugin;
-use common::{over_run::OverRunSettings, sync::SyncRole, CommonPlugins};
+use bindings::BindingsPlugin;
+use callouts::CalloutPlugin;
+use camera_controls::CameraControlsPlugin;
+use checklist::ChecklistPlugin;
+use common::{
+    components::SurfaceRole,
+    over_run::{self, OverRunSettings, UnfocusedBudget},
+    sync::SyncRole,
+    CommonPlugins,
+};
 use crossbeam::channel::unbounded;
+use dashboard::DashboardPlugin;
+use dive_log::DiveLogPlugin;
+use frame_budget::FrameBudgetPlugin;
+use geofence::GeofencePlugin;
+use heading_assist::HeadingAssistPlugin;
+use hid::HidPlugin;
 use input::InputPlugin;
+use inspector::InspectorPlugin;
+use journal::JournalPlugin;
+use layout::UiLayoutPlugin;
+use macros::InputMacroPlugin;
+use maintenance::MaintenancePlugin;
+use measurement::MeasurementPlugin;
+use motor_editor::MotorEditorPlugin;
+use notifications::NotificationPlugin;
 use opencv::{highgui, imgcodecs};
+use parameters::ParametersPlugin;
+use photogrammetry::PhotogrammetryPlugin;
 use photosphere::PhotoSpherePlugin;
-use shipwreck::ShipwreckMeasurementPlugin;
+use pid_tuning::PidTuningPlugin;
+use pilot_modes::PilotModesPlugin;
+use plotting::PlottingPlugin;
+use preferences::PreferencesPlugin;
+use replay::TelemetryReplayPlugin;
+use robot_logs::RobotLogsPlugin;
+use robot_power::RobotPowerPlugin;
+use robot_update::RobotUpdatePlugin;
+use robot_view::RobotViewPlugin;
+#[cfg(feature = "scripting")]
+use scripting::ScriptingPlugin;
+use session::SessionPlugin;
+use settings::SettingsPlugin;
+use snapshot::SnapshotPlugin;
+use stations::StationsPlugin;
+use stereo::StereoPlugin;
 use surface::SurfacePlugin;
-use ui::{EguiUiPlugin, ShowInspector};
+use telemetry_export::TelemetryExportPlugin;
+use thruster_allocation::ThrusterAllocationPlugin;
+use touch::TouchControlsPlugin;
+use ui::EguiUiPlugin;
 // use video_display_2d_tile::{VideoDisplay2DPlugin, VideoDisplay2DSettings};
 use video_display_2d_master::{VideoDisplay2DPlugin, VideoDisplay2DSettings};
 // use video_display_3d::{VideoDisplay3DPlugin, VideoDisplay3DSettings};
 use video_stream::VideoStreamPlugin;
+#[cfg(feature = "rerun")]
+use visualization::VisualizationPlugin;
 
 use crate::video_pipelines::{
     measure::{MeasurePipeline, MeasurementTarget},
     Pipeline, PipelineCallbacks, VideoPipelinePlugins,
 };
 
-pub const DARK_MODE: bool = false;
-
 fn main() -> anyhow::Result<()> {
     // opencv_shipwreck()?;
     //
     // return Ok(());
 
+    // Headless gamepad forwarding for a pilot station separate from the video wall
+    let mut args = env::args().skip_while(|arg| arg != "--input-client");
+    if args.next().is_some() {
+        let host = args
+            .next()
+            .context("--input-client needs the robot's host")?;
+
+        return input_client::run(host);
+    }
+
     info!("---------- Starting Control Station ----------");
 
-    // FIXME(high): Times out when focus is lost
-    App::new()
-        .insert_resource(OverRunSettings {
-            max_time: Duration::from_secs_f32(1.0 / 60.0),
-            tracy_frame_mark: false,
-        })
-        .insert_resource(VideoDisplay2DSettings { enabled: true })
-        // .insert_resource(VideoDisplay3DSettings { enabled: true })
-        .insert_resource(if DARK_MODE {
-            ClearColor(Color::srgb_u8(33, 34, 37))
-        } else {
-            ClearColor(Color::srgb_u8(240, 238, 233))
-        })
-        .add_plugins((
-            // Bevy Core
-            DefaultPlugins
-                .build()
-                .disable::<bevy::audio::AudioPlugin>()
-                .set(RenderPlugin {
-                    render_creation: RenderCreation::Automatic(WgpuSettings {
-                        // WARN this is a native only feature. It will not work with webgl or webgpu
-                        features: WgpuFeatures::POLYGON_MODE_LINE,
-                        ..default()
-                    }),
-                    ..default()
-                }),
-            WireframePlugin,
-            MeshPickingPlugin,
-            // .set(TaskPoolPlugin {
-            //     task_pool_options: TaskPoolOptions {
-            //         compute: TaskPoolThreadAssignmentPolicy {
-            //             // set the minimum # of compute threads
-            //             // to the total number of available threads
-            //             min_threads: available_parallelism(),
-            //             max_threads: std::usize::MAX, // unlimited max threads
-            //             percent: 1.0,                 // this value is irrelevant in this case
-            //         },
-            //         // keep the defaults for everything else
-            //         ..default()
-            //     },
-            // }),
-            // Diagnostics
-            (
-                // LogDiagnosticsPlugin::default(),
-                EntityCountDiagnosticsPlugin,
-                FrameTimeDiagnosticsPlugin,
-            ),
-            // MATE
-            (
-                CommonPlugins {
-                    name: "Control Station".to_owned(),
-                    role: SyncRole::Client,
-                },
-                SurfacePlugin,
-                InputPlugin,
-                EguiUiPlugin,
-                AttitudePlugin,
-                PhotoSpherePlugin,
-                VideoStreamPlugin,
-                VideoDisplay2DPlugin,
-                // VideoDisplay3DPlugin,
-                VideoPipelinePlugins,
-                ShipwreckMeasurementPlugin,
-            ),
-            // 3rd Party
-            (
-                TokioTasksPlugin::default(),
-                // TODO(high): Way to close and re open
-                WorldInspectorPlugin::default().run_if(resource_exists::<ShowInspector>),
-                PanOrbitCameraPlugin,
-            ),
-        ))
-        .run();
+    let role = if env::args().any(|arg| arg == "--co-pilot") {
+        SurfaceRole::CoPilot
+    } else {
+        SurfaceRole::Pilot
+    };
+    info!("Requesting the {} role", role.name());
+
+    let mut app = App::new();
+    app.insert_resource(OverRunSettings {
+        max_time: Duration::from_secs_f32(1.0 / 60.0),
+        render_max_time: Some(Duration::from_secs_f32(1.0 / 30.0)),
+        unfocused: UnfocusedBudget::Scaled(4.0),
+        tracy_frame_mark: false,
+        ..default()
+    })
+    .insert_resource(VideoDisplay2DSettings {
+        enabled: true,
+        ..default()
+    })
+    // .insert_resource(VideoDisplay3DSettings { enabled: true })
+    .add_plugins((
+        // Bevy Core
+        default_plugins(),
+        WireframePlugin,
+        MeshPickingPlugin,
+        // .set(TaskPoolPlugin {
+        //     task_pool_options: TaskPoolOptions {
+        //         compute: TaskPoolThreadAssignmentPolicy {
+        //             // set the minimum # of compute threads
+        //             // to the total number of available threads
+        //             min_threads: available_parallelism(),
+        //             max_threads: std::usize::MAX, // unlimited max threads
+        //             percent: 1.0,                 // this value is irrelevant in this case
+        //         },
+        //         // keep the defaults for everything else
+        //         ..default()
+        //     },
+        // }),
+        // Diagnostics
+        (
+            // LogDiagnosticsPlugin::default(),
+            EntityCountDiagnosticsPlugin,
+            FrameTimeDiagnosticsPlugin,
+        ),
+        // MATE
+        (
+            CommonPlugins {
+                name: "Control Station".to_owned(),
+                role: SyncRole::Client,
+            },
+            SurfacePlugin(role),
+            InputPlugin,
+            InputMacroPlugin,
+            BindingsPlugin,
+            PilotModesPlugin,
+            EguiUiPlugin,
+            UiLayoutPlugin,
+            AttitudePlugin,
+            PhotoSpherePlugin,
+            VideoStreamPlugin,
+            VideoDisplay2DPlugin,
+            // VideoDisplay3DPlugin,
+            VideoPipelinePlugins,
+            MeasurementPlugin,
+            StereoPlugin,
+        ),
+        // Tools
+        (
+            RobotLogsPlugin,
+            ChecklistPlugin,
+            NotificationPlugin,
+            PlottingPlugin,
+            DiveLogPlugin,
+            TouchControlsPlugin,
+            SnapshotPlugin,
+            AutonomyPlugin,
+            TelemetryExportPlugin,
+            DashboardPlugin,
+            RobotViewPlugin,
+            MotorEditorPlugin,
+            PidTuningPlugin,
+            StationsPlugin,
+            SessionPlugin,
+        ),
+        (
+            CalloutPlugin,
+            PreferencesPlugin,
+            SettingsPlugin,
+            RobotUpdatePlugin,
+            RobotPowerPlugin,
+            PhotogrammetryPlugin,
+            JournalPlugin,
+            GeofencePlugin,
+            InspectorPlugin,
+            FrameBudgetPlugin,
+            HidPlugin,
+            ParametersPlugin,
+            ThrusterAllocationPlugin,
+            MaintenancePlugin,
+            HeadingAssistPlugin,
+        ),
+        (CameraControlsPlugin, TelemetryReplayPlugin),
+        // 3rd Party
+        (TokioTasksPlugin::default(), PanOrbitCameraPlugin),
+    ));
+
+    #[cfg(feature = "scripting")]
+    app.add_plugins(ScriptingPlugin);
+    #[cfg(feature = "rerun")]
+    app.add_plugins(VisualizationPlugin);
+
+    app.run();
 
     info!("---------- Control Station Exited Cleanly ----------");
 
     Ok(())
 }
 
+fn default_plugins() -> PluginGroupBuilder {
+    DefaultPlugins
+        .build()
+        .set(RenderPlugin {
+            render_creation: RenderCreation::Automatic(WgpuSettings {
+                // WARN this is a native only feature. It will not work with webgl or webgpu
+                features: WgpuFeatures::POLYGON_MODE_LINE,
+                ..default()
+            }),
+            ..default()
+        })
+        .set(LogPlugin {
+            custom_layer: over_run::system_timing_layer,
+            ..default()
+        })
+}
+
 fn opencv_pipeline() -> anyhow::Result<()> {
     let mut img = imgcodecs::imread_def("test.jpg").context("Read image")?;
 
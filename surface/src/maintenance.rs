@@ -0,0 +1,99 @@
+//! Confirmation, progress and findings for the robot's actuator maintenance routines, started from
+//! the Maintenance menu. The routines themselves run on the robot's `maintenance` plugin
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{Maintenance, MaintenanceFinding, Robot},
+    events::{MaintenanceCommand, MaintenanceStep},
+};
+use egui::{widgets, Align2, Color32, RichText};
+
+pub struct MaintenancePlugin;
+
+impl Plugin for MaintenancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, maintenance_ui);
+    }
+}
+
+fn maintenance_ui(
+    mut contexts: EguiContexts,
+    // Findings the pilot already closed, shown again once a new routine reports
+    mut dismissed: Local<Vec<MaintenanceFinding>>,
+    // TODO(low): Support multiple robots
+    robot: Query<&Maintenance, With<Robot>>,
+    mut commands: EventWriter<MaintenanceCommand>,
+) {
+    let Ok(maintenance) = robot.get_single() else {
+        return;
+    };
+
+    if maintenance.running.is_some() {
+        dismissed.clear();
+    }
+
+    let show_findings = !maintenance.findings.is_empty() && maintenance.findings != *dismissed;
+    if maintenance.override_challenge.is_none() && maintenance.running.is_none() && !show_findings {
+        return;
+    }
+
+    egui::Window::new("Maintenance")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .show(contexts.ctx_mut(), |ui| {
+            if let Some((routine, challenge)) = maintenance.override_challenge {
+                ui.label(RichText::new(format!("Run {}?", routine.name())).strong());
+                ui.label("The robot will drive its actuators while disarmed. Keep clear of them.");
+
+                ui.horizontal(|ui| {
+                    if ui.button("Confirm").clicked() {
+                        warn!("Confirming {}", routine.name());
+                        commands.send(MaintenanceCommand(MaintenanceStep::Confirm(challenge)));
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        commands.send(MaintenanceCommand(MaintenanceStep::Cancel));
+                    }
+                });
+            } else if let Some(running) = &maintenance.running {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    RichText::new(format!("Running {}", running.routine.name())).strong(),
+                );
+
+                if running.actuator.is_empty() {
+                    ui.label("Waiting for the escs");
+                } else {
+                    ui.label(format!("Testing {}", running.actuator));
+                }
+
+                ui.add(
+                    widgets::ProgressBar::new(running.progress)
+                        .desired_width(250.0)
+                        .show_percentage(),
+                );
+
+                if ui.button("Stop").clicked() {
+                    commands.send(MaintenanceCommand(MaintenanceStep::Cancel));
+                }
+            } else {
+                for finding in &maintenance.findings {
+                    ui.horizontal(|ui| {
+                        if finding.passed {
+                            ui.colored_label(Color32::GREEN, "OK");
+                        } else {
+                            ui.colored_label(Color32::RED, "FAIL");
+                        }
+
+                        ui.label(format!("{}: {}", finding.actuator, finding.detail));
+                    });
+                }
+
+                if ui.button("Close").clicked() {
+                    *dismissed = maintenance.findings.clone();
+                }
+            }
+        });
+}
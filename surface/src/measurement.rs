@@ -0,0 +1,854 @@
+//! Measures lengths on a plane using a reference object of known size
+//!
+//! The operator marks the four corners of a reference whose bottom edge has a known length, the
+//! quad pins down the orientation and scale of the plane it lies on so any segment drawn on that
+//! plane can then be measured
+//!
+//! Without a reference, lengths are scaled by the robot's laser scaler dots when they were found
+//! in the frame.
+
+pub mod picking;
+
+use std::{f32, fmt::Write as _, fs};
+
+use anyhow::{anyhow, Context};
+use bevy::{math::Mat3A, prelude::*};
+use bevy_egui::{EguiContexts, EguiUserTextures};
+use common::error::ErrorEvent;
+use egui::{Color32, Id, RichText, TextureId};
+use egui_plot::{Line, Plot, PlotImage, PlotPoints, Points, Polygon, Text};
+use opencv::{
+    core::{AlgorithmHint, Point, Scalar},
+    imgcodecs, imgproc,
+    prelude::*,
+};
+use picking::PoiPickingPlugin;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Iso8601;
+
+use crate::{
+    session::{artifact_directory, ActiveSession},
+    settings::config_path,
+    video_pipelines::{
+        copy_to_ecs::{CopyToEcsPipeline, CopyToEcsState},
+        laser::{LaserScale, LaserScalePipeline, LaserScalerConfig},
+        save::SavePipeline,
+        undistort::{CroppedCameraMatrix, UndistortPipeline},
+        AppPipelineExt, SerialPipeline,
+    },
+    wall_clock::now,
+};
+
+pub const REFERENCE_CATALOG_FILE: &str = "measurement_references.toml";
+pub const MEASUREMENT_DIRECTORY: &str = "measurements";
+pub const MEASURE_PIPELINE_NAME: &str = "Measure Image";
+
+const REFERENCE_CORNERS: usize = 4;
+const CUSTOM_REFERENCE: &str = "Custom";
+
+pub struct MeasurementPlugin;
+
+impl Plugin for MeasurementPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<SerialPipeline<(
+            UndistortPipeline,
+            LaserScalePipeline,
+            SavePipeline,
+            CopyToEcsPipeline<MeasurementBundle>,
+        )>>(MEASURE_PIPELINE_NAME)
+            .add_plugins(PoiPickingPlugin)
+            .add_event::<ExportMeasurement>()
+            .add_systems(PreStartup, load_reference_catalog)
+            .add_systems(
+                Update,
+                (measurement_ui, export_measurement.after(measurement_ui)),
+            );
+    }
+}
+
+/// Objects of known size to measure against, loaded from `measurement_references.toml`
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceCatalog {
+    pub references: Vec<ReferenceObject>,
+    #[serde(default)]
+    pub laser_scaler: LaserScalerConfig,
+}
+
+impl Default for ReferenceCatalog {
+    fn default() -> Self {
+        Self {
+            references: vec![
+                ReferenceObject {
+                    name: "Shipwreck Width".to_owned(),
+                    length: 0.47,
+                    // The bow overhangs the measured hull
+                    offset: 0.30 * f32::consts::FRAC_1_SQRT_2,
+                },
+                ReferenceObject {
+                    name: "1/2in PVC Pipe".to_owned(),
+                    length: 0.0213,
+                    offset: 0.0,
+                },
+                ReferenceObject {
+                    name: "Checkerboard Tile".to_owned(),
+                    length: 0.025,
+                    offset: 0.0,
+                },
+                ReferenceObject {
+                    name: "Ruler Sticker".to_owned(),
+                    length: 0.10,
+                    offset: 0.0,
+                },
+            ],
+            laser_scaler: LaserScalerConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceObject {
+    pub name: String,
+    /// Real length of the reference's bottom edge in meters
+    pub length: f32,
+    /// Added to every length measured against this reference, for parts of the target that are
+    /// known but can not be seen
+    #[serde(default)]
+    pub offset: f32,
+}
+
+impl Default for ReferenceObject {
+    fn default() -> Self {
+        Self {
+            name: CUSTOM_REFERENCE.to_owned(),
+            length: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ExportMeasurement(pub Entity);
+
+#[derive(Bundle)]
+pub struct MeasurementBundle {
+    pub image: MeasurementImage,
+    pub pois: MeasurementPOIs,
+    pub camera_mat: CroppedCameraMatrix,
+}
+
+#[derive(Component, Clone)]
+pub struct MeasurementImage {
+    pub image_handle: Handle<Image>,
+    pub egui_texture: TextureId,
+}
+
+impl<'a> TryFrom<CopyToEcsState<'a>> for MeasurementBundle {
+    type Error = anyhow::Error;
+
+    fn try_from(state: CopyToEcsState<'a>) -> anyhow::Result<Self> {
+        let mut image_assets = state
+            .world
+            .get_resource_mut::<Assets<Image>>()
+            .context("Get image asset manager")?;
+        let image_handle = image_assets.add(state.img);
+
+        let mut egui_textures = state
+            .world
+            .get_resource_mut::<EguiUserTextures>()
+            .context("Get egui texture manager")?;
+        let egui_texture = egui_textures.add_image(image_handle.clone_weak());
+
+        let camera_mat = *state
+            .world
+            .get::<CroppedCameraMatrix>(state.camera_entity)
+            .context("Get camera matrix")?;
+
+        let reference_object = state
+            .world
+            .get_resource::<ReferenceCatalog>()
+            .and_then(|it| it.references.first().cloned())
+            .unwrap_or_default();
+
+        // Found in this frame by the `LaserScalePipeline` earlier in the pipeline
+        let laser = state.world.get::<LaserScale>(state.camera_entity).copied();
+
+        Ok(Self {
+            image: MeasurementImage {
+                image_handle,
+                egui_texture,
+            },
+            pois: MeasurementPOIs {
+                reference_object,
+                laser,
+                ..default()
+            },
+            camera_mat,
+        })
+    }
+}
+
+/// What clicking on the measurement image edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PickMode {
+    #[default]
+    Reference,
+    Measure,
+}
+
+#[derive(Component, Default, Clone)]
+pub struct MeasurementPOIs {
+    /// Corners of the reference: bottom-left, bottom-right, top-right, top-left
+    pub reference: Vec<Vec2>,
+    /// Start and end of each measurement, in pairs
+    pub points: Vec<Vec2>,
+    pub reference_object: ReferenceObject,
+    pub mode: PickMode,
+    /// The laser scaler dots in the image, used when there is no reference
+    pub laser: Option<LaserScale>,
+
+    /// Earlier `reference` and `points`, newest last
+    history: Vec<(Vec<Vec2>, Vec<Vec2>)>,
+}
+
+impl MeasurementPOIs {
+    /// Adds a point for the current mode, once the reference is complete clicks in reference
+    /// mode move its closest corner instead
+    pub fn place(&mut self, point: Vec2) {
+        self.checkpoint();
+
+        match self.mode {
+            PickMode::Reference if self.reference.len() < REFERENCE_CORNERS => {
+                self.reference.push(point);
+            }
+            PickMode::Reference => {
+                let closest = self.reference.iter_mut().min_by(|a, b| {
+                    f32::total_cmp(&a.distance_squared(point), &b.distance_squared(point))
+                });
+
+                if let Some(closest) = closest {
+                    *closest = point;
+                }
+            }
+            PickMode::Measure => self.points.push(point),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        if let Some((reference, points)) = self.history.pop() {
+            self.reference = reference;
+            self.points = points;
+        }
+    }
+
+    pub fn clear_measurements(&mut self) {
+        self.checkpoint();
+        self.points.clear();
+    }
+
+    fn checkpoint(&mut self) {
+        self.history
+            .push((self.reference.clone(), self.points.clone()));
+    }
+
+    /// The plane the reference lies on, once all of its corners are placed
+    pub fn plane(&self, camera_mat: Mat3A) -> Option<ReferencePlane> {
+        ReferencePlane::from_reference(&self.reference, self.reference_object.length, camera_mat)
+    }
+
+    /// Every complete segment with its real length in meters if it could be measured
+    ///
+    /// A complete reference takes priority over the laser dots, it accounts for the perspective
+    /// of the plane.
+    pub fn measurements(&self, camera_mat: Mat3A) -> Vec<(Vec2, Vec2, Option<f32>)> {
+        let plane = self.plane(camera_mat);
+
+        self.points
+            .chunks_exact(2)
+            .map(|it| {
+                let length = match (plane, self.laser) {
+                    (Some(plane), _) => plane
+                        .distance(it[0], it[1])
+                        .map(|length| length + self.reference_object.offset),
+                    (None, Some(laser)) => Some(laser.distance(it[0], it[1])),
+                    (None, None) => None,
+                };
+
+                (it[0], it[1], length)
+            })
+            .collect()
+    }
+}
+
+/// A plane in camera space recovered from the perspective of a reference rectangle
+#[derive(Debug, Clone, Copy)]
+pub struct ReferencePlane {
+    normal: Vec3,
+    /// Converts from the unit depth plane to real units
+    scale: f32,
+    camera_mat: Mat3A,
+}
+
+impl ReferencePlane {
+    /// corners: bottom-left, bottom-right, top-right, top-left
+    /// width: the known real length of the bottom edge (in whatever units you like)
+    pub fn from_reference(corners: &[Vec2], width: f32, camera_mat: Mat3A) -> Option<Self> {
+        if corners.len() != REFERENCE_CORNERS {
+            return None;
+        }
+
+        // build homogeneous points p[i], these are also the back-projected rays
+        let mut p = [Vec3::ZERO; 4];
+        for i in 0..4 {
+            p[i] = normalize_point(corners[i], camera_mat).extend(1.0)
+        }
+
+        // vanishing in width direction = intersection of lines (p0,p1) and (p3,p2)
+        let l01 = p[0].cross(p[1]);
+        let l32 = p[3].cross(p[2]);
+        let v_w = l01.cross(l32);
+
+        // vanishing in length direction = intersection of lines (p1,p2) and (p0,p3)
+        let l12 = p[1].cross(p[2]);
+        let l03 = p[0].cross(p[3]);
+        let v_l = l12.cross(l03);
+
+        // plane normal (in camera space)
+        let normal = v_w.cross(v_l).try_normalize()?;
+
+        let mut plane = Self {
+            normal,
+            scale: 1.0,
+            camera_mat,
+        };
+
+        // length in camera‐space of the known edge (p0→p1)
+        let denom = plane
+            .project(corners[0])?
+            .distance(plane.project(corners[1])?);
+        if denom.abs() < 1e-6 {
+            return None; // degenerate
+        }
+
+        // global scale to make that edge == width
+        plane.scale = width / denom;
+
+        Some(plane)
+    }
+
+    /// Where the ray through an image point hits the plane, in camera space
+    pub fn project(&self, point: Vec2) -> Option<Vec3> {
+        let ray = normalize_point(point, self.camera_mat).extend(1.0);
+
+        // αᵢ = 1 / (n·rᵢ)
+        let depth = self.normal.dot(ray);
+        if depth.abs() < 1e-6 {
+            return None;
+        }
+
+        Some(ray / depth * self.scale)
+    }
+
+    /// Real distance between two image points on the plane
+    pub fn distance(&self, a: Vec2, b: Vec2) -> Option<f32> {
+        Some(self.project(a)?.distance(self.project(b)?))
+    }
+}
+
+/// corners: bottom-left, bottom-right, top-right, top-left
+/// width: the known real length of the bottom edge (in whatever units you like)
+/// returns the real length of the right edge
+pub fn measure_length_calibrated(corners: &[Vec2], width: f32, camera_mat: Mat3A) -> Option<f32> {
+    ReferencePlane::from_reference(corners, width, camera_mat)?.distance(corners[1], corners[2])
+}
+
+fn normalize_point(point: Vec2, camera_mat: Mat3A) -> Vec2 {
+    Vec2::new(
+        (point.x - camera_mat.x_axis.z) / camera_mat.x_axis.x,
+        (point.y - camera_mat.y_axis.z) / camera_mat.y_axis.y,
+    )
+}
+
+fn load_reference_catalog(mut cmds: Commands) {
+    let res: anyhow::Result<ReferenceCatalog> = try {
+        let catalog =
+            fs::read_to_string(config_path(REFERENCE_CATALOG_FILE)).context("Read references")?;
+        toml::from_str(&catalog).context("Parse references")?
+    };
+
+    let catalog = match res {
+        Ok(catalog) => catalog,
+        Err(err) => {
+            warn!("Using default measurement references: {err:?}");
+            ReferenceCatalog::default()
+        }
+    };
+
+    cmds.insert_resource(catalog);
+}
+
+fn measurement_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut measurements: Query<(
+        Entity,
+        &MeasurementImage,
+        &mut MeasurementPOIs,
+        &CroppedCameraMatrix,
+    )>,
+    catalog: Res<ReferenceCatalog>,
+    images: Res<Assets<Image>>,
+    mut exports: EventWriter<ExportMeasurement>,
+) {
+    for (entity, image, mut pois, camera_mat) in measurements.iter_mut() {
+        let mut open = true;
+
+        let context = contexts.ctx_mut();
+        egui::Window::new("Measurement")
+            .id(Id::new(entity))
+            .constrain_to(context.available_rect().shrink(20.0))
+            .default_size((230.0, 230.0))
+            .open(&mut open)
+            .show(context, |ui| {
+                egui::ComboBox::from_label("Reference")
+                    .selected_text(pois.reference_object.name.clone())
+                    .show_ui(ui, |ui| {
+                        for reference in &catalog.references {
+                            let selected = pois.reference_object == *reference;
+                            if ui
+                                .selectable_label(selected, reference.name.as_str())
+                                .clicked()
+                            {
+                                pois.reference_object = reference.clone();
+                            }
+                        }
+
+                        let custom = pois.reference_object.name == CUSTOM_REFERENCE;
+                        if ui.selectable_label(custom, CUSTOM_REFERENCE).clicked() {
+                            pois.reference_object = ReferenceObject {
+                                length: pois.reference_object.length,
+                                ..default()
+                            };
+                        }
+                    });
+
+                if pois.reference_object.name == CUSTOM_REFERENCE {
+                    ui.horizontal(|ui| {
+                        ui.label("Known Length");
+                        ui.add(
+                            egui::DragValue::new(&mut pois.reference_object.length)
+                                .range(0.001..=100.0)
+                                .speed(0.01)
+                                .suffix("m"),
+                        );
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut pois.mode, PickMode::Reference, "Reference");
+                    ui.selectable_value(&mut pois.mode, PickMode::Measure, "Measure");
+
+                    if ui
+                        .add_enabled(pois.can_undo(), egui::Button::new("Undo"))
+                        .clicked()
+                    {
+                        pois.undo();
+                    }
+                    if ui.button("Clear").clicked() {
+                        pois.clear_measurements();
+                    }
+                    if ui.button("Export").clicked() {
+                        exports.send(ExportMeasurement(entity));
+                    }
+                });
+
+                match pois.mode {
+                    PickMode::Reference => ui.label(
+                        "Corner Order: bottom-left, bottom-right, top-right, top-left\n\
+                         Known side is bottom/top",
+                    ),
+                    PickMode::Measure => {
+                        ui.label("Click the start then the end of each length on the same plane")
+                    }
+                };
+
+                let measured = pois.measurements(camera_mat.mat);
+
+                let response = Plot::new("Measurement Plot")
+                    .data_aspect(1.0)
+                    .min_size(egui::Vec2::new(100.0, 100.0))
+                    .width(ui.available_width())
+                    .height(ui.available_width())
+                    .show(ui, |ui| {
+                        let image_size = images
+                            .get(&image.image_handle)
+                            .map(|it| it.size_f32())
+                            .unwrap_or_default();
+
+                        ui.image(PlotImage::new(
+                            "Measurement",
+                            image.egui_texture,
+                            [image_size.x as f64 / 2.0, -image_size.y as f64 / 2.0].into(),
+                            [image_size.x, image_size.y],
+                        ));
+
+                        let to_plot = |it: Vec2| [it.x as f64, -it.y as f64];
+
+                        for (idx, point) in pois.reference.iter().enumerate() {
+                            ui.points(
+                                Points::new(format!("Reference {idx}"), to_plot(*point))
+                                    .color(Color32::RED)
+                                    .radius(3.0)
+                                    .id(Id::new(("reference", idx))),
+                            );
+                        }
+
+                        if let Some(laser) = pois.laser {
+                            ui.points(
+                                Points::new(
+                                    "Laser Dots",
+                                    laser.dots.map(to_plot).into_iter().collect::<PlotPoints>(),
+                                )
+                                .color(Color32::GREEN)
+                                .radius(3.0),
+                            );
+                        }
+
+                        ui.polygon(
+                            Polygon::new(
+                                "Reference",
+                                pois.reference
+                                    .iter()
+                                    .map(|it| to_plot(*it))
+                                    .collect::<PlotPoints>(),
+                            )
+                            .stroke((2.0, Color32::RED)),
+                        );
+
+                        for (idx, (start, end, length)) in measured.iter().enumerate() {
+                            ui.line(
+                                Line::new(
+                                    format!("Measurement {idx}"),
+                                    PlotPoints::from(vec![to_plot(*start), to_plot(*end)]),
+                                )
+                                .color(Color32::YELLOW)
+                                .width(2.0),
+                            );
+
+                            let label = match length {
+                                Some(length) => format!("{length:.2}m"),
+                                None => "?".to_owned(),
+                            };
+                            ui.text(Text::new(
+                                format!("Measurement {idx} Length"),
+                                to_plot((*start + *end) / 2.0).into(),
+                                RichText::new(label).color(Color32::YELLOW).strong(),
+                            ));
+                        }
+
+                        if pois.points.len() % 2 == 1 {
+                            if let Some(start) = pois.points.last() {
+                                ui.points(
+                                    Points::new("Measurement Start", to_plot(*start))
+                                        .color(Color32::YELLOW)
+                                        .radius(3.0),
+                                );
+                            }
+                        }
+                    });
+
+                if let Some(pointer) = response.response.hover_pos() {
+                    if response.response.clicked() {
+                        let point = response.transform.value_from_position(pointer);
+                        pois.place(Vec2::new(point.x as f32, -point.y as f32));
+                    }
+                }
+
+                if pois.reference.len() == REFERENCE_CORNERS && pois.plane(camera_mat.mat).is_none()
+                {
+                    ui.colored_label(Color32::RED, "The reference corners are degenerate");
+                }
+
+                if let Some(laser) = pois.laser {
+                    let source = if pois.plane(camera_mat.mat).is_some() {
+                        "unused, the reference is complete"
+                    } else {
+                        "used without a reference"
+                    };
+                    ui.label(format!(
+                        "Laser Dots: {:.0} px/m ({source})",
+                        laser.px_per_meter
+                    ));
+                }
+
+                for (idx, (.., length)) in measured.iter().enumerate() {
+                    match length {
+                        Some(length) => ui.label(format!("Measurement {}: {length:.2}m", idx + 1)),
+                        None => ui.label(format!(
+                            "Measurement {}: Needs a reference or laser dots",
+                            idx + 1
+                        )),
+                    };
+                }
+            });
+
+        if !open {
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn export_measurement(
+    mut events: EventReader<ExportMeasurement>,
+    measurements: Query<(&MeasurementImage, &MeasurementPOIs, &CroppedCameraMatrix)>,
+    images: Res<Assets<Image>>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for &ExportMeasurement(entity) in events.read() {
+        let res: anyhow::Result<()> = try {
+            let (image, pois, camera_mat) = measurements
+                .get(entity)
+                .map_err(|_| anyhow!("Measurement no longer exists"))?;
+            let image = images
+                .get(&image.image_handle)
+                .context("Measurement image is not loaded")?;
+
+            let size = image.size();
+            let rgba = Mat::from_slice(&image.data).context("Wrap image")?;
+            let rgba = rgba.reshape(4, size.y as i32).context("Reshape image")?;
+
+            let mut img = Mat::default();
+            imgproc::cvt_color(
+                &rgba,
+                &mut img,
+                imgproc::COLOR_RGBA2BGR,
+                0,
+                AlgorithmHint::ALGO_HINT_DEFAULT,
+            )
+            .context("Convert colors")?;
+
+            let measured = pois.measurements(camera_mat.mat);
+            draw_measurements(&mut img, pois, &measured).context("Draw measurements")?;
+
+            let directory = artifact_directory(session.as_deref(), MEASUREMENT_DIRECTORY);
+            fs::create_dir_all(&directory).context("Create measurement directory")?;
+
+            let time = now().format(&Iso8601::DATE_TIME).context("Format time")?;
+            let base = format!("{}/measurement_{time}", directory.display());
+            let image_path = format!("{base}.png");
+            let csv_path = format!("{base}.csv");
+
+            imgcodecs::imwrite_def(&image_path, &img).context("Write annotated image")?;
+            fs::write(&csv_path, measurements_csv(pois, &measured))
+                .context("Write measurements")?;
+
+            info!("Exported measurement to {image_path}");
+        };
+
+        if let Err(err) = res {
+            errors.send(err.context("Export measurement").into());
+        }
+    }
+}
+
+fn draw_measurements(
+    img: &mut Mat,
+    pois: &MeasurementPOIs,
+    measured: &[(Vec2, Vec2, Option<f32>)],
+) -> anyhow::Result<()> {
+    // BGR
+    let red = Scalar::new(0.0, 0.0, 255.0, 0.0);
+    let yellow = Scalar::new(0.0, 255.0, 255.0, 0.0);
+
+    let to_cv = |it: Vec2| Point::new(it.x.round() as i32, it.y.round() as i32);
+
+    for (idx, corner) in pois.reference.iter().enumerate() {
+        imgproc::circle(img, to_cv(*corner), 5, red, -1, imgproc::LINE_AA, 0)?;
+
+        if pois.reference.len() == REFERENCE_CORNERS {
+            let next = pois.reference[(idx + 1) % REFERENCE_CORNERS];
+            imgproc::line(
+                img,
+                to_cv(*corner),
+                to_cv(next),
+                red,
+                2,
+                imgproc::LINE_AA,
+                0,
+            )?;
+        }
+    }
+
+    for (start, end, length) in measured {
+        imgproc::line(
+            img,
+            to_cv(*start),
+            to_cv(*end),
+            yellow,
+            2,
+            imgproc::LINE_AA,
+            0,
+        )?;
+
+        let label = match length {
+            Some(length) => format!("{length:.2}m"),
+            None => "?".to_owned(),
+        };
+        imgproc::put_text(
+            img,
+            &label,
+            to_cv((*start + *end) / 2.0),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.7,
+            yellow,
+            2,
+            imgproc::LINE_AA,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn measurements_csv(pois: &MeasurementPOIs, measured: &[(Vec2, Vec2, Option<f32>)]) -> String {
+    let reference = &pois.reference_object;
+    let mut csv = format!(
+        "# Reference: {} ({}m, offset {}m)\nmeasurement,start_x,start_y,end_x,end_y,length_m\n",
+        reference.name, reference.length, reference.offset
+    );
+
+    for (idx, (start, end, length)) in measured.iter().enumerate() {
+        let length = length.map(|it| it.to_string()).unwrap_or_default();
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{length}",
+            idx + 1,
+            start.x,
+            start.y,
+            end.x,
+            end.y
+        );
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{vec3a, Mat3A, Vec2, Vec3};
+    use opencv::{
+        core::{self, Point, Scalar, Vector},
+        imgproc,
+        prelude::*,
+    };
+
+    use crate::video_pipelines::{
+        harness::{self, PipelineHarness},
+        undistort::{CroppedCameraMatrix, UndistortPipeline},
+    };
+
+    use super::measure_length_calibrated;
+
+    /// Corners of the largest red quad in the frame, bottom-left, bottom-right, top-right, top-left
+    fn red_quad(img: &Mat) -> Vec<Vec2> {
+        let mut mask = Mat::default();
+        core::in_range(
+            img,
+            &Scalar::new(0.0, 0.0, 150.0, 0.0),
+            &Scalar::new(80.0, 80.0, 255.0, 0.0),
+            &mut mask,
+        )
+        .expect("Mask red");
+
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours_def(
+            &mask,
+            &mut contours,
+            imgproc::RETR_EXTERNAL,
+            imgproc::CHAIN_APPROX_SIMPLE,
+        )
+        .expect("Find contours");
+
+        let contour = contours
+            .iter()
+            .max_by(|a, b| {
+                let a = imgproc::contour_area_def(a).unwrap_or_default();
+                let b = imgproc::contour_area_def(b).unwrap_or_default();
+                a.total_cmp(&b)
+            })
+            .expect("No red quad in frame");
+
+        let epsilon = 0.02 * imgproc::arc_length(&contour, true).expect("Arc length");
+        let mut quad = Vector::<Point>::new();
+        imgproc::approx_poly_dp(&contour, &mut quad, epsilon, true).expect("Approximate quad");
+        assert_eq!(quad.len(), 4, "Red shape is not a quad");
+
+        let mut points = quad
+            .iter()
+            .map(|it| Vec2::new(it.x as f32, it.y as f32))
+            .collect::<Vec<_>>();
+        points.sort_by(|a, b| b.y.total_cmp(&a.y));
+
+        let (bottom, top) = points.split_at_mut(2);
+        bottom.sort_by(|a, b| a.x.total_cmp(&b.x));
+        top.sort_by(|a, b| b.x.total_cmp(&a.x));
+
+        points
+    }
+
+    #[test]
+    fn measure_projected_rectangle() {
+        let camera_mat = Mat3A::from_cols(
+            vec3a(800.0, 0.0, 640.0),
+            vec3a(0.0, 800.0, 360.0),
+            vec3a(0.0, 0.0, 1.0),
+        );
+
+        // 0.5m by 1.2m rectangle on a plane tilted away from the camera
+        let origin = Vec3::new(-0.25, 0.3, 2.0);
+        let width = Vec3::X * 0.5;
+        let length = Vec3::new(0.0, -0.6, 0.8) * 1.2;
+
+        let corners = [
+            origin,
+            origin + width,
+            origin + width + length,
+            origin + length,
+        ]
+        .map(|it| Vec2::new(800.0 * it.x / it.z + 640.0, 800.0 * it.y / it.z + 360.0));
+
+        let measured = measure_length_calibrated(&corners, 0.5, camera_mat).expect("Measure");
+        assert!((measured - 1.2).abs() < 1e-3, "Measured {measured}m");
+    }
+
+    #[test]
+    fn measure_shipwreck_frame() {
+        let golden = harness::golden();
+        let frame = harness::sample_frame(&golden.shipwreck.frame);
+
+        let (world, camera) = harness::camera_world(&golden.camera);
+        let mut harness = PipelineHarness::<UndistortPipeline>::new(world, camera);
+        let output = harness.process(&frame);
+
+        let camera_mat = harness
+            .world
+            .get::<CroppedCameraMatrix>(camera)
+            .expect("Cropped camera matrix was not published")
+            .mat;
+
+        let corners = red_quad(&output);
+        let measured = measure_length_calibrated(&corners, golden.shipwreck.width, camera_mat)
+            .expect("Measure");
+
+        let error = (measured - golden.shipwreck.length).abs() / golden.shipwreck.length;
+        assert!(
+            error < golden.shipwreck.tolerance,
+            "Measured {measured}m, expected {}m",
+            golden.shipwreck.length
+        );
+    }
+}
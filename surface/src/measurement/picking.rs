@@ -0,0 +1,388 @@
+//! Places measurement points by clicking on the master video display
+//!
+//! Picking starts the measure pipeline on the master camera, once it has captured a frame the
+//! display shows that frame instead of the live video until picking is done. Clicks snap to the
+//! nearest corner with sub-pixel accuracy and a loupe magnifies the area under the cursor
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use egui::{load::SizedTexture, pos2, vec2, Color32, Stroke};
+use opencv::{
+    core::{AlgorithmHint, Point2f, Size, TermCriteria, TermCriteria_Type, Vector},
+    imgproc,
+    prelude::*,
+};
+
+use crate::{
+    measurement::{MeasurementImage, MeasurementPOIs, PickMode, MEASURE_PIPELINE_NAME},
+    video_display_2d_master::VideoMasterMarker,
+    video_pipelines::VideoPipelines,
+    video_stream::ImageHandle,
+};
+
+/// Half size of the corner search window in pixels
+const REFINE_RADIUS: i32 = 5;
+/// Side length of the loupe in the picker window
+const LOUPE_SIZE: f32 = 200.0;
+
+pub struct PoiPickingPlugin;
+
+impl Plugin for PoiPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                // Runs every frame so `Added` only sees measurements spawned while picking
+                attach_measurement,
+                picking_ui
+                    .after(attach_measurement)
+                    .run_if(resource_exists::<PoiPicking>),
+            ),
+        )
+        .add_observer(start_picking)
+        .add_observer(stop_picking)
+        .add_observer(pick_on_click)
+        .add_observer(track_hover);
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct PoiPicking {
+    pub camera: Entity,
+    /// Spawned by the measure pipeline once it has captured the frame
+    pub measurement: Option<Entity>,
+    /// Pixel of the paused frame under the cursor
+    pub hovered: Option<Vec2>,
+    pub zoom: f32,
+    /// Snap clicks to the nearest corner
+    pub refine: bool,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StartPoiPicking;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StopPoiPicking;
+
+fn start_picking(
+    _event: Trigger<StartPoiPicking>,
+    mut cmds: Commands,
+    master: Query<Entity, With<VideoMasterMarker>>,
+    pipelines: Res<VideoPipelines>,
+    picking: Option<Res<PoiPicking>>,
+) {
+    if picking.is_some() {
+        return;
+    }
+
+    let Ok(camera) = master.get_single() else {
+        warn!("No master camera to pick points on");
+        return;
+    };
+
+    let Some(pipeline) = pipelines
+        .0
+        .iter()
+        .find(|it| it.name == MEASURE_PIPELINE_NAME)
+    else {
+        error!("Measure pipeline is not registered");
+        return;
+    };
+
+    cmds.entity(camera).insert(pipeline.factory.clone());
+    cmds.insert_resource(PoiPicking {
+        camera,
+        measurement: None,
+        hovered: None,
+        zoom: 4.0,
+        refine: true,
+    });
+}
+
+fn stop_picking(
+    _event: Trigger<StopPoiPicking>,
+    mut cmds: Commands,
+    picking: Option<Res<PoiPicking>>,
+    displays: Query<(&ImageHandle, &MeshMaterial2d<ColorMaterial>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Some(picking) = picking else {
+        return;
+    };
+
+    // Back to the live video
+    if let Ok((image, material)) = displays.get(picking.camera) {
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.texture = Some(image.0.clone_weak());
+        }
+    }
+
+    cmds.remove_resource::<PoiPicking>();
+}
+
+/// Pauses the display on the frame the measure pipeline captured
+fn attach_measurement(
+    picking: Option<ResMut<PoiPicking>>,
+    added: Query<(Entity, &MeasurementImage), Added<MeasurementPOIs>>,
+    displays: Query<&MeshMaterial2d<ColorMaterial>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Some(mut picking) = picking.filter(|it| it.measurement.is_none()) else {
+        return;
+    };
+
+    let Some((entity, image)) = added.iter().next() else {
+        return;
+    };
+
+    picking.measurement = Some(entity);
+
+    if let Ok(material) = displays.get(picking.camera) {
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.texture = Some(image.image_handle.clone());
+        }
+    }
+}
+
+fn pick_on_click(
+    event: Trigger<Pointer<Click>>,
+    picking: Option<Res<PoiPicking>>,
+    displays: Query<&GlobalTransform>,
+    mut measurements: Query<(&MeasurementImage, &mut MeasurementPOIs)>,
+    images: Res<Assets<Image>>,
+) {
+    let Some(picking) = picking else {
+        return;
+    };
+    if event.entity() != picking.camera || event.button != PointerButton::Primary {
+        return;
+    }
+
+    let Some((image, mut pois)) = picking
+        .measurement
+        .and_then(|it| measurements.get_mut(it).ok())
+    else {
+        return;
+    };
+    let Some(image) = images.get(&image.image_handle) else {
+        return;
+    };
+    let Ok(display) = displays.get(picking.camera) else {
+        return;
+    };
+
+    let Some(point) = event
+        .hit
+        .position
+        .and_then(|hit| image_point(hit, display, image.size_f32()))
+    else {
+        return;
+    };
+
+    let point = if picking.refine {
+        refine_corner(image, point).unwrap_or_else(|err| {
+            warn!("Could not refine point: {err:?}");
+            point
+        })
+    } else {
+        point
+    };
+
+    pois.place(point);
+}
+
+fn track_hover(
+    event: Trigger<Pointer<Move>>,
+    picking: Option<ResMut<PoiPicking>>,
+    displays: Query<&GlobalTransform>,
+    measurements: Query<&MeasurementImage>,
+    images: Res<Assets<Image>>,
+) {
+    let Some(mut picking) = picking else {
+        return;
+    };
+    if event.entity() != picking.camera {
+        return;
+    }
+
+    let size = picking
+        .measurement
+        .and_then(|it| measurements.get(it).ok())
+        .and_then(|it| images.get(&it.image_handle))
+        .map(|it| it.size_f32());
+    let Some(size) = size else {
+        return;
+    };
+    let Ok(display) = displays.get(picking.camera) else {
+        return;
+    };
+
+    picking.hovered = event
+        .hit
+        .position
+        .and_then(|hit| image_point(hit, display, size));
+}
+
+fn picking_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut picking: ResMut<PoiPicking>,
+    mut measurements: Query<(&MeasurementImage, &mut MeasurementPOIs)>,
+    images: Res<Assets<Image>>,
+) {
+    let mut open = true;
+
+    let context = contexts.ctx_mut();
+    egui::Window::new("Pick Points")
+        .constrain_to(context.available_rect().shrink(20.0))
+        .default_size((230.0, 230.0))
+        .open(&mut open)
+        .show(context, |ui| {
+            let Some((image, mut pois)) = picking
+                .measurement
+                .and_then(|it| measurements.get_mut(it).ok())
+            else {
+                ui.label("Waiting for a frame from the measure pipeline...");
+                return;
+            };
+
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut pois.mode, PickMode::Reference, "Reference");
+                ui.selectable_value(&mut pois.mode, PickMode::Measure, "Start/End");
+            });
+            ui.label(format!(
+                "Click on the video to place the {}",
+                next_marker(&pois)
+            ));
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut picking.refine, "Snap to corners");
+
+                if ui
+                    .add_enabled(pois.can_undo(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    pois.undo();
+                }
+            });
+            ui.add(egui::Slider::new(&mut picking.zoom, 1.0..=16.0).text("Zoom"));
+
+            let size = images
+                .get(&image.image_handle)
+                .map(|it| it.size_f32())
+                .unwrap_or_default();
+
+            if let Some(hovered) = picking.hovered.filter(|_| size != Vec2::ZERO) {
+                // Region of the frame shown in the loupe, in uv coordinates
+                let extent = Vec2::splat(LOUPE_SIZE / picking.zoom) / size;
+                let center = hovered / size;
+                let uv = egui::Rect::from_center_size(
+                    pos2(center.x, center.y),
+                    vec2(extent.x, extent.y),
+                );
+
+                let response = ui.add(
+                    egui::Image::new(SizedTexture::new(
+                        image.egui_texture,
+                        (LOUPE_SIZE, LOUPE_SIZE),
+                    ))
+                    .uv(uv),
+                );
+
+                let rect = response.rect;
+                let stroke = Stroke::new(1.0, Color32::YELLOW);
+                ui.painter().hline(rect.x_range(), rect.center().y, stroke);
+                ui.painter().vline(rect.center().x, rect.y_range(), stroke);
+
+                ui.label(format!("({:.1}, {:.1})", hovered.x, hovered.y));
+            } else {
+                ui.label("Hover over the video to magnify it");
+            }
+
+            if ui.button("Done").clicked() {
+                cmds.trigger(StopPoiPicking);
+            }
+        });
+
+    if !open {
+        cmds.trigger(StopPoiPicking);
+    }
+}
+
+fn next_marker(pois: &MeasurementPOIs) -> &'static str {
+    const CORNERS: [&str; 4] = [
+        "bottom-left reference corner",
+        "bottom-right reference corner",
+        "top-right reference corner",
+        "top-left reference corner",
+    ];
+
+    match pois.mode {
+        PickMode::Reference => CORNERS
+            .get(pois.reference.len())
+            .copied()
+            .unwrap_or("closest reference corner"),
+        PickMode::Measure if pois.points.len() % 2 == 0 => "start marker",
+        PickMode::Measure => "end marker",
+    }
+}
+
+/// Converts a world space hit on a display into a pixel of the image it shows, displays are unit
+/// rectangles scaled to their size
+fn image_point(hit: Vec3, display: &GlobalTransform, image_size: Vec2) -> Option<Vec2> {
+    let local = display.affine().inverse().transform_point3(hit);
+    let uv = Vec2::new(local.x + 0.5, 0.5 - local.y);
+
+    (uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()).then_some(uv * image_size)
+}
+
+/// Moves a point onto the strongest corner near it
+fn refine_corner(image: &Image, point: Vec2) -> anyhow::Result<Vec2> {
+    let size = image.size_f32();
+    let margin = (REFINE_RADIUS + 1) as f32;
+
+    // The search window has to fit inside the image
+    if point.cmplt(Vec2::splat(margin)).any() || point.cmpgt(size - margin).any() {
+        return Ok(point);
+    }
+
+    let rgba = Mat::from_slice(&image.data).context("Wrap image")?;
+    let rgba = rgba.reshape(4, size.y as i32).context("Reshape image")?;
+
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        &rgba,
+        &mut gray,
+        imgproc::COLOR_RGBA2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )
+    .context("Convert to gray")?;
+
+    let mut corners = Vector::<Point2f>::from_slice(&[Point2f::new(point.x, point.y)]);
+    let criteria = TermCriteria::new(
+        TermCriteria_Type::COUNT as i32 + TermCriteria_Type::EPS as i32,
+        30,
+        0.01,
+    )
+    .context("Create criteria")?;
+    imgproc::corner_sub_pix(
+        &gray,
+        &mut corners,
+        Size::new(REFINE_RADIUS, REFINE_RADIUS),
+        Size::new(-1, -1),
+        criteria,
+    )
+    .context("Refine corner")?;
+
+    let refined = corners.get(0).context("Get refined corner")?;
+    let refined = Vec2::new(refined.x, refined.y);
+
+    // Flat areas have no corner to converge on and can wander off
+    if refined.distance(point) > REFINE_RADIUS as f32 {
+        return Ok(point);
+    }
+
+    Ok(refined)
+}
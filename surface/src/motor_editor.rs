@@ -0,0 +1,430 @@
+//! Edits the robot's thruster layout with a live preview in the robot view and the resulting axis
+//! maximums, the robot saves layouts it is sent to its config
+
+use ahash::HashSet;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{
+        ActuatorChannelType, ActuatorIdentity, Armed, CenterOfMass, GenericMotorId,
+        MovementAxisMaximums, MovementCurrentCap, Robot, ThrusterDefinition,
+    },
+    events::{SetThrusterLayout, ThrusterLayout},
+};
+use egui::{Color32, DragValue};
+use motor_math::{
+    glam::ThrusterGlam,
+    motor_preformance::{self, MotorData},
+    solve::reverse::{self, Axis},
+    Direction, ErasedMotorId, MotorConfig, Thruster,
+};
+use nalgebra::vector;
+
+use crate::{
+    layout::DockableWindow,
+    robot_view::ThrusterPreview,
+    ui::{MotorEditorUi, RobotViewUi},
+};
+
+const MOTOR_DATA: &str = include_str!("../../robot/motor_data.csv");
+
+/// Used for the axis maximums while no robot is connected
+const DEFAULT_CURRENT_CAP: f32 = 20.0;
+
+const AXES: [(Axis, &str); 6] = [
+    (Axis::X, "X (N)"),
+    (Axis::Y, "Y (N)"),
+    (Axis::Z, "Z (N)"),
+    (Axis::XRot, "Pitch (Nm)"),
+    (Axis::YRot, "Roll (Nm)"),
+    (Axis::ZRot, "Yaw (Nm)"),
+];
+
+pub struct MotorEditorPlugin;
+
+impl Plugin for MotorEditorPlugin {
+    fn build(&self, app: &mut App) {
+        let motor_data =
+            motor_preformance::read_motor_data_from_string(MOTOR_DATA).expect("Read motor data");
+
+        app.init_resource::<MotorEditor>()
+            .insert_resource(EditorMotorData(motor_data))
+            .add_systems(
+                Update,
+                (
+                    (check_layout, motor_editor_ui)
+                        .chain()
+                        .run_if(resource_exists::<MotorEditorUi>),
+                    clear_preview.run_if(resource_removed::<MotorEditorUi>),
+                ),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct EditorMotorData(MotorData);
+
+#[derive(Resource, Default)]
+pub struct MotorEditor {
+    pub thrusters: Vec<EditedThruster>,
+
+    /// The layout `errors` and `maximums` were computed for
+    checked: Option<(Vec<ThrusterLayout>, f32, Vec3A)>,
+    errors: Vec<String>,
+    maximums: Vec<(Axis, f32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditedThruster {
+    pub name: String,
+    pub channel: u8,
+    pub dc: bool,
+    /// Meters from the robot's origin
+    pub position: Vec3,
+    /// Degrees counterclockwise from forward
+    pub azimuth: f32,
+    /// Degrees above horizontal
+    pub elevation: f32,
+    pub direction: Direction,
+}
+
+impl EditedThruster {
+    fn new(name: String, channel: u8) -> Self {
+        Self {
+            name,
+            channel,
+            dc: false,
+            position: Vec3::ZERO,
+            azimuth: 0.0,
+            elevation: 0.0,
+            direction: Direction::Clockwise,
+        }
+    }
+
+    fn from_robot(identity: &ActuatorIdentity, thruster: &ThrusterGlam) -> Self {
+        let orientation = thruster.orientation.normalize_or_zero();
+
+        Self {
+            name: identity.name.clone(),
+            channel: identity.index,
+            dc: identity.channel_type == ActuatorChannelType::Dc,
+            position: thruster.position.into(),
+            azimuth: f32::atan2(-orientation.x, orientation.y).to_degrees(),
+            elevation: orientation.z.clamp(-1.0, 1.0).asin().to_degrees(),
+            direction: thruster.direction,
+        }
+    }
+
+    fn channel_type(&self) -> ActuatorChannelType {
+        if self.dc {
+            ActuatorChannelType::Dc
+        } else {
+            ActuatorChannelType::Pwm
+        }
+    }
+
+    fn to_layout(&self) -> ThrusterLayout {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        let orientation = Vec3A::new(
+            -azimuth.sin() * elevation.cos(),
+            azimuth.cos() * elevation.cos(),
+            elevation.sin(),
+        );
+
+        ThrusterLayout {
+            name: self.name.clone(),
+            channel: GenericMotorId::new(self.channel_type(), self.channel),
+            thruster: ThrusterGlam {
+                position: self.position.into(),
+                orientation,
+                direction: self.direction,
+            },
+        }
+    }
+}
+
+impl MotorEditor {
+    pub fn layout(&self) -> Vec<ThrusterLayout> {
+        self.thrusters
+            .iter()
+            .map(EditedThruster::to_layout)
+            .collect()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Mirrors the checks the robot does before accepting a layout
+fn validate(layout: &[ThrusterLayout]) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    if layout.is_empty() {
+        errors.push("Layout has no thrusters".to_owned());
+    }
+
+    let mut names = HashSet::default();
+    let mut channels = HashSet::default();
+    for thruster in layout {
+        if thruster.name.trim().is_empty() {
+            errors.push("Thrusters must have a name".to_owned());
+        } else if !names.insert(&thruster.name) {
+            errors.push(format!("More than one thruster is named {}", thruster.name));
+        }
+
+        if !thruster.channel.exists() {
+            errors.push(format!(
+                "{} uses channel {}, which doesn't exist",
+                thruster.name, thruster.channel
+            ));
+        }
+        if !channels.insert(thruster.channel) {
+            errors.push(format!(
+                "{} uses a channel used by another thruster",
+                thruster.name
+            ));
+        }
+    }
+
+    errors
+}
+
+fn axis_maximums(
+    layout: &[ThrusterLayout],
+    motor_data: &MotorData,
+    current_cap: f32,
+    center_of_mass: Vec3A,
+) -> Vec<(Axis, f32)> {
+    let config = MotorConfig::<ErasedMotorId, motor_math::FloatType>::new_raw(
+        layout
+            .iter()
+            .enumerate()
+            .map(|(idx, it)| (idx as ErasedMotorId, Thruster::from(it.thruster))),
+        vector![
+            center_of_mass.x as _,
+            center_of_mass.y as _,
+            center_of_mass.z as _
+        ],
+    );
+
+    let maximums = reverse::axis_maximums(&config, motor_data, current_cap as _, 0.05);
+
+    AXES.iter()
+        .map(|(axis, _)| {
+            (
+                *axis,
+                maximums.get(axis).copied().unwrap_or_default() as f32,
+            )
+        })
+        .collect()
+}
+
+/// Rechecks the layout and updates the preview when it changes
+fn check_layout(
+    mut cmds: Commands,
+    mut editor: ResMut<MotorEditor>,
+    motor_data: Res<EditorMotorData>,
+    // TODO(low): Support multiple robots
+    robot: Query<(Option<&MovementCurrentCap>, Option<&CenterOfMass>), With<Robot>>,
+) {
+    let (current_cap, center_of_mass) = robot.get_single().unwrap_or_default();
+    let current_cap = current_cap.map(|it| it.0 .0).unwrap_or(DEFAULT_CURRENT_CAP);
+    let center_of_mass = center_of_mass.map(|it| it.0).unwrap_or_default();
+
+    let layout = editor.layout();
+    let key = (layout, current_cap, center_of_mass);
+    if editor.checked.as_ref() == Some(&key) {
+        return;
+    }
+    let (layout, ..) = &key;
+
+    let errors = validate(layout);
+    let maximums = if errors.is_empty() {
+        axis_maximums(layout, &motor_data.0, current_cap, center_of_mass)
+    } else {
+        Vec::new()
+    };
+
+    cmds.insert_resource(ThrusterPreview(
+        layout.iter().map(|it| it.thruster).collect(),
+    ));
+
+    editor.errors = errors;
+    editor.maximums = maximums;
+    editor.checked = Some(key);
+}
+
+fn clear_preview(mut cmds: Commands, mut editor: ResMut<MotorEditor>) {
+    cmds.remove_resource::<ThrusterPreview>();
+    // Restores the preview when the editor is reopened
+    editor.checked = None;
+}
+
+fn motor_editor_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut editor: ResMut<MotorEditor>,
+    // TODO(low): Support multiple robots
+    robot: Query<(Option<&Armed>, Option<&MovementAxisMaximums>), With<Robot>>,
+    thrusters: Query<(&ActuatorIdentity, &ThrusterDefinition)>,
+    robot_view: Option<Res<RobotViewUi>>,
+    mut set_layout: EventWriter<SetThrusterLayout>,
+) {
+    let robot = robot.get_single().ok();
+    let disarmed = robot.is_some_and(|(armed, _)| armed == Some(&Armed::Disarmed));
+    let robot_maximums = robot.and_then(|(_, it)| it);
+
+    let mut open = true;
+
+    egui::Window::new("Motor Editor")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Motor Editor"), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Add Thruster").clicked() {
+                    let name = format!("Thruster {}", editor.thrusters.len());
+                    let channel = (0..ActuatorChannelType::Pwm.channels())
+                        .find(|channel| {
+                            !editor
+                                .thrusters
+                                .iter()
+                                .any(|it| !it.dc && it.channel == *channel)
+                        })
+                        .unwrap_or_default();
+
+                    editor.thrusters.push(EditedThruster::new(name, channel));
+                }
+
+                if ui
+                    .add_enabled(!thrusters.is_empty(), egui::Button::new("Load From Robot"))
+                    .clicked()
+                {
+                    let mut loaded = thrusters
+                        .iter()
+                        .map(|(identity, ThrusterDefinition(id, thruster))| {
+                            (*id, EditedThruster::from_robot(identity, thruster))
+                        })
+                        .collect::<Vec<_>>();
+                    loaded.sort_by_key(|(id, _)| *id);
+
+                    editor.thrusters = loaded.into_iter().map(|(_, it)| it).collect();
+                }
+
+                if robot_view.is_none() && ui.button("Show Preview").clicked() {
+                    cmds.insert_resource(RobotViewUi);
+                }
+            });
+
+            ui.separator();
+
+            let mut remove = None;
+            egui::Grid::new("Thrusters").striped(true).show(ui, |ui| {
+                for label in [
+                    "Name",
+                    "Channel",
+                    "DC",
+                    "X (m)",
+                    "Y (m)",
+                    "Z (m)",
+                    "Azimuth",
+                    "Elevation",
+                    "Direction",
+                    "",
+                ] {
+                    ui.label(label);
+                }
+                ui.end_row();
+
+                for (idx, thruster) in editor.thrusters.iter_mut().enumerate() {
+                    ui.add(egui::TextEdit::singleline(&mut thruster.name).desired_width(100.0));
+
+                    let channels = thruster.channel_type().channels();
+                    ui.add(DragValue::new(&mut thruster.channel).range(0..=channels - 1));
+                    if ui.checkbox(&mut thruster.dc, "").changed() {
+                        let channels = thruster.channel_type().channels();
+                        thruster.channel = thruster.channel.min(channels - 1);
+                    }
+
+                    for axis in thruster.position.as_mut() {
+                        ui.add(DragValue::new(axis).speed(0.005).fixed_decimals(3));
+                    }
+
+                    ui.add(
+                        DragValue::new(&mut thruster.azimuth)
+                            .range(-180.0..=180.0)
+                            .suffix("°"),
+                    );
+                    ui.add(
+                        DragValue::new(&mut thruster.elevation)
+                            .range(-90.0..=90.0)
+                            .suffix("°"),
+                    );
+
+                    egui::ComboBox::from_id_salt(("Direction", idx))
+                        .selected_text(format!("{:?}", thruster.direction))
+                        .show_ui(ui, |ui| {
+                            for direction in [Direction::Clockwise, Direction::CounterClockwise] {
+                                ui.selectable_value(
+                                    &mut thruster.direction,
+                                    direction,
+                                    format!("{direction:?}"),
+                                );
+                            }
+                        });
+
+                    if ui.button("Remove").clicked() {
+                        remove = Some(idx);
+                    }
+                    ui.end_row();
+                }
+            });
+
+            if let Some(idx) = remove {
+                editor.thrusters.remove(idx);
+            }
+
+            ui.separator();
+
+            for error in &editor.errors {
+                ui.colored_label(Color32::RED, error);
+            }
+
+            if !editor.maximums.is_empty() {
+                egui::Grid::new("Axis Maximums")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Axis");
+                        ui.label("Layout");
+                        ui.label("Robot");
+                        ui.end_row();
+
+                        for ((axis, label), (_, maximum)) in AXES.iter().zip(&editor.maximums) {
+                            ui.label(*label);
+                            ui.label(format!("{maximum:.1}"));
+
+                            match robot_maximums.and_then(|it| it.0.get(axis)) {
+                                Some(robot) => ui.label(format!("{:.1}", robot.0)),
+                                None => ui.label("-"),
+                            };
+                            ui.end_row();
+                        }
+                    });
+            }
+
+            ui.separator();
+
+            let can_send = editor.is_valid() && disarmed;
+            let send = ui
+                .add_enabled(can_send, egui::Button::new("Send To Robot"))
+                .on_disabled_hover_text("The layout must be valid and the robot disarmed");
+            if send.clicked() {
+                set_layout.send(SetThrusterLayout {
+                    thrusters: editor.layout(),
+                });
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<MotorEditorUi>();
+    }
+}
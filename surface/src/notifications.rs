@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use common::{
+    error::{self, ErrorEvent},
+    events::{Alarm, AlarmKind},
+};
+use time::OffsetDateTime;
+
+use crate::wall_clock::now;
+
+/// How long a toast stays on screen after it was last reported
+pub const TOAST_DURATION: Duration = Duration::from_secs(6);
+const MAX_TOASTS: usize = 6;
+
+pub struct NotificationPlugin;
+
+impl Plugin for NotificationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Notifications>()
+            .add_systems(Update, (collect_alarms, expire_toasts))
+            .add_systems(Last, collect_errors.after(error::error_channel));
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Notifications {
+    /// Newest last
+    pub toasts: Vec<Toast>,
+    pub alarms: Vec<ActiveAlarm>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub source: &'static str,
+    pub message: String,
+    /// Repeated messages are merged into one toast
+    pub count: u32,
+    pub last_seen: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveAlarm {
+    pub kind: AlarmKind,
+    pub message: String,
+    pub raised: OffsetDateTime,
+    pub count: u32,
+    pub acknowledged: bool,
+}
+
+impl Notifications {
+    pub fn toast(&mut self, source: &'static str, message: String) {
+        let existing = self
+            .toasts
+            .iter_mut()
+            .find(|it| it.source == source && it.message == message);
+
+        if let Some(toast) = existing {
+            toast.count += 1;
+            toast.last_seen = Instant::now();
+            return;
+        }
+
+        self.toasts.push(Toast {
+            source,
+            message,
+            count: 1,
+            last_seen: Instant::now(),
+        });
+
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+    }
+
+    pub fn raise(&mut self, kind: AlarmKind, message: String) {
+        let existing = self
+            .alarms
+            .iter_mut()
+            .find(|it| it.kind == kind && it.message == message);
+
+        match existing {
+            Some(alarm) => {
+                alarm.count += 1;
+
+                if alarm.acknowledged {
+                    alarm.acknowledged = false;
+                    alarm.raised = now();
+                }
+            }
+            None => {
+                self.alarms.push(ActiveAlarm {
+                    kind,
+                    message,
+                    raised: now(),
+                    count: 1,
+                    acknowledged: false,
+                });
+            }
+        }
+    }
+
+    pub fn has_unacknowledged(&self) -> bool {
+        self.alarms.iter().any(|it| !it.acknowledged)
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for alarm in &mut self.alarms {
+            alarm.acknowledged = true;
+        }
+    }
+}
+
+fn collect_alarms(mut alarms: EventReader<Alarm>, mut notifications: ResMut<Notifications>) {
+    for Alarm { kind, message } in alarms.read() {
+        if kind.is_critical() {
+            warn!("Robot alarm: {}: {message}", kind.name());
+            notifications.raise(*kind, message.clone());
+        } else {
+            notifications.toast("Robot", message.clone());
+        }
+    }
+}
+
+fn collect_errors(mut errors: EventReader<ErrorEvent>, mut notifications: ResMut<Notifications>) {
+    for ErrorEvent(error) in errors.read() {
+        notifications.toast("Control Station", format!("{error:#}"));
+    }
+}
+
+fn expire_toasts(mut notifications: ResMut<Notifications>) {
+    let expired = notifications
+        .toasts
+        .iter()
+        .any(|it| it.last_seen.elapsed() > TOAST_DURATION);
+
+    if expired {
+        notifications
+            .toasts
+            .retain(|it| it.last_seen.elapsed() <= TOAST_DURATION);
+    }
+}
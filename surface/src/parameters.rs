@@ -0,0 +1,143 @@
+//! Tuning panel for the parameters of the control station and every connected robot
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{ParamEntry, ParamValue, Parameters, Robot},
+    events::SetParameter,
+    params::{Params, ParamsPlugin},
+};
+use egui::{CollapsingHeader, Ui};
+
+use crate::{layout::DockableWindow, settings::config_path, ui::ParametersUi};
+
+pub const PARAMS_FILE: &str = "params.toml";
+
+pub struct ParametersPlugin;
+
+impl Plugin for ParametersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ParamsPlugin {
+            path: config_path(PARAMS_FILE),
+        })
+        .add_systems(
+            Update,
+            parameters_ui.run_if(resource_exists::<ParametersUi>),
+        );
+    }
+}
+
+/// A slider being dragged, only sent once it is released so peers don't save every step
+#[derive(Default)]
+struct Dragging(Option<(String, ParamValue)>);
+
+fn parameters_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut dragging: Local<Dragging>,
+    params: Res<Params>,
+    robots: Query<(&Name, &Parameters), With<Robot>>,
+    mut set_parameter: EventWriter<SetParameter>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Parameters")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Parameters"), |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let mut sections = vec![("Control Station".to_owned(), params.entries())];
+                sections.extend(
+                    robots
+                        .iter()
+                        .map(|(name, parameters)| (name.to_string(), parameters.0.as_slice())),
+                );
+
+                for (title, entries) in sections {
+                    CollapsingHeader::new(title.as_str())
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            if entries.is_empty() {
+                                ui.label("No parameters");
+                            }
+
+                            let mut groups = entries.iter().map(|it| &it.group).collect::<Vec<_>>();
+                            groups.sort();
+                            groups.dedup();
+
+                            for group in groups {
+                                CollapsingHeader::new(group.as_str())
+                                    .id_salt((&title, group))
+                                    .show(ui, |ui| {
+                                        egui::Grid::new((&title, group, "Grid"))
+                                            .num_columns(3)
+                                            .show(ui, |ui| {
+                                                for entry in
+                                                    entries.iter().filter(|it| &it.group == group)
+                                                {
+                                                    param_row(
+                                                        ui,
+                                                        entry,
+                                                        &mut dragging,
+                                                        &mut set_parameter,
+                                                    );
+                                                    ui.end_row();
+                                                }
+                                            });
+                                    });
+                            }
+                        });
+                }
+            });
+        });
+
+    if !open {
+        cmds.remove_resource::<ParametersUi>();
+    }
+}
+
+fn param_row(
+    ui: &mut Ui,
+    entry: &ParamEntry,
+    dragging: &mut Dragging,
+    set_parameter: &mut EventWriter<SetParameter>,
+) {
+    let name = entry.key.rsplit('.').next().unwrap_or(&entry.key);
+    ui.label(name).on_hover_text(&entry.description);
+
+    let mut value = match &dragging.0 {
+        Some((key, value)) if *key == entry.key => *value,
+        _ => entry.value,
+    };
+
+    let response = match (&mut value, entry.min, entry.max) {
+        (ParamValue::Float(value), ParamValue::Float(min), ParamValue::Float(max)) => {
+            ui.add(egui::Slider::new(value, min..=max))
+        }
+        (ParamValue::Int(value), ParamValue::Int(min), ParamValue::Int(max)) => {
+            ui.add(egui::Slider::new(value, min..=max))
+        }
+        (ParamValue::Bool(value), ..) => ui.checkbox(value, ""),
+        _ => ui.label(format!("{:?}", entry.value)),
+    };
+
+    if response.dragged() {
+        dragging.0 = Some((entry.key.clone(), value));
+    } else if response.changed() || response.drag_stopped() {
+        dragging.0 = None;
+        set_parameter.send(SetParameter {
+            key: entry.key.clone(),
+            value: Some(value),
+        });
+    }
+
+    if ui
+        .add_enabled(!entry.is_default(), egui::Button::new("Reset"))
+        .on_hover_text(format!("Default: {:?}", entry.default))
+        .clicked()
+    {
+        set_parameter.send(SetParameter {
+            key: entry.key.clone(),
+            value: None,
+        });
+    }
+}
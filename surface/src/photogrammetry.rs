@@ -0,0 +1,266 @@
+//! Bundles of frames grabbed from every camera at nearly the same instant, tagged with the
+//! robot's pose for photogrammetry software
+//!
+//! Every bundle is written to the same directory and described by a shared `poses.csv`, so a
+//! whole survey pass can be imported as one set of referenced images.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::{
+    components::{CameraDefinition, DepthMeasurement, Orientation, PositionEstimate, Robot},
+    error::Errors,
+};
+use leafwing_input_manager::action_state::ActionState;
+use time::format_description::well_known::Iso8601;
+
+use crate::{
+    input::{Action, InputMarker},
+    session::{artifact_directory, ActiveSession},
+    snapshot::{self, SnapshotMetadata},
+    video_stream::{FrameDecodedAt, ImageHandle},
+    wall_clock,
+};
+
+pub const PHOTOGRAMMETRY_DIRECTORY: &str = "photogrammetry";
+const POSES_FILE: &str = "poses.csv";
+const POSES_HEADER: &str = "image,time,camera,robot,x,y,z,horizontal_std,depth,yaw,pitch,roll,\
+    qw,qx,qy,qz,frame_age_ms\n";
+
+/// Largest spread between the decode times of the frames in a bundle
+const SYNC_WINDOW: Duration = Duration::from_millis(40);
+/// Saves the freshest frames available once this passes, even if they are not in sync
+const SYNC_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct PhotogrammetryPlugin;
+
+impl Plugin for PhotogrammetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CaptureBundle>()
+            .init_resource::<BundleCapture>()
+            .add_systems(
+                Update,
+                (capture_hotkey, request_bundle, capture_bundle).chain(),
+            );
+    }
+}
+
+/// Grabs a frame from every camera once they have all decoded a frame within `SYNC_WINDOW`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CaptureBundle;
+
+#[derive(Resource, Debug, Default)]
+pub struct BundleCapture {
+    /// When the bundle waiting on fresh frames was requested
+    pending: Option<Instant>,
+}
+
+/// One camera's frame in a bundle
+struct BundleFrame {
+    camera: String,
+    image: Image,
+    age: Duration,
+}
+
+/// The robot's state when a bundle was grabbed
+#[derive(Default)]
+struct BundlePose {
+    robot: Option<String>,
+    position: Option<PositionEstimate>,
+    depth: Option<DepthMeasurement>,
+    orientation: Option<Orientation>,
+}
+
+fn capture_hotkey(
+    inputs: Query<&ActionState<Action>, With<InputMarker>>,
+    mut events: EventWriter<CaptureBundle>,
+) {
+    for action_state in &inputs {
+        if action_state.just_pressed(&Action::CaptureBundle) {
+            events.send(CaptureBundle);
+        }
+    }
+}
+
+fn request_bundle(mut capture: ResMut<BundleCapture>, mut events: EventReader<CaptureBundle>) {
+    // Presses while waiting on frames fold into the pending bundle
+    if events.read().count() > 0 && capture.pending.is_none() {
+        capture.pending = Some(Instant::now());
+    }
+}
+
+fn capture_bundle(
+    mut capture: ResMut<BundleCapture>,
+    cameras: Query<(&Name, &ImageHandle, Option<&FrameDecodedAt>), With<CameraDefinition>>,
+    // TODO(low): Support multiple robots
+    robot: Query<
+        (
+            &Name,
+            Option<&PositionEstimate>,
+            Option<&DepthMeasurement>,
+            Option<&Orientation>,
+        ),
+        With<Robot>,
+    >,
+    images: Res<Assets<Image>>,
+    session: Option<Res<ActiveSession>>,
+    runtime: Res<TokioTasksRuntime>,
+    errors: Res<Errors>,
+) {
+    let Some(requested) = capture.pending else {
+        return;
+    };
+
+    let now = Instant::now();
+    let timed_out = now.duration_since(requested) > SYNC_TIMEOUT;
+
+    let decoded = cameras
+        .iter()
+        .filter_map(|(_, _, decoded)| decoded.map(|it| it.0))
+        .collect::<Vec<_>>();
+    let fresh =
+        decoded.len() == cameras.iter().count() && decoded.iter().all(|it| *it >= requested);
+    let spread = match (decoded.iter().min(), decoded.iter().max()) {
+        (Some(first), Some(last)) => last.duration_since(*first),
+        _ => Duration::ZERO,
+    };
+
+    if !timed_out && !(fresh && spread <= SYNC_WINDOW) {
+        return;
+    }
+    capture.pending = None;
+
+    if timed_out {
+        warn!("Cameras did not sync within {SYNC_TIMEOUT:?}, frames are {spread:?} apart");
+    }
+
+    let frames = cameras
+        .iter()
+        .filter_map(|(name, handle, decoded)| {
+            let image = images.get(&handle.0).filter(|it| !it.data.is_empty())?;
+
+            Some(BundleFrame {
+                camera: name.to_string(),
+                image: image.clone(),
+                age: now.duration_since(decoded?.0),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if frames.is_empty() {
+        let _ = errors.0.send(anyhow::anyhow!("No camera frames to bundle"));
+        return;
+    }
+
+    let pose = robot
+        .get_single()
+        .map(|(name, position, depth, orientation)| BundlePose {
+            robot: Some(name.to_string()),
+            position: position.copied(),
+            depth: depth.copied(),
+            orientation: orientation.copied(),
+        })
+        .unwrap_or_default();
+
+    let directory = artifact_directory(session.as_deref(), PHOTOGRAMMETRY_DIRECTORY);
+    let errors = errors.0.clone();
+
+    // Encoding several pngs would stall the frame
+    runtime.spawn_background_task(async move |_| {
+        let res = tokio::task::spawn_blocking(move || save_bundle(directory, frames, pose)).await;
+
+        match res.context("Join bundle writer") {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) | Err(err) => {
+                let _ = errors.send(err.context("Save photogrammetry bundle"));
+            }
+        }
+    });
+}
+
+fn save_bundle(
+    directory: PathBuf,
+    frames: Vec<BundleFrame>,
+    pose: BundlePose,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(&directory).context("Create photogrammetry directory")?;
+
+    let time = wall_clock::now();
+    let time_name = time.format(&Iso8601::DATE_TIME).context("Format time")?;
+
+    let (yaw, pitch, roll) = pose
+        .orientation
+        .map(|it| it.yaw_pitch_roll())
+        .map(|(yaw, pitch, roll)| (Some(yaw), Some(pitch), Some(roll)))
+        .unwrap_or_default();
+    let quat = pose.orientation.map(|it| it.0);
+    let position = pose.position.map(|it| it.position);
+
+    let opt = |it: Option<f32>| it.map(|it| format!("{it:.4}")).unwrap_or_default();
+
+    let mut rows = String::new();
+    for frame in frames {
+        let camera = frame.camera.replace(|it: char| !it.is_alphanumeric(), "_");
+        let file_name = format!("{time_name}_{camera}.png");
+
+        let metadata = SnapshotMetadata {
+            time,
+            robot: pose.robot.clone(),
+            camera: frame.camera.clone(),
+            depth: pose.depth.map(|it| it.depth),
+            heading: pose.orientation.map(|it| it.heading().0),
+        };
+        let png = snapshot::encode_png(&frame.image, &metadata).context("Encode frame")?;
+        fs::write(directory.join(&file_name), png).context("Write frame")?;
+
+        let _ = writeln!(
+            rows,
+            "{file_name},{time_name},\"{}\",\"{}\",{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            frame.camera.replace('"', "\"\""),
+            pose.robot
+                .as_deref()
+                .unwrap_or_default()
+                .replace('"', "\"\""),
+            opt(position.map(|it| it.x)),
+            opt(position.map(|it| it.y)),
+            opt(position.map(|it| it.z)),
+            opt(pose.position.map(|it| it.horizontal_std.0)),
+            opt(pose.depth.map(|it| it.depth.0)),
+            opt(yaw),
+            opt(pitch),
+            opt(roll),
+            opt(quat.map(|it| it.w)),
+            opt(quat.map(|it| it.x)),
+            opt(quat.map(|it| it.y)),
+            opt(quat.map(|it| it.z)),
+            frame.age.as_millis(),
+        );
+    }
+
+    let path = directory.join(POSES_FILE);
+    let new = !path.exists();
+    let mut poses = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context("Open poses")?;
+
+    if new {
+        poses
+            .write_all(POSES_HEADER.as_bytes())
+            .context("Write poses header")?;
+    }
+    poses.write_all(rows.as_bytes()).context("Write poses")?;
+
+    info!("Saved photogrammetry bundle {time_name} to {directory:?}");
+
+    Ok(())
+}
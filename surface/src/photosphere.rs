@@ -1,3 +1,9 @@
+pub mod stitch;
+pub mod targets;
+
+use std::fs;
+
+use anyhow::Context;
 use bevy::{
     pbr::wireframe::{Wireframe, WireframeColor},
     prelude::*,
@@ -6,24 +12,40 @@ use bevy::{
         render_resource::{
             Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
         },
-        view::RenderLayers,
     },
+    tasks::AsyncComputeTaskPool,
 };
 use bevy_egui::EguiContexts;
-use common::components::{Orientation, Robot, RobotId};
-use egui::TextureId;
+use common::components::{CameraCalibration, Orientation, OrientationTarget, Robot, RobotId};
+use egui::{pos2, vec2, Color32, Rect, Response, Sense, Stroke, TextureId, Ui};
+use stitch::{equirect_sphere, EquirectCanvas, EXPORT_WIDTH, PREVIEW_WIDTH};
+use targets::{
+    direction_to_uv, mark_captured, next_target, plan_targets, PhotoSphereSettings,
+    PhotoSphereTarget,
+};
+use time::format_description::well_known::Iso8601;
 
 use crate::{
-    layer_allocator::next_render_layer, video_display_2d_master::VideoMasterMarker,
+    layer_allocator::next_render_layer,
+    session::{artifact_directory, ActiveSession},
+    video_display_2d_master::VideoMasterMarker,
     video_stream::ImageHandle,
+    wall_clock::now,
 };
 
-// TODO: Consider switching to rendering each image to a plane instead of projecting to a sphere
+pub const PHOTOSPHERE_DIRECTORY: &str = "photospheres";
+
+/// Resolution of the coverage map in the photosphere window
+pub const COVERAGE_COLUMNS: usize = 72;
+pub const COVERAGE_ROWS: usize = 36;
+
 pub struct PhotoSpherePlugin;
 
 impl Plugin for PhotoSpherePlugin {
     fn build(&self, app: &mut App) {
-        app.add_observer(spawn_photo_sphere)
+        app.init_resource::<PhotoSphereSettings>()
+            .add_systems(Update, replan_targets)
+            .add_observer(spawn_photo_sphere)
             .add_observer(take_photo_sphere_image);
     }
 }
@@ -33,9 +55,26 @@ pub struct PhotoSphere {
     pub view_texture: Handle<Image>,
     pub view_texture_egui: TextureId,
 
-    pub images: Vec<(Handle<Image>, TextureId)>,
-    pub materials: Vec<Handle<StandardMaterial>>,
-    pub square_mesh: Handle<Mesh>,
+    pub shots: Vec<PhotoSphereShot>,
+
+    /// Every shot stitched so far at preview resolution
+    pub canvas: EquirectCanvas,
+    /// Texture of the preview sphere, rewritten from `canvas` after each shot
+    pub canvas_texture: Handle<Image>,
+    /// Covered fraction of each cell of the coverage map, see `COVERAGE_COLUMNS`
+    pub coverage: Vec<f32>,
+
+    pub targets: Vec<PhotoSphereTarget>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhotoSphereShot {
+    pub image: Handle<Image>,
+    pub texture: TextureId,
+
+    // Radians
+    pub fov: f32,
+    pub quat: Quat,
 }
 
 #[derive(Component, Debug, Clone)]
@@ -62,15 +101,25 @@ pub struct TakePhotoSphereImage;
 #[derive(Event, Debug, Clone)]
 pub struct RotatePhotoSphere(pub Vec2);
 
+// Trigger on photosphere entity
+#[derive(Event, Debug, Clone)]
+pub struct ExportPhotoSphere;
+
+// Trigger on photosphere entity
+#[derive(Event, Debug, Clone)]
+pub struct GoToNextPhotoSphereTarget;
+
 fn spawn_photo_sphere(
     event: Trigger<SpawnPhotoSphere>,
 
     robot: Query<&RobotId, With<Robot>>,
+    settings: Res<PhotoSphereSettings>,
     mut cmds: Commands,
     mut images: ResMut<Assets<Image>>,
     mut egui_context: EguiContexts,
 
     mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
     let Ok(robot_id) = robot.get(event.entity()) else {
         error!("Tried to setup photosphere on non robot entity");
@@ -109,6 +158,9 @@ fn spawn_photo_sphere(
     let view_image_handle = images.add(view_image);
     let view_image_texture = egui_context.add_image(view_image_handle.clone_weak());
 
+    let canvas = EquirectCanvas::new(PREVIEW_WIDTH);
+    let canvas_texture = images.add(canvas.to_image());
+
     cmds.spawn((
         Name::new("Photosphere"),
         Transform::default(),
@@ -116,15 +168,19 @@ fn spawn_photo_sphere(
         PhotoSphere {
             view_texture: view_image_handle.clone(),
             view_texture_egui: view_image_texture,
-            materials: vec![],
-            images: vec![],
-            square_mesh: meshes.add(Plane3d::new(Vec3::NEG_Y, Vec2::splat(1.0))),
+            shots: vec![],
+            canvas,
+            canvas_texture: canvas_texture.clone(),
+            coverage: vec![0.0; COVERAGE_COLUMNS * COVERAGE_ROWS],
+            targets: plan_targets(&settings),
         },
         layer.clone(),
         *robot_id,
     ))
     .observe(update_photo_sphere)
     .observe(rotate_camera)
+    .observe(export_photo_sphere)
+    .observe(go_to_next_target)
     .with_children(|cmds| {
         cmds.spawn((
             Camera3d::default(),
@@ -143,6 +199,18 @@ fn spawn_photo_sphere(
             PhotoSphereCameraMarker,
         ));
 
+        // Sits just outside the wireframe so the grid stays visible over the stitched images
+        cmds.spawn((
+            Mesh3d(meshes.add(equirect_sphere(5.5, 64, 32))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color_texture: Some(canvas_texture),
+                unlit: true,
+                cull_mode: None,
+                ..default()
+            })),
+            layer.clone(),
+        ));
+
         cmds.spawn((
             Mesh3d(meshes.add(Sphere::new(-5.0).mesh().uv(32, 18))),
             Wireframe,
@@ -157,46 +225,45 @@ fn spawn_photo_sphere(
 fn update_photo_sphere(
     event: Trigger<UpdatePhotoSphere>,
     mut cmds: Commands,
-    mut query: Query<(Entity, &mut PhotoSphere, &Children, &RenderLayers)>,
+    mut query: Query<(&mut PhotoSphere, &Children)>,
     cameras: Query<Entity, With<PhotoSphereCameraMarker>>,
+    settings: Res<PhotoSphereSettings>,
 
     mut images: ResMut<Assets<Image>>,
     mut egui_context: EguiContexts,
-
-    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let Ok((entity, mut photosphere, children, layer)) = query.get_mut(event.entity()) else {
+    let Ok((mut photosphere, children)) = query.get_mut(event.entity()) else {
         return;
     };
 
     let update = event.event().clone();
-    let mut size = update.image.size_f32().normalize();
-    // let mut size = update.image.size_f32();
-    // size /= size.y;
 
-    let radius = 1.0;
-    size *= (update.fov / 2.0).tan() * radius;
+    // Only the new image needs to be projected, the canvas keeps the earlier ones blended in
+    if let Err(err) = photosphere
+        .canvas
+        .add(&update.image, update.fov, update.quat)
+    {
+        error!("Stitch photosphere image: {err:?}");
+    }
+    if let Some(canvas_texture) = images.get_mut(&photosphere.canvas_texture) {
+        canvas_texture.data = photosphere.canvas.to_rgba();
+    }
+    photosphere.coverage = photosphere
+        .canvas
+        .coverage_grid(COVERAGE_COLUMNS, COVERAGE_ROWS);
 
     let image_handle = images.add(update.image);
     let texture = egui_context.add_image(image_handle.clone_weak());
 
-    cmds.entity(entity).with_child((
-        Mesh3d(photosphere.square_mesh.clone()),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color_texture: Some(image_handle.clone()),
-            unlit: true,
-            ..default()
-        })),
-        Transform {
-            translation: update.quat * Vec3::Y * radius,
-            rotation: update.quat,
-            // scale: size.extend(1.0),
-            scale: Vec3::new(size.x, 1.0, size.y),
-        },
-        layer.clone(),
-    ));
+    photosphere.shots.push(PhotoSphereShot {
+        image: image_handle,
+        texture,
+        fov: update.fov,
+        quat: update.quat,
+    });
 
-    photosphere.images.push((image_handle, texture));
+    let photosphere = &mut *photosphere;
+    mark_captured(&mut photosphere.targets, &photosphere.shots, &settings);
 
     for child in children {
         if let Ok(camera) = cameras.get(*child) {
@@ -206,6 +273,44 @@ fn update_photo_sphere(
     }
 }
 
+/// Keeps the targets in sync with the settings edited in the photosphere window
+fn replan_targets(settings: Res<PhotoSphereSettings>, mut photospheres: Query<&mut PhotoSphere>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    for mut photosphere in &mut photospheres {
+        let photosphere = &mut *photosphere;
+
+        photosphere.targets = plan_targets(&settings);
+        mark_captured(&mut photosphere.targets, &photosphere.shots, &settings);
+    }
+}
+
+/// Points the robot at the closest target that still needs an image
+fn go_to_next_target(
+    event: Trigger<GoToNextPhotoSphereTarget>,
+    mut cmds: Commands,
+    photospheres: Query<(&PhotoSphere, &RobotId)>,
+    robots: Query<(Entity, &Orientation, &RobotId), With<Robot>>,
+) {
+    let Ok((photosphere, robot_id)) = photospheres.get(event.entity()) else {
+        error!("Get photosphere for next target");
+        return;
+    };
+
+    let Some((robot, orientation, _)) = robots.iter().find(|(.., other)| *other == robot_id) else {
+        warn!("No ROV attached");
+        return;
+    };
+
+    if let Some(target) = next_target(&photosphere.targets, orientation.0) {
+        cmds.entity(robot).insert(OrientationTarget(target));
+    } else {
+        info!("Every reachable photosphere target has been captured");
+    }
+}
+
 fn rotate_camera(
     event: Trigger<RotatePhotoSphere>,
     photosphere: Query<&Children, With<PhotoSphere>>,
@@ -237,13 +342,66 @@ fn rotate_camera(
     }
 }
 
+/// Restitches every shot at full resolution on the compute pool and writes it to
+/// `PHOTOSPHERE_DIRECTORY` as an equirectangular jpeg
+fn export_photo_sphere(
+    event: Trigger<ExportPhotoSphere>,
+    photospheres: Query<&PhotoSphere>,
+    images: Res<Assets<Image>>,
+    session: Option<Res<ActiveSession>>,
+) {
+    let Ok(photosphere) = photospheres.get(event.entity()) else {
+        error!("Export non photosphere entity");
+        return;
+    };
+
+    let shots = photosphere
+        .shots
+        .iter()
+        .filter_map(|shot| Some((images.get(&shot.image)?.clone(), shot.fov, shot.quat)))
+        .collect::<Vec<_>>();
+
+    if shots.is_empty() {
+        warn!("Photosphere has no images to export");
+        return;
+    }
+
+    let directory = artifact_directory(session.as_deref(), PHOTOSPHERE_DIRECTORY);
+
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            let res: anyhow::Result<()> = try {
+                let mut canvas = EquirectCanvas::new(EXPORT_WIDTH);
+                for (image, fov, quat) in &shots {
+                    canvas.add(image, *fov, *quat).context("Stitch image")?;
+                }
+
+                let jpeg = canvas.to_jpeg(shots.len()).context("Encode photosphere")?;
+
+                fs::create_dir_all(&directory).context("Create photosphere directory")?;
+
+                let time = now().format(&Iso8601::DATE_TIME).context("Format time")?;
+                let path = directory.join(format!("photosphere_{time}.jpg"));
+                fs::write(&path, jpeg).context("Write photosphere")?;
+
+                info!("Exported photosphere to {path:?}");
+            };
+
+            if let Err(err) = res {
+                error!("Export photosphere: {err:?}");
+            }
+        })
+        .detach();
+}
+
 fn take_photo_sphere_image(
     event: Trigger<TakePhotoSphereImage>,
     mut cmds: Commands,
     robot: Query<(&Orientation, &RobotId), With<Robot>>,
-    master_camera: Query<&ImageHandle, With<VideoMasterMarker>>,
+    master_camera: Query<(&ImageHandle, Option<&CameraCalibration>), With<VideoMasterMarker>>,
     photo_spheres: Query<(Entity, &RobotId), With<PhotoSphere>>,
     images: Res<Assets<Image>>,
+    settings: Res<PhotoSphereSettings>,
 ) {
     let Ok((orientation, robot_id)) = robot.get(event.entity()) else {
         error!("Get robot orientation for image");
@@ -257,7 +415,7 @@ fn take_photo_sphere_image(
             continue;
         }
 
-        let Ok(image_handle) = master_camera.get_single() else {
+        let Ok((image_handle, calibration)) = master_camera.get_single() else {
             error!("Get image from master camera");
             return;
         };
@@ -267,9 +425,18 @@ fn take_photo_sphere_image(
             return;
         };
 
+        // The calibration knows the real focal length, otherwise trust the configured fov
+        let fov = calibration
+            .map(|calibration| {
+                let focal =
+                    (calibration.camera_matrix.x_axis.x + calibration.camera_matrix.y_axis.y) / 2.0;
+                2.0 * (image.size_f32().length() / 2.0 / focal).atan()
+            })
+            .unwrap_or(settings.fov.to_radians());
+
         cmds.entity(photosphere).trigger(UpdatePhotoSphere {
             image: image.clone(),
-            fov: 100.0f32.to_radians(),
+            fov,
             quat: /*Quat::from_rotation_x(90f32.to_radians()) **/ orientation.0,
         });
 
@@ -282,3 +449,79 @@ fn take_photo_sphere_image(
             .trigger(TakePhotoSphereImage);
     }
 }
+
+/// Equirectangular map of which directions have been captured, with the planned targets and the
+/// direction the robot is currently facing drawn over it
+pub fn coverage_map(ui: &mut Ui, photosphere: &PhotoSphere, orientation: Option<Quat>) -> Response {
+    let width = ui.available_width();
+    let (response, painter) = ui.allocate_painter(vec2(width, width / 2.0), Sense::hover());
+    let rect = response.rect;
+
+    let cell = vec2(
+        rect.width() / COVERAGE_COLUMNS as f32,
+        rect.height() / COVERAGE_ROWS as f32,
+    );
+    let to_screen = |uv: Vec2| rect.min + vec2(uv.x * rect.width(), uv.y * rect.height());
+
+    painter.rect_filled(rect, 0.0, Color32::from_gray(30));
+
+    for (idx, covered) in photosphere.coverage.iter().enumerate() {
+        if *covered <= 0.0 {
+            continue;
+        }
+
+        let (x, y) = (idx % COVERAGE_COLUMNS, idx / COVERAGE_COLUMNS);
+        let min = rect.min + vec2(x as f32 * cell.x, y as f32 * cell.y);
+        let alpha = (60.0 + 160.0 * covered) as u8;
+
+        painter.rect_filled(
+            Rect::from_min_size(min, cell),
+            0.0,
+            Color32::from_rgba_unmultiplied(40, 160, 60, alpha),
+        );
+    }
+
+    // Horizon
+    painter.line_segment(
+        [
+            pos2(rect.left(), rect.center().y),
+            pos2(rect.right(), rect.center().y),
+        ],
+        Stroke::new(1.0, Color32::from_gray(90)),
+    );
+
+    let radius = (cell.x * 1.5).max(3.0);
+    for target in &photosphere.targets {
+        let center = to_screen(direction_to_uv(target.quat * Vec3::Y));
+
+        if !target.reachable {
+            painter.circle_stroke(center, radius / 2.0, Stroke::new(1.0, Color32::DARK_RED));
+        } else if target.captured {
+            painter.circle_filled(center, radius / 2.0, Color32::LIGHT_GREEN);
+        } else {
+            painter.circle_stroke(center, radius, Stroke::new(1.5, Color32::YELLOW));
+        }
+    }
+
+    if let Some(orientation) = orientation {
+        let center = to_screen(direction_to_uv(orientation * Vec3::Y));
+        let stroke = Stroke::new(1.5, Color32::WHITE);
+
+        painter.line_segment(
+            [
+                center - vec2(radius * 2.0, 0.0),
+                center + vec2(radius * 2.0, 0.0),
+            ],
+            stroke,
+        );
+        painter.line_segment(
+            [
+                center - vec2(0.0, radius * 2.0),
+                center + vec2(0.0, radius * 2.0),
+            ],
+            stroke,
+        );
+    }
+
+    response
+}
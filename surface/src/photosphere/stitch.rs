@@ -0,0 +1,320 @@
+//! Projects photosphere images onto an equirectangular canvas
+//!
+//! The canvas uses the usual 360 photo layout, the center column looks along +Y (robot forward),
+//! yaw increases to the right and the top row is straight up (+Z)
+
+use std::{
+    f32::consts::{FRAC_PI_2, PI, TAU},
+    fmt::{self, Debug},
+};
+
+use anyhow::{bail, Context};
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+use opencv::{core::Vector, imgcodecs, prelude::*};
+
+/// Width of the canvas shown in the photosphere window, updated as each image is added
+pub const PREVIEW_WIDTH: usize = 1024;
+/// Width of exported panoramas, these are only stitched once so they can be much larger
+pub const EXPORT_WIDTH: usize = 4096;
+
+/// Running weighted average of every image projected onto the sphere
+#[derive(Clone)]
+pub struct EquirectCanvas {
+    width: usize,
+    height: usize,
+
+    color: Vec<Vec3>,
+    weight: Vec<f32>,
+}
+
+impl Debug for EquirectCanvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EquirectCanvas")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EquirectCanvas {
+    /// Equirectangular images are always twice as wide as they are tall
+    pub fn new(width: usize) -> Self {
+        let height = width / 2;
+
+        Self {
+            width,
+            height,
+            color: vec![Vec3::ZERO; width * height],
+            weight: vec![0.0; width * height],
+        }
+    }
+
+    /// Blends an rgba8 image taken with the camera facing `quat * Y` into the canvas
+    ///
+    /// `fov` is the diagonal field of view in radians, pixels are weighted by their distance from
+    /// the edges of the image so overlapping images fade into each other instead of leaving seams
+    pub fn add(&mut self, image: &Image, fov: f32, quat: Quat) -> anyhow::Result<()> {
+        let size = image.size();
+        let (width, height) = (size.x as usize, size.y as usize);
+
+        if width == 0 || height == 0 || image.data.len() != width * height * 4 {
+            bail!("Expected a non empty rgba8 image, got {size}");
+        }
+
+        let half = Vec2::new(width as f32, height as f32) / 2.0;
+        let focal = half.length() / (fov / 2.0).tan();
+        let inverse = quat.inverse();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let camera = inverse * self.direction(x, y);
+
+                // Behind the camera
+                if camera.y <= 1e-3 {
+                    continue;
+                }
+
+                let pixel = Vec2::new(
+                    half.x + focal * camera.x / camera.y,
+                    half.y - focal * camera.z / camera.y,
+                );
+                let normalized = (pixel - half) / half;
+                if normalized.x.abs() >= 1.0 || normalized.y.abs() >= 1.0 {
+                    continue;
+                }
+
+                let feather = (1.0 - normalized.x.abs()) * (1.0 - normalized.y.abs());
+                let color = sample_bilinear(image, width, height, pixel);
+
+                let idx = y * self.width + x;
+                self.color[idx] += color * feather;
+                self.weight[idx] += feather;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Direction the center of a canvas pixel looks along
+    fn direction(&self, x: usize, y: usize) -> Vec3 {
+        let u = (x as f32 + 0.5) / self.width as f32;
+        let v = (y as f32 + 0.5) / self.height as f32;
+
+        equirect_direction(u, v)
+    }
+
+    /// Fraction of the canvas covered by at least one image
+    pub fn coverage(&self) -> f32 {
+        let covered = self.weight.iter().filter(|it| **it > 0.0).count();
+        covered as f32 / self.weight.len() as f32
+    }
+
+    /// Covered fraction of each cell of a `columns` by `rows` grid over the canvas, row major
+    pub fn coverage_grid(&self, columns: usize, rows: usize) -> Vec<f32> {
+        let mut covered = vec![0usize; columns * rows];
+        let mut total = vec![0usize; columns * rows];
+
+        for (idx, weight) in self.weight.iter().enumerate() {
+            let (x, y) = (idx % self.width, idx / self.width);
+            let cell = (y * rows / self.height) * columns + x * columns / self.width;
+
+            total[cell] += 1;
+            if *weight > 0.0 {
+                covered[cell] += 1;
+            }
+        }
+
+        covered
+            .iter()
+            .zip(&total)
+            .map(|(covered, total)| *covered as f32 / (*total).max(1) as f32)
+            .collect()
+    }
+
+    /// Blended colors as rgba8, areas no image covers are left black
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.color.len() * 4);
+
+        for (color, weight) in self.color.iter().zip(&self.weight) {
+            let color = if *weight > 0.0 {
+                *color / *weight
+            } else {
+                Vec3::ZERO
+            };
+            let color = (color * 255.0)
+                .round()
+                .clamp(Vec3::ZERO, Vec3::splat(255.0));
+
+            data.extend([color.x as u8, color.y as u8, color.z as u8, 255]);
+        }
+
+        data
+    }
+
+    pub fn to_image(&self) -> Image {
+        Image::new(
+            Extent3d {
+                width: self.width as u32,
+                height: self.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.to_rgba(),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        )
+    }
+
+    /// Encodes the canvas as a jpeg tagged with the photo sphere XMP metadata viewers look for
+    pub fn to_jpeg(&self, source_images: usize) -> anyhow::Result<Vec<u8>> {
+        let rgba = self.to_rgba();
+        let bgr = rgba
+            .chunks_exact(4)
+            .flat_map(|it| [it[2], it[1], it[0]])
+            .collect::<Vec<_>>();
+
+        let mat = Mat::from_slice(&bgr).context("Wrap canvas")?;
+        let mat = mat
+            .reshape(3, self.height as i32)
+            .context("Reshape canvas")?;
+
+        let mut jpeg = Vector::<u8>::new();
+        let params = Vector::from_slice(&[imgcodecs::IMWRITE_JPEG_QUALITY, 95]);
+        imgcodecs::imencode(".jpg", &mat, &mut jpeg, &params).context("Encode jpeg")?;
+
+        let xmp = gpano_xmp(self.width, self.height, source_images);
+        insert_xmp(jpeg.to_vec(), &xmp)
+    }
+}
+
+/// Inverse of the canvas layout, `u` and `v` are in 0..1
+fn equirect_direction(u: f32, v: f32) -> Vec3 {
+    let yaw = (u - 0.5) * TAU;
+    let pitch = FRAC_PI_2 - v * PI;
+
+    Vec3::new(
+        yaw.sin() * pitch.cos(),
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+    )
+}
+
+fn sample_bilinear(image: &Image, width: usize, height: usize, pixel: Vec2) -> Vec3 {
+    let pixel = (pixel - 0.5).clamp(Vec2::ZERO, Vec2::new(width as f32, height as f32) - 1.0);
+    let (x0, y0) = (pixel.x as usize, pixel.y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (pixel.x - x0 as f32, pixel.y - y0 as f32);
+
+    let get = |x: usize, y: usize| {
+        let idx = (y * width + x) * 4;
+        let rgb = &image.data[idx..idx + 3];
+
+        Vec3::new(rgb[0] as f32, rgb[1] as f32, rgb[2] as f32) / 255.0
+    };
+
+    let top = get(x0, y0).lerp(get(x1, y0), tx);
+    let bottom = get(x0, y1).lerp(get(x1, y1), tx);
+
+    top.lerp(bottom, ty)
+}
+
+/// A sphere whose uvs follow the canvas layout, seen from the inside
+pub fn equirect_sphere(radius: f32, sectors: u32, stacks: u32) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+
+    for stack in 0..=stacks {
+        for sector in 0..=sectors {
+            let uv = Vec2::new(sector as f32 / sectors as f32, stack as f32 / stacks as f32);
+            let direction = equirect_direction(uv.x, uv.y);
+
+            positions.push(direction * radius);
+            normals.push(-direction);
+            uvs.push(uv);
+        }
+    }
+
+    let mut indices = Vec::new();
+    for stack in 0..stacks {
+        for sector in 0..sectors {
+            let top_left = stack * (sectors + 1) + sector;
+            let bottom_left = top_left + sectors + 1;
+
+            indices.extend([top_left, bottom_left, top_left + 1]);
+            indices.extend([top_left + 1, bottom_left, bottom_left + 1]);
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+/// Google's photo sphere metadata, without it most viewers show the export as a flat image
+fn gpano_xmp(width: usize, height: usize, source_images: usize) -> String {
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about="" xmlns:GPano="http://ns.google.com/photos/1.0/panorama/">
+<GPano:ProjectionType>equirectangular</GPano:ProjectionType>
+<GPano:UsePanoramaViewer>True</GPano:UsePanoramaViewer>
+<GPano:StitchingSoftware>robocode surface</GPano:StitchingSoftware>
+<GPano:SourcePhotosCount>{source_images}</GPano:SourcePhotosCount>
+<GPano:CroppedAreaImageWidthPixels>{width}</GPano:CroppedAreaImageWidthPixels>
+<GPano:CroppedAreaImageHeightPixels>{height}</GPano:CroppedAreaImageHeightPixels>
+<GPano:FullPanoWidthPixels>{width}</GPano:FullPanoWidthPixels>
+<GPano:FullPanoHeightPixels>{height}</GPano:FullPanoHeightPixels>
+<GPano:CroppedAreaLeftPixels>0</GPano:CroppedAreaLeftPixels>
+<GPano:CroppedAreaTopPixels>0</GPano:CroppedAreaTopPixels>
+</rdf:Description>
+</rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#
+    )
+}
+
+/// Adds an APP1 XMP segment after the SOI marker and the JFIF APP0 segment if there is one
+fn insert_xmp(jpeg: Vec<u8>, xmp: &str) -> anyhow::Result<Vec<u8>> {
+    const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+    if jpeg.get(0..2) != Some(&[0xFF, 0xD8]) {
+        bail!("Not a jpeg");
+    }
+
+    let mut insert_at = 2;
+    if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let length = jpeg.get(4..6).context("Truncated APP0 segment")?;
+        insert_at += 2 + u16::from_be_bytes([length[0], length[1]]) as usize;
+    }
+    if insert_at > jpeg.len() {
+        bail!("Truncated APP0 segment");
+    }
+
+    // The length includes itself but not the marker
+    let length = 2 + XMP_NAMESPACE.len() + xmp.len();
+    let length = u16::try_from(length).context("XMP packet too large")?;
+
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend(length.to_be_bytes());
+    segment.extend(XMP_NAMESPACE);
+    segment.extend(xmp.as_bytes());
+
+    let mut out = jpeg;
+    out.splice(insert_at..insert_at, segment);
+
+    Ok(out)
+}
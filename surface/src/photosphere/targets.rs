@@ -0,0 +1,143 @@
+//! Plans the attitudes a photosphere should be captured from
+//!
+//! Targets are laid out in rings of constant pitch sized from the camera's field of view so that
+//! neighbouring images overlap, each one is built from a yaw and a pitch with no roll so the
+//! stabilization never has to pass through gimbal lock to reach it
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::prelude::*;
+
+use crate::photosphere::PhotoSphereShot;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PhotoSphereSettings {
+    /// Diagonal field of view of the camera in degrees, used when it has no calibration
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    /// Fraction of each image that should also be in its neighbours
+    pub overlap: f32,
+    /// Steepest pitch in degrees the robot can hold steady, targets past it are skipped
+    pub max_pitch: f32,
+}
+
+impl Default for PhotoSphereSettings {
+    fn default() -> Self {
+        Self {
+            fov: 100.0,
+            aspect_ratio: 16.0 / 9.0,
+            overlap: 0.3,
+            max_pitch: 60.0,
+        }
+    }
+}
+
+impl PhotoSphereSettings {
+    /// Horizontal and vertical field of view in radians
+    pub fn fov_axes(&self) -> Vec2 {
+        let half_diagonal = (self.fov.to_radians() / 2.0).tan();
+        let shape = Vec2::new(self.aspect_ratio, 1.0).normalize();
+
+        Vec2::new(
+            2.0 * (half_diagonal * shape.x).atan(),
+            2.0 * (half_diagonal * shape.y).atan(),
+        )
+    }
+
+    /// Angle between neighbouring targets in radians
+    pub fn step(&self) -> Vec2 {
+        self.fov_axes() * (1.0 - self.overlap.clamp(0.0, 0.9))
+    }
+
+    /// Whether the robot can hold `quat` as an `OrientationTarget`, it has to stay upright and
+    /// within `max_pitch`
+    pub fn is_reachable(&self, quat: Quat) -> bool {
+        let forward = quat * Vec3::Y;
+        let up = quat * Vec3::Z;
+        let pitch = forward.z.clamp(-1.0, 1.0).asin();
+
+        up.z > 0.0 && pitch.abs() <= self.max_pitch.to_radians() + 1e-3
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PhotoSphereTarget {
+    pub quat: Quat,
+    pub reachable: bool,
+    pub captured: bool,
+}
+
+/// Yaw is positive to the right to match the equirectangular canvas
+pub fn attitude(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_rotation_z(-yaw) * Quat::from_rotation_x(pitch)
+}
+
+pub fn plan_targets(settings: &PhotoSphereSettings) -> Vec<PhotoSphereTarget> {
+    let fov = settings.fov_axes();
+    let step = settings.step();
+
+    // The outer rings are placed so the edges of their images reach the poles
+    let span = (FRAC_PI_2 - fov.y / 2.0).max(0.0);
+    let rings = (2.0 * span / step.y).ceil() as usize + 1;
+
+    let mut targets = Vec::new();
+    for ring in 0..rings {
+        let pitch = if rings == 1 {
+            0.0
+        } else {
+            -span + 2.0 * span * ring as f32 / (rings - 1) as f32
+        };
+
+        // The ring is widest at the edge closest to the horizon
+        let widest = (pitch.abs() - fov.y / 2.0).max(0.0);
+        let count = (TAU * widest.cos() / step.x).ceil().max(1.0) as usize;
+
+        for idx in 0..count {
+            let yaw = TAU * idx as f32 / count as f32;
+            let quat = attitude(yaw, pitch);
+
+            targets.push(PhotoSphereTarget {
+                quat,
+                reachable: settings.is_reachable(quat),
+                captured: false,
+            });
+        }
+    }
+
+    targets
+}
+
+/// Marks targets that a shot was taken close enough to
+pub fn mark_captured(
+    targets: &mut [PhotoSphereTarget],
+    shots: &[PhotoSphereShot],
+    settings: &PhotoSphereSettings,
+) {
+    let tolerance = settings.step().min_element() / 2.0;
+
+    for target in targets {
+        let forward = target.quat * Vec3::Y;
+
+        target.captured = shots
+            .iter()
+            .any(|shot| forward.angle_between(shot.quat * Vec3::Y) <= tolerance);
+    }
+}
+
+/// The closest reachable target that has not been captured yet
+pub fn next_target(targets: &[PhotoSphereTarget], orientation: Quat) -> Option<Quat> {
+    targets
+        .iter()
+        .filter(|it| it.reachable && !it.captured)
+        .map(|it| it.quat)
+        .min_by(|a, b| f32::total_cmp(&a.angle_between(orientation), &b.angle_between(orientation)))
+}
+
+/// Where a direction lands on the equirectangular canvas, both axes are in 0..1
+pub fn direction_to_uv(direction: Vec3) -> Vec2 {
+    let direction = direction.normalize();
+    let yaw = direction.x.atan2(direction.y);
+    let pitch = direction.z.clamp(-1.0, 1.0).asin();
+
+    Vec2::new(yaw / TAU + 0.5, 0.5 - pitch / PI)
+}
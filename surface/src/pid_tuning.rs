@@ -0,0 +1,671 @@
+//! Auto tuning for the robot's stabilization pid controllers, driven from the Pid Helper
+//!
+//! A relay test switches the helper's movement contribution on the sign of the controller's error
+//! to find the ultimate gain and period of the loop. A step test holds a constant contribution and
+//! fits a first order plus dead time model to the response. The controller's gains are zeroed for
+//! the length of a test and restored after it.
+
+use std::{f32::consts::PI, mem, time::Duration};
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{MovementContribution, PidConfig, PidResult, RobotId},
+    events::SetPidConfig,
+};
+use egui::{Color32, DragValue, Id};
+use egui_plot::{Line, Plot};
+use motor_math::glam::MovementGlam;
+
+use crate::{
+    settings::{Palette, SurfaceSettings},
+    ui::{PidAxis, PidHelper},
+};
+
+/// Upward zero crossings of the error before a relay test ends early
+const RELAY_CYCLES: usize = 6;
+
+pub struct PidTuningPlugin;
+
+impl Plugin for PidTuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (run_auto_tune, auto_tune_ui).chain())
+            .add_observer(restore_on_remove);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuneMethod {
+    Relay,
+    Step,
+}
+
+/// Added to a Pid Helper to open its auto tuner
+#[derive(Component)]
+pub struct PidAutoTune {
+    pub axis: PidAxis,
+    pub method: TuneMethod,
+    /// Relay output or step size, in newtons or newton meters
+    pub amplitude: f32,
+    /// Band of error the relay holds its output through, rejects sensor noise
+    pub hysteresis: f32,
+    pub duration: Duration,
+
+    state: TuneState,
+}
+
+impl Default for PidAutoTune {
+    fn default() -> Self {
+        Self {
+            axis: PidAxis::Yaw,
+            method: TuneMethod::Relay,
+            amplitude: 5.0,
+            hysteresis: 0.5,
+            duration: Duration::from_secs(30),
+            state: TuneState::Idle,
+        }
+    }
+}
+
+enum TuneState {
+    Idle,
+    Running(TuneRun),
+    Finished {
+        original: PidConfig,
+        /// Seconds since the start and the error
+        samples: Vec<[f64; 2]>,
+        result: Result<ProcessModel, String>,
+        /// Index of the proposal waiting for confirmation
+        confirm: Option<usize>,
+    },
+}
+
+struct TuneRun {
+    controller: Entity,
+    original: PidConfig,
+    started: Duration,
+    samples: Vec<[f64; 2]>,
+    /// Sign of the relay's output
+    output: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessModel {
+    /// From a relay test
+    Ultimate { gain: f32, period: f32 },
+    /// First order plus dead time, from a step test
+    Fopdt {
+        gain: f32,
+        time_constant: f32,
+        dead_time: f32,
+    },
+}
+
+impl ProcessModel {
+    /// Named `(kp, ki, kd)` gains for the model
+    pub fn proposals(&self) -> Vec<(&'static str, [f32; 3])> {
+        match *self {
+            ProcessModel::Ultimate { gain, period } => vec![
+                (
+                    "Ziegler-Nichols",
+                    [0.6 * gain, 1.2 * gain / period, 0.075 * gain * period],
+                ),
+                (
+                    "Some Overshoot",
+                    [0.33 * gain, 0.66 * gain / period, 0.11 * gain * period],
+                ),
+                (
+                    "No Overshoot",
+                    [0.2 * gain, 0.4 * gain / period, 0.066 * gain * period],
+                ),
+            ],
+            ProcessModel::Fopdt {
+                gain,
+                time_constant: tau,
+                dead_time: theta,
+            } => {
+                let imc = |lambda: f32| {
+                    let kc = (2.0 * tau + theta) / (gain * (2.0 * lambda + theta));
+                    let ti = tau + theta / 2.0;
+                    let td = tau * theta / (2.0 * tau + theta);
+
+                    [kc, kc / ti, kc * td]
+                };
+
+                let mut proposals = vec![
+                    ("IMC", imc(tau)),
+                    ("IMC Aggressive", imc((0.25 * tau).max(0.8 * theta))),
+                ];
+
+                if theta > 0.0 {
+                    let kc = 1.2 * tau / (gain * theta);
+                    proposals.push((
+                        "Ziegler-Nichols",
+                        [kc, kc / (2.0 * theta), kc * 0.5 * theta],
+                    ));
+                }
+
+                proposals
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ProcessModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessModel::Ultimate { gain, period } => {
+                write!(f, "Ultimate gain {gain:.3}, period {period:.2} s")
+            }
+            ProcessModel::Fopdt {
+                gain,
+                time_constant,
+                dead_time,
+            } => write!(
+                f,
+                "Gain {gain:.3}, time constant {time_constant:.2} s, dead time {dead_time:.2} s"
+            ),
+        }
+    }
+}
+
+/// Interpolated times the error rises through zero
+fn upward_crossings(samples: &[[f64; 2]]) -> Vec<f64> {
+    samples
+        .windows(2)
+        .filter(|it| it[0][1] < 0.0 && it[1][1] >= 0.0)
+        .map(|it| {
+            let [[t0, e0], [t1, e1]] = [it[0], it[1]];
+            t0 + (t1 - t0) * -e0 / (e1 - e0)
+        })
+        .collect()
+}
+
+/// Describing function estimate of the ultimate gain and period from a relay test
+fn analyze_relay(
+    samples: &[[f64; 2]],
+    amplitude: f32,
+    hysteresis: f32,
+) -> Result<ProcessModel, String> {
+    // The first cycle is the transient from wherever the test started
+    let crossings = upward_crossings(samples);
+    let [_, start, .., end] = crossings[..] else {
+        return Err("The error did not oscillate, try a larger amplitude".to_owned());
+    };
+
+    let period = (end - start) / (crossings.len() - 2) as f64;
+
+    let (min, max) = samples
+        .iter()
+        .filter(|[t, _]| (start..=end).contains(t))
+        .fold((f64::MAX, f64::MIN), |(min, max), [_, e]| {
+            (min.min(*e), max.max(*e))
+        });
+    let oscillation = ((max - min) / 2.0) as f32;
+
+    if oscillation <= hysteresis {
+        return Err(
+            "The oscillation is within the hysteresis, try a smaller hysteresis".to_owned(),
+        );
+    }
+
+    let gain = 4.0 * amplitude / (PI * (oscillation.powi(2) - hysteresis.powi(2)).sqrt());
+
+    Ok(ProcessModel::Ultimate {
+        gain,
+        period: period as f32,
+    })
+}
+
+/// Two point fit of a first order plus dead time model to a step test
+fn analyze_step(samples: &[[f64; 2]], amplitude: f32) -> Result<ProcessModel, String> {
+    let Some([_, initial]) = samples.first() else {
+        return Err("No samples were recorded".to_owned());
+    };
+
+    // The correction reduces the error
+    let response = samples
+        .iter()
+        .map(|[t, e]| (*t, initial - e))
+        .collect::<Vec<_>>();
+
+    let tail = &response[response.len() - (response.len() / 10).max(1)..];
+    let settled = tail.iter().map(|(_, y)| y).sum::<f64>() / tail.len() as f64;
+
+    if settled.abs() < 1e-3 {
+        return Err("The error did not respond to the step".to_owned());
+    }
+
+    let time_at = |fraction: f64| {
+        response
+            .iter()
+            .find(|(_, y)| y / settled >= fraction)
+            .map(|(t, _)| *t)
+    };
+    let (Some(t28), Some(t63)) = (time_at(0.283), time_at(0.632)) else {
+        return Err("The response did not settle, try a longer test".to_owned());
+    };
+
+    let time_constant = 1.5 * (t63 - t28);
+    if time_constant <= 0.0 {
+        return Err("The response was too fast to fit, try a smaller amplitude".to_owned());
+    }
+
+    Ok(ProcessModel::Fopdt {
+        gain: (settled / amplitude as f64) as f32,
+        time_constant: time_constant as f32,
+        dead_time: (t63 - time_constant).max(0.0) as f32,
+    })
+}
+
+fn run_auto_tune(
+    mut cmds: Commands,
+    time: Res<Time<Real>>,
+    mut helpers: Query<(&mut MovementContribution, &mut PidAutoTune)>,
+    controllers: Query<Option<&PidResult>, With<PidConfig>>,
+) {
+    for (mut contribution, mut tune) in &mut helpers {
+        let tune = &mut *tune;
+
+        let TuneState::Running(run) = &mut tune.state else {
+            if contribution.0 != MovementGlam::default() {
+                contribution.0 = MovementGlam::default();
+            }
+
+            continue;
+        };
+
+        let elapsed = (time.elapsed() - run.started).as_secs_f64();
+        let error = controllers
+            .get(run.controller)
+            .ok()
+            .flatten()
+            .map(|it| it.error);
+
+        let result = match error {
+            Some(error) => {
+                run.samples.push([elapsed, error as f64]);
+
+                if error > tune.hysteresis {
+                    run.output = 1.0;
+                } else if error < -tune.hysteresis {
+                    run.output = -1.0;
+                }
+
+                match tune.method {
+                    TuneMethod::Relay
+                        if elapsed > tune.duration.as_secs_f64()
+                            || upward_crossings(&run.samples).len() > RELAY_CYCLES =>
+                    {
+                        Some(analyze_relay(&run.samples, tune.amplitude, tune.hysteresis))
+                    }
+                    TuneMethod::Step if elapsed > tune.duration.as_secs_f64() => {
+                        Some(analyze_step(&run.samples, tune.amplitude))
+                    }
+                    _ => None,
+                }
+            }
+            None => Some(Err(
+                "The controller stopped, keep the robot armed with a target".to_owned(),
+            )),
+        };
+
+        let Some(result) = result else {
+            let output = match tune.method {
+                TuneMethod::Relay => run.output,
+                TuneMethod::Step => 1.0,
+            };
+            let movement = tune.axis.unit_movement() * (tune.amplitude * output);
+
+            if contribution.0 != movement {
+                contribution.0 = movement;
+            }
+
+            continue;
+        };
+
+        contribution.0 = MovementGlam::default();
+
+        if let TuneState::Running(run) = mem::replace(&mut tune.state, TuneState::Idle) {
+            cmds.entity(run.controller).insert(run.original.clone());
+
+            tune.state = TuneState::Finished {
+                original: run.original,
+                samples: run.samples,
+                result,
+                confirm: None,
+            };
+        }
+    }
+}
+
+/// Puts the gains back if the tuner or its helper is closed mid test
+fn restore_on_remove(
+    trigger: Trigger<OnRemove, PidAutoTune>,
+    mut cmds: Commands,
+    tuners: Query<&PidAutoTune>,
+) {
+    let Ok(PidAutoTune {
+        state: TuneState::Running(run),
+        ..
+    }) = tuners.get(trigger.entity())
+    else {
+        return;
+    };
+
+    if let Some(mut controller) = cmds.get_entity(run.controller) {
+        controller.insert(run.original.clone());
+    }
+}
+
+fn auto_tune_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    time: Res<Time<Real>>,
+
+    mut helpers: Query<(Entity, &RobotId, &mut PidAutoTune), With<PidHelper>>,
+    controllers: Query<(Entity, &Name, &RobotId, &PidConfig, Option<&PidResult>)>,
+
+    mut set_pid_config: EventWriter<SetPidConfig>,
+    settings: Res<SurfaceSettings>,
+) {
+    let palette = settings.theme.palette();
+
+    for (helper, robot, mut tune) in &mut helpers {
+        let tune = &mut *tune;
+        let mut open = true;
+
+        let context = contexts.ctx_mut();
+        egui::Window::new("Pid Auto Tune")
+            .id(Id::new((helper, "Pid Auto Tune")))
+            .constrain_to(context.available_rect().shrink(20.0))
+            .open(&mut open)
+            .show(context, |ui| {
+                let running = matches!(tune.state, TuneState::Running(_));
+                let (force_unit, error_unit) = match tune.axis {
+                    PidAxis::Depth => ("N", "m"),
+                    _ => ("Nm", "°"),
+                };
+
+                ui.add_enabled_ui(!running, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Axis:");
+                        for axis in PidAxis::ALL {
+                            ui.selectable_value(&mut tune.axis, axis, format!("{axis:?}"));
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Test:");
+                        ui.selectable_value(&mut tune.method, TuneMethod::Relay, "Relay");
+                        ui.selectable_value(&mut tune.method, TuneMethod::Step, "Step");
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Amplitude:");
+                        ui.add(
+                            DragValue::new(&mut tune.amplitude)
+                                .range(0.5..=50.0)
+                                .suffix(format!(" {force_unit}")),
+                        );
+                    });
+
+                    if tune.method == TuneMethod::Relay {
+                        ui.horizontal(|ui| {
+                            ui.label("Hysteresis:");
+                            ui.add(
+                                DragValue::new(&mut tune.hysteresis)
+                                    .range(0.0..=10.0)
+                                    .speed(0.01)
+                                    .suffix(format!(" {error_unit}")),
+                            );
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Max Duration:");
+                        let mut duration = tune.duration.as_secs_f32();
+                        ui.add(
+                            DragValue::new(&mut duration)
+                                .range(5.0..=120.0)
+                                .suffix(" s"),
+                        );
+                        tune.duration = Duration::from_secs_f32(duration);
+                    });
+                });
+
+                ui.label(match tune.method {
+                    TuneMethod::Relay => "Oscillates the robot about its target, suits every axis",
+                    TuneMethod::Step => {
+                        "Pushes the robot away from its target, suits axes that settle on \
+                         their own like pitch and roll"
+                    }
+                });
+
+                ui.separator();
+
+                let controller = controllers.iter().find(|(_, name, robot_id, ..)| {
+                    *robot_id == robot && name.as_str() == tune.axis.controller_name()
+                });
+
+                let mut next_state = None;
+                match &mut tune.state {
+                    TuneState::Running(run) => {
+                        let elapsed = time.elapsed().saturating_sub(run.started);
+                        ui.label(format!("Testing, {:.0} s", elapsed.as_secs_f32()));
+
+                        if ui.button("Abort").clicked() {
+                            cmds.entity(run.controller).insert(run.original.clone());
+                            next_state = Some(TuneState::Idle);
+                        }
+
+                        error_plot(ui, helper, &run.samples, &palette);
+                    }
+                    state => {
+                        let ready = controller.is_some_and(|(.., result)| result.is_some());
+                        if !ready {
+                            ui.label("Arm the robot and set a target for this axis to start");
+                        }
+
+                        let start = ui.add_enabled(ready, egui::Button::new("Start")).clicked();
+                        if let Some((entity, .., config, _)) = controller.filter(|_| start) {
+                            cmds.entity(entity).insert(PidConfig {
+                                kp: 0.0,
+                                ki: 0.0,
+                                kd: 0.0,
+                                ..config.clone()
+                            });
+
+                            next_state = Some(TuneState::Running(TuneRun {
+                                controller: entity,
+                                original: config.clone(),
+                                started: time.elapsed(),
+                                samples: Vec::new(),
+                                output: 1.0,
+                            }));
+                        }
+
+                        if let TuneState::Finished {
+                            original,
+                            samples,
+                            result,
+                            confirm,
+                        } = state
+                        {
+                            error_plot(ui, helper, samples, &palette);
+
+                            match result {
+                                Ok(model) => {
+                                    ui.label(model.to_string());
+                                    proposals_ui(
+                                        ui,
+                                        tune.axis,
+                                        model,
+                                        original,
+                                        confirm,
+                                        &mut set_pid_config,
+                                    );
+                                }
+                                Err(err) => {
+                                    ui.colored_label(Color32::RED, err.as_str());
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if let Some(state) = next_state {
+                    tune.state = state;
+                }
+            });
+
+        if !open {
+            cmds.entity(helper).remove::<PidAutoTune>();
+        }
+    }
+}
+
+fn error_plot(ui: &mut egui::Ui, helper: Entity, samples: &[[f64; 2]], palette: &Palette) {
+    Plot::new(("Pid Auto Tune Plot", helper))
+        .height(200.0)
+        .show(ui, |plot| {
+            plot.add(
+                Line::new("Error", samples.to_vec())
+                    .stroke((palette.stroke_width, palette.series[0])),
+            );
+        });
+}
+
+fn proposals_ui(
+    ui: &mut egui::Ui,
+    axis: PidAxis,
+    model: &ProcessModel,
+    original: &PidConfig,
+    confirm: &mut Option<usize>,
+    set_pid_config: &mut EventWriter<SetPidConfig>,
+) {
+    let proposals = model.proposals();
+
+    egui::Grid::new("Pid Proposals")
+        .striped(true)
+        .show(ui, |ui| {
+            for label in ["", "kp", "ki", "kd", ""] {
+                ui.label(label);
+            }
+            ui.end_row();
+
+            ui.label("Current");
+            for gain in [original.kp, original.ki, original.kd] {
+                ui.label(format!("{gain:.4}"));
+            }
+            ui.end_row();
+
+            for (idx, (name, gains)) in proposals.iter().enumerate() {
+                ui.label(*name);
+                for gain in gains {
+                    ui.label(format!("{gain:.4}"));
+                }
+
+                let valid = gains.iter().all(|it| it.is_finite() && *it >= 0.0);
+                if ui.add_enabled(valid, egui::Button::new("Apply")).clicked() {
+                    *confirm = Some(idx);
+                }
+                ui.end_row();
+            }
+        });
+
+    let Some((name, [kp, ki, kd])) = confirm.and_then(|it| proposals.get(it)) else {
+        return;
+    };
+
+    ui.separator();
+    ui.label(format!(
+        "Apply the {name} gains to {} and save them to the robot's config?",
+        axis.controller_name()
+    ));
+
+    ui.horizontal(|ui| {
+        if ui.button("Confirm").clicked() {
+            set_pid_config.send(SetPidConfig {
+                controller: axis.controller_name().to_owned(),
+                config: PidConfig {
+                    kp: *kp,
+                    ki: *ki,
+                    kd: *kd,
+                    ..original.clone()
+                },
+            });
+            *confirm = None;
+        }
+
+        if ui.button("Cancel").clicked() {
+            *confirm = None;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::{analyze_relay, analyze_step, ProcessModel};
+
+    #[test]
+    fn relay_finds_ultimate_point() {
+        let samples = (0..2000)
+            .map(|it| {
+                let t = it as f64 * 0.01;
+                [t, 2.0 * (TAU * t / 4.0).sin()]
+            })
+            .collect::<Vec<_>>();
+
+        let ProcessModel::Ultimate { gain, period } =
+            analyze_relay(&samples, 5.0, 0.0).expect("Analyze relay")
+        else {
+            panic!("Expected ultimate point");
+        };
+
+        assert!((period - 4.0).abs() < 0.01, "{period}");
+        assert!((gain - 10.0 / std::f32::consts::PI).abs() < 0.01, "{gain}");
+    }
+
+    #[test]
+    fn step_fits_fopdt() {
+        let (gain, tau, theta, amplitude) = (0.5, 2.0, 0.5, 4.0);
+
+        let samples = (0..2000)
+            .map(|it| {
+                let t = it as f64 * 0.01;
+                let response = if t > theta {
+                    gain * amplitude * (1.0 - (-(t - theta) / tau).exp())
+                } else {
+                    0.0
+                };
+
+                [t, 10.0 - response]
+            })
+            .collect::<Vec<_>>();
+
+        let ProcessModel::Fopdt {
+            gain: fit_gain,
+            time_constant,
+            dead_time,
+        } = analyze_step(&samples, amplitude as f32).expect("Analyze step")
+        else {
+            panic!("Expected fopdt model");
+        };
+
+        assert!((fit_gain - 0.5).abs() < 0.01, "{fit_gain}");
+        assert!((time_constant - 2.0).abs() < 0.05, "{time_constant}");
+        assert!((dead_time - 0.5).abs() < 0.05, "{dead_time}");
+    }
+
+    #[test]
+    fn relay_rejects_flat_error() {
+        let samples = (0..100)
+            .map(|it| [it as f64 * 0.1, 1.0])
+            .collect::<Vec<_>>();
+
+        assert!(analyze_relay(&samples, 5.0, 0.0).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use common::{components::PilotModes, types::pilot::PilotMode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{InputInterpolation, InputMarker},
+    settings::config_path,
+    surface::{LocalSurface, LocalSurfaceMarker},
+};
+
+pub const PILOT_MODES_FILE: &str = "pilot_modes.toml";
+
+pub struct PilotModesPlugin;
+
+impl Plugin for PilotModesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_pilot_modes)
+            .add_systems(Update, (sync_pilot_modes, save_pilot_modes));
+    }
+}
+
+/// Toml cant represent a top level array
+#[derive(Serialize, Deserialize)]
+struct PilotModesFile {
+    modes: Vec<PilotMode>,
+}
+
+fn load_pilot_modes(mut cmds: Commands, surface: Res<LocalSurface>) {
+    let res: anyhow::Result<PilotModesFile> = try {
+        let modes =
+            fs::read_to_string(config_path(PILOT_MODES_FILE)).context("Read pilot modes")?;
+        toml::from_str(&modes).context("Parse pilot modes")?
+    };
+
+    let modes = match res {
+        Ok(file) => PilotModes(file.modes),
+        Err(err) => {
+            warn!("Using default pilot modes: {err:?}");
+            PilotModes::default()
+        }
+    };
+
+    cmds.entity(surface.entity).insert(modes);
+}
+
+fn save_pilot_modes(modes: Query<Ref<PilotModes>, With<LocalSurfaceMarker>>) {
+    let Ok(modes) = modes.get_single() else {
+        return;
+    };
+
+    if !modes.is_changed() || modes.is_added() {
+        return;
+    }
+
+    let file = PilotModesFile {
+        modes: modes.0.clone(),
+    };
+
+    let Ok(str) = toml::to_string_pretty(&file) else {
+        error!("Could not serialize pilot modes");
+        return;
+    };
+
+    let res = fs::write(config_path(PILOT_MODES_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write pilot modes: {err:?}");
+    }
+}
+
+/// Pushes edited mode parameters to the inputs currently using that mode
+fn sync_pilot_modes(
+    modes: Query<&PilotModes, (With<LocalSurfaceMarker>, Changed<PilotModes>)>,
+    mut inputs: Query<&mut InputInterpolation, With<InputMarker>>,
+) {
+    let Ok(modes) = modes.get_single() else {
+        return;
+    };
+
+    for mut interpolation in &mut inputs {
+        let mode = modes
+            .get(&interpolation.name)
+            .filter(|it| it.enabled)
+            .or_else(|| modes.enabled().next());
+
+        if let Some(mode) = mode {
+            if interpolation.0 != *mode {
+                interpolation.0 = mode.clone();
+            }
+        }
+    }
+}
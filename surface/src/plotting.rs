@@ -0,0 +1,300 @@
+use std::{collections::VecDeque, fmt::Write as _, fs};
+
+use anyhow::Context;
+use bevy::{
+    prelude::*,
+    reflect::{GetPath, ReflectRef},
+    render::view::screenshot::{save_to_disk, Screenshot},
+};
+use common::{
+    adapters::serde::ReflectSerdeAdapter,
+    components::{Robot, RobotId},
+    error::ErrorEvent,
+};
+use egui_plot::PlotPoint;
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::session::{artifact_directory, ActiveSession};
+
+pub const PLOT_DIRECTORY: &str = "plots";
+
+/// Roughly ten minutes at 60 fps
+const MAX_SAMPLES: usize = 36_000;
+/// Nested fields deeper than this are not offered in the browser
+const MAX_FIELD_DEPTH: usize = 4;
+
+pub struct PlottingPlugin;
+
+impl Plugin for PlottingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlotWorkspace>()
+            .init_resource::<PlotBrowser>()
+            .add_event::<ExportPlot>()
+            .add_systems(
+                Update,
+                (
+                    sample_series,
+                    refresh_browser.run_if(|browser: Res<PlotBrowser>| browser.enabled),
+                    export_plot,
+                ),
+            );
+    }
+}
+
+/// The series being recorded, shared by every plot in the workspace
+#[derive(Resource, Debug, Default)]
+pub struct PlotWorkspace {
+    pub series: Vec<PlotSeries>,
+    /// Stops recording new samples so the plot can be inspected
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlotSeries {
+    pub entity: Entity,
+    pub entity_name: String,
+    /// The type path of the component
+    pub component: String,
+    /// A reflect path into the component such as `.0.x`
+    pub field: String,
+    pub points: VecDeque<PlotPoint>,
+}
+
+impl PlotSeries {
+    pub fn new(entity: Entity, entity_name: String, component: String, field: String) -> Self {
+        Self {
+            entity,
+            entity_name,
+            component,
+            field,
+            points: VecDeque::new(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let component = self
+            .component
+            .rsplit("::")
+            .next()
+            .unwrap_or(&self.component);
+
+        format!("{} {component}{}", self.entity_name, self.field)
+    }
+}
+
+/// Numeric fields of the replicated components on robot entities, only kept up to date while
+/// `enabled` is set
+#[derive(Resource, Debug, Default)]
+pub struct PlotBrowser {
+    pub enabled: bool,
+    pub entities: Vec<BrowserEntity>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrowserEntity {
+    pub entity: Entity,
+    pub name: String,
+    /// Component type path and the reflect paths of its numeric fields
+    pub components: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportPlot {
+    Csv,
+    /// Saves a screenshot of the control station
+    Png,
+}
+
+fn as_f64(value: &dyn PartialReflect) -> Option<f64> {
+    macro_rules! numeric {
+        ($($ty:ty),*) => {
+            $(
+                if let Some(value) = value.try_downcast_ref::<$ty>() {
+                    return Some(*value as f64);
+                }
+            )*
+        };
+    }
+
+    if let Some(value) = value.try_downcast_ref::<f64>() {
+        return Some(*value);
+    }
+
+    numeric!(f32, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+    value
+        .try_downcast_ref::<bool>()
+        .map(|it| if *it { 1.0 } else { 0.0 })
+}
+
+fn numeric_fields(value: &dyn PartialReflect, path: String, depth: usize, out: &mut Vec<String>) {
+    if as_f64(value).is_some() {
+        out.push(path);
+        return;
+    }
+
+    if depth >= MAX_FIELD_DEPTH {
+        return;
+    }
+
+    match value.reflect_ref() {
+        ReflectRef::Struct(value) => {
+            for idx in 0..value.field_len() {
+                if let (Some(name), Some(field)) = (value.name_at(idx), value.field_at(idx)) {
+                    numeric_fields(field, format!("{path}.{name}"), depth + 1, out);
+                }
+            }
+        }
+        ReflectRef::TupleStruct(value) => {
+            for (idx, field) in value.iter_fields().enumerate() {
+                numeric_fields(field, format!("{path}.{idx}"), depth + 1, out);
+            }
+        }
+        ReflectRef::Tuple(value) => {
+            for (idx, field) in value.iter_fields().enumerate() {
+                numeric_fields(field, format!("{path}.{idx}"), depth + 1, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn refresh_browser(world: &mut World) {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    let mut entities = world.query_filtered::<(Entity, &Name), Or<(With<Robot>, With<RobotId>)>>();
+    let entities = entities
+        .iter(world)
+        .map(|(entity, name)| (entity, name.to_string()))
+        .collect::<Vec<_>>();
+
+    let mut browser_entities = Vec::new();
+
+    for (entity, name) in entities {
+        let entity_ref = world.entity(entity);
+        let mut components = Vec::new();
+
+        for component_id in entity_ref.archetype().components() {
+            let Some(type_id) = world
+                .components()
+                .get_info(component_id)
+                .and_then(|it| it.type_id())
+            else {
+                continue;
+            };
+
+            let Some(registration) = registry.get(type_id) else {
+                continue;
+            };
+
+            // Only replicated components
+            if registration.data::<ReflectSerdeAdapter>().is_none() {
+                continue;
+            }
+
+            let Some(value) = registration
+                .data::<ReflectComponent>()
+                .and_then(|it| it.reflect(entity_ref))
+            else {
+                continue;
+            };
+
+            let mut fields = Vec::new();
+            numeric_fields(value.as_partial_reflect(), String::new(), 0, &mut fields);
+
+            if !fields.is_empty() {
+                let type_path = registration.type_info().type_path().to_owned();
+                components.push((type_path, fields));
+            }
+        }
+
+        components.sort();
+        browser_entities.push(BrowserEntity {
+            entity,
+            name,
+            components,
+        });
+    }
+
+    browser_entities.sort_by(|a, b| a.name.cmp(&b.name));
+    world.resource_mut::<PlotBrowser>().entities = browser_entities;
+}
+
+fn sample_series(world: &mut World) {
+    if world.resource::<PlotWorkspace>().paused {
+        return;
+    }
+
+    let now = world.resource::<Time<Real>>().elapsed_secs_f64();
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    world.resource_scope(|world, mut workspace: Mut<PlotWorkspace>| {
+        for series in &mut workspace.series {
+            let value: Option<f64> = try {
+                let entity = world.get_entity(series.entity).ok()?;
+                let registration = registry.get_with_type_path(&series.component)?;
+                let component = registration.data::<ReflectComponent>()?.reflect(entity)?;
+                let field = component.reflect_path(series.field.as_str()).ok()?;
+
+                as_f64(field)?
+            };
+
+            if let Some(value) = value {
+                series.points.push_back(PlotPoint::new(now, value));
+
+                while series.points.len() > MAX_SAMPLES {
+                    series.points.pop_front();
+                }
+            }
+        }
+    });
+}
+
+fn export_plot(
+    mut cmds: Commands,
+    mut events: EventReader<ExportPlot>,
+    workspace: Res<PlotWorkspace>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for export in events.read() {
+        let res: anyhow::Result<()> = try {
+            let directory = artifact_directory(session.as_deref(), PLOT_DIRECTORY);
+            fs::create_dir_all(&directory).context("Create plot directory")?;
+
+            let time = OffsetDateTime::now_utc();
+            let file_name = time.format(&Iso8601::DATE_TIME).context("Format time")?;
+
+            match export {
+                ExportPlot::Csv => {
+                    let path = directory.join(format!("plot_{file_name}.csv"));
+
+                    let mut csv = "series,time,value\n".to_owned();
+                    for series in &workspace.series {
+                        let name = series.name().replace('"', "\"\"");
+
+                        for point in &series.points {
+                            let _ = writeln!(csv, "\"{name}\",{},{}", point.x, point.y);
+                        }
+                    }
+
+                    fs::write(&path, csv).context("Write plot csv")?;
+                    info!("Saved plot to {path:?}");
+                }
+                ExportPlot::Png => {
+                    let path = directory.join(format!("plot_{file_name}.png"));
+
+                    info!("Saving screenshot to {path:?}");
+                    cmds.spawn(Screenshot::primary_window())
+                        .observe(save_to_disk(path));
+                }
+            }
+        };
+
+        if let Err(err) = res {
+            errors.send(err.into());
+        }
+    }
+}
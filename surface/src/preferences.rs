@@ -0,0 +1,55 @@
+//! Display units chosen by the pilot, every readout formats through `UnitPreferences`, edited
+//! from the settings window
+
+use std::fs;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use common::types::units::UnitPreferences;
+
+use crate::settings::config_path;
+
+pub const PREFERENCES_FILE: &str = "preferences.toml";
+
+pub struct PreferencesPlugin;
+
+impl Plugin for PreferencesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, load_preferences)
+            .add_systems(Last, write_preferences);
+    }
+}
+
+fn load_preferences(mut cmds: Commands) {
+    let res: anyhow::Result<UnitPreferences> = try {
+        let preferences =
+            fs::read_to_string(config_path(PREFERENCES_FILE)).context("Read preferences")?;
+        toml::from_str(&preferences).context("Parse preferences")?
+    };
+
+    let preferences = match res {
+        Ok(preferences) => preferences,
+        Err(err) => {
+            warn!("Using default preferences: {err:?}");
+            UnitPreferences::default()
+        }
+    };
+
+    cmds.insert_resource(preferences);
+}
+
+fn write_preferences(preferences: Res<UnitPreferences>) {
+    if !preferences.is_changed() || preferences.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*preferences) else {
+        error!("Could not serialize preferences");
+        return;
+    };
+
+    let res = fs::write(config_path(PREFERENCES_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write preferences: {err:?}");
+    }
+}
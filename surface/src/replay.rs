@@ -0,0 +1,524 @@
+//! Replays a recording session's telemetry, and the video recorded with it, as if the robot was
+//! connected
+//!
+//! Recording sessions log every change replicated from the robot to `telemetry.bin`, starting
+//! with the state when the session started. The replay clock feeds the logged changes through
+//! the normal replication path under `REPLAY_TOKEN`, so the HUD and debuggers work unchanged, and
+//! the recorded video is decoded at the same position, see `video_stream::playback`.
+//!
+//! Replayed state is read only, events are not logged and nothing is sent to a robot. A replay
+//! can only start while disconnected and stops if a robot connects.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ahash::HashMap;
+use anyhow::{anyhow, Context};
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    adapters::BackingType,
+    ecs_sync::{
+        apply_changes::ChangeApplicationSet, EntityMap, NetId, NetTypeId, SerializedChange,
+        SerializedChangeInEvent,
+    },
+    error::ErrorEvent,
+    sync::{Peers, REPLAY_TOKEN},
+};
+use networking::Token;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dive_log::format_secs,
+    layout::DockableWindow,
+    session::{artifact_directory, ActiveSession},
+    ui::ReplayUi,
+    video_stream::recording::RECORDING_DIRECTORY,
+};
+
+pub const TELEMETRY_LOG_FILE: &str = "telemetry.bin";
+
+pub struct TelemetryReplayPlugin;
+
+impl Plugin for TelemetryReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RemoteState>()
+            .add_event::<StartReplay>()
+            .add_event::<StopReplay>()
+            .add_systems(
+                PreUpdate,
+                (
+                    track_remote_state.after(ChangeApplicationSet),
+                    (handle_replay_events, advance_replay)
+                        .chain()
+                        .before(ChangeApplicationSet),
+                ),
+            )
+            .add_systems(Update, replay_ui.run_if(resource_exists::<ReplayUi>));
+    }
+}
+
+/// An entry in a session's telemetry log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryRecord {
+    /// Since the recording session started
+    pub elapsed: Duration,
+    pub entry: TelemetryEntry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TelemetryEntry {
+    Change(SerializedChange),
+    /// A camera started recording to `file`, relative to the session folder
+    VideoStarted {
+        camera: String,
+        file: String,
+    },
+}
+
+/// Entities and their replicated components, built up from a stream of changes
+#[derive(Debug, Clone, Default)]
+pub struct ReplicatedState {
+    entities: HashMap<NetId, HashMap<NetTypeId, BackingType>>,
+}
+
+impl ReplicatedState {
+    pub fn apply(&mut self, change: &SerializedChange) {
+        match change {
+            SerializedChange::EntitySpawned(net_id) => {
+                self.entities.entry(*net_id).or_default();
+            }
+            SerializedChange::EntityDespawned(net_id) => {
+                self.entities.remove(net_id);
+            }
+            SerializedChange::ComponentUpdated(net_id, token, raw) => {
+                let Some(components) = self.entities.get_mut(net_id) else {
+                    return;
+                };
+
+                match raw {
+                    Some(raw) => components.insert(token.clone(), raw.clone()),
+                    None => components.remove(token),
+                };
+            }
+            SerializedChange::EventEmitted(..) => {}
+        }
+    }
+
+    /// The changes that recreate this state, every spawn comes before the components
+    pub fn changes(&self) -> impl Iterator<Item = SerializedChange> + '_ {
+        let spawns = self
+            .entities
+            .keys()
+            .map(|net_id| SerializedChange::EntitySpawned(*net_id));
+        let components = self.entities.iter().flat_map(|(net_id, components)| {
+            components.iter().map(|(token, raw)| {
+                SerializedChange::ComponentUpdated(*net_id, token.clone(), Some(raw.clone()))
+            })
+        });
+
+        spawns.chain(components)
+    }
+
+    /// The changes that turn this state into `target`
+    pub fn diff(&self, target: &Self) -> Vec<SerializedChange> {
+        let mut changes = Vec::new();
+
+        for net_id in self.entities.keys() {
+            if !target.entities.contains_key(net_id) {
+                changes.push(SerializedChange::EntityDespawned(*net_id));
+            }
+        }
+
+        for (net_id, components) in &target.entities {
+            let current = self.entities.get(net_id);
+            if current.is_none() {
+                changes.push(SerializedChange::EntitySpawned(*net_id));
+            }
+
+            for (token, raw) in components {
+                if current.and_then(|it| it.get(token)) != Some(raw) {
+                    changes.push(SerializedChange::ComponentUpdated(
+                        *net_id,
+                        token.clone(),
+                        Some(raw.clone()),
+                    ));
+                }
+            }
+
+            for token in current.into_iter().flat_map(|it| it.keys()) {
+                if !components.contains_key(token) {
+                    changes.push(SerializedChange::ComponentUpdated(
+                        *net_id,
+                        token.clone(),
+                        None,
+                    ));
+                }
+            }
+        }
+
+        changes
+    }
+}
+
+/// What each connected peer has replicated to us, written at the start of every telemetry log
+#[derive(Resource, Debug, Default)]
+pub struct RemoteState(HashMap<Token, ReplicatedState>);
+
+impl RemoteState {
+    pub fn changes(&self) -> impl Iterator<Item = SerializedChange> + '_ {
+        self.0.values().flat_map(|it| it.changes())
+    }
+}
+
+/// Loads a recording session folder and starts replaying it from the beginning
+#[derive(Event, Debug, Clone)]
+pub struct StartReplay(pub PathBuf);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StopReplay;
+
+/// A recording session being replayed, exists while the replay is loaded
+#[derive(Resource, Debug)]
+pub struct TelemetryReplay {
+    pub folder: PathBuf,
+    pub videos: Vec<ReplayVideo>,
+    pub duration: Duration,
+    /// The replay clock, both the replicated state and the video follow it
+    pub position: Duration,
+    pub playing: bool,
+
+    changes: Vec<(Duration, SerializedChange)>,
+    /// Changes before this index have been applied
+    applied: usize,
+    /// The state the applied changes add up to
+    state: ReplicatedState,
+}
+
+/// A video file recorded during the session
+#[derive(Debug, Clone)]
+pub struct ReplayVideo {
+    pub camera: String,
+    pub path: PathBuf,
+    /// When the recording started on the replay clock
+    pub started: Duration,
+}
+
+impl TelemetryReplay {
+    pub fn load(folder: &Path) -> anyhow::Result<Self> {
+        let file = File::open(folder.join(TELEMETRY_LOG_FILE)).context("Open telemetry log")?;
+        let mut reader = BufReader::new(file);
+
+        let mut changes = Vec::new();
+        let mut videos = Vec::new();
+
+        loop {
+            let record: TelemetryRecord = match bincode::deserialize_from(&mut reader) {
+                Ok(record) => record,
+                Err(err) => {
+                    let eof = matches!(
+                        &*err,
+                        bincode::ErrorKind::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof
+                    );
+
+                    // The last record is cut short if the control station didn't exit cleanly
+                    if !eof {
+                        warn!("Telemetry log ends early: {err}");
+                    }
+
+                    break;
+                }
+            };
+
+            match record.entry {
+                TelemetryEntry::Change(change) => changes.push((record.elapsed, change)),
+                TelemetryEntry::VideoStarted { camera, file } => videos.push(ReplayVideo {
+                    camera,
+                    path: folder.join(file),
+                    started: record.elapsed,
+                }),
+            }
+        }
+
+        let duration = changes.last().map(|(it, _)| *it).unwrap_or_default();
+
+        Ok(Self {
+            folder: folder.to_owned(),
+            videos,
+            duration,
+            position: Duration::ZERO,
+            playing: false,
+            changes,
+            applied: 0,
+            state: ReplicatedState::default(),
+        })
+    }
+
+    pub fn seek(&mut self, position: Duration) {
+        self.position = position.min(self.duration);
+    }
+}
+
+fn track_remote_state(
+    mut state: ResMut<RemoteState>,
+    peers: Res<Peers>,
+    mut changes: EventReader<SerializedChangeInEvent>,
+) {
+    for SerializedChangeInEvent(change, token) in changes.read() {
+        if *token != REPLAY_TOKEN && peers.is_valid(*token) {
+            state.0.entry(*token).or_default().apply(change);
+        }
+    }
+
+    state.0.retain(|token, _| peers.is_valid(*token));
+}
+
+fn despawn_replayed(cmds: &mut Commands, entity_map: &mut EntityMap) {
+    for entity in entity_map.forget_peer(REPLAY_TOKEN) {
+        if let Some(mut entity) = cmds.get_entity(entity) {
+            entity.despawn();
+        }
+    }
+}
+
+fn handle_replay_events(
+    mut cmds: Commands,
+    mut start: EventReader<StartReplay>,
+    mut stop: EventReader<StopReplay>,
+    replay: Option<Res<TelemetryReplay>>,
+    mut peers: ResMut<Peers>,
+    mut entity_map: ResMut<EntityMap>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let stop = stop.read().count() > 0;
+    let connected = peers.is_connected();
+
+    if replay.is_some() && (stop || connected) {
+        if connected {
+            warn!("Robot connected, stopping replay");
+        }
+
+        info!("Stopped replay");
+        despawn_replayed(&mut cmds, &mut entity_map);
+        peers.set_replaying(false);
+        cmds.remove_resource::<TelemetryReplay>();
+    }
+
+    let Some(StartReplay(folder)) = start.read().last() else {
+        return;
+    };
+
+    if connected {
+        errors.send(anyhow!("Disconnect from the robot before replaying a recording").into());
+        return;
+    }
+
+    match TelemetryReplay::load(folder) {
+        Ok(replay) => {
+            info!(
+                "Replaying {folder:?}, {} changes and {} videos",
+                replay.changes.len(),
+                replay.videos.len()
+            );
+
+            // Entities from a replay being replaced
+            despawn_replayed(&mut cmds, &mut entity_map);
+
+            peers.set_replaying(true);
+            cmds.insert_resource(replay);
+        }
+        Err(err) => {
+            errors.send(err.context(format!("Load replay {folder:?}")).into());
+        }
+    }
+}
+
+/// Moves the replay clock and applies the changes logged up to it
+fn advance_replay(
+    replay: Option<ResMut<TelemetryReplay>>,
+    mut changes: EventWriter<SerializedChangeInEvent>,
+    time: Res<Time<Real>>,
+) {
+    let Some(mut replay) = replay else {
+        return;
+    };
+    let replay = &mut *replay;
+
+    if replay.playing {
+        replay.position = (replay.position + time.delta()).min(replay.duration);
+        replay.playing = replay.position < replay.duration;
+    }
+
+    let end = replay
+        .changes
+        .partition_point(|(elapsed, _)| *elapsed <= replay.position);
+
+    if end < replay.applied {
+        // Seeking back rebuilds the state from the start of the log and applies the difference,
+        // so entities and their video threads survive scrubbing
+        let mut state = ReplicatedState::default();
+        for (_, change) in &replay.changes[..end] {
+            state.apply(change);
+        }
+
+        changes.send_batch(
+            replay
+                .state
+                .diff(&state)
+                .into_iter()
+                .map(|change| SerializedChangeInEvent(change, REPLAY_TOKEN)),
+        );
+        replay.state = state;
+    } else {
+        for (_, change) in &replay.changes[replay.applied..end] {
+            replay.state.apply(change);
+        }
+
+        changes.send_batch(
+            replay.changes[replay.applied..end]
+                .iter()
+                .map(|(_, change)| SerializedChangeInEvent(change.clone(), REPLAY_TOKEN)),
+        );
+    }
+
+    replay.applied = end;
+}
+
+/// Recording session folders with a telemetry log, newest first
+fn find_recordings(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut recordings = entries
+        .filter_map(|it| it.ok())
+        .map(|it| it.path())
+        .filter(|it| it.join(TELEMETRY_LOG_FILE).is_file())
+        .collect::<Vec<_>>();
+    recordings.sort_unstable_by(|a, b| b.cmp(a));
+
+    recordings
+}
+
+fn replay_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<ReplayUi>,
+    replay: Option<ResMut<TelemetryReplay>>,
+    peers: Res<Peers>,
+    session: Option<Res<ActiveSession>>,
+    mut recordings: Local<Option<Vec<PathBuf>>>,
+    mut start: EventWriter<StartReplay>,
+    mut stop: EventWriter<StopReplay>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Replay").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        egui::Id::new("Replay"),
+        |ui| {
+            if let Some(mut replay) = replay {
+                ui.label(format!("Replaying {}", replay.folder.display()));
+
+                ui.horizontal(|ui| {
+                    let label = if replay.playing { "Pause" } else { "Play" };
+                    if ui.button(label).clicked() {
+                        if !replay.playing && replay.position >= replay.duration {
+                            replay.seek(Duration::ZERO);
+                        }
+
+                        replay.playing = !replay.playing;
+                    }
+
+                    if ui.button("Stop").clicked() {
+                        stop.send(StopReplay);
+                    }
+
+                    ui.label(format!(
+                        "{} / {}",
+                        format_secs(replay.position.as_secs_f64()),
+                        format_secs(replay.duration.as_secs_f64())
+                    ));
+                });
+
+                let mut position = replay.position.as_secs_f32();
+                let slider = egui::Slider::new(&mut position, 0.0..=replay.duration.as_secs_f32())
+                    .show_value(false);
+                if ui.add(slider).changed() {
+                    replay.seek(Duration::from_secs_f32(position));
+                }
+
+                if replay.videos.is_empty() {
+                    ui.label("No video was recorded");
+                }
+                for video in &replay.videos {
+                    ui.label(format!(
+                        "{} from {}",
+                        video.camera,
+                        format_secs(video.started.as_secs_f64())
+                    ));
+                }
+
+                return;
+            }
+
+            let directory = artifact_directory(session.as_deref(), RECORDING_DIRECTORY);
+
+            ui.horizontal(|ui| {
+                ui.label(format!("Recordings in {}", directory.display()));
+
+                if ui.button("Refresh").clicked() {
+                    *recordings = None;
+                }
+            });
+
+            let recordings = recordings.get_or_insert_with(|| find_recordings(&directory));
+            if recordings.is_empty() {
+                ui.label("No recordings with telemetry");
+            }
+
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for recording in recordings.iter() {
+                        let name = recording
+                            .file_name()
+                            .map(|it| it.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+
+                        if ui
+                            .selectable_label(ui_state.folder == recording.to_string_lossy(), name)
+                            .clicked()
+                        {
+                            ui_state.folder = recording.to_string_lossy().into_owned();
+                        }
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut ui_state.folder);
+
+                let connected = peers.is_connected();
+                let folder = ui_state.folder.trim();
+
+                let button = ui
+                    .add_enabled(
+                        !connected && !folder.is_empty(),
+                        egui::Button::new("Replay"),
+                    )
+                    .on_disabled_hover_text("Disconnect from the robot to replay a recording");
+                if button.clicked() {
+                    start.send(StartReplay(PathBuf::from(folder)));
+                }
+            });
+        },
+    );
+
+    if !open {
+        cmds.remove_resource::<ReplayUi>();
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use common::{error::ErrorEvent, events::LogLines};
+use time::format_description::well_known::Iso8601;
+
+use crate::session::{artifact_directory, ActiveSession};
+
+pub const ROBOT_LOG_DIRECTORY: &str = "robot_logs";
+
+pub struct RobotLogsPlugin;
+
+impl Plugin for RobotLogsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, save_logs);
+    }
+}
+
+/// Writes the log lines returned by a `FetchLogs` request to a new file per response
+fn save_logs(
+    mut events: EventReader<LogLines>,
+    mut output: Local<Option<BufWriter<File>>>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for LogLines { lines, last } in events.read() {
+        let res: anyhow::Result<()> = try {
+            if output.is_none() {
+                let directory = artifact_directory(session.as_deref(), ROBOT_LOG_DIRECTORY);
+                fs::create_dir_all(&directory).context("Create log directory")?;
+
+                let time = time::OffsetDateTime::now_utc();
+                let file_name = time.format(&Iso8601::DATE_TIME).context("Format time")?;
+                let path = directory.join(format!("robot_{file_name}.log"));
+
+                info!("Saving robot logs to {path:?}");
+                let file = File::create(&path).context("Create log file")?;
+
+                *output = Some(BufWriter::new(file));
+            }
+
+            let writer = output.as_mut().expect("Log file is opened above");
+
+            for line in lines {
+                writeln!(writer, "{line}").context("Write log line")?;
+            }
+
+            if *last {
+                writer.flush().context("Flush log file")?;
+                *output = None;
+
+                info!("Finished saving robot logs");
+            }
+        };
+
+        if let Err(err) = res {
+            *output = None;
+            errors.send(err.into());
+        }
+    }
+}
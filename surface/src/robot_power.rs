@@ -0,0 +1,57 @@
+//! Confirmation for the robot restart and shutdown commands in the File menu
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::events::{PowerAction, PowerCommand};
+use egui::Align2;
+
+pub struct RobotPowerPlugin;
+
+impl Plugin for RobotPowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            confirm_power_command.run_if(resource_exists::<ConfirmPowerCommand>),
+        );
+    }
+}
+
+/// Shows the confirmation dialog for the action
+#[derive(Resource)]
+pub struct ConfirmPowerCommand(pub PowerAction);
+
+fn confirm_power_command(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    confirm: Res<ConfirmPowerCommand>,
+    mut commands: EventWriter<PowerCommand>,
+) {
+    let action = confirm.0;
+    let warning = match action {
+        PowerAction::RestartProcess => "The robot will disconnect for a few seconds.",
+        PowerAction::RebootOs => "The robot will disconnect for about a minute.",
+        PowerAction::Shutdown => "The robot stays off until its power is cycled.",
+    };
+
+    egui::Window::new(format!("{}?", action.name()))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("The robot will be disarmed first.");
+            ui.label(warning);
+
+            ui.horizontal(|ui| {
+                if ui.button(action.name()).clicked() {
+                    info!("Sending {}", action.name());
+
+                    commands.send(PowerCommand(action));
+                    cmds.remove_resource::<ConfirmPowerCommand>();
+                }
+
+                if ui.button("Cancel").clicked() {
+                    cmds.remove_resource::<ConfirmPowerCommand>();
+                }
+            });
+        });
+}
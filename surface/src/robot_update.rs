@@ -0,0 +1,267 @@
+//! Pushes new robot binaries to the robot, replacing deploys over ssh. The robot side lives in
+//! the robot's `update` plugin
+
+use std::fs;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::{
+    error::Errors,
+    events::{BeginUpdate, CancelUpdate, UpdateChunk, UpdateState, UpdateStatus},
+    git::GitMetadata,
+    sync::Peer,
+    update::{self, CHUNK_SIZE, CHUNK_WINDOW},
+};
+use egui::Color32;
+
+use crate::{layout::DockableWindow, settings::SurfaceSettings, ui::RobotUpdateUi};
+
+pub struct RobotUpdatePlugin;
+
+impl Plugin for RobotUpdatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RobotUpdate>().add_systems(
+            Update,
+            (
+                track_status,
+                send_chunks,
+                robot_update_ui.run_if(resource_exists::<RobotUpdateUi>),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct RobotUpdate {
+    /// Install even if the robot already runs the same version
+    force: bool,
+    /// The binary is being read and hashed
+    loading: bool,
+    transfer: Option<Transfer>,
+    /// Last status reported by the robot
+    status: Option<UpdateStatus>,
+}
+
+struct Transfer {
+    binary: Vec<u8>,
+    sent: u64,
+}
+
+fn track_status(mut update: ResMut<RobotUpdate>, mut events: EventReader<UpdateStatus>) {
+    for status in events.read() {
+        if status.state != UpdateState::Receiving && update.transfer.take().is_some() {
+            info!("Robot update {}: {}", status.state.name(), status.message);
+        }
+
+        update.status = Some(status.clone());
+    }
+}
+
+/// Streams the binary, staying at most `CHUNK_WINDOW` chunks ahead of the robot
+fn send_chunks(mut update: ResMut<RobotUpdate>, mut chunks: EventWriter<UpdateChunk>) {
+    let update = &mut *update;
+
+    let Some(ref mut transfer) = update.transfer else {
+        return;
+    };
+    // Waits for the robot to accept the update
+    let Some(UpdateStatus {
+        state: UpdateState::Receiving,
+        received,
+        ..
+    }) = update.status
+    else {
+        return;
+    };
+
+    let size = transfer.binary.len() as u64;
+    let window = received + CHUNK_WINDOW * CHUNK_SIZE as u64;
+
+    while transfer.sent < size && transfer.sent < window {
+        let start = transfer.sent as usize;
+        let end = (start + CHUNK_SIZE).min(transfer.binary.len());
+
+        chunks.send(UpdateChunk {
+            offset: transfer.sent,
+            data: transfer.binary[start..end].to_vec(),
+        });
+        transfer.sent = end as u64;
+    }
+}
+
+/// Reads and hashes the binary off the main thread, then announces it to the robot
+fn start_update(
+    runtime: &TokioTasksRuntime,
+    errors: &Errors,
+    path: String,
+    key: Option<String>,
+    force: bool,
+) {
+    let errors = errors.0.clone();
+
+    runtime.spawn_background_task(async move |mut ctx| {
+        let res = tokio::task::spawn_blocking(move || {
+            let binary = fs::read(&path).with_context(|| format!("Read robot binary {path:?}"))?;
+            let checksum = update::checksum(&binary, key.as_deref());
+
+            anyhow::Ok((binary, checksum))
+        })
+        .await;
+
+        let res = match res.context("Join binary reader") {
+            Ok(Ok(loaded)) => Some(loaded),
+            Ok(Err(err)) | Err(err) => {
+                let _ = errors.send(err);
+                None
+            }
+        };
+
+        ctx.run_on_main_thread(move |ctx| {
+            let mut update = ctx.world.resource_mut::<RobotUpdate>();
+            update.loading = false;
+
+            let Some((binary, checksum)) = res else {
+                return;
+            };
+
+            let size = binary.len() as u64;
+            info!("Pushing a {size} byte robot binary");
+
+            update.status = None;
+            update.transfer = Some(Transfer { binary, sent: 0 });
+
+            ctx.world.send_event(BeginUpdate {
+                size,
+                checksum,
+                force,
+            });
+        })
+        .await;
+    });
+}
+
+fn robot_update_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut update: ResMut<RobotUpdate>,
+    mut settings: ResMut<SurfaceSettings>,
+    runtime: Res<TokioTasksRuntime>,
+    errors: Res<Errors>,
+    robot: Query<Option<&GitMetadata>, With<Peer>>,
+    mut cancel: EventWriter<CancelUpdate>,
+) {
+    let mut open = true;
+    let mut edited = settings.clone();
+
+    let connected = !robot.is_empty();
+    let robot_version = robot.iter().flatten().next().map(|it| it.version());
+    let surface_version = GitMetadata::new().map(|it| it.version());
+
+    egui::Window::new("Robot Update")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Robot Update"), |ui| {
+            egui::Grid::new("Robot Update Versions")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Robot");
+                    ui.label(match (connected, &robot_version) {
+                        (false, _) => "Not connected",
+                        (true, Some(version)) => version.as_str(),
+                        (true, None) => "Unknown",
+                    });
+                    ui.end_row();
+
+                    ui.label("Surface Build");
+                    ui.label(surface_version.as_deref().unwrap_or("Unknown"));
+                    ui.end_row();
+
+                    ui.label("Binary");
+                    ui.text_edit_singleline(&mut edited.robot_binary);
+                    ui.end_row();
+
+                    ui.label("Update Key");
+                    let mut key = edited.update_key.clone().unwrap_or_default();
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut key).password(true))
+                        .changed()
+                    {
+                        edited.update_key = (!key.is_empty()).then_some(key);
+                    }
+                    ui.end_row();
+                });
+
+            ui.checkbox(&mut update.force, "Reinstall the same version");
+
+            // The binary is usually built from the same checkout as the surface
+            let up_to_date = robot_version.is_some()
+                && robot_version == surface_version
+                && !robot_version
+                    .as_ref()
+                    .is_some_and(|it| it.ends_with("-dirty"));
+            if up_to_date && !update.force {
+                ui.weak("The robot already runs this surface's version");
+            }
+
+            ui.separator();
+
+            let busy = update.loading || update.transfer.is_some();
+
+            ui.horizontal(|ui| {
+                let can_send = connected && !busy && !edited.robot_binary.is_empty();
+                if ui
+                    .add_enabled(can_send, egui::Button::new("Send"))
+                    .clicked()
+                {
+                    update.loading = true;
+                    start_update(
+                        &runtime,
+                        &errors,
+                        edited.robot_binary.clone(),
+                        edited.update_key.clone(),
+                        update.force,
+                    );
+                }
+
+                if ui
+                    .add_enabled(update.transfer.is_some(), egui::Button::new("Cancel"))
+                    .clicked()
+                {
+                    cancel.send(CancelUpdate);
+                    update.transfer = None;
+                }
+
+                if update.loading {
+                    ui.spinner();
+                    ui.label("Reading binary");
+                }
+            });
+
+            if let Some(ref status) = update.status {
+                if status.state == UpdateState::Receiving && status.total > 0 {
+                    let progress = status.received as f32 / status.total as f32;
+                    ui.add(egui::ProgressBar::new(progress).show_percentage());
+                }
+
+                let color = match status.state {
+                    UpdateState::Failed | UpdateState::RolledBack => Color32::RED,
+                    UpdateState::Installed => Color32::GREEN,
+                    _ => ui.visuals().text_color(),
+                };
+                ui.colored_label(
+                    color,
+                    format!("{}: {}", status.state.name(), status.message),
+                );
+            }
+        });
+
+    if edited != *settings {
+        *settings = edited;
+    }
+
+    if !open {
+        cmds.remove_resource::<RobotUpdateUi>();
+    }
+}
@@ -0,0 +1,317 @@
+use bevy::{
+    color::palettes::css,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        view::RenderLayers,
+    },
+};
+use bevy_egui::EguiContexts;
+use common::components::{
+    ActualForce, ActualMovement, Orientation, Robot, TargetForce, TargetMovement,
+    ThrusterDefinition, Thrusters,
+};
+use egui::{Color32, RichText, TextureId};
+use motor_math::glam::ThrusterGlam;
+
+use crate::{layer_allocator::next_render_layer, layout::DockableWindow, ui::RobotViewUi};
+
+const IMAGE_SIZE: u32 = 512;
+/// Distance of the camera from the robot in meters
+const CAMERA_DISTANCE: f32 = 1.5;
+/// Radians the camera orbits per pixel dragged
+const ORBIT_SPEED: f32 = 0.01;
+
+/// Arrow length in meters per newton
+const FORCE_SCALE: f32 = 0.01;
+/// Arrow length in meters per newton meter
+const TORQUE_SCALE: f32 = 0.05;
+
+/// Used until the robot's thrusters are known
+const DEFAULT_BODY_SIZE: Vec3 = Vec3::new(0.3, 0.45, 0.25);
+
+pub struct RobotViewPlugin;
+
+impl Plugin for RobotViewPlugin {
+    fn build(&self, app: &mut App) {
+        let layer = next_render_layer();
+
+        app.insert_gizmo_config(
+            RobotViewGizmo,
+            GizmoConfig {
+                render_layers: layer.clone(),
+                ..default()
+            },
+        )
+        .insert_resource(RobotViewLayer(layer))
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                activate_camera,
+                update_body,
+                (orbit_camera, draw_forces, robot_view_ui).run_if(resource_exists::<RobotViewUi>),
+            ),
+        );
+    }
+}
+
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct RobotViewGizmo;
+
+#[derive(Resource)]
+struct RobotViewLayer(RenderLayers);
+
+#[derive(Resource)]
+struct RobotView {
+    texture: TextureId,
+    /// Radians about the vertical axis
+    yaw: f32,
+    /// Radians above the horizon
+    pitch: f32,
+}
+
+/// Thrusters drawn on top of the robot, such as a layout that is being edited
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ThrusterPreview(pub Vec<ThrusterGlam>);
+
+#[derive(Component)]
+struct RobotViewCamera;
+
+#[derive(Component)]
+struct RobotViewBody;
+
+fn setup(
+    mut cmds: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut contexts: EguiContexts,
+    layer: Res<RobotViewLayer>,
+) {
+    let size = Extent3d {
+        width: IMAGE_SIZE,
+        height: IMAGE_SIZE,
+        ..default()
+    };
+
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    image.resize(size);
+
+    let image = images.add(image);
+
+    cmds.spawn((
+        Name::new("Robot View Camera"),
+        Camera3d::default(),
+        Camera {
+            order: -1,
+            target: RenderTarget::Image(image.clone()),
+            is_active: false,
+            ..default()
+        },
+        Transform::default(),
+        RobotViewCamera,
+        layer.0.clone(),
+    ));
+
+    cmds.spawn((
+        Name::new("Robot View Light"),
+        PointLight {
+            intensity: 500_000.0,
+            ..default()
+        },
+        Transform::from_xyz(2.0, -2.0, 4.0),
+        layer.0.clone(),
+    ));
+
+    cmds.spawn((
+        Name::new("Robot View Body"),
+        Mesh3d(meshes.add(Cuboid::from_size(DEFAULT_BODY_SIZE))),
+        MeshMaterial3d(materials.add(Color::srgba(0.8, 0.7, 0.6, 0.6))),
+        Transform::default(),
+        RobotViewBody,
+        layer.0.clone(),
+    ));
+
+    let texture = contexts.add_image(image.clone_weak());
+    cmds.insert_resource(RobotView {
+        texture,
+        yaw: 30f32.to_radians(),
+        pitch: 30f32.to_radians(),
+    });
+}
+
+/// Only renders while the window is open
+fn activate_camera(
+    ui: Option<Res<RobotViewUi>>,
+    mut cameras: Query<&mut Camera, With<RobotViewCamera>>,
+) {
+    for mut camera in &mut cameras {
+        if camera.is_active != ui.is_some() {
+            camera.is_active = ui.is_some();
+        }
+    }
+}
+
+/// Sizes the body to enclose the thrusters and follows the robot's orientation
+fn update_body(
+    robot: Query<(Option<&Orientation>, Option<Ref<Thrusters>>), With<Robot>>,
+    mut body: Query<(&mut Transform, &Mesh3d), With<RobotViewBody>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Ok((orientation, thrusters)) = robot.get_single() else {
+        return;
+    };
+
+    for (mut transform, mesh) in &mut body {
+        transform.rotation = orientation.map(|it| it.0).unwrap_or_default();
+
+        let Some(thrusters) = thrusters.as_ref().filter(|it| it.is_changed()) else {
+            continue;
+        };
+
+        let extent = thrusters
+            .0
+            .motors()
+            .map(|(_, motor)| Vec3::from(ThrusterGlam::from(motor).position).abs())
+            .fold(Vec3::ZERO, Vec3::max);
+
+        if let Some(mesh) = meshes.get_mut(&mesh.0) {
+            *mesh = Cuboid::from_size((extent * 2.0).max(Vec3::splat(0.05))).into();
+        }
+    }
+}
+
+fn orbit_camera(view: Res<RobotView>, mut cameras: Query<&mut Transform, With<RobotViewCamera>>) {
+    let position = CAMERA_DISTANCE
+        * Vec3::new(
+            view.pitch.cos() * view.yaw.sin(),
+            -view.pitch.cos() * view.yaw.cos(),
+            view.pitch.sin(),
+        );
+
+    for mut transform in &mut cameras {
+        *transform = Transform::from_translation(position).looking_at(Vec3::ZERO, Vec3::Z);
+    }
+}
+
+fn draw_forces(
+    robot: Query<
+        (
+            Option<&Orientation>,
+            Option<&TargetMovement>,
+            Option<&ActualMovement>,
+        ),
+        With<Robot>,
+    >,
+    thrusters: Query<(
+        &ThrusterDefinition,
+        Option<&TargetForce>,
+        Option<&ActualForce>,
+    )>,
+    preview: Option<Res<ThrusterPreview>>,
+    mut gizmos: Gizmos<RobotViewGizmo>,
+) {
+    // Still draws the preview without a robot
+    let (orientation, target, actual) = robot.get_single().unwrap_or_default();
+    let rotation = orientation.map(|it| it.0).unwrap_or_default();
+
+    // World axes
+    gizmos.arrow(Vec3::ZERO, Vec3::X * 0.5, css::RED);
+    gizmos.arrow(Vec3::ZERO, Vec3::Y * 0.5, css::GREEN);
+    gizmos.arrow(Vec3::ZERO, Vec3::Z * 0.5, css::BLUE);
+
+    for (ThrusterDefinition(_, thruster), target, actual) in &thrusters {
+        let position = rotation * Vec3::from(thruster.position);
+        let direction = rotation * Vec3::from(thruster.orientation);
+
+        if let Some(target) = target {
+            let end = position + direction * target.0 .0 * FORCE_SCALE;
+            gizmos.arrow(position, end, css::GRAY);
+        }
+        if let Some(actual) = actual {
+            let end = position + direction * actual.0 .0 * FORCE_SCALE;
+            gizmos.arrow(position, end, css::ORANGE);
+        }
+    }
+
+    for thruster in preview.iter().flat_map(|it| &it.0) {
+        let position = rotation * Vec3::from(thruster.position);
+        let direction = rotation * Vec3::from(thruster.orientation);
+
+        gizmos.sphere(Isometry3d::from_translation(position), 0.02, css::MAGENTA);
+        gizmos.arrow(position, position + direction * 0.15, css::MAGENTA);
+    }
+
+    for (movement, force_color, torque_color) in [
+        (target.map(|it| it.0), css::YELLOW, css::KHAKI),
+        (actual.map(|it| it.0), css::AQUA, css::STEEL_BLUE),
+    ] {
+        let Some(movement) = movement else {
+            continue;
+        };
+
+        let force = rotation * Vec3::from(movement.force) * FORCE_SCALE;
+        let torque = rotation * Vec3::from(movement.torque) * TORQUE_SCALE;
+
+        gizmos.arrow(Vec3::ZERO, force, force_color);
+        gizmos.arrow(Vec3::ZERO, torque, torque_color);
+    }
+}
+
+fn robot_view_ui(mut cmds: Commands, mut contexts: EguiContexts, mut view: ResMut<RobotView>) {
+    let mut open = true;
+
+    egui::Window::new("Robot View")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Robot View"), |ui| {
+            let size = egui::vec2(IMAGE_SIZE as f32, IMAGE_SIZE as f32);
+            let response = ui.add(
+                egui::Image::new((view.texture, size))
+                    .max_size(size)
+                    .sense(egui::Sense::drag()),
+            );
+
+            let delta = response.drag_delta();
+            view.yaw -= delta.x * ORBIT_SPEED;
+            view.pitch = (view.pitch + delta.y * ORBIT_SPEED).clamp(-1.5, 1.5);
+
+            ui.horizontal_wrapped(|ui| {
+                let legend = [
+                    ("Thruster target", css::GRAY),
+                    ("Thruster actual", css::ORANGE),
+                    ("Target force", css::YELLOW),
+                    ("Target torque", css::KHAKI),
+                    ("Actual force", css::AQUA),
+                    ("Actual torque", css::STEEL_BLUE),
+                    ("Layout preview", css::MAGENTA),
+                ];
+
+                for (label, color) in legend {
+                    let [r, g, b, _] = color.to_u8_array();
+                    ui.label(RichText::new(label).color(Color32::from_rgb(r, g, b)));
+                }
+            });
+        });
+
+    if !open {
+        cmds.remove_resource::<RobotViewUi>();
+    }
+}
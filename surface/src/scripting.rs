@@ -0,0 +1,687 @@
+//! Rhai scripts for automating task sequences without recompiling
+//!
+//! Scripts are loaded from `scripts/` and each run gets its own thread and engine. They can only
+//! touch the robot through the bindings registered here, which read a snapshot of the robot's
+//! telemetry and send commands back to be applied on the main thread. Killing a run stops it at
+//! its next operation and clears any depth or orientation target it set
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{
+        Armed, CurrentDraw, DepthMeasurement, DepthTarget, Heading, Leak, MeasuredVoltage,
+        Orientation, OrientationTarget, Robot,
+    },
+    events::{CalibrateSeaLevel, ResetServos, ResetYaw},
+    types::units::Meters,
+};
+use crossbeam::channel::{self, Receiver, Sender};
+use egui::{Color32, RichText, ScrollArea};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext};
+
+use crate::{layout::DockableWindow, ui::ScriptConsoleUi};
+
+pub const SCRIPT_DIRECTORY: &str = "scripts";
+/// Lines kept in the console
+const CONSOLE_LINES: usize = 500;
+/// How often `sleep` and `wait_until` check whether the run was killed
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Scripting>().add_systems(
+            Update,
+            (
+                update_telemetry,
+                apply_script_commands.after(update_telemetry),
+                script_console.run_if(resource_exists::<ScriptConsoleUi>),
+            ),
+        );
+    }
+}
+
+/// What the bindings can read, copied from the robot every frame
+#[derive(Debug, Clone, Copy, Default)]
+struct Telemetry {
+    connected: bool,
+    armed: bool,
+    leak: bool,
+    depth: Option<f64>,
+    depth_target: Option<f64>,
+    heading: Option<f64>,
+    pitch: Option<f64>,
+    roll: Option<f64>,
+    voltage: Option<f64>,
+    current: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+struct ScriptCommand {
+    run: u64,
+    action: ScriptAction,
+}
+
+#[derive(Debug, Clone)]
+enum ScriptAction {
+    Print(String),
+    Finished(Result<String, String>),
+    Arm(bool),
+    SetDepth(Option<f32>),
+    /// Heading, pitch and roll in degrees
+    SetOrientation(Option<(f32, f32, f32)>),
+    ResetYaw,
+    ResetServos,
+    CalibrateSeaLevel,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunState {
+    Running,
+    Finished(String),
+    Failed(String),
+    Killed,
+}
+
+pub struct ScriptRun {
+    pub id: u64,
+    pub name: String,
+    pub started: Instant,
+    pub state: RunState,
+    killed: Arc<AtomicBool>,
+    /// Targets this run set, cleared if it is killed
+    set_depth: bool,
+    set_orientation: bool,
+}
+
+impl ScriptRun {
+    pub fn kill(&mut self) {
+        self.killed.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Resource)]
+pub struct Scripting {
+    telemetry: Arc<RwLock<Telemetry>>,
+    cmd_tx: Sender<ScriptCommand>,
+    cmd_rx: Receiver<ScriptCommand>,
+
+    pub runs: Vec<ScriptRun>,
+    next_id: u64,
+
+    /// Script files found in `SCRIPT_DIRECTORY`
+    pub scripts: Vec<PathBuf>,
+    pub console: VecDeque<(String, String)>,
+    pub input: String,
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        let (cmd_tx, cmd_rx) = channel::unbounded();
+
+        let mut scripting = Self {
+            telemetry: Default::default(),
+            cmd_tx,
+            cmd_rx,
+            runs: Vec::new(),
+            next_id: 0,
+            scripts: Vec::new(),
+            console: VecDeque::new(),
+            input: String::new(),
+        };
+        scripting.refresh();
+
+        scripting
+    }
+}
+
+impl Scripting {
+    pub fn refresh(&mut self) {
+        let res: anyhow::Result<Vec<PathBuf>> = try {
+            let mut scripts = fs::read_dir(SCRIPT_DIRECTORY)
+                .context("Read script directory")?
+                .filter_map(|it| it.ok().map(|it| it.path()))
+                .filter(|it| it.extension().is_some_and(|it| it == "rhai"))
+                .collect::<Vec<_>>();
+            scripts.sort();
+
+            scripts
+        };
+
+        self.scripts = res.unwrap_or_else(|err| {
+            warn!("Could not load scripts: {err:?}");
+            Vec::new()
+        });
+    }
+
+    pub fn run_file(&mut self, path: PathBuf) {
+        let name = path
+            .file_stem()
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        match fs::read_to_string(&path) {
+            Ok(source) => self.run(name, source),
+            Err(err) => self.log(&name, format!("Could not read {}: {err}", path.display())),
+        }
+    }
+
+    pub fn run(&mut self, name: String, source: String) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let killed = Arc::new(AtomicBool::new(false));
+        let engine = script_engine(id, self.telemetry.clone(), self.cmd_tx.clone(), &killed);
+        let tx = self.cmd_tx.clone();
+
+        let res = thread::Builder::new()
+            .name(format!("Script {name}"))
+            .spawn(move || {
+                let result = match engine.eval::<Dynamic>(&source) {
+                    Ok(value) if value.is_unit() => Ok(String::new()),
+                    Ok(value) => Ok(value.to_string()),
+                    Err(err) if matches!(*err, EvalAltResult::ErrorTerminated(..)) => {
+                        Err("Killed".to_owned())
+                    }
+                    Err(err) => Err(err.to_string()),
+                };
+
+                let _ = tx.send(ScriptCommand {
+                    run: id,
+                    action: ScriptAction::Finished(result),
+                });
+            });
+
+        if let Err(err) = res {
+            self.log(&name, format!("Could not start script: {err}"));
+            return;
+        }
+
+        info!("Started script {name}");
+        self.runs.push(ScriptRun {
+            id,
+            name,
+            started: Instant::now(),
+            state: RunState::Running,
+            killed,
+            set_depth: false,
+            set_orientation: false,
+        });
+    }
+
+    pub fn kill_all(&mut self) {
+        for run in &mut self.runs {
+            run.kill();
+        }
+    }
+
+    fn log(&mut self, name: &str, line: String) {
+        self.console.push_back((name.to_owned(), line));
+        while self.console.len() > CONSOLE_LINES {
+            self.console.pop_front();
+        }
+    }
+}
+
+fn snapshot(telemetry: &RwLock<Telemetry>) -> Telemetry {
+    telemetry.read().map(|it| *it).unwrap_or_default()
+}
+
+/// When `seconds` from now will be, negative times are now
+fn deadline(seconds: f64) -> Result<Instant, Box<EvalAltResult>> {
+    Duration::try_from_secs_f64(seconds.max(0.0))
+        .ok()
+        .and_then(|it| Instant::now().checked_add(it))
+        .ok_or_else(|| format!("Invalid duration of {seconds}s").into())
+}
+
+/// Sleeps until `end` in small steps so a killed run stops promptly
+fn sleep_checked(end: Instant, killed: &AtomicBool) -> Result<(), Box<EvalAltResult>> {
+    while Instant::now() < end {
+        if killed.load(Ordering::Relaxed) {
+            return Err("Killed".into());
+        }
+
+        thread::sleep(POLL_INTERVAL.min(end.saturating_duration_since(Instant::now())));
+    }
+
+    Ok(())
+}
+
+fn script_engine(
+    run: u64,
+    telemetry: Arc<RwLock<Telemetry>>,
+    tx: Sender<ScriptCommand>,
+    killed: &Arc<AtomicBool>,
+) -> Engine {
+    let mut engine = Engine::new();
+
+    // Keeps runaway scripts from taking the surface down with them
+    engine.set_max_call_levels(64);
+    engine.set_max_string_size(1 << 16);
+    engine.set_max_array_size(1 << 16);
+    engine.set_max_map_size(1 << 16);
+
+    {
+        let killed = killed.clone();
+        engine.on_progress(move |_| killed.load(Ordering::Relaxed).then(|| "Killed".into()));
+    }
+
+    let send = move |action| {
+        let _ = tx.send(ScriptCommand { run, action });
+    };
+
+    {
+        let send = send.clone();
+        engine.on_print(move |text| send(ScriptAction::Print(text.to_owned())));
+    }
+    {
+        let send = send.clone();
+        engine.on_debug(move |text, _, position| {
+            send(ScriptAction::Print(format!("[{position}] {text}")));
+        });
+    }
+
+    let readings: [(&'static str, fn(&Telemetry) -> Option<f64>); 7] = [
+        ("depth", |it| it.depth),
+        ("depth_target", |it| it.depth_target),
+        ("heading", |it| it.heading),
+        ("pitch", |it| it.pitch),
+        ("roll", |it| it.roll),
+        ("voltage", |it| it.voltage),
+        ("current", |it| it.current),
+    ];
+    for (name, reading) in readings {
+        let telemetry = telemetry.clone();
+        engine.register_fn(name, move || -> Result<f64, Box<EvalAltResult>> {
+            reading(&snapshot(&telemetry)).ok_or_else(|| format!("No {name} reading").into())
+        });
+    }
+
+    let flags: [(&'static str, fn(&Telemetry) -> bool); 3] = [
+        ("connected", |it| it.connected),
+        ("armed", |it| it.armed),
+        ("leak", |it| it.leak),
+    ];
+    for (name, flag) in flags {
+        let telemetry = telemetry.clone();
+        engine.register_fn(name, move || flag(&snapshot(&telemetry)));
+    }
+
+    let actions: [(&'static str, ScriptAction); 7] = [
+        ("arm", ScriptAction::Arm(true)),
+        ("disarm", ScriptAction::Arm(false)),
+        ("clear_depth", ScriptAction::SetDepth(None)),
+        ("clear_orientation", ScriptAction::SetOrientation(None)),
+        ("reset_yaw", ScriptAction::ResetYaw),
+        ("reset_servos", ScriptAction::ResetServos),
+        ("calibrate_sea_level", ScriptAction::CalibrateSeaLevel),
+    ];
+    for (name, action) in actions {
+        let send = send.clone();
+        engine.register_fn(name, move || send(action.clone()));
+    }
+
+    {
+        let send = send.clone();
+        engine.register_fn("set_depth", move |depth: f64| {
+            send(ScriptAction::SetDepth(Some(depth as f32)));
+        });
+    }
+    {
+        let send = send.clone();
+        engine.register_fn("set_heading", move |heading: f64| {
+            send(ScriptAction::SetOrientation(Some((
+                heading as f32,
+                0.0,
+                0.0,
+            ))));
+        });
+    }
+    {
+        let send = send.clone();
+        engine.register_fn(
+            "set_orientation",
+            move |heading: f64, pitch: f64, roll: f64| {
+                send(ScriptAction::SetOrientation(Some((
+                    heading as f32,
+                    pitch as f32,
+                    roll as f32,
+                ))));
+            },
+        );
+    }
+
+    {
+        let killed = killed.clone();
+        engine.register_fn("sleep", move |seconds: f64| {
+            sleep_checked(deadline(seconds)?, &killed)
+        });
+    }
+    {
+        let killed = killed.clone();
+        engine.register_fn(
+            "wait_until",
+            move |context: NativeCallContext,
+                  condition: FnPtr,
+                  timeout: f64|
+                  -> Result<bool, Box<EvalAltResult>> {
+                let end = deadline(timeout)?;
+
+                loop {
+                    if condition.call_within_context::<bool>(&context, ())? {
+                        return Ok(true);
+                    }
+                    if Instant::now() >= end {
+                        return Ok(false);
+                    }
+
+                    sleep_checked(Instant::now() + POLL_INTERVAL, &killed)?;
+                }
+            },
+        );
+    }
+
+    engine
+}
+
+fn update_telemetry(
+    scripting: Res<Scripting>,
+    robot: Query<
+        (
+            &Armed,
+            Option<&DepthMeasurement>,
+            Option<&DepthTarget>,
+            Option<&Orientation>,
+            Option<&Heading>,
+            Option<&MeasuredVoltage>,
+            Option<&CurrentDraw>,
+            Option<&Leak>,
+        ),
+        With<Robot>,
+    >,
+) {
+    let telemetry = match robot.get_single() {
+        Ok((armed, depth, depth_target, orientation, heading, voltage, current, leak)) => {
+            let (_, pitch, roll) = orientation
+                .map(|it| it.yaw_pitch_roll())
+                .unwrap_or_default();
+            let heading = heading
+                .copied()
+                .or_else(|| orientation.map(|it| it.heading()));
+
+            Telemetry {
+                connected: true,
+                armed: *armed == Armed::Armed,
+                leak: leak.is_some_and(|it| it.0),
+                depth: depth.map(|it| it.depth.0 as f64),
+                depth_target: depth_target.map(|it| it.0 .0 as f64),
+                heading: heading.map(|it| it.0 .0 as f64),
+                pitch: orientation.map(|_| pitch as f64),
+                roll: orientation.map(|_| roll as f64),
+                voltage: voltage.map(|it| it.0 .0 as f64),
+                current: current.map(|it| it.0 .0 as f64),
+            }
+        }
+        Err(_) => Telemetry::default(),
+    };
+
+    if let Ok(mut lock) = scripting.telemetry.write() {
+        *lock = telemetry;
+    }
+}
+
+fn apply_script_commands(
+    mut cmds: Commands,
+    mut scripting: ResMut<Scripting>,
+    robot: Query<(Entity, Option<&Orientation>, Option<&Heading>), With<Robot>>,
+) {
+    let scripting = &mut *scripting;
+    let robot = robot.get_single().ok();
+
+    for ScriptCommand { run: id, action } in scripting.cmd_rx.try_iter().collect::<Vec<_>>() {
+        let Some(run) = scripting.runs.iter_mut().find(|it| it.id == id) else {
+            continue;
+        };
+        let name = run.name.clone();
+
+        match action {
+            ScriptAction::Print(line) => {
+                scripting.log(&name, line);
+                continue;
+            }
+            ScriptAction::Finished(result) => {
+                let killed = run.killed.load(Ordering::Relaxed);
+
+                run.state = match result {
+                    _ if killed => RunState::Killed,
+                    Ok(value) => RunState::Finished(value),
+                    Err(err) => RunState::Failed(err),
+                };
+
+                // Leave nothing behind that the pilot didn't ask for
+                if let Some((robot, ..)) = robot.filter(|_| killed) {
+                    if run.set_depth {
+                        cmds.entity(robot).remove::<DepthTarget>();
+                    }
+                    if run.set_orientation {
+                        cmds.entity(robot).remove::<OrientationTarget>();
+                    }
+                }
+
+                let line = match &run.state {
+                    RunState::Finished(value) if value.is_empty() => "Finished".to_owned(),
+                    RunState::Finished(value) => format!("Finished: {value}"),
+                    RunState::Failed(err) => format!("Failed: {err}"),
+                    _ => "Killed".to_owned(),
+                };
+                info!("Script {name} {line}");
+                scripting.log(&name, line);
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some((robot, orientation, heading)) = robot else {
+            scripting.log(&name, "No robot connected".to_owned());
+            continue;
+        };
+
+        match action {
+            ScriptAction::Arm(true) => {
+                cmds.entity(robot).insert(Armed::Armed);
+            }
+            ScriptAction::Arm(false) => {
+                cmds.entity(robot).insert(Armed::Disarmed);
+            }
+            ScriptAction::SetDepth(Some(depth)) => {
+                run.set_depth = true;
+                cmds.entity(robot).insert(DepthTarget(Meters(depth)));
+            }
+            ScriptAction::SetDepth(None) => {
+                cmds.entity(robot).remove::<DepthTarget>();
+            }
+            ScriptAction::SetOrientation(Some((target_heading, pitch, roll))) => {
+                let Some(orientation) = orientation else {
+                    scripting.log(&name, "No orientation to turn from".to_owned());
+                    continue;
+                };
+
+                // The compass heading can be offset from the orientation's yaw, so turn by the
+                // difference instead of using the heading directly
+                let current_heading = heading.copied().unwrap_or_else(|| orientation.heading());
+                let (yaw, _, _) = orientation.yaw_pitch_roll();
+                let yaw = yaw - (target_heading - current_heading.0 .0);
+
+                run.set_orientation = true;
+                cmds.entity(robot)
+                    .insert(OrientationTarget(Quat::from_euler(
+                        EulerRot::ZXY,
+                        yaw.to_radians(),
+                        pitch.to_radians(),
+                        roll.to_radians(),
+                    )));
+            }
+            ScriptAction::SetOrientation(None) => {
+                cmds.entity(robot).remove::<OrientationTarget>();
+            }
+            ScriptAction::ResetYaw => {
+                cmds.queue(|world: &mut World| {
+                    world.send_event(ResetYaw);
+                });
+            }
+            ScriptAction::ResetServos => {
+                cmds.queue(|world: &mut World| {
+                    world.send_event(ResetServos);
+                });
+            }
+            ScriptAction::CalibrateSeaLevel => {
+                cmds.queue(|world: &mut World| {
+                    world.send_event(CalibrateSeaLevel);
+                });
+            }
+            ScriptAction::Print(_) | ScriptAction::Finished(_) => {}
+        }
+    }
+}
+
+fn script_console(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut scripting: ResMut<Scripting>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Script Console")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Script Console"), |ui| {
+            let scripting = &mut *scripting;
+            let running = scripting
+                .runs
+                .iter()
+                .any(|it| it.state == RunState::Running);
+
+            ui.horizontal(|ui| {
+                if ui.button("Refresh").clicked() {
+                    scripting.refresh();
+                }
+
+                let kill = egui::Button::new(RichText::new("Kill All").color(Color32::WHITE))
+                    .fill(Color32::DARK_RED);
+                if ui.add_enabled(running, kill).clicked() {
+                    scripting.kill_all();
+                }
+            });
+
+            ui.separator();
+
+            if scripting.scripts.is_empty() {
+                ui.label(format!("No scripts in {SCRIPT_DIRECTORY}/"));
+            }
+
+            let mut start = None;
+            egui::Grid::new("Scripts").striped(true).show(ui, |ui| {
+                for path in &scripting.scripts {
+                    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+
+                    ui.label(name.as_ref());
+                    if ui.button("Run").clicked() {
+                        start = Some(path.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(path) = start {
+                scripting.run_file(path);
+            }
+
+            if !scripting.runs.is_empty() {
+                ui.separator();
+
+                egui::Grid::new("Script Runs").striped(true).show(ui, |ui| {
+                    for run in &mut scripting.runs {
+                        ui.label(run.name.as_str());
+
+                        match &run.state {
+                            RunState::Running => {
+                                let elapsed = run.started.elapsed().as_secs();
+                                ui.label(
+                                    RichText::new(format!(
+                                        "Running {:02}:{:02}",
+                                        elapsed / 60,
+                                        elapsed % 60
+                                    ))
+                                    .color(Color32::YELLOW),
+                                );
+
+                                if ui.button("Kill").clicked() {
+                                    run.kill();
+                                }
+                            }
+                            RunState::Finished(_) => {
+                                ui.label(RichText::new("Finished").color(Color32::GREEN));
+                                ui.label("");
+                            }
+                            RunState::Failed(err) => {
+                                ui.label(RichText::new("Failed").color(Color32::RED))
+                                    .on_hover_text(err.as_str());
+                                ui.label("");
+                            }
+                            RunState::Killed => {
+                                ui.label(RichText::new("Killed").color(Color32::ORANGE));
+                                ui.label("");
+                            }
+                        }
+
+                        ui.end_row();
+                    }
+                });
+
+                if ui.button("Clear Finished").clicked() {
+                    scripting.runs.retain(|it| it.state == RunState::Running);
+                }
+            }
+
+            ui.separator();
+
+            ScrollArea::vertical()
+                .max_height(250.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for (name, line) in &scripting.console {
+                        ui.label(RichText::new(format!("{name}: {line}")).monospace());
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                let response = ui.text_edit_singleline(&mut scripting.input);
+                let submitted =
+                    response.lost_focus() && ui.input(|it| it.key_pressed(egui::Key::Enter));
+
+                if (ui.button("Eval").clicked() || submitted) && !scripting.input.trim().is_empty()
+                {
+                    let source = std::mem::take(&mut scripting.input);
+                    scripting.log("Console", format!("> {source}"));
+                    scripting.run("Console".to_owned(), source);
+                }
+            });
+        });
+
+    if !open {
+        cmds.remove_resource::<ScriptConsoleUi>();
+    }
+}
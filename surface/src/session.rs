@@ -0,0 +1,393 @@
+//! Named dives, every artifact saved while a session is active goes into the session's directory
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::error::ErrorEvent;
+use egui::{Color32, RichText};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Iso8601;
+
+use crate::{
+    dive_log::{self, DiveLog},
+    layout::DockableWindow,
+    ui::SessionUi,
+    wall_clock::now,
+};
+
+pub const SESSION_DIRECTORY: &str = "sessions";
+const METADATA_FILE: &str = "session.toml";
+
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SessionForm>()
+            .init_resource::<SessionBrowser>()
+            .add_event::<StartSession>()
+            .add_event::<EndSession>()
+            .add_systems(
+                Update,
+                (
+                    (end_session, start_session).chain(),
+                    session_ui.run_if(resource_exists::<SessionUi>),
+                    refresh_browser.run_if(
+                        resource_added::<SessionUi>
+                            .or(|browser: Res<SessionBrowser>| browser.stale),
+                    ),
+                ),
+            );
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SessionMetadata {
+    pub name: String,
+    pub pilot: String,
+    pub location: String,
+    pub notes: String,
+    /// Iso8601 local time
+    pub started: String,
+    pub ended: Option<String>,
+}
+
+/// The session artifacts are currently saved into
+#[derive(Resource, Debug, Clone)]
+pub struct ActiveSession {
+    pub metadata: SessionMetadata,
+    pub directory: PathBuf,
+    pub started: Instant,
+}
+
+/// Where artifacts that normally go in `directory` should be saved, inside the active session if
+/// there is one
+pub fn artifact_directory(session: Option<&ActiveSession>, directory: &str) -> PathBuf {
+    match session {
+        Some(session) => session.directory.join(directory),
+        None => PathBuf::from(directory),
+    }
+}
+
+/// Ends the active session first if there is one
+#[derive(Event, Debug, Clone)]
+pub struct StartSession {
+    pub name: String,
+    pub pilot: String,
+    pub location: String,
+    pub notes: String,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EndSession;
+
+#[derive(Resource, Default)]
+struct SessionForm {
+    name: String,
+    pilot: String,
+    location: String,
+    notes: String,
+}
+
+#[derive(Resource, Default)]
+struct SessionBrowser {
+    sessions: Vec<PastSession>,
+    selected: Option<PathBuf>,
+    stale: bool,
+}
+
+struct PastSession {
+    directory: PathBuf,
+    metadata: SessionMetadata,
+    /// Number of files in each artifact directory
+    artifacts: Vec<(String, usize)>,
+}
+
+fn write_metadata(directory: &Path, metadata: &SessionMetadata) -> anyhow::Result<()> {
+    let metadata = toml::to_string_pretty(metadata).context("Serialize session metadata")?;
+    fs::write(directory.join(METADATA_FILE), metadata).context("Write session metadata")
+}
+
+fn start_session(
+    mut cmds: Commands,
+    mut events: EventReader<StartSession>,
+    mut dive_log: ResMut<DiveLog>,
+    mut browser: ResMut<SessionBrowser>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    // Only the last one matters if several were sent at once
+    let Some(event) = events.read().last() else {
+        return;
+    };
+
+    let res: anyhow::Result<()> = try {
+        let started = now().format(&Iso8601::DATE_TIME).context("Format time")?;
+        let slug = event
+            .name
+            .trim()
+            .replace(|it: char| !it.is_alphanumeric() && it != '-', "_");
+        let directory = PathBuf::from(format!("{SESSION_DIRECTORY}/{started}_{slug}"));
+
+        fs::create_dir_all(&directory).context("Create session directory")?;
+
+        let metadata = SessionMetadata {
+            name: event.name.clone(),
+            pilot: event.pilot.clone(),
+            location: event.location.clone(),
+            notes: event.notes.clone(),
+            started,
+            ended: None,
+        };
+        write_metadata(&directory, &metadata)?;
+
+        info!("Started session {:?} in {directory:?}", metadata.name);
+
+        // The dive log covers exactly one session
+        *dive_log = DiveLog::default();
+
+        cmds.insert_resource(ActiveSession {
+            metadata,
+            directory,
+            started: Instant::now(),
+        });
+        browser.stale = true;
+    };
+
+    if let Err(err) = res {
+        errors.send(err.context("Start session").into());
+    }
+}
+
+fn end_session(
+    mut cmds: Commands,
+    mut events: EventReader<EndSession>,
+    mut start: EventReader<StartSession>,
+    session: Option<ResMut<ActiveSession>>,
+    dive_log: Res<DiveLog>,
+    mut browser: ResMut<SessionBrowser>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let ending = events.read().count() > 0 || !start.is_empty();
+    // Leave the start events for `start_session`
+    start.clear();
+
+    let Some(mut session) = session.filter(|_| ending) else {
+        return;
+    };
+
+    let res: anyhow::Result<()> = try {
+        session.metadata.ended = Some(now().format(&Iso8601::DATE_TIME).context("Format time")?);
+        write_metadata(&session.directory, &session.metadata)?;
+
+        let directory = session.directory.join(dive_log::DIVE_LOG_DIRECTORY);
+        fs::create_dir_all(&directory).context("Create dive log directory")?;
+        fs::write(directory.join("dive.txt"), dive_log.summary()).context("Write dive log")?;
+
+        info!("Ended session {:?}", session.metadata.name);
+    };
+
+    if let Err(err) = res {
+        errors.send(err.context("End session").into());
+    }
+
+    cmds.remove_resource::<ActiveSession>();
+    browser.stale = true;
+}
+
+fn refresh_browser(mut browser: ResMut<SessionBrowser>, mut errors: EventWriter<ErrorEvent>) {
+    browser.stale = false;
+
+    let res: anyhow::Result<()> = try {
+        browser.sessions.clear();
+
+        let Ok(entries) = fs::read_dir(SESSION_DIRECTORY) else {
+            // No sessions yet
+            return;
+        };
+
+        for entry in entries {
+            let directory = entry.context("Read session directory")?.path();
+
+            let Ok(metadata) = fs::read_to_string(directory.join(METADATA_FILE)) else {
+                continue;
+            };
+            let metadata = match toml::from_str(&metadata) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    warn!("Bad session metadata in {directory:?}: {err}");
+                    continue;
+                }
+            };
+
+            let mut artifacts = fs::read_dir(&directory)
+                .context("Read session")?
+                .flatten()
+                .filter(|it| it.path().is_dir())
+                .map(|it| {
+                    let count = fs::read_dir(it.path()).map(|it| it.count()).unwrap_or(0);
+                    (it.file_name().to_string_lossy().into_owned(), count)
+                })
+                .collect::<Vec<_>>();
+            artifacts.sort();
+
+            browser.sessions.push(PastSession {
+                directory,
+                metadata,
+                artifacts,
+            });
+        }
+
+        // Newest first
+        browser
+            .sessions
+            .sort_by(|a, b| b.metadata.started.cmp(&a.metadata.started));
+    };
+
+    if let Err(err) = res {
+        errors.send(err.context("List sessions").into());
+    }
+}
+
+fn session_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut form: ResMut<SessionForm>,
+    mut browser: ResMut<SessionBrowser>,
+    session: Option<Res<ActiveSession>>,
+    mut start: EventWriter<StartSession>,
+    mut end: EventWriter<EndSession>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Sessions").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        egui::Id::new("Sessions"),
+        |ui| {
+            if let Some(session) = &session {
+                ui.label(
+                    RichText::new(format!("Active: {}", session.metadata.name))
+                        .strong()
+                        .color(Color32::GREEN),
+                );
+                ui.label(format!(
+                    "Elapsed: {}",
+                    dive_log::format_secs(session.started.elapsed().as_secs_f64())
+                ));
+                ui.label(format!("Saving to {}", session.directory.display()));
+
+                if ui.button("End Session").clicked() {
+                    end.send(EndSession);
+                }
+            } else {
+                egui::Grid::new("Session Form")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Dive Name");
+                        ui.text_edit_singleline(&mut form.name);
+                        ui.end_row();
+
+                        ui.label("Pilot");
+                        ui.text_edit_singleline(&mut form.pilot);
+                        ui.end_row();
+
+                        ui.label("Location");
+                        ui.text_edit_singleline(&mut form.location);
+                        ui.end_row();
+
+                        ui.label("Notes");
+                        ui.text_edit_multiline(&mut form.notes);
+                        ui.end_row();
+                    });
+
+                let named = !form.name.trim().is_empty();
+                if ui
+                    .add_enabled(named, egui::Button::new("Start Session"))
+                    .clicked()
+                {
+                    start.send(StartSession {
+                        name: form.name.trim().to_owned(),
+                        pilot: form.pilot.clone(),
+                        location: form.location.clone(),
+                        notes: form.notes.clone(),
+                    });
+                    form.name.clear();
+                    form.notes.clear();
+                }
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.heading("Past Sessions");
+                if ui.button("Refresh").clicked() {
+                    browser.stale = true;
+                }
+            });
+
+            let browser = &mut *browser;
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for past in &browser.sessions {
+                        let selected = browser.selected.as_ref() == Some(&past.directory);
+                        let label = format!("{} - {}", past.metadata.started, past.metadata.name);
+
+                        if ui.selectable_label(selected, label).clicked() {
+                            browser.selected = (!selected).then(|| past.directory.clone());
+                        }
+                    }
+
+                    if browser.sessions.is_empty() {
+                        ui.label("No sessions");
+                    }
+                });
+
+            let selected = browser
+                .sessions
+                .iter()
+                .find(|it| browser.selected.as_ref() == Some(&it.directory));
+            if let Some(past) = selected {
+                ui.separator();
+
+                let metadata = &past.metadata;
+                egui::Grid::new("Session Details")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        let ended = metadata.ended.as_deref().unwrap_or("Not ended");
+                        let fields = [
+                            ("Pilot", metadata.pilot.as_str()),
+                            ("Location", metadata.location.as_str()),
+                            ("Started", metadata.started.as_str()),
+                            ("Ended", ended),
+                        ];
+
+                        for (label, value) in fields {
+                            ui.label(label);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+
+                        for (artifact, count) in &past.artifacts {
+                            ui.label(artifact);
+                            ui.label(format!("{count} files"));
+                            ui.end_row();
+                        }
+                    });
+
+                if !metadata.notes.is_empty() {
+                    ui.label(&metadata.notes);
+                }
+                ui.label(format!("{}", past.directory.display()));
+            }
+        },
+    );
+
+    if !open {
+        cmds.remove_resource::<SessionUi>();
+    }
+}
@@ -0,0 +1,576 @@
+//! Control station settings and where persisted files live
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_tokio_tasks::TokioTasksRuntime;
+use common::{
+    components::Robot,
+    sync::{ConnectToPeer, Latency, MdnsPeers},
+    types::units::{ElectricalUnit, LengthUnit, TemperatureUnit, UnitPreferences},
+};
+use egui::{Color32, Stroke, Visuals};
+use serde::{Deserialize, Serialize};
+use tokio::net::lookup_host;
+
+use crate::{
+    bindings::BindingProfiles,
+    layout::DockableWindow,
+    ui::SettingsUi,
+    video_display_2d_master::{CameraLayoutEvent, CameraLayoutMode, CameraLayouts},
+};
+
+pub const SETTINGS_FILE: &str = "settings.toml";
+/// Created in the platform's config directory
+const APP_DIRECTORY: &str = "robocode";
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, load_settings)
+            .add_systems(
+                Update,
+                (
+                    apply_theme.run_if(resource_changed::<SurfaceSettings>),
+                    auto_connect,
+                    settings_ui.run_if(resource_exists::<SettingsUi>),
+                ),
+            )
+            .add_systems(Last, write_settings);
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SurfaceSettings {
+    pub theme: Theme,
+    /// The host last typed into the connect box or picked from the discovered peers
+    pub last_host: Option<String>,
+    pub auto_connect: AutoConnect,
+    pub connection: ConnectionThresholds,
+    /// Pushed to the robot from the robot update window
+    pub robot_binary: String,
+    /// Must match the `update.key` in the robot's config when it has one
+    pub update_key: Option<String>,
+}
+
+impl Default for SurfaceSettings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Light,
+            last_host: None,
+            auto_connect: AutoConnect::Off,
+            connection: ConnectionThresholds::default(),
+            robot_binary: "target/aarch64-unknown-linux-gnu/debug/robot".to_owned(),
+            update_key: None,
+        }
+    }
+}
+
+/// Where the connection indicator turns yellow and red, the adaptive video quality controller
+/// treats a red link as saturated
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ConnectionThresholds {
+    pub degraded_rtt_ms: f32,
+    pub bad_rtt_ms: f32,
+    pub degraded_jitter_ms: f32,
+    pub bad_jitter_ms: f32,
+    /// Fraction of recent heartbeats missed
+    pub degraded_loss: f32,
+    pub bad_loss: f32,
+}
+
+impl Default for ConnectionThresholds {
+    fn default() -> Self {
+        Self {
+            degraded_rtt_ms: 40.0,
+            bad_rtt_ms: 120.0,
+            degraded_jitter_ms: 10.0,
+            bad_jitter_ms: 30.0,
+            degraded_loss: 0.05,
+            bad_loss: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkGrade {
+    Good,
+    Degraded,
+    Bad,
+}
+
+impl ConnectionThresholds {
+    pub fn grade(&self, latency: &Latency) -> LinkGrade {
+        let rtt = latency
+            .rtt
+            .map(|it| it.as_secs_f32() * 1000.0)
+            .unwrap_or(0.0);
+        let jitter = latency.jitter.as_secs_f32() * 1000.0;
+        let loss = latency.loss();
+
+        let grade = |value: f32, degraded: f32, bad: f32| {
+            if value >= bad {
+                LinkGrade::Bad
+            } else if value >= degraded {
+                LinkGrade::Degraded
+            } else {
+                LinkGrade::Good
+            }
+        };
+
+        grade(rtt, self.degraded_rtt_ms, self.bad_rtt_ms)
+            .max(grade(jitter, self.degraded_jitter_ms, self.bad_jitter_ms))
+            .max(grade(loss, self.degraded_loss, self.bad_loss))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+    /// Black on white with heavy strokes, readable in direct sunlight
+    HighContrast,
+}
+
+/// The colors that change with the theme, anything drawn outside of egui's widgets should pick
+/// its colors from here
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// Behind the cameras and the attitude display
+    pub background: Color,
+    /// Plot line colors, a plot with several series uses them in order
+    pub series: [Color32; 6],
+    pub stroke_width: f32,
+    /// Brightness of the attitude display's point light
+    pub light_intensity: f32,
+    /// Multiplier on the default ambient light
+    pub ambient_scale: f32,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Light, Theme::Dark, Theme::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+            Theme::HighContrast => "High Contrast",
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        match self {
+            Theme::Light => Palette {
+                background: Color::srgb_u8(240, 238, 233),
+                series: [
+                    Color32::BROWN,
+                    Color32::from_rgb(200, 120, 0),
+                    Color32::BLACK,
+                    Color32::RED,
+                    Color32::GREEN,
+                    Color32::BLUE,
+                ],
+                stroke_width: 1.5,
+                light_intensity: 4_000_000.0,
+                ambient_scale: 7.0,
+            },
+            Theme::Dark => Palette {
+                background: Color::srgb_u8(33, 34, 37),
+                series: [
+                    Color32::from_rgb(210, 150, 90),
+                    Color32::ORANGE,
+                    Color32::WHITE,
+                    Color32::LIGHT_RED,
+                    Color32::LIGHT_GREEN,
+                    Color32::LIGHT_BLUE,
+                ],
+                stroke_width: 1.5,
+                light_intensity: 1_000_000.0,
+                ambient_scale: 1.0,
+            },
+            Theme::HighContrast => Palette {
+                background: Color::WHITE,
+                series: [
+                    Color32::from_rgb(120, 50, 0),
+                    Color32::from_rgb(230, 100, 0),
+                    Color32::BLACK,
+                    Color32::from_rgb(220, 0, 0),
+                    Color32::from_rgb(0, 130, 0),
+                    Color32::from_rgb(0, 0, 220),
+                ],
+                stroke_width: 3.0,
+                light_intensity: 4_000_000.0,
+                ambient_scale: 7.0,
+            },
+        }
+    }
+
+    pub fn visuals(&self) -> Visuals {
+        match self {
+            Theme::Light => Visuals::light(),
+            Theme::Dark => Visuals::dark(),
+            Theme::HighContrast => {
+                let strong = Stroke::new(2.0, Color32::BLACK);
+
+                let mut visuals = Visuals::light();
+                visuals.override_text_color = Some(Color32::BLACK);
+                visuals.panel_fill = Color32::WHITE;
+                visuals.window_fill = Color32::WHITE;
+                visuals.extreme_bg_color = Color32::WHITE;
+                visuals.window_stroke = strong;
+                visuals.widgets.noninteractive.bg_stroke = strong;
+                visuals.widgets.noninteractive.fg_stroke = strong;
+                visuals.widgets.inactive.bg_stroke = strong;
+                visuals.widgets.inactive.fg_stroke = strong;
+
+                visuals
+            }
+        }
+    }
+}
+
+/// What the control station connects to on its own at launch
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoConnect {
+    Off,
+    LastHost,
+    /// The first robot found through mdns
+    FirstDiscovered,
+}
+
+impl AutoConnect {
+    pub const ALL: [AutoConnect; 3] = [
+        AutoConnect::Off,
+        AutoConnect::LastHost,
+        AutoConnect::FirstDiscovered,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutoConnect::Off => "Off",
+            AutoConnect::LastHost => "Last Host",
+            AutoConnect::FirstDiscovered => "First Discovered",
+        }
+    }
+}
+
+/// The platform's config directory, falls back to the working directory if it can't be found
+pub fn config_dir() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|it| PathBuf::from(it).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|it| PathBuf::from(it).join(".config")))
+    };
+
+    base.map(|it| it.join(APP_DIRECTORY))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Where a persisted file lives, files older versions left in the working directory are moved
+/// into the config directory
+pub fn config_path(file: &str) -> PathBuf {
+    let directory = config_dir();
+    let path = directory.join(file);
+
+    if let Err(err) = fs::create_dir_all(&directory) {
+        warn!("Could not create config directory {directory:?}, using {file}: {err}");
+        return PathBuf::from(file);
+    }
+
+    let legacy = Path::new(file);
+    if !path.exists() && legacy.exists() {
+        let res = fs::copy(legacy, &path).and_then(|_| fs::remove_file(legacy));
+
+        match res {
+            Ok(()) => info!("Moved {file} to {path:?}"),
+            Err(err) => warn!("Could not move {file} to {path:?}: {err}"),
+        }
+    }
+
+    path
+}
+
+/// Resolves `host` and connects to it unless a robot is already connected
+pub fn connect_to_host(runtime: &TokioTasksRuntime, host: String) {
+    runtime.spawn_background_task(|mut ctx| async move {
+        let resolve = lookup_host(host).await;
+        let addrs = resolve.ok().and_then(|mut it| it.next());
+
+        if let Some(addrs) = addrs {
+            ctx.run_on_main_thread(move |ctx| {
+                let world = ctx.world;
+                let count = world.query::<&Robot>().iter(world).count();
+
+                if count == 0 {
+                    info!("Peer ip resolved to {:?}", addrs);
+                    world.send_event(ConnectToPeer(addrs));
+                } else {
+                    warn!("Already connected to peer");
+                }
+            })
+            .await;
+        } else {
+            error!("Could not resolve host");
+        }
+    });
+}
+
+fn load_settings(mut cmds: Commands) {
+    let res: anyhow::Result<SurfaceSettings> = try {
+        let settings = fs::read_to_string(config_path(SETTINGS_FILE)).context("Read settings")?;
+        toml::from_str(&settings).context("Parse settings")?
+    };
+
+    let settings = match res {
+        Ok(settings) => settings,
+        Err(err) => {
+            warn!("Using default settings: {err:?}");
+            SurfaceSettings::default()
+        }
+    };
+
+    cmds.insert_resource(ClearColor(settings.theme.palette().background));
+    cmds.insert_resource(settings);
+}
+
+fn write_settings(settings: Res<SurfaceSettings>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*settings) else {
+        error!("Could not serialize settings");
+        return;
+    };
+
+    let res = fs::write(config_path(SETTINGS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write settings: {err:?}");
+    }
+}
+
+fn apply_theme(
+    mut contexts: EguiContexts,
+    settings: Res<SurfaceSettings>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    contexts.ctx_mut().set_visuals(settings.theme.visuals());
+    clear_color.0 = settings.theme.palette().background;
+}
+
+fn auto_connect(
+    mut attempted: Local<bool>,
+    settings: Res<SurfaceSettings>,
+    runtime: Res<TokioTasksRuntime>,
+    peers: Option<Res<MdnsPeers>>,
+    robots: Query<(), With<Robot>>,
+    mut connect: EventWriter<ConnectToPeer>,
+) {
+    // Only on launch, a robot that drops out shouldn't be reconnected behind the pilot's back
+    if *attempted || !robots.is_empty() {
+        *attempted = true;
+        return;
+    }
+
+    match settings.auto_connect {
+        AutoConnect::Off => {
+            *attempted = true;
+        }
+        AutoConnect::LastHost => {
+            *attempted = true;
+
+            if let Some(host) = settings.last_host.clone() {
+                info!("Auto connecting to {host}");
+                connect_to_host(&runtime, host);
+            }
+        }
+        AutoConnect::FirstDiscovered => {
+            let addrs = peers
+                .iter()
+                .flat_map(|it| it.0.values())
+                .flat_map(|it| it.addresses.iter())
+                .next();
+
+            if let Some(&addrs) = addrs {
+                info!("Auto connecting to discovered peer {addrs}");
+                connect.send(ConnectToPeer(addrs));
+                *attempted = true;
+            }
+        }
+    }
+}
+
+fn settings_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<SurfaceSettings>,
+    mut units: ResMut<UnitPreferences>,
+    mut bindings: ResMut<BindingProfiles>,
+    camera_layouts: Res<CameraLayouts>,
+    mut camera_layout: EventWriter<CameraLayoutEvent>,
+) {
+    let mut open = true;
+
+    let mut edited = settings.clone();
+    let mut edited_units = *units;
+    let mut profile = bindings.active.clone();
+    let mut layout_mode = camera_layouts.current().mode;
+
+    egui::Window::new("Settings").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        egui::Id::new("Settings"),
+        |ui| {
+            ui.label(format!("Saved in {}", config_dir().display()));
+
+            ui.heading("Appearance");
+            egui::Grid::new("Appearance Settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Theme");
+                    ui.horizontal(|ui| {
+                        for theme in Theme::ALL {
+                            ui.selectable_value(&mut edited.theme, theme, theme.name());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Camera Layout");
+                    egui::ComboBox::from_id_salt("Camera Layout Setting")
+                        .selected_text(layout_mode.name())
+                        .show_ui(ui, |ui| {
+                            for mode in CameraLayoutMode::ALL {
+                                ui.selectable_value(&mut layout_mode, mode, mode.name());
+                            }
+                        });
+                    ui.end_row();
+                });
+
+            ui.separator();
+            ui.heading("Units");
+            egui::Grid::new("Unit Settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Length");
+                    ui.horizontal(|ui| {
+                        for unit in LengthUnit::ALL {
+                            ui.selectable_value(&mut edited_units.length, unit, unit.name());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Temperature");
+                    ui.horizontal(|ui| {
+                        for unit in TemperatureUnit::ALL {
+                            ui.selectable_value(&mut edited_units.temperature, unit, unit.name());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Current Draw");
+                    ui.horizontal(|ui| {
+                        for unit in ElectricalUnit::ALL {
+                            ui.selectable_value(&mut edited_units.electrical, unit, unit.name());
+                        }
+                    });
+                    ui.end_row();
+                });
+
+            ui.separator();
+            ui.heading("Input");
+            egui::Grid::new("Input Settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Binding Profile");
+                    egui::ComboBox::from_id_salt("Binding Profile Setting")
+                        .selected_text(profile.as_str())
+                        .show_ui(ui, |ui| {
+                            for name in bindings.profiles.keys() {
+                                ui.selectable_value(&mut profile, name.clone(), name.as_str());
+                            }
+                        });
+                    ui.end_row();
+                });
+
+            ui.separator();
+            ui.heading("Connection");
+            egui::Grid::new("Connection Settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Auto Connect");
+                    ui.horizontal(|ui| {
+                        for auto_connect in AutoConnect::ALL {
+                            ui.selectable_value(
+                                &mut edited.auto_connect,
+                                auto_connect,
+                                auto_connect.name(),
+                            );
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Last Host");
+                    ui.label(edited.last_host.as_deref().unwrap_or("None"));
+                    ui.end_row();
+                });
+
+            ui.label("Connection quality thresholds, degraded and bad");
+            egui::Grid::new("Connection Thresholds")
+                .num_columns(3)
+                .show(ui, |ui| {
+                    let thresholds = &mut edited.connection;
+
+                    ui.label("Round Trip (ms)");
+                    ui.add(
+                        egui::DragValue::new(&mut thresholds.degraded_rtt_ms).range(0.0..=1000.0),
+                    );
+                    ui.add(egui::DragValue::new(&mut thresholds.bad_rtt_ms).range(0.0..=1000.0));
+                    ui.end_row();
+
+                    ui.label("Jitter (ms)");
+                    ui.add(
+                        egui::DragValue::new(&mut thresholds.degraded_jitter_ms).range(0.0..=500.0),
+                    );
+                    ui.add(egui::DragValue::new(&mut thresholds.bad_jitter_ms).range(0.0..=500.0));
+                    ui.end_row();
+
+                    ui.label("Missed Heartbeats (%)");
+                    let mut degraded = thresholds.degraded_loss * 100.0;
+                    let mut bad = thresholds.bad_loss * 100.0;
+                    ui.add(egui::DragValue::new(&mut degraded).range(0.0..=100.0));
+                    ui.add(egui::DragValue::new(&mut bad).range(0.0..=100.0));
+                    thresholds.degraded_loss = degraded / 100.0;
+                    thresholds.bad_loss = bad / 100.0;
+                    ui.end_row();
+                });
+        },
+    );
+
+    if edited != *settings {
+        *settings = edited;
+    }
+    if edited_units != *units {
+        *units = edited_units;
+    }
+    if profile != bindings.active {
+        bindings.active = profile;
+    }
+    if layout_mode != camera_layouts.current().mode {
+        camera_layout.send(CameraLayoutEvent::SetMode(layout_mode));
+    }
+
+    if !open {
+        cmds.remove_resource::<SettingsUi>();
+    }
+}
@@ -0,0 +1,721 @@
+use std::{fmt::Write as _, fs, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiUserTextures};
+use common::{
+    components::{CameraDefinition, DepthMeasurement, Heading, Orientation, Robot},
+    error::ErrorEvent,
+    types::units::{Degrees, Meters, UnitPreferences},
+};
+use egui::{Color32, Id, RichText, TextureId};
+use egui_plot::{Line, Plot, PlotImage, PlotPoint, PlotPoints, Points, Text};
+use leafwing_input_manager::action_state::ActionState;
+use opencv::{
+    core::{AlgorithmHint, Point, Scalar, Vector},
+    imgcodecs, imgproc,
+    prelude::*,
+};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::{
+    input::{Action, InputMarker},
+    session::{artifact_directory, ActiveSession},
+    video_display_2d_master::VideoMasterMarker,
+    video_stream::ImageHandle,
+    wall_clock::{format_time, now},
+};
+
+pub const SNAPSHOT_DIRECTORY: &str = "snapshots";
+
+/// Clicks within this many pixels of an annotation select it for removal
+const PICK_RADIUS: f32 = 15.0;
+
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CaptureStill>()
+            .add_event::<ExportSnapshot>()
+            .add_systems(
+                Update,
+                (
+                    capture_hotkey,
+                    capture_still.after(capture_hotkey),
+                    snapshot_ui,
+                    export_snapshot.after(snapshot_ui),
+                ),
+            );
+    }
+}
+
+/// Saves the current frame of the master camera and opens it for annotation
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CaptureStill;
+
+/// Writes the annotated image and a csv of the annotations next to the snapshot
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ExportSnapshot(pub Entity);
+
+/// Where and when a snapshot was taken, also embedded in the png as text chunks
+#[derive(Debug, Clone)]
+pub struct SnapshotMetadata {
+    pub time: OffsetDateTime,
+    pub robot: Option<String>,
+    pub camera: String,
+    pub depth: Option<Meters>,
+    pub heading: Option<Degrees>,
+}
+
+impl SnapshotMetadata {
+    fn text_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("Creation Time", format_time(self.time)),
+            ("Camera", self.camera.clone()),
+        ];
+
+        if let Some(robot) = &self.robot {
+            fields.push(("Robot", robot.clone()));
+        }
+        if let Some(depth) = self.depth {
+            fields.push(("Depth", depth.to_string()));
+        }
+        if let Some(heading) = self.heading {
+            fields.push(("Heading", heading.to_string()));
+        }
+
+        fields
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Snapshot {
+    pub image_handle: Handle<Image>,
+    pub egui_texture: TextureId,
+    pub path: PathBuf,
+    pub metadata: SnapshotMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    Point(Vec2),
+    Line(Vec2, Vec2),
+    Text(Vec2, String),
+}
+
+impl Annotation {
+    fn distance_to(&self, point: Vec2) -> f32 {
+        match self {
+            Annotation::Point(it) | Annotation::Text(it, _) => it.distance(point),
+            Annotation::Line(a, b) => {
+                let ab = *b - *a;
+                let t =
+                    ((point - *a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+
+                (*a + ab * t).distance(point)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnnotationTool {
+    #[default]
+    Point,
+    Line,
+    Text,
+    Erase,
+}
+
+impl AnnotationTool {
+    pub const ALL: [AnnotationTool; 4] = [
+        AnnotationTool::Point,
+        AnnotationTool::Line,
+        AnnotationTool::Text,
+        AnnotationTool::Erase,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnnotationTool::Point => "Point",
+            AnnotationTool::Line => "Line",
+            AnnotationTool::Text => "Text",
+            AnnotationTool::Erase => "Erase",
+        }
+    }
+}
+
+/// Annotations in image pixel coordinates
+#[derive(Component, Debug, Clone, Default)]
+pub struct SnapshotAnnotations {
+    pub annotations: Vec<Annotation>,
+    pub tool: AnnotationTool,
+    pub text: String,
+    /// A line of known length used to estimate the length of the other lines, only meaningful
+    /// when everything measured lies in a plane facing the camera
+    pub reference: Option<(usize, Meters)>,
+    line_start: Option<Vec2>,
+}
+
+impl SnapshotAnnotations {
+    /// Meters per pixel from the reference line
+    pub fn scale(&self) -> Option<f32> {
+        let (idx, length) = self.reference?;
+        let Some(Annotation::Line(a, b)) = self.annotations.get(idx) else {
+            return None;
+        };
+
+        let pixels = a.distance(*b);
+        (pixels > 0.0).then(|| length.0 / pixels)
+    }
+
+    fn remove(&mut self, idx: usize) {
+        self.annotations.remove(idx);
+
+        self.reference = match self.reference {
+            Some((reference, _)) if reference == idx => None,
+            Some((reference, length)) if reference > idx => Some((reference - 1, length)),
+            reference => reference,
+        };
+    }
+
+    pub fn csv(&self) -> String {
+        let scale = self.scale();
+        let mut csv = "kind,x1,y1,x2,y2,length_px,length_m,text\n".to_owned();
+
+        for annotation in &self.annotations {
+            let _ = match annotation {
+                Annotation::Point(point) => {
+                    writeln!(csv, "point,{},{},,,,,", point.x, point.y)
+                }
+                Annotation::Line(a, b) => {
+                    let length = a.distance(*b);
+                    let meters = scale
+                        .map(|it| format!("{:.3}", length * it))
+                        .unwrap_or_default();
+
+                    writeln!(
+                        csv,
+                        "line,{},{},{},{},{length:.1},{meters},",
+                        a.x, a.y, b.x, b.y
+                    )
+                }
+                Annotation::Text(point, text) => {
+                    let text = text.replace('"', "\"\"");
+                    writeln!(csv, "text,{},{},,,,,\"{text}\"", point.x, point.y)
+                }
+            };
+        }
+
+        csv
+    }
+}
+
+fn capture_hotkey(
+    inputs: Query<&ActionState<Action>, With<InputMarker>>,
+    mut events: EventWriter<CaptureStill>,
+) {
+    for action_state in &inputs {
+        if action_state.just_pressed(&Action::CaptureStill) {
+            events.send(CaptureStill);
+        }
+    }
+}
+
+fn capture_still(
+    mut cmds: Commands,
+    mut events: EventReader<CaptureStill>,
+    cameras: Query<(&Name, &ImageHandle, Has<VideoMasterMarker>), With<CameraDefinition>>,
+    // TODO(low): Support multiple robots
+    robot: Query<
+        (
+            &Name,
+            Option<&DepthMeasurement>,
+            Option<&Heading>,
+            Option<&Orientation>,
+        ),
+        With<Robot>,
+    >,
+    mut images: ResMut<Assets<Image>>,
+    mut egui_textures: ResMut<EguiUserTextures>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    // Several operators pressing capture on the same frame only need one image
+    if events.read().count() == 0 {
+        return;
+    }
+
+    let res: anyhow::Result<()> = try {
+        // No camera has the marker until one is clicked or cycled to
+        let (camera, handle, _) = cameras
+            .iter()
+            .max_by_key(|(.., master)| *master)
+            .context("No cameras")?;
+
+        let image = images
+            .get(&handle.0)
+            .filter(|it| !it.data.is_empty())
+            .cloned()
+            .with_context(|| format!("No frame from {camera}"))?;
+
+        let robot = robot.get_single().ok();
+        let metadata = SnapshotMetadata {
+            time: now(),
+            robot: robot.map(|(name, ..)| name.to_string()),
+            camera: camera.to_string(),
+            depth: robot.and_then(|(_, depth, ..)| depth.map(|it| it.depth)),
+            heading: robot.and_then(|(_, _, heading, orientation)| {
+                heading
+                    .copied()
+                    .or_else(|| orientation.map(|it| it.heading()))
+                    .map(|it| it.0)
+            }),
+        };
+
+        let directory = artifact_directory(session.as_deref(), SNAPSHOT_DIRECTORY);
+        fs::create_dir_all(&directory).context("Create snapshot directory")?;
+
+        let file_name = metadata
+            .time
+            .format(&Iso8601::DATE_TIME)
+            .context("Format time")?;
+        let path = directory.join(format!("snapshot_{file_name}.png"));
+
+        let png = encode_png(&image, &metadata).context("Encode snapshot")?;
+        fs::write(&path, png).context("Write snapshot")?;
+        info!("Saved snapshot to {path:?}");
+
+        let image_handle = images.add(image);
+        let egui_texture = egui_textures.add_image(image_handle.clone_weak());
+
+        cmds.spawn((
+            Name::new(format!("Snapshot {file_name}")),
+            Snapshot {
+                image_handle,
+                egui_texture,
+                path,
+                metadata,
+            },
+            SnapshotAnnotations::default(),
+        ));
+    };
+
+    if let Err(err) = res {
+        errors.send(err.context("Capture still").into());
+    }
+}
+
+/// Converts the RGBA frame back to a png, with the metadata as text chunks
+pub fn encode_png(image: &Image, metadata: &SnapshotMetadata) -> anyhow::Result<Vec<u8>> {
+    let size = image.size();
+
+    let rgba = Mat::from_slice(&image.data).context("Wrap image")?;
+    let rgba = rgba.reshape(4, size.y as i32).context("Reshape image")?;
+
+    let mut bgr = Mat::default();
+    imgproc::cvt_color(
+        &rgba,
+        &mut bgr,
+        imgproc::COLOR_RGBA2BGR,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )
+    .context("Convert colors")?;
+
+    let mut png = Vector::<u8>::new();
+    imgcodecs::imencode_def(".png", &bgr, &mut png).context("Encode png")?;
+
+    Ok(insert_text_chunks(png.to_vec(), &metadata.text_fields()))
+}
+
+/// Adds tEXt chunks directly after the IHDR chunk, which is always first in a png
+fn insert_text_chunks(png: Vec<u8>, fields: &[(&str, String)]) -> Vec<u8> {
+    // 8 byte signature then the 13 byte IHDR chunk with its length, type and crc
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+
+    if png.len() < IHDR_END {
+        return png;
+    }
+
+    let mut chunks = Vec::new();
+    for (key, value) in fields {
+        let mut data = b"tEXt".to_vec();
+        data.extend(key.bytes());
+        data.push(0);
+        // tEXt is latin-1, anything else is dropped
+        data.extend(value.chars().filter(|it| it.is_ascii()).map(|it| it as u8));
+
+        chunks.extend(((data.len() - 4) as u32).to_be_bytes());
+        chunks.extend(&data);
+        chunks.extend(crc32(&data).to_be_bytes());
+    }
+
+    let mut out = png;
+    out.splice(IHDR_END..IHDR_END, chunks);
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+fn snapshot_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut snapshots: Query<(Entity, &Snapshot, &mut SnapshotAnnotations)>,
+    images: Res<Assets<Image>>,
+    units: Res<UnitPreferences>,
+) {
+    for (entity, snapshot, mut annotations) in snapshots.iter_mut() {
+        let mut open = true;
+        let annotations = &mut *annotations;
+
+        let context = contexts.ctx_mut();
+        egui::Window::new(format!("Snapshot {}", snapshot.metadata.camera))
+            .id(Id::new(entity))
+            .constrain_to(context.available_rect().shrink(20.0))
+            .default_size((400.0, 400.0))
+            .open(&mut open)
+            .show(context, |ui| {
+                let metadata = &snapshot.metadata;
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(format_time(metadata.time));
+                    if let Some(robot) = &metadata.robot {
+                        ui.label(robot);
+                    }
+                    if let Some(depth) = metadata.depth {
+                        ui.label(format!("Depth: {}", units.length(depth)));
+                    }
+                    if let Some(heading) = metadata.heading {
+                        ui.label(format!("Heading: {heading}"));
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    for tool in AnnotationTool::ALL {
+                        if ui
+                            .selectable_label(annotations.tool == tool, tool.name())
+                            .clicked()
+                        {
+                            annotations.tool = tool;
+                            annotations.line_start = None;
+                        }
+                    }
+
+                    if annotations.tool == AnnotationTool::Text {
+                        ui.text_edit_singleline(&mut annotations.text);
+                    }
+                });
+
+                let scale = annotations.scale();
+                let image_size = images
+                    .get(&snapshot.image_handle)
+                    .map(|it| it.size_f32())
+                    .unwrap_or_default();
+
+                let response = Plot::new(("Snapshot Plot", entity))
+                    .data_aspect(1.0)
+                    .min_size(egui::Vec2::new(100.0, 100.0))
+                    .width(ui.available_width())
+                    .height(ui.available_width() * image_size.y / image_size.x.max(1.0))
+                    .show_axes(false)
+                    .show(ui, |ui| {
+                        ui.image(PlotImage::new(
+                            "Snapshot",
+                            snapshot.egui_texture,
+                            [image_size.x as f64 / 2.0, -image_size.y as f64 / 2.0].into(),
+                            [image_size.x, image_size.y],
+                        ));
+
+                        let to_plot = |it: Vec2| PlotPoint::new(it.x, -it.y);
+
+                        for (idx, annotation) in annotations.annotations.iter().enumerate() {
+                            match annotation {
+                                Annotation::Point(point) => {
+                                    ui.points(
+                                        Points::new(
+                                            format!("Point {idx}"),
+                                            [point.x as f64, -point.y as f64],
+                                        )
+                                        .color(Color32::RED)
+                                        .radius(4.0)
+                                        .id(Id::new(idx)),
+                                    );
+                                }
+                                Annotation::Line(a, b) => {
+                                    let reference =
+                                        annotations.reference.is_some_and(|(it, _)| it == idx);
+                                    let color = if reference {
+                                        Color32::GREEN
+                                    } else {
+                                        Color32::YELLOW
+                                    };
+
+                                    ui.line(
+                                        Line::new(
+                                            format!("Line {idx}"),
+                                            PlotPoints::from(vec![
+                                                [a.x as f64, -a.y as f64],
+                                                [b.x as f64, -b.y as f64],
+                                            ]),
+                                        )
+                                        .color(color)
+                                        .width(2.0),
+                                    );
+
+                                    let length = a.distance(*b);
+                                    let label = match scale {
+                                        Some(scale) => format!("{:.2}m", length * scale),
+                                        None => format!("{length:.0}px"),
+                                    };
+                                    ui.text(Text::new(
+                                        format!("Line {idx} Length"),
+                                        to_plot((*a + *b) / 2.0),
+                                        RichText::new(label).color(color).strong(),
+                                    ));
+                                }
+                                Annotation::Text(point, text) => {
+                                    ui.text(Text::new(
+                                        format!("Text {idx}"),
+                                        to_plot(*point),
+                                        RichText::new(text).color(Color32::WHITE).strong(),
+                                    ));
+                                }
+                            }
+                        }
+
+                        if let Some(start) = annotations.line_start {
+                            ui.points(
+                                Points::new("Line Start", [start.x as f64, -start.y as f64])
+                                    .color(Color32::YELLOW)
+                                    .radius(3.0),
+                            );
+                        }
+                    });
+
+                if let Some(pointer) = response.response.hover_pos() {
+                    if response.response.clicked() {
+                        let point = response.transform.value_from_position(pointer);
+                        let point = Vec2::new(point.x as f32, -point.y as f32);
+
+                        match annotations.tool {
+                            AnnotationTool::Point => {
+                                annotations.annotations.push(Annotation::Point(point));
+                            }
+                            AnnotationTool::Line => match annotations.line_start.take() {
+                                Some(start) => {
+                                    annotations.annotations.push(Annotation::Line(start, point));
+                                }
+                                None => annotations.line_start = Some(point),
+                            },
+                            AnnotationTool::Text => {
+                                if !annotations.text.is_empty() {
+                                    let text = annotations.text.clone();
+                                    annotations.annotations.push(Annotation::Text(point, text));
+                                }
+                            }
+                            AnnotationTool::Erase => {
+                                let radius = PICK_RADIUS
+                                    * (response.transform.dvalue_dpos()[0].abs() as f32);
+                                let closest = annotations
+                                    .annotations
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(idx, it)| (idx, it.distance_to(point)))
+                                    .filter(|(_, distance)| *distance < radius)
+                                    .min_by(|a, b| f32::total_cmp(&a.1, &b.1));
+
+                                if let Some((idx, _)) = closest {
+                                    annotations.remove(idx);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Any line can be the scale reference for the others
+                let lines = annotations
+                    .annotations
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, it)| matches!(it, Annotation::Line(..)))
+                    .map(|(idx, _)| idx)
+                    .collect::<Vec<_>>();
+
+                if !lines.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label("Reference:");
+
+                        let selected = annotations.reference.map(|(idx, _)| idx);
+                        egui::ComboBox::from_id_salt(("Snapshot Reference", entity))
+                            .selected_text(match selected {
+                                Some(idx) => format!("Line {idx}"),
+                                None => "None".to_owned(),
+                            })
+                            .show_ui(ui, |ui| {
+                                if ui.selectable_label(selected.is_none(), "None").clicked() {
+                                    annotations.reference = None;
+                                }
+
+                                for idx in &lines {
+                                    if ui
+                                        .selectable_label(
+                                            selected == Some(*idx),
+                                            format!("Line {idx}"),
+                                        )
+                                        .clicked()
+                                    {
+                                        let length = annotations
+                                            .reference
+                                            .map(|(_, it)| it)
+                                            .unwrap_or(Meters(1.0));
+                                        annotations.reference = Some((*idx, length));
+                                    }
+                                }
+                            });
+
+                        if let Some((_, length)) = &mut annotations.reference {
+                            ui.add(
+                                egui::DragValue::new(&mut length.0)
+                                    .range(0.01..=100.0)
+                                    .speed(0.01)
+                                    .suffix("m"),
+                            );
+                        }
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Export").clicked() {
+                        cmds.queue(move |world: &mut World| {
+                            world.send_event(ExportSnapshot(entity));
+                        });
+                    }
+
+                    if ui
+                        .add_enabled(
+                            !annotations.annotations.is_empty(),
+                            egui::Button::new("Clear"),
+                        )
+                        .clicked()
+                    {
+                        *annotations = SnapshotAnnotations {
+                            tool: annotations.tool,
+                            ..default()
+                        };
+                    }
+
+                    ui.label(
+                        RichText::new(snapshot.path.display().to_string())
+                            .small()
+                            .weak(),
+                    );
+                });
+            });
+
+        if !open {
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn export_snapshot(
+    mut events: EventReader<ExportSnapshot>,
+    snapshots: Query<(&Snapshot, &SnapshotAnnotations)>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for &ExportSnapshot(entity) in events.read() {
+        let res: anyhow::Result<()> = try {
+            let (snapshot, annotations) = snapshots
+                .get(entity)
+                .map_err(|_| anyhow!("Snapshot no longer exists"))?;
+
+            let path = snapshot
+                .path
+                .to_str()
+                .context("Snapshot path is not utf-8")?;
+            let mut img = imgcodecs::imread_def(path).context("Read snapshot")?;
+
+            draw_annotations(&mut img, annotations).context("Draw annotations")?;
+
+            let base = snapshot.path.with_extension("");
+            let image_path = format!("{}_annotated.png", base.display());
+            let csv_path = format!("{}_annotations.csv", base.display());
+
+            imgcodecs::imwrite_def(&image_path, &img).context("Write annotated snapshot")?;
+            fs::write(&csv_path, annotations.csv()).context("Write annotations")?;
+
+            info!("Exported snapshot to {image_path}");
+        };
+
+        if let Err(err) = res {
+            errors.send(err.context("Export snapshot").into());
+        }
+    }
+}
+
+fn draw_annotations(img: &mut Mat, annotations: &SnapshotAnnotations) -> anyhow::Result<()> {
+    // BGR
+    let red = Scalar::new(0.0, 0.0, 255.0, 0.0);
+    let yellow = Scalar::new(0.0, 255.0, 255.0, 0.0);
+    let green = Scalar::new(0.0, 255.0, 0.0, 0.0);
+    let white = Scalar::new(255.0, 255.0, 255.0, 0.0);
+
+    let to_cv = |it: Vec2| Point::new(it.x.round() as i32, it.y.round() as i32);
+    let scale = annotations.scale();
+
+    let text = |img: &mut Mat, text: &str, at: Vec2, color: Scalar| {
+        imgproc::put_text(
+            img,
+            text,
+            to_cv(at),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.7,
+            color,
+            2,
+            imgproc::LINE_AA,
+            false,
+        )
+    };
+
+    for (idx, annotation) in annotations.annotations.iter().enumerate() {
+        match annotation {
+            Annotation::Point(point) => {
+                imgproc::circle(img, to_cv(*point), 5, red, -1, imgproc::LINE_AA, 0)?;
+            }
+            Annotation::Line(a, b) => {
+                let reference = annotations.reference.is_some_and(|(it, _)| it == idx);
+                let color = if reference { green } else { yellow };
+
+                imgproc::line(img, to_cv(*a), to_cv(*b), color, 2, imgproc::LINE_AA, 0)?;
+
+                let length = a.distance(*b);
+                let label = match scale {
+                    Some(scale) => format!("{:.2}m", length * scale),
+                    None => format!("{length:.0}px"),
+                };
+                text(img, &label, (*a + *b) / 2.0, color)?;
+            }
+            Annotation::Text(point, label) => {
+                text(img, label, *point, white)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! Lists the surfaces connected to the robot, the pilot station grants and revokes what each
+//! co-pilot station may control
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{Robot, Stations, SurfaceRole},
+    ecs_sync::NetId,
+    events::SetCapabilities,
+};
+use egui::{Color32, RichText};
+
+use crate::surface::LocalSurfaceMarker;
+
+pub struct StationsPlugin;
+
+impl Plugin for StationsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, stations_ui);
+    }
+}
+
+/// Only shown while more than one station is connected
+fn stations_ui(
+    mut contexts: EguiContexts,
+    robots: Query<(&Name, &Stations), With<Robot>>,
+    surface: Query<&NetId, With<LocalSurfaceMarker>>,
+    mut set_capabilities: EventWriter<SetCapabilities>,
+) {
+    let Ok((robot_name, stations)) = robots.get_single() else {
+        return;
+    };
+    if stations.0.len() < 2 {
+        return;
+    }
+
+    let local = surface.get_single().ok();
+    let local_role = local
+        .and_then(|it| stations.get(*it))
+        .map(|it| it.role)
+        .unwrap_or_default();
+
+    egui::Window::new(format!("Stations on {robot_name}")).show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("Stations").striped(true).show(ui, |ui| {
+            ui.label("Station");
+            ui.label("Role");
+            ui.label("Arming");
+            ui.label("Movement");
+            ui.end_row();
+
+            for station in &stations.0 {
+                if local == Some(&station.surface) {
+                    ui.label(RichText::new(format!("{} (This Station)", station.name)).strong());
+                } else {
+                    ui.label(&station.name);
+                }
+
+                let color = match station.role {
+                    SurfaceRole::Pilot => Color32::GREEN,
+                    SurfaceRole::CoPilot => Color32::LIGHT_BLUE,
+                };
+                ui.label(RichText::new(station.role.name()).color(color));
+
+                let mut capabilities = station.capabilities;
+                let editable =
+                    local_role == SurfaceRole::Pilot && station.role == SurfaceRole::CoPilot;
+
+                ui.add_enabled_ui(editable, |ui| {
+                    ui.checkbox(&mut capabilities.arming, "");
+                });
+                ui.add_enabled_ui(editable, |ui| {
+                    ui.checkbox(&mut capabilities.movement, "");
+                });
+                ui.end_row();
+
+                if capabilities != station.capabilities {
+                    set_capabilities.send(SetCapabilities {
+                        surface: station.surface,
+                        capabilities,
+                    });
+                }
+            }
+        });
+
+        if local_role == SurfaceRole::CoPilot {
+            ui.label("Only the pilot station can change what this station controls");
+        }
+    });
+}
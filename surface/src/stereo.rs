@@ -0,0 +1,587 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiUserTextures};
+use common::components::CameraDefinition;
+use crossbeam::atomic::AtomicCell;
+use egui::{Color32, ComboBox, DragValue, Id, TextureId};
+use egui_plot::{Plot, PlotImage, Points};
+use opencv::{
+    calib3d::{self, StereoSGBM},
+    core::{Mat, Vec3f, CV_32F, CV_64F},
+    imgproc,
+    prelude::*,
+};
+
+use crate::{
+    layout::DockableWindow,
+    ui::StereoUi,
+    video_pipelines::{
+        calibration::{self, CalibrationStore, LoadedCalibration, StereoCalibration},
+        FromWorldEntity, Pipeline, PipelineCallbacks, PipelineHandler,
+    },
+    video_stream::{self, VideoProcessorFactory},
+};
+
+/// Frames further apart than this are not used as a stereo pair
+const SYNC_TOLERANCE: Duration = Duration::from_millis(40);
+const NUM_DISPARITIES: i32 = 128;
+const BLOCK_SIZE: i32 = 5;
+/// Distance in pixels around a click that is searched for a valid depth
+const PICK_RADIUS: i32 = 6;
+/// Points further than this in meters are treated as bad matches
+const MAX_DEPTH: f32 = 10.0;
+
+pub struct StereoPlugin;
+
+impl Plugin for StereoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                stereo_ui.run_if(resource_exists::<StereoUi>),
+                stereo_capture_ui,
+            ),
+        );
+    }
+}
+
+/// A stereo pair that is streaming, the left camera's pipeline does the processing
+#[derive(Component)]
+pub struct StereoRig {
+    pub left: Entity,
+    pub right: Entity,
+
+    sync: Arc<StereoSync>,
+}
+
+/// Shared between the video threads of both cameras
+#[derive(Default)]
+struct StereoSync {
+    /// The latest frame from the right camera and when it arrived
+    right: Mutex<Option<(Instant, Mat)>>,
+    /// Set by the UI to request a measurement from the next synchronized pair
+    capture: AtomicBool,
+    /// Time between the latest left and right frames
+    offset: AtomicCell<Option<Duration>>,
+}
+
+/// A rectified frame from the left camera along with the 3D position of each of its pixels
+#[derive(Component)]
+pub struct StereoCapture {
+    pub image: Handle<Image>,
+    pub texture: TextureId,
+    pub width: i32,
+    pub height: i32,
+    /// Row major, in meters relative to the left camera
+    pub points: Vec<Vec3>,
+}
+
+impl StereoCapture {
+    /// The position of the closest pixel to `pixel` with a valid depth
+    pub fn point(&self, pixel: Vec2) -> Option<Vec3> {
+        let (center_x, center_y) = (pixel.x.round() as i32, pixel.y.round() as i32);
+        let mut best: Option<(i32, Vec3)> = None;
+
+        for dy in -PICK_RADIUS..=PICK_RADIUS {
+            for dx in -PICK_RADIUS..=PICK_RADIUS {
+                let (x, y) = (center_x + dx, center_y + dy);
+                if x < 0 || y < 0 || x >= self.width || y >= self.height {
+                    continue;
+                }
+
+                let point = self.points[(y * self.width + x) as usize];
+                if !point.is_finite() || point.z <= 0.0 || point.z > MAX_DEPTH {
+                    continue;
+                }
+
+                let distance = dx * dx + dy * dy;
+                if best.is_none_or(|(best, _)| distance < best) {
+                    best = Some((distance, point));
+                }
+            }
+        }
+
+        best.map(|(_, point)| point)
+    }
+}
+
+#[derive(Component, Default, Clone)]
+pub struct StereoMeasurement {
+    points: Vec<Vec2>,
+}
+
+pub struct StereoRightPipeline {
+    sync: Arc<StereoSync>,
+}
+
+impl Pipeline for StereoRightPipeline {
+    type Input = ();
+
+    fn collect_inputs(_world: &World, _entity: &EntityRef) -> Self::Input {
+        // No-op
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        _cmds: &mut PipelineCallbacks,
+        _data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let frame = img.try_clone().context("Copy right frame")?;
+        *self
+            .sync
+            .right
+            .lock()
+            .map_err(|_| anyhow!("Lock stereo frame"))? = Some((Instant::now(), frame));
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        if let Ok(mut right) = self.sync.right.lock() {
+            *right = None;
+        }
+    }
+}
+
+impl FromWorldEntity for StereoRightPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let sync = world
+            .query::<&StereoRig>()
+            .iter(world)
+            .find(|it| it.right == camera)
+            .map(|it| it.sync.clone())
+            .context("Camera is not the right camera of a stereo rig")?;
+
+        Ok(Self { sync })
+    }
+}
+
+pub struct StereoLeftPipeline {
+    sync: Arc<StereoSync>,
+
+    left: LoadedCalibration,
+    right: LoadedCalibration,
+    baseline: f32,
+}
+
+impl Pipeline for StereoLeftPipeline {
+    type Input = ();
+
+    fn collect_inputs(_world: &World, _entity: &EntityRef) -> Self::Input {
+        // No-op
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        _data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let now = Instant::now();
+        let right = {
+            let right = self
+                .sync
+                .right
+                .lock()
+                .map_err(|_| anyhow!("Lock stereo frame"))?;
+
+            let Some((time, right)) = &*right else {
+                return Ok(img);
+            };
+
+            let offset = now.duration_since(*time);
+            self.sync.offset.store(Some(offset));
+
+            // Wait for a closer pair, the cameras are not synchronized in hardware
+            if offset > SYNC_TOLERANCE || !self.sync.capture.load(Ordering::Relaxed) {
+                return Ok(img);
+            }
+
+            right.try_clone().context("Copy right frame")?
+        };
+
+        self.sync.capture.store(false, Ordering::Relaxed);
+
+        let (rectified, points) = self.measure(img, &right).context("Stereo measurement")?;
+        let size = rectified.size().context("Get image size")?;
+
+        let mut image = Image::default();
+        video_stream::mat_to_image(&rectified, &mut image).context("Mat to image")?;
+
+        cmds.world(move |world| {
+            let image = world.resource_mut::<Assets<Image>>().add(image);
+            let texture = world
+                .resource_mut::<EguiUserTextures>()
+                .add_image(image.clone_weak());
+
+            world.spawn((
+                StereoCapture {
+                    image,
+                    texture,
+                    width: size.width,
+                    height: size.height,
+                    points,
+                },
+                StereoMeasurement::default(),
+            ));
+        });
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+impl StereoLeftPipeline {
+    /// Rectifies both frames and computes the 3D position of each pixel of the rectified left frame
+    fn measure(&self, left: &Mat, right: &Mat) -> anyhow::Result<(Mat, Vec<Vec3>)> {
+        let size = left.size().context("Get left size")?;
+        if right.size().context("Get right size")? != size {
+            bail!("Both stereo cameras must stream at the same resolution");
+        }
+
+        let as_f64 = |mat: Mat| -> anyhow::Result<Mat> {
+            let mut out = Mat::default();
+            mat.convert_to_def(&mut out, CV_64F)
+                .context("Convert to f64")?;
+            Ok(out)
+        };
+
+        let left_mtx = as_f64(self.left.camera_matrix(size)?)?;
+        let left_dist = as_f64(self.left.distortion()?)?;
+        let right_mtx = as_f64(self.right.camera_matrix(size)?)?;
+        let right_dist = as_f64(self.right.distortion()?)?;
+
+        // The cameras are assumed to be parallel with the right one offset along the x axis
+        let rotation = Mat::eye(3, 3, CV_64F)
+            .and_then(|it| it.to_mat())
+            .context("Identity rotation")?;
+        let translation = Mat::from_slice(&[-self.baseline as f64, 0.0, 0.0])
+            .context("Translation")?
+            .clone_pointee();
+
+        let mut left_rect = Mat::default();
+        let mut right_rect = Mat::default();
+        let mut left_proj = Mat::default();
+        let mut right_proj = Mat::default();
+        let mut reprojection = Mat::default();
+        calib3d::stereo_rectify_def(
+            &left_mtx,
+            &left_dist,
+            &right_mtx,
+            &right_dist,
+            size,
+            &rotation,
+            &translation,
+            &mut left_rect,
+            &mut right_rect,
+            &mut left_proj,
+            &mut right_proj,
+            &mut reprojection,
+        )
+        .context("Stereo rectify")?;
+
+        let rectify =
+            |img: &Mat, mtx: &Mat, dist: &Mat, rect: &Mat, proj: &Mat| -> anyhow::Result<Mat> {
+                let mut map_x = Mat::default();
+                let mut map_y = Mat::default();
+                calib3d::init_undistort_rectify_map(
+                    mtx, dist, rect, proj, size, CV_32F, &mut map_x, &mut map_y,
+                )
+                .context("Init rectify map")?;
+
+                let mut rectified = Mat::default();
+                imgproc::remap_def(img, &mut rectified, &map_x, &map_y, imgproc::INTER_LINEAR)
+                    .context("Remap")?;
+
+                Ok(rectified)
+            };
+
+        let left = rectify(left, &left_mtx, &left_dist, &left_rect, &left_proj)?;
+        let right = rectify(right, &right_mtx, &right_dist, &right_rect, &right_proj)?;
+
+        let mut left_gray = Mat::default();
+        let mut right_gray = Mat::default();
+        imgproc::cvt_color_def(&left, &mut left_gray, imgproc::COLOR_BGR2GRAY)
+            .context("Convert left to grayscale")?;
+        imgproc::cvt_color_def(&right, &mut right_gray, imgproc::COLOR_BGR2GRAY)
+            .context("Convert right to grayscale")?;
+
+        let mut matcher = StereoSGBM::create(
+            0,
+            NUM_DISPARITIES,
+            BLOCK_SIZE,
+            8 * BLOCK_SIZE * BLOCK_SIZE,
+            32 * BLOCK_SIZE * BLOCK_SIZE,
+            1,
+            63,
+            10,
+            100,
+            32,
+            calib3d::StereoSGBM_MODE_SGBM_3WAY,
+        )
+        .context("Create stereo matcher")?;
+
+        let mut disparity = Mat::default();
+        matcher
+            .compute(&left_gray, &right_gray, &mut disparity)
+            .context("Compute disparity")?;
+
+        // SGBM outputs fixed point disparities with 4 fractional bits
+        let mut disparity_f32 = Mat::default();
+        disparity
+            .convert_to(&mut disparity_f32, CV_32F, 1.0 / 16.0, 0.0)
+            .context("Convert disparity")?;
+
+        let mut points = Mat::default();
+        calib3d::reproject_image_to_3d_def(&disparity_f32, &mut points, &reprojection)
+            .context("Reproject to 3D")?;
+
+        let points = points
+            .data_typed::<Vec3f>()
+            .context("Read 3D points")?
+            .iter()
+            .map(|it| Vec3::new(it[0], it[1], it[2]))
+            .collect();
+
+        Ok((left, points))
+    }
+}
+
+impl FromWorldEntity for StereoLeftPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let (right, sync) = world
+            .query::<&StereoRig>()
+            .iter(world)
+            .find(|it| it.left == camera)
+            .map(|it| (it.right, it.sync.clone()))
+            .context("Camera is not the left camera of a stereo rig")?;
+
+        let baseline = world
+            .get_resource::<CalibrationStore>()
+            .and_then(|it| it.stereo.as_ref())
+            .map(|it| it.baseline)
+            .context("No stereo calibration")?;
+
+        Ok(Self {
+            sync,
+            left: calibration::camera_calibration(world, camera)
+                .context("Left camera has no calibration")?,
+            right: calibration::camera_calibration(world, right)
+                .context("Right camera has no calibration")?,
+            baseline,
+        })
+    }
+}
+
+fn stereo_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut store: ResMut<CalibrationStore>,
+    cameras: Query<(Entity, &Name), With<CameraDefinition>>,
+    rigs: Query<(Entity, &StereoRig)>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Stereo").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        egui::Id::new("Stereo"),
+        |ui| {
+            let mut stereo = store.stereo.clone().unwrap_or(StereoCalibration {
+                left: String::new(),
+                right: String::new(),
+                baseline: 0.1,
+            });
+
+            for (label, selected) in [("Left", &mut stereo.left), ("Right", &mut stereo.right)] {
+                ComboBox::from_label(label)
+                    .selected_text(selected.as_str())
+                    .show_ui(ui, |ui| {
+                        for (_, name) in &cameras {
+                            ui.selectable_value(selected, name.to_string(), name.as_str());
+                        }
+                    });
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Baseline:");
+                ui.add(
+                    DragValue::new(&mut stereo.baseline)
+                        .speed(0.001)
+                        .range(0.01..=2.0)
+                        .suffix("m"),
+                );
+            });
+
+            if store.stereo.as_ref() != Some(&stereo) {
+                store.stereo = Some(stereo.clone());
+            }
+
+            ui.separator();
+
+            if let Ok((entity, rig)) = rigs.get_single() {
+                if let Some(offset) = rig.sync.offset.load() {
+                    ui.label(format!(
+                        "Frame Offset: {:.0}ms",
+                        offset.as_secs_f32() * 1000.0
+                    ));
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Capture").clicked() {
+                        rig.sync.capture.store(true, Ordering::Relaxed);
+                    }
+
+                    if ui.button("Stop").clicked() {
+                        cmds.entity(rig.left).remove::<VideoProcessorFactory>();
+                        cmds.entity(rig.right).remove::<VideoProcessorFactory>();
+                        cmds.entity(entity).despawn();
+                    }
+                });
+            } else {
+                let find = |name: &str| {
+                    cameras
+                        .iter()
+                        .find(|(_, it)| it.as_str() == name)
+                        .map(|(entity, _)| entity)
+                };
+                let pair = find(&stereo.left)
+                    .zip(find(&stereo.right))
+                    .filter(|(left, right)| left != right);
+
+                if ui
+                    .add_enabled(pair.is_some(), egui::Button::new("Start"))
+                    .clicked()
+                {
+                    if let Some((left, right)) = pair {
+                        cmds.spawn((
+                            Name::new("Stereo Rig"),
+                            StereoRig {
+                                left,
+                                right,
+                                sync: default(),
+                            },
+                        ));
+                        cmds.entity(left).insert(VideoProcessorFactory::new::<
+                            PipelineHandler<StereoLeftPipeline>,
+                        >("Stereo Left"));
+                        cmds.entity(right).insert(VideoProcessorFactory::new::<
+                            PipelineHandler<StereoRightPipeline>,
+                        >("Stereo Right"));
+                    }
+                }
+            }
+        },
+    );
+
+    if !open {
+        cmds.remove_resource::<StereoUi>();
+    }
+}
+
+fn stereo_capture_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut captures: Query<(Entity, &StereoCapture, &mut StereoMeasurement)>,
+) {
+    for (entity, capture, mut measurement) in captures.iter_mut() {
+        let mut open = true;
+
+        let context = contexts.ctx_mut();
+        egui::Window::new("Stereo Measurement")
+            .id(Id::new(entity))
+            .constrain_to(context.available_rect().shrink(20.0))
+            .default_size((400.0, 300.0))
+            .open(&mut open)
+            .show(context, |ui| {
+                ui.label("Click two points to measure the distance between them");
+
+                let (width, height) = (capture.width as f32, capture.height as f32);
+                let response = Plot::new("Stereo Plot")
+                    .data_aspect(1.0)
+                    .min_size(egui::Vec2::new(100.0, 100.0))
+                    .width(ui.available_width())
+                    .height(ui.available_width() * height / width)
+                    .show(ui, |ui| {
+                        ui.image(PlotImage::new(
+                            "Stereo",
+                            capture.texture,
+                            [width as f64 / 2.0, -height as f64 / 2.0].into(),
+                            [width, height],
+                        ));
+
+                        for (idx, point) in measurement.points.iter().enumerate() {
+                            ui.points(
+                                Points::new(
+                                    format!("Point {idx}"),
+                                    [point.x as f64, -point.y as f64],
+                                )
+                                .color(Color32::RED)
+                                .radius(3.0)
+                                .id(Id::new(idx)),
+                            );
+                        }
+                    });
+
+                if let Some(pointer) = response.response.hover_pos() {
+                    if response.response.clicked() {
+                        let point = response.transform.value_from_position(pointer);
+                        let point = Vec2::new(point.x as f32, -point.y as f32);
+
+                        if measurement.points.len() < 2 {
+                            measurement.points.push(point);
+                        } else if let Some(closest) =
+                            measurement.points.iter_mut().min_by(|a, b| {
+                                f32::total_cmp(
+                                    &a.distance_squared(point),
+                                    &b.distance_squared(point),
+                                )
+                            })
+                        {
+                            *closest = point;
+                        }
+                    }
+                }
+
+                let positions = measurement
+                    .points
+                    .iter()
+                    .map(|it| capture.point(*it))
+                    .collect::<Vec<_>>();
+
+                for (idx, position) in positions.iter().enumerate() {
+                    match position {
+                        Some(position) => {
+                            ui.label(format!("Point {idx}: {:.2}m away", position.length()))
+                        }
+                        None => ui.colored_label(Color32::YELLOW, format!("Point {idx}: No depth")),
+                    };
+                }
+
+                if let [Some(a), Some(b)] = positions[..] {
+                    ui.label(format!("Distance: {:.3}m", a.distance(b)));
+                }
+            });
+
+        if !open {
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+}
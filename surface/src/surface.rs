@@ -1,11 +1,13 @@
 use bevy::prelude::*;
 use common::{
-    components::{Singleton, Surface},
-    ecs_sync::Replicate,
+    components::{Capabilities, Singleton, Stations, Surface, SurfaceRole},
+    ecs_sync::{NetId, Replicate},
+    sync::{LocalPeerKind, PeerKind},
     InstanceName,
 };
 
-pub struct SurfacePlugin;
+/// Holds the role this station asks the robot for
+pub struct SurfacePlugin(pub SurfaceRole);
 
 // TODO(low): This nameing is kinda bad
 #[derive(Component, Debug, Copy, Clone, PartialEq, Default)]
@@ -18,15 +20,21 @@ pub struct LocalSurface {
 
 impl Plugin for SurfacePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, setup_surface);
+        app.insert_resource(RequestedRole(self.0))
+            .insert_resource(LocalPeerKind(PeerKind::Surface(self.0)))
+            .add_systems(PreStartup, setup_surface);
     }
 }
 
-fn setup_surface(mut cmds: Commands, name: Res<InstanceName>) {
+#[derive(Resource)]
+struct RequestedRole(SurfaceRole);
+
+fn setup_surface(mut cmds: Commands, name: Res<InstanceName>, role: Res<RequestedRole>) {
     let surface = cmds
         .spawn((
             Name::new(name.0.clone()),
             Surface,
+            role.0,
             LocalSurfaceMarker,
             Replicate,
             Singleton,
@@ -35,3 +43,12 @@ fn setup_surface(mut cmds: Commands, name: Res<InstanceName>) {
 
     cmds.insert_resource(LocalSurface { entity: surface })
 }
+
+/// What this station may control on a robot, unrestricted until the robot says otherwise
+pub fn local_capabilities(stations: Option<&Stations>, surface: Option<&NetId>) -> Capabilities {
+    stations
+        .zip(surface)
+        .and_then(|(stations, surface)| stations.get(*surface))
+        .map(|station| station.capabilities)
+        .unwrap_or(Capabilities::ALL)
+}
@@ -0,0 +1,340 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{
+        CurrentDraw, DepthMeasurement, DepthTarget, MeasuredVoltage, MotorSignal, Orientation,
+        PidResult, Robot, RobotId,
+    },
+    error::ErrorEvent,
+};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::{
+    dive_log::format_secs,
+    layout::DockableWindow,
+    session::{artifact_directory, ActiveSession},
+    ui::TelemetryExportUi,
+    wall_clock::now,
+};
+
+pub const EXPORT_DIRECTORY: &str = "exports";
+
+const MIN_SAMPLE_RATE: f32 = 1.0;
+const MAX_SAMPLE_RATE: f32 = 60.0;
+
+pub struct TelemetryExportPlugin;
+
+impl Plugin for TelemetryExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TelemetryExport>()
+            .add_event::<SetTelemetryExport>()
+            .add_systems(
+                Update,
+                (
+                    handle_set_export,
+                    export_telemetry.after(handle_set_export),
+                    telemetry_export_ui.run_if(resource_exists::<TelemetryExportUi>),
+                ),
+            );
+    }
+}
+
+/// Which components are written to the export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportChannels {
+    pub depth: bool,
+    pub orientation: bool,
+    /// Voltage and current draw of the robot and its motors
+    pub power: bool,
+    pub pid: bool,
+    pub motors: bool,
+}
+
+impl Default for ExportChannels {
+    fn default() -> Self {
+        Self {
+            depth: true,
+            orientation: true,
+            power: true,
+            pid: true,
+            motors: true,
+        }
+    }
+}
+
+/// Dumps replicated components of the robot to a csv while running
+///
+/// Rows are `elapsed_s,unix_time_ms,source,channel,value`, every row of a sample shares its
+/// timestamps so the file can be pivoted into one column per source and channel
+#[derive(Resource, Debug)]
+pub struct TelemetryExport {
+    pub channels: ExportChannels,
+    /// Samples per second
+    pub sample_rate: f32,
+
+    running: Option<ExportFile>,
+}
+
+#[derive(Debug)]
+struct ExportFile {
+    path: PathBuf,
+    started: Instant,
+    writer: BufWriter<File>,
+    last_sample: Option<Instant>,
+    rows: usize,
+}
+
+impl Default for TelemetryExport {
+    fn default() -> Self {
+        Self {
+            channels: ExportChannels::default(),
+            sample_rate: 20.0,
+            running: None,
+        }
+    }
+}
+
+impl TelemetryExport {
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// The file being written and how long it has been running for
+    pub fn status(&self) -> Option<(&PathBuf, Duration, usize)> {
+        self.running
+            .as_ref()
+            .map(|it| (&it.path, it.started.elapsed(), it.rows))
+    }
+}
+
+impl ExportFile {
+    fn new(directory: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(directory).context("Create export directory")?;
+
+        let name = now().format(&Iso8601::DATE_TIME).context("Format time")?;
+        let path = directory.join(format!("telemetry_{name}.csv"));
+
+        let mut writer = BufWriter::new(File::create(&path).context("Create export file")?);
+        writeln!(writer, "elapsed_s,unix_time_ms,source,channel,value")
+            .context("Write export header")?;
+
+        info!("Exporting telemetry to {path:?}");
+
+        Ok(Self {
+            path,
+            started: Instant::now(),
+            writer,
+            last_sample: None,
+            rows: 0,
+        })
+    }
+}
+
+/// Starts or stops exporting
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SetTelemetryExport(pub bool);
+
+fn handle_set_export(
+    mut events: EventReader<SetTelemetryExport>,
+    mut export: ResMut<TelemetryExport>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for &SetTelemetryExport(enabled) in events.read() {
+        if enabled == export.is_running() {
+            continue;
+        }
+
+        if !enabled {
+            if let Some(mut file) = export.running.take() {
+                if let Err(err) = file.writer.flush() {
+                    errors.send(anyhow::Error::from(err).context("Flush export").into());
+                }
+
+                info!("Exported {} rows to {:?}", file.rows, file.path);
+            }
+
+            continue;
+        }
+
+        match ExportFile::new(&artifact_directory(session.as_deref(), EXPORT_DIRECTORY)) {
+            Ok(file) => export.running = Some(file),
+            Err(err) => {
+                errors.send(err.context("Start telemetry export").into());
+            }
+        }
+    }
+}
+
+fn export_telemetry(
+    mut export: ResMut<TelemetryExport>,
+    // Components on the robot itself as well as on its motors and controllers
+    entities: Query<
+        (
+            &Name,
+            (Option<&DepthMeasurement>, Option<&DepthTarget>),
+            Option<&Orientation>,
+            (Option<&MeasuredVoltage>, Option<&CurrentDraw>),
+            Option<&PidResult>,
+            Option<&MotorSignal>,
+        ),
+        Or<(With<Robot>, With<RobotId>)>,
+    >,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let export = &mut *export;
+    let channels = export.channels;
+    let interval = Duration::from_secs_f32(1.0 / export.sample_rate.max(MIN_SAMPLE_RATE));
+
+    let Some(file) = &mut export.running else {
+        return;
+    };
+
+    if file.last_sample.is_some_and(|it| it.elapsed() < interval) {
+        return;
+    }
+    file.last_sample = Some(Instant::now());
+
+    let elapsed = file.started.elapsed().as_secs_f64();
+    let unix_ms = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+
+    let mut rows = Vec::new();
+    for (name, (depth, depth_target), orientation, (voltage, current), pid, motor) in &entities {
+        let mut row = |channel: &'static str, value: f32| rows.push((name, channel, value));
+
+        if channels.depth {
+            if let Some(depth) = depth {
+                row("depth_m", depth.depth.0);
+            }
+            if let Some(target) = depth_target {
+                row("depth_target_m", target.0 .0);
+            }
+        }
+
+        if channels.orientation {
+            if let Some(orientation) = orientation {
+                let (yaw, pitch, roll) = orientation.yaw_pitch_roll();
+
+                row("yaw_deg", yaw);
+                row("pitch_deg", pitch);
+                row("roll_deg", roll);
+            }
+        }
+
+        if channels.power {
+            if let Some(voltage) = voltage {
+                row("voltage_v", voltage.0 .0);
+            }
+            if let Some(current) = current {
+                row("current_a", current.0 .0);
+            }
+        }
+
+        if channels.pid {
+            if let Some(pid) = pid {
+                row("pid_error", pid.error);
+                row("pid_p", pid.p);
+                row("pid_i", pid.i);
+                row("pid_d", pid.d);
+                row("pid_correction", pid.correction);
+            }
+        }
+
+        if channels.motors {
+            match motor {
+                Some(MotorSignal::Percent(pct)) => row("motor_percent", *pct),
+                Some(MotorSignal::Raw(raw)) => row("motor_raw", *raw as f32),
+                None => {}
+            }
+        }
+    }
+
+    let res: anyhow::Result<()> = try {
+        for (name, channel, value) in rows {
+            let source = name.as_str().replace('"', "\"\"");
+
+            writeln!(
+                file.writer,
+                "{elapsed:.3},{unix_ms},\"{source}\",{channel},{value}"
+            )
+            .context("Write export row")?;
+            file.rows += 1;
+        }
+    };
+
+    if let Err(err) = res {
+        errors.send(err.into());
+
+        // Don't report the same failure every sample
+        export.running = None;
+    }
+}
+
+fn telemetry_export_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut export: ResMut<TelemetryExport>,
+    mut set_export: EventWriter<SetTelemetryExport>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Telemetry Export")
+        .open(&mut open)
+        .show_dockable(
+            contexts.ctx_mut(),
+            egui::Id::new("Telemetry Export"),
+            |ui| {
+                let running = export.is_running();
+
+                ui.add_enabled_ui(!running, |ui| {
+                    let channels = &mut export.channels;
+
+                    ui.checkbox(&mut channels.depth, "Depth");
+                    ui.checkbox(&mut channels.orientation, "Orientation");
+                    ui.checkbox(&mut channels.power, "Voltage and Current");
+                    ui.checkbox(&mut channels.pid, "PID Results");
+                    ui.checkbox(&mut channels.motors, "Motor Signals");
+
+                    ui.add(
+                        egui::Slider::new(
+                            &mut export.sample_rate,
+                            MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE,
+                        )
+                        .text("Sample Rate (Hz)"),
+                    );
+                });
+
+                ui.separator();
+
+                if let Some((path, elapsed, rows)) = export.status() {
+                    ui.label(format!("Writing to {}", path.display()));
+                    ui.label(format!(
+                        "{} rows in {}",
+                        rows,
+                        format_secs(elapsed.as_secs_f64())
+                    ));
+                }
+
+                let label = if running {
+                    "Stop Export"
+                } else {
+                    "Start Export"
+                };
+                if ui.button(label).clicked() {
+                    set_export.send(SetTelemetryExport(!running));
+                }
+            },
+        );
+
+    if !open {
+        cmds.remove_resource::<TelemetryExportUi>();
+    }
+}
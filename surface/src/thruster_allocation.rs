@@ -0,0 +1,186 @@
+//! Tool window showing what the thrust allocator did with each thruster
+//!
+//! The robot replicates the force requested from and given to every thruster along with its share
+//! of the current budget. A thruster that keeps saturating while the others have headroom is what
+//! limits the robot when it feels sluggish.
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::{
+    components::{Robot, RobotId, ThrusterDefinition, ThrusterDiagnostics},
+    ecs_sync::NetId,
+};
+use egui::Color32;
+use egui_plot::{Bar, BarChart, Legend, Plot};
+
+use crate::{layout::DockableWindow, settings::SurfaceSettings, tool_windows::ToolWindow};
+
+pub struct ThrusterAllocationPlugin;
+
+impl Plugin for ThrusterAllocationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, thruster_allocation_debug);
+    }
+}
+
+#[derive(Component)]
+pub struct ThrusterAllocationDebugger;
+
+fn thruster_allocation_debug(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+
+    mut windows: Query<(Entity, &ToolWindow, &mut RobotId), With<ThrusterAllocationDebugger>>,
+
+    robots: Query<
+        (&Name, &RobotId, Option<&ThrusterDiagnostics>),
+        (With<Robot>, Without<ThrusterAllocationDebugger>),
+    >,
+    thrusters: Query<
+        (&Name, &ThrusterDefinition, &RobotId),
+        (Without<Robot>, Without<ThrusterAllocationDebugger>),
+    >,
+    settings: Res<SurfaceSettings>,
+) {
+    let palette = settings.theme.palette();
+
+    for (window, tool, mut selected_robot) in &mut windows {
+        let mut open = true;
+
+        let context = contexts.ctx_mut();
+        tool.window()
+            .constrain_to(context.available_rect().shrink(20.0))
+            .open(&mut open)
+            .show_dockable(context, tool.id(), |ui| {
+                ui.label("Robot:");
+                let Some((robot_id, diagnostics)) = ui
+                    .horizontal(|ui| {
+                        let mut data = None;
+                        for (name, robot_id, diagnostics) in &robots {
+                            ui.selectable_value(&mut selected_robot.0, robot_id.0, name.as_str());
+
+                            if selected_robot.0 == robot_id.0 {
+                                data = Some((robot_id, diagnostics));
+                            }
+                        }
+                        ui.selectable_value(&mut selected_robot.0, NetId::invalid(), "None");
+
+                        if selected_robot.0 != NetId::invalid() {
+                            data
+                        } else {
+                            None
+                        }
+                    })
+                    .inner
+                else {
+                    return;
+                };
+
+                let Some(diagnostics) = diagnostics else {
+                    ui.label("The robot has not sent any thruster diagnostics");
+                    return;
+                };
+
+                let mut rows = thrusters
+                    .iter()
+                    .filter(|(_, _, other_robot_id)| *other_robot_id == robot_id)
+                    .filter_map(|(name, ThrusterDefinition(id, _), _)| {
+                        Some((*id, name.as_str(), diagnostics.0.get(id)?))
+                    })
+                    .collect::<Vec<_>>();
+                rows.sort_by_key(|(id, ..)| *id);
+
+                let bottleneck = rows
+                    .iter()
+                    .filter(|(.., diagnostic)| diagnostic.saturated)
+                    .max_by(|(.., a), (.., b)| a.current_share.total_cmp(&b.current_share));
+                match bottleneck {
+                    Some((_, name, _)) => {
+                        ui.colored_label(Color32::RED, format!("Bottleneck: {name}"));
+                    }
+                    None => {
+                        ui.label("No thrusters are saturated");
+                    }
+                }
+
+                let requested = rows
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (_, name, diagnostic))| {
+                        Bar::new(idx as f64 - 0.2, diagnostic.requested.0 as f64)
+                            .width(0.4)
+                            .name(name)
+                    })
+                    .collect();
+                let clamped = rows
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (_, name, diagnostic))| {
+                        let bar = Bar::new(idx as f64 + 0.2, diagnostic.clamped.0 as f64)
+                            .width(0.4)
+                            .name(name);
+
+                        if diagnostic.saturated {
+                            bar.fill(Color32::RED)
+                        } else {
+                            bar
+                        }
+                    })
+                    .collect();
+
+                let names = rows.iter().map(|(_, name, _)| *name).collect::<Vec<_>>();
+                Plot::new(("Thruster Allocation", tool.instance))
+                    .height(200.0)
+                    .legend(Legend::default())
+                    .x_axis_formatter(move |mark, _| {
+                        let idx = mark.value.round();
+                        if (mark.value - idx).abs() > 0.01 || idx < 0.0 {
+                            return String::new();
+                        }
+
+                        names
+                            .get(idx as usize)
+                            .map(|it| it.to_string())
+                            .unwrap_or_default()
+                    })
+                    .show(ui, |plot_ui| {
+                        plot_ui.bar_chart(
+                            BarChart::new(requested)
+                                .name("Requested")
+                                .color(palette.series[0]),
+                        );
+                        plot_ui.bar_chart(
+                            BarChart::new(clamped)
+                                .name("Delivered")
+                                .color(palette.series[1]),
+                        );
+                    });
+
+                egui::Grid::new(("Thruster Allocation Grid", tool.instance))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Thruster");
+                        ui.label("Requested");
+                        ui.label("Delivered");
+                        ui.label("Current Share");
+                        ui.end_row();
+
+                        for (_, name, diagnostic) in &rows {
+                            if diagnostic.saturated {
+                                ui.colored_label(Color32::RED, *name);
+                            } else {
+                                ui.label(*name);
+                            }
+                            ui.label(format!("{:.1}", diagnostic.requested));
+                            ui.label(format!("{:.1}", diagnostic.clamped));
+                            ui.label(format!("{:.0}%", diagnostic.current_share * 100.0));
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if !open {
+            cmds.entity(window).despawn();
+        }
+    }
+}
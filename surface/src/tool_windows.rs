@@ -0,0 +1,130 @@
+//! Debug tools that can be opened more than once, each window backed by its own entity
+//!
+//! Every open tool takes the lowest free instance number for its kind. The instance gives the
+//! window a title and egui id that don't collide with the other windows of the same kind and
+//! stay the same across restarts, so layouts can place and reopen them.
+
+use bevy::prelude::*;
+use common::{
+    bundles::MovementContributionBundle,
+    components::RobotId,
+    ecs_sync::{NetId, Replicate},
+};
+use egui::Id;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    thruster_allocation::ThrusterAllocationDebugger,
+    ui::{CurrentDrawDebugger, MovementController, MovementDebugger, PidData, PidHelper},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ToolKind {
+    MovementController,
+    MovementDebugger,
+    CurrentDrawDebugger,
+    ThrusterAllocationDebugger,
+    PidHelper,
+}
+
+impl ToolKind {
+    pub const ALL: [ToolKind; 5] = [
+        ToolKind::MovementController,
+        ToolKind::MovementDebugger,
+        ToolKind::CurrentDrawDebugger,
+        ToolKind::ThrusterAllocationDebugger,
+        ToolKind::PidHelper,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToolKind::MovementController => "Movement Controller",
+            ToolKind::MovementDebugger => "Movement Debugger",
+            ToolKind::CurrentDrawDebugger => "Current Draw Debugger",
+            ToolKind::ThrusterAllocationDebugger => "Thruster Allocation",
+            ToolKind::PidHelper => "PID Helper",
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ToolWindow {
+    pub kind: ToolKind,
+    /// Starts at 1
+    pub instance: u32,
+}
+
+impl ToolWindow {
+    /// The first instance is titled by its kind alone
+    pub fn title(&self) -> String {
+        if self.instance == 1 {
+            self.kind.name().to_owned()
+        } else {
+            format!("{} {}", self.kind.name(), self.instance)
+        }
+    }
+
+    pub fn id(&self) -> Id {
+        Id::new(("Tool Window", self.kind, self.instance))
+    }
+
+    pub fn window(&self) -> egui::Window<'static> {
+        egui::Window::new(self.title()).id(self.id())
+    }
+}
+
+/// Opens another window of `kind`
+pub fn open_tool(world: &mut World, kind: ToolKind) -> Entity {
+    let mut tools = world.query::<&ToolWindow>();
+    let mut taken = tools
+        .iter(world)
+        .filter(|it| it.kind == kind)
+        .map(|it| it.instance)
+        .collect::<Vec<_>>();
+    taken.sort_unstable();
+
+    let instance = (1..).find(|it| taken.binary_search(it).is_err()).unwrap();
+
+    open_tool_instance(world, ToolWindow { kind, instance })
+}
+
+/// Opens a specific window, used to restore a layout
+pub fn open_tool_instance(world: &mut World, tool: ToolWindow) -> Entity {
+    let robot = RobotId(NetId::invalid());
+    let mut entity = world.spawn((tool, robot, Replicate));
+
+    match tool.kind {
+        ToolKind::MovementController => {
+            entity.insert((
+                MovementController,
+                MovementContributionBundle {
+                    name: Name::new(format!("Manual {}", tool.title())),
+                    contribution: Default::default(),
+                    robot,
+                },
+            ));
+        }
+        ToolKind::MovementDebugger => {
+            entity.insert(MovementDebugger);
+        }
+        ToolKind::CurrentDrawDebugger => {
+            entity.insert(CurrentDrawDebugger);
+        }
+        ToolKind::ThrusterAllocationDebugger => {
+            entity.insert(ThrusterAllocationDebugger);
+        }
+        ToolKind::PidHelper => {
+            entity.insert((
+                PidData::default(),
+                PidHelper,
+                MovementContributionBundle {
+                    name: Name::new(tool.title()),
+                    contribution: Default::default(),
+                    robot,
+                },
+            ));
+        }
+    }
+
+    entity.id()
+}
@@ -0,0 +1,259 @@
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+use bevy::{input::touch::Touches, prelude::*};
+use egui::{Color32, Response, Sense, Stroke, Ui};
+use leafwing_input_manager::{action_state::ActionState, plugin::InputManagerSystem};
+
+use crate::input::{Action, GamepadRoles, InputMarker, InputRole};
+
+pub struct TouchControlsPlugin;
+
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VirtualInput>().add_systems(
+            PreUpdate,
+            apply_virtual_input.in_set(InputManagerSystem::ManualControl),
+        );
+    }
+}
+
+/// Input from the on screen controls, merged into the `ActionState` of the input entity that owns
+/// each action so it is handled exactly like the gamepad
+#[derive(Resource, Debug, Default)]
+pub struct VirtualInput {
+    /// Yaw and surge, matching the left stick of the default gamepad bindings
+    pub left: Vec2,
+    /// Sway and heave, matching the right stick of the default gamepad bindings
+    pub right: Vec2,
+    /// Touches currently dragging the left and right sticks
+    pub captured: [Option<u64>; 2],
+    pulses: Vec<Pulse>,
+}
+
+/// An input that only lasts a single frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pulse {
+    Press(Action),
+    Nudge(Action, f32),
+}
+
+impl Pulse {
+    fn action(&self) -> &Action {
+        match self {
+            Pulse::Press(action) | Pulse::Nudge(action, _) => action,
+        }
+    }
+}
+
+impl VirtualInput {
+    pub fn press(&mut self, action: Action) {
+        self.pulses.push(Pulse::Press(action));
+    }
+
+    /// Sets an axis action for one frame, used for the depth and heading increment buttons
+    pub fn nudge(&mut self, action: Action, value: f32) {
+        self.pulses.push(Pulse::Nudge(action, value));
+    }
+
+    pub fn release_sticks(&mut self) {
+        self.left = Vec2::ZERO;
+        self.right = Vec2::ZERO;
+        self.captured = [None; 2];
+    }
+
+    fn axes(&self) -> [(Action, f32); 4] {
+        [
+            (Action::Yaw, self.left.x),
+            (Action::Surge, self.left.y),
+            (Action::Sway, self.right.x),
+            (Action::Heave, self.right.y),
+        ]
+    }
+}
+
+fn apply_virtual_input(
+    mut virtual_input: ResMut<VirtualInput>,
+    roles: Res<GamepadRoles>,
+    mut inputs: Query<(&InputRole, &mut ActionState<Action>), With<InputMarker>>,
+    mut last_pulses: Local<Vec<Pulse>>,
+    mut last_axes: Local<Vec<Action>>,
+) {
+    let pulses = mem::take(&mut virtual_input.pulses);
+    let axes = virtual_input.axes();
+
+    for (role, mut action_state) in &mut inputs {
+        let owned = |action: &Action| roles.owner(action) == *role;
+
+        // End last frame's pulses so a repeated press is seen as a new press
+        for pulse in last_pulses.iter().filter(|it| owned(it.action())) {
+            match pulse {
+                Pulse::Press(action) => action_state.release(action),
+                Pulse::Nudge(action, _) => action_state.set_value(action, 0.0),
+            }
+        }
+
+        for pulse in pulses.iter().filter(|it| owned(it.action())) {
+            match pulse {
+                Pulse::Press(action) => action_state.press(action),
+                Pulse::Nudge(action, value) => action_state.set_value(action, *value),
+            }
+        }
+
+        // The sticks override the gamepad while they are touched
+        for (action, value) in axes.iter().filter(|(action, _)| owned(action)) {
+            if *value != 0.0 {
+                action_state.set_value(action, *value);
+            } else if last_axes.contains(action) {
+                action_state.set_value(action, 0.0);
+            }
+        }
+    }
+
+    *last_pulses = pulses;
+    *last_axes = axes
+        .into_iter()
+        .filter(|(_, value)| *value != 0.0)
+        .map(|(action, _)| action)
+        .collect();
+}
+
+/// A thumb stick that can be dragged by touch or with the mouse, springs back to the center when
+/// released. `scale` converts from window coordinates to egui points
+pub fn virtual_stick(
+    ui: &mut Ui,
+    size: f32,
+    value: &mut Vec2,
+    captured: &mut Option<u64>,
+    touches: &Touches,
+    scale: f32,
+) -> Response {
+    let (response, painter) = ui.allocate_painter(egui::Vec2::splat(size), Sense::drag());
+    let rect = response.rect;
+    let center = rect.center();
+
+    let knob_radius = size * 0.15;
+    let travel = size / 2.0 - knob_radius;
+
+    let to_egui = |it: Vec2| egui::pos2(it.x / scale, it.y / scale);
+
+    // Each stick follows its own touch so both can be used at once
+    if let Some(id) = *captured {
+        if touches.get_pressed(id).is_none() {
+            *captured = None;
+        }
+    }
+    if captured.is_none() {
+        *captured = touches
+            .iter_just_pressed()
+            .find(|it| rect.contains(to_egui(it.position())))
+            .map(|it| it.id());
+    }
+
+    let pointer = match *captured {
+        Some(id) => touches.get_pressed(id).map(|it| to_egui(it.position())),
+        None if response.dragged() => response.interact_pointer_pos(),
+        None => None,
+    };
+
+    *value = match pointer {
+        Some(pointer) => {
+            let offset = (pointer - center) / travel;
+            let offset = if offset.length() > 1.0 {
+                offset.normalized()
+            } else {
+                offset
+            };
+
+            // Screen y grows downwards
+            Vec2::new(offset.x, -offset.y)
+        }
+        None => Vec2::ZERO,
+    };
+
+    let visuals = ui.visuals();
+    let stroke = Stroke::new(2.0, visuals.widgets.inactive.fg_stroke.color);
+
+    painter.circle(center, size / 2.0, visuals.extreme_bg_color, stroke);
+    painter.line_segment(
+        [
+            center - egui::vec2(travel, 0.0),
+            center + egui::vec2(travel, 0.0),
+        ],
+        Stroke::new(1.0, visuals.weak_text_color()),
+    );
+    painter.line_segment(
+        [
+            center - egui::vec2(0.0, travel),
+            center + egui::vec2(0.0, travel),
+        ],
+        Stroke::new(1.0, visuals.weak_text_color()),
+    );
+
+    let knob = center + egui::vec2(value.x, -value.y) * travel;
+    let knob_color = if pointer.is_some() {
+        visuals.selection.bg_fill
+    } else {
+        visuals.widgets.inactive.bg_fill
+    };
+    painter.circle(knob, knob_radius, knob_color, stroke);
+
+    response
+}
+
+/// State of a `hold_button`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HoldState {
+    since: Option<Instant>,
+    fired: bool,
+}
+
+/// A button that only activates after being held for `hold`, returns true on the frame it
+/// activates. The fill shows how long is left
+pub fn hold_button(
+    ui: &mut Ui,
+    text: &str,
+    size: egui::Vec2,
+    color: Color32,
+    hold: Duration,
+    state: &mut HoldState,
+) -> bool {
+    let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+    let rect = response.rect;
+
+    if response.is_pointer_button_down_on() {
+        state.since.get_or_insert_with(Instant::now);
+    } else {
+        *state = HoldState::default();
+    }
+
+    let progress = state
+        .since
+        .map(|it| (it.elapsed().as_secs_f32() / hold.as_secs_f32()).min(1.0))
+        .unwrap_or(0.0);
+
+    let activated = progress >= 1.0 && !state.fired;
+    if activated {
+        state.fired = true;
+    }
+
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 8.0, visuals.extreme_bg_color);
+    painter.rect_filled(
+        rect.with_max_x(rect.left() + rect.width() * progress),
+        8.0,
+        color.gamma_multiply(0.6),
+    );
+    painter.rect_stroke(rect, 8.0, Stroke::new(3.0, color), egui::StrokeKind::Inside);
+    painter.text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        text,
+        egui::FontId::proportional(size.y * 0.4),
+        visuals.strong_text_color(),
+    );
+
+    activated
+}
@@ -1,50 +1,98 @@
 use std::{
     collections::{hash_map::Entry, BTreeMap, VecDeque},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use ahash::HashMap;
-use bevy::{app::AppExit, math::vec3a, prelude::*};
-use bevy_egui::{EguiContexts, EguiPlugin};
+use bevy::{app::AppExit, math::vec3a, prelude::*, window::PrimaryWindow};
+use bevy_egui::{EguiContextSettings, EguiContexts, EguiPlugin};
 use bevy_tokio_tasks::TokioTasksRuntime;
 use common::{
-    bundles::MovementContributionBundle,
     components::{
-        ActualMovement, Armed, CameraDefinition, CurrentDraw, DepthMeasurement, DepthTarget,
-        DisableMovementApi, GenericMotorId, MeasuredVoltage, MotorRawSignalRange, MotorSignal,
-        MovementAxisMaximums, MovementContribution, OrientationTarget, PidController, PidResult,
-        Robot, RobotId, SystemCpuTotal, SystemLoadAverage, SystemMemory, SystemTemperatures,
-        TargetMovement, TempertureMeasurement, ThrusterDefinition,
+        ActiveServoPreset, ActualMovement, ActuationLatency, ActuatorIdentity, Armed, BatteryFault,
+        BatteryStatus, BottomLockStatus, CameraCapabilities, CameraControls, CameraDefinition,
+        CameraStream, CurrentDraw, DepthMeasurement, DepthTarget, DisableMovementApi, Heading,
+        LatencyIdentification, LevelTrim, LockState, MaintenanceRoutine, MeasuredVoltage,
+        MotorRawSignalRange, MotorSignal, MovementAxisMaximums, MovementContribution, Orientation,
+        OrientationTarget, PidController, PidResult, PilotModes, Robot, RobotId, Salinity,
+        ServoPresets, SystemCpuTotal, SystemLoadAverage, SystemMemory, SystemTemperatures,
+        TargetMovement, TempertureMeasurement, TetherTurns, ThermalDerate, ThrusterDefinition,
+        WaterTemperature,
     },
-    ecs_sync::{NetId, Replicate},
-    events::{CalibrateSeaLevel, ResetServos, ResetYaw, ResyncCameras},
+    ecs_sync::NetId,
+    events::{
+        CalibrateLevel, CalibrateSeaLevel, ClearLevelTrim, FetchLogs, GeofenceOverride,
+        GeofenceOverrideStep, GoToServoPreset, IdentifyActuationLatency, MaintenanceCommand,
+        MaintenanceStep, PowerAction, ResetServos, ResetTetherTurns, ResetYaw, ResyncCameras,
+    },
+    params::{AppParamExt, Param, Params},
     sync::{ConnectToPeer, DisconnectPeer, Latency, MdnsPeers, Peer},
-    types::units::Amperes,
+    types::{
+        pilot::PilotMode,
+        units::{Amperes, UnitPreferences, Volts},
+        video::CameraQuality,
+    },
 };
 use egui::{
-    load::SizedTexture, text::LayoutJob, widgets, Align, Color32, Id, Label, Layout, RichText,
-    ScrollArea, Sense, TextBuffer, TextFormat, Visuals, Widget,
+    load::SizedTexture, text::LayoutJob, widgets, Align, Color32, Id, Label, LayerId, Layout,
+    Order, RichText, ScrollArea, Sense, TextBuffer, TextFormat, Widget,
 };
-use egui_plot::{Line, Plot, PlotPoint};
-use leafwing_input_manager::input_map::InputMap;
+use egui_plot::{Line, LineStyle, Plot, PlotPoint, Points, VLine};
+use leafwing_input_manager::{action_state::ActionState, input_map::InputMap};
 use motor_math::{glam::MovementGlam, solve::reverse::Axis};
-use tokio::net::lookup_host;
 
 use crate::{
     attitude::OrientationDisplay,
-    input::{Action, InputInterpolation, InputMarker, SelectedServo},
-    photosphere::{PhotoSphere, RotatePhotoSphere, SpawnPhotoSphere},
-    video_display_2d_master::VideoMasterMarker,
+    bindings::{BindingCapture, BindingProfiles, KeyboardPiloting, KeyboardPilotingMode},
+    callouts::CalloutSettings,
+    checklist::{ChecklistRun, Checklists, ExportChecklist},
+    dive_log::{self, DiveEventKind, DiveLog, ExportDiveLog},
+    flight_display,
+    heading_assist::{self, HEADING_ASSIST},
+    hid::HidPanel,
+    input::{
+        Action, GamepadRoles, InputInterpolation, InputMarker, InputRole, LevelingType,
+        SelectedServo,
+    },
+    input_shaping::{AxisInputs, ShapedAxis},
+    layout::{
+        ApplyLayout, DockSide, DockWindow, DockableWindow, LayoutWindow, SaveLayout, UiDock,
+        UiLayouts,
+    },
+    measurement::picking::StartPoiPicking,
+    notifications::{Notifications, TOAST_DURATION},
+    photogrammetry::CaptureBundle,
+    photosphere::{
+        coverage_map, targets::PhotoSphereSettings, ExportPhotoSphere, GoToNextPhotoSphereTarget,
+        PhotoSphere, RotatePhotoSphere, SpawnPhotoSphere,
+    },
+    pid_tuning::PidAutoTune,
+    plotting::{ExportPlot, PlotBrowser, PlotSeries, PlotWorkspace},
+    robot_power::ConfirmPowerCommand,
+    settings::{connect_to_host, LinkGrade, SurfaceSettings, Theme},
+    snapshot::CaptureStill,
+    surface::LocalSurfaceMarker,
+    tool_windows::{self, ToolKind, ToolWindow},
+    touch::{self, HoldState, VirtualInput},
+    video_display_2d_master::{
+        CameraLayoutEvent, CameraLayoutMode, CameraLayouts, VideoDisplay2DSettings,
+        VideoMasterMarker,
+    },
     video_pipelines::VideoPipelines,
-    video_stream::{VideoProcessorFactory, VideoThread},
-    DARK_MODE,
+    video_stream::{
+        adaptive::{AdaptiveQuality, QualityOverride},
+        recording::{Recording, RecordingOverlays, RecordingSession, SetRecording, LOW_FREE_SPACE},
+        watchdog::CameraHealth,
+        FrameDecimation, VideoDecoder, VideoProcessorFactory, VideoThread,
+    },
 };
 
 pub struct EguiUiPlugin;
 
 impl Plugin for EguiUiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, set_style);
+        app.register_param(&PID_DISTURBANCE_TIME);
+
         app.add_plugins(EguiPlugin).add_systems(
             Update,
             // TODO: create a system set for `.after(topbar)` and move each
@@ -65,6 +113,37 @@ impl Plugin for EguiUiPlugin {
                     .after(topbar)
                     .run_if(resource_removed::<PwmControl>),
                 timer.after(topbar).run_if(resource_exists::<TimerUi>),
+                gamepads.after(topbar).run_if(resource_exists::<GamepadUi>),
+                bindings.after(topbar).run_if(resource_exists::<BindingsUi>),
+                input_shaping
+                    .after(topbar)
+                    .run_if(resource_exists::<InputShapingUi>),
+                pilot_modes
+                    .after(topbar)
+                    .run_if(resource_exists::<PilotModesUi>),
+                checklist
+                    .after(topbar)
+                    .run_if(resource_exists::<ChecklistUi>),
+                plotting.after(topbar).run_if(resource_exists::<PlotUi>),
+                cleanup_plotting
+                    .after(topbar)
+                    .run_if(resource_removed::<PlotUi>),
+                dive_log.after(topbar).run_if(resource_exists::<DiveLogUi>),
+                touch_controls
+                    .after(topbar)
+                    .run_if(resource_exists::<TouchControlsUi>),
+                cleanup_touch_controls
+                    .after(topbar)
+                    .run_if(resource_removed::<TouchControlsUi>),
+                keyboard_hints
+                    .after(topbar)
+                    .run_if(|piloting: Res<KeyboardPiloting>| piloting.active),
+                toasts
+                    .after(topbar)
+                    .run_if(|notifications: Res<Notifications>| !notifications.toasts.is_empty()),
+                alarms
+                    .after(topbar)
+                    .run_if(|notifications: Res<Notifications>| notifications.has_unacknowledged()),
             ),
         );
     }
@@ -73,12 +152,163 @@ impl Plugin for EguiUiPlugin {
 #[derive(Resource)]
 pub struct ShowInspector;
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct PwmControl(bool);
 
 #[derive(Resource)]
 pub struct TimerUi(TimerState, TimerType);
 
+impl Default for TimerUi {
+    fn default() -> Self {
+        TimerUi(
+            TimerState::Paused {
+                elapsed: Duration::ZERO,
+            },
+            TimerType::Setup,
+        )
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct GamepadUi;
+
+#[derive(Resource, Default)]
+pub struct InputShapingUi;
+
+#[derive(Resource, Default)]
+pub struct BindingsUi {
+    new_profile: String,
+}
+
+#[derive(Resource, Default)]
+pub struct ChecklistUi;
+
+#[derive(Resource, Default)]
+pub struct PlotUi;
+
+#[derive(Resource, Default)]
+pub struct DiveLogUi;
+
+#[derive(Resource, Default)]
+pub struct StereoUi;
+
+#[derive(Resource, Default)]
+pub struct PipelineGraphUi;
+
+#[derive(Resource, Default)]
+pub struct BehaviorsUi;
+
+#[derive(Resource, Default)]
+pub struct ScriptConsoleUi;
+
+#[derive(Resource, Default)]
+pub struct TelemetryExportUi;
+
+#[derive(Resource, Default)]
+pub struct DashboardUi;
+
+#[derive(Resource, Default)]
+pub struct RobotViewUi;
+
+#[derive(Resource, Default)]
+pub struct MotorEditorUi;
+
+#[derive(Resource, Default)]
+pub struct SessionUi;
+
+#[derive(Resource, Default)]
+pub struct CalloutsUi;
+
+#[derive(Resource, Default)]
+pub struct SettingsUi;
+
+#[derive(Resource, Default)]
+pub struct RobotUpdateUi;
+
+#[derive(Resource, Default)]
+pub struct HistoryUi;
+
+#[derive(Resource, Default)]
+pub struct FrameBudgetUi;
+
+#[derive(Resource, Default)]
+pub struct ParametersUi;
+
+#[derive(Resource, Default)]
+pub struct ReplayUi {
+    /// The recording session folder to replay
+    pub folder: String,
+}
+
+#[derive(Resource, Default)]
+pub struct TouchControlsUi {
+    arm: HoldState,
+    disarm: HoldState,
+}
+
+#[derive(Resource, Default)]
+pub struct PilotModesUi {
+    new_mode: String,
+}
+
+#[derive(Resource, Default)]
+pub struct CameraControlsUi {
+    pub camera: Option<Entity>,
+    /// Controls being dragged, sent to the robot once the drag ends
+    pub pending: Option<(Entity, CameraControls)>,
+}
+
+/// The windows placed by `UiLayouts`, titles must match the titles passed to `egui::Window`
+pub const LAYOUT_WINDOWS: &[LayoutWindow] = &[
+    layout_window::<PwmControl>("PWM Control"),
+    layout_window::<TimerUi>("Timer"),
+    layout_window::<GamepadUi>("Gamepads"),
+    layout_window::<BindingsUi>("Bindings"),
+    layout_window::<InputShapingUi>("Input Shaping"),
+    layout_window::<PilotModesUi>("Pilot Modes"),
+    layout_window::<ChecklistUi>("Checklist"),
+    layout_window::<PlotUi>("Plotting"),
+    layout_window::<DiveLogUi>("Dive Log"),
+    layout_window::<TouchControlsUi>("Touch Controls"),
+    layout_window::<StereoUi>("Stereo"),
+    layout_window::<PipelineGraphUi>("Pipeline Graph"),
+    layout_window::<BehaviorsUi>("Behaviors"),
+    layout_window::<ScriptConsoleUi>("Script Console"),
+    layout_window::<TelemetryExportUi>("Telemetry Export"),
+    layout_window::<DashboardUi>("Web Dashboard"),
+    layout_window::<RobotViewUi>("Robot View"),
+    layout_window::<MotorEditorUi>("Motor Editor"),
+    layout_window::<SessionUi>("Sessions"),
+    layout_window::<CalloutsUi>("Callouts"),
+    layout_window::<SettingsUi>("Settings"),
+    layout_window::<RobotUpdateUi>("Robot Update"),
+    layout_window::<HistoryUi>("History"),
+    layout_window::<FrameBudgetUi>("Frame Budget"),
+    layout_window::<ParametersUi>("Parameters"),
+    layout_window::<CameraControlsUi>("Camera Controls"),
+    layout_window::<ReplayUi>("Replay"),
+];
+
+const fn layout_window<R: Resource + Default>(title: &'static str) -> LayoutWindow {
+    LayoutWindow {
+        title,
+        set_open: set_resource_open::<R>,
+        is_open: resource_open::<R>,
+    }
+}
+
+fn set_resource_open<R: Resource + Default>(world: &mut World, open: bool) {
+    if open {
+        world.init_resource::<R>();
+    } else {
+        world.remove_resource::<R>();
+    }
+}
+
+fn resource_open<R: Resource>(world: &World) -> bool {
+    world.contains_resource::<R>()
+}
+
 pub enum TimerState {
     Running { start: Duration, offset: Duration },
     Paused { elapsed: Duration },
@@ -103,15 +333,7 @@ pub struct CurrentDrawDebugger;
 #[derive(Component)]
 pub struct PidHelper;
 
-fn set_style(mut contexts: EguiContexts) {
-    contexts.ctx_mut().set_visuals(if DARK_MODE {
-        Visuals::dark()
-    } else {
-        Visuals::light()
-    });
-}
-
-fn topbar(
+pub fn topbar(
     mut cmds: Commands,
     mut contexts: EguiContexts,
 
@@ -122,19 +344,106 @@ fn topbar(
             &Armed,
             Option<&DepthTarget>,
             Option<&OrientationTarget>,
+            Option<(&ServoPresets, Option<&ActiveServoPreset>)>,
+            Option<&LevelTrim>,
         ),
         With<Robot>,
     >,
 
     cameras: Query<
-        (Entity, &Name, Option<&VideoProcessorFactory>),
+        (
+            Entity,
+            &Name,
+            Option<&VideoProcessorFactory>,
+            Option<&Recording>,
+            (
+                Option<&CameraStream>,
+                Option<&CameraCapabilities>,
+                Option<&VideoDecoder>,
+                Option<&QualityOverride>,
+            ),
+        ),
         (With<CameraDefinition>, With<VideoThread>),
     >,
     pipelines: Res<VideoPipelines>,
+    (
+        camera_layouts,
+        mut display_settings,
+        recording_session,
+        mut recording_overlays,
+        mut adaptive_quality,
+    ): (
+        Res<CameraLayouts>,
+        ResMut<VideoDisplay2DSettings>,
+        Option<Res<RecordingSession>>,
+        ResMut<RecordingOverlays>,
+        ResMut<AdaptiveQuality>,
+    ),
 
     inspector: Option<Res<ShowInspector>>,
     pwm_control: Option<Res<PwmControl>>,
     timer_ui: Option<Res<TimerUi>>,
+    (
+        gamepad_ui,
+        bindings_ui,
+        input_shaping_ui,
+        pilot_modes_ui,
+        checklist_ui,
+        plot_ui,
+        dive_log_ui,
+        touch_controls_ui,
+        stereo_ui,
+        pipeline_graph_ui,
+        behaviors_ui,
+        script_console_ui,
+        telemetry_export_ui,
+        dashboard_ui,
+        robot_view_ui,
+    ): (
+        Option<Res<GamepadUi>>,
+        Option<Res<BindingsUi>>,
+        Option<Res<InputShapingUi>>,
+        Option<Res<PilotModesUi>>,
+        Option<Res<ChecklistUi>>,
+        Option<Res<PlotUi>>,
+        Option<Res<DiveLogUi>>,
+        Option<Res<TouchControlsUi>>,
+        Option<Res<StereoUi>>,
+        Option<Res<PipelineGraphUi>>,
+        Option<Res<BehaviorsUi>>,
+        Option<Res<ScriptConsoleUi>>,
+        Option<Res<TelemetryExportUi>>,
+        Option<Res<DashboardUi>>,
+        Option<Res<RobotViewUi>>,
+    ),
+    (
+        motor_editor_ui,
+        session_ui,
+        callouts_ui,
+        settings_ui,
+        robot_update_ui,
+        history_ui,
+        frame_budget_ui,
+        parameters_ui,
+        replay_ui,
+        tools,
+        mut settings,
+    ): (
+        Option<Res<MotorEditorUi>>,
+        Option<Res<SessionUi>>,
+        Option<Res<CalloutsUi>>,
+        Option<Res<SettingsUi>>,
+        Option<Res<RobotUpdateUi>>,
+        Option<Res<HistoryUi>>,
+        Option<Res<FrameBudgetUi>>,
+        Option<Res<ParametersUi>>,
+        Option<Res<ReplayUi>>,
+        Query<(Entity, &ToolWindow)>,
+        ResMut<SurfaceSettings>,
+    ),
+    mut keyboard_piloting: ResMut<KeyboardPiloting>,
+    (layouts, dock): (Res<UiLayouts>, Res<UiDock>),
+    mut new_layout: Local<String>,
 
     peers: Query<(&Peer, Option<&Name>)>,
     mut disconnect: EventWriter<DisconnectPeer>,
@@ -160,6 +469,43 @@ fn topbar(
                     }
                 });
 
+                ui.menu_button("Fetch Robot Logs", |ui| {
+                    let fetch = [
+                        ("Last Hour", Some(Duration::from_secs(60 * 60))),
+                        ("Last Day", Some(Duration::from_secs(24 * 60 * 60))),
+                        ("All", None),
+                    ];
+
+                    for (label, window) in fetch {
+                        if ui.button(label).clicked() {
+                            let since_ms = window
+                                .and_then(|it| SystemTime::now().checked_sub(it))
+                                .and_then(|it| it.duration_since(UNIX_EPOCH).ok())
+                                .map(|it| it.as_millis() as u64)
+                                .unwrap_or(0);
+
+                            cmds.queue(move |world: &mut World| {
+                                world.send_event(FetchLogs { since_ms });
+                            })
+                        }
+                    }
+                });
+
+                ui.menu_button("Robot Power", |ui| {
+                    for action in PowerAction::ALL {
+                        if ui.button(action.name()).clicked() {
+                            cmds.insert_resource(ConfirmPowerCommand(action));
+                        }
+                    }
+                });
+
+                // Confirmed in the geofence window once the robot answers
+                if ui.button("Override Geofence").clicked() {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(GeofenceOverride(GeofenceOverrideStep::Request));
+                    })
+                }
+
                 if ui.button("Exit").clicked() {
                     cmds.queue(|world: &mut World| {
                         world.send_event(AppExit::Success);
@@ -180,11 +526,98 @@ fn topbar(
                     })
                 }
 
+                ui.menu_button("Servo Presets", |ui| {
+                    let mut any = false;
+
+                    for (_, robot, _, _, _, presets, _) in &robots {
+                        let Some((presets, active)) = presets else {
+                            continue;
+                        };
+
+                        if robots.iter().count() > 1 {
+                            ui.label(robot.as_str());
+                        }
+
+                        for preset in &presets.0 {
+                            any = true;
+
+                            let active = active.filter(|it| it.name == preset.name);
+                            let response = ui.selectable_label(active.is_some(), &preset.name);
+                            let response = match active {
+                                Some(active) if !active.arrived => response.on_hover_text("Moving"),
+                                _ => response,
+                            };
+
+                            if response.clicked() {
+                                let event = GoToServoPreset {
+                                    preset: preset.name.clone(),
+                                    rate: None,
+                                };
+                                cmds.queue(move |world: &mut World| {
+                                    world.send_event(event);
+                                });
+                            }
+                        }
+                    }
+
+                    if !any {
+                        ui.label("No presets");
+                    }
+                });
+
                 if ui.button("Reset Yaw").clicked() {
                     cmds.queue(|world: &mut World| {
                         world.send_event(ResetYaw);
                     })
                 }
+
+                let trims = robots
+                    .iter()
+                    .filter_map(|(_, robot, .., trim)| {
+                        let trim = trim?;
+                        Some(format!("{robot}: {} pitch, {} roll", trim.pitch, trim.roll))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let calibrate = ui
+                    .button("Calibrate Level")
+                    .on_hover_text("Records the robot's current pitch and roll as level");
+                if calibrate.clicked() {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(CalibrateLevel);
+                    })
+                }
+
+                let clear = ui.button("Clear Level Trim");
+                let clear = if trims.is_empty() {
+                    clear
+                } else {
+                    clear.on_hover_text(trims)
+                };
+                if clear.clicked() {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(ClearLevelTrim);
+                    })
+                }
+            });
+
+            ui.menu_button("Maintenance", |ui| {
+                // Routines drive the actuators, so the robot only runs them while disarmed
+                let armed = robots
+                    .iter()
+                    .any(|(_, _, armed, ..)| *armed == Armed::Armed);
+
+                for routine in MaintenanceRoutine::ALL {
+                    let button = ui
+                        .add_enabled(!armed, egui::Button::new(routine.name()))
+                        .on_disabled_hover_text("Disarm the robot first");
+                    if button.clicked() {
+                        cmds.queue(move |world: &mut World| {
+                            world.send_event(MaintenanceCommand(MaintenanceStep::Request(routine)));
+                        })
+                    }
+                }
             });
 
             ui.menu_button("Cameras", |ui| {
@@ -194,16 +627,249 @@ fn topbar(
                     })
                 }
 
-                // TODO: Hide/Show All
+                let camera_layout = camera_layouts.current();
+                let send_layout_event = |cmds: &mut Commands, event: CameraLayoutEvent| {
+                    cmds.queue(move |world: &mut World| {
+                        world.send_event(event);
+                    });
+                };
+
+                ui.menu_button("Layout", |ui| {
+                    for mode in CameraLayoutMode::ALL {
+                        if ui
+                            .selectable_label(camera_layout.mode == mode, mode.name())
+                            .clicked()
+                        {
+                            send_layout_event(&mut cmds, CameraLayoutEvent::SetMode(mode));
+                        }
+                    }
+
+                    ui.separator();
+
+                    ui.menu_button(
+                        format!("Side Cameras: {}", camera_layout.tiles.name()),
+                        |ui| {
+                            for (name, decimation) in FrameDecimation::PRESETS {
+                                if ui
+                                    .selectable_label(camera_layout.tiles == decimation, name)
+                                    .clicked()
+                                {
+                                    send_layout_event(
+                                        &mut cmds,
+                                        CameraLayoutEvent::SetTileDecimation(decimation),
+                                    );
+                                }
+                            }
+                        },
+                    );
+
+                    ui.checkbox(&mut display_settings.show_fps, "Show Display FPS");
+                });
+
+                if ui.button("Capture Still").clicked() {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(CaptureStill);
+                    })
+                }
+
+                if ui.button("Capture Photogrammetry Bundle").clicked() {
+                    cmds.queue(|world: &mut World| {
+                        world.send_event(CaptureBundle);
+                    })
+                }
+
+                if ui.button("Next Camera").clicked() {
+                    send_layout_event(&mut cmds, CameraLayoutEvent::CycleMaster);
+                }
+
+                if ui
+                    .add_enabled(
+                        !camera_layout.hidden.is_empty(),
+                        egui::Button::new("Show All"),
+                    )
+                    .clicked()
+                {
+                    send_layout_event(&mut cmds, CameraLayoutEvent::ShowAll);
+                }
+
+                if ui.button("Hide All").clicked() {
+                    for (_, name, ..) in &cameras {
+                        send_layout_event(
+                            &mut cmds,
+                            CameraLayoutEvent::SetHidden {
+                                camera: name.to_string(),
+                                hidden: true,
+                            },
+                        );
+                    }
+                }
+
+                ui.separator();
+
+                let send_recording = |cmds: &mut Commands, event: SetRecording| {
+                    cmds.queue(move |world: &mut World| {
+                        world.send_event(event);
+                    });
+                };
+
+                let recording_count = cameras.iter().filter(|it| it.3.is_some()).count();
+                if ui
+                    .add_enabled(
+                        recording_count < cameras.iter().len(),
+                        egui::Button::new("Record All"),
+                    )
+                    .clicked()
+                {
+                    send_recording(
+                        &mut cmds,
+                        SetRecording {
+                            camera: None,
+                            record: true,
+                        },
+                    );
+                }
+
+                if ui
+                    .add_enabled(recording_count > 0, egui::Button::new("Stop Recording"))
+                    .clicked()
+                {
+                    send_recording(
+                        &mut cmds,
+                        SetRecording {
+                            camera: None,
+                            record: false,
+                        },
+                    );
+                }
+
+                ui.separator();
+
+                let link = adaptive_quality.link;
+                ui.checkbox(&mut adaptive_quality.enabled, "Adaptive Quality");
+                if let Some(robot_tx) = link.robot_tx {
+                    ui.label(format!("Robot TX: {:.1} Mbit/s", robot_tx * 8.0 / 1e6));
+                }
+                if let Some(ratio) = link.worst_fps_ratio {
+                    ui.label(format!("Worst Framerate: {:.0}%", ratio * 100.0));
+                }
+                if let Some(rtt) = link.rtt {
+                    ui.label(format!(
+                        "Round Trip: {:.0}ms, Jitter: {:.1}ms, Missed: {:.0}%",
+                        rtt.as_secs_f32() * 1000.0,
+                        link.jitter.as_secs_f32() * 1000.0,
+                        link.loss * 100.0
+                    ));
+                }
+                if link.saturated {
+                    ui.colored_label(Color32::YELLOW, "Link Saturated");
+                }
+
+                ui.separator();
 
                 let cameras = cameras
                     .iter()
                     .map(|it| (it.1.as_str(), it))
                     .collect::<BTreeMap<_, _>>();
 
-                for (entity, name, processor) in cameras.values() {
+                for (
+                    entity,
+                    name,
+                    processor,
+                    recording,
+                    (stream, capabilities, decoder, quality_override),
+                ) in cameras.values()
+                {
                     ui.menu_button(name.as_str(), |ui| {
-                        // TODO: Hide/Show
+                        let mut shown = !camera_layout.hidden.contains(name.as_str());
+                        if ui.checkbox(&mut shown, "Shown").changed() {
+                            send_layout_event(
+                                &mut cmds,
+                                CameraLayoutEvent::SetHidden {
+                                    camera: name.to_string(),
+                                    hidden: !shown,
+                                },
+                            );
+                        }
+
+                        let mut record = recording.is_some();
+                        if ui.checkbox(&mut record, "Record").changed() {
+                            send_recording(
+                                &mut cmds,
+                                SetRecording {
+                                    camera: Some(*entity),
+                                    record,
+                                },
+                            );
+                        }
+
+                        let mut burn_in = recording_overlays.cameras.contains(name.as_str());
+                        if ui
+                            .checkbox(&mut burn_in, "Burn In Overlay")
+                            .on_hover_text(
+                                "Adds the time, depth and heading to this camera's recordings",
+                            )
+                            .changed()
+                        {
+                            if burn_in {
+                                recording_overlays.cameras.insert(name.to_string());
+                            } else {
+                                recording_overlays.cameras.remove(name.as_str());
+                            }
+                        }
+
+                        if ui.button("Image Controls").clicked() {
+                            cmds.insert_resource(CameraControlsUi {
+                                camera: Some(*entity),
+                                pending: None,
+                            });
+                        }
+
+                        let decoder = decoder.copied().unwrap_or_default();
+                        ui.menu_button(format!("Decoder: {}", decoder.name()), |ui| {
+                            if let Some(capabilities) = capabilities {
+                                ui.label(&capabilities.card);
+
+                                if let Some(format) = &capabilities.active {
+                                    ui.label(format.to_string());
+                                }
+                            }
+                            if let Some(stream) = stream {
+                                ui.label(format!("{} over {}", stream.codec, stream.transport));
+                            }
+                            ui.separator();
+
+                            for option in VideoDecoder::ALL {
+                                if ui
+                                    .selectable_label(decoder == option, option.name())
+                                    .clicked()
+                                {
+                                    cmds.entity(*entity).insert(option);
+                                }
+                            }
+                        });
+
+                        let quality = stream.map(|it| it.quality).unwrap_or_default();
+                        let quality_label = match quality_override {
+                            Some(_) => format!("Quality: {}", quality.name()),
+                            None => format!("Quality: {} (Auto)", quality.name()),
+                        };
+                        ui.menu_button(quality_label, |ui| {
+                            if ui
+                                .selectable_label(quality_override.is_none(), "Auto")
+                                .clicked()
+                            {
+                                cmds.entity(*entity).remove::<QualityOverride>();
+                            }
+
+                            for option in CameraQuality::ALL {
+                                let selected = quality_override.map(|it| it.0) == Some(option);
+                                if ui.selectable_label(selected, option.name()).clicked() {
+                                    cmds.entity(*entity).insert(QualityOverride(option));
+                                }
+                            }
+                        });
+
+                        ui.separator();
 
                         let processor_name = processor.map(|it| &it.name);
 
@@ -225,6 +891,18 @@ fn topbar(
             });
 
             ui.menu_button("View", |ui| {
+                ui.menu_button("Theme", |ui| {
+                    for theme in Theme::ALL {
+                        if ui
+                            .selectable_label(settings.theme == theme, theme.name())
+                            .clicked()
+                            && settings.theme != theme
+                        {
+                            settings.theme = theme;
+                        }
+                    }
+                });
+
                 if ui
                     .selectable_label(inspector.is_some(), "ECS Inspector")
                     .clicked()
@@ -236,38 +914,38 @@ fn topbar(
                     }
                 }
 
-                if ui.button("Movement Controller").clicked() {
-                    cmds.spawn((
-                        MovementController,
-                        MovementContributionBundle {
-                            name: Name::new("Manual Movement Controller"),
-                            contribution: Default::default(),
-                            robot: RobotId(NetId::invalid()),
-                        },
-                        Replicate,
-                    ));
+                if ui.button("Pick Points On Video").clicked() {
+                    cmds.trigger(StartPoiPicking);
                 }
 
-                if ui.button("Movement Debugger").clicked() {
-                    cmds.spawn((MovementDebugger, Replicate, RobotId(NetId::invalid())));
+                for kind in ToolKind::ALL {
+                    if ui.button(kind.name()).clicked() {
+                        cmds.queue(move |world: &mut World| {
+                            tool_windows::open_tool(world, kind);
+                        });
+                    }
                 }
 
-                if ui.button("Current Draw Debugger").clicked() {
-                    cmds.spawn((CurrentDrawDebugger, Replicate, RobotId(NetId::invalid())));
-                }
+                ui.add_enabled_ui(!tools.is_empty(), |ui| {
+                    ui.menu_button("Open Tools", |ui| {
+                        let mut tools = tools.iter().collect::<Vec<_>>();
+                        tools.sort_by_key(|(_, tool)| (tool.kind, tool.instance));
 
-                if ui.button("PID Helper").clicked() {
-                    cmds.spawn((
-                        PidData::default(),
-                        PidHelper,
-                        MovementContributionBundle {
-                            name: Name::new("PID Helper"),
-                            contribution: Default::default(),
-                            robot: RobotId(NetId::invalid()),
-                        },
-                        Replicate,
-                    ));
-                }
+                        for (entity, tool) in tools {
+                            ui.horizontal(|ui| {
+                                ui.label(tool.title());
+
+                                if ui.small_button("Focus").clicked() {
+                                    ui.ctx().move_to_top(LayerId::new(Order::Middle, tool.id()));
+                                    ui.close_menu();
+                                }
+                                if ui.small_button("Close").clicked() {
+                                    cmds.entity(entity).despawn_recursive();
+                                }
+                            });
+                        }
+                    });
+                });
 
                 if ui
                     .selectable_label(pwm_control.is_some(), "PWM Control")
@@ -284,50 +962,386 @@ fn topbar(
                     if timer_ui.is_some() {
                         cmds.remove_resource::<TimerUi>()
                     } else {
-                        cmds.insert_resource(TimerUi(
-                            TimerState::Paused {
-                                elapsed: Duration::ZERO,
-                            },
-                            TimerType::Setup,
-                        ));
+                        cmds.init_resource::<TimerUi>();
                     }
                 }
 
-                if ui.button("Photo Sphere").clicked() {
-                    for (robot, ..) in robots.iter() {
-                        cmds.entity(robot).trigger(SpawnPhotoSphere);
+                if ui
+                    .selectable_label(gamepad_ui.is_some(), "Gamepads")
+                    .clicked()
+                {
+                    if gamepad_ui.is_some() {
+                        cmds.remove_resource::<GamepadUi>()
+                    } else {
+                        cmds.insert_resource(GamepadUi);
                     }
-                    // cmds.trigger(SpawnPhotoSphere);
                 }
-            });
 
-            // RTL needs reverse order
-            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                if !robots.is_empty() {
-                    let mut layout_job = LayoutJob::default();
+                if ui
+                    .selectable_label(bindings_ui.is_some(), "Bindings")
+                    .clicked()
+                {
+                    if bindings_ui.is_some() {
+                        cmds.remove_resource::<BindingsUi>()
+                    } else {
+                        cmds.init_resource::<BindingsUi>();
+                    }
+                }
 
-                    for (_entity, robot, state, depth_target, orientation_target) in &robots {
-                        layout_job.append(
-                            robot.as_str(),
-                            20.0,
-                            TextFormat {
-                                color: if DARK_MODE {
-                                    Color32::WHITE
-                                } else {
-                                    Color32::BLACK
-                                },
-                                ..default()
-                            },
-                        );
+                if ui
+                    .selectable_label(input_shaping_ui.is_some(), "Input Shaping")
+                    .clicked()
+                {
+                    if input_shaping_ui.is_some() {
+                        cmds.remove_resource::<InputShapingUi>()
+                    } else {
+                        cmds.insert_resource(InputShapingUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(pilot_modes_ui.is_some(), "Pilot Modes")
+                    .clicked()
+                {
+                    if pilot_modes_ui.is_some() {
+                        cmds.remove_resource::<PilotModesUi>()
+                    } else {
+                        cmds.init_resource::<PilotModesUi>();
+                    }
+                }
+
+                if ui
+                    .selectable_label(checklist_ui.is_some(), "Checklist")
+                    .clicked()
+                {
+                    if checklist_ui.is_some() {
+                        cmds.remove_resource::<ChecklistUi>()
+                    } else {
+                        cmds.insert_resource(ChecklistUi);
+                    }
+                }
+
+                if ui.selectable_label(plot_ui.is_some(), "Plotting").clicked() {
+                    if plot_ui.is_some() {
+                        cmds.remove_resource::<PlotUi>()
+                    } else {
+                        cmds.insert_resource(PlotUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(dive_log_ui.is_some(), "Dive Log")
+                    .clicked()
+                {
+                    if dive_log_ui.is_some() {
+                        cmds.remove_resource::<DiveLogUi>()
+                    } else {
+                        cmds.insert_resource(DiveLogUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(touch_controls_ui.is_some(), "Touch Controls")
+                    .clicked()
+                {
+                    if touch_controls_ui.is_some() {
+                        cmds.remove_resource::<TouchControlsUi>()
+                    } else {
+                        cmds.init_resource::<TouchControlsUi>();
+                    }
+                }
+
+                if ui.selectable_label(stereo_ui.is_some(), "Stereo").clicked() {
+                    if stereo_ui.is_some() {
+                        cmds.remove_resource::<StereoUi>()
+                    } else {
+                        cmds.insert_resource(StereoUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(pipeline_graph_ui.is_some(), "Pipeline Graph")
+                    .clicked()
+                {
+                    if pipeline_graph_ui.is_some() {
+                        cmds.remove_resource::<PipelineGraphUi>()
+                    } else {
+                        cmds.insert_resource(PipelineGraphUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(behaviors_ui.is_some(), "Behaviors")
+                    .clicked()
+                {
+                    if behaviors_ui.is_some() {
+                        cmds.remove_resource::<BehaviorsUi>()
+                    } else {
+                        cmds.insert_resource(BehaviorsUi);
+                    }
+                }
+
+                if cfg!(feature = "scripting")
+                    && ui
+                        .selectable_label(script_console_ui.is_some(), "Script Console")
+                        .clicked()
+                {
+                    if script_console_ui.is_some() {
+                        cmds.remove_resource::<ScriptConsoleUi>()
+                    } else {
+                        cmds.insert_resource(ScriptConsoleUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(telemetry_export_ui.is_some(), "Telemetry Export")
+                    .clicked()
+                {
+                    if telemetry_export_ui.is_some() {
+                        cmds.remove_resource::<TelemetryExportUi>()
+                    } else {
+                        cmds.insert_resource(TelemetryExportUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(dashboard_ui.is_some(), "Web Dashboard")
+                    .clicked()
+                {
+                    if dashboard_ui.is_some() {
+                        cmds.remove_resource::<DashboardUi>()
+                    } else {
+                        cmds.insert_resource(DashboardUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(robot_view_ui.is_some(), "Robot View")
+                    .clicked()
+                {
+                    if robot_view_ui.is_some() {
+                        cmds.remove_resource::<RobotViewUi>()
+                    } else {
+                        cmds.insert_resource(RobotViewUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(motor_editor_ui.is_some(), "Motor Editor")
+                    .clicked()
+                {
+                    if motor_editor_ui.is_some() {
+                        cmds.remove_resource::<MotorEditorUi>()
+                    } else {
+                        cmds.insert_resource(MotorEditorUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(session_ui.is_some(), "Sessions")
+                    .clicked()
+                {
+                    if session_ui.is_some() {
+                        cmds.remove_resource::<SessionUi>()
+                    } else {
+                        cmds.insert_resource(SessionUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(callouts_ui.is_some(), "Callouts")
+                    .clicked()
+                {
+                    if callouts_ui.is_some() {
+                        cmds.remove_resource::<CalloutsUi>()
+                    } else {
+                        cmds.insert_resource(CalloutsUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(settings_ui.is_some(), "Settings")
+                    .clicked()
+                {
+                    if settings_ui.is_some() {
+                        cmds.remove_resource::<SettingsUi>()
+                    } else {
+                        cmds.insert_resource(SettingsUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(robot_update_ui.is_some(), "Robot Update")
+                    .clicked()
+                {
+                    if robot_update_ui.is_some() {
+                        cmds.remove_resource::<RobotUpdateUi>()
+                    } else {
+                        cmds.insert_resource(RobotUpdateUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(history_ui.is_some(), "History")
+                    .clicked()
+                {
+                    if history_ui.is_some() {
+                        cmds.remove_resource::<HistoryUi>()
+                    } else {
+                        cmds.insert_resource(HistoryUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(frame_budget_ui.is_some(), "Frame Budget")
+                    .clicked()
+                {
+                    if frame_budget_ui.is_some() {
+                        cmds.remove_resource::<FrameBudgetUi>()
+                    } else {
+                        cmds.insert_resource(FrameBudgetUi);
+                    }
+                }
+
+                if ui
+                    .selectable_label(parameters_ui.is_some(), "Parameters")
+                    .clicked()
+                {
+                    if parameters_ui.is_some() {
+                        cmds.remove_resource::<ParametersUi>()
+                    } else {
+                        cmds.insert_resource(ParametersUi);
+                    }
+                }
+
+                if ui.selectable_label(replay_ui.is_some(), "Replay").clicked() {
+                    if replay_ui.is_some() {
+                        cmds.remove_resource::<ReplayUi>()
+                    } else {
+                        cmds.insert_resource(ReplayUi::default());
+                    }
+                }
+
+                ui.menu_button("Layouts", |ui| {
+                    for name in layouts.layouts.keys() {
+                        if ui
+                            .selectable_label(*name == layouts.active, name.as_str())
+                            .clicked()
+                        {
+                            let name = name.clone();
+                            cmds.queue(move |world: &mut World| {
+                                world.send_event(ApplyLayout(name));
+                            });
+                        }
+                    }
+
+                    ui.separator();
+
+                    if ui.button("Save Current").clicked() {
+                        let name = layouts.active.clone();
+                        cmds.queue(move |world: &mut World| {
+                            world.send_event(SaveLayout(name));
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut *new_layout);
+
+                        let name = new_layout.trim().to_owned();
+                        if ui.button("Save As").clicked() && !name.is_empty() {
+                            cmds.queue(move |world: &mut World| {
+                                world.send_event(SaveLayout(name));
+                            });
+                            new_layout.clear();
+                        }
+                    });
+                });
+
+                ui.add_enabled_ui(!dock.open.is_empty(), |ui| {
+                    ui.menu_button("Dock", |ui| {
+                        for tab in &dock.open {
+                            let docked = dock.layout.side(tab);
+
+                            ui.menu_button(tab.title(), |ui| {
+                                let sides =
+                                    DockSide::ALL.into_iter().map(|it| (Some(it), it.name()));
+
+                                for (side, name) in sides.chain([(None, "Floating")]) {
+                                    if ui.selectable_label(docked == side, name).clicked()
+                                        && docked != side
+                                    {
+                                        let tab = tab.clone();
+                                        cmds.queue(move |world: &mut World| {
+                                            world.send_event(DockWindow { tab, side });
+                                        });
+                                    }
+                                }
+                            });
+                        }
+                    });
+                });
+
+                ui.menu_button("Keyboard Piloting", |ui| {
+                    for mode in KeyboardPilotingMode::ALL {
+                        let selected = keyboard_piloting.mode == mode;
+                        if ui.selectable_label(selected, mode.name()).clicked() && !selected {
+                            keyboard_piloting.mode = mode;
+                        }
+                    }
+                });
+
+                if ui.button("Photo Sphere").clicked() {
+                    for (robot, ..) in robots.iter() {
+                        cmds.entity(robot).trigger(SpawnPhotoSphere);
+                    }
+                    // cmds.trigger(SpawnPhotoSphere);
+                }
+            });
+
+            // RTL needs reverse order
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if let Some(session) = &recording_session {
+                    let elapsed = session.started.elapsed().as_secs_f64();
+                    let mut text = format!("REC {}", dive_log::format_secs(elapsed));
+
+                    if let Some(available) = session.available_space {
+                        text += &format!(" ({:.1} GiB free)", available as f64 / 1024f64.powi(3));
+                    }
+
+                    let low_space = session
+                        .available_space
+                        .is_some_and(|it| it < LOW_FREE_SPACE);
+                    let color = if low_space {
+                        Color32::YELLOW
+                    } else {
+                        Color32::RED
+                    };
+
+                    ui.label(RichText::new(text).color(color))
+                        .on_hover_text(session.folder.display().to_string());
+                    ui.separator();
+                }
+
+                // Drawn straight onto the top bar so it has to follow the theme
+                let text_color = ui.visuals().strong_text_color();
+
+                if !robots.is_empty() {
+                    let mut layout_job = LayoutJob::default();
+
+                    for (_entity, robot, state, depth_target, orientation_target, ..) in &robots {
+                        layout_job.append(
+                            robot.as_str(),
+                            20.0,
+                            TextFormat {
+                                color: text_color,
+                                ..default()
+                            },
+                        );
                         layout_job.append(
                             ":",
                             0.0,
                             TextFormat {
-                                color: if DARK_MODE {
-                                    Color32::WHITE
-                                } else {
-                                    Color32::BLACK
-                                },
+                                color: text_color,
                                 ..default()
                             },
                         );
@@ -382,22 +1396,27 @@ fn topbar(
 
                     ui.label(layout_job);
                 } else {
-                    ui.label(RichText::new("No Robot").color(if DARK_MODE {
-                        Color32::WHITE
-                    } else {
-                        Color32::BLACK
-                    }));
+                    ui.label(RichText::new("No Robot").color(text_color));
                 }
             })
         });
     });
 }
 
+/// Colors the pilot mode shown in the hud by its position in the mode list
+const PILOT_MODE_COLORS: [Color32; 4] = [
+    Color32::GREEN,
+    Color32::ORANGE,
+    Color32::BLUE,
+    Color32::GOLD,
+];
+
 fn hud(
     mut cmds: Commands,
 
-    mut host: Local<String>,
+    mut host: Local<Option<String>>,
     runtime: ResMut<TokioTasksRuntime>,
+    mut settings: ResMut<SurfaceSettings>,
 
     mut contexts: EguiContexts,
     attitude: Option<Res<OrientationDisplay>>,
@@ -405,16 +1424,32 @@ fn hud(
         (
             &Name,
             Option<&Armed>,
-            (Option<&MeasuredVoltage>, Option<&CurrentDraw>),
-            (Option<&OrientationTarget>, Option<&TempertureMeasurement>),
+            (
+                Option<&MeasuredVoltage>,
+                Option<&CurrentDraw>,
+                Option<&BatteryStatus>,
+                Option<&BatteryFault>,
+                Option<&ThermalDerate>,
+            ),
+            (
+                Option<&OrientationTarget>,
+                Option<&TempertureMeasurement>,
+                Option<&BottomLockStatus>,
+            ),
             (
                 Option<&SystemCpuTotal>,
                 Option<&SystemLoadAverage>,
                 Option<&SystemMemory>,
                 Option<&SystemTemperatures>,
             ),
-            (Option<&DepthMeasurement>, Option<&DepthTarget>),
-            (Option<&Peer>, Option<&Latency>),
+            (
+                Option<&DepthMeasurement>,
+                Option<&DepthTarget>,
+                Option<&WaterTemperature>,
+                Option<&Salinity>,
+            ),
+            (Option<&Orientation>, Option<&Heading>, Option<&TetherTurns>),
+            (Option<&Peer>, Option<&Latency>, Option<&CameraHealth>),
             &RobotId,
         ),
         With<Robot>,
@@ -425,15 +1460,22 @@ fn hud(
             &SelectedServo,
             &InputInterpolation,
             &InputMap<Action>,
+            &InputRole,
             &RobotId,
+            Entity,
         ),
         With<InputMarker>,
     >,
+    modes: Query<&PilotModes, With<LocalSurfaceMarker>>,
+    mut params: ResMut<Params>,
+    gamepad_roles: Res<GamepadRoles>,
     selected_camera: Query<(&Name, &RobotId), With<VideoMasterMarker>>,
 
     peers: Option<Res<MdnsPeers>>,
 
     mut disconnect: EventWriter<DisconnectPeer>,
+    mut reset_tether: EventWriter<ResetTetherTurns>,
+    units: Res<UnitPreferences>,
 ) {
     let context = contexts.ctx_mut();
 
@@ -441,11 +1483,12 @@ fn hud(
     if let Ok((
         robot_name,
         armed,
-        (voltage, current_draw),
-        (orientation_target, imu_temp),
+        (voltage, current_draw, battery, battery_fault, derate),
+        (orientation_target, imu_temp, bottom_lock),
         (cpu, load, memory, temps),
-        (depth, depth_target),
-        (peer, latency),
+        (depth, depth_target, water_temp, salinity),
+        (orientation, heading, tether),
+        (peer, latency, camera_health),
         robot_id,
     )) = robots.get_single()
     {
@@ -494,21 +1537,72 @@ fn hud(
                         });
                     }
 
-                    if let Some((selected_servo, input_interpolation, input_map, _)) =
-                        inputs.iter().find(|(_, _, _, robot)| **robot == *robot_id)
+                    let servo_role = gamepad_roles.owner(&Action::Servo);
+                    let selected_servo = inputs
+                        .iter()
+                        .find(|(_, _, _, role, robot, _)| {
+                            **robot == *robot_id && **role == servo_role
+                        })
+                        .map(|(selected_servo, ..)| selected_servo);
+
+                    if let Some((_, input_interpolation, input_map, _, _, input)) =
+                        inputs.iter().find(|(_, _, _, role, robot, _)| {
+                            **robot == *robot_id && **role == InputRole::Pilot
+                        })
                     {
                         ui.horizontal(|ui| {
                             ui.label(RichText::new("Robot Mode:").size(size));
-                            if *input_interpolation == InputInterpolation::normal() {
-                                ui.label(RichText::new("Normal").size(size).color(Color32::GREEN));
-                            } else if *input_interpolation == InputInterpolation::slow() {
-                                ui.label(RichText::new("Slow").size(size).color(Color32::ORANGE));
-                            } else if *input_interpolation == InputInterpolation::precision() {
-                                ui.label(
-                                    RichText::new("Precision").size(size).color(Color32::BLUE),
-                                );
+
+                            let modes = modes.get_single().ok();
+                            let color = modes
+                                .and_then(|it| {
+                                    it.0.iter()
+                                        .position(|mode| mode.name == input_interpolation.name)
+                                })
+                                .map(|idx| PILOT_MODE_COLORS[idx % PILOT_MODE_COLORS.len()])
+                                .unwrap_or(Color32::GOLD);
+
+                            egui::ComboBox::from_id_salt("Robot Mode")
+                                .selected_text(
+                                    RichText::new(input_interpolation.name.as_str())
+                                        .size(size)
+                                        .color(color),
+                                )
+                                .show_ui(ui, |ui| {
+                                    for mode in modes.iter().flat_map(|it| it.0.iter()) {
+                                        let selected = mode.name == input_interpolation.name;
+
+                                        if ui.selectable_label(selected, &mode.name).clicked()
+                                            && !selected
+                                        {
+                                            cmds.entity(input)
+                                                .insert(InputInterpolation(mode.clone()));
+                                        }
+                                    }
+                                });
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Heading Assist:").size(size));
+
+                            let enabled = params.get(&HEADING_ASSIST);
+                            let (text, color) = if enabled {
+                                ("On", Color32::GREEN)
                             } else {
-                                ui.label(RichText::new("Unknown").size(size).color(Color32::RED));
+                                ("Off", Color32::GRAY)
+                            };
+
+                            let toggle = ui
+                                .add(egui::Button::new(
+                                    RichText::new(text).size(size).color(color),
+                                ))
+                                .on_hover_text("Holds the heading while translating without yaw");
+                            if toggle.clicked() {
+                                if let Err(err) =
+                                    heading_assist::set_heading_assist(&mut params, !enabled)
+                                {
+                                    error!("Could not toggle heading assist: {err:?}");
+                                }
                             }
                         });
 
@@ -537,7 +1631,9 @@ fn hud(
 
                         ui.horizontal(|ui| {
                             ui.label(RichText::new("Servo:").size(size));
-                            if let Some(selected_servo) = &selected_servo.servo {
+                            if let Some(selected_servo) =
+                                selected_servo.and_then(|it| it.servo.as_ref())
+                            {
                                 ui.label(
                                     RichText::new(selected_servo.1.clone())
                                         .size(size)
@@ -556,18 +1652,18 @@ fn hud(
                             ui.label(RichText::new("Power:").size(size));
 
                             let voltage_color;
-                            if voltage.0 .0 < 11.5 {
+                            if voltage.0 < Volts(11.5) {
                                 voltage_color = Color32::RED;
-                            } else if voltage.0 .0 < 12.5 {
+                            } else if voltage.0 < Volts(12.5) {
                                 voltage_color = Color32::YELLOW;
                             } else {
                                 voltage_color = Color32::GREEN;
                             }
 
                             let current_color;
-                            if current.0 .0 < 15.0 {
+                            if current.0 < Amperes(15.0) {
                                 current_color = Color32::GREEN;
-                            } else if current.0 .0 < 20.0 {
+                            } else if current.0 < Amperes(20.0) {
                                 current_color = Color32::YELLOW;
                             } else {
                                 current_color = Color32::RED;
@@ -579,7 +1675,7 @@ fn hud(
                                     .color(voltage_color),
                             );
                             ui.label(
-                                RichText::new(format!("{}", current.0))
+                                RichText::new(units.current(current.0, Some(voltage.0)))
                                     .size(size)
                                     .color(current_color),
                             );
@@ -588,6 +1684,33 @@ fn hud(
                         ui.add_space(10.0);
                     }
 
+                    if let Some(derate) = derate.filter(|it| it.factor < 1.0) {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Thrust:").size(size));
+                            ui.label(
+                                RichText::new(format!("{:.0}%", derate.factor * 100.0))
+                                    .size(size)
+                                    .color(Color32::ORANGE),
+                            );
+                        });
+                        ui.label(
+                            RichText::new(format!(
+                                "Derated, {} at {}",
+                                derate.sensor,
+                                units.temperature(derate.temperature)
+                            ))
+                            .size(size * 0.75),
+                        );
+
+                        ui.add_space(10.0);
+                    }
+
+                    if let Some(battery) = battery {
+                        battery_cells(ui, battery, battery_fault, size);
+
+                        ui.add_space(10.0);
+                    }
+
                     if let Some(cpu) = cpu {
                         ui.label(RichText::new(format!("CPU: {:.2}%", cpu.0.usage)).size(size));
                     }
@@ -620,53 +1743,185 @@ fn hud(
                             ui.label(RichText::new(format!("{:?}", peer.addrs)).size(size * 0.75));
                         });
 
-                        if let Some(ping) = latency.ping {
-                            ui.label(RichText::new(format!("Ping: {ping:.2?} frames")).size(size));
-                        }
+                        ui.horizontal(|ui| {
+                            let (bars, color) = match settings.connection.grade(latency) {
+                                LinkGrade::Good => (3, Color32::GREEN),
+                                LinkGrade::Degraded => (2, Color32::YELLOW),
+                                LinkGrade::Bad => (1, Color32::RED),
+                            };
 
-                        ui.add_space(10.0);
-                    }
+                            ui.label(RichText::new("Link:").size(size));
+                            flight_display::signal_bars(ui, size, bars, color);
 
-                    if let Some(imu_temp) = imu_temp {
+                            if let Some(rtt) = latency.rtt {
+                                ui.label(
+                                    RichText::new(format!("{:.0}ms", rtt.as_secs_f32() * 1000.0))
+                                        .size(size),
+                                );
+                            }
+                        });
                         ui.label(
-                            RichText::new(format!("IMU Temp: {}", imu_temp.temperature)).size(size),
+                            RichText::new(format!(
+                                "Jitter: {:.1}ms, Missed: {:.0}% ({} total)",
+                                latency.jitter.as_secs_f32() * 1000.0,
+                                latency.loss() * 100.0,
+                                latency.missed_heartbeats,
+                            ))
+                            .size(size * 0.75),
+                        );
+
+                        ui.add_space(10.0);
+                    }
+
+                    if let Some(camera_health) = camera_health.filter(|it| it.cameras > 0) {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Cameras:").size(size));
+
+                            let color = if camera_health.stale > 0 {
+                                Color32::RED
+                            } else if camera_health.recovering > 0 {
+                                Color32::ORANGE
+                            } else {
+                                Color32::GREEN
+                            };
+                            ui.label(
+                                RichText::new(format!(
+                                    "{}/{} Live",
+                                    camera_health.healthy(),
+                                    camera_health.cameras
+                                ))
+                                .size(size)
+                                .color(color),
+                            );
+
+                            if camera_health.recovering > 0 {
+                                ui.label(
+                                    RichText::new(format!(
+                                        "({} recovering)",
+                                        camera_health.recovering
+                                    ))
+                                    .size(size * 0.75),
+                                );
+                            }
+                        });
+
+                        ui.add_space(10.0);
+                    }
+
+                    if let Some(imu_temp) = imu_temp {
+                        ui.label(
+                            RichText::new(format!(
+                                "IMU Temp: {}",
+                                units.temperature(imu_temp.temperature)
+                            ))
+                            .size(size),
                         );
                     }
 
                     if let Some(temps) = temps {
                         for temp in &temps.0 {
                             ui.label(
-                                RichText::new(format!("{}: {}", temp.name, temp.tempature))
-                                    .size(size),
+                                RichText::new(format!(
+                                    "{}: {}",
+                                    temp.name,
+                                    units.temperature(temp.tempature)
+                                ))
+                                .size(size),
                             );
                         }
                     }
 
-                    // TODO: Find a way to support this again
-                    // if let Some(depth) = depth {
-                    //     ui.label(
-                    //         RichText::new(format!("Water Temp: {}", depth.0.temperature))
-                    //             .size(size),
-                    //     );
-                    // }
+                    if let Some(water_temp) = water_temp {
+                        ui.label(
+                            RichText::new(format!(
+                                "Water Temp: {}",
+                                units.temperature(water_temp.0)
+                            ))
+                            .size(size),
+                        );
+                    }
+
+                    if let Some(salinity) = salinity {
+                        ui.label(
+                            RichText::new(format!("Salinity: {:.1} PSU", salinity.0)).size(size),
+                        );
+                    }
 
-                    if imu_temp.is_some() || temps.is_some() {
+                    if imu_temp.is_some() || temps.is_some() || water_temp.is_some() {
                         ui.add_space(10.0);
                     }
 
                     if let Some(depth) = depth {
-                        ui.label(RichText::new(format!("Depth: {}", depth.depth)).size(size));
+                        ui.label(
+                            RichText::new(format!("Depth: {}", units.length(depth.depth)))
+                                .size(size),
+                        );
 
                         if let Some(depth_target) = depth_target {
                             ui.label(
-                                RichText::new(format!("Depth Target: {}", depth_target.0))
-                                    .size(size),
+                                RichText::new(format!(
+                                    "Depth Target: {}",
+                                    units.length(depth_target.0)
+                                ))
+                                .size(size),
                             );
                         }
 
                         ui.add_space(10.0);
                     }
 
+                    if let Some(bottom_lock) = bottom_lock {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Bottom Lock:").size(size));
+
+                            for (name, state) in [
+                                ("Alt", bottom_lock.altitude),
+                                ("Pos", bottom_lock.position),
+                                ("Hdg", bottom_lock.heading),
+                            ] {
+                                let color = match state {
+                                    LockState::Active => Color32::GREEN,
+                                    LockState::Degraded => Color32::ORANGE,
+                                    LockState::Off => Color32::GRAY,
+                                };
+
+                                ui.label(RichText::new(name).size(size).color(color))
+                                    .on_hover_text(state.name());
+                            }
+                        });
+
+                        ui.add_space(10.0);
+                    }
+
+                    if let Some(tether) = tether {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("Tether:").size(size));
+
+                            let direction = if tether.turns >= 0.0 { "CW" } else { "CCW" };
+                            let color = if tether.over_budget() {
+                                Color32::RED
+                            } else if tether.turns.abs() > tether.budget * 0.75 {
+                                Color32::YELLOW
+                            } else {
+                                Color32::GREEN
+                            };
+                            ui.label(
+                                RichText::new(format!(
+                                    "{:.1} turns {direction}",
+                                    tether.turns.abs()
+                                ))
+                                .size(size)
+                                .color(color),
+                            );
+
+                            if ui.button("Reset").clicked() {
+                                reset_tether.send(ResetTetherTurns);
+                            }
+                        });
+
+                        ui.add_space(10.0);
+                    }
+
                     if let Some(_orientation_target) = orientation_target {
                         ui.label(RichText::new("Orientation Control").size(size));
                     }
@@ -684,6 +1939,34 @@ fn hud(
 
                 ui.allocate_space((0.0, 0.0).into());
             });
+
+            if let Some(orientation) = orientation {
+                // Sized relative to the window so the instruments stay readable on large displays
+                let scale = (ui.ctx().screen_rect().height() / 1080.0).clamp(0.5, 2.0);
+                let (_, pitch, roll) = orientation.yaw_pitch_roll();
+                let heading = heading.copied().unwrap_or_else(|| orientation.heading());
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    ui.vertical(|ui| {
+                        flight_display::heading_tape(
+                            ui,
+                            egui::vec2(230.0, 40.0) * scale,
+                            heading.0 .0,
+                        );
+                        flight_display::artificial_horizon(ui, 230.0 * scale, pitch, roll);
+                    });
+
+                    if let Some(depth) = depth {
+                        flight_display::depth_tape(
+                            ui,
+                            egui::vec2(70.0, 270.0) * scale,
+                            units.length.from_meters(depth.depth),
+                            depth_target.map(|it| units.length.from_meters(it.0)),
+                        );
+                    }
+                });
+            }
         });
 
         if let Some(peer) = peer {
@@ -698,34 +1981,20 @@ fn hud(
             .constrain_to(context.available_rect().shrink(20.0))
             // .movable(false)
             .show(contexts.ctx_mut(), |ui| {
+                let host =
+                    host.get_or_insert_with(|| settings.last_host.clone().unwrap_or_default());
+
                 ui.horizontal(|ui| {
                     ui.label("Connect To:");
-                    let line_response = ui.text_edit_singleline(&mut *host);
+                    let line_response = ui.text_edit_singleline(host);
                     let button_response = ui.button("Connect");
 
                     if line_response.lost_focus() || button_response.clicked() {
-                        let host = host.clone();
-                        runtime.spawn_background_task(|mut ctx| async move {
-                            let resolve = lookup_host(host).await;
-                            let addrs = resolve.ok().and_then(|mut it| it.next());
-
-                            if let Some(addrs) = addrs {
-                                ctx.run_on_main_thread(move |ctx| {
-                                    let world = ctx.world;
-                                    let count = world.query::<&Robot>().iter(world).count();
-
-                                    if count == 0 {
-                                        info!("Peer ip resolved to {:?}", addrs);
-                                        world.send_event(ConnectToPeer(addrs));
-                                    } else {
-                                        warn!("Already connected to peer");
-                                    }
-                                })
-                                .await;
-                            } else {
-                                error!("Could not resolve host");
-                            }
-                        });
+                        if settings.last_host.as_ref() != Some(host) {
+                            settings.last_host = Some(host.clone());
+                        }
+
+                        connect_to_host(&runtime, host.clone());
                     }
                 });
 
@@ -753,6 +2022,7 @@ fn hud(
                                     let addrs = *addrs;
 
                                     if ui.button(format!("{}", addrs.ip())).clicked() {
+                                        settings.last_host = Some(addrs.to_string());
                                         cmds.queue(move |world: &mut World| {
                                             world.send_event(ConnectToPeer(addrs));
                                         });
@@ -766,13 +2036,80 @@ fn hud(
     }
 }
 
+/// An empty and a full lithium ion cell, the ends of the cell bars
+const CELL_EMPTY: Volts = Volts(3.0);
+const CELL_FULL: Volts = Volts(4.2);
+
+fn battery_cells(
+    ui: &mut egui::Ui,
+    battery: &BatteryStatus,
+    fault: Option<&BatteryFault>,
+    size: f32,
+) {
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Cells:").size(size));
+
+        for voltage in &battery.cell_voltages {
+            let color = if *voltage < Volts(3.4) {
+                Color32::RED
+            } else if *voltage < Volts(3.7) {
+                Color32::YELLOW
+            } else {
+                Color32::GREEN
+            };
+
+            ui.vertical(|ui| {
+                let (rect, _) =
+                    ui.allocate_exact_size(egui::vec2(size, size * 2.0), Sense::hover());
+                let fill = ((*voltage - CELL_EMPTY).0 / (CELL_FULL - CELL_EMPTY).0).clamp(0.0, 1.0);
+
+                let painter = ui.painter();
+                painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(rect.min.x, rect.max.y - rect.height() * fill),
+                        rect.max,
+                    ),
+                    2.0,
+                    color,
+                );
+
+                ui.label(RichText::new(format!("{voltage}")).size(size * 0.6));
+            });
+        }
+    });
+
+    ui.label(
+        RichText::new(format!(
+            "{:.0}%, {}, {} cycles",
+            battery.state_of_charge, battery.temperature, battery.cycle_count
+        ))
+        .size(size * 0.75),
+    );
+
+    if let Some(BatteryFault(fault)) = fault {
+        ui.label(
+            RichText::new(format!("Cannot arm: {fault}"))
+                .size(size * 0.75)
+                .color(Color32::RED),
+        );
+    }
+}
+
 fn photosphere(
     mut cmds: Commands,
     mut contexts: EguiContexts,
-    photospheres: Query<(Entity, &PhotoSphere)>,
+    photospheres: Query<(Entity, &PhotoSphere, &RobotId)>,
+    robots: Query<(&Orientation, &RobotId), With<Robot>>,
+    mut settings: ResMut<PhotoSphereSettings>,
     images: Res<Assets<Image>>,
 ) {
-    for (entity, photosphere) in photospheres.iter() {
+    for (entity, photosphere, robot_id) in photospheres.iter() {
+        let orientation = robots
+            .iter()
+            .find(|(_, other)| *other == robot_id)
+            .map(|(orientation, _)| orientation.0);
+
         let mut open = true;
 
         let context = contexts.ctx_mut();
@@ -796,15 +2133,68 @@ fn photosphere(
                         Vec2::new(delta.x, delta.y) / response.interact_rect.width(),
                     ));
                 }
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{} images, {:.0}% covered",
+                        photosphere.shots.len(),
+                        photosphere.canvas.coverage() * 100.0
+                    ));
+
+                    if ui.button("Export Equirectangular").clicked() {
+                        cmds.entity(entity).trigger(ExportPhotoSphere);
+                    }
+                });
+
+                ui.collapsing("Coverage", |ui| {
+                    coverage_map(ui, photosphere, orientation);
+
+                    let captured = photosphere.targets.iter().filter(|it| it.captured).count();
+                    let reachable = photosphere.targets.iter().filter(|it| it.reachable).count();
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "{captured}/{reachable} targets, {} unreachable",
+                            photosphere.targets.len() - reachable
+                        ));
+
+                        if ui.button("Next Target").clicked() {
+                            cmds.entity(entity).trigger(GoToNextPhotoSphereTarget);
+                        }
+                    });
+
+                    // Only write back real edits so the targets are not replanned every frame
+                    let mut edited = *settings;
+                    ui.horizontal(|ui| {
+                        ui.label("FOV");
+                        ui.add(
+                            widgets::DragValue::new(&mut edited.fov)
+                                .range(20.0..=170.0)
+                                .suffix("°"),
+                        );
+                        ui.label("Overlap");
+                        ui.add(widgets::Slider::new(&mut edited.overlap, 0.0..=0.9));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Max Pitch");
+                        ui.add(
+                            widgets::DragValue::new(&mut edited.max_pitch)
+                                .range(0.0..=90.0)
+                                .suffix("°"),
+                        );
+                    });
+                    if edited != *settings {
+                        *settings = edited;
+                    }
+                });
+
                 ui.collapsing("Raw Images", |ui| {
                     ScrollArea::vertical().show(ui, |ui| {
-                        for (image_handle, texture) in &photosphere.images {
-                            if let Some(image) = images.get(image_handle) {
+                        for shot in &photosphere.shots {
+                            if let Some(image) = images.get(&shot.image) {
                                 let width = ui.available_width();
                                 let height = width * image.aspect_ratio().inverse().ratio();
 
                                 // FIXME: Instead of hard coding image rotation, get it from the ECS
-                                egui::Image::new(SizedTexture::new(*texture, (width, height)))
+                                egui::Image::new(SizedTexture::new(shot.texture, (width, height)))
                                     .rotate(180.0f32.to_radians(), egui::Vec2::splat(0.5))
                                     .ui(ui);
                             }
@@ -828,7 +2218,7 @@ fn pwm_control(
         Entity,
         Option<&MotorSignal>,
         Option<&MotorRawSignalRange>,
-        &GenericMotorId,
+        &ActuatorIdentity,
         &RobotId,
     )>,
 ) {
@@ -838,7 +2228,7 @@ fn pwm_control(
         // .current_pos(context.screen_rect().left_top())
         // .constrain_to(context.available_rect().shrink(20.0))
         .open(&mut open)
-        .show(contexts.ctx_mut(), |ui| {
+        .show_dockable(contexts.ctx_mut(), Id::new("PWM Control"), |ui| {
             if let Ok((robot, manual, robot_id)) = robots.get_single() {
                 let mut enabled = pwm_control.0;
                 ui.checkbox(&mut enabled, "Manual Enabled");
@@ -855,25 +2245,37 @@ fn pwm_control(
                     }
                 }
 
-                for (motor, signal, raw_range, channel, m_robot_id) in &motors {
-                    if robot_id != m_robot_id {
-                        continue;
-                    }
+                let mut motors = motors
+                    .iter()
+                    .filter(|(.., m_robot_id)| *m_robot_id == robot_id)
+                    .collect::<Vec<_>>();
+                motors.sort_by_key(|(_, _, _, identity, _)| identity.channel());
 
+                for (motor, signal, raw_range, identity, _) in motors {
                     let last_value = if let (Some(signal), Some(raw_range)) = (signal, raw_range) {
-                        // This is repeated in s few places, mode into method on MotorSignal
-                        match *signal {
-                            MotorSignal::Percent(pct) => pct,
-                            MotorSignal::Raw(raw) => raw_range.percent_from_raw(raw),
-                        }
+                        signal.to_percent(raw_range)
                     } else {
                         0.0
                     };
                     let mut value = last_value;
 
+                    let range = if identity.reversible {
+                        -1.0..=1.0
+                    } else {
+                        0.0..=1.0
+                    };
+
                     ui.horizontal(|ui| {
-                        ui.label(format!("{}", channel.0));
-                        ui.add(widgets::Slider::new(&mut value, -1.0..=1.0));
+                        ui.label(identity.label()).on_hover_text(format!(
+                            "{:?}, {}",
+                            identity.kind,
+                            if identity.positional {
+                                "positional"
+                            } else {
+                                "continuous"
+                            }
+                        ));
+                        ui.add(widgets::Slider::new(&mut value, range));
                         if ui.button("Clear").clicked() {
                             value = 0.0;
                         }
@@ -905,21 +2307,20 @@ fn movement_control(
     mut contexts: EguiContexts,
 
     mut controllers: Query<
-        (Entity, &mut RobotId, &mut MovementContribution),
+        (Entity, &ToolWindow, &mut RobotId, &mut MovementContribution),
         (With<MovementController>, Without<Robot>),
     >,
     robots: Query<(&Name, &RobotId, &MovementAxisMaximums), With<Robot>>,
     // motors: Query<(Entity, Option<&PwmSignal>, &PwmChannel, &RobotId)>,
 ) {
-    for (contoller, mut selected_robot, mut contribution) in &mut controllers {
+    for (contoller, tool, mut selected_robot, mut contribution) in &mut controllers {
         let mut open = true;
 
         let context = contexts.ctx_mut();
-        egui::Window::new("Movement Controller")
-            .id(Id::new(contoller))
+        tool.window()
             .constrain_to(context.available_rect().shrink(20.0))
             .open(&mut open)
-            .show(context, |ui| {
+            .show_dockable(context, tool.id(), |ui| {
                 ui.label("Robot:");
                 let Some(maximums) = ui
                     .horizontal(|ui| {
@@ -1004,7 +2405,7 @@ fn movement_debug(
     mut cmds: Commands,
     mut contexts: EguiContexts,
 
-    mut controllers: Query<(Entity, &mut RobotId), With<MovementDebugger>>,
+    mut controllers: Query<(Entity, &ToolWindow, &mut RobotId), With<MovementDebugger>>,
 
     contributors: Query<(&Name, &MovementContribution, &RobotId), Without<MovementDebugger>>,
     robots: Query<
@@ -1012,15 +2413,14 @@ fn movement_debug(
         (With<Robot>, Without<MovementDebugger>),
     >,
 ) {
-    for (contoller, mut selected_robot) in &mut controllers {
+    for (contoller, tool, mut selected_robot) in &mut controllers {
         let mut open = true;
 
         let context = contexts.ctx_mut();
-        egui::Window::new("Movement Debugger")
-            .id(Id::new(contoller))
+        tool.window()
             .constrain_to(context.available_rect().shrink(20.0))
             .open(&mut open)
-            .show(context, |ui| {
+            .show_dockable(context, tool.id(), |ui| {
                 ui.label("Robot:");
                 let Some((robot_id, target_movement, actual_movement)) = ui
                     .horizontal(|ui| {
@@ -1075,35 +2475,40 @@ fn current_draw_debug(
     mut cmds: Commands,
     mut contexts: EguiContexts,
 
-    mut controllers: Query<(Entity, &mut RobotId), With<CurrentDrawDebugger>>,
+    mut controllers: Query<(Entity, &ToolWindow, &mut RobotId), With<CurrentDrawDebugger>>,
 
     components: Query<
         (&Name, &CurrentDraw, &RobotId, Option<&ThrusterDefinition>),
         (Without<Robot>, Without<CurrentDrawDebugger>),
     >,
     robots: Query<
-        (&Name, &RobotId, Option<&CurrentDraw>),
+        (
+            &Name,
+            &RobotId,
+            Option<&CurrentDraw>,
+            Option<&MeasuredVoltage>,
+        ),
         (With<Robot>, Without<CurrentDrawDebugger>),
     >,
+    units: Res<UnitPreferences>,
 ) {
-    for (contoller, mut selected_robot) in &mut controllers {
+    for (contoller, tool, mut selected_robot) in &mut controllers {
         let mut open = true;
 
         let context = contexts.ctx_mut();
-        egui::Window::new("Current Draw Debugger")
-            .id(Id::new(contoller))
+        tool.window()
             .constrain_to(context.available_rect().shrink(20.0))
             .open(&mut open)
-            .show(context, |ui| {
+            .show_dockable(context, tool.id(), |ui| {
                 ui.label("Robot:");
-                let Some((robot_id, current_draw)) = ui
+                let Some((robot_id, current_draw, voltage)) = ui
                     .horizontal(|ui| {
                         let mut data = None;
-                        for (name, robot_id, current_draw) in &robots {
+                        for (name, robot_id, current_draw, voltage) in &robots {
                             ui.selectable_value(&mut selected_robot.0, robot_id.0, name.as_str());
 
                             if selected_robot.0 == robot_id.0 {
-                                data = Some((robot_id, current_draw));
+                                data = Some((robot_id, current_draw, voltage.map(|it| it.0)));
                             }
                         }
                         ui.selectable_value(&mut selected_robot.0, NetId::invalid(), "None");
@@ -1119,8 +2524,10 @@ fn current_draw_debug(
                     return;
                 };
 
+                let current = |current| units.current(current, voltage);
+
                 if let Some(current_draw) = current_draw {
-                    ui.label(format!("Actual Current Draw: {:.2?}", current_draw.0));
+                    ui.label(format!("Actual Current Draw: {}", current(current_draw.0)));
                 }
 
                 let mut current_draw_thrusters = Amperes::ZERO;
@@ -1131,7 +2538,7 @@ fn current_draw_debug(
                         continue;
                     }
 
-                    ui.label(format!("{}: {:.2?}", name.as_str(), current_draw.0));
+                    ui.label(format!("{}: {}", name.as_str(), current(current_draw.0)));
 
                     if thruster_definition.is_some() {
                         current_draw_thrusters += current_draw.0;
@@ -1141,20 +2548,25 @@ fn current_draw_debug(
                 }
 
                 ui.label(format!(
-                    "Thruster Current Draw: {current_draw_thrusters:.2?}"
+                    "Thruster Current Draw: {}",
+                    current(current_draw_thrusters)
+                ));
+                ui.label(format!(
+                    "Other Current Draw: {}",
+                    current(current_draw_other)
                 ));
-                ui.label(format!("Other Current Draw: {current_draw_other:.2?}"));
 
                 let total_predicted = current_draw_thrusters + current_draw_other;
                 ui.label(format!(
-                    "Total Predicted Current Draw: {total_predicted:.2?}"
+                    "Total Predicted Current Draw: {}",
+                    current(total_predicted)
                 ));
 
                 if let Some(current_draw) = current_draw {
-                    ui.label(format!("Actual Current Draw: {:.2?}", current_draw.0));
+                    ui.label(format!("Actual Current Draw: {}", current(current_draw.0)));
                     ui.label(format!(
-                        "Unaccounted Current Draw: {:.2?}",
-                        current_draw.0 - total_predicted
+                        "Unaccounted Current Draw: {}",
+                        current(current_draw.0 - total_predicted)
                     ));
                 }
             });
@@ -1166,7 +2578,7 @@ fn current_draw_debug(
 }
 
 #[derive(Component, Default)]
-struct PidData {
+pub struct PidData {
     log: HashMap<PidAxis, PidDataEntry>,
     show_total: bool,
     show_error: bool,
@@ -1177,13 +2589,49 @@ struct PidData {
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
-enum PidAxis {
+pub enum PidAxis {
     Yaw,
     Pitch,
     Roll,
     Depth,
 }
 
+impl PidAxis {
+    pub const ALL: [PidAxis; 4] = [PidAxis::Yaw, PidAxis::Pitch, PidAxis::Roll, PidAxis::Depth];
+
+    /// Name of the robot's controller for this axis
+    pub fn controller_name(&self) -> &'static str {
+        match self {
+            PidAxis::Yaw => "Stabalize Yaw",
+            PidAxis::Pitch => "Stabalize Pitch",
+            PidAxis::Roll => "Stabalize Roll",
+            PidAxis::Depth => "Stabalize Depth",
+        }
+    }
+
+    /// Movement in the robot's frame that the controller's correction scales
+    pub fn unit_movement(&self) -> MovementGlam {
+        match self {
+            PidAxis::Yaw => MovementGlam {
+                force: Vec3A::ZERO,
+                torque: Vec3A::Z,
+            },
+            PidAxis::Pitch => MovementGlam {
+                force: Vec3A::ZERO,
+                torque: Vec3A::X,
+            },
+            PidAxis::Roll => MovementGlam {
+                force: Vec3A::ZERO,
+                torque: Vec3A::Y,
+            },
+            PidAxis::Depth => MovementGlam {
+                force: Vec3A::NEG_Z,
+                torque: Vec3A::ZERO,
+            },
+        }
+    }
+}
+
 struct PidDataEntry {
     error: VecDeque<PlotPoint>,
     filtered_error: VecDeque<PlotPoint>,
@@ -1210,7 +2658,18 @@ impl Default for PidDataEntry {
 struct PidDisturbanceDeadline(Duration);
 
 const PID_SAMPLES: usize = 500;
-const PID_DISTURBANCE_TIME: Duration = Duration::from_millis(500);
+const PID_DISTURBANCE_TIME: Param<Duration> = Param::new(
+    "surface.pid_helper.disturbance_time",
+    "PID Helper",
+    "Seconds each disturbance button pushes the robot for",
+    Duration::from_millis(500),
+    Duration::from_millis(50),
+    Duration::from_secs(5),
+);
+
+/// Peak torque of the latency identification chirp, in newton meters
+const LATENCY_CHIRP_AMPLITUDE: f32 = 5.0;
+const LATENCY_CHIRP_DURATION: Duration = Duration::from_secs(20);
 
 // TODO: Use telemetry infra here after we get around to making that
 fn pid_helper(
@@ -1218,32 +2677,49 @@ fn pid_helper(
     mut contexts: EguiContexts,
 
     time: Res<Time<Real>>,
+    params: Res<Params>,
 
     mut controllers: Query<
         (
             Entity,
+            &ToolWindow,
             &mut RobotId,
             &mut MovementContribution,
             &mut PidData,
             Option<&PidDisturbanceDeadline>,
+            Has<PidAutoTune>,
         ),
         (With<PidHelper>, Without<Robot>),
     >,
 
     pid_controllers: Query<(&Name, &PidResult, &PidController, &RobotId), Without<PidData>>,
+    latencies: Query<
+        (
+            &Name,
+            &RobotId,
+            Option<&ActuationLatency>,
+            Option<&LatencyIdentification>,
+        ),
+        Without<PidData>,
+    >,
+    mut identify_latency: EventWriter<IdentifyActuationLatency>,
 
     robots: Query<(&Name, &RobotId, &MovementAxisMaximums), With<Robot>>,
+    settings: Res<SurfaceSettings>,
     // motors: Query<(Entity, Option<&PwmSignal>, &PwmChannel, &RobotId)>,
 ) {
-    for (controller, mut selected_robot, mut contribution, mut data, deadline) in &mut controllers {
+    let palette = settings.theme.palette();
+
+    for (controller, tool, mut selected_robot, mut contribution, mut data, deadline, tuning) in
+        &mut controllers
+    {
         let mut open = true;
 
         let context = contexts.ctx_mut();
-        egui::Window::new("Pid Helper")
-            .id(Id::new(controller))
+        tool.window()
             .constrain_to(context.available_rect().shrink(20.0))
             .open(&mut open)
-            .show(context, |ui| {
+            .show_dockable(context, tool.id(), |ui| {
                 ui.label("Robot:");
                 let Some(maximums) = ui
                     .horizontal(|ui| {
@@ -1329,12 +2805,7 @@ fn pid_helper(
                 });
 
                 for (axis, entry) in data.log.iter_mut() {
-                    let controller_name = match axis {
-                        PidAxis::Yaw => "Stabalize Yaw",
-                        PidAxis::Pitch => "Stabalize Pitch",
-                        PidAxis::Roll => "Stabalize Roll",
-                        PidAxis::Depth => "Stabalize Depth",
-                    };
+                    let controller_name = axis.controller_name();
 
                     let pid_result = pid_controllers.iter().find(|(name, _, _, robot_id)| {
                         **robot_id == *selected_robot && name.as_str() == controller_name
@@ -1396,11 +2867,11 @@ fn pid_helper(
                                 let (first, second) = entry.error.as_slices();
                                 plot.add(
                                     Line::new(format!("{axis:?}, error"), first)
-                                        .stroke((1.5, Color32::BROWN)),
+                                        .stroke((palette.stroke_width, palette.series[0])),
                                 );
                                 plot.add(
                                     Line::new(format!("{axis:?}, error"), second)
-                                        .stroke((1.5, Color32::BROWN)),
+                                        .stroke((palette.stroke_width, palette.series[0])),
                                 );
                             }
 
@@ -1408,11 +2879,11 @@ fn pid_helper(
                                 let (first, second) = entry.filtered_error.as_slices();
                                 plot.add(
                                     Line::new(format!("{axis:?}, filtered error"), first)
-                                        .stroke((1.5, Color32::BROWN)),
+                                        .stroke((palette.stroke_width, palette.series[1])),
                                 );
                                 plot.add(
                                     Line::new(format!("{axis:?}, filtered error"), second)
-                                        .stroke((1.5, Color32::BROWN)),
+                                        .stroke((palette.stroke_width, palette.series[1])),
                                 );
                             }
 
@@ -1420,11 +2891,11 @@ fn pid_helper(
                                 let (first, second) = entry.total.as_slices();
                                 plot.add(
                                     Line::new(format!("{axis:?}, total"), first)
-                                        .stroke((1.5, Color32::BLACK)),
+                                        .stroke((palette.stroke_width, palette.series[2])),
                                 );
                                 plot.add(
                                     Line::new(format!("{axis:?}, total"), second)
-                                        .stroke((1.5, Color32::BLACK)),
+                                        .stroke((palette.stroke_width, palette.series[2])),
                                 );
                             }
 
@@ -1432,11 +2903,11 @@ fn pid_helper(
                                 let (first, second) = entry.kp.as_slices();
                                 plot.add(
                                     Line::new(format!("{axis:?}, kp"), first)
-                                        .stroke((1.5, Color32::RED)),
+                                        .stroke((palette.stroke_width, palette.series[3])),
                                 );
                                 plot.add(
                                     Line::new(format!("{axis:?}, kp"), second)
-                                        .stroke((1.5, Color32::RED)),
+                                        .stroke((palette.stroke_width, palette.series[3])),
                                 );
                             }
 
@@ -1444,11 +2915,11 @@ fn pid_helper(
                                 let (first, second) = entry.ki.as_slices();
                                 plot.add(
                                     Line::new(format!("{axis:?}, ki"), first)
-                                        .stroke((1.5, Color32::GREEN)),
+                                        .stroke((palette.stroke_width, palette.series[4])),
                                 );
                                 plot.add(
                                     Line::new(format!("{axis:?}, ki"), second)
-                                        .stroke((1.5, Color32::GREEN)),
+                                        .stroke((palette.stroke_width, palette.series[4])),
                                 );
                             }
 
@@ -1456,11 +2927,11 @@ fn pid_helper(
                                 let (first, second) = entry.kd.as_slices();
                                 plot.add(
                                     Line::new(format!("{axis:?}, kd"), first)
-                                        .stroke((1.5, Color32::BLUE)),
+                                        .stroke((palette.stroke_width, palette.series[5])),
                                 );
                                 plot.add(
                                     Line::new(format!("{axis:?}, kd"), second)
-                                        .stroke((1.5, Color32::BLUE)),
+                                        .stroke((palette.stroke_width, palette.series[5])),
                                 );
                             }
                         });
@@ -1485,7 +2956,7 @@ fn pid_helper(
                         torque: vec3a(0.0, 0.0, 10.0),
                     };
                     cmds.entity(controller).insert(PidDisturbanceDeadline(
-                        time.elapsed() + PID_DISTURBANCE_TIME,
+                        time.elapsed() + params.get(&PID_DISTURBANCE_TIME),
                     ));
                 }
 
@@ -1495,7 +2966,7 @@ fn pid_helper(
                         torque: vec3a(10.0, 0.0, 0.0),
                     };
                     cmds.entity(controller).insert(PidDisturbanceDeadline(
-                        time.elapsed() + PID_DISTURBANCE_TIME,
+                        time.elapsed() + params.get(&PID_DISTURBANCE_TIME),
                     ));
                 }
 
@@ -1505,7 +2976,7 @@ fn pid_helper(
                         torque: vec3a(0.0, 10.0, 0.0),
                     };
                     cmds.entity(controller).insert(PidDisturbanceDeadline(
-                        time.elapsed() + PID_DISTURBANCE_TIME,
+                        time.elapsed() + params.get(&PID_DISTURBANCE_TIME),
                     ));
                 }
 
@@ -1515,13 +2986,69 @@ fn pid_helper(
                         torque: vec3a(0.0, 0.0, 0.0),
                     };
                     cmds.entity(controller).insert(PidDisturbanceDeadline(
-                        time.elapsed() + PID_DISTURBANCE_TIME,
+                        time.elapsed() + params.get(&PID_DISTURBANCE_TIME),
                     ));
                 }
 
                 ui.add_space(7.0);
 
-                if movement != contribution.0 {
+                if !tuning && ui.button("Auto Tune").clicked() {
+                    cmds.entity(controller).insert(PidAutoTune::default());
+                }
+
+                ui.add_space(7.0);
+                ui.label("Actuation Latency:");
+                for axis in [PidAxis::Yaw, PidAxis::Pitch, PidAxis::Roll] {
+                    let controller_name = axis.controller_name();
+
+                    let Some((_, _, latency, identification)) =
+                        latencies.iter().find(|(name, robot_id, _, _)| {
+                            **robot_id == *selected_robot && name.as_str() == controller_name
+                        })
+                    else {
+                        continue;
+                    };
+
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{axis:?}:"));
+
+                        if let Some(identification) = identification {
+                            let progress = identification.elapsed.as_secs_f32()
+                                / identification.duration.as_secs_f32();
+                            ui.add(
+                                widgets::ProgressBar::new(progress)
+                                    .desired_width(150.0)
+                                    .show_percentage(),
+                            );
+                        } else {
+                            match latency {
+                                Some(latency) => {
+                                    ui.label(format!("{}ms", latency.0.as_millis()));
+                                }
+                                None => {
+                                    ui.label("Unmeasured");
+                                }
+                            }
+
+                            let identify = ui
+                                .add_enabled(!tuning, egui::Button::new("Identify"))
+                                .on_hover_text(
+                                    "Chirps this axis while armed to measure how long the robot \
+                                     takes to respond, used to compensate the controller",
+                                );
+                            if identify.clicked() {
+                                identify_latency.send(IdentifyActuationLatency {
+                                    controller: controller_name.to_owned(),
+                                    amplitude: LATENCY_CHIRP_AMPLITUDE,
+                                    duration: LATENCY_CHIRP_DURATION,
+                                });
+                            }
+                        }
+                    });
+                }
+
+                // The auto tuner drives the contribution while it is open
+                if !tuning && movement != contribution.0 {
                     contribution.0 = movement;
                 }
             });
@@ -1545,7 +3072,7 @@ fn timer(
         .default_pos(context.screen_rect().left_top())
         .constrain_to(context.available_rect().shrink(20.0))
         .open(&mut open)
-        .show(contexts.ctx_mut(), |ui| {
+        .show_dockable(contexts.ctx_mut(), Id::new("Timer"), |ui| {
             let current_value = &mut timer.1;
             ui.horizontal(|ui| {
                 ui.selectable_value(current_value, TimerType::Setup, "Setup");
@@ -1609,3 +3136,1047 @@ fn timer(
         cmds.remove_resource::<TimerUi>();
     }
 }
+
+fn gamepads(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut roles: ResMut<GamepadRoles>,
+    gamepads: Query<(Entity, Option<&Name>, Option<&HidPanel>), With<Gamepad>>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Gamepads").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        Id::new("Gamepads"),
+        |ui| {
+            if gamepads.is_empty() {
+                ui.label("No gamepads connected");
+            }
+
+            for (gamepad, name, panel) in &gamepads {
+                let name = name.map(|it| it.as_str()).unwrap_or("Unknown Gamepad");
+
+                if let Some(HidPanel(device)) = panel {
+                    ui.label(format!("{name} ({gamepad})"))
+                        .on_hover_text(format!("Mapped as {} in hid.toml", device.label()));
+                    continue;
+                }
+
+                let current = roles.assignments.get(&gamepad).copied();
+                let mut selected = current;
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("{name} ({gamepad})"));
+
+                    egui::ComboBox::from_id_salt(gamepad)
+                        .selected_text(selected.map(|it| it.name()).unwrap_or("Unassigned"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut selected, None, "Unassigned");
+
+                            for role in InputRole::ALL {
+                                ui.selectable_value(&mut selected, Some(role), role.name());
+                            }
+                        });
+                });
+
+                if selected != current {
+                    // Each role is driven by at most one gamepad
+                    roles
+                        .assignments
+                        .retain(|other, role| *other == gamepad || Some(*role) != selected);
+
+                    match selected {
+                        Some(role) => {
+                            roles.assignments.insert(gamepad, role);
+                        }
+                        None => {
+                            roles.assignments.remove(&gamepad);
+                        }
+                    }
+                }
+            }
+        },
+    );
+
+    if !open {
+        cmds.remove_resource::<GamepadUi>();
+    }
+}
+
+fn bindings(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut bindings_ui: ResMut<BindingsUi>,
+    mut profiles: ResMut<BindingProfiles>,
+    mut capture: ResMut<BindingCapture>,
+    robots: Query<(&Name, Option<&ServoPresets>), With<Robot>>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Bindings").open(&mut open).show_dockable(
+        contexts.ctx_mut(),
+        Id::new("Bindings"),
+        |ui| {
+            let names = profiles.profiles.keys().cloned().collect::<Vec<_>>();
+
+            ui.horizontal(|ui| {
+                ui.label("Active Profile:");
+
+                let mut active = profiles.active.clone();
+                egui::ComboBox::from_id_salt("Active Binding Profile")
+                    .selected_text(active.as_str())
+                    .show_ui(ui, |ui| {
+                        for name in &names {
+                            ui.selectable_value(&mut active, name.clone(), name.as_str());
+                        }
+                    });
+
+                if active != profiles.active {
+                    profiles.active = active;
+                }
+
+                if names.len() > 1 && ui.button("Delete").clicked() {
+                    let active = profiles.active.clone();
+                    profiles.profiles.remove(&active);
+                    profiles.robot_overrides.retain(|_, it| *it != active);
+                    profiles.active = names
+                        .iter()
+                        .find(|it| **it != active)
+                        .cloned()
+                        .unwrap_or_default();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut bindings_ui.new_profile);
+
+                let name = bindings_ui.new_profile.trim().to_owned();
+                if ui.button("Copy To New Profile").clicked()
+                    && !name.is_empty()
+                    && !profiles.profiles.contains_key(&name)
+                {
+                    let profile = profiles
+                        .profiles
+                        .get(&profiles.active)
+                        .cloned()
+                        .unwrap_or_default();
+                    profiles.profiles.insert(name.clone(), profile);
+                    profiles.active = name;
+                    bindings_ui.new_profile.clear();
+                }
+            });
+
+            if !robots.is_empty() {
+                ui.add_space(7.0);
+                ui.label("Robot Overrides:");
+
+                for (robot, _) in &robots {
+                    let robot = robot.as_str();
+                    let current = profiles.robot_overrides.get(robot).cloned();
+                    let mut selected = current.clone();
+
+                    ui.horizontal(|ui| {
+                        ui.label(robot);
+
+                        egui::ComboBox::from_id_salt(("Binding Override", robot))
+                            .selected_text(selected.as_deref().unwrap_or("Use Active"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut selected, None, "Use Active");
+
+                                for name in &names {
+                                    ui.selectable_value(
+                                        &mut selected,
+                                        Some(name.clone()),
+                                        name.as_str(),
+                                    );
+                                }
+                            });
+                    });
+
+                    if selected != current {
+                        match selected {
+                            Some(profile) => {
+                                profiles.robot_overrides.insert(robot.to_owned(), profile);
+                            }
+                            None => {
+                                profiles.robot_overrides.remove(robot);
+                            }
+                        }
+                    }
+                }
+            }
+
+            ui.add_space(7.0);
+            ui.separator();
+
+            let editing = profiles.active.clone();
+            let Some(profile) = profiles.profiles.get(&editing) else {
+                return;
+            };
+
+            let mut remove = None;
+            let mut start_capture = None;
+
+            ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("Bindings Grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for action in Action::ALL {
+                            let label = ui.label(format!("{action:?}"));
+
+                            if let Action::RunMacro(slot) = action {
+                                let input_macro = profile.macros.iter().find(|it| it.slot == slot);
+
+                                if let Some(input_macro) = input_macro {
+                                    let steps = input_macro
+                                        .steps
+                                        .iter()
+                                        .map(|it| it.name())
+                                        .collect::<Vec<_>>()
+                                        .join("\n");
+                                    label.on_hover_text(format!("{}\n\n{steps}", input_macro.name));
+                                } else {
+                                    label.on_hover_text("No macro assigned");
+                                }
+                            } else if let Action::ServoPreset(slot) = action {
+                                let presets = robots
+                                    .iter()
+                                    .filter_map(|(robot, presets)| {
+                                        let preset = presets?.0.get(slot.index())?;
+                                        Some(format!("{robot}: {}", preset.name))
+                                    })
+                                    .collect::<Vec<_>>();
+
+                                if presets.is_empty() {
+                                    label.on_hover_text("No preset in this slot");
+                                } else {
+                                    label.on_hover_text(presets.join("\n"));
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                for (idx, binding) in profile.bindings_for(action) {
+                                    if ui
+                                        .button(binding.input.name())
+                                        .on_hover_text("Click to remove")
+                                        .clicked()
+                                    {
+                                        remove = Some(idx);
+                                    }
+                                }
+
+                                let capturing =
+                                    capture.0.as_ref() == Some(&(editing.clone(), action));
+                                if capturing {
+                                    ui.label(
+                                        RichText::new("Press an input (Esc to cancel)")
+                                            .color(Color32::ORANGE),
+                                    );
+                                } else if ui.button("+").clicked() {
+                                    start_capture = Some(action);
+                                }
+                            });
+
+                            ui.end_row();
+                        }
+                    });
+            });
+
+            if let Some(idx) = remove {
+                if let Some(profile) = profiles.profiles.get_mut(&editing) {
+                    profile.bindings.remove(idx);
+                }
+            }
+
+            if let Some(action) = start_capture {
+                capture.0 = Some((editing, action));
+            }
+        },
+    );
+
+    if !open {
+        capture.0 = None;
+        cmds.remove_resource::<BindingsUi>();
+    }
+}
+
+fn keyboard_hints(mut contexts: EguiContexts) {
+    let hints = [
+        ("W / S", "Surge"),
+        ("A / D", "Sway"),
+        ("Q / E", "Heave"),
+        ("Arrows", "Pitch & Yaw"),
+        ("Right Drag", "Pitch & Yaw"),
+        ("Scroll", "Depth Target"),
+        ("H", "Depth Hold"),
+        ("B", "Bottom Lock"),
+        ("L", "Leveling"),
+        ("M", "Robot Mode"),
+        ("Z / X", "Servo"),
+        ("C", "Switch Servo"),
+        ("Enter / Space", "Arm / Disarm"),
+    ];
+
+    egui::Area::new("Keyboard Hints".into())
+        .anchor(egui::Align2::LEFT_BOTTOM, (10.0, -10.0))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(RichText::new("Keyboard Piloting").strong());
+
+                egui::Grid::new("Keyboard Hints Grid").show(ui, |ui| {
+                    for (keys, action) in hints {
+                        ui.label(RichText::new(keys).monospace());
+                        ui.label(action);
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+}
+
+fn toasts(mut contexts: EguiContexts, notifications: Res<Notifications>) {
+    egui::Area::new("Toasts".into())
+        .anchor(egui::Align2::RIGHT_BOTTOM, (-10.0, -10.0))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for toast in &notifications.toasts {
+                // Fade out over the last second
+                let remaining = TOAST_DURATION.saturating_sub(toast.last_seen.elapsed());
+                let alpha = remaining.as_secs_f32().min(1.0);
+
+                egui::Frame::popup(ui.style())
+                    .multiply_with_opacity(alpha)
+                    .show(ui, |ui| {
+                        ui.set_max_width(350.0);
+
+                        let mut title = toast.source.to_owned();
+                        if toast.count > 1 {
+                            title.push_str(&format!(" (x{})", toast.count));
+                        }
+
+                        ui.label(RichText::new(title).strong().color(Color32::ORANGE));
+                        ui.label(toast.message.as_str());
+                    });
+
+                ui.add_space(5.0);
+            }
+        });
+}
+
+fn alarms(
+    mut contexts: EguiContexts,
+    mut notifications: ResMut<Notifications>,
+    mut callouts: ResMut<CalloutSettings>,
+) {
+    egui::Window::new(RichText::new("Alarms").color(Color32::RED))
+        .id(Id::new("Alarms"))
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_TOP, (0.0, 40.0))
+        .show(contexts.ctx_mut(), |ui| {
+            let mut acknowledge = None;
+
+            egui::Grid::new("Alarms Grid").striped(true).show(ui, |ui| {
+                for (idx, alarm) in notifications.alarms.iter().enumerate() {
+                    if alarm.acknowledged {
+                        continue;
+                    }
+
+                    ui.label(
+                        RichText::new(alarm.kind.name())
+                            .strong()
+                            .color(Color32::RED),
+                    );
+
+                    let mut message = alarm.message.clone();
+                    if alarm.count > 1 {
+                        message.push_str(&format!(" (x{})", alarm.count));
+                    }
+                    ui.label(message);
+
+                    let raised = alarm.raised;
+                    ui.label(format!(
+                        "{:02}:{:02}:{:02}",
+                        raised.hour(),
+                        raised.minute(),
+                        raised.second()
+                    ));
+
+                    if ui.button("Acknowledge").clicked() {
+                        acknowledge = Some(idx);
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+            if let Some(idx) = acknowledge {
+                notifications.alarms[idx].acknowledged = true;
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Acknowledge All").clicked() {
+                    notifications.acknowledge_all();
+                }
+
+                let mut enabled = callouts.enabled;
+                if ui.checkbox(&mut enabled, "Sound").changed() {
+                    callouts.enabled = enabled;
+                }
+            });
+        });
+}
+
+const SHAPING_PREVIEW_SAMPLES: usize = 100;
+
+fn input_shaping(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut profiles: ResMut<BindingProfiles>,
+    inputs: Query<(&ActionState<Action>, &InputRole), With<InputMarker>>,
+) {
+    let mut open = true;
+
+    let raw_inputs = inputs
+        .iter()
+        .find(|(_, role)| **role == InputRole::Pilot)
+        .map(|(action_state, _)| AxisInputs::from_action_state(action_state))
+        .unwrap_or_default();
+
+    egui::Window::new("Input Shaping")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), Id::new("Input Shaping"), |ui| {
+            let active = profiles.active.clone();
+            let Some(current) = profiles.profiles.get(&active).map(|it| it.shaping) else {
+                ui.label("No active binding profile");
+                return;
+            };
+            let mut shaping = current;
+
+            ui.label(format!("Profile: {active}"));
+            ui.checkbox(&mut shaping.square_sticks, "Square Sticks");
+
+            ScrollArea::vertical().show(ui, |ui| {
+                for axis in ShapedAxis::ALL {
+                    ui.add_space(7.0);
+                    ui.label(RichText::new(format!("{axis:?}")).strong());
+
+                    let axis_shaping = shaping.axis_mut(axis);
+                    ui.horizontal(|ui| {
+                        ui.label("Deadzone");
+                        ui.add(widgets::Slider::new(&mut axis_shaping.deadzone, 0.0..=0.5));
+                        ui.label("Expo");
+                        ui.add(widgets::Slider::new(&mut axis_shaping.expo, 0.0..=1.0));
+                    });
+
+                    let axis_shaping = *shaping.axis(axis);
+                    let curve = (0..=SHAPING_PREVIEW_SAMPLES)
+                        .map(|idx| {
+                            let x = idx as f32 / SHAPING_PREVIEW_SAMPLES as f32 * 2.0 - 1.0;
+                            [x as f64, axis_shaping.apply(x) as f64]
+                        })
+                        .collect::<Vec<_>>();
+
+                    let raw = raw_inputs.get(axis);
+                    let shaped = shaping.apply(raw_inputs).get(axis);
+
+                    Plot::new(format!("Input Shaping Plot {axis:?}"))
+                        .height(120.0)
+                        .data_aspect(1.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot| {
+                            plot.add(Line::new(format!("{axis:?}"), curve));
+                            plot.add(
+                                Points::new("Current", vec![[raw as f64, shaped as f64]])
+                                    .radius(4.0)
+                                    .color(Color32::RED),
+                            );
+                        });
+                }
+            });
+
+            if shaping != current {
+                if let Some(profile) = profiles.profiles.get_mut(&active) {
+                    profile.shaping = shaping;
+                }
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<InputShapingUi>();
+    }
+}
+
+fn pilot_modes(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut state: ResMut<PilotModesUi>,
+    mut modes: Query<&mut PilotModes, With<LocalSurfaceMarker>>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Pilot Modes")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), Id::new("Pilot Modes"), |ui| {
+            let Ok(mut modes) = modes.get_single_mut() else {
+                ui.label("Pilot modes not loaded");
+                return;
+            };
+
+            let current = modes.0.clone();
+            let mut edited = current.clone();
+            let mut delete = None;
+
+            ScrollArea::vertical().show(ui, |ui| {
+                for (idx, mode) in edited.iter_mut().enumerate() {
+                    ui.add_space(7.0);
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut mode.enabled, RichText::new(&mode.name).strong());
+
+                        if current.len() > 1 && ui.button("Delete").clicked() {
+                            delete = Some(idx);
+                        }
+                    });
+
+                    egui::Grid::new(format!("Pilot Mode {}", mode.name))
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Scale");
+                            ui.add(widgets::Slider::new(&mut mode.scale, 0.0..=1.0));
+                            ui.end_row();
+
+                            ui.label("Depth Rate (m/s)");
+                            ui.add(
+                                widgets::DragValue::new(&mut mode.depth_mps)
+                                    .speed(0.01)
+                                    .range(0.0..=2.0),
+                            );
+                            ui.end_row();
+
+                            ui.label("Expo Override");
+                            ui.horizontal(|ui| {
+                                let mut overridden = mode.expo.is_some();
+                                ui.checkbox(&mut overridden, "");
+
+                                let mut expo = mode.expo.unwrap_or(1.0);
+                                ui.add_enabled(
+                                    overridden,
+                                    widgets::Slider::new(&mut expo, 0.0..=1.0),
+                                );
+
+                                mode.expo = overridden.then_some(expo);
+                            });
+                            ui.end_row();
+
+                            ui.label("Servo Rate");
+                            ui.add(
+                                widgets::DragValue::new(&mut mode.servo_rate)
+                                    .speed(0.01)
+                                    .range(0.0..=10.0),
+                            );
+                            ui.end_row();
+
+                            let vectors = [
+                                ("Trim Rate (deg/s)", &mut mode.trim_dps),
+                                ("Translate Gain", &mut mode.translate_gain),
+                                (
+                                    "Translate Gain (Depth Hold)",
+                                    &mut mode.translate_gain_depth_hold,
+                                ),
+                                ("Torque Gain", &mut mode.torque_gain),
+                                ("Torque Gain (Stabilize)", &mut mode.torque_gain_stabalize),
+                            ];
+
+                            for (label, vector) in vectors {
+                                ui.label(label);
+                                ui.horizontal(|ui| {
+                                    ui.add(widgets::DragValue::new(&mut vector.x).speed(0.01));
+                                    ui.add(widgets::DragValue::new(&mut vector.y).speed(0.01));
+                                    ui.add(widgets::DragValue::new(&mut vector.z).speed(0.01));
+                                });
+                                ui.end_row();
+                            }
+                        });
+                }
+            });
+
+            if let Some(idx) = delete {
+                edited.remove(idx);
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut state.new_mode);
+
+                let name = state.new_mode.trim().to_owned();
+                let valid = !name.is_empty() && !edited.iter().any(|it| it.name == name);
+
+                if ui
+                    .add_enabled(valid, egui::Button::new("Add Mode"))
+                    .clicked()
+                {
+                    edited.push(PilotMode {
+                        name,
+                        ..PilotMode::normal()
+                    });
+                    state.new_mode.clear();
+                }
+            });
+
+            if edited != current {
+                modes.0 = edited;
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<PilotModesUi>();
+    }
+}
+
+fn checklist(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    checklists: Res<Checklists>,
+    run: Option<ResMut<ChecklistRun>>,
+    mut export: EventWriter<ExportChecklist>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Checklist")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), Id::new("Checklist"), |ui| {
+            let Some(mut run) = run else {
+                if checklists.checklists.is_empty() {
+                    ui.label("No checklists defined");
+                }
+
+                for definition in &checklists.checklists {
+                    if ui.button(format!("Start {}", definition.name)).clicked() {
+                        cmds.insert_resource(ChecklistRun::new(definition.clone()));
+                    }
+                }
+
+                return;
+            };
+
+            let done = run.completed.iter().filter(|it| it.is_some()).count();
+            let total = run.completed.len();
+
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&run.definition.name).strong());
+
+                let color = if run.is_complete() {
+                    Color32::GREEN
+                } else {
+                    Color32::ORANGE
+                };
+                ui.label(RichText::new(format!("{done}/{total}")).color(color));
+            });
+
+            ui.add_space(7.0);
+
+            let mut toggled = None;
+
+            egui::Grid::new("Checklist Grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (idx, (item, completed)) in
+                        run.definition.items.iter().zip(&run.completed).enumerate()
+                    {
+                        let mut checked = completed.is_some();
+                        let checkbox = ui.checkbox(&mut checked, item.label.as_str());
+                        if let Some(check) = item.auto {
+                            checkbox.on_hover_text(format!("Automatic: {}", check.name()));
+                        }
+
+                        if checked != completed.is_some() {
+                            toggled = Some((idx, checked));
+                        }
+
+                        if let Some(completed) = completed {
+                            let elapsed = completed.elapsed.as_secs();
+                            let mut text = format!("+{:02}:{:02}", elapsed / 60, elapsed % 60);
+                            if completed.automatic {
+                                text.push_str(" (auto)");
+                            }
+
+                            ui.label(text);
+                        } else {
+                            ui.label("");
+                        }
+
+                        ui.end_row();
+                    }
+                });
+
+            match toggled {
+                Some((idx, true)) => run.check(idx, false),
+                Some((idx, false)) => run.uncheck(idx),
+                None => {}
+            }
+
+            ui.add_space(7.0);
+            ui.horizontal(|ui| {
+                if ui.button("Export").clicked() {
+                    export.send(ExportChecklist);
+                }
+
+                if ui.button("Reset").clicked() {
+                    cmds.remove_resource::<ChecklistRun>();
+                }
+            });
+        });
+
+    if !open {
+        cmds.remove_resource::<ChecklistUi>();
+    }
+}
+
+fn plotting(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut workspace: ResMut<PlotWorkspace>,
+    mut browser: ResMut<PlotBrowser>,
+    mut export: EventWriter<ExportPlot>,
+) {
+    let mut open = true;
+
+    if !browser.enabled {
+        browser.enabled = true;
+    }
+
+    egui::Window::new("Plotting")
+        .default_size((900.0, 500.0))
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), Id::new("Plotting"), |ui| {
+            ui.horizontal(|ui| {
+                let paused = workspace.paused;
+                if ui.selectable_label(paused, "Pause").clicked() {
+                    workspace.paused = !paused;
+                }
+
+                if ui.button("Clear").clicked() {
+                    for series in &mut workspace.series {
+                        series.points.clear();
+                    }
+                }
+
+                if ui.button("Export CSV").clicked() {
+                    export.send(ExportPlot::Csv);
+                }
+
+                if ui.button("Export PNG").clicked() {
+                    export.send(ExportPlot::Png);
+                }
+            });
+
+            ui.separator();
+
+            egui::SidePanel::left("Plot Browser")
+                .resizable(true)
+                .default_width(250.0)
+                .show_inside(ui, |ui| {
+                    ScrollArea::vertical().show(ui, |ui| {
+                        ui.label(RichText::new("Series").strong());
+
+                        let mut remove = None;
+                        for (idx, series) in workspace.series.iter().enumerate() {
+                            if ui
+                                .button(series.name())
+                                .on_hover_text("Click to remove")
+                                .clicked()
+                            {
+                                remove = Some(idx);
+                            }
+                        }
+
+                        if let Some(idx) = remove {
+                            workspace.series.remove(idx);
+                        }
+
+                        ui.add_space(7.0);
+                        ui.label(RichText::new("Components").strong());
+
+                        for entity in &browser.entities {
+                            ui.collapsing(entity.name.as_str(), |ui| {
+                                for (component, fields) in &entity.components {
+                                    let short_name =
+                                        component.rsplit("::").next().unwrap_or(component);
+
+                                    ui.collapsing(short_name, |ui| {
+                                        for field in fields {
+                                            let plotted = workspace.series.iter().any(|it| {
+                                                it.entity == entity.entity
+                                                    && it.component == *component
+                                                    && it.field == *field
+                                            });
+
+                                            let label =
+                                                if field.is_empty() { "value" } else { field };
+                                            if ui.selectable_label(plotted, label).clicked()
+                                                && !plotted
+                                            {
+                                                workspace.series.push(PlotSeries::new(
+                                                    entity.entity,
+                                                    entity.name.clone(),
+                                                    component.clone(),
+                                                    field.clone(),
+                                                ));
+                                            }
+                                        }
+                                    });
+                                }
+                            });
+                        }
+                    });
+                });
+
+            Plot::new("Plotting Workspace")
+                .legend(egui_plot::Legend::default())
+                .x_axis_label("Time (s)")
+                .show(ui, |plot| {
+                    for series in &workspace.series {
+                        let name = series.name();
+                        let (first, second) = series.points.as_slices();
+
+                        plot.add(Line::new(name.clone(), first));
+                        plot.add(Line::new(name, second));
+                    }
+                });
+        });
+
+    if !open {
+        cmds.remove_resource::<PlotUi>();
+    }
+}
+
+fn dive_log(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut log: ResMut<DiveLog>,
+    mut export: EventWriter<ExportDiveLog>,
+    units: Res<UnitPreferences>,
+    settings: Res<SurfaceSettings>,
+) {
+    let mut open = true;
+
+    let palette = settings.theme.palette();
+
+    egui::Window::new("Dive Log")
+        .default_size((700.0, 400.0))
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), Id::new("Dive Log"), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Max Depth: {}", units.length(log.max_depth)));
+                ui.separator();
+                ui.label(format!(
+                    "Duration: {}",
+                    dive_log::format_secs(log.dive_secs)
+                ));
+                ui.separator();
+
+                if let Some(current) = log.average_current() {
+                    ui.label(format!("Average Current: {}", units.current(current, None)));
+                } else {
+                    ui.label("Average Current: Unknown");
+                }
+
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui
+                        .button("New Dive")
+                        .on_hover_text("Clears the recorded profile")
+                        .clicked()
+                    {
+                        *log = DiveLog::default();
+                    }
+
+                    if ui.button("Export Summary").clicked() {
+                        export.send(ExportDiveLog);
+                    }
+                });
+            });
+
+            ui.separator();
+
+            Plot::new("Dive Profile")
+                .x_axis_label("Time (s)")
+                .y_axis_label("Depth (m)")
+                // Depth is plotted negated so deeper is lower
+                .y_axis_formatter(|mark, _| format!("{:.1}", -mark.value))
+                .label_formatter(|name, point| {
+                    if name.is_empty() {
+                        format!("{:.1}s\n{:.2}m", point.x, -point.y)
+                    } else {
+                        format!("{name}\n{:.1}s", point.x)
+                    }
+                })
+                .show(ui, |plot| {
+                    let profile = log.depth.iter().map(|it| [it.x, -it.y]).collect::<Vec<_>>();
+                    plot.add(
+                        Line::new("", profile).stroke((palette.stroke_width, palette.series[5])),
+                    );
+
+                    for event in &log.events {
+                        let color = match event.kind {
+                            DiveEventKind::Armed => Color32::GREEN,
+                            DiveEventKind::Disarmed => Color32::RED,
+                            DiveEventKind::DepthTarget => Color32::from_rgb(255, 0, 255),
+                            DiveEventKind::Alarm => Color32::ORANGE,
+                        };
+
+                        plot.add(
+                            VLine::new(event.label.as_str(), event.time)
+                                .color(color)
+                                .style(LineStyle::dashed_loose()),
+                        );
+                    }
+                });
+        });
+
+    if !open {
+        cmds.remove_resource::<DiveLogUi>();
+    }
+}
+
+/// Holding a button for this long arms the robot
+const TOUCH_ARM_HOLD: Duration = Duration::from_millis(1500);
+/// Disarming still asks for a hold to avoid accidental taps, but a short one so it stays quick in
+/// an emergency
+const TOUCH_DISARM_HOLD: Duration = Duration::from_millis(300);
+
+fn touch_controls(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut state: ResMut<TouchControlsUi>,
+    mut virtual_input: ResMut<VirtualInput>,
+    touches: Res<Touches>,
+    egui_settings: Query<&EguiContextSettings, With<PrimaryWindow>>,
+) {
+    let mut open = true;
+
+    let scale = egui_settings
+        .get_single()
+        .map(|it| it.scale_factor)
+        .unwrap_or(1.0);
+
+    let context = contexts.ctx_mut();
+    let size = (context.screen_rect().height() / 1080.0).clamp(0.5, 2.0) * 220.0;
+
+    let state = &mut *state;
+    let virtual_input = &mut *virtual_input;
+    let [left_touch, right_touch] = &mut virtual_input.captured;
+    let mut left = Vec2::ZERO;
+    let mut right = Vec2::ZERO;
+    let mut pulses = Vec::new();
+    let mut nudges = Vec::new();
+
+    egui::Window::new("Touch Controls")
+        .default_pos(context.screen_rect().left_bottom() + egui::vec2(20.0, -size - 120.0))
+        .resizable(false)
+        .open(&mut open)
+        .show_dockable(context, Id::new("Touch Controls"), |ui| {
+            ui.horizontal(|ui| {
+                ui.vertical_centered(|ui| {
+                    touch::virtual_stick(ui, size, &mut left, left_touch, &touches, scale);
+                    ui.label("Yaw / Surge");
+                });
+
+                ui.add_space(20.0);
+
+                ui.vertical(|ui| {
+                    let button = egui::vec2(size * 0.9, size * 0.25);
+                    let small = egui::vec2(size * 0.43, size * 0.2);
+
+                    if touch::hold_button(
+                        ui,
+                        "Hold to Arm",
+                        button,
+                        Color32::GREEN,
+                        TOUCH_ARM_HOLD,
+                        &mut state.arm,
+                    ) {
+                        pulses.push(Action::Arm);
+                    }
+
+                    if touch::hold_button(
+                        ui,
+                        "Hold to Disarm",
+                        button,
+                        Color32::RED,
+                        TOUCH_DISARM_HOLD,
+                        &mut state.disarm,
+                    ) {
+                        pulses.push(Action::Disarm);
+                    }
+
+                    ui.add_space(10.0);
+
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_sized(small, egui::Button::new("Depth Hold"))
+                            .clicked()
+                        {
+                            pulses.push(Action::ToggleDepthHold);
+                        }
+                        if ui.add_sized(small, egui::Button::new("Level")).clicked() {
+                            pulses.push(Action::ToggleLeveling(LevelingType::Upright));
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        // The depth target moves 5cm per unit
+                        if ui.add_sized(small, egui::Button::new("Up 10cm")).clicked() {
+                            nudges.push((Action::DepthTargetAxis, 2.0));
+                        }
+                        if ui
+                            .add_sized(small, egui::Button::new("Down 10cm"))
+                            .clicked()
+                        {
+                            nudges.push((Action::DepthTargetAxis, -2.0));
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.add_sized(small, egui::Button::new("⟲ 5°")).clicked() {
+                            nudges.push((Action::HeadingTargetAxis, -1.0));
+                        }
+                        if ui.add_sized(small, egui::Button::new("⟳ 5°")).clicked() {
+                            nudges.push((Action::HeadingTargetAxis, 1.0));
+                        }
+                    });
+                });
+
+                ui.add_space(20.0);
+
+                ui.vertical_centered(|ui| {
+                    touch::virtual_stick(ui, size, &mut right, right_touch, &touches, scale);
+                    ui.label("Sway / Heave");
+                });
+            });
+        });
+
+    virtual_input.left = left;
+    virtual_input.right = right;
+    for action in pulses {
+        virtual_input.press(action);
+    }
+    for (action, value) in nudges {
+        virtual_input.nudge(action, value);
+    }
+
+    if !open {
+        cmds.remove_resource::<TouchControlsUi>();
+    }
+}
+
+fn cleanup_touch_controls(mut virtual_input: ResMut<VirtualInput>) {
+    virtual_input.release_sticks();
+}
+
+fn cleanup_plotting(mut browser: ResMut<PlotBrowser>) {
+    browser.enabled = false;
+    browser.entities.clear();
+}
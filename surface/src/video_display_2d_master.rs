@@ -1,14 +1,48 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    time::{Duration, Instant},
+};
+
+use ahash::HashMap;
+use anyhow::Context;
 use bevy::{
     math::f32,
     prelude::*,
     render::{camera::Camera as BevyCamera, view::RenderLayers},
+    window::PrimaryWindow,
+};
+use bevy_egui::{EguiContextSettings, EguiContexts};
+use common::components::{CameraDefinition, Robot};
+use egui::{Align2, Color32, FontId, Id, LayerId, Order, Stroke, StrokeKind};
+use leafwing_input_manager::action_state::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{Action, InputMarker},
+    settings::config_path,
+    video_stream::{
+        watchdog::{StreamHealth, StreamState},
+        FrameDecimation, FramesReceived, ImageHandle,
+    },
 };
-use common::components::CameraDefinition;
-
-use crate::video_stream::ImageHandle;
 
 const RENDER_LAYERS: RenderLayers = RenderLayers::layer(2);
 
+pub const CAMERA_LAYOUTS_FILE: &str = "camera_layouts.toml";
+
+/// Width of the inset cameras in picture in picture mode as a fraction of the window width
+const PIP_WIDTH_PCT: f32 = 0.25;
+const PIP_MARGIN: f32 = 10.0;
+
+/// Hidden cameras still get a few frames so their stream watchdog stays quiet
+const HIDDEN_DECIMATION: FrameDecimation = FrameDecimation {
+    every: 10,
+    scale: 0.25,
+};
+/// How often the effective frame rate of each display is measured
+const FPS_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct VideoDisplay2DPlugin;
 
 impl Plugin for VideoDisplay2DPlugin {
@@ -16,14 +50,26 @@ impl Plugin for VideoDisplay2DPlugin {
         app.init_resource::<VideoDisplay2DSettings>()
             // .init_resource::<VideoTree>()
             .add_event::<MakeMaster>()
+            .add_event::<CameraLayoutEvent>()
+            .add_systems(PreStartup, load_camera_layouts)
             .add_systems(Startup, setup)
             .add_systems(
                 Update,
                 (
                     create_display,
-                    update_aspect_ratio.after(create_display),
-                    handle_new_masters,
+                    select_robot_layout,
+                    camera_hotkeys,
+                    handle_layout_events
+                        .after(camera_hotkeys)
+                        .after(create_display),
+                    update_layout
+                        .after(create_display)
+                        .after(handle_layout_events),
+                    handle_new_masters.after(handle_layout_events),
                     enable_camera,
+                    save_camera_layouts.after(handle_layout_events),
+                    stream_health_overlay.after(update_layout),
+                    tile_fps_overlay.after(update_layout),
                 ),
             )
             .add_observer(remove_other_master_markers);
@@ -52,11 +98,98 @@ struct MakeMaster(Entity);
 #[derive(Resource, Default)]
 pub struct VideoDisplay2DSettings {
     pub enabled: bool,
+    /// Debug overlay with the frame rate and scale each display is actually receiving
+    pub show_fps: bool,
 }
 
 #[derive(Component, Clone, Copy)]
 pub struct VideoMasterMarker;
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraLayoutMode {
+    /// One large camera with the rest in a column on the right
+    #[default]
+    MasterColumn,
+    Grid,
+    /// The master camera fills the window with the rest inset in the corner
+    PictureInPicture,
+    /// Only the master camera
+    Fullscreen,
+}
+
+impl CameraLayoutMode {
+    pub const ALL: [CameraLayoutMode; 4] = [
+        CameraLayoutMode::MasterColumn,
+        CameraLayoutMode::Grid,
+        CameraLayoutMode::PictureInPicture,
+        CameraLayoutMode::Fullscreen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraLayoutMode::MasterColumn => "Master and Column",
+            CameraLayoutMode::Grid => "Grid",
+            CameraLayoutMode::PictureInPicture => "Picture in Picture",
+            CameraLayoutMode::Fullscreen => "Fullscreen",
+        }
+    }
+
+    fn next(&self) -> Self {
+        let idx = Self::ALL.iter().position(|it| it == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraLayout {
+    pub mode: CameraLayoutMode,
+    /// Names of the cameras that are not displayed
+    #[serde(default)]
+    pub hidden: BTreeSet<String>,
+    /// How every camera other than the master is decoded, the master always gets full rate
+    #[serde(default)]
+    pub tiles: FrameDecimation,
+}
+
+/// The camera layout of each robot, persisted to `camera_layouts.toml`
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraLayouts {
+    /// Robot name to layout
+    pub robots: BTreeMap<String, CameraLayout>,
+    /// The robot whose layout is shown
+    #[serde(skip)]
+    pub active_robot: Option<String>,
+}
+
+impl CameraLayouts {
+    pub fn current(&self) -> CameraLayout {
+        self.active_robot
+            .as_ref()
+            .and_then(|it| self.robots.get(it))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn current_mut(&mut self) -> &mut CameraLayout {
+        let robot = self.active_robot.clone().unwrap_or_default();
+        self.robots.entry(robot).or_default()
+    }
+}
+
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum CameraLayoutEvent {
+    SetMode(CameraLayoutMode),
+    CycleMode,
+    /// Makes the next shown camera the master
+    CycleMaster,
+    SetHidden {
+        camera: String,
+        hidden: bool,
+    },
+    ShowAll,
+    SetTileDecimation(FrameDecimation),
+}
+
 fn remove_other_master_markers(
     event: Trigger<OnInsert, VideoMasterMarker>,
     mut cmds: Commands,
@@ -155,9 +288,159 @@ fn create_display(
     }
 }
 
-fn update_aspect_ratio(
-    mut displays: Query<(&ImageHandle, &DisplayMarker, &mut Transform)>,
+/// Where a display is drawn, in logical pixels relative to the center of the window
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    center: Vec2,
+    size: Vec2,
+    /// Drawn above displays with a lower depth
+    depth: f32,
+}
+
+/// The largest size with the given aspect ratio (height/width) that fits in `bounds`
+fn fit(aspect_ratio: f32, bounds: Vec2) -> Vec2 {
+    let width = if bounds.x * aspect_ratio > bounds.y {
+        bounds.y / aspect_ratio
+    } else {
+        bounds.x
+    };
+
+    Vec2::new(width, width * aspect_ratio)
+}
+
+/// Places the visible displays, the first display is the master. Displays without a placement are
+/// hidden
+fn place_displays(
+    mode: CameraLayoutMode,
+    aspect_ratios: &[f32],
+    logical: Vec2,
+) -> Vec<Option<Placement>> {
+    let Some((&master_aspect_ratio, others)) = aspect_ratios.split_first() else {
+        return Vec::new();
+    };
+
+    let fullscreen_master = Placement {
+        center: Vec2::ZERO,
+        size: fit(master_aspect_ratio, logical),
+        depth: 0.0,
+    };
+
+    match mode {
+        CameraLayoutMode::MasterColumn => {
+            let other_max_width_pct = 1.0 / 3.0;
+            let other_aspect_ratio = others.iter().sum::<f32>();
+            let count = others.len();
+
+            let other_width_needed = other_aspect_ratio * logical.y;
+            let other_width = if other_width_needed < other_max_width_pct * logical.x {
+                other_width_needed
+            } else {
+                other_max_width_pct * logical.x
+            };
+            let other_width = if other_width * other_aspect_ratio > logical.y {
+                (1.0 / other_aspect_ratio) * logical.y
+            } else {
+                other_width
+            };
+
+            let other_remaining_height = logical.y - other_width * other_aspect_ratio;
+
+            let master_width_needed = logical.x - other_width;
+            let master_width = if master_width_needed * master_aspect_ratio > logical.y {
+                (1.0 / master_aspect_ratio) * logical.y
+            } else {
+                master_width_needed
+            };
+
+            let master = Placement {
+                center: Vec2::new(master_width_needed / 2.0 - logical.x / 2.0, 0.0),
+                size: Vec2::new(master_width, master_aspect_ratio * master_width),
+                depth: 0.0,
+            };
+
+            let others = others.iter().enumerate().map(|(idx, &aspect_ratio)| {
+                let total_aspect_ratio = others[..idx].iter().sum::<f32>();
+                let height_so_far = total_aspect_ratio * other_width
+                    + other_remaining_height / (count as f32 + 1.0) * (idx + 1) as f32;
+
+                Some(Placement {
+                    center: Vec2::new(
+                        logical.x / 2.0 - other_width / 2.0,
+                        logical.y / 2.0 - height_so_far - 0.5 * aspect_ratio * other_width,
+                    ),
+                    size: Vec2::new(other_width, aspect_ratio * other_width),
+                    depth: 0.0,
+                })
+            });
+
+            std::iter::once(Some(master)).chain(others).collect()
+        }
+        CameraLayoutMode::Grid => {
+            let count = aspect_ratios.len();
+            let columns = (count as f32).sqrt().ceil() as usize;
+            let rows = count.div_ceil(columns);
+            let cell = logical / Vec2::new(columns as f32, rows as f32);
+
+            aspect_ratios
+                .iter()
+                .enumerate()
+                .map(|(idx, &aspect_ratio)| {
+                    let column = (idx % columns) as f32;
+                    let row = (idx / columns) as f32;
+
+                    Some(Placement {
+                        center: Vec2::new(
+                            -logical.x / 2.0 + cell.x * (column + 0.5),
+                            logical.y / 2.0 - cell.y * (row + 0.5),
+                        ),
+                        size: fit(aspect_ratio, cell),
+                        depth: 0.0,
+                    })
+                })
+                .collect()
+        }
+        CameraLayoutMode::PictureInPicture => {
+            let inset_width = logical.x * PIP_WIDTH_PCT;
+            let mut bottom = -logical.y / 2.0 + PIP_MARGIN;
+
+            let insets = others.iter().map(|&aspect_ratio| {
+                let size = Vec2::new(inset_width, inset_width * aspect_ratio);
+                let center = Vec2::new(
+                    logical.x / 2.0 - PIP_MARGIN - size.x / 2.0,
+                    bottom + size.y / 2.0,
+                );
+                bottom += size.y + PIP_MARGIN;
+
+                Some(Placement {
+                    center,
+                    size,
+                    depth: 1.0,
+                })
+            });
+
+            std::iter::once(Some(fullscreen_master))
+                .chain(insets)
+                .collect()
+        }
+        CameraLayoutMode::Fullscreen => std::iter::once(Some(fullscreen_master))
+            .chain(others.iter().map(|_| None))
+            .collect(),
+    }
+}
+
+fn update_layout(
+    mut cmds: Commands,
+    mut displays: Query<(
+        Entity,
+        (&ImageHandle, Option<&FrameDecimation>),
+        &DisplayMarker,
+        &Name,
+        &mut Transform,
+        &mut Visibility,
+    )>,
     images: Res<Assets<Image>>,
+    layouts: Res<CameraLayouts>,
+    settings: Res<VideoDisplay2DSettings>,
 
     camera: Query<&BevyCamera, With<DisplayCamera>>,
 ) {
@@ -165,92 +448,167 @@ fn update_aspect_ratio(
     let camera = camera.single();
     let logical = camera.logical_viewport_size().unwrap();
 
-    let other_max_width_pct = 1.0 / 3.0;
-
-    // height/width
-    let mut master_aspect_ratio = 0.0f32;
-    let mut aspect_ratios = Vec::default();
-    let mut other_aspect_ratio = 0.0f32;
-    let mut count = 0;
+    let layout = layouts.current();
+
+    // height/width, ordered with the master first
+    let mut visible = displays
+        .iter()
+        .filter(|(_, _, _, name, ..)| !layout.hidden.contains(name.as_str()))
+        .filter_map(|(_, (handle, _), display, name, ..)| {
+            let image = images.get(&handle.0)?;
+            Some((
+                display.0,
+                name.as_str(),
+                1.0f32 / f32::from(image.aspect_ratio()),
+            ))
+        })
+        .collect::<Vec<_>>();
+    visible.sort_by_key(|it| it.0);
+
+    let aspect_ratios = visible.iter().map(|it| it.2).collect::<Vec<_>>();
+    let placements = place_displays(layout.mode, &aspect_ratios, logical);
+
+    for (entity, (_, decimation), display, _, mut transform, mut visibility) in &mut displays {
+        let idx = visible.iter().position(|it| it.0 == display.0);
+        let placement = idx.and_then(|idx| placements.get(idx).copied().flatten());
+
+        // The master is first, only it needs every frame at full resolution
+        let new_decimation = match (placement, idx) {
+            _ if !settings.enabled => FrameDecimation::FULL,
+            (Some(_), Some(0)) => FrameDecimation::FULL,
+            (Some(_), _) => layout.tiles,
+            (None, _) => HIDDEN_DECIMATION,
+        };
+        if decimation != Some(&new_decimation) {
+            cmds.entity(entity).insert(new_decimation);
+        }
 
-    for (handle, display, _transform) in &displays {
-        let Some(image) = images.get(&handle.0) else {
+        let Some(placement) = placement else {
+            visibility.set_if_neq(Visibility::Hidden);
             continue;
         };
 
-        aspect_ratios.push((display.0, 1.0f32 / f32::from(image.aspect_ratio())));
-
-        if display.0 != 0 {
-            other_aspect_ratio += 1.0f32 / f32::from(image.aspect_ratio());
-            count += 1;
-        } else {
-            master_aspect_ratio = 1.0f32 / f32::from(image.aspect_ratio());
-        }
+        visibility.set_if_neq(Visibility::Inherited);
+        *transform = transform
+            .with_translation(placement.center.extend(placement.depth))
+            .with_scale(placement.size.extend(1.0));
     }
+}
 
-    aspect_ratios.sort_by_key(|it| it.0);
-
-    let other_width_needed = other_aspect_ratio * logical.y;
-    let other_width = if other_width_needed < other_max_width_pct * logical.x {
-        other_width_needed
-    } else {
-        other_max_width_pct * logical.x
+fn load_camera_layouts(mut cmds: Commands) {
+    let res: anyhow::Result<CameraLayouts> = try {
+        let layouts =
+            fs::read_to_string(config_path(CAMERA_LAYOUTS_FILE)).context("Read camera layouts")?;
+        toml::from_str(&layouts).context("Parse camera layouts")?
     };
-    let other_width = if other_width * other_aspect_ratio > logical.y {
-        (1.0 / other_aspect_ratio) * logical.y
-    } else {
-        other_width
+
+    let layouts = match res {
+        Ok(layouts) => layouts,
+        Err(err) => {
+            warn!("Using default camera layouts: {err:?}");
+            CameraLayouts::default()
+        }
     };
 
-    let other_remaining_height = logical.y - other_width * other_aspect_ratio;
+    cmds.insert_resource(layouts);
+}
 
-    let master_width_needed = logical.x - other_width;
-    let master_width = if master_width_needed * master_aspect_ratio > logical.y {
-        (1.0 / master_aspect_ratio) * logical.y
-    } else {
-        master_width_needed
+fn save_camera_layouts(layouts: Res<CameraLayouts>) {
+    if !layouts.is_changed() || layouts.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*layouts) else {
+        error!("Could not serialize camera layouts");
+        return;
     };
 
-    for (handle, display, mut transform) in &mut displays {
-        let Some(image) = images.get(&handle.0) else {
-            continue;
-        };
+    let res = fs::write(config_path(CAMERA_LAYOUTS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write camera layouts: {err:?}");
+    }
+}
 
-        if display.0 != 0 {
-            let total_aspect_ratio = aspect_ratios
-                .iter()
-                .skip(1)
-                .take(display.0 as usize - 1)
-                .map(|it| it.1)
-                .sum::<f32>();
-            let height_so_far = total_aspect_ratio * other_width
-                + other_remaining_height / (count as f32 + 1.0) * display.0 as f32;
-
-            *transform = transform
-                .with_translation(Vec3::new(
-                    logical.x / 2.0 - other_width / 2.0,
-                    logical.y / 2.0
-                        - height_so_far
-                        - 0.5 / f32::from(image.aspect_ratio()) * other_width,
-                    0.0,
-                ))
-                .with_scale(Vec3::new(
-                    other_width,
-                    1.0 / f32::from(image.aspect_ratio()) * other_width,
-                    1.0,
-                ));
-        } else {
-            *transform = transform
-                .with_translation(Vec3::new(
-                    master_width_needed / 2.0 - logical.x / 2.0,
-                    0.0,
-                    0.0,
-                ))
-                .with_scale(Vec3::new(
-                    master_width,
-                    master_aspect_ratio * master_width,
-                    1.0,
-                ));
+// TODO(low): Support multiple robots
+fn select_robot_layout(robots: Query<&Name, Added<Robot>>, mut layouts: ResMut<CameraLayouts>) {
+    for name in &robots {
+        if layouts.active_robot.as_deref() != Some(name.as_str()) {
+            layouts.active_robot = Some(name.to_string());
+        }
+    }
+}
+
+fn camera_hotkeys(
+    inputs: Query<&ActionState<Action>, With<InputMarker>>,
+    mut events: EventWriter<CameraLayoutEvent>,
+) {
+    for action_state in &inputs {
+        if action_state.just_pressed(&Action::CycleCameraLayout) {
+            events.send(CameraLayoutEvent::CycleMode);
+        }
+
+        if action_state.just_pressed(&Action::CycleMasterCamera) {
+            events.send(CameraLayoutEvent::CycleMaster);
+        }
+    }
+}
+
+fn handle_layout_events(
+    mut cmds: Commands,
+    mut events: EventReader<CameraLayoutEvent>,
+    mut layouts: ResMut<CameraLayouts>,
+    mut masters: EventWriter<MakeMaster>,
+    tree: Query<&Video, With<DisplayParent>>,
+    displays: Query<(&DisplayMarker, &Name)>,
+) {
+    for event in events.read() {
+        match event {
+            CameraLayoutEvent::SetMode(mode) => {
+                layouts.current_mut().mode = *mode;
+            }
+            CameraLayoutEvent::CycleMode => {
+                let layout = layouts.current_mut();
+                layout.mode = layout.mode.next();
+                info!("Camera layout: {}", layout.mode.name());
+            }
+            CameraLayoutEvent::CycleMaster => {
+                let Ok(tree) = tree.get_single() else {
+                    continue;
+                };
+                let hidden = layouts.current().hidden;
+
+                let shown = tree
+                    .cameras
+                    .iter()
+                    .filter_map(|&it| displays.get(it).ok().map(|display| (it, display)))
+                    .filter(|(_, (_, name))| !hidden.contains(name.as_str()))
+                    .collect::<Vec<_>>();
+
+                let master = shown.iter().position(|(_, (display, _))| display.0 == 0);
+                let next = match master {
+                    Some(idx) => shown.get((idx + 1) % shown.len()),
+                    None => shown.first(),
+                };
+
+                if let Some(&(next, _)) = next {
+                    masters.send(MakeMaster(next));
+                    cmds.entity(next).insert(VideoMasterMarker);
+                }
+            }
+            CameraLayoutEvent::SetHidden { camera, hidden } => {
+                let layout = layouts.current_mut();
+                if *hidden {
+                    layout.hidden.insert(camera.clone());
+                } else {
+                    layout.hidden.remove(camera);
+                }
+            }
+            CameraLayoutEvent::ShowAll => {
+                layouts.current_mut().hidden.clear();
+            }
+            CameraLayoutEvent::SetTileDecimation(decimation) => {
+                layouts.current_mut().tiles = *decimation;
+            }
         }
     }
 }
@@ -284,3 +642,155 @@ fn enable_camera(
         *last = settings.enabled;
     }
 }
+
+/// Dims displays whose stream stopped and labels them, so a frozen frame isn't mistaken for live
+/// video
+fn stream_health_overlay(
+    mut contexts: EguiContexts,
+    displays: Query<(&StreamHealth, &Transform, &Visibility), With<DisplayMarker>>,
+    camera: Query<&BevyCamera, With<DisplayCamera>>,
+    egui_settings: Query<&EguiContextSettings, With<PrimaryWindow>>,
+    settings: Res<VideoDisplay2DSettings>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    let Some(logical) = camera
+        .get_single()
+        .ok()
+        .and_then(|it| it.logical_viewport_size())
+    else {
+        return;
+    };
+    let scale = egui_settings
+        .get_single()
+        .map(|it| it.scale_factor)
+        .unwrap_or(1.0);
+
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Background, Id::new("Stream Health")));
+
+    for (health, transform, visibility) in &displays {
+        if health.state == StreamState::Healthy || *visibility == Visibility::Hidden {
+            continue;
+        }
+
+        let rect = display_rect(transform, logical, scale);
+
+        let (text, color) = match health.state {
+            StreamState::Stale => (
+                format!("STALE {:.0}s", health.frame_age.as_secs_f32()),
+                Color32::RED,
+            ),
+            _ => ("RECOVERING".to_owned(), Color32::ORANGE),
+        };
+
+        painter.rect_filled(rect, 0.0, Color32::from_black_alpha(140));
+        painter.rect_stroke(rect, 0.0, Stroke::new(3.0, color), StrokeKind::Inside);
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            text,
+            FontId::proportional((rect.height() / 8.0).clamp(14.0, 48.0)),
+            color,
+        );
+    }
+}
+
+/// Labels each display with the frame rate it is receiving after decimation
+fn tile_fps_overlay(
+    mut contexts: EguiContexts,
+    mut last_measurement: Local<Option<Instant>>,
+    // Frames received at the last measurement and the measured rate
+    mut measured: Local<HashMap<Entity, (u64, f32)>>,
+    displays: Query<
+        (
+            Entity,
+            &FramesReceived,
+            Option<&FrameDecimation>,
+            &Transform,
+            &Visibility,
+        ),
+        With<DisplayMarker>,
+    >,
+    camera: Query<&BevyCamera, With<DisplayCamera>>,
+    egui_settings: Query<&EguiContextSettings, With<PrimaryWindow>>,
+    settings: Res<VideoDisplay2DSettings>,
+) {
+    if !settings.enabled || !settings.show_fps {
+        *last_measurement = None;
+        measured.clear();
+        return;
+    }
+
+    let elapsed = last_measurement.map(|it| it.elapsed());
+    if elapsed.is_none_or(|it| it >= FPS_INTERVAL) {
+        *last_measurement = Some(Instant::now());
+        measured.retain(|entity, _| displays.contains(*entity));
+
+        for (entity, frames, ..) in &displays {
+            let (last_frames, fps) = measured.entry(entity).or_insert((frames.0, 0.0));
+
+            if let Some(elapsed) = elapsed {
+                // The counter restarts along with the stream
+                *fps = frames.0.saturating_sub(*last_frames) as f32 / elapsed.as_secs_f32();
+            }
+            *last_frames = frames.0;
+        }
+    }
+
+    let Some(logical) = camera
+        .get_single()
+        .ok()
+        .and_then(|it| it.logical_viewport_size())
+    else {
+        return;
+    };
+    let scale = egui_settings
+        .get_single()
+        .map(|it| it.scale_factor)
+        .unwrap_or(1.0);
+
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Background, Id::new("Tile FPS")));
+
+    for (entity, _, decimation, transform, visibility) in &displays {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+
+        let Some(&(_, fps)) = measured.get(&entity) else {
+            continue;
+        };
+        let decimation = decimation.copied().unwrap_or_default();
+
+        let rect = display_rect(transform, logical, scale);
+        let text = format!(
+            "{fps:.1} fps, 1/{}, {:.0}%",
+            decimation.every,
+            decimation.scale * 100.0
+        );
+
+        let galley = painter.layout_no_wrap(text, FontId::monospace(12.0), Color32::WHITE);
+        let text_rect = Align2::LEFT_BOTTOM
+            .anchor_size(rect.left_bottom() + egui::vec2(4.0, -4.0), galley.size());
+
+        painter.rect_filled(text_rect.expand(2.0), 2.0, Color32::from_black_alpha(160));
+        painter.galley(text_rect.min, galley, Color32::WHITE);
+    }
+}
+
+/// Where a display is drawn in egui's coordinates
+fn display_rect(transform: &Transform, logical: Vec2, scale: f32) -> egui::Rect {
+    // Displays are placed relative to the center of the window with +Y up
+    let center = Vec2::new(
+        logical.x / 2.0 + transform.translation.x,
+        logical.y / 2.0 - transform.translation.y,
+    ) / scale;
+    let size = transform.scale.truncate() / scale;
+
+    egui::Rect::from_center_size(egui::pos2(center.x, center.y), egui::vec2(size.x, size.y))
+}
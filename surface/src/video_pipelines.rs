@@ -1,15 +1,30 @@
+pub mod background;
+pub mod calibration;
+pub mod color;
 pub mod edges;
 pub mod marker;
 pub mod measure;
+pub mod mosaic;
+pub mod odometry;
 // pub mod photosphere;
 pub mod copy_to_ecs;
+pub mod denoise;
+pub mod detection;
+pub mod graph;
+#[cfg(test)]
+pub mod harness;
+pub mod horizon;
+pub mod laser;
 pub mod save;
 pub mod scale;
 pub mod squares;
+pub mod tags;
+pub mod transect;
 pub mod undistort;
 
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     marker::PhantomData,
     sync::{Arc, Mutex},
 };
@@ -28,20 +43,28 @@ use bevy::{
     hierarchy::DespawnRecursiveExt,
     utils::all_tuples,
 };
-use common::{components::RobotId, error::ErrorEvent};
+use common::{
+    components::{Robot, RobotId},
+    error::ErrorEvent,
+};
 use crossbeam::{
     atomic::AtomicCell,
     channel::{bounded, Receiver, Sender},
 };
 use opencv::core::Mat;
+use serde::{Deserialize, Serialize};
 // use photosphere::PhotoSpherePipelinePlugin;
 use tracing::{debug, error};
 use undistort::UndistortPipelinePlugin;
 
 use crate::{
     video_pipelines::{
-        edges::EdgesPipelinePlugin, marker::MarkerPipelinePlugin, save::SavePipelinePlugin,
-        squares::SquarePipelinePlugin,
+        background::Background, calibration::CalibrationPipelinePlugin, color::ColorPipelinePlugin,
+        denoise::DenoisePipelinePlugin, detection::DetectionPipelinePlugin,
+        edges::EdgesPipelinePlugin, graph::PipelineGraphPlugin, horizon::HorizonPipelinePlugin,
+        laser::LaserPipelinePlugin, marker::MarkerPipelinePlugin, mosaic::MosaicPipelinePlugin,
+        odometry::OdometryPipelinePlugin, save::SavePipelinePlugin, squares::SquarePipelinePlugin,
+        tags::TagPipelinePlugin, transect::TransectPipelinePlugin,
     },
     video_stream::{VideoProcessor, VideoProcessorFactory},
 };
@@ -56,14 +79,25 @@ impl PluginGroup for VideoPipelinePlugins {
                 app.insert_resource(VideoCallbackChannels { cmd_tx, cmd_rx });
                 app.add_systems(Update, schedule_pipeline_callbacks);
             })
+            .add(CalibrationPipelinePlugin)
+            .add(ColorPipelinePlugin)
+            .add(DenoisePipelinePlugin)
+            .add(DetectionPipelinePlugin)
             .add(EdgesPipelinePlugin)
+            .add(HorizonPipelinePlugin)
+            .add(LaserPipelinePlugin)
             .add(MarkerPipelinePlugin)
+            .add(MosaicPipelinePlugin)
             // .add(MeasurePipelinePlugin)
+            .add(OdometryPipelinePlugin)
             // .add(PhotoSpherePipelinePlugin)
             .add(SavePipelinePlugin)
             // .add(ScalePipelinePlugin)
             .add(SquarePipelinePlugin)
+            .add(TagPipelinePlugin)
+            .add(TransectPipelinePlugin)
             .add(UndistortPipelinePlugin)
+            .add(PipelineGraphPlugin)
     }
 }
 
@@ -71,6 +105,15 @@ pub trait AppPipelineExt {
     fn register_video_pipeline<P>(&mut self, name: impl Into<Cow<'static, str>>) -> &mut Self
     where
         P: Pipeline + FromWorldEntity;
+
+    /// Registers a pipeline that runs on the async compute pool instead of the video thread, see
+    /// `background::Background`
+    fn register_background_video_pipeline<P>(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+    ) -> &mut Self
+    where
+        P: Pipeline + FromWorldEntity;
 }
 
 impl AppPipelineExt for App {
@@ -88,11 +131,23 @@ impl AppPipelineExt for App {
             .0
             .push(VideoPipeline {
                 name: name.clone(),
-                factory: VideoProcessorFactory::new::<PipelineHandler<P>>(name),
+                factory: VideoProcessorFactory::new::<PipelineHandler<P>>(name.clone()),
             });
 
+        graph::register_stage::<P>(self, name);
+
         self
     }
+
+    fn register_background_video_pipeline<P>(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+    ) -> &mut Self
+    where
+        P: Pipeline + FromWorldEntity,
+    {
+        self.register_video_pipeline::<Background<P>>(name)
+    }
 }
 
 #[derive(Resource)]
@@ -132,6 +187,118 @@ pub trait Pipeline: FromWorldEntity + Send + 'static {
     /// Entity is implicitly despawned after this function returns
     // TODO: Expose camera entity as well
     fn cleanup(self, entity_world: &mut EntityWorldMut);
+
+    /// Values that can be tuned live from the pipeline graph editor
+    fn parameters() -> &'static [PipelineParameter]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    /// Called before `process` whenever the tuned values change
+    fn set_parameters(&mut self, _parameters: &PipelineParameters) {}
+}
+
+/// A value exposed by a pipeline for tuning at runtime
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipelineParameter {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterKind {
+    Toggle { default: bool },
+    Int { default: i64, min: i64, max: i64 },
+    Float { default: f64, min: f64, max: f64 },
+}
+
+impl PipelineParameter {
+    pub const fn toggle(name: &'static str, default: bool) -> Self {
+        Self {
+            name,
+            kind: ParameterKind::Toggle { default },
+        }
+    }
+
+    pub const fn int(name: &'static str, default: i64, min: i64, max: i64) -> Self {
+        Self {
+            name,
+            kind: ParameterKind::Int { default, min, max },
+        }
+    }
+
+    pub const fn float(name: &'static str, default: f64, min: f64, max: f64) -> Self {
+        Self {
+            name,
+            kind: ParameterKind::Float { default, min, max },
+        }
+    }
+
+    pub fn default_value(&self) -> ParameterValue {
+        match self.kind {
+            ParameterKind::Toggle { default } => ParameterValue::Toggle(default),
+            ParameterKind::Int { default, .. } => ParameterValue::Int(default),
+            ParameterKind::Float { default, .. } => ParameterValue::Float(default),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterValue {
+    Toggle(bool),
+    Int(i64),
+    Float(f64),
+}
+
+/// Tuned values of a pipeline by parameter name, missing values use the parameter's default
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PipelineParameters(pub BTreeMap<String, ParameterValue>);
+
+impl PipelineParameters {
+    pub fn get(&self, parameter: &PipelineParameter) -> ParameterValue {
+        self.0
+            .get(parameter.name)
+            .copied()
+            .unwrap_or_else(|| parameter.default_value())
+    }
+
+    pub fn toggle(&self, parameter: &PipelineParameter) -> bool {
+        match self.get(parameter) {
+            ParameterValue::Toggle(value) => value,
+            _ => matches!(parameter.kind, ParameterKind::Toggle { default: true }),
+        }
+    }
+
+    pub fn int(&self, parameter: &PipelineParameter) -> i64 {
+        let value = match self.get(parameter) {
+            ParameterValue::Int(value) => value,
+            ParameterValue::Float(value) => value.round() as i64,
+            ParameterValue::Toggle(value) => value as i64,
+        };
+
+        match parameter.kind {
+            ParameterKind::Int { min, max, .. } => value.clamp(min, max),
+            _ => value,
+        }
+    }
+
+    pub fn float(&self, parameter: &PipelineParameter) -> f64 {
+        // Whole numbers written by hand into the config deserialize as ints
+        let value = match self.get(parameter) {
+            ParameterValue::Float(value) => value,
+            ParameterValue::Int(value) => value as f64,
+            ParameterValue::Toggle(value) => value as u8 as f64,
+        };
+
+        match parameter.kind {
+            ParameterKind::Float { min, max, .. } => value.clamp(min, max),
+            _ => value,
+        }
+    }
 }
 
 pub trait FromWorldEntity {
@@ -346,6 +513,38 @@ impl PipelineCallbacks<'_> {
         }
     }
 
+    /// Runs `f` on the robot the camera belongs to
+    pub fn robot<F: FnOnce(EntityWorldMut) + Send + 'static>(&mut self, f: F) {
+        let entity = self.pipeline_entity;
+        let res = self.cmds_tx.send(Box::new(move |world: &mut World| {
+            let Some(&robot_id) = world.get::<RobotId>(entity) else {
+                world.send_event(ErrorEvent(anyhow!("No robot for video pipeline")));
+
+                return;
+            };
+
+            let robot = world
+                .query_filtered::<(Entity, &RobotId), With<Robot>>()
+                .iter(world)
+                .find(|(_, id)| **id == robot_id)
+                .map(|(robot, _)| robot);
+            let Some(robot) = robot else {
+                world.send_event(ErrorEvent(anyhow!(
+                    "No entity for video pipeline robot callback"
+                )));
+
+                return;
+            };
+
+            (f)(world.entity_mut(robot));
+        }));
+
+        if res.is_err() {
+            error!("Could not send entity callback to bevy");
+            *self.should_end = true;
+        }
+    }
+
     pub fn should_end(&mut self) {
         debug!("video pipeline should_end hit");
         *self.should_end = true;
@@ -0,0 +1,211 @@
+//! Runs a pipeline on bevy's async compute pool so slow processing does not hold back the video
+//! thread, frames that arrive while the pipeline is busy replace each other so only the newest
+//! one gets processed
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::{
+    prelude::{Component, Entity, EntityRef, EntityWorldMut, World},
+    tasks::AsyncComputeTaskPool,
+};
+use common::error::ErrorEvent;
+use crossbeam::channel::Sender;
+use opencv::{core::Mat, prelude::*};
+
+use crate::video_pipelines::{
+    FromWorldEntity, Pipeline, PipelineCallbacks, PipelineParameter, PipelineParameters,
+    WorldCallback,
+};
+
+/// Processing statistics of a background pipeline, kept on the pipeline entity
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct BackgroundStats {
+    pub processed: u64,
+    /// Frames replaced by a newer frame before a worker got to them
+    pub dropped: u64,
+    pub last_duration: Duration,
+}
+
+/// Wraps a pipeline to run it off of the video thread, the preview is passed through untouched
+/// and the wrapped pipeline reports its results through its callbacks
+pub struct Background<P: Pipeline> {
+    shared: Arc<Shared<P>>,
+}
+
+struct Shared<P: Pipeline> {
+    state: Mutex<State<P>>,
+    should_end: AtomicBool,
+}
+
+struct State<P: Pipeline> {
+    /// `None` while a worker owns the pipeline
+    pipeline: Option<P>,
+    /// The newest frame that has not been processed yet
+    pending: Option<Job<P>>,
+    parameters: Option<PipelineParameters>,
+    stats: BackgroundStats,
+    ended: bool,
+}
+
+struct Job<P: Pipeline> {
+    img: Mat,
+    input: Arc<P::Input>,
+
+    cmds_tx: Sender<WorldCallback>,
+    pipeline_entity: Entity,
+    camera_entity: Entity,
+}
+
+impl<P: Pipeline> Pipeline for Background<P> {
+    type Input = Arc<P::Input>;
+
+    fn collect_inputs(world: &World, entity: &EntityRef) -> Self::Input {
+        Arc::new(P::collect_inputs(world, entity))
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        if self.shared.should_end.load(Ordering::Relaxed) {
+            cmds.should_end();
+            return Ok(img);
+        }
+
+        let job = Job {
+            img: img.try_clone().context("Copy frame")?,
+            input: data.clone(),
+
+            cmds_tx: cmds.cmds_tx.clone(),
+            pipeline_entity: cmds.pipeline_entity,
+            camera_entity: cmds.camera_entity,
+        };
+
+        let mut state = self.shared.state.lock().expect("Lock background state");
+        if state.pending.replace(job).is_some() {
+            state.stats.dropped += 1;
+        }
+
+        if let Some(pipeline) = state.pipeline.take() {
+            let shared = self.shared.clone();
+            AsyncComputeTaskPool::get()
+                .spawn(async move { run_background(shared, pipeline) })
+                .detach();
+        }
+
+        Ok(img)
+    }
+
+    fn cleanup(self, entity_world: &mut EntityWorldMut) {
+        let mut state = self.shared.state.lock().expect("Lock background state");
+        state.ended = true;
+        state.pending = None;
+
+        // When a worker is still busy it drops the pipeline once it finishes instead
+        if let Some(pipeline) = state.pipeline.take() {
+            pipeline.cleanup(entity_world);
+        }
+    }
+
+    fn parameters() -> &'static [PipelineParameter] {
+        P::parameters()
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        let mut state = self.shared.state.lock().expect("Lock background state");
+        state.parameters = Some(parameters.clone());
+    }
+}
+
+impl<P: Pipeline> FromWorldEntity for Background<P> {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    pipeline: Some(P::from(world, camera)?),
+                    pending: None,
+                    parameters: None,
+                    stats: BackgroundStats::default(),
+                    ended: false,
+                }),
+                should_end: AtomicBool::new(false),
+            }),
+        })
+    }
+}
+
+/// Processes pending frames until none are left, then hands the pipeline back
+fn run_background<P: Pipeline>(shared: Arc<Shared<P>>, mut pipeline: P) {
+    loop {
+        let (job, parameters) = {
+            let mut state = shared.state.lock().expect("Lock background state");
+            if state.ended {
+                return;
+            }
+
+            let Some(job) = state.pending.take() else {
+                state.pipeline = Some(pipeline);
+                return;
+            };
+
+            (job, state.parameters.take())
+        };
+
+        if let Some(parameters) = parameters {
+            pipeline.set_parameters(&parameters);
+        }
+
+        let Job {
+            mut img,
+            input,
+            cmds_tx,
+            pipeline_entity,
+            camera_entity,
+        } = job;
+
+        let mut should_end = false;
+        let mut cmds = PipelineCallbacks {
+            cmds_tx: &cmds_tx,
+            pipeline_entity,
+            camera_entity,
+            should_end: &mut should_end,
+        };
+
+        let start = Instant::now();
+        let res = pipeline.process(&mut cmds, &input, &mut img).map(|_| ());
+        let duration = start.elapsed();
+
+        if let Err(err) = res {
+            cmds.world(move |world| {
+                world.send_event(ErrorEvent(err.context("Process background pipeline")));
+            });
+        }
+
+        let stats = {
+            let mut state = shared.state.lock().expect("Lock background state");
+            state.stats.processed += 1;
+            state.stats.last_duration = duration;
+
+            state.stats
+        };
+        cmds.pipeline(move |mut entity| {
+            entity.insert(stats);
+        });
+
+        if should_end {
+            shared.should_end.store(true, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use bevy::{math::Mat3A, prelude::*};
+use bevy_egui::EguiContexts;
+use common::components::CameraCalibration;
+use egui::{DragValue, Id};
+use opencv::{
+    calib3d,
+    core::{Point2f, Point3f, Size, Vector},
+    imgproc,
+    objdetect::{self, CharucoBoard, CharucoDetector, PredefinedDictionaryType},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::video_pipelines::{AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks};
+
+use super::PipelineCamera;
+
+pub const CALIBRATIONS_FILE: &str = "camera_calibrations.toml";
+
+/// Number of board views collected before calibrating
+const TARGET_VIEWS: usize = 20;
+/// Minimum time between collected views so the board is seen from different angles
+const VIEW_INTERVAL: Duration = Duration::from_millis(1000);
+/// ChArUco views with fewer corners than this are ignored
+const MIN_CHARUCO_CORNERS: usize = 6;
+
+pub struct CalibrationPipelinePlugin;
+
+impl Plugin for CalibrationPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<CalibrationPipeline>("Calibration Pipeline")
+            .add_systems(PreStartup, load_calibrations)
+            .add_systems(Update, (calibration_ui, save_calibrations).chain());
+    }
+}
+
+/// Intrinsics computed on the surface, keyed by camera name and saved to `CALIBRATIONS_FILE`
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CalibrationStore {
+    pub board: CalibrationBoard,
+    pub cameras: BTreeMap<String, StoredCalibration>,
+    pub stereo: Option<StereoCalibration>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredCalibration {
+    pub calibration: CameraCalibration,
+    /// The resolution the calibration was computed at
+    pub width: u32,
+    pub height: u32,
+    /// RMS reprojection error in pixels
+    pub error: f64,
+}
+
+/// Two cameras mounted side by side facing the same direction, by camera name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StereoCalibration {
+    pub left: String,
+    pub right: String,
+    /// Distance between the optical centers in meters
+    pub baseline: f32,
+}
+
+/// The calibration target held in front of the camera
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationBoard {
+    pub kind: BoardKind,
+    /// Number of squares along each side of the board
+    pub columns: u32,
+    pub rows: u32,
+    /// Side length of a square in meters
+    pub square_size: f32,
+    /// Side length of an ArUco marker in meters, only used by ChArUco boards
+    pub marker_size: f32,
+}
+
+impl Default for CalibrationBoard {
+    fn default() -> Self {
+        Self {
+            kind: BoardKind::Chessboard,
+            columns: 10,
+            rows: 7,
+            square_size: 0.025,
+            marker_size: 0.018,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardKind {
+    Chessboard,
+    /// A chessboard with ArUco markers from `DICT_5X5_100` in the white squares
+    Charuco,
+}
+
+impl BoardKind {
+    pub const ALL: [BoardKind; 2] = [BoardKind::Chessboard, BoardKind::Charuco];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoardKind::Chessboard => "Chessboard",
+            BoardKind::Charuco => "ChArUco",
+        }
+    }
+}
+
+/// A camera's intrinsics along with the resolution they were computed at, if known
+#[derive(Debug, Clone)]
+pub struct LoadedCalibration {
+    pub calibration: CameraCalibration,
+    pub size: Option<Size>,
+}
+
+impl LoadedCalibration {
+    /// The camera matrix as an OpenCV mat, rescaled if the stream is not at the calibrated
+    /// resolution
+    pub fn camera_matrix(&self, size: Size) -> anyhow::Result<Mat> {
+        let mut mtx = Mat::from_slice_2d(&self.calibration.camera_matrix.to_cols_array_2d())
+            .context("Mat from camera matrix")?;
+
+        if let Some(calibrated) = self.size {
+            if calibrated != size && calibrated.width > 0 && calibrated.height > 0 {
+                let scale_x = size.width as f32 / calibrated.width as f32;
+                let scale_y = size.height as f32 / calibrated.height as f32;
+
+                for col in 0..3 {
+                    *mtx.at_2d_mut::<f32>(0, col).context("Scale fx")? *= scale_x;
+                    *mtx.at_2d_mut::<f32>(1, col).context("Scale fy")? *= scale_y;
+                }
+            }
+        }
+
+        Ok(mtx)
+    }
+
+    pub fn distortion(&self) -> anyhow::Result<Mat> {
+        Mat::from_slice_2d(&[&self.calibration.distortion_coefficients])
+            .context("Mat from dist coeffs")
+    }
+}
+
+/// The calibration to use for a camera, one computed on the surface takes priority over the one in
+/// the robot's config
+pub fn camera_calibration(world: &World, camera: Entity) -> Option<LoadedCalibration> {
+    let stored = world
+        .get::<Name>(camera)
+        .zip(world.get_resource::<CalibrationStore>())
+        .and_then(|(name, store)| store.cameras.get(name.as_str()));
+
+    if let Some(stored) = stored {
+        return Some(LoadedCalibration {
+            calibration: stored.calibration.clone(),
+            size: Some(Size::new(stored.width as i32, stored.height as i32)),
+        });
+    }
+
+    world
+        .get::<CameraCalibration>(camera)
+        .filter(|it| it.camera_matrix != Mat3A::ZERO)
+        .map(|calibration| LoadedCalibration {
+            calibration: calibration.clone(),
+            size: None,
+        })
+}
+
+/// Shown while a calibration pipeline is running
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CalibrationProgress {
+    pub views: usize,
+    pub calibrating: bool,
+}
+
+pub struct CalibrationPipeline {
+    camera_name: String,
+
+    gray: Mat,
+    size: Size,
+    board: Option<CalibrationBoard>,
+    object_points: Vector<Vector<Point3f>>,
+    image_points: Vector<Vector<Point2f>>,
+    last_view: Option<Instant>,
+    started: bool,
+
+    charuco: Option<(CalibrationBoard, CharucoDetector)>,
+}
+
+impl Pipeline for CalibrationPipeline {
+    type Input = CalibrationBoard;
+
+    fn collect_inputs(world: &World, _entity: &EntityRef) -> Self::Input {
+        world
+            .get_resource::<CalibrationStore>()
+            .map(|it| it.board)
+            .unwrap_or_default()
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        board: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        if !self.started {
+            self.started = true;
+            cmds.pipeline(|mut entity| {
+                entity.insert(CalibrationProgress {
+                    views: 0,
+                    calibrating: false,
+                });
+            });
+        }
+
+        let size = img.size().context("Get image size")?;
+
+        // Views taken at different resolutions or of different boards cannot be combined
+        if size != self.size || self.board != Some(*board) {
+            self.size = size;
+            self.board = Some(*board);
+            self.object_points.clear();
+            self.image_points.clear();
+        }
+
+        imgproc::cvt_color_def(img, &mut self.gray, imgproc::COLOR_BGR2GRAY)
+            .context("Convert to grayscale")?;
+
+        let view = match board.kind {
+            BoardKind::Chessboard => self.detect_chessboard(board, img)?,
+            BoardKind::Charuco => self.detect_charuco(board, img)?,
+        };
+
+        let Some((object_points, image_points)) = view else {
+            return Ok(img);
+        };
+
+        if self
+            .last_view
+            .is_some_and(|last| last.elapsed() < VIEW_INTERVAL)
+        {
+            return Ok(img);
+        }
+
+        self.last_view = Some(Instant::now());
+        self.object_points.push(object_points);
+        self.image_points.push(image_points);
+
+        let views = self.object_points.len();
+        let calibrating = views >= TARGET_VIEWS;
+        cmds.pipeline(move |mut entity| {
+            entity.insert(CalibrationProgress { views, calibrating });
+        });
+
+        if calibrating {
+            let stored = self.calibrate().context("Calibrate camera")?;
+            info!(
+                "Calibrated {} with an error of {:.3}px",
+                self.camera_name, stored.error
+            );
+
+            let name = self.camera_name.clone();
+            cmds.world(move |world| {
+                world
+                    .resource_mut::<CalibrationStore>()
+                    .cameras
+                    .insert(name, stored);
+            });
+            cmds.should_end();
+        }
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+impl CalibrationPipeline {
+    fn detect_chessboard(
+        &mut self,
+        board: &CalibrationBoard,
+        img: &mut Mat,
+    ) -> anyhow::Result<Option<(Vector<Point3f>, Vector<Point2f>)>> {
+        // OpenCV wants the number of inner corners
+        let pattern = Size::new(board.columns as i32 - 1, board.rows as i32 - 1);
+        if pattern.width < 2 || pattern.height < 2 {
+            bail!("Chessboard must be at least 3x3");
+        }
+
+        let mut corners = Vector::<Point2f>::new();
+        let found = calib3d::find_chessboard_corners_sb(&self.gray, pattern, &mut corners, 0)
+            .context("Find chessboard corners")?;
+
+        calib3d::draw_chessboard_corners(img, pattern, &corners, found)
+            .context("Draw chessboard corners")?;
+
+        if !found {
+            return Ok(None);
+        }
+
+        let object_points = (0..pattern.height)
+            .flat_map(|row| (0..pattern.width).map(move |col| (row, col)))
+            .map(|(row, col)| {
+                Point3f::new(
+                    col as f32 * board.square_size,
+                    row as f32 * board.square_size,
+                    0.0,
+                )
+            })
+            .collect();
+
+        Ok(Some((object_points, corners)))
+    }
+
+    fn detect_charuco(
+        &mut self,
+        board: &CalibrationBoard,
+        img: &mut Mat,
+    ) -> anyhow::Result<Option<(Vector<Point3f>, Vector<Point2f>)>> {
+        if self.charuco.as_ref().map(|it| it.0) != Some(*board) {
+            let dictionary =
+                objdetect::get_predefined_dictionary(PredefinedDictionaryType::DICT_5X5_100)
+                    .context("Get ArUco dictionary")?;
+            let charuco_board = CharucoBoard::new_def(
+                Size::new(board.columns as i32, board.rows as i32),
+                board.square_size,
+                board.marker_size,
+                &dictionary,
+            )
+            .context("Create ChArUco board")?;
+            let detector =
+                CharucoDetector::new_def(&charuco_board).context("Create ChArUco detector")?;
+
+            self.charuco = Some((*board, detector));
+        }
+
+        let Some((_, detector)) = &self.charuco else {
+            unreachable!();
+        };
+
+        let mut corners = Vector::<Point2f>::new();
+        let mut ids = Vector::<i32>::new();
+        detector
+            .detect_board_def(&self.gray, &mut corners, &mut ids)
+            .context("Detect ChArUco board")?;
+
+        if ids.is_empty() {
+            return Ok(None);
+        }
+
+        objdetect::draw_detected_corners_charuco_def(img, &corners, &ids)
+            .context("Draw ChArUco corners")?;
+
+        if ids.len() < MIN_CHARUCO_CORNERS {
+            return Ok(None);
+        }
+
+        let mut object_points = Vector::<Point3f>::new();
+        let mut image_points = Vector::<Point2f>::new();
+        detector
+            .get_board()
+            .context("Get ChArUco board")?
+            .match_image_points(&corners, &ids, &mut object_points, &mut image_points)
+            .context("Match ChArUco points")?;
+
+        Ok(Some((object_points, image_points)))
+    }
+
+    fn calibrate(&self) -> anyhow::Result<StoredCalibration> {
+        let mut camera_matrix = Mat::default();
+        let mut distortion = Mat::default();
+        let mut rvecs = Vector::<Mat>::new();
+        let mut tvecs = Vector::<Mat>::new();
+
+        let error = calib3d::calibrate_camera_def(
+            &self.object_points,
+            &self.image_points,
+            self.size,
+            &mut camera_matrix,
+            &mut distortion,
+            &mut rvecs,
+            &mut tvecs,
+        )
+        .context("Calibrate")?;
+
+        // `CameraCalibration` stores the rows of the camera matrix as the columns of a `Mat3A`
+        let mut rows = [[0.0; 3]; 3];
+        for (row, values) in rows.iter_mut().enumerate() {
+            for (col, value) in values.iter_mut().enumerate() {
+                *value = *camera_matrix
+                    .at_2d::<f64>(row as i32, col as i32)
+                    .context("Read camera matrix")? as f32;
+            }
+        }
+
+        let distortion_coefficients = distortion
+            .data_typed::<f64>()
+            .context("Read distortion coefficients")?
+            .iter()
+            .map(|it| *it as f32)
+            .collect();
+
+        Ok(StoredCalibration {
+            calibration: CameraCalibration {
+                camera_matrix: Mat3A::from_cols_array_2d(&rows),
+                distortion_coefficients,
+            },
+            width: self.size.width as u32,
+            height: self.size.height as u32,
+            error,
+        })
+    }
+}
+
+impl FromWorldEntity for CalibrationPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let camera_name = world
+            .get::<Name>(camera)
+            .context("Camera entity must exist and have a name")?
+            .to_string();
+
+        Ok(Self {
+            camera_name,
+            gray: Mat::default(),
+            size: Size::default(),
+            board: None,
+            object_points: Vector::new(),
+            image_points: Vector::new(),
+            last_view: None,
+            started: false,
+            charuco: None,
+        })
+    }
+}
+
+fn calibration_ui(
+    mut contexts: EguiContexts,
+    mut store: ResMut<CalibrationStore>,
+    pipelines: Query<(Entity, &PipelineCamera, &CalibrationProgress)>,
+    cameras: Query<&Name>,
+) {
+    for (entity, camera, progress) in &pipelines {
+        let name = cameras
+            .get(camera.camera())
+            .map(|it| it.to_string())
+            .unwrap_or_default();
+
+        egui::Window::new(format!("Calibrate {name}"))
+            .id(Id::new(entity))
+            .show(contexts.ctx_mut(), |ui| {
+                let mut board = store.board;
+
+                ui.horizontal(|ui| {
+                    for kind in BoardKind::ALL {
+                        ui.selectable_value(&mut board.kind, kind, kind.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Squares:");
+                    ui.add(DragValue::new(&mut board.columns).range(3..=30));
+                    ui.label("x");
+                    ui.add(DragValue::new(&mut board.rows).range(3..=30));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Square Size:");
+                    ui.add(
+                        DragValue::new(&mut board.square_size)
+                            .speed(0.001)
+                            .range(0.001..=1.0)
+                            .suffix("m"),
+                    );
+                });
+                if board.kind == BoardKind::Charuco {
+                    ui.horizontal(|ui| {
+                        ui.label("Marker Size:");
+                        ui.add(
+                            DragValue::new(&mut board.marker_size)
+                                .speed(0.001)
+                                .range(0.001..=board.square_size)
+                                .suffix("m"),
+                        );
+                    });
+                }
+
+                if board != store.board {
+                    store.board = board;
+                }
+
+                ui.separator();
+
+                if progress.calibrating {
+                    ui.label("Calibrating...");
+                } else {
+                    ui.label(format!(
+                        "Collected {}/{TARGET_VIEWS} views, move the board between views",
+                        progress.views
+                    ));
+                }
+
+                if let Some(stored) = store.cameras.get(&name) {
+                    ui.label(format!(
+                        "Current: {}x{}, {:.3}px error",
+                        stored.width, stored.height, stored.error
+                    ));
+
+                    if ui.button("Forget Calibration").clicked() {
+                        store.cameras.remove(&name);
+                    }
+                }
+            });
+    }
+}
+
+fn load_calibrations(mut cmds: Commands) {
+    let res: anyhow::Result<CalibrationStore> = try {
+        let calibrations = fs::read_to_string(CALIBRATIONS_FILE).context("Read calibrations")?;
+        toml::from_str(&calibrations).context("Parse calibrations")?
+    };
+
+    let calibrations = match res {
+        Ok(calibrations) => calibrations,
+        Err(err) => {
+            warn!("Using default calibrations: {err:?}");
+            CalibrationStore::default()
+        }
+    };
+
+    cmds.insert_resource(calibrations);
+}
+
+fn save_calibrations(store: Res<CalibrationStore>) {
+    if !store.is_changed() || store.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*store) else {
+        error!("Could not serialize calibrations");
+        return;
+    };
+
+    let res = fs::write(CALIBRATIONS_FILE, &str);
+    if let Err(err) = res {
+        error!("Could not write calibrations: {err:?}");
+    }
+}
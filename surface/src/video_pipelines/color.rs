@@ -0,0 +1,176 @@
+//! Color restoration for underwater footage, red is absorbed first at depth so the red channel is
+//! rebuilt from the green channel before a gray world white balance and a CLAHE contrast boost
+
+use std::mem;
+
+use anyhow::Context;
+use bevy::{
+    app::{App, Plugin},
+    prelude::{EntityRef, EntityWorldMut, World},
+};
+use opencv::{
+    core::{self, Ptr, Scalar, Size, Vector, CV_32F, CV_8U, CV_8UC3},
+    imgproc::{self, CLAHE},
+    prelude::*,
+};
+
+use crate::video_pipelines::{
+    AppPipelineExt, Pipeline, PipelineCallbacks, PipelineParameter, PipelineParameters,
+};
+
+pub struct ColorPipelinePlugin;
+
+impl Plugin for ColorPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<ColorPipeline>("Color Correction Pipeline");
+    }
+}
+
+const RED_COMPENSATION: PipelineParameter =
+    PipelineParameter::float("Red Compensation", 1.0, 0.0, 2.0);
+const WHITE_BALANCE: PipelineParameter = PipelineParameter::float("White Balance", 1.0, 0.0, 1.0);
+/// A clip limit of zero disables CLAHE
+const CLAHE_CLIP: PipelineParameter = PipelineParameter::float("CLAHE Clip Limit", 2.0, 0.0, 8.0);
+const CLAHE_TILES: PipelineParameter = PipelineParameter::int("CLAHE Tiles", 8, 2, 16);
+
+#[derive(Default)]
+pub struct ColorPipeline {
+    parameters: PipelineParameters,
+
+    float: Mat,
+    channels: Vector<Mat>,
+    red: Mat,
+    tmp: (Mat, Mat),
+    balanced_channels: Vector<Mat>,
+    balanced: Mat,
+
+    clahe: Option<Ptr<CLAHE>>,
+    lab: Mat,
+    lab_channels: Vector<Mat>,
+    lightness: Mat,
+    output: Mat,
+}
+
+impl Pipeline for ColorPipeline {
+    type Input = ();
+
+    fn collect_inputs(_world: &World, _entity: &EntityRef) -> Self::Input {
+        // No-op
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        _cmds: &mut PipelineCallbacks,
+        _data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        if img.typ() != CV_8UC3 {
+            return Ok(img);
+        }
+
+        let compensation = self.parameters.float(&RED_COMPENSATION);
+        let balance = self.parameters.float(&WHITE_BALANCE);
+        let clip = self.parameters.float(&CLAHE_CLIP);
+        let tiles = self.parameters.int(&CLAHE_TILES) as i32;
+
+        img.convert_to(&mut self.float, CV_32F, 1.0 / 255.0, 0.0)
+            .context("Convert to float")?;
+        core::split(&self.float, &mut self.channels).context("Split channels")?;
+
+        let blue = self.channels.get(0).context("Blue channel")?;
+        let green = self.channels.get(1).context("Green channel")?;
+        let mut red = self.channels.get(2).context("Red channel")?;
+
+        let mean_blue = core::mean_def(&blue).context("Mean blue")?.0[0];
+        let mean_green = core::mean_def(&green).context("Mean green")?.0[0];
+        let mut mean_red = core::mean_def(&red).context("Mean red")?.0[0];
+
+        // Moves some of the green channel into the red channel, weighted towards pixels where red
+        // is weak and green is strong, as described by Ancuti et al.
+        if compensation > 0.0 && mean_green > mean_red {
+            core::subtract_def(&Scalar::all(1.0), &red, &mut self.tmp.0).context("Invert red")?;
+            core::multiply(
+                &self.tmp.0,
+                &green,
+                &mut self.tmp.1,
+                compensation * (mean_green - mean_red),
+                -1,
+            )
+            .context("Red compensation")?;
+            core::add_def(&red, &self.tmp.1, &mut self.red).context("Compensate red")?;
+
+            red = mem::take(&mut self.red);
+            mean_red = core::mean_def(&red).context("Mean red")?.0[0];
+        }
+
+        // Gray world, scales each channel so that the average color is neutral
+        let gray = (mean_blue + mean_green + mean_red) / 3.0;
+        self.balanced_channels.clear();
+        for (channel, mean) in [(&blue, mean_blue), (&green, mean_green), (&red, mean_red)] {
+            let gain = if mean > 1e-6 {
+                1.0 + balance * (gray / mean - 1.0)
+            } else {
+                1.0
+            };
+
+            let mut balanced = Mat::default();
+            channel
+                .convert_to(&mut balanced, -1, gain, 0.0)
+                .context("Balance channel")?;
+            self.balanced_channels.push(balanced);
+        }
+
+        core::merge(&self.balanced_channels, &mut self.float).context("Merge channels")?;
+        self.float
+            .convert_to(&mut self.balanced, CV_8U, 255.0, 0.0)
+            .context("Convert to bytes")?;
+
+        if clip <= 0.0 {
+            return Ok(&mut self.balanced);
+        }
+
+        // Equalize the lightness only so the corrected colors are left alone
+        if self.clahe.is_none() {
+            let clahe =
+                imgproc::create_clahe(clip, Size::new(tiles, tiles)).context("Create CLAHE")?;
+            self.clahe = Some(clahe);
+        }
+        let Some(clahe) = &mut self.clahe else {
+            unreachable!();
+        };
+        clahe.set_clip_limit(clip).context("Set clip limit")?;
+        clahe
+            .set_tiles_grid_size(Size::new(tiles, tiles))
+            .context("Set tile size")?;
+
+        imgproc::cvt_color_def(&self.balanced, &mut self.lab, imgproc::COLOR_BGR2Lab)
+            .context("Convert to Lab")?;
+        core::split(&self.lab, &mut self.lab_channels).context("Split Lab")?;
+
+        let lightness = self.lab_channels.get(0).context("Lightness channel")?;
+        clahe
+            .apply(&lightness, &mut self.lightness)
+            .context("Apply CLAHE")?;
+        self.lab_channels
+            .set(0, mem::take(&mut self.lightness))
+            .context("Replace lightness")?;
+
+        core::merge(&self.lab_channels, &mut self.lab).context("Merge Lab")?;
+        imgproc::cvt_color_def(&self.lab, &mut self.output, imgproc::COLOR_Lab2BGR)
+            .context("Convert to BGR")?;
+
+        Ok(&mut self.output)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+
+    fn parameters() -> &'static [PipelineParameter] {
+        &[RED_COMPENSATION, WHITE_BALANCE, CLAHE_CLIP, CLAHE_TILES]
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        self.parameters = parameters.clone();
+    }
+}
@@ -0,0 +1,225 @@
+//! Brightens, temporally denoises and sharpens dim footage, the filters run through OpenCV's
+//! transparent API on a `UMat` when OpenCL is available so they can be offloaded to the GPU
+
+use std::mem;
+
+use anyhow::Context;
+use bevy::{
+    app::{App, Plugin},
+    prelude::{EntityRef, EntityWorldMut, World},
+};
+use opencv::{
+    core::{self, Size, ToInputArray, ToOutputArray, UMat, UMatUsageFlags, CV_32F, CV_8U},
+    imgproc,
+    prelude::*,
+};
+use tracing::{error, warn};
+
+use crate::video_pipelines::{
+    AppPipelineExt, Pipeline, PipelineCallbacks, PipelineParameter, PipelineParameters,
+};
+
+pub struct DenoisePipelinePlugin;
+
+impl Plugin for DenoisePipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<DenoisePipeline>("Denoise Pipeline");
+    }
+}
+
+const USE_GPU: PipelineParameter = PipelineParameter::toggle("GPU (OpenCL)", true);
+const GAIN: PipelineParameter = PipelineParameter::float("Gain", 1.0, 1.0, 4.0);
+/// Weight of the previous frames in the running average
+const SMOOTHING: PipelineParameter = PipelineParameter::float("Temporal Smoothing", 0.5, 0.0, 0.95);
+const SHARPEN_AMOUNT: PipelineParameter = PipelineParameter::float("Sharpen Amount", 0.8, 0.0, 3.0);
+const SHARPEN_RADIUS: PipelineParameter =
+    PipelineParameter::float("Sharpen Radius", 2.0, 0.5, 10.0);
+
+pub struct DenoisePipeline {
+    parameters: PipelineParameters,
+
+    cpu: Buffers<Mat>,
+    gpu: Buffers<UMat>,
+    /// Cleared when OpenCL is missing or failed, the CPU path is used from then on
+    gpu_available: bool,
+    /// Size and backend of the frames in the running average
+    last: Option<(Size, bool)>,
+
+    output: Mat,
+}
+
+impl Default for DenoisePipeline {
+    fn default() -> Self {
+        let gpu_available = core::have_opencl().unwrap_or(false);
+        if !gpu_available {
+            warn!("OpenCL is not available, the denoise pipeline will run on the CPU");
+        }
+
+        Self {
+            parameters: PipelineParameters::default(),
+            cpu: Buffers::default(),
+            gpu: Buffers {
+                gained: UMat::new(UMatUsageFlags::USAGE_DEFAULT),
+                average: UMat::new(UMatUsageFlags::USAGE_DEFAULT),
+                next: UMat::new(UMatUsageFlags::USAGE_DEFAULT),
+                blurred: UMat::new(UMatUsageFlags::USAGE_DEFAULT),
+                sharpened: UMat::new(UMatUsageFlags::USAGE_DEFAULT),
+            },
+            gpu_available,
+            last: None,
+            output: Mat::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Buffers<M> {
+    gained: M,
+    /// Running average of the frames in 32 bit floats to avoid rounding drift
+    average: M,
+    next: M,
+    blurred: M,
+    sharpened: M,
+}
+
+struct FilterSettings {
+    gain: f64,
+    smoothing: f64,
+    sharpen_amount: f64,
+    sharpen_radius: f64,
+}
+
+impl Pipeline for DenoisePipeline {
+    type Input = ();
+
+    fn collect_inputs(_world: &World, _entity: &EntityRef) -> Self::Input {
+        // No-op
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        _cmds: &mut PipelineCallbacks,
+        _data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let settings = FilterSettings {
+            gain: self.parameters.float(&GAIN),
+            smoothing: self.parameters.float(&SMOOTHING),
+            sharpen_amount: self.parameters.float(&SHARPEN_AMOUNT),
+            sharpen_radius: self.parameters.float(&SHARPEN_RADIUS),
+        };
+
+        let use_gpu = self.parameters.toggle(&USE_GPU) && self.gpu_available;
+        let size = img.size().context("Get image size")?;
+
+        // The running average is restarted whenever the frames it holds are not comparable
+        let reset = self.last != Some((size, use_gpu));
+        self.last = Some((size, use_gpu));
+
+        if use_gpu {
+            let res: anyhow::Result<()> = try {
+                filter(&mut self.gpu, img, reset, &settings).context("Filter on GPU")?;
+                self.gpu
+                    .sharpened
+                    .copy_to(&mut self.output)
+                    .context("Download frame")?;
+            };
+
+            match res {
+                Ok(()) => return Ok(&mut self.output),
+                Err(err) => {
+                    error!("Denoise pipeline falling back to the CPU: {err:?}");
+
+                    self.gpu_available = false;
+                    self.last = None;
+
+                    return Ok(img);
+                }
+            }
+        }
+
+        filter(&mut self.cpu, img, reset, &settings).context("Filter on CPU")?;
+
+        Ok(&mut self.cpu.sharpened)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+
+    fn parameters() -> &'static [PipelineParameter] {
+        &[USE_GPU, GAIN, SMOOTHING, SHARPEN_AMOUNT, SHARPEN_RADIUS]
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        self.parameters = parameters.clone();
+    }
+}
+
+/// Runs the filters into `buffers.sharpened`, generic over `Mat` and `UMat` so the same code runs
+/// on either backend
+fn filter<M>(
+    buffers: &mut Buffers<M>,
+    frame: &Mat,
+    reset: bool,
+    settings: &FilterSettings,
+) -> anyhow::Result<()>
+where
+    M: ToInputArray + ToOutputArray,
+{
+    // Brighten, saturating at white
+    core::add_weighted(
+        frame,
+        settings.gain,
+        frame,
+        0.0,
+        0.0,
+        &mut buffers.gained,
+        -1,
+    )
+    .context("Apply gain")?;
+
+    // Exponential moving average across frames, trades motion blur for less sensor noise
+    let (previous, current) = if reset {
+        (0.0, 1.0)
+    } else {
+        (settings.smoothing, 1.0 - settings.smoothing)
+    };
+    let previous_frame = if reset {
+        &buffers.gained
+    } else {
+        &buffers.average
+    };
+    core::add_weighted(
+        previous_frame,
+        previous,
+        &buffers.gained,
+        current,
+        0.0,
+        &mut buffers.next,
+        CV_32F,
+    )
+    .context("Average frames")?;
+    mem::swap(&mut buffers.average, &mut buffers.next);
+
+    // Unsharp mask
+    imgproc::gaussian_blur_def(
+        &buffers.average,
+        &mut buffers.blurred,
+        Size::default(),
+        settings.sharpen_radius,
+    )
+    .context("Blur")?;
+    core::add_weighted(
+        &buffers.average,
+        1.0 + settings.sharpen_amount,
+        &buffers.blurred,
+        -settings.sharpen_amount,
+        0.0,
+        &mut buffers.sharpened,
+        CV_8U,
+    )
+    .context("Sharpen")?;
+
+    Ok(())
+}
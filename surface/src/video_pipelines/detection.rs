@@ -0,0 +1,465 @@
+//! Runs an ONNX object detection model through OpenCV's DNN module, which expects YOLO style
+//! outputs of either `[1, 4 + classes, boxes]` or `[1, boxes, 5 + classes]`
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use bevy::{math::Rect as BevyRect, prelude::*};
+use bevy_egui::EguiContexts;
+use egui::{DragValue, Id, Slider};
+use opencv::{
+    core::{self, Point, Rect, Scalar, Size, Vector},
+    dnn::{self, Net},
+    imgproc,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::video_pipelines::{
+    background::BackgroundStats, AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks,
+};
+
+pub const DETECTION_CONFIG_FILE: &str = "object_detection.toml";
+
+pub struct DetectionPipelinePlugin;
+
+impl Plugin for DetectionPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<DetectionPipeline>("Object Detection Pipeline")
+            .register_background_video_pipeline::<DetectionPipeline>(
+                "Object Detection Pipeline (Background)",
+            )
+            .add_systems(PreStartup, load_detection_config)
+            .add_systems(Update, (detection_ui, save_detection_config).chain());
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DetectionConfig {
+    /// Path to the `.onnx` model, read when the pipeline starts
+    pub model: PathBuf,
+    /// Class names in the order the model outputs them
+    pub classes: Vec<String>,
+    pub input_width: u32,
+    pub input_height: u32,
+    pub settings: DetectionSettings,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            model: "model.onnx".into(),
+            classes: Vec::new(),
+            input_width: 640,
+            input_height: 640,
+            settings: DetectionSettings::default(),
+        }
+    }
+}
+
+/// Settings that can be changed while the pipeline is running
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DetectionSettings {
+    pub backend: DetectionBackend,
+    /// Inference runs on every `stride`th frame, the last detections are drawn in between
+    pub stride: u32,
+    pub confidence: f32,
+    /// IoU above which overlapping boxes are merged
+    pub nms: f32,
+}
+
+impl Default for DetectionSettings {
+    fn default() -> Self {
+        Self {
+            backend: DetectionBackend::Cpu,
+            stride: 3,
+            confidence: 0.5,
+            nms: 0.45,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionBackend {
+    Cpu,
+    Cuda,
+    OpenCl,
+}
+
+impl DetectionBackend {
+    pub const ALL: [DetectionBackend; 3] = [
+        DetectionBackend::Cpu,
+        DetectionBackend::Cuda,
+        DetectionBackend::OpenCl,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DetectionBackend::Cpu => "CPU",
+            DetectionBackend::Cuda => "CUDA",
+            DetectionBackend::OpenCl => "OpenCL",
+        }
+    }
+
+    fn apply(&self, net: &mut Net) -> anyhow::Result<()> {
+        let (backend, target) = match self {
+            DetectionBackend::Cpu => (dnn::DNN_BACKEND_OPENCV, dnn::DNN_TARGET_CPU),
+            DetectionBackend::Cuda => (dnn::DNN_BACKEND_CUDA, dnn::DNN_TARGET_CUDA),
+            DetectionBackend::OpenCl => (dnn::DNN_BACKEND_OPENCV, dnn::DNN_TARGET_OPENCL),
+        };
+
+        net.set_preferable_backend(backend)
+            .context("Set DNN backend")?;
+        net.set_preferable_target(target)
+            .context("Set DNN target")?;
+
+        Ok(())
+    }
+}
+
+/// An object found by an object detection pipeline, these are children of the pipeline entity and
+/// are replaced after every inference
+#[derive(Component, Debug, Clone)]
+pub struct ObjectDetection {
+    pub class: usize,
+    pub label: String,
+    pub confidence: f32,
+    /// In pixels of the camera's frame
+    pub bbox: BevyRect,
+    pub camera: Entity,
+}
+
+/// Marks the pipeline entity of a running object detection pipeline
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ObjectDetector;
+
+pub struct DetectionPipeline {
+    net: Net,
+    classes: Vec<String>,
+    input_size: Size,
+
+    backend: Option<DetectionBackend>,
+    frame: u32,
+    blob: Mat,
+    detections: Vec<(usize, f32, Rect)>,
+
+    started: bool,
+}
+
+impl Pipeline for DetectionPipeline {
+    type Input = DetectionSettings;
+
+    fn collect_inputs(world: &World, _entity: &EntityRef) -> Self::Input {
+        world
+            .get_resource::<DetectionConfig>()
+            .map(|it| it.settings)
+            .unwrap_or_default()
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        settings: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        if !self.started {
+            self.started = true;
+            cmds.pipeline(|mut entity| {
+                entity.insert(ObjectDetector);
+            });
+        }
+
+        if self.backend != Some(settings.backend) {
+            settings.backend.apply(&mut self.net)?;
+            self.backend = Some(settings.backend);
+        }
+
+        if self.frame % settings.stride.max(1) == 0 {
+            self.detections = self.detect(img, settings).context("Detect objects")?;
+
+            let camera = cmds.camera_entity;
+            let detections = self
+                .detections
+                .iter()
+                .map(|&(class, confidence, bbox)| ObjectDetection {
+                    class,
+                    label: self.label(class),
+                    confidence,
+                    bbox: BevyRect::new(
+                        bbox.x as f32,
+                        bbox.y as f32,
+                        (bbox.x + bbox.width) as f32,
+                        (bbox.y + bbox.height) as f32,
+                    ),
+                    camera,
+                })
+                .collect::<Vec<_>>();
+
+            cmds.pipeline(move |mut entity| {
+                entity.despawn_descendants();
+                entity.with_children(|builder| {
+                    for detection in detections {
+                        builder.spawn((Name::new(detection.label.clone()), detection));
+                    }
+                });
+            });
+        }
+        self.frame = self.frame.wrapping_add(1);
+
+        for &(class, confidence, bbox) in &self.detections {
+            let color = Scalar::new(0.0, 255.0, 255.0, 0.0);
+            imgproc::rectangle_def(img, bbox, color).context("Draw detection")?;
+            imgproc::put_text_def(
+                img,
+                &format!("{} {:.0}%", self.label(class), confidence * 100.0),
+                Point::new(bbox.x, (bbox.y - 5).max(10)),
+                imgproc::FONT_HERSHEY_SIMPLEX,
+                0.5,
+                color,
+            )
+            .context("Draw label")?;
+        }
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+impl DetectionPipeline {
+    fn label(&self, class: usize) -> String {
+        self.classes
+            .get(class)
+            .cloned()
+            .unwrap_or_else(|| format!("Class {class}"))
+    }
+
+    fn detect(
+        &mut self,
+        img: &Mat,
+        settings: &DetectionSettings,
+    ) -> anyhow::Result<Vec<(usize, f32, Rect)>> {
+        let size = img.size().context("Get image size")?;
+
+        dnn::blob_from_image_to(
+            img,
+            &mut self.blob,
+            1.0 / 255.0,
+            self.input_size,
+            Scalar::default(),
+            true,
+            false,
+            core::CV_32F,
+        )
+        .context("Create blob")?;
+        self.net
+            .set_input_def(&self.blob)
+            .context("Set network input")?;
+
+        let output = self.net.forward_single_def().context("Run network")?;
+
+        let dims = output.mat_size();
+        if dims.len() != 3 {
+            bail!("Expected a 3 dimensional output, got {}", dims.len());
+        }
+
+        // YOLOv8 and later put the boxes along the last axis and have no objectness score
+        let transposed = dims[1] < dims[2];
+        let has_objectness = !transposed;
+
+        let output = output.reshape(1, dims[1]).context("Reshape output")?;
+        let output = if transposed {
+            let mut out = Mat::default();
+            core::transpose(&*output, &mut out).context("Transpose output")?;
+            out
+        } else {
+            output.clone_pointee()
+        };
+
+        let scale_x = size.width as f32 / self.input_size.width as f32;
+        let scale_y = size.height as f32 / self.input_size.height as f32;
+        let scores_start = if has_objectness { 5 } else { 4 };
+
+        let mut boxes = Vector::<Rect>::new();
+        let mut scores = Vector::<f32>::new();
+        let mut classes = Vec::new();
+        for row in 0..output.rows() {
+            let values = output.at_row::<f32>(row).context("Read detection")?;
+            if values.len() <= scores_start {
+                bail!("Model output has no class scores");
+            }
+
+            let objectness = if has_objectness { values[4] } else { 1.0 };
+            let Some((class, score)) = values[scores_start..]
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+            else {
+                continue;
+            };
+
+            let confidence = score * objectness;
+            if confidence < settings.confidence {
+                continue;
+            }
+
+            let (center_x, center_y, width, height) = (values[0], values[1], values[2], values[3]);
+            boxes.push(Rect::new(
+                ((center_x - width / 2.0) * scale_x) as i32,
+                ((center_y - height / 2.0) * scale_y) as i32,
+                (width * scale_x) as i32,
+                (height * scale_y) as i32,
+            ));
+            scores.push(confidence);
+            classes.push(class);
+        }
+
+        let mut indices = Vector::<i32>::new();
+        dnn::nms_boxes_def(
+            &boxes,
+            &scores,
+            settings.confidence,
+            settings.nms,
+            &mut indices,
+        )
+        .context("Non maximum suppression")?;
+
+        indices
+            .iter()
+            .map(|idx| {
+                let idx = idx as usize;
+                Ok((
+                    classes[idx],
+                    scores.get(idx).context("Get score")?,
+                    boxes.get(idx).context("Get box")?,
+                ))
+            })
+            .collect()
+    }
+}
+
+impl FromWorldEntity for DetectionPipeline {
+    fn from(world: &mut World, _camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let config = world
+            .get_resource::<DetectionConfig>()
+            .context("Get detection config")?;
+
+        let model = config
+            .model
+            .to_str()
+            .context("Model path is not valid UTF-8")?;
+        let net = dnn::read_net_from_onnx(model)
+            .with_context(|| format!("Load model {}", config.model.display()))?;
+
+        Ok(Self {
+            net,
+            classes: config.classes.clone(),
+            input_size: Size::new(config.input_width as i32, config.input_height as i32),
+            backend: None,
+            frame: 0,
+            blob: Mat::default(),
+            detections: Vec::new(),
+            started: false,
+        })
+    }
+}
+
+fn detection_ui(
+    mut contexts: EguiContexts,
+    mut config: ResMut<DetectionConfig>,
+    detectors: Query<(Entity, Option<&Children>, Option<&BackgroundStats>), With<ObjectDetector>>,
+    detections: Query<&ObjectDetection>,
+) {
+    for (entity, children, background) in &detectors {
+        let detections = children
+            .iter()
+            .flat_map(|it| it.iter())
+            .filter_map(|it| detections.get(*it).ok())
+            .collect::<Vec<_>>();
+
+        egui::Window::new("Object Detection")
+            .id(Id::new(entity))
+            .show(contexts.ctx_mut(), |ui| {
+                let mut settings = config.settings;
+
+                ui.label(format!("Model: {}", config.model.display()));
+                ui.horizontal(|ui| {
+                    for backend in DetectionBackend::ALL {
+                        ui.selectable_value(&mut settings.backend, backend, backend.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Stride:");
+                    ui.add(DragValue::new(&mut settings.stride).range(1..=30));
+                });
+                ui.add(Slider::new(&mut settings.confidence, 0.05..=1.0).text("Confidence"));
+                ui.add(Slider::new(&mut settings.nms, 0.05..=1.0).text("NMS"));
+
+                if settings != config.settings {
+                    config.settings = settings;
+                }
+
+                if let Some(stats) = background {
+                    ui.label(format!(
+                        "Background: {:.0}ms per frame, {} processed, {} dropped",
+                        stats.last_duration.as_secs_f32() * 1000.0,
+                        stats.processed,
+                        stats.dropped
+                    ));
+                }
+
+                ui.separator();
+
+                if detections.is_empty() {
+                    ui.label("Nothing detected");
+                }
+                for detection in detections {
+                    ui.label(format!(
+                        "{}: {:.0}%",
+                        detection.label,
+                        detection.confidence * 100.0
+                    ));
+                }
+            });
+    }
+}
+
+fn load_detection_config(mut cmds: Commands) {
+    let res: anyhow::Result<DetectionConfig> = try {
+        let config = fs::read_to_string(DETECTION_CONFIG_FILE).context("Read detection config")?;
+        toml::from_str(&config).context("Parse detection config")?
+    };
+
+    let config = match res {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Using default detection config: {err:?}");
+            DetectionConfig::default()
+        }
+    };
+
+    cmds.insert_resource(config);
+}
+
+fn save_detection_config(config: Res<DetectionConfig>) {
+    if !config.is_changed() || config.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*config) else {
+        error!("Could not serialize detection config");
+        return;
+    };
+
+    let res = fs::write(DETECTION_CONFIG_FILE, &str);
+    if let Err(err) = res {
+        error!("Could not write detection config: {err:?}");
+    }
+}
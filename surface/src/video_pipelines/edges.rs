@@ -5,7 +5,9 @@ use bevy::{
 };
 use opencv::{imgproc, prelude::*};
 
-use crate::video_pipelines::{AppPipelineExt, Pipeline, PipelineCallbacks};
+use crate::video_pipelines::{
+    AppPipelineExt, Pipeline, PipelineCallbacks, PipelineParameter, PipelineParameters,
+};
 
 pub struct EdgesPipelinePlugin;
 
@@ -15,9 +17,16 @@ impl Plugin for EdgesPipelinePlugin {
     }
 }
 
+const LOW_THRESHOLD: PipelineParameter =
+    PipelineParameter::float("Low Threshold", 150.0, 0.0, 1000.0);
+const HIGH_THRESHOLD: PipelineParameter =
+    PipelineParameter::float("High Threshold", 150.0, 0.0, 1000.0);
+const L2_GRADIENT: PipelineParameter = PipelineParameter::toggle("L2 Gradient", false);
+
 #[derive(Default)]
 pub struct EdgesPipeline {
     edges: Mat,
+    parameters: PipelineParameters,
 }
 
 impl Pipeline for EdgesPipeline {
@@ -33,7 +42,15 @@ impl Pipeline for EdgesPipeline {
         _data: &Self::Input,
         img: &'b mut Mat,
     ) -> anyhow::Result<&'b mut Mat> {
-        imgproc::canny_def(img, &mut self.edges, 150.0, 150.0).context("Canny")?;
+        imgproc::canny(
+            img,
+            &mut self.edges,
+            self.parameters.float(&LOW_THRESHOLD),
+            self.parameters.float(&HIGH_THRESHOLD),
+            3,
+            self.parameters.toggle(&L2_GRADIENT),
+        )
+        .context("Canny")?;
 
         Ok(&mut self.edges)
     }
@@ -41,4 +58,12 @@ impl Pipeline for EdgesPipeline {
     fn cleanup(self, _entity_world: &mut EntityWorldMut) {
         // No-op
     }
+
+    fn parameters() -> &'static [PipelineParameter] {
+        &[LOW_THRESHOLD, HIGH_THRESHOLD, L2_GRADIENT]
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        self.parameters = parameters.clone();
+    }
 }
@@ -0,0 +1,586 @@
+//! Runtime composition of the registered pipelines, each camera gets an ordered list of stages
+//! that can be reordered, toggled and tuned from the UI without recompiling
+
+use std::{any::Any, borrow::Cow, collections::BTreeMap, fs};
+
+use ahash::HashMap;
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::components::CameraDefinition;
+use egui::{ComboBox, Slider};
+use opencv::{core::Mat, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    layout::DockableWindow,
+    settings::config_path,
+    ui::PipelineGraphUi,
+    video_pipelines::{
+        forward_pipeline_inputs, FromWorldEntity, ParameterKind, ParameterValue, Pipeline,
+        PipelineCallbacks, PipelineCamera, PipelineHandler, PipelineParameter, PipelineParameters,
+        VideoPipeline, VideoPipelines,
+    },
+    video_stream::VideoProcessorFactory,
+};
+
+pub const PIPELINE_GRAPHS_FILE: &str = "pipeline_graphs.toml";
+pub const GRAPH_PIPELINE_NAME: &str = "Pipeline Graph";
+
+pub struct PipelineGraphPlugin;
+
+impl Plugin for PipelineGraphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GraphStages>()
+            .init_resource::<VideoPipelines>()
+            .add_systems(PreStartup, load_pipeline_graphs)
+            .add_systems(
+                Update,
+                (
+                    forward_pipeline_inputs::<GraphPipeline>,
+                    graph_ui.run_if(resource_exists::<PipelineGraphUi>),
+                    restart_changed_graphs,
+                    save_pipeline_graphs,
+                )
+                    .chain(),
+            );
+
+        // Not registered as a stage, graphs can not be nested
+        app.world_mut()
+            .resource_mut::<VideoPipelines>()
+            .0
+            .push(VideoPipeline {
+                name: GRAPH_PIPELINE_NAME.into(),
+                factory: graph_factory(),
+            });
+    }
+}
+
+/// The pipeline graph of every camera by camera name, along with graphs saved for reuse
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PipelineGraphs {
+    pub cameras: BTreeMap<String, PipelineGraph>,
+    pub presets: BTreeMap<String, PipelineGraph>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PipelineGraph {
+    pub stages: Vec<GraphStage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GraphStage {
+    /// Name the pipeline was registered with
+    pub pipeline: String,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    /// Runs the stage on a copy of the frame, its output is discarded and the frame continues
+    /// to the next stage unchanged
+    #[serde(default)]
+    pub branch: bool,
+    #[serde(default)]
+    pub parameters: PipelineParameters,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+impl GraphStage {
+    pub fn new(pipeline: impl Into<String>) -> Self {
+        Self {
+            pipeline: pipeline.into(),
+            enabled: true,
+            branch: false,
+            parameters: PipelineParameters::default(),
+        }
+    }
+}
+
+/// Pipelines that can be used as stages of a graph, populated by `register_video_pipeline`
+#[derive(Resource, Default)]
+pub struct GraphStages(pub Vec<GraphStageType>);
+
+pub struct GraphStageType {
+    pub name: Cow<'static, str>,
+    pub parameters: &'static [PipelineParameter],
+
+    collect: fn(&World, &EntityRef) -> StageInput,
+    create: fn(&mut World, Entity) -> anyhow::Result<Box<dyn ErasedStage>>,
+}
+
+impl GraphStages {
+    pub fn get(&self, name: &str) -> Option<&GraphStageType> {
+        self.0.iter().find(|it| it.name == name)
+    }
+}
+
+type StageInput = Box<dyn Any + Send + Sync>;
+
+pub(super) fn register_stage<P>(app: &mut App, name: Cow<'static, str>)
+where
+    P: Pipeline + FromWorldEntity,
+{
+    app.init_resource::<GraphStages>();
+    app.world_mut()
+        .resource_mut::<GraphStages>()
+        .0
+        .push(GraphStageType {
+            name,
+            parameters: P::parameters(),
+            collect: |world, entity| Box::new(P::collect_inputs(world, entity)),
+            create: |world, camera| Ok(Box::new(P::from(world, camera)?)),
+        });
+}
+
+pub fn graph_factory() -> VideoProcessorFactory {
+    VideoProcessorFactory::new::<PipelineHandler<GraphPipeline>>(GRAPH_PIPELINE_NAME)
+}
+
+/// Object safe version of `Pipeline`
+trait ErasedStage: Send {
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        data: &(dyn Any + Send + Sync),
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat>;
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters);
+
+    fn cleanup(self: Box<Self>, entity_world: &mut EntityWorldMut);
+}
+
+impl<P: Pipeline> ErasedStage for P {
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        data: &(dyn Any + Send + Sync),
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let data = data
+            .downcast_ref::<P::Input>()
+            .context("Stage input has the wrong type")?;
+
+        Pipeline::process(self, cmds, data, img)
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        Pipeline::set_parameters(self, parameters)
+    }
+
+    fn cleanup(self: Box<Self>, entity_world: &mut EntityWorldMut) {
+        Pipeline::cleanup(*self, entity_world)
+    }
+}
+
+/// The stages of a graph that get instantiated, disabled and unknown stages are left out
+fn active_stages<'a>(
+    graph: &'a PipelineGraph,
+    stages: &'a GraphStages,
+) -> impl Iterator<Item = (&'a GraphStage, &'a GraphStageType)> {
+    graph
+        .stages
+        .iter()
+        .filter(|it| it.enabled)
+        .filter_map(|it| Some((it, stages.get(&it.pipeline)?)))
+}
+
+/// Runs the stages of a camera's graph in order, changing which stages are active restarts the
+/// pipeline while parameters and branching are applied live
+pub struct GraphPipeline {
+    stages: Vec<RunningStage>,
+}
+
+struct RunningStage {
+    pipeline: String,
+    stage: Box<dyn ErasedStage>,
+    parameters: Option<PipelineParameters>,
+    branch_img: Mat,
+}
+
+#[derive(Default)]
+pub struct GraphInput {
+    stages: Vec<GraphStageInput>,
+}
+
+struct GraphStageInput {
+    pipeline: String,
+    branch: bool,
+    parameters: PipelineParameters,
+    data: StageInput,
+}
+
+impl Pipeline for GraphPipeline {
+    type Input = GraphInput;
+
+    fn collect_inputs(world: &World, entity: &EntityRef) -> Self::Input {
+        let graph = entity
+            .get::<PipelineCamera>()
+            .and_then(|it| world.get::<Name>(it.camera()))
+            .zip(world.get_resource::<PipelineGraphs>())
+            .and_then(|(name, graphs)| graphs.cameras.get(name.as_str()));
+        let (Some(graph), Some(stages)) = (graph, world.get_resource::<GraphStages>()) else {
+            return GraphInput::default();
+        };
+
+        GraphInput {
+            stages: active_stages(graph, stages)
+                .map(|(stage, ty)| GraphStageInput {
+                    pipeline: stage.pipeline.clone(),
+                    branch: stage.branch,
+                    parameters: stage.parameters.clone(),
+                    data: (ty.collect)(world, entity),
+                })
+                .collect(),
+        }
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        data: &Self::Input,
+        mut img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        // The stages were changed and the pipeline is about to be restarted
+        let matches = data.stages.len() == self.stages.len()
+            && data
+                .stages
+                .iter()
+                .zip(&self.stages)
+                .all(|(input, stage)| input.pipeline == stage.pipeline);
+        if !matches {
+            return Ok(img);
+        }
+
+        for (stage, input) in self.stages.iter_mut().zip(&data.stages) {
+            if stage.parameters.as_ref() != Some(&input.parameters) {
+                stage.stage.set_parameters(&input.parameters);
+                stage.parameters = Some(input.parameters.clone());
+            }
+
+            if input.branch {
+                img.copy_to(&mut stage.branch_img)
+                    .context("Copy frame for branch")?;
+                stage
+                    .stage
+                    .process(cmds, &*input.data, &mut stage.branch_img)
+                    .with_context(|| format!("Process {}", stage.pipeline))?;
+            } else {
+                img = stage
+                    .stage
+                    .process(cmds, &*input.data, img)
+                    .with_context(|| format!("Process {}", stage.pipeline))?;
+            }
+        }
+
+        Ok(img)
+    }
+
+    fn cleanup(self, entity_world: &mut EntityWorldMut) {
+        for stage in self.stages {
+            stage.stage.cleanup(entity_world);
+        }
+    }
+}
+
+impl FromWorldEntity for GraphPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let name = world.get::<Name>(camera).context("Camera has no name")?;
+        let graph = world
+            .resource::<PipelineGraphs>()
+            .cameras
+            .get(name.as_str())
+            .cloned()
+            .unwrap_or_default();
+
+        let creates = active_stages(&graph, world.resource::<GraphStages>())
+            .map(|(stage, ty)| (stage.pipeline.clone(), ty.create))
+            .collect::<Vec<_>>();
+
+        let mut stages = Vec::with_capacity(creates.len());
+        for (pipeline, create) in creates {
+            let stage = (create)(world, camera).with_context(|| format!("Create {pipeline}"))?;
+
+            stages.push(RunningStage {
+                pipeline,
+                stage,
+                parameters: None,
+                branch_img: Mat::default(),
+            });
+        }
+
+        Ok(Self { stages })
+    }
+}
+
+fn load_pipeline_graphs(mut cmds: Commands) {
+    let res: anyhow::Result<PipelineGraphs> = try {
+        let graphs = fs::read_to_string(config_path(PIPELINE_GRAPHS_FILE))
+            .context("Read pipeline graphs")?;
+        toml::from_str(&graphs).context("Parse pipeline graphs")?
+    };
+
+    let graphs = match res {
+        Ok(graphs) => graphs,
+        Err(err) => {
+            warn!("Using default pipeline graphs: {err:?}");
+            PipelineGraphs::default()
+        }
+    };
+
+    cmds.insert_resource(graphs);
+}
+
+fn save_pipeline_graphs(graphs: Res<PipelineGraphs>) {
+    if !graphs.is_changed() || graphs.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*graphs) else {
+        error!("Could not serialize pipeline graphs");
+        return;
+    };
+
+    let res = fs::write(config_path(PIPELINE_GRAPHS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write pipeline graphs: {err:?}");
+    }
+}
+
+/// Restarts running graphs whose active stages changed
+fn restart_changed_graphs(
+    mut cmds: Commands,
+    graphs: Res<PipelineGraphs>,
+    stages: Res<GraphStages>,
+    cameras: Query<(Entity, &Name, Ref<VideoProcessorFactory>)>,
+    mut running: Local<HashMap<Entity, Vec<String>>>,
+) {
+    for (entity, name, factory) in &cameras {
+        if factory.name != GRAPH_PIPELINE_NAME || !(factory.is_changed() || graphs.is_changed()) {
+            continue;
+        }
+
+        let active = graphs
+            .cameras
+            .get(name.as_str())
+            .map(|graph| {
+                active_stages(graph, &stages)
+                    .map(|(stage, _)| stage.pipeline.clone())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        if factory.is_changed() {
+            // The pipeline was just (re)started with the current graph
+            running.insert(entity, active);
+        } else if running.get(&entity) != Some(&active) {
+            running.insert(entity, active);
+
+            // Inserting the factory again makes the video thread rebuild the pipeline
+            cmds.entity(entity).insert(graph_factory());
+        }
+    }
+}
+
+#[derive(Default)]
+struct GraphEditorState {
+    camera: String,
+    preset: String,
+}
+
+fn graph_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut graphs: ResMut<PipelineGraphs>,
+    stages: Res<GraphStages>,
+    cameras: Query<(Entity, &Name, Option<&VideoProcessorFactory>), With<CameraDefinition>>,
+    mut state: Local<GraphEditorState>,
+) {
+    let mut open = true;
+
+    egui::Window::new("Pipeline Graph")
+        .open(&mut open)
+        .show_dockable(contexts.ctx_mut(), egui::Id::new("Pipeline Graph"), |ui| {
+            let state = &mut *state;
+
+            if state.camera.is_empty() {
+                if let Some((_, name, _)) = cameras.iter().next() {
+                    state.camera = name.to_string();
+                }
+            }
+
+            ComboBox::from_label("Camera")
+                .selected_text(state.camera.as_str())
+                .show_ui(ui, |ui| {
+                    for (_, name, _) in &cameras {
+                        ui.selectable_value(&mut state.camera, name.to_string(), name.as_str());
+                    }
+                });
+
+            let camera = cameras
+                .iter()
+                .find(|(_, name, _)| name.as_str() == state.camera);
+            if let Some((entity, _, factory)) = camera {
+                let running = factory.is_some_and(|it| it.name == GRAPH_PIPELINE_NAME);
+
+                ui.horizontal(|ui| {
+                    if running {
+                        ui.label("Running");
+                        if ui.button("Stop").clicked() {
+                            cmds.entity(entity).remove::<VideoProcessorFactory>();
+                        }
+                    } else {
+                        if let Some(factory) = factory {
+                            ui.label(format!("Running {}", factory.name));
+                        }
+                        if ui.button("Start").clicked() {
+                            cmds.entity(entity).insert(graph_factory());
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+
+            let mut graph = graphs
+                .cameras
+                .get(&state.camera)
+                .cloned()
+                .unwrap_or_default();
+
+            let mut move_up = None;
+            let mut remove = None;
+            let count = graph.stages.len();
+            for (idx, stage) in graph.stages.iter_mut().enumerate() {
+                ui.push_id(idx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut stage.enabled, stage.pipeline.as_str());
+                        ui.checkbox(&mut stage.branch, "Branch");
+
+                        if ui.add_enabled(idx > 0, egui::Button::new("Up")).clicked() {
+                            move_up = Some(idx);
+                        }
+                        if ui
+                            .add_enabled(idx + 1 < count, egui::Button::new("Down"))
+                            .clicked()
+                        {
+                            move_up = Some(idx + 1);
+                        }
+                        if ui.button("Remove").clicked() {
+                            remove = Some(idx);
+                        }
+                    });
+
+                    let Some(ty) = stages.get(&stage.pipeline) else {
+                        ui.colored_label(egui::Color32::YELLOW, "Unknown pipeline, skipped");
+                        return;
+                    };
+
+                    if !ty.parameters.is_empty() {
+                        ui.indent("parameters", |ui| {
+                            for parameter in ty.parameters {
+                                parameter_ui(ui, parameter, &mut stage.parameters);
+                            }
+
+                            if !stage.parameters.0.is_empty() && ui.button("Reset").clicked() {
+                                stage.parameters = PipelineParameters::default();
+                            }
+                        });
+                    }
+                });
+            }
+
+            if let Some(idx) = move_up {
+                graph.stages.swap(idx - 1, idx);
+            }
+            if let Some(idx) = remove {
+                graph.stages.remove(idx);
+            }
+
+            ui.menu_button("Add Stage", |ui| {
+                for ty in &stages.0 {
+                    if ui.button(ty.name.as_ref()).clicked() {
+                        graph.stages.push(GraphStage::new(ty.name.as_ref()));
+                        ui.close_menu();
+                    }
+                }
+            });
+
+            if !state.camera.is_empty() && graphs.cameras.get(&state.camera) != Some(&graph) {
+                graphs.cameras.insert(state.camera.clone(), graph.clone());
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut state.preset);
+                if ui.button("Save Preset").clicked() && !state.preset.is_empty() {
+                    graphs.presets.insert(state.preset.clone(), graph.clone());
+                }
+            });
+
+            let mut load = None;
+            let mut delete = None;
+            for name in graphs.presets.keys() {
+                ui.horizontal(|ui| {
+                    ui.label(name);
+                    if ui.button("Load").clicked() {
+                        load = Some(name.clone());
+                    }
+                    if ui.button("Delete").clicked() {
+                        delete = Some(name.clone());
+                    }
+                });
+            }
+
+            if let Some(preset) = load.and_then(|it| graphs.presets.get(&it).cloned()) {
+                if !state.camera.is_empty() {
+                    graphs.cameras.insert(state.camera.clone(), preset);
+                }
+            }
+            if let Some(preset) = delete {
+                graphs.presets.remove(&preset);
+            }
+        });
+
+    if !open {
+        cmds.remove_resource::<PipelineGraphUi>();
+    }
+}
+
+fn parameter_ui(
+    ui: &mut egui::Ui,
+    parameter: &PipelineParameter,
+    parameters: &mut PipelineParameters,
+) {
+    let value = match parameter.kind {
+        ParameterKind::Toggle { .. } => {
+            let mut value = parameters.toggle(parameter);
+            ui.checkbox(&mut value, parameter.name);
+
+            ParameterValue::Toggle(value)
+        }
+        ParameterKind::Int { min, max, .. } => {
+            let mut value = parameters.int(parameter);
+            ui.add(Slider::new(&mut value, min..=max).text(parameter.name));
+
+            ParameterValue::Int(value)
+        }
+        ParameterKind::Float { min, max, .. } => {
+            let mut value = parameters.float(parameter);
+            ui.add(Slider::new(&mut value, min..=max).text(parameter.name));
+
+            ParameterValue::Float(value)
+        }
+    };
+
+    if value != parameters.get(parameter) {
+        parameters.0.insert(parameter.name.to_owned(), value);
+    }
+}
@@ -0,0 +1,148 @@
+//! Runs pipelines outside of the video thread so they can be tested against the sample frames
+//! and golden values in `tests/frames`
+
+use std::fs;
+
+use bevy::{
+    math::{vec3a, Mat3A},
+    prelude::{Entity, Name, World},
+};
+use common::components::CameraCalibration;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use opencv::{core::Mat, imgcodecs, prelude::*};
+use serde::Deserialize;
+
+use crate::video_pipelines::{
+    FromWorldEntity, Pipeline, PipelineCallbacks, PipelineCamera, WorldCallback,
+};
+
+const FRAMES_DIR: &str = "tests/frames";
+
+pub struct PipelineHarness<P: Pipeline> {
+    pub world: World,
+    pub camera: Entity,
+
+    pipeline: P,
+    pipeline_entity: Entity,
+
+    cmds_tx: Sender<WorldCallback>,
+    cmds_rx: Receiver<WorldCallback>,
+    should_end: bool,
+}
+
+impl<P: Pipeline + FromWorldEntity> PipelineHarness<P> {
+    pub fn new(mut world: World, camera: Entity) -> Self {
+        let pipeline = P::from(&mut world, camera).expect("Create pipeline");
+        let pipeline_entity = world.spawn(PipelineCamera(camera)).id();
+        let (cmds_tx, cmds_rx) = unbounded();
+
+        Self {
+            world,
+            camera,
+            pipeline,
+            pipeline_entity,
+            cmds_tx,
+            cmds_rx,
+            should_end: false,
+        }
+    }
+
+    /// Runs a frame through the pipeline and applies the world callbacks it queued
+    pub fn process(&mut self, frame: &Mat) -> Mat {
+        let input = P::collect_inputs(&self.world, &self.world.entity(self.pipeline_entity));
+
+        let mut img = frame.try_clone().expect("Copy frame");
+        let mut callbacks = PipelineCallbacks {
+            cmds_tx: &self.cmds_tx,
+            pipeline_entity: self.pipeline_entity,
+            camera_entity: self.camera,
+            should_end: &mut self.should_end,
+        };
+
+        let output = self
+            .pipeline
+            .process(&mut callbacks, &input, &mut img)
+            .expect("Process frame")
+            .try_clone()
+            .expect("Copy output");
+
+        for callback in self.cmds_rx.try_iter() {
+            (callback)(&mut self.world);
+        }
+
+        output
+    }
+
+    pub fn should_end(&self) -> bool {
+        self.should_end
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Golden {
+    pub camera: GoldenCamera,
+    pub undistort: UndistortGolden,
+    pub shipwreck: ShipwreckGolden,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoldenCamera {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+    pub distortion: Vec<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UndistortGolden {
+    pub frame: String,
+    pub pattern: [i32; 2],
+    pub max_line_error: f32,
+    pub min_raw_line_error: f32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ShipwreckGolden {
+    pub frame: String,
+    pub width: f32,
+    pub length: f32,
+    pub tolerance: f32,
+}
+
+pub fn golden() -> Golden {
+    let golden = fs::read_to_string(format!("{FRAMES_DIR}/golden.toml")).expect("Read golden.toml");
+    toml::from_str(&golden).expect("Parse golden.toml")
+}
+
+pub fn sample_frame(name: &str) -> Mat {
+    let frame = imgcodecs::imread(&format!("{FRAMES_DIR}/{name}"), imgcodecs::IMREAD_COLOR)
+        .expect("Read sample frame");
+    assert!(!frame.empty(), "Sample frame {name} is missing");
+
+    frame
+}
+
+/// A world containing only a camera with the golden calibration
+pub fn camera_world(camera: &GoldenCamera) -> (World, Entity) {
+    let mut world = World::new();
+
+    // Rows of the OpenCV matrix are stored as columns, see `LoadedCalibration::camera_matrix`
+    let camera_matrix = Mat3A::from_cols(
+        vec3a(camera.fx, 0.0, camera.cx),
+        vec3a(0.0, camera.fy, camera.cy),
+        vec3a(0.0, 0.0, 1.0),
+    );
+
+    let entity = world
+        .spawn((
+            Name::new("Test Camera"),
+            CameraCalibration {
+                camera_matrix,
+                distortion_coefficients: camera.distortion.clone(),
+            },
+        ))
+        .id();
+
+    (world, entity)
+}
@@ -0,0 +1,275 @@
+//! Keeps the horizon level in a camera's feed by rotating each frame against the robot's roll
+//!
+//! The rotation comes from the replicated `Orientation` combined with the camera's mounting
+//! rotation. The frame is zoomed in just enough to hide the corners the rotation leaves empty, up
+//! to `HorizonSettings::max_zoom`.
+
+use std::time::Instant;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::components::{CameraInputRotation, Orientation, Robot, RobotId};
+use egui::Id;
+use opencv::{
+    core::{self, Point2f, Scalar, Size},
+    imgproc,
+    prelude::*,
+};
+
+use crate::video_pipelines::{AppPipelineExt, Pipeline, PipelineCallbacks, PipelineCamera};
+
+/// Below this the camera is looking close to straight up or down and the roll is meaningless
+const MIN_HORIZON: f32 = 0.2;
+
+pub struct HorizonPipelinePlugin;
+
+impl Plugin for HorizonPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HorizonSettings>()
+            .register_video_pipeline::<HorizonPipeline>("Horizon Stabilization Pipeline")
+            .add_systems(Update, horizon_ui);
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct HorizonSettings {
+    /// Time constant of the rotation's low pass filter in seconds, zero follows the IMU exactly
+    pub smoothing: f32,
+    /// The most the frame is zoomed in to hide the empty corners, past this they are left black
+    pub max_zoom: f32,
+}
+
+impl Default for HorizonSettings {
+    fn default() -> Self {
+        Self {
+            smoothing: 0.15,
+            max_zoom: 1.3,
+        }
+    }
+}
+
+/// The current correction of a horizon pipeline, lives on the pipeline entity
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct HorizonCorrection {
+    /// Degrees the frame is rotated counter clockwise
+    pub angle: f32,
+    pub zoom: f32,
+}
+
+#[derive(Default)]
+pub struct HorizonPipeline {
+    angle: Option<f32>,
+    last_frame: Option<Instant>,
+
+    output: Mat,
+}
+
+impl Pipeline for HorizonPipeline {
+    // The camera's orientation in the world
+    type Input = (HorizonSettings, Option<Quat>);
+
+    fn collect_inputs(world: &World, entity: &EntityRef) -> Self::Input {
+        let settings = world
+            .get_resource::<HorizonSettings>()
+            .copied()
+            .unwrap_or_default();
+
+        let orientation: Option<Quat> = try {
+            let camera = entity.get::<PipelineCamera>()?.camera();
+            let camera = world.get_entity(camera).ok()?;
+            let robot_id = camera.get::<RobotId>()?;
+
+            let robot = world.iter_entities().find(|entity| {
+                entity.contains::<Robot>() && entity.get::<RobotId>() == Some(robot_id)
+            })?;
+            let orientation = robot.get::<Orientation>()?;
+
+            let mounting = camera
+                .get::<CameraInputRotation>()
+                .map(|it| it.0)
+                .unwrap_or_default();
+
+            orientation.0 * mounting
+        };
+
+        (settings, orientation)
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        (settings, orientation): &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let now = Instant::now();
+        let elapsed = self.last_frame.map(|it| now - it).unwrap_or_default();
+        self.last_frame = Some(now);
+
+        // The last correction is held while the horizon can't be seen
+        if let Some(target) = orientation.and_then(horizon_angle) {
+            self.angle = Some(match self.angle {
+                Some(angle) if settings.smoothing > 0.0 => {
+                    let alpha = 1.0 - (-elapsed.as_secs_f32() / settings.smoothing).exp();
+                    wrap_degrees(angle + wrap_degrees(target - angle) * alpha)
+                }
+                _ => target,
+            });
+        }
+
+        let Some(angle) = self.angle else {
+            return Ok(img);
+        };
+
+        let size = img.size().context("Get image size")?;
+        let zoom = zoom_to_fill(angle, size).clamp(1.0, settings.max_zoom.max(1.0));
+
+        let center = Point2f::new(size.width as f32 / 2.0, size.height as f32 / 2.0);
+        let matrix = imgproc::get_rotation_matrix_2d(center, angle as f64, zoom as f64)
+            .context("Rotation matrix")?;
+        imgproc::warp_affine(
+            img,
+            &mut self.output,
+            &matrix,
+            size,
+            imgproc::INTER_LINEAR,
+            core::BORDER_CONSTANT,
+            Scalar::default(),
+        )
+        .context("Rotate frame")?;
+
+        cmds.pipeline(move |mut entity| {
+            entity.insert(HorizonCorrection { angle, zoom });
+        });
+
+        Ok(&mut self.output)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+/// Degrees the frame of a camera with this orientation has to be rotated counter clockwise to
+/// level the horizon
+///
+/// The camera looks along +Y with +Z up in its frame, like the robot.
+fn horizon_angle(camera: Quat) -> Option<f32> {
+    let up = camera.inverse() * Vec3::Z;
+
+    if up.x.hypot(up.z) < MIN_HORIZON {
+        return None;
+    }
+
+    Some(up.x.atan2(up.z).to_degrees())
+}
+
+/// The zoom that hides the corners left empty by rotating a frame of `size` by `angle` degrees
+fn zoom_to_fill(angle: f32, size: Size) -> f32 {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let aspect_ratio =
+        size.width.max(size.height) as f32 / size.width.min(size.height).max(1) as f32;
+
+    cos.abs() + aspect_ratio * sin.abs()
+}
+
+/// Wraps an angle into -180..180
+fn wrap_degrees(angle: f32) -> f32 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
+
+fn horizon_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<HorizonSettings>,
+    pipelines: Query<(Entity, &HorizonCorrection, &PipelineCamera)>,
+    names: Query<&Name>,
+) {
+    for (entity, correction, camera) in &pipelines {
+        let camera = names
+            .get(camera.camera())
+            .map(|it| it.as_str())
+            .unwrap_or("Camera");
+
+        egui::Window::new(format!("Horizon Stabilization: {camera}"))
+            .id(Id::new(entity))
+            .show(contexts.ctx_mut(), |ui| {
+                let mut new_settings = *settings;
+
+                ui.add(
+                    egui::Slider::new(&mut new_settings.smoothing, 0.0..=2.0)
+                        .text("Smoothing")
+                        .suffix("s"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut new_settings.max_zoom, 1.0..=2.0)
+                        .text("Max Zoom")
+                        .suffix("x"),
+                );
+
+                if new_settings != *settings {
+                    *settings = new_settings;
+                }
+
+                ui.separator();
+
+                ui.label(format!(
+                    "Rotation: {:.1} deg, Zoom: {:.2}x",
+                    correction.angle, correction.zoom
+                ));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Quat;
+    use opencv::core::Size;
+
+    use super::{horizon_angle, wrap_degrees, zoom_to_fill};
+
+    #[test]
+    fn level_camera_is_not_rotated() {
+        let angle = horizon_angle(Quat::IDENTITY).expect("Horizon visible");
+        assert!(angle.abs() < 1e-3, "{angle}");
+    }
+
+    #[test]
+    fn roll_is_rotated_back() {
+        // Rolling right tilts the scene counter clockwise, so it is turned back clockwise
+        let angle = horizon_angle(Quat::from_rotation_y(30f32.to_radians())).expect("Horizon");
+        assert!((angle + 30.0).abs() < 1e-3, "{angle}");
+
+        let angle = horizon_angle(Quat::from_rotation_y(180f32.to_radians())).expect("Horizon");
+        assert!((angle.abs() - 180.0).abs() < 1e-3, "{angle}");
+    }
+
+    #[test]
+    fn pitch_does_not_rotate() {
+        let angle = horizon_angle(Quat::from_rotation_x(-45f32.to_radians())).expect("Horizon");
+        assert!(angle.abs() < 1e-3, "{angle}");
+    }
+
+    #[test]
+    fn looking_down_has_no_horizon() {
+        assert_eq!(
+            horizon_angle(Quat::from_rotation_x(-90f32.to_radians())),
+            None
+        );
+    }
+
+    #[test]
+    fn zoom_hides_corners() {
+        let size = Size::new(1920, 1080);
+
+        assert!((zoom_to_fill(0.0, size) - 1.0).abs() < 1e-3);
+        assert!((zoom_to_fill(180.0, size) - 1.0).abs() < 1e-3);
+        assert!((zoom_to_fill(90.0, size) - 1920.0 / 1080.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn wraps_degrees() {
+        assert_eq!(wrap_degrees(190.0), -170.0);
+        assert_eq!(wrap_degrees(-190.0), 170.0);
+        assert_eq!(wrap_degrees(45.0), 45.0);
+    }
+}
@@ -0,0 +1,354 @@
+//! Finds the two parallel laser dots of a laser scaler
+//!
+//! The lasers are mounted a known distance apart and parallel to the camera, so the dots are that
+//! far apart on whatever they hit. Their distance in pixels gives the scale of the plane they land
+//! on, which the measurement window uses when no reference object has been marked.
+
+use anyhow::Context;
+use bevy::prelude::*;
+use opencv::{
+    core::{self, Point, Scalar, Vector},
+    imgproc,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    measurement::ReferenceCatalog,
+    video_pipelines::{AppPipelineExt, Pipeline, PipelineCallbacks, PipelineCamera},
+};
+
+/// Blobs smaller than this, in pixels, are noise
+const MIN_DOT_AREA: f64 = 4.0;
+/// Blobs larger than this fraction of the frame are lights or reflections
+const MAX_DOT_AREA: f64 = 0.01;
+/// Dots closer than this, in pixels, are likely the same laser
+const MIN_DOT_SEPARATION: f32 = 10.0;
+/// Only the largest blobs are considered when pairing dots
+const MAX_CANDIDATES: usize = 6;
+
+pub struct LaserPipelinePlugin;
+
+impl Plugin for LaserPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<LaserOverlayPipeline>("Laser Scaler Pipeline");
+    }
+}
+
+/// The laser scaler mounted on the robot, configured in `measurement_references.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaserScalerConfig {
+    /// Distance between the two lasers in meters
+    pub separation: f32,
+    pub color: LaserColor,
+}
+
+impl Default for LaserScalerConfig {
+    fn default() -> Self {
+        Self {
+            separation: 0.10,
+            color: LaserColor::Red,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LaserColor {
+    Red,
+    Green,
+}
+
+impl LaserColor {
+    /// Ranges of bright, saturated pixels in OpenCV's HSV, where hue goes from 0 to 180
+    fn hsv_ranges(&self) -> &'static [(Scalar, Scalar)] {
+        const RED: &[(Scalar, Scalar)] = &[
+            (
+                Scalar::new(0.0, 100.0, 180.0, 0.0),
+                Scalar::new(10.0, 255.0, 255.0, 0.0),
+            ),
+            (
+                Scalar::new(170.0, 100.0, 180.0, 0.0),
+                Scalar::new(180.0, 255.0, 255.0, 0.0),
+            ),
+        ];
+        const GREEN: &[(Scalar, Scalar)] = &[(
+            Scalar::new(45.0, 100.0, 180.0, 0.0),
+            Scalar::new(85.0, 255.0, 255.0, 0.0),
+        )];
+
+        match self {
+            LaserColor::Red => RED,
+            LaserColor::Green => GREEN,
+        }
+    }
+}
+
+/// The laser dots last found in a camera's frame, lives on the camera
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct LaserScale {
+    /// Centers of the dots in pixels, left first
+    pub dots: [Vec2; 2],
+    /// Pixels per meter on the plane the dots landed on
+    pub px_per_meter: f32,
+}
+
+impl LaserScale {
+    /// Real distance between two image points on the plane of the dots
+    pub fn distance(&self, a: Vec2, b: Vec2) -> f32 {
+        a.distance(b) / self.px_per_meter
+    }
+}
+
+#[derive(Default)]
+pub struct LaserDetector {
+    hsv: Mat,
+    range: Mat,
+    mask: Mat,
+    contours: Vector<Vector<Point>>,
+}
+
+impl LaserDetector {
+    pub fn detect(
+        &mut self,
+        img: &Mat,
+        config: &LaserScalerConfig,
+    ) -> anyhow::Result<Option<LaserScale>> {
+        if config.separation <= 0.0 {
+            return Ok(None);
+        }
+
+        imgproc::cvt_color_def(img, &mut self.hsv, imgproc::COLOR_BGR2HSV)
+            .context("Convert to HSV")?;
+
+        self.mask = Mat::default();
+        for (lower, upper) in config.color.hsv_ranges() {
+            core::in_range(&self.hsv, lower, upper, &mut self.range).context("Threshold")?;
+
+            if self.mask.empty() {
+                self.mask = self.range.clone();
+            } else {
+                let mask = self.mask.clone();
+                core::bitwise_or_def(&mask, &self.range, &mut self.mask).context("Combine")?;
+            }
+        }
+
+        self.contours.clear();
+        imgproc::find_contours_def(
+            &self.mask,
+            &mut self.contours,
+            imgproc::RETR_EXTERNAL,
+            imgproc::CHAIN_APPROX_SIMPLE,
+        )
+        .context("Find contours")?;
+
+        let max_area = (img.rows() * img.cols()) as f64 * MAX_DOT_AREA;
+        let mut blobs = Vec::new();
+        for contour in &self.contours {
+            let moments = imgproc::moments_def(&contour).context("Get moments")?;
+            if moments.m00 < MIN_DOT_AREA || moments.m00 > max_area {
+                continue;
+            }
+
+            let center = Vec2::new(
+                (moments.m10 / moments.m00) as f32,
+                (moments.m01 / moments.m00) as f32,
+            );
+            blobs.push((center, moments.m00 as f32));
+        }
+
+        blobs.sort_by(|a, b| f32::total_cmp(&b.1, &a.1));
+        blobs.truncate(MAX_CANDIDATES);
+
+        // Both lasers are the same, so the dots are the pair closest in size
+        let mut best: Option<([Vec2; 2], f32)> = None;
+        for (idx, &(a, a_area)) in blobs.iter().enumerate() {
+            for &(b, b_area) in &blobs[idx + 1..] {
+                if a.distance(b) < MIN_DOT_SEPARATION {
+                    continue;
+                }
+
+                let similarity = a_area.min(b_area) / a_area.max(b_area);
+                if best.is_none_or(|(_, it)| similarity > it) {
+                    let dots = if a.x <= b.x { [a, b] } else { [b, a] };
+                    best = Some((dots, similarity));
+                }
+            }
+        }
+
+        Ok(best.map(|(dots, _)| LaserScale {
+            dots,
+            px_per_meter: dots[0].distance(dots[1]) / config.separation,
+        }))
+    }
+}
+
+fn laser_config(world: &World) -> LaserScalerConfig {
+    world
+        .get_resource::<ReferenceCatalog>()
+        .map(|it| it.laser_scaler)
+        .unwrap_or_default()
+}
+
+fn publish(cmds: &mut PipelineCallbacks, scale: Option<LaserScale>) {
+    cmds.camera(move |mut camera| match scale {
+        Some(scale) => {
+            camera.insert(scale);
+        }
+        None => {
+            camera.remove::<LaserScale>();
+        }
+    });
+}
+
+/// Publishes the scale of the laser dots on the camera, leaving the frame untouched
+#[derive(Default)]
+pub struct LaserScalePipeline {
+    detector: LaserDetector,
+}
+
+impl Pipeline for LaserScalePipeline {
+    type Input = LaserScalerConfig;
+
+    fn collect_inputs(world: &World, _entity: &EntityRef) -> Self::Input {
+        laser_config(world)
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        config: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let scale = self.detector.detect(img, config)?;
+        publish(cmds, scale);
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+/// Draws the laser dots and their scale over the frame, for aiming the lasers live
+#[derive(Default)]
+pub struct LaserOverlayPipeline {
+    detector: LaserDetector,
+}
+
+impl Pipeline for LaserOverlayPipeline {
+    type Input = LaserScalerConfig;
+
+    fn collect_inputs(world: &World, _entity: &EntityRef) -> Self::Input {
+        laser_config(world)
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        config: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let scale = self.detector.detect(img, config)?;
+        publish(cmds, scale);
+
+        // BGR
+        let color = Scalar::new(0.0, 255.0, 255.0, 0.0);
+        let to_cv = |it: Vec2| Point::new(it.x.round() as i32, it.y.round() as i32);
+
+        let label = match scale {
+            Some(scale) => {
+                let [left, right] = scale.dots.map(to_cv);
+
+                imgproc::circle(img, left, 8, color, 2, imgproc::LINE_AA, 0)?;
+                imgproc::circle(img, right, 8, color, 2, imgproc::LINE_AA, 0)?;
+                imgproc::line(img, left, right, color, 1, imgproc::LINE_AA, 0)?;
+
+                format!("{:.0} px/m", scale.px_per_meter)
+            }
+            None => "No laser dots".to_owned(),
+        };
+
+        imgproc::put_text(
+            img,
+            &label,
+            Point::new(10, 30),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.8,
+            color,
+            2,
+            imgproc::LINE_AA,
+            false,
+        )?;
+
+        Ok(img)
+    }
+
+    fn cleanup(self, entity_world: &mut EntityWorldMut) {
+        let camera = entity_world.get::<PipelineCamera>().map(|it| it.camera());
+
+        entity_world.world_scope(|world| {
+            if let Some(mut camera) = camera.and_then(|it| world.get_entity_mut(it).ok()) {
+                camera.remove::<LaserScale>();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::{
+        core::{Point, Scalar, CV_8UC3},
+        imgproc,
+        prelude::*,
+    };
+
+    use super::{LaserColor, LaserDetector, LaserScalerConfig};
+
+    fn frame_with_dots(dots: &[(i32, i32)], bgr: Scalar) -> Mat {
+        let mut frame =
+            Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::new(60.0, 70.0, 50.0, 0.0))
+                .expect("Create frame");
+
+        for &(x, y) in dots {
+            imgproc::circle(&mut frame, Point::new(x, y), 6, bgr, -1, imgproc::LINE_8, 0)
+                .expect("Draw dot");
+        }
+
+        frame
+    }
+
+    #[test]
+    fn finds_red_dots() {
+        let frame = frame_with_dots(&[(400, 242), (200, 240)], Scalar::new(0.0, 0.0, 255.0, 0.0));
+        let config = LaserScalerConfig {
+            separation: 0.1,
+            color: LaserColor::Red,
+        };
+
+        let scale = LaserDetector::default()
+            .detect(&frame, &config)
+            .expect("Detect")
+            .expect("Dots found");
+
+        assert!((scale.dots[0].x - 200.0).abs() < 1.0, "{scale:?}");
+        assert!((scale.dots[1].x - 400.0).abs() < 1.0, "{scale:?}");
+        assert!((scale.px_per_meter - 2000.0).abs() < 10.0, "{scale:?}");
+    }
+
+    #[test]
+    fn ignores_other_colors() {
+        let frame = frame_with_dots(&[(400, 240), (200, 240)], Scalar::new(0.0, 255.0, 0.0, 0.0));
+        let config = LaserScalerConfig {
+            separation: 0.1,
+            color: LaserColor::Red,
+        };
+
+        let scale = LaserDetector::default()
+            .detect(&frame, &config)
+            .expect("Detect");
+
+        assert_eq!(scale, None);
+    }
+}
@@ -0,0 +1,666 @@
+//! Builds a top-down map of the seafloor from a downward facing camera
+//!
+//! Meant for a downward facing camera mounted so the top of the image points toward the robot's
+//! front, like the transect pipeline. Each frame is placed on a fixed size canvas at the robot's
+//! `PositionEstimate`, where the estimator fuses acoustic fixes with visual odometry or a DVL, and
+//! scaled by the altitude from the echosounder. The map is in the positioning frame, meters from
+//! its calibrated origin, and is exported with a world file so GIS tools place it there.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiUserTextures};
+use common::{
+    components::{Altimeter, Orientation, PositionEstimate, Robot, RobotId},
+    error::ErrorEvent,
+};
+use egui::{Color32, Id, TextureId};
+use egui_plot::{Line, Plot, PlotImage, PlotPoints, Points};
+use opencv::{
+    core::{self, Scalar, Size, CV_8UC3},
+    imgcodecs, imgproc,
+    prelude::*,
+};
+use time::format_description::well_known::Iso8601;
+
+use crate::{
+    session::{artifact_directory, ActiveSession},
+    video_pipelines::{
+        calibration::{self, LoadedCalibration},
+        AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks,
+    },
+    video_stream, wall_clock,
+};
+
+pub const MOSAIC_DIRECTORY: &str = "mosaics";
+
+/// Width and height of the map in pixels
+const CANVAS_SIZE: i32 = 2048;
+/// How often the map shown on the surface is refreshed
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+/// The track gets a new point once the robot moved this many meters
+const TRACK_SPACING: f32 = 0.05;
+/// Altimeter readings the echosounder is less sure of than this fall back to the set altitude
+const MIN_ALTIMETER_CONFIDENCE: f32 = 0.5;
+
+pub struct MosaicPipelinePlugin;
+
+impl Plugin for MosaicPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MosaicSettings>()
+            .add_event::<ExportMosaic>()
+            .register_video_pipeline::<MosaicPipeline>("Survey Mosaic Pipeline")
+            .add_systems(Update, (mosaic_ui, export_mosaic.after(mosaic_ui)));
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct MosaicSettings {
+    /// Meters per map pixel, changing it starts a new map
+    pub resolution: f32,
+    /// Meters the robot has to move before another frame is placed
+    pub spacing: f32,
+    /// Distance to the bottom in meters when the echosounder has no good reading
+    pub altitude: f32,
+    /// Horizontal field of view in degrees for cameras without a calibration
+    pub fov: f32,
+    /// Frames taken while the robot is tilted more than this many degrees are not placed
+    pub max_tilt: f32,
+}
+
+impl Default for MosaicSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 0.01,
+            spacing: 0.1,
+            altitude: 1.5,
+            fov: 80.0,
+            max_tilt: 15.0,
+        }
+    }
+}
+
+/// Saves the map of a mosaic pipeline
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ExportMosaic(pub Entity);
+
+/// Starts a new map, removed by the pipeline once it did
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ResetMosaic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MosaicStatus {
+    #[default]
+    Waiting,
+    Mapping,
+    Tilted,
+    OffMap,
+}
+
+impl MosaicStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MosaicStatus::Waiting => "Waiting for a position estimate",
+            MosaicStatus::Mapping => "Mapping",
+            MosaicStatus::Tilted => "Too tilted to map",
+            MosaicStatus::OffMap => "Outside of the map",
+        }
+    }
+}
+
+/// The state of a mosaic pipeline's map, lives on the pipeline entity
+#[derive(Component, Debug, Clone)]
+pub struct MosaicMap {
+    /// Position of the canvas's center in meters
+    pub origin: Vec2,
+    /// Meters per canvas pixel
+    pub resolution: f32,
+    /// Width and height of the canvas in pixels
+    pub size: i32,
+    /// Positions the robot passed through
+    pub track: Vec<Vec2>,
+    pub frames: usize,
+    pub status: MosaicStatus,
+}
+
+impl MosaicMap {
+    fn new(origin: Vec2, resolution: f32) -> Self {
+        Self {
+            origin,
+            resolution,
+            size: CANVAS_SIZE,
+            track: Vec::new(),
+            frames: 0,
+            status: MosaicStatus::Mapping,
+        }
+    }
+
+    /// Canvas pixel of a position, +Y points up the canvas
+    pub fn to_canvas(&self, position: Vec2) -> Vec2 {
+        let offset = (position - self.origin) / self.resolution;
+        Vec2::new(offset.x, -offset.y) + self.size as f32 / 2.0
+    }
+
+    pub fn contains(&self, position: Vec2) -> bool {
+        let canvas = self.to_canvas(position);
+        canvas.cmpge(Vec2::ZERO).all() && canvas.cmplt(Vec2::splat(self.size as f32)).all()
+    }
+
+    /// Side length of the map in meters
+    pub fn extent(&self) -> f32 {
+        self.size as f32 * self.resolution
+    }
+
+    /// Affine transform from the pixels of a frame to the canvas
+    ///
+    /// The frame was taken at `position` with the top of the image toward `forward`, each of its
+    /// pixels covers `ground_px` meters of the bottom.
+    fn image_to_canvas(
+        &self,
+        image: Size,
+        ground_px: f32,
+        position: Vec2,
+        forward: Vec2,
+    ) -> [[f64; 3]; 2] {
+        let right = Vec2::new(forward.y, -forward.x);
+        let center = Vec2::new(image.width as f32, image.height as f32) / 2.0;
+        let canvas = self.to_canvas(position);
+        let scale = ground_px / self.resolution;
+
+        let x = Vec2::new(right.x, -forward.x) * scale;
+        let y = Vec2::new(-right.y, forward.y) * scale;
+
+        [
+            [x.x as f64, x.y as f64, (canvas.x - x.dot(center)) as f64],
+            [y.x as f64, y.y as f64, (canvas.y - y.dot(center)) as f64],
+        ]
+    }
+
+    /// An ESRI world file placing the exported image at its position
+    pub fn world_file(&self) -> String {
+        let half = self.extent() / 2.0;
+
+        // Scale, rotation terms, then the center of the top left pixel
+        format!(
+            "{}\n0\n0\n{}\n{}\n{}\n",
+            self.resolution,
+            -self.resolution,
+            self.origin.x - half,
+            self.origin.y + half
+        )
+    }
+}
+
+/// A mosaic pipeline's latest map, lives on the pipeline entity
+#[derive(Component, Clone)]
+pub struct MosaicImage {
+    pub image_handle: Handle<Image>,
+    pub egui_texture: TextureId,
+}
+
+/// Where the robot is, in the frame of its `PositionEstimate`
+#[derive(Debug, Clone, Copy)]
+pub struct RobotPose {
+    position: Vec2,
+    rotation: Quat,
+    altitude: Option<f32>,
+}
+
+pub struct MosaicPipeline {
+    calibration: Option<LoadedCalibration>,
+    focal: Option<(Size, f32)>,
+
+    canvas: Mat,
+    map: Option<MosaicMap>,
+    last_placed: Option<Vec2>,
+    last_published: Option<Instant>,
+}
+
+impl Pipeline for MosaicPipeline {
+    type Input = (MosaicSettings, Option<RobotPose>, bool);
+
+    fn collect_inputs(world: &World, entity: &EntityRef) -> Self::Input {
+        let settings = world
+            .get_resource::<MosaicSettings>()
+            .copied()
+            .unwrap_or_default();
+
+        let pose: Option<RobotPose> = try {
+            let robot_id = entity.get::<RobotId>()?;
+            let robot = world.iter_entities().find(|entity| {
+                entity.contains::<Robot>() && entity.get::<RobotId>() == Some(robot_id)
+            })?;
+
+            let position = robot.get::<PositionEstimate>()?;
+            let orientation = robot.get::<Orientation>()?;
+            let altitude = robot
+                .get::<Altimeter>()
+                .filter(|it| it.confidence >= MIN_ALTIMETER_CONFIDENCE)
+                .map(|it| it.altitude.0);
+
+            RobotPose {
+                position: position.position.truncate(),
+                rotation: position.frame_rotation * orientation.0,
+                altitude,
+            }
+        };
+
+        (settings, pose, entity.contains::<ResetMosaic>())
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        (settings, pose, reset): &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let resolution_changed = self
+            .map
+            .as_ref()
+            .is_some_and(|it| it.resolution != settings.resolution);
+
+        if *reset || resolution_changed {
+            self.map = None;
+            self.last_placed = None;
+            self.last_published = None;
+
+            cmds.pipeline(|mut entity| {
+                entity.remove::<ResetMosaic>();
+            });
+        }
+
+        let Some(pose) = pose else {
+            if let Some(map) = &mut self.map {
+                map.status = MosaicStatus::Waiting;
+            }
+
+            return self.publish(cmds, img);
+        };
+
+        let map = self.map.get_or_insert_with(|| {
+            // The canvas is centered on where the map was started
+            self.canvas = Mat::default();
+            MosaicMap::new(pose.position, settings.resolution)
+        });
+        if self.canvas.empty() {
+            self.canvas =
+                Mat::new_rows_cols_with_default(map.size, map.size, CV_8UC3, Scalar::all(0.0))
+                    .context("Create canvas")?;
+        }
+
+        if map
+            .track
+            .last()
+            .is_none_or(|it| it.distance(pose.position) >= TRACK_SPACING)
+        {
+            map.track.push(pose.position);
+        }
+
+        let up = pose.rotation * Vec3::Z;
+        let forward = (pose.rotation * Vec3::Y).truncate().normalize_or_zero();
+
+        map.status = if !map.contains(pose.position) {
+            MosaicStatus::OffMap
+        } else if up.z < settings.max_tilt.to_radians().cos() || forward == Vec2::ZERO {
+            MosaicStatus::Tilted
+        } else {
+            MosaicStatus::Mapping
+        };
+
+        let due = self
+            .last_placed
+            .is_none_or(|it| it.distance(pose.position) >= settings.spacing);
+        if map.status == MosaicStatus::Mapping && due {
+            let size = img.size().context("Get image size")?;
+            let focal = match self.focal {
+                Some((focal_size, focal)) if focal_size == size => focal,
+                _ => {
+                    let focal = match &self.calibration {
+                        Some(calibration) => *calibration
+                            .camera_matrix(size)
+                            .context("Scale camera matrix")?
+                            .at_2d::<f32>(0, 0)
+                            .context("Read focal length")?,
+                        None => size.width as f32 / 2.0 / (settings.fov.to_radians() / 2.0).tan(),
+                    };
+                    self.focal = Some((size, focal));
+
+                    focal
+                }
+            };
+
+            let altitude = pose.altitude.unwrap_or(settings.altitude);
+            let transform = map.image_to_canvas(size, altitude / focal, pose.position, forward);
+            let transform = Mat::from_slice_2d(&transform).context("Transform to mat")?;
+
+            // Newer frames are drawn over older ones, the rest of the canvas is left as is
+            imgproc::warp_affine(
+                img,
+                &mut self.canvas,
+                &transform,
+                Size::new(map.size, map.size),
+                imgproc::INTER_LINEAR,
+                core::BORDER_TRANSPARENT,
+                Scalar::default(),
+            )
+            .context("Place frame")?;
+
+            map.frames += 1;
+            self.last_placed = Some(pose.position);
+        }
+
+        self.publish(cmds, img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+impl MosaicPipeline {
+    /// Sends the map to the surface every `PUBLISH_INTERVAL`, converting the whole canvas is slow
+    fn publish<'b>(
+        &mut self,
+        cmds: &mut PipelineCallbacks,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let now = Instant::now();
+        let due = self
+            .last_published
+            .is_none_or(|it| now - it >= PUBLISH_INTERVAL);
+
+        let Some(map) = self.map.as_ref().filter(|_| due).cloned() else {
+            return Ok(img);
+        };
+        self.last_published = Some(now);
+
+        let mut image = Image::default();
+        video_stream::mat_to_image(&self.canvas, &mut image).context("Convert map")?;
+
+        cmds.pipeline(move |mut entity| {
+            let existing = entity
+                .get::<MosaicImage>()
+                .map(|it| it.image_handle.clone());
+
+            let created = entity.world_scope(|world| -> Option<MosaicImage> {
+                let mut images = world.get_resource_mut::<Assets<Image>>()?;
+                if let Some(handle) = existing {
+                    images.insert(&handle, image);
+                    return None;
+                }
+
+                let image_handle = images.add(image);
+                let egui_texture = world
+                    .get_resource_mut::<EguiUserTextures>()?
+                    .add_image(image_handle.clone_weak());
+
+                Some(MosaicImage {
+                    image_handle,
+                    egui_texture,
+                })
+            });
+
+            if let Some(created) = created {
+                entity.insert(created);
+            }
+            entity.insert(map);
+        });
+
+        Ok(img)
+    }
+}
+
+impl FromWorldEntity for MosaicPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        // Without a calibration the field of view from the settings is used
+        let calibration = calibration::camera_calibration(world, camera);
+
+        Ok(Self {
+            calibration,
+            focal: None,
+            canvas: Mat::default(),
+            map: None,
+            last_placed: None,
+            last_published: None,
+        })
+    }
+}
+
+fn mosaic_ui(
+    mut cmds: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<MosaicSettings>,
+    pipelines: Query<(Entity, &MosaicMap, Option<&MosaicImage>)>,
+    mut exports: EventWriter<ExportMosaic>,
+) {
+    for (entity, map, image) in &pipelines {
+        let context = contexts.ctx_mut();
+        egui::Window::new("Survey Mosaic")
+            .id(Id::new(entity))
+            .constrain_to(context.available_rect().shrink(20.0))
+            .default_size((300.0, 300.0))
+            .show(context, |ui| {
+                let mut new_settings = *settings;
+
+                ui.add(
+                    egui::Slider::new(&mut new_settings.resolution, 0.002..=0.05)
+                        .text("Resolution")
+                        .suffix("m/px"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut new_settings.spacing, 0.0..=1.0)
+                        .text("Frame Spacing")
+                        .suffix("m"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut new_settings.altitude, 0.2..=10.0)
+                        .text("Fallback Altitude")
+                        .suffix("m"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut new_settings.fov, 30.0..=150.0)
+                        .text("Uncalibrated FOV")
+                        .suffix("deg"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut new_settings.max_tilt, 0.0..=45.0)
+                        .text("Max Tilt")
+                        .suffix("deg"),
+                );
+
+                if new_settings != *settings {
+                    *settings = new_settings;
+                }
+
+                ui.separator();
+
+                ui.label(format!(
+                    "{}, {} frames, {:.1}m map",
+                    map.status.name(),
+                    map.frames,
+                    map.extent()
+                ));
+
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        cmds.entity(entity).insert(ResetMosaic);
+                    }
+                    if ui
+                        .add_enabled(image.is_some(), egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        exports.send(ExportMosaic(entity));
+                    }
+                });
+
+                Plot::new(("Mosaic Plot", entity))
+                    .data_aspect(1.0)
+                    .min_size(egui::Vec2::new(100.0, 100.0))
+                    .width(ui.available_width())
+                    .height(ui.available_width())
+                    .show(ui, |ui| {
+                        let to_plot = |it: Vec2| [it.x as f64, it.y as f64];
+
+                        if let Some(image) = image {
+                            let extent = map.extent();
+                            ui.image(PlotImage::new(
+                                "Mosaic",
+                                image.egui_texture,
+                                to_plot(map.origin).into(),
+                                [extent, extent],
+                            ));
+                        }
+
+                        ui.line(
+                            Line::new(
+                                "Track",
+                                map.track
+                                    .iter()
+                                    .map(|it| to_plot(*it))
+                                    .collect::<PlotPoints>(),
+                            )
+                            .color(Color32::YELLOW)
+                            .width(2.0),
+                        );
+
+                        if let Some(robot) = map.track.last() {
+                            ui.points(
+                                Points::new("Robot", to_plot(*robot))
+                                    .color(Color32::RED)
+                                    .radius(4.0),
+                            );
+                        }
+                    });
+            });
+    }
+}
+
+fn export_mosaic(
+    mut events: EventReader<ExportMosaic>,
+    mosaics: Query<(&MosaicMap, &MosaicImage)>,
+    images: Res<Assets<Image>>,
+    session: Option<Res<ActiveSession>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    for &ExportMosaic(entity) in events.read() {
+        let res: anyhow::Result<()> = try {
+            let (map, image) = mosaics
+                .get(entity)
+                .map_err(|_| anyhow!("Mosaic no longer exists"))?;
+            let image = images
+                .get(&image.image_handle)
+                .context("Mosaic image is not loaded")?;
+
+            let size = image.size();
+            let rgba = Mat::from_slice(&image.data).context("Wrap image")?;
+            let rgba = rgba.reshape(4, size.y as i32).context("Reshape image")?;
+
+            let mut img = Mat::default();
+            imgproc::cvt_color_def(&rgba, &mut img, imgproc::COLOR_RGBA2BGR)
+                .context("Convert colors")?;
+
+            let directory = artifact_directory(session.as_deref(), MOSAIC_DIRECTORY);
+            fs::create_dir_all(&directory).context("Create mosaic directory")?;
+
+            let time = wall_clock::now()
+                .format(&Iso8601::DATE_TIME)
+                .context("Format time")?;
+            let base = format!("{}/mosaic_{time}", directory.display());
+            let image_path = format!("{base}.png");
+
+            imgcodecs::imwrite_def(&image_path, &img).context("Write mosaic")?;
+            fs::write(format!("{base}.pgw"), map.world_file()).context("Write world file")?;
+            fs::write(format!("{base}_track.csv"), track_csv(map)).context("Write track")?;
+
+            info!("Exported mosaic to {image_path}");
+        };
+
+        if let Err(err) = res {
+            errors.send(err.context("Export mosaic").into());
+        }
+    }
+}
+
+fn track_csv(map: &MosaicMap) -> String {
+    let mut csv = "# Meters from the positioning origin, same frame as the world file\n".to_owned();
+    csv.push_str("x,y\n");
+
+    for point in &map.track {
+        let _ = writeln!(csv, "{},{}", point.x, point.y);
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use opencv::core::Size;
+
+    use super::MosaicMap;
+
+    fn apply(transform: [[f64; 3]; 2], point: Vec2) -> Vec2 {
+        let [x, y] = transform
+            .map(|row| (row[0] * point.x as f64 + row[1] * point.y as f64 + row[2]) as f32);
+
+        Vec2::new(x, y)
+    }
+
+    #[test]
+    fn origin_is_centered() {
+        let map = MosaicMap::new(Vec2::new(3.0, -2.0), 0.01);
+
+        assert_eq!(map.to_canvas(Vec2::new(3.0, -2.0)), Vec2::splat(1024.0));
+        assert_eq!(
+            map.to_canvas(Vec2::new(3.0, -1.0)),
+            Vec2::new(1024.0, 924.0)
+        );
+        assert!(map.contains(Vec2::new(12.0, 7.0)));
+        assert!(!map.contains(Vec2::new(14.0, -2.0)));
+    }
+
+    #[test]
+    fn frame_top_faces_forward() {
+        let map = MosaicMap::new(Vec2::ZERO, 0.01);
+        let image = Size::new(640, 480);
+        let top = Vec2::new(320.0, 0.0);
+        let center = Vec2::new(320.0, 240.0);
+
+        // Facing +Y, the top of the frame is up the canvas
+        let transform = map.image_to_canvas(image, 0.01, Vec2::ZERO, Vec2::Y);
+        assert!(apply(transform, center).distance(Vec2::splat(1024.0)) < 1e-3);
+        assert!(apply(transform, top).distance(Vec2::new(1024.0, 784.0)) < 1e-3);
+
+        // Facing +X, it is to the right
+        let transform = map.image_to_canvas(image, 0.01, Vec2::ZERO, Vec2::X);
+        assert!(apply(transform, top).distance(Vec2::new(1264.0, 1024.0)) < 1e-3);
+
+        // Half the ground covered by each pixel halves the frame on the canvas
+        let transform = map.image_to_canvas(image, 0.005, Vec2::new(1.0, 0.0), Vec2::Y);
+        assert!(apply(transform, top).distance(Vec2::new(1124.0, 904.0)) < 1e-3);
+    }
+
+    #[test]
+    fn world_file_places_top_left() {
+        let map = MosaicMap::new(Vec2::new(10.0, 20.0), 0.01);
+        let lines = map
+            .world_file()
+            .lines()
+            .map(|it| it.parse::<f32>().expect("Number"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], 0.01);
+        assert_eq!(lines[3], -0.01);
+        assert!((lines[4] - (10.0 - 10.24)).abs() < 1e-4);
+        assert!((lines[5] - (20.0 + 10.24)).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,565 @@
+//! Estimates the robot's motion from the camera
+//!
+//! Features are tracked between frames with optical flow and the essential matrix between the
+//! two views gives the rotation and the direction of travel. A single camera cannot tell how far
+//! it moved, so the scale comes from fiducial tags seen in both frames when there are any and from
+//! an assumed distance to the scene otherwise
+
+use std::{collections::HashMap, mem, time::Instant};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use common::components::VisualOdometry;
+use egui::{DragValue, Id};
+use opencv::{
+    calib3d,
+    core::{no_array, Point, Point2f, Point3f, Scalar, Size, Vector},
+    imgproc,
+    objdetect::{self, ArucoDetector},
+    prelude::*,
+    video,
+};
+
+use crate::video_pipelines::{
+    calibration::{self, LoadedCalibration},
+    tags::{self, TagFamily, TagSettings},
+    AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks,
+};
+
+/// Features are detected again once fewer than this many are still being tracked
+const MIN_FEATURES: usize = 80;
+const MAX_FEATURES: i32 = 300;
+/// Fewest inliers an essential matrix needs to be trusted
+const MIN_INLIERS: i32 = 15;
+
+pub struct OdometryPipelinePlugin;
+
+impl Plugin for OdometryPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OdometrySettings>()
+            .register_video_pipeline::<OdometryPipeline>("Visual Odometry Pipeline")
+            .add_systems(Update, odometry_ui);
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct OdometrySettings {
+    /// Distance from the camera to what it is looking at in meters, sets the scale of the motion
+    /// when no tags are in view
+    pub scene_distance: f32,
+    /// Estimates with a smaller fraction of inliers are not published
+    pub min_quality: f32,
+    /// Use tags seen in consecutive frames for metric scale
+    pub use_tags: bool,
+}
+
+impl Default for OdometrySettings {
+    fn default() -> Self {
+        Self {
+            scene_distance: 1.5,
+            min_quality: 0.5,
+            use_tags: true,
+        }
+    }
+}
+
+/// Accumulated motion of a visual odometry pipeline, lives on the pipeline entity
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct OdometryTrack {
+    /// Pose of the robot relative to where the pipeline started, in the robot's frame
+    pub pose: Transform,
+    pub latest: Option<VisualOdometry>,
+    pub features: usize,
+    pub inliers: usize,
+}
+
+pub struct OdometryPipeline {
+    calibration: LoadedCalibration,
+    camera_matrix: Option<(Size, Mat)>,
+    distortion: Mat,
+
+    detector: Option<(TagFamily, ArucoDetector)>,
+    corners: Vector<Vector<Point2f>>,
+    ids: Vector<i32>,
+
+    gray: Mat,
+    previous: Option<Frame>,
+    track: OdometryTrack,
+}
+
+/// What is kept from the last frame to compare the next one against
+struct Frame {
+    gray: Mat,
+    features: Vector<Point2f>,
+    tags: HashMap<i32, Transform>,
+    time: Instant,
+}
+
+impl Pipeline for OdometryPipeline {
+    type Input = (OdometrySettings, TagSettings);
+
+    fn collect_inputs(world: &World, _entity: &EntityRef) -> Self::Input {
+        (
+            world
+                .get_resource::<OdometrySettings>()
+                .copied()
+                .unwrap_or_default(),
+            world
+                .get_resource::<TagSettings>()
+                .copied()
+                .unwrap_or_default(),
+        )
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        (settings, tag_settings): &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let time = Instant::now();
+
+        let size = img.size().context("Get image size")?;
+        if self.camera_matrix.as_ref().map(|it| it.0) != Some(size) {
+            let mtx = self
+                .calibration
+                .camera_matrix(size)
+                .context("Scale camera matrix")?;
+            self.camera_matrix = Some((size, mtx));
+            // Features from a different resolution cannot be matched
+            self.previous = None;
+        }
+        let Some((_, mtx)) = &self.camera_matrix else {
+            unreachable!();
+        };
+        let focal = *mtx.at_2d::<f32>(0, 0).context("Read focal length")?;
+
+        imgproc::cvt_color_def(img, &mut self.gray, imgproc::COLOR_BGR2GRAY)
+            .context("Convert to gray")?;
+
+        let tags = if settings.use_tags {
+            self.detect_tags(tag_settings)?
+        } else {
+            HashMap::new()
+        };
+
+        let Some(previous) = self.previous.take() else {
+            let features = detect_features(&self.gray)?;
+            self.previous = Some(Frame {
+                gray: mem::take(&mut self.gray),
+                features,
+                tags,
+                time,
+            });
+
+            return Ok(img);
+        };
+
+        let mut tracked = Vector::<Point2f>::new();
+        let mut status = Vector::<u8>::new();
+        let mut error = Vector::<f32>::new();
+        if !previous.features.is_empty() {
+            video::calc_optical_flow_pyr_lk_def(
+                &previous.gray,
+                &self.gray,
+                &previous.features,
+                &mut tracked,
+                &mut status,
+                &mut error,
+            )
+            .context("Track features")?;
+        }
+
+        let (before, after): (Vector<Point2f>, Vector<Point2f>) = previous
+            .features
+            .iter()
+            .zip(tracked.iter())
+            .zip(status.iter())
+            .filter(|(_, status)| *status == 1)
+            .map(|(points, _)| points)
+            .unzip();
+
+        for (before, after) in before.iter().zip(after.iter()) {
+            let color = Scalar::new(0.0, 255.0, 255.0, 0.0);
+            let before = Point::new(before.x as i32, before.y as i32);
+            let after = Point::new(after.x as i32, after.y as i32);
+
+            imgproc::line(img, before, after, color, 1, imgproc::LINE_AA, 0)
+                .context("Draw track")?;
+            imgproc::circle(img, after, 2, color, -1, imgproc::LINE_AA, 0)
+                .context("Draw feature")?;
+        }
+
+        let dt = time.duration_since(previous.time).as_secs_f32();
+        let motion = if dt > 0.0 {
+            self.estimate_motion(&before, &after, &previous.tags, &tags, settings, focal)?
+        } else {
+            None
+        };
+
+        self.track.features = after.len();
+        if let Some(motion) = motion {
+            self.track.inliers = motion.inliers;
+
+            let displacement = CAMERA_TO_ROBOT * motion.displacement;
+            let rotation = CAMERA_TO_ROBOT * motion.rotation.to_scaled_axis();
+
+            // Pose of this frame relative to the last one, chained onto the accumulated pose
+            let step = Transform {
+                translation: displacement,
+                rotation: Quat::from_scaled_axis(rotation),
+                scale: Vec3::ONE,
+            };
+            self.track.pose = self.track.pose * step;
+
+            let quality = motion.inliers as f32 / after.len().max(1) as f32;
+            let odometry = VisualOdometry {
+                velocity: (displacement / dt).into(),
+                angular_velocity: (rotation / dt).into(),
+                quality,
+                metric: motion.metric,
+            };
+            self.track.latest = Some(odometry);
+
+            if quality >= settings.min_quality {
+                cmds.robot(move |mut robot| {
+                    robot.insert(odometry);
+                });
+            }
+        }
+
+        let track = self.track;
+        cmds.pipeline(move |mut entity| {
+            entity.insert(track);
+        });
+
+        // Keep following the surviving features until too many have been lost
+        let features = if after.len() < MIN_FEATURES {
+            detect_features(&self.gray)?
+        } else {
+            after
+        };
+
+        self.previous = Some(Frame {
+            gray: mem::take(&mut self.gray),
+            features,
+            tags,
+            time,
+        });
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+/// OpenCV's camera frame (x right, y down, z forward) expressed in the robot's frame (x right,
+/// y forward, z up), assumes the camera looks along the robot's forward axis
+const CAMERA_TO_ROBOT: Mat3 = Mat3::from_cols(Vec3::X, Vec3::NEG_Z, Vec3::Y);
+
+/// Motion of the camera between two frames, expressed in the first camera's frame
+struct CameraMotion {
+    displacement: Vec3,
+    rotation: Quat,
+    inliers: usize,
+    metric: bool,
+}
+
+impl OdometryPipeline {
+    fn detect_tags(&mut self, settings: &TagSettings) -> anyhow::Result<HashMap<i32, Transform>> {
+        let Some((_, mtx)) = &self.camera_matrix else {
+            return Ok(HashMap::new());
+        };
+
+        if self.detector.as_ref().map(|it| it.0) != Some(settings.family) {
+            let dictionary = objdetect::get_predefined_dictionary(settings.family.dictionary())
+                .context("Get tag dictionary")?;
+            let mut detector = ArucoDetector::new_def().context("Create tag detector")?;
+            detector
+                .set_dictionary(&dictionary)
+                .context("Set tag dictionary")?;
+
+            self.detector = Some((settings.family, detector));
+        }
+        let Some((_, detector)) = &self.detector else {
+            unreachable!();
+        };
+
+        detector
+            .detect_markers_def(&self.gray, &mut self.corners, &mut self.ids)
+            .context("Detect tags")?;
+
+        // Same corner order as the tag pipeline
+        let half = settings.size / 2.0;
+        let object_points = Vector::<Point3f>::from_iter([
+            Point3f::new(-half, half, 0.0),
+            Point3f::new(half, half, 0.0),
+            Point3f::new(half, -half, 0.0),
+            Point3f::new(-half, -half, 0.0),
+        ]);
+
+        let mut tags = HashMap::new();
+        for (id, corners) in self.ids.iter().zip(self.corners.iter()) {
+            let mut rvec = Mat::default();
+            let mut tvec = Mat::default();
+            let found = calib3d::solve_pnp(
+                &object_points,
+                &corners,
+                mtx,
+                &self.distortion,
+                &mut rvec,
+                &mut tvec,
+                false,
+                calib3d::SOLVEPNP_IPPE_SQUARE,
+            )
+            .context("Solve tag pose")?;
+
+            if found {
+                tags.insert(id, tags::pose(&rvec, &tvec)?);
+            }
+        }
+
+        Ok(tags)
+    }
+
+    fn estimate_motion(
+        &self,
+        before: &Vector<Point2f>,
+        after: &Vector<Point2f>,
+        tags_before: &HashMap<i32, Transform>,
+        tags_after: &HashMap<i32, Transform>,
+        settings: &OdometrySettings,
+        focal: f32,
+    ) -> anyhow::Result<Option<CameraMotion>> {
+        // Tags do not move, so any change in their pose is the camera moving
+        let from_tags = tags_after
+            .iter()
+            .filter_map(|(id, after)| {
+                let before = tags_before.get(id)?;
+                Some(before.mul_transform(Transform::from_matrix(after.compute_matrix().inverse())))
+            })
+            .collect::<Vec<_>>();
+
+        if !from_tags.is_empty() {
+            let count = from_tags.len() as f32;
+            let displacement = from_tags.iter().map(|it| it.translation).sum::<Vec3>() / count;
+            let rotation = from_tags[0].to_scale_rotation_translation().1;
+
+            return Ok(Some(CameraMotion {
+                displacement,
+                rotation,
+                inliers: before.len(),
+                metric: true,
+            }));
+        }
+
+        if before.len() < MIN_INLIERS as usize {
+            return Ok(None);
+        }
+
+        let Some((_, mtx)) = &self.camera_matrix else {
+            return Ok(None);
+        };
+
+        // Work in normalized image coordinates so distortion does not bias the estimate
+        let mut before_normalized = Vector::<Point2f>::new();
+        let mut after_normalized = Vector::<Point2f>::new();
+        calib3d::undistort_points_def(before, &mut before_normalized, mtx, &self.distortion)
+            .context("Undistort features")?;
+        calib3d::undistort_points_def(after, &mut after_normalized, mtx, &self.distortion)
+            .context("Undistort features")?;
+
+        let identity = Mat::from_slice_2d(&[[1.0f64, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+            .context("Identity matrix")?;
+
+        let mut mask = Mat::default();
+        let essential = calib3d::find_essential_mat(
+            &before_normalized,
+            &after_normalized,
+            &identity,
+            calib3d::RANSAC,
+            0.999,
+            1.0 / focal as f64,
+            1000,
+            &mut mask,
+        )
+        .context("Find essential matrix")?;
+
+        if essential.rows() != 3 || essential.cols() != 3 {
+            return Ok(None);
+        }
+
+        let mut rotation = Mat::default();
+        let mut translation = Mat::default();
+        let inliers = calib3d::recover_pose_estimated(
+            &essential,
+            &before_normalized,
+            &after_normalized,
+            &identity,
+            &mut rotation,
+            &mut translation,
+            &mut mask,
+        )
+        .context("Recover pose")?;
+
+        if inliers < MIN_INLIERS {
+            return Ok(None);
+        }
+
+        let rotation = mat3(&rotation)?;
+        let translation = translation
+            .data_typed::<f64>()
+            .context("Read translation")?;
+        let translation = Vec3::new(
+            translation[0] as f32,
+            translation[1] as f32,
+            translation[2] as f32,
+        );
+
+        // Flow left over once the rotation is removed comes from the translation, at a known
+        // distance it gives how far the camera moved
+        let mask = mask.data_typed::<u8>().context("Read inliers")?;
+        let mut parallax = before_normalized
+            .iter()
+            .zip(after_normalized.iter())
+            .zip(mask)
+            .filter(|(_, inlier)| **inlier != 0)
+            .map(|((before, after), _)| {
+                let rotated = rotation * Vec3::new(before.x, before.y, 1.0);
+                let rotated = rotated.truncate() / rotated.z;
+
+                rotated.distance(Vec2::new(after.x, after.y))
+            })
+            .collect::<Vec<_>>();
+        parallax.sort_by(f32::total_cmp);
+        let parallax = parallax.get(parallax.len() / 2).copied().unwrap_or(0.0);
+
+        // `recover_pose` maps points from the first camera into the second, invert it to get how
+        // the camera moved
+        let rotation = rotation.transpose();
+        let direction = -(rotation * translation).normalize_or_zero();
+
+        Ok(Some(CameraMotion {
+            displacement: direction * parallax * settings.scene_distance,
+            rotation: Quat::from_mat3(&rotation),
+            inliers: inliers as usize,
+            metric: false,
+        }))
+    }
+}
+
+fn detect_features(gray: &Mat) -> anyhow::Result<Vector<Point2f>> {
+    let mut features = Vector::<Point2f>::new();
+    imgproc::good_features_to_track(
+        gray,
+        &mut features,
+        MAX_FEATURES,
+        0.01,
+        10.0,
+        &no_array(),
+        3,
+        false,
+        0.04,
+    )
+    .context("Detect features")?;
+
+    Ok(features)
+}
+
+/// Reads a row major 3x3 `f64` matrix
+fn mat3(mat: &Mat) -> anyhow::Result<Mat3> {
+    let data = mat.data_typed::<f64>().context("Read matrix")?;
+    let data: [f32; 9] = std::array::from_fn(|idx| data[idx] as f32);
+
+    Ok(Mat3::from_cols_array(&data).transpose())
+}
+
+impl FromWorldEntity for OdometryPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let calibration = calibration::camera_calibration(world, camera)
+            .context("Camera has no calibration, run the calibration pipeline")?;
+        let distortion = calibration.distortion()?;
+
+        Ok(Self {
+            calibration,
+            camera_matrix: None,
+            distortion,
+            detector: None,
+            corners: Vector::new(),
+            ids: Vector::new(),
+            gray: Mat::default(),
+            previous: None,
+            track: OdometryTrack::default(),
+        })
+    }
+}
+
+fn odometry_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<OdometrySettings>,
+    pipelines: Query<(Entity, &OdometryTrack)>,
+) {
+    for (entity, track) in &pipelines {
+        egui::Window::new("Visual Odometry")
+            .id(Id::new(entity))
+            .show(contexts.ctx_mut(), |ui| {
+                let mut new_settings = *settings;
+
+                ui.horizontal(|ui| {
+                    ui.label("Scene Distance:");
+                    ui.add(
+                        DragValue::new(&mut new_settings.scene_distance)
+                            .speed(0.01)
+                            .range(0.1..=20.0)
+                            .suffix("m"),
+                    );
+                });
+                ui.add(
+                    egui::Slider::new(&mut new_settings.min_quality, 0.0..=1.0).text("Min Quality"),
+                );
+                ui.checkbox(&mut new_settings.use_tags, "Scale from tags");
+
+                if new_settings != *settings {
+                    *settings = new_settings;
+                }
+
+                ui.separator();
+
+                ui.label(format!(
+                    "Tracking {} features, {} inliers",
+                    track.features, track.inliers
+                ));
+
+                if let Some(latest) = track.latest {
+                    let velocity = latest.velocity;
+                    ui.label(format!(
+                        "Velocity: ({:.2}, {:.2}, {:.2}) m/s",
+                        velocity.x, velocity.y, velocity.z
+                    ));
+                    ui.label(format!(
+                        "Yaw Rate: {:.1} deg/s",
+                        latest.angular_velocity.z.to_degrees()
+                    ));
+                    ui.label(format!(
+                        "Quality: {:.0}%{}",
+                        latest.quality * 100.0,
+                        if latest.metric { " (tags)" } else { "" }
+                    ));
+                } else {
+                    ui.label("No motion estimate yet");
+                }
+
+                let translation = track.pose.translation;
+                ui.label(format!(
+                    "Travelled: ({:.2}, {:.2}, {:.2}) m",
+                    translation.x, translation.y, translation.z
+                ));
+            });
+    }
+}
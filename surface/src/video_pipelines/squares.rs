@@ -21,7 +21,9 @@ use opencv::{
 };
 use tracing::error;
 
-use crate::video_pipelines::{AppPipelineExt, Pipeline, PipelineCallbacks};
+use crate::video_pipelines::{
+    AppPipelineExt, Pipeline, PipelineCallbacks, PipelineParameter, PipelineParameters,
+};
 
 // Autonomous pipeline for brain coral transplantation
 pub struct SquarePipelinePlugin;
@@ -32,6 +34,11 @@ impl Plugin for SquarePipelinePlugin {
     }
 }
 
+// Tuning for what counts as the red target
+const MIN_SATURATION: PipelineParameter = PipelineParameter::int("Min Saturation", 30, 0, 255);
+const MIN_VALUE: PipelineParameter = PipelineParameter::int("Min Value", 100, 0, 255);
+const MIN_AREA: PipelineParameter = PipelineParameter::float("Min Area", 750.0, 0.0, 50000.0);
+
 // Stores internal state necessry for tracking the target
 #[derive(Default)]
 pub struct SquareTrackingPipeline {
@@ -60,6 +67,9 @@ pub struct SquareTrackingPipeline {
     // Computed translation relative to the square
     tvec: Vector<f64>,
     // rotation_mat: Mat,
+
+    // Values tuned from the pipeline graph editor
+    parameters: PipelineParameters,
 }
 
 // State Machiene for target following pipeline
@@ -118,9 +128,11 @@ impl Pipeline for SquareTrackingPipeline {
                 .context("Convert to HSV")?;
 
             // Bounds for what counts as red
-            let lower_red_1: Scalar = (0, 30, 100).into();
+            let saturation = self.parameters.int(&MIN_SATURATION) as f64;
+            let value = self.parameters.int(&MIN_VALUE) as f64;
+            let lower_red_1 = Scalar::new(0.0, saturation, value, 0.0);
             let upper_red_1: Scalar = (15, 255, 255).into();
-            let lower_red_2: Scalar = (160, 30, 100).into();
+            let lower_red_2 = Scalar::new(160.0, saturation, value, 0.0);
             let upper_red_2: Scalar = (180, 255, 255).into();
 
             // Create mask containing everything thats red
@@ -175,8 +187,7 @@ impl Pipeline for SquareTrackingPipeline {
                     let is_convex = imgproc::is_contour_convex(&approx).context("Is convex")?;
                     let area = imgproc::contour_area_def(&approx).context("Area")?;
 
-                    // TODO: Determine good threshold
-                    if is_convex && area > 750.0 {
+                    if is_convex && area > self.parameters.float(&MIN_AREA) {
                         // Its square enough to be considered a canidate
                         self.squares.push(approx);
                     }
@@ -476,4 +487,12 @@ impl Pipeline for SquareTrackingPipeline {
         // Pipeline entity is automatically despawned
         // No-op
     }
+
+    fn parameters() -> &'static [PipelineParameter] {
+        &[MIN_SATURATION, MIN_VALUE, MIN_AREA]
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        self.parameters = parameters.clone();
+    }
 }
@@ -0,0 +1,355 @@
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use egui::{DragValue, Id};
+use opencv::{
+    calib3d,
+    core::{Point2f, Point3f, Scalar, Size, Vector},
+    objdetect::{self, ArucoDetector, PredefinedDictionaryType},
+    prelude::*,
+};
+
+use crate::video_pipelines::{
+    calibration::{self, LoadedCalibration},
+    AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks,
+};
+
+pub struct TagPipelinePlugin;
+
+impl Plugin for TagPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TagSettings>()
+            .register_video_pipeline::<TagPipeline>("Tag Detection Pipeline")
+            .add_systems(Update, tags_ui);
+    }
+}
+
+/// A fiducial marker seen by a tag detection pipeline, these are children of the pipeline entity
+/// and are replaced every frame
+#[derive(Component, Debug, Clone, Copy)]
+pub struct DetectedTag {
+    pub id: i32,
+    pub camera: Entity,
+    /// Pose of the tag's center relative to the camera in meters, using OpenCV's camera
+    /// convention of x right, y down and z forward
+    pub transform: Transform,
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct TagSettings {
+    pub family: TagFamily,
+    /// Side length of the black square of a tag in meters
+    pub size: f32,
+}
+
+impl Default for TagSettings {
+    fn default() -> Self {
+        Self {
+            family: TagFamily::AprilTag36h11,
+            size: 0.15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFamily {
+    Aruco4x4,
+    Aruco5x5,
+    Aruco6x6,
+    AprilTag16h5,
+    AprilTag25h9,
+    AprilTag36h11,
+}
+
+impl TagFamily {
+    pub const ALL: [TagFamily; 6] = [
+        TagFamily::Aruco4x4,
+        TagFamily::Aruco5x5,
+        TagFamily::Aruco6x6,
+        TagFamily::AprilTag16h5,
+        TagFamily::AprilTag25h9,
+        TagFamily::AprilTag36h11,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TagFamily::Aruco4x4 => "ArUco 4x4",
+            TagFamily::Aruco5x5 => "ArUco 5x5",
+            TagFamily::Aruco6x6 => "ArUco 6x6",
+            TagFamily::AprilTag16h5 => "AprilTag 16h5",
+            TagFamily::AprilTag25h9 => "AprilTag 25h9",
+            TagFamily::AprilTag36h11 => "AprilTag 36h11",
+        }
+    }
+
+    pub(crate) fn dictionary(&self) -> PredefinedDictionaryType {
+        match self {
+            TagFamily::Aruco4x4 => PredefinedDictionaryType::DICT_4X4_250,
+            TagFamily::Aruco5x5 => PredefinedDictionaryType::DICT_5X5_250,
+            TagFamily::Aruco6x6 => PredefinedDictionaryType::DICT_6X6_250,
+            TagFamily::AprilTag16h5 => PredefinedDictionaryType::DICT_APRILTAG_16h5,
+            TagFamily::AprilTag25h9 => PredefinedDictionaryType::DICT_APRILTAG_25h9,
+            TagFamily::AprilTag36h11 => PredefinedDictionaryType::DICT_APRILTAG_36h11,
+        }
+    }
+}
+
+/// Marks the pipeline entity of a running tag detection pipeline
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TagDetector;
+
+pub struct TagPipeline {
+    calibration: LoadedCalibration,
+    camera_matrix: Option<(Size, Mat)>,
+    distortion: Mat,
+
+    detector: Option<(TagFamily, ArucoDetector)>,
+    corners: Vector<Vector<Point2f>>,
+    ids: Vector<i32>,
+
+    started: bool,
+}
+
+impl Pipeline for TagPipeline {
+    type Input = TagSettings;
+
+    fn collect_inputs(world: &World, _entity: &EntityRef) -> Self::Input {
+        world
+            .get_resource::<TagSettings>()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        settings: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        if !self.started {
+            self.started = true;
+            cmds.pipeline(|mut entity| {
+                entity.insert(TagDetector);
+            });
+        }
+
+        let size = img.size().context("Get image size")?;
+        if self.camera_matrix.as_ref().map(|it| it.0) != Some(size) {
+            let mtx = self
+                .calibration
+                .camera_matrix(size)
+                .context("Scale camera matrix")?;
+            self.camera_matrix = Some((size, mtx));
+        }
+        let Some((_, mtx)) = &self.camera_matrix else {
+            unreachable!();
+        };
+
+        if self.detector.as_ref().map(|it| it.0) != Some(settings.family) {
+            let dictionary = objdetect::get_predefined_dictionary(settings.family.dictionary())
+                .context("Get tag dictionary")?;
+            let mut detector = ArucoDetector::new_def().context("Create tag detector")?;
+            detector
+                .set_dictionary(&dictionary)
+                .context("Set tag dictionary")?;
+
+            self.detector = Some((settings.family, detector));
+        }
+        let Some((_, detector)) = &self.detector else {
+            unreachable!();
+        };
+
+        detector
+            .detect_markers_def(img, &mut self.corners, &mut self.ids)
+            .context("Detect tags")?;
+
+        if !self.ids.is_empty() {
+            objdetect::draw_detected_markers(
+                img,
+                &self.corners,
+                &self.ids,
+                Scalar::new(0.0, 255.0, 0.0, 0.0),
+            )
+            .context("Draw tags")?;
+        }
+
+        // Corner order used by ArUco, starting top left and going clockwise
+        let half = settings.size / 2.0;
+        let object_points = Vector::<Point3f>::from_iter([
+            Point3f::new(-half, half, 0.0),
+            Point3f::new(half, half, 0.0),
+            Point3f::new(half, -half, 0.0),
+            Point3f::new(-half, -half, 0.0),
+        ]);
+
+        let mut tags = Vec::new();
+        for (id, corners) in self.ids.iter().zip(self.corners.iter()) {
+            let mut rvec = Mat::default();
+            let mut tvec = Mat::default();
+            let found = calib3d::solve_pnp(
+                &object_points,
+                &corners,
+                mtx,
+                &self.distortion,
+                &mut rvec,
+                &mut tvec,
+                false,
+                calib3d::SOLVEPNP_IPPE_SQUARE,
+            )
+            .context("Solve tag pose")?;
+
+            if !found {
+                continue;
+            }
+
+            calib3d::draw_frame_axes_def(img, mtx, &self.distortion, &rvec, &tvec, half)
+                .context("Draw tag axes")?;
+
+            tags.push((id, pose(&rvec, &tvec)?));
+        }
+
+        let camera = cmds.camera_entity;
+        cmds.pipeline(move |mut entity| {
+            entity.despawn_descendants();
+            entity.with_children(|builder| {
+                for (id, transform) in tags {
+                    builder.spawn((
+                        Name::new(format!("Tag {id}")),
+                        DetectedTag {
+                            id,
+                            camera,
+                            transform,
+                        },
+                    ));
+                }
+            });
+        });
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+}
+
+/// Converts the rotation and translation vectors from `solve_pnp` to a `Transform`
+pub(crate) fn pose(rvec: &Mat, tvec: &Mat) -> anyhow::Result<Transform> {
+    let mut rotation = Mat::default();
+    calib3d::rodrigues_def(rvec, &mut rotation).context("Rodrigues")?;
+
+    let rotation = rotation.data_typed::<f64>().context("Read rotation")?;
+    let translation = tvec.data_typed::<f64>().context("Read translation")?;
+
+    // OpenCV is row major while glam is column major
+    let rotation = Mat3::from_cols_array(&[
+        rotation[0] as f32,
+        rotation[3] as f32,
+        rotation[6] as f32,
+        rotation[1] as f32,
+        rotation[4] as f32,
+        rotation[7] as f32,
+        rotation[2] as f32,
+        rotation[5] as f32,
+        rotation[8] as f32,
+    ]);
+
+    Ok(Transform {
+        translation: Vec3::new(
+            translation[0] as f32,
+            translation[1] as f32,
+            translation[2] as f32,
+        ),
+        rotation: Quat::from_mat3(&rotation),
+        scale: Vec3::ONE,
+    })
+}
+
+impl FromWorldEntity for TagPipeline {
+    fn from(world: &mut World, camera: Entity) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let calibration = calibration::camera_calibration(world, camera)
+            .context("Camera has no calibration, run the calibration pipeline")?;
+        let distortion = calibration.distortion()?;
+
+        Ok(Self {
+            calibration,
+            camera_matrix: None,
+            distortion,
+            detector: None,
+            corners: Vector::new(),
+            ids: Vector::new(),
+            started: false,
+        })
+    }
+}
+
+fn tags_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<TagSettings>,
+    detectors: Query<(Entity, Option<&Children>), With<TagDetector>>,
+    tags: Query<&DetectedTag>,
+    names: Query<&Name>,
+) {
+    for (entity, children) in &detectors {
+        let tags = children
+            .iter()
+            .flat_map(|it| it.iter())
+            .filter_map(|it| tags.get(*it).ok())
+            .collect::<Vec<_>>();
+
+        let camera = tags
+            .first()
+            .and_then(|it| names.get(it.camera).ok())
+            .map(|it| it.to_string());
+
+        egui::Window::new("Tag Detection")
+            .id(Id::new(entity))
+            .show(contexts.ctx_mut(), |ui| {
+                let mut new_settings = *settings;
+
+                egui::ComboBox::from_label("Family")
+                    .selected_text(new_settings.family.name())
+                    .show_ui(ui, |ui| {
+                        for family in TagFamily::ALL {
+                            ui.selectable_value(&mut new_settings.family, family, family.name());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Tag Size:");
+                    ui.add(
+                        DragValue::new(&mut new_settings.size)
+                            .speed(0.001)
+                            .range(0.005..=2.0)
+                            .suffix("m"),
+                    );
+                });
+
+                if new_settings != *settings {
+                    *settings = new_settings;
+                }
+
+                ui.separator();
+
+                if let Some(camera) = camera {
+                    ui.label(camera);
+                }
+                if tags.is_empty() {
+                    ui.label("No tags in view");
+                }
+                for tag in tags {
+                    let translation = tag.transform.translation;
+                    ui.label(format!(
+                        "Tag {}: {:.2}m away ({:.2}, {:.2}, {:.2})",
+                        tag.id,
+                        translation.length(),
+                        translation.x,
+                        translation.y,
+                        translation.z
+                    ));
+                }
+            });
+    }
+}
@@ -0,0 +1,225 @@
+//! Finds a transect line on the seafloor for the line following behavior
+//!
+//! Meant for a downward facing camera mounted so the top of the image points toward the robot's
+//! front. Straight edges are found with a hough transform and the dominant direction among them
+//! is taken as the line
+
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Context;
+use bevy::{
+    app::{App, Plugin},
+    math::Vec2,
+    prelude::{EntityRef, EntityWorldMut, World},
+};
+use common::{components::TransectLine, types::units::Radians};
+use opencv::{
+    core::{Point, Scalar, Vec4i, Vector},
+    imgproc,
+    prelude::*,
+};
+
+use crate::video_pipelines::{
+    AppPipelineExt, Pipeline, PipelineCallbacks, PipelineParameter, PipelineParameters,
+};
+
+pub struct TransectPipelinePlugin;
+
+impl Plugin for TransectPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_video_pipeline::<TransectPipeline>("Transect Line Pipeline");
+    }
+}
+
+const LOW_THRESHOLD: PipelineParameter =
+    PipelineParameter::float("Low Threshold", 50.0, 0.0, 1000.0);
+const HIGH_THRESHOLD: PipelineParameter =
+    PipelineParameter::float("High Threshold", 150.0, 0.0, 1000.0);
+/// Shortest segment considered, as a fraction of the image height
+const MIN_LENGTH: PipelineParameter = PipelineParameter::float("Min Length", 0.15, 0.01, 1.0);
+/// Segments further than this many degrees from the dominant direction are ignored
+const MAX_DEVIATION: PipelineParameter = PipelineParameter::float("Max Deviation", 15.0, 1.0, 90.0);
+
+#[derive(Default)]
+pub struct TransectPipeline {
+    gray: Mat,
+    edges: Mat,
+    lines: Vector<Vec4i>,
+    parameters: PipelineParameters,
+}
+
+impl Pipeline for TransectPipeline {
+    type Input = ();
+
+    fn collect_inputs(_world: &World, _entity: &EntityRef) -> Self::Input {
+        // No-op
+    }
+
+    fn process<'b, 'a: 'b>(
+        &'a mut self,
+        cmds: &mut PipelineCallbacks,
+        _data: &Self::Input,
+        img: &'b mut Mat,
+    ) -> anyhow::Result<&'b mut Mat> {
+        let size = img.size().context("Get image size")?;
+        let (width, height) = (size.width as f32, size.height as f32);
+
+        imgproc::cvt_color_def(img, &mut self.gray, imgproc::COLOR_BGR2GRAY)
+            .context("Convert to gray")?;
+        imgproc::canny(
+            &self.gray,
+            &mut self.edges,
+            self.parameters.float(&LOW_THRESHOLD),
+            self.parameters.float(&HIGH_THRESHOLD),
+            3,
+            false,
+        )
+        .context("Canny")?;
+
+        let min_length = self.parameters.float(&MIN_LENGTH) * height as f64;
+        imgproc::hough_lines_p(
+            &self.edges,
+            &mut self.lines,
+            1.0,
+            1f64.to_radians(),
+            50,
+            min_length,
+            min_length / 4.0,
+        )
+        .context("Find lines")?;
+
+        // Segments pointing up the image with their length, the line's direction is ambiguous so
+        // every segment is flipped to point toward the top
+        let segments = self
+            .lines
+            .iter()
+            .map(|it| {
+                let (a, b) = (
+                    Vec2::new(it[0] as f32, it[1] as f32),
+                    Vec2::new(it[2] as f32, it[3] as f32),
+                );
+
+                if a.y > b.y {
+                    (a, b)
+                } else {
+                    (b, a)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let max_deviation = (self.parameters.float(&MAX_DEVIATION) as f32).to_radians();
+        let line = dominant_line(&segments, max_deviation, width, height);
+
+        if let Some(line) = line {
+            draw_line(img, &line, width, height)?;
+
+            cmds.robot(move |mut robot| {
+                robot.insert(line);
+            });
+        }
+
+        Ok(img)
+    }
+
+    fn cleanup(self, _entity_world: &mut EntityWorldMut) {
+        // No-op
+    }
+
+    fn parameters() -> &'static [PipelineParameter] {
+        &[LOW_THRESHOLD, HIGH_THRESHOLD, MIN_LENGTH, MAX_DEVIATION]
+    }
+
+    fn set_parameters(&mut self, parameters: &PipelineParameters) {
+        self.parameters = parameters.clone();
+    }
+}
+
+/// Angle of a segment from the image's vertical axis, positive leaning right
+fn segment_angle((bottom, top): (Vec2, Vec2)) -> f32 {
+    let direction = top - bottom;
+    direction.x.atan2(-direction.y)
+}
+
+fn dominant_line(
+    segments: &[(Vec2, Vec2)],
+    max_deviation: f32,
+    width: f32,
+    height: f32,
+) -> Option<TransectLine> {
+    // Length weighted average of the doubled angles so near vertical segments leaning either way
+    // don't cancel out
+    let doubled = segments
+        .iter()
+        .map(|it| {
+            let angle = 2.0 * segment_angle(*it);
+            Vec2::new(angle.cos(), angle.sin()) * it.0.distance(it.1)
+        })
+        .sum::<Vec2>();
+    if doubled == Vec2::ZERO {
+        return None;
+    }
+    let dominant = doubled.y.atan2(doubled.x) / 2.0;
+
+    let mut total_length = 0.0;
+    let mut angle = 0.0;
+    let mut offset = 0.0;
+    for segment in segments {
+        let segment_angle = segment_angle(*segment);
+        if (segment_angle - dominant).abs() > max_deviation {
+            continue;
+        }
+
+        // Where the segment extended to a full line crosses the middle row
+        let (bottom, top) = *segment;
+        let direction = top - bottom;
+        if direction.y.abs() < 1e-3 {
+            continue;
+        }
+        let crossing = bottom.x + direction.x * (height / 2.0 - bottom.y) / direction.y;
+
+        let length = bottom.distance(top);
+        total_length += length;
+        angle += segment_angle * length;
+        offset += (crossing - width / 2.0) / (width / 2.0) * length;
+    }
+
+    if total_length == 0.0 {
+        return None;
+    }
+
+    Some(TransectLine {
+        angle: Radians((angle / total_length).clamp(-FRAC_PI_2, FRAC_PI_2)),
+        offset: (offset / total_length).clamp(-1.0, 1.0),
+        confidence: (total_length / height).min(1.0),
+    })
+}
+
+fn draw_line(img: &mut Mat, line: &TransectLine, width: f32, height: f32) -> anyhow::Result<()> {
+    let center = Vec2::new(width / 2.0 * (1.0 + line.offset), height / 2.0);
+    let direction = Vec2::new(line.angle.0.sin(), -line.angle.0.cos()) * height;
+
+    let (top, bottom) = (center + direction, center - direction);
+    imgproc::line(
+        img,
+        Point::new(bottom.x as i32, bottom.y as i32),
+        Point::new(top.x as i32, top.y as i32),
+        Scalar::new(0.0, 0.0, 255.0, 0.0),
+        3,
+        imgproc::LINE_AA,
+        0,
+    )
+    .context("Draw line")?;
+
+    imgproc::line(
+        img,
+        Point::new((width / 2.0) as i32, 0),
+        Point::new((width / 2.0) as i32, height as i32),
+        Scalar::new(255.0, 255.0, 255.0, 0.0),
+        1,
+        imgproc::LINE_8,
+        0,
+    )
+    .context("Draw center")?;
+
+    Ok(())
+}
@@ -5,7 +5,6 @@ use bevy::{
     math::Mat3A,
     prelude::{Entity, EntityRef, EntityWorldMut, World},
 };
-use common::components::CameraCalibration;
 use opencv::{
     calib3d,
     core::{Range, Rect, Size},
@@ -13,7 +12,10 @@ use opencv::{
     prelude::*,
 };
 
-use crate::video_pipelines::{AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks};
+use crate::video_pipelines::{
+    calibration::{self, LoadedCalibration},
+    AppPipelineExt, FromWorldEntity, Pipeline, PipelineCallbacks,
+};
 
 pub struct UndistortPipelinePlugin;
 
@@ -32,7 +34,7 @@ pub struct UndistortPipeline {
     undistorted: Mat,
     cropped: Mat,
 
-    mtx: Mat,
+    calibration: LoadedCalibration,
     dist: Mat,
 
     remap: Option<RemapData>,
@@ -74,7 +76,7 @@ impl Pipeline for UndistortPipeline {
         let UndistortPipeline {
             undistorted,
             cropped,
-            mtx,
+            calibration,
             dist,
             remap,
             camera_entity,
@@ -87,9 +89,13 @@ impl Pipeline for UndistortPipeline {
         } = match remap {
             Some(remap) => remap,
             None => {
+                let mtx = calibration
+                    .camera_matrix(size)
+                    .context("Scale camera matrix")?;
+
                 let mut roi = Rect::default();
                 let new_mtx = calib3d::get_optimal_new_camera_matrix(
-                    mtx,
+                    &mtx,
                     dist,
                     size,
                     0.0,
@@ -108,7 +114,7 @@ impl Pipeline for UndistortPipeline {
                 let mut map_x = Mat::default();
                 let mut map_y = Mat::default();
                 calib3d::init_undistort_rectify_map(
-                    mtx,
+                    &mtx,
                     dist,
                     &Mat::default(),
                     &new_mtx,
@@ -152,22 +158,112 @@ impl FromWorldEntity for UndistortPipeline {
     where
         Self: Sized,
     {
-        let calib = world
-            .get::<CameraCalibration>(camera)
-            .context("Camera entity must exist and have calib")?;
-
-        let mtx = Mat::from_slice_2d(&calib.camera_matrix.to_cols_array_2d())
-            .context("Mat from camera matrix")?;
-        let dist = Mat::from_slice_2d(&[&calib.distortion_coefficients])
-            .context("Mat from dist coeffs")?;
+        let calibration = calibration::camera_calibration(world, camera)
+            .context("Camera has no calibration, run the calibration pipeline")?;
+        let dist = calibration.distortion()?;
 
         Ok(Self {
             undistorted: Mat::default(),
             cropped: Mat::default(),
-            mtx,
+            calibration,
             dist,
             remap: None,
             camera_entity: camera,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use opencv::{
+        calib3d,
+        core::{Point2f, Size, TermCriteria, TermCriteria_Type, Vector},
+        imgproc,
+        prelude::*,
+    };
+
+    use crate::video_pipelines::harness::{self, PipelineHarness};
+
+    use super::{CroppedCameraMatrix, UndistortPipeline};
+
+    /// Largest distance of a chessboard corner from the line fitted through its row, in pixels
+    fn line_error(img: &Mat, pattern: Size) -> f32 {
+        let mut gray = Mat::default();
+        imgproc::cvt_color_def(img, &mut gray, imgproc::COLOR_BGR2GRAY).expect("Convert to gray");
+
+        let mut corners = Vector::<Point2f>::new();
+        let found = calib3d::find_chessboard_corners_def(&gray, pattern, &mut corners)
+            .expect("Find chessboard");
+        assert!(found, "Chessboard not found");
+
+        let criteria = TermCriteria::new(
+            TermCriteria_Type::COUNT as i32 + TermCriteria_Type::EPS as i32,
+            30,
+            0.01,
+        )
+        .expect("Create criteria");
+        imgproc::corner_sub_pix(
+            &gray,
+            &mut corners,
+            Size::new(5, 5),
+            Size::new(-1, -1),
+            criteria,
+        )
+        .expect("Refine corners");
+
+        let corners = corners.to_vec();
+        let mut worst = 0.0f32;
+        for row in corners.chunks(pattern.width as usize) {
+            let count = row.len() as f32;
+            let mean_x = row.iter().map(|it| it.x).sum::<f32>() / count;
+            let mean_y = row.iter().map(|it| it.y).sum::<f32>() / count;
+
+            let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+            for point in row {
+                let (dx, dy) = (point.x - mean_x, point.y - mean_y);
+                xx += dx * dx;
+                yy += dy * dy;
+                xy += dx * dy;
+            }
+
+            // Total least squares fit
+            let angle = 0.5 * f32::atan2(2.0 * xy, xx - yy);
+            let normal = (-angle.sin(), angle.cos());
+
+            for point in row {
+                let distance = (point.x - mean_x) * normal.0 + (point.y - mean_y) * normal.1;
+                worst = worst.max(distance.abs());
+            }
+        }
+
+        worst
+    }
+
+    #[test]
+    fn undistort_straightens_lines() {
+        let golden = harness::golden();
+        let frame = harness::sample_frame(&golden.undistort.frame);
+        let pattern = Size::new(golden.undistort.pattern[0], golden.undistort.pattern[1]);
+
+        let raw = line_error(&frame, pattern);
+        assert!(
+            raw > golden.undistort.min_raw_line_error,
+            "Sample frame is not distorted enough: {raw}px"
+        );
+
+        let (world, camera) = harness::camera_world(&golden.camera);
+        let mut harness = PipelineHarness::<UndistortPipeline>::new(world, camera);
+        let output = harness.process(&frame);
+
+        let undistorted = line_error(&output, pattern);
+        assert!(
+            undistorted < golden.undistort.max_line_error,
+            "Lines are still curved after undistortion: {undistorted}px"
+        );
+
+        assert!(
+            harness.world.get::<CroppedCameraMatrix>(camera).is_some(),
+            "Cropped camera matrix was not published"
+        );
+    }
+}
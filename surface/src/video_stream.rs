@@ -1,39 +1,181 @@
-use std::{borrow::Cow, ffi::c_void, mem, sync::Arc, thread};
+pub mod adaptive;
+pub mod playback;
+pub mod recording;
+pub mod upload;
+pub mod watchdog;
+
+use std::{
+    borrow::Cow,
+    ffi::c_void,
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
 
 use anyhow::{anyhow, Context};
 use bevy::{
+    asset::RenderAssetUsages,
     image::Volume,
     prelude::*,
     render::render_resource::{Extent3d, TextureUsages},
 };
 use common::{
-    components::CameraDefinition,
+    components::{CameraDefinition, CameraStream},
     error::{self, ErrorEvent, Errors},
+    types::video::{StreamTransport, VideoCodec},
 };
 use crossbeam::channel::{self, Receiver, Sender};
 use opencv::{
-    core::AlgorithmHint,
+    core::{AlgorithmHint, Size},
     imgproc,
     platform_types::size_t,
     prelude::*,
     videoio::{self, VideoCapture},
 };
+use serde::{Deserialize, Serialize};
+
+use self::{
+    recording::{RecordingCommand, ThreadRecording},
+    upload::FrameUploads,
+};
+use crate::replay::TelemetryReplay;
 
 #[derive(Component, Clone)]
 pub struct ImageHandle(pub Handle<Image>);
 
+/// Number of frames the camera's video thread has delivered since it was started
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct FramesReceived(pub u64);
+
+/// When the frame currently in the camera's `ImageHandle` was decoded by its video thread
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FrameDecodedAt(pub Instant);
+
+/// When the camera's current video thread was started
+#[derive(Component, Debug, Clone, Copy)]
+pub struct StreamStartedAt(pub Instant);
+
+/// Number of frames the camera's video thread failed to read or convert since it was started
+#[derive(Component, Debug, Clone, Default)]
+pub struct DecodeErrors(pub Arc<AtomicU64>);
+
+/// Frames the video thread skips and how much it shrinks the rest before handing them to bevy, set
+/// by the display layout so cameras in small tiles cost less
+///
+/// Recordings and video processors always get every frame at full resolution.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameDecimation {
+    /// Only every nth frame is converted and displayed
+    pub every: u32,
+    /// Scale applied to the displayed frames
+    pub scale: f32,
+}
+
+impl FrameDecimation {
+    pub const FULL: FrameDecimation = FrameDecimation {
+        every: 1,
+        scale: 1.0,
+    };
+
+    pub const PRESETS: [(&'static str, FrameDecimation); 3] = [
+        ("Full", FrameDecimation::FULL),
+        (
+            "Half Rate, Half Size",
+            FrameDecimation {
+                every: 2,
+                scale: 0.5,
+            },
+        ),
+        (
+            "Quarter Rate, Half Size",
+            FrameDecimation {
+                every: 4,
+                scale: 0.5,
+            },
+        ),
+    ];
+
+    pub fn name(&self) -> String {
+        Self::PRESETS
+            .iter()
+            .find(|(_, it)| it == self)
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| format!("1/{} Rate, {:.0}% Size", self.every, self.scale * 100.0))
+    }
+}
+
+impl Default for FrameDecimation {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// The gstreamer decoder used for a camera, changing it restarts the stream
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoDecoder {
+    #[default]
+    Software,
+    Vaapi,
+    Nvidia,
+}
+
+impl VideoDecoder {
+    pub const ALL: [VideoDecoder; 3] = [
+        VideoDecoder::Software,
+        VideoDecoder::Vaapi,
+        VideoDecoder::Nvidia,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoDecoder::Software => "Software",
+            VideoDecoder::Vaapi => "VA-API",
+            VideoDecoder::Nvidia => "NVDEC",
+        }
+    }
+
+    fn element(&self, codec: VideoCodec) -> &'static str {
+        match (self, codec) {
+            (VideoDecoder::Software, VideoCodec::H264) => {
+                "avdec_h264 discard-corrupted-frames=true"
+            }
+            (VideoDecoder::Software, VideoCodec::H265) => {
+                "avdec_h265 discard-corrupted-frames=true"
+            }
+            (VideoDecoder::Vaapi, VideoCodec::H264) => "vaapih264dec",
+            (VideoDecoder::Vaapi, VideoCodec::H265) => "vaapih265dec",
+            (VideoDecoder::Nvidia, VideoCodec::H264) => "nvh264dec",
+            (VideoDecoder::Nvidia, VideoCodec::H265) => "nvh265dec",
+        }
+    }
+}
+
 pub struct VideoStreamPlugin;
 
 impl Plugin for VideoStreamPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.add_plugins((
+            recording::RecordingPlugin,
+            adaptive::AdaptiveQualityPlugin,
+            watchdog::StreamWatchdogPlugin,
+            upload::FrameUploadPlugin,
+            playback::VideoPlaybackPlugin,
+        ))
+        .add_systems(
             Update,
             (
+                // Replayed cameras are played back from their recordings instead
                 handle_added_camera
                     .pipe(error::handle_errors)
-                    .before(handle_frames),
+                    .before(handle_frames)
+                    .run_if(not(resource_exists::<TelemetryReplay>)),
                 handle_frames,
                 handle_video_processors,
+                handle_decimation.after(handle_added_camera),
             ),
         );
     }
@@ -73,33 +215,61 @@ impl VideoProcessorFactory {
 pub struct VideoThread(
     // Used by the video thread to detect when its handle is droped from the ECS
     Arc<()>,
-    // Channels for displaying and reusing bevy images
+    // Channels for displaying and reusing bevy images, frames are sent with when they were decoded
     Sender<Image>,
-    Receiver<Image>,
+    Receiver<(Image, Instant)>,
     // Channel to update the thread's VideoProcessor
     Sender<Option<BoxedVideoProcessor>>,
+    // Channel to start and stop writing the stream to disk
+    Sender<RecordingCommand>,
+    // Channel to change how many frames are displayed and at what size
+    Sender<FrameDecimation>,
 );
 
 fn handle_added_camera(
     mut cmds: Commands,
-    cameras: Query<(Entity, &CameraDefinition), Changed<CameraDefinition>>,
+    cameras: Query<
+        (
+            Entity,
+            &CameraDefinition,
+            Option<&CameraStream>,
+            Option<&VideoDecoder>,
+        ),
+        Or<(
+            Changed<CameraDefinition>,
+            Changed<CameraStream>,
+            Changed<VideoDecoder>,
+        )>,
+    >,
     mut images: ResMut<Assets<Image>>,
     errors: Res<Errors>,
 ) -> anyhow::Result<()> {
-    for (entity, camera) in &cameras {
-        cmds.entity(entity).remove::<VideoThread>();
+    for (entity, camera, stream, decoder) in &cameras {
+        // Any recording ends with the old thread
+        cmds.entity(entity)
+            .remove::<(VideoThread, recording::Recording)>();
 
         let handle = Arc::new(());
         let (tx_cv, rx_cv) = channel::bounded(10);
         let (tx_bevy, rx_bevy) = channel::bounded(10);
         let (tx_proc, rx_proc) = channel::bounded(10);
+        let (tx_rec, rx_rec) = channel::bounded(10);
+        let (tx_dec, rx_dec) = channel::bounded(10);
+        let decode_errors = DecodeErrors::default();
 
         cmds.entity(entity).insert((
-            VideoThread(handle.clone(), tx_bevy, rx_cv, tx_proc),
+            VideoThread(handle.clone(), tx_bevy, rx_cv, tx_proc, tx_rec, tx_dec),
             ImageHandle(images.add(Image::default())),
+            FramesReceived::default(),
+            StreamStartedAt(Instant::now()),
+            decode_errors.clone(),
         ));
 
-        let camera = camera.clone();
+        let src = gen_src(
+            camera,
+            &stream.cloned().unwrap_or_default(),
+            decoder.copied().unwrap_or_default(),
+        );
         let errors = errors.0.clone();
         thread::Builder::new()
             .name("Video Thread".to_owned())
@@ -107,7 +277,7 @@ fn handle_added_camera(
                 let handle = Arc::downgrade(&handle);
                 let mut images: Vec<Image> = Vec::new();
 
-                let src = VideoCapture::from_file(&gen_src(&camera), videoio::CAP_GSTREAMER);
+                let src = VideoCapture::from_file(&src, videoio::CAP_GSTREAMER);
                 let mut src = match src.context("Open video capture") {
                     Ok(src) => src,
                     Err(err) => {
@@ -119,17 +289,40 @@ fn handle_added_camera(
                 // Loop until the VideoThread component is dropped
                 let mut mat = Mat::default();
                 let mut proc: Option<BoxedVideoProcessor> = None;
+                let mut recording = ThreadRecording::default();
+                let mut decimation = FrameDecimation::FULL;
+                let mut scaled = Mat::default();
+                let mut frames = 0u64;
+                let fps = src.get(videoio::CAP_PROP_FPS).unwrap_or_default();
 
                 while handle.strong_count() > 0 {
-                    let res = src.read(&mut mat).context("Read video frame");
+                    if let Some(new_decimation) = rx_dec.try_iter().last() {
+                        decimation = new_decimation;
+                    }
+
+                    for command in rx_rec.try_iter() {
+                        recording.handle(command);
+                    }
+
+                    // Skipped frames are still pulled from the decoder so it keeps up, but are
+                    // never converted or uploaded
+                    let full_rate = recording.is_active() || proc.is_some();
+                    let skip = !full_rate && frames % decimation.every.max(1) as u64 != 0;
+
+                    let res = if skip { src.grab() } else { src.read(&mut mat) }
+                        .context("Read video frame");
 
                     let new_frame = match res {
                         Ok(ret) => ret,
                         Err(err) => {
+                            decode_errors.0.fetch_add(1, Ordering::Relaxed);
                             let _ = errors.send(err);
                             continue;
                         }
                     };
+                    if new_frame {
+                        frames += 1;
+                    }
 
                     if let Some(mut new_proc) = rx_proc.try_iter().last() {
                         if let Some(proc) = proc.take() {
@@ -143,7 +336,13 @@ fn handle_added_camera(
                         proc = new_proc;
                     }
 
-                    if new_frame {
+                    if new_frame && !skip {
+                        // Record the stream as it was received, before any processing
+                        let res = recording.write(&mat, fps).context("Record video frame");
+                        if let Err(err) = res {
+                            let _ = errors.send(err);
+                        }
+
                         let mat = if let Some(proc_local) = &mut proc {
                             if !proc_local.should_end() {
                                 let res = proc_local.process(&mut mat);
@@ -168,17 +367,43 @@ fn handle_added_camera(
                             &mat
                         };
 
+                        let mat = if decimation.scale < 1.0 {
+                            let scale = decimation.scale as f64;
+                            let res = imgproc::resize(
+                                mat,
+                                &mut scaled,
+                                Size::default(),
+                                scale,
+                                scale,
+                                imgproc::INTER_AREA,
+                            )
+                            .context("Scale frame");
+
+                            match res {
+                                Ok(()) => &scaled,
+                                Err(err) => {
+                                    let _ = errors.send(err);
+                                    mat
+                                }
+                            }
+                        } else {
+                            mat
+                        };
+
                         images.extend(rx_bevy.try_iter());
                         images.truncate(15);
                         let mut image = images.pop().unwrap_or_default();
 
                         let res = mat_to_image(mat, &mut image).context("Mat to image");
                         if let Err(err) = res {
+                            decode_errors.0.fetch_add(1, Ordering::Relaxed);
                             let _ = errors.send(err);
                             continue;
                         }
+                        // Uploaded by `upload::FrameUploadPlugin` instead of bevy
+                        image.asset_usage = RenderAssetUsages::MAIN_WORLD;
 
-                        let _ = tx_cv.send(image);
+                        let _ = tx_cv.send((image, Instant::now()));
                     }
                 }
 
@@ -193,35 +418,53 @@ fn handle_added_camera(
 }
 
 fn handle_frames(
-    cameras: Query<
+    mut cmds: Commands,
+    mut cameras: Query<
         (
+            Entity,
             &VideoThread,
             &ImageHandle,
+            &mut FramesReceived,
             Option<&MeshMaterial3d<StandardMaterial>>,
             Option<&MeshMaterial2d<ColorMaterial>>,
         ),
         With<CameraDefinition>,
     >,
     mut images: ResMut<Assets<Image>>,
+    mut uploads: ResMut<FrameUploads>,
     mut image_events1: EventWriter<AssetEvent<StandardMaterial>>,
     mut image_events2: EventWriter<AssetEvent<ColorMaterial>>,
 ) {
-    for (thread, handle, material, color) in &cameras {
+    // Last update's frames have been extracted by now
+    uploads.0.clear();
+
+    for (entity, thread, handle, mut frames, material, color) in &mut cameras {
         let latest = thread.2.try_iter().fold(None, |last, next| {
-            if let Some(last) = last {
+            frames.0 += 1;
+
+            if let Some((last, _)) = last {
                 let _ = thread.1.send(last);
             }
 
             Some(next)
         });
 
-        if let Some(latest) = latest {
+        if let Some((latest, decoded_at)) = latest {
             let Some(image) = images.get_mut(&handle.0) else {
                 warn!("Couldnt get render asset for image");
                 continue;
             };
             let old = mem::replace(image, latest);
+            let resized = old.size() != image.size();
             let _ = thread.1.send(old);
+            uploads.0.push(handle.0.id());
+            cmds.entity(entity).insert(FrameDecodedAt(decoded_at));
+
+            // Frames of the same size are written into the existing texture, materials only need
+            // to pick up the new texture when the stream changes resolution
+            if !resized {
+                continue;
+            }
 
             // This shouldnt be the responsibility of this system but oh well
             if let Some(material) = material {
@@ -238,6 +481,20 @@ fn handle_frames(
     }
 }
 
+fn handle_decimation(
+    cameras: Query<
+        (&VideoThread, Option<&FrameDecimation>),
+        (
+            With<CameraDefinition>,
+            Or<(Changed<FrameDecimation>, Changed<VideoThread>)>,
+        ),
+    >,
+) {
+    for (thread, decimation) in &cameras {
+        let _ = thread.5.send(decimation.copied().unwrap_or_default());
+    }
+}
+
 fn handle_video_processors(
     mut cmds: Commands,
 
@@ -289,11 +546,26 @@ fn handle_video_processors(
 }
 
 /// Generates the gstreamer pipeline to recieve data from `camera`
-fn gen_src(camera: &CameraDefinition) -> String {
-    let ip = camera.location.ip();
-    let port = camera.location.port();
+fn gen_src(camera: &CameraDefinition, stream: &CameraStream, decoder: VideoDecoder) -> String {
+    let (depay, parse) = match stream.codec {
+        VideoCodec::H264 => ("rtph264depay", "h264parse"),
+        VideoCodec::H265 => ("rtph265depay", "h265parse"),
+    };
+    let decoder = decoder.element(stream.codec);
+
+    let src = match &stream.transport {
+        StreamTransport::Rtp => {
+            let ip = camera.location.ip();
+            let port = camera.location.port();
+
+            format!("udpsrc address={ip} port={port} caps=application/x-rtp,payload=96")
+        }
+        StreamTransport::Rtsp { url } => {
+            format!("rtspsrc location={url} latency=0 protocols=tcp")
+        }
+    };
 
-    format!("udpsrc address={ip} port={port} caps=application/x-rtp,payload=96 ! rtph264depay ! avdec_h264 discard-corrupted-frames=true ! videoconvert ! video/x-raw,format=BGR ! appsink async=false sync=false drop=1")
+    format!("{src} ! {depay} ! {parse} ! {decoder} ! videoconvert ! video/x-raw,format=BGR ! appsink async=false sync=false drop=1")
     // format!("udpsrc address={ip} port={port} caps=application/x-rtp,media=video,clock-rate=90000,encoding-name=H264,a-framerate=30,payload=96 ! rtph264depay ! h264parse ! vaapih264dec ! videoconvert ! video/x-raw,format=BGR ! appsink drop=1")
 }
 
@@ -348,3 +620,64 @@ pub fn mat_to_image(mat: &Mat, image: &mut Image) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use bevy::image::Image;
+    use opencv::{
+        core::{Scalar, CV_8UC3},
+        prelude::*,
+    };
+    use test::Bencher;
+
+    use super::mat_to_image;
+
+    fn frame(rows: i32, cols: i32) -> Mat {
+        // BGR
+        Mat::new_rows_cols_with_default(rows, cols, CV_8UC3, Scalar::new(10.0, 20.0, 30.0, 0.0))
+            .expect("Create frame")
+    }
+
+    #[test]
+    fn converts_to_rgba() {
+        let mut image = Image::default();
+        mat_to_image(&frame(2, 3), &mut image).expect("Mat to image");
+
+        assert_eq!(image.size().to_array(), [3, 2]);
+        assert_eq!(image.data.len(), 2 * 3 * 4);
+        assert!(image.data.chunks(4).all(|it| it == [30, 20, 10, 255]));
+    }
+
+    #[test]
+    fn reuses_allocation() {
+        let mut image = Image::default();
+        mat_to_image(&frame(720, 1280), &mut image).expect("Mat to image");
+        let ptr = image.data.as_ptr();
+
+        mat_to_image(&frame(480, 640), &mut image).expect("Mat to image");
+
+        assert_eq!(image.data.as_ptr(), ptr);
+        assert_eq!(image.data.len(), 480 * 640 * 4);
+    }
+
+    /// The video thread's path, images come back from bevy once displayed
+    #[bench]
+    fn bench_mat_to_image_pooled_1080p(b: &mut Bencher) {
+        let mat = frame(1080, 1920);
+        let mut image = Image::default();
+
+        b.iter(|| mat_to_image(&mat, &mut image));
+    }
+
+    #[bench]
+    fn bench_mat_to_image_fresh_1080p(b: &mut Bencher) {
+        let mat = frame(1080, 1920);
+
+        b.iter(|| {
+            let mut image = Image::default();
+            mat_to_image(&mat, &mut image).map(|_| image)
+        });
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use ahash::HashMap;
+use bevy::prelude::*;
+use common::{
+    components::{CameraCapabilities, CameraDefinition, CameraStream, Robot, SystemNetworks},
+    events::SetCameraQuality,
+    sync::Latency,
+    types::video::CameraQuality,
+};
+
+use crate::{
+    settings::{LinkGrade, SurfaceSettings},
+    video_display_2d_master::VideoMasterMarker,
+};
+
+use super::FramesReceived;
+
+/// How often the link is checked
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+/// Ping above which the link is treated as saturated, in frames
+const SATURATED_PING: u32 = 5;
+/// Fraction of a camera's framerate that must arrive for its stream to be healthy
+const MIN_FPS_RATIO: f32 = 0.7;
+/// Time between stepping cameras down, gives the restarted stream a chance to settle
+const STEP_DOWN_COOLDOWN: Duration = Duration::from_secs(4);
+/// Time the link must stay healthy before a camera is stepped back up
+const STEP_UP_DELAY: Duration = Duration::from_secs(15);
+
+pub struct AdaptiveQualityPlugin;
+
+impl Plugin for AdaptiveQualityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AdaptiveQuality>().add_systems(
+            Update,
+            (sample_link, adjust_quality, apply_overrides).chain(),
+        );
+    }
+}
+
+/// Controller that steps camera quality down when the link to the robot is saturated and back up
+/// once it recovers, the master camera is degraded last and restored first
+#[derive(Resource)]
+pub struct AdaptiveQuality {
+    pub enabled: bool,
+    pub link: LinkQuality,
+
+    timer: Timer,
+    last_change: Option<Duration>,
+    healthy_since: Option<Duration>,
+    last_frames: HashMap<Entity, u64>,
+    last_tx_bytes: Option<u64>,
+}
+
+impl Default for AdaptiveQuality {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            link: LinkQuality::default(),
+            timer: Timer::new(SAMPLE_INTERVAL, TimerMode::Repeating),
+            last_change: None,
+            healthy_since: None,
+            last_frames: HashMap::default(),
+            last_tx_bytes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinkQuality {
+    /// In frames
+    pub ping: Option<u32>,
+    pub rtt: Option<Duration>,
+    pub jitter: Duration,
+    /// Fraction of recent heartbeats missed
+    pub loss: f32,
+    pub grade: Option<LinkGrade>,
+    /// Bytes per second sent by the robot across all of its interfaces
+    pub robot_tx: Option<f64>,
+    /// Lowest ratio of received to captured framerate across all cameras
+    pub worst_fps_ratio: Option<f32>,
+    pub saturated: bool,
+}
+
+/// Pins a camera to a quality, the controller leaves these cameras alone
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityOverride(pub CameraQuality);
+
+fn sample_link(
+    mut adaptive: ResMut<AdaptiveQuality>,
+    time: Res<Time>,
+    settings: Res<SurfaceSettings>,
+    robots: Query<(Option<&Latency>, Option<&SystemNetworks>), With<Robot>>,
+    cameras: Query<(Entity, &FramesReceived, Option<&CameraCapabilities>), With<CameraDefinition>>,
+) {
+    let adaptive = &mut *adaptive;
+
+    if !adaptive.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let interval = adaptive.timer.duration().as_secs_f32();
+    let settled = adaptive
+        .last_change
+        .is_none_or(|last| time.elapsed() - last >= STEP_DOWN_COOLDOWN);
+
+    let (latency, networks) = robots.get_single().unwrap_or_default();
+
+    let ping = latency.and_then(|it| it.ping);
+    let grade = latency.map(|it| settings.connection.grade(it));
+    let robot_tx = networks.map(|networks| {
+        let tx_bytes: u64 = networks
+            .0
+            .iter()
+            .filter(|it| it.name != "lo")
+            .map(|it| it.tx_bytes)
+            .sum();
+        let last = adaptive.last_tx_bytes.replace(tx_bytes).unwrap_or(tx_bytes);
+
+        tx_bytes.saturating_sub(last) as f64 / interval as f64
+    });
+
+    let mut worst_fps_ratio: Option<f32> = None;
+    let mut last_frames = HashMap::default();
+    for (entity, frames, capabilities) in &cameras {
+        last_frames.insert(entity, frames.0);
+
+        let Some(last) = adaptive.last_frames.get(&entity) else {
+            continue;
+        };
+        let Some(format) = capabilities.and_then(|it| it.active.as_ref()) else {
+            continue;
+        };
+
+        // The counter restarts along with the camera's stream
+        if frames.0 < *last || format.fps <= 0.0 {
+            continue;
+        }
+
+        let ratio = (frames.0 - last) as f32 / interval / format.fps;
+        worst_fps_ratio = Some(worst_fps_ratio.map_or(ratio, |it| it.min(ratio)));
+    }
+    adaptive.last_frames = last_frames;
+
+    // Streams that were just restarted have not caught up yet
+    let starved = settled && worst_fps_ratio.is_some_and(|it| it < MIN_FPS_RATIO);
+
+    adaptive.link = LinkQuality {
+        ping,
+        rtt: latency.and_then(|it| it.rtt),
+        jitter: latency.map(|it| it.jitter).unwrap_or_default(),
+        loss: latency.map(|it| it.loss()).unwrap_or_default(),
+        grade,
+        saturated: ping.is_some_and(|it| it > SATURATED_PING)
+            || grade == Some(LinkGrade::Bad)
+            || starved,
+        robot_tx,
+        worst_fps_ratio,
+    };
+}
+
+fn adjust_quality(
+    mut adaptive: ResMut<AdaptiveQuality>,
+    time: Res<Time>,
+    cameras: Query<
+        (&CameraStream, &CameraCapabilities, Has<VideoMasterMarker>),
+        (With<CameraDefinition>, Without<QualityOverride>),
+    >,
+    mut quality_events: EventWriter<SetCameraQuality>,
+) {
+    if !adaptive.enabled || !adaptive.timer.just_finished() {
+        return;
+    }
+
+    let now = time.elapsed();
+    let since_change = adaptive.last_change.map(|last| now - last);
+
+    if adaptive.link.saturated {
+        adaptive.healthy_since = None;
+
+        if since_change.is_some_and(|it| it < STEP_DOWN_COOLDOWN) {
+            return;
+        }
+
+        // Spread the degradation across the other cameras before touching the master
+        let target = cameras
+            .iter()
+            .filter_map(|(stream, capabilities, master)| {
+                Some((stream.quality.lower()?, capabilities, master))
+            })
+            .max_by_key(|(quality, _, master)| (!master, *quality));
+
+        if let Some((quality, capabilities, _)) = target {
+            info!(
+                "Link saturated, lowering {} to {}",
+                capabilities.card,
+                quality.name()
+            );
+
+            quality_events.send(SetCameraQuality {
+                device: capabilities.device.clone(),
+                quality,
+            });
+            adaptive.last_change = Some(now);
+        }
+    } else {
+        let healthy_since = *adaptive.healthy_since.get_or_insert(now);
+
+        if now - healthy_since < STEP_UP_DELAY || since_change.is_some_and(|it| it < STEP_UP_DELAY)
+        {
+            return;
+        }
+
+        // Restore the master first, then whichever camera was degraded the most
+        let target = cameras
+            .iter()
+            .filter_map(|(stream, capabilities, master)| {
+                Some((stream.quality.higher()?, capabilities, master))
+            })
+            .max_by_key(|(quality, _, master)| (*master, std::cmp::Reverse(*quality)));
+
+        if let Some((quality, capabilities, _)) = target {
+            info!(
+                "Link recovered, raising {} to {}",
+                capabilities.card,
+                quality.name()
+            );
+
+            quality_events.send(SetCameraQuality {
+                device: capabilities.device.clone(),
+                quality,
+            });
+            adaptive.last_change = Some(now);
+            adaptive.healthy_since = Some(now);
+        }
+    }
+}
+
+fn apply_overrides(
+    cameras: Query<
+        (&QualityOverride, &CameraStream, &CameraCapabilities),
+        Or<(Changed<QualityOverride>, Changed<CameraStream>)>,
+    >,
+    mut quality_events: EventWriter<SetCameraQuality>,
+) {
+    for (quality, stream, capabilities) in &cameras {
+        if stream.quality != quality.0 {
+            quality_events.send(SetCameraQuality {
+                device: capabilities.device.clone(),
+                quality: quality.0,
+            });
+        }
+    }
+}
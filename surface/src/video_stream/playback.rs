@@ -0,0 +1,238 @@
+//! Plays back the video recorded during a session while it is replayed
+//!
+//! Replayed cameras get a video thread decoding their recordings instead of the live stream. The
+//! thread follows the replay clock, so the displays, video processors and anything else using the
+//! camera's `ImageHandle` work the same as with a live stream.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::{asset::RenderAssetUsages, prelude::*};
+use common::{
+    components::CameraDefinition,
+    error::{self, Errors},
+};
+use crossbeam::channel;
+use opencv::{
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+
+use super::{
+    mat_to_image, BoxedVideoProcessor, DecodeErrors, FramesReceived, ImageHandle, StreamStartedAt,
+    VideoThread,
+};
+use crate::replay::TelemetryReplay;
+
+/// Seeks instead of decoding forward when the recording is further than this behind the clock
+const MAX_DECODE_AHEAD: Duration = Duration::from_secs(2);
+/// Seeks can land a little after the target, frames this far ahead are still shown
+const SEEK_TOLERANCE: Duration = Duration::from_millis(100);
+/// How long the thread sleeps when there is no new frame to show
+const IDLE_INTERVAL: Duration = Duration::from_millis(5);
+
+pub struct VideoPlaybackPlugin;
+
+impl Plugin for VideoPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                attach_playback.pipe(error::handle_errors),
+                sync_playback_clock,
+            )
+                .chain()
+                .before(super::handle_frames)
+                .run_if(resource_exists::<TelemetryReplay>),
+        );
+    }
+}
+
+/// The replay clock in microseconds, shared with a camera's playback thread
+#[derive(Component, Debug, Clone, Default)]
+pub struct PlaybackClock(Arc<AtomicU64>);
+
+/// A recording of one camera, starting at `started` on the replay clock
+#[derive(Debug, Clone)]
+struct Track {
+    path: PathBuf,
+    started: Duration,
+}
+
+fn attach_playback(
+    mut cmds: Commands,
+    cameras: Query<(Entity, &Name), (With<CameraDefinition>, Without<PlaybackClock>)>,
+    replay: Res<TelemetryReplay>,
+    mut images: ResMut<Assets<Image>>,
+    errors: Res<Errors>,
+) -> anyhow::Result<()> {
+    for (entity, name) in &cameras {
+        let mut tracks = replay
+            .videos
+            .iter()
+            .filter(|it| it.camera == name.as_str())
+            .map(|it| Track {
+                path: it.path.clone(),
+                started: it.started,
+            })
+            .collect::<Vec<_>>();
+        tracks.sort_by_key(|it| it.started);
+
+        let handle = Arc::new(());
+        let (tx_cv, rx_cv) = channel::bounded(10);
+        let (tx_bevy, rx_bevy) = channel::bounded(10);
+        let (tx_proc, rx_proc) = channel::bounded::<Option<BoxedVideoProcessor>>(10);
+        let (tx_rec, rx_rec) = channel::bounded(10);
+        let (tx_dec, rx_dec) = channel::bounded(10);
+        let decode_errors = DecodeErrors::default();
+        let clock = PlaybackClock::default();
+
+        // Cameras without a recording still get a clock so they aren't retried every update
+        cmds.entity(entity).insert(clock.clone());
+        if tracks.is_empty() {
+            continue;
+        }
+
+        cmds.entity(entity).insert((
+            VideoThread(handle.clone(), tx_bevy, rx_cv, tx_proc, tx_rec, tx_dec),
+            ImageHandle(images.add(Image::default())),
+            FramesReceived::default(),
+            StreamStartedAt(Instant::now()),
+            decode_errors.clone(),
+        ));
+
+        let errors = errors.0.clone();
+        thread::Builder::new()
+            .name("Playback Thread".to_owned())
+            .spawn(move || {
+                let handle = Arc::downgrade(&handle);
+                let mut images: Vec<Image> = Vec::new();
+
+                let mut mat = Mat::default();
+                // The open track, its index and the position of the last decoded frame in it
+                let mut open: Option<(usize, VideoCapture, Duration)> = None;
+                let mut shown: Option<(usize, Duration)> = None;
+                let mut last_seek: Option<Duration> = None;
+
+                // Loop until the VideoThread component is dropped
+                while handle.strong_count() > 0 {
+                    // Recording and decimation don't apply to playback, processors are ended
+                    // right away
+                    rx_rec.try_iter().for_each(drop);
+                    rx_dec.try_iter().for_each(drop);
+                    for proc in rx_proc.try_iter().flatten() {
+                        proc.end();
+                    }
+
+                    let position = Duration::from_micros(clock.0.load(Ordering::Relaxed));
+                    let Some(index) = tracks.iter().rposition(|it| it.started <= position) else {
+                        thread::sleep(IDLE_INTERVAL);
+                        continue;
+                    };
+                    let target = position - tracks[index].started;
+
+                    if open.as_ref().is_none_or(|(it, ..)| *it != index) {
+                        let src = VideoCapture::from_file(
+                            &tracks[index].path.to_string_lossy(),
+                            videoio::CAP_ANY,
+                        )
+                        .context("Open recording");
+
+                        match src {
+                            Ok(src) => {
+                                open = Some((index, src, Duration::ZERO));
+                                last_seek = None;
+                            }
+                            Err(err) => {
+                                let _ = errors.send(err);
+                                // Skip this recording instead of retrying it every frame
+                                tracks.remove(index);
+                                open = None;
+                                continue;
+                            }
+                        }
+                    }
+                    let Some((_, src, decoded)) = &mut open else {
+                        continue;
+                    };
+
+                    let seek = last_seek != Some(target)
+                        && (target + SEEK_TOLERANCE < *decoded
+                            || target > *decoded + MAX_DECODE_AHEAD);
+                    if seek {
+                        last_seek = Some(target);
+
+                        let res = src
+                            .set(videoio::CAP_PROP_POS_MSEC, target.as_secs_f64() * 1000.0)
+                            .context("Seek recording");
+                        if let Err(err) = res {
+                            let _ = errors.send(err);
+                        }
+                    }
+
+                    // Decode up to the frame shown at `target`
+                    let mut new_frame = false;
+                    while seek || *decoded < target {
+                        match src.read(&mut mat).context("Read recorded frame") {
+                            Ok(true) => {}
+                            // The recording ended before the clock
+                            Ok(false) => break,
+                            Err(err) => {
+                                decode_errors.0.fetch_add(1, Ordering::Relaxed);
+                                let _ = errors.send(err);
+                                break;
+                            }
+                        }
+
+                        let msec = src.get(videoio::CAP_PROP_POS_MSEC).unwrap_or_default();
+                        *decoded = Duration::try_from_secs_f64(msec / 1000.0).unwrap_or(target);
+                        new_frame = true;
+
+                        if seek {
+                            break;
+                        }
+                    }
+
+                    if !new_frame || shown == Some((index, *decoded)) {
+                        thread::sleep(IDLE_INTERVAL);
+                        continue;
+                    }
+                    shown = Some((index, *decoded));
+
+                    images.extend(rx_bevy.try_iter());
+                    images.truncate(15);
+                    let mut image = images.pop().unwrap_or_default();
+
+                    let res = mat_to_image(&mat, &mut image).context("Mat to image");
+                    if let Err(err) = res {
+                        decode_errors.0.fetch_add(1, Ordering::Relaxed);
+                        let _ = errors.send(err);
+                        continue;
+                    }
+                    // Uploaded by `upload::FrameUploadPlugin` instead of bevy
+                    image.asset_usage = RenderAssetUsages::MAIN_WORLD;
+
+                    let _ = tx_cv.send((image, Instant::now()));
+                }
+            })
+            .context("Spawn thread")?;
+    }
+
+    Ok(())
+}
+
+fn sync_playback_clock(cameras: Query<&PlaybackClock>, replay: Res<TelemetryReplay>) {
+    let position = replay.position.as_micros() as u64;
+
+    for clock in &cameras {
+        clock.0.store(position, Ordering::Relaxed);
+    }
+}
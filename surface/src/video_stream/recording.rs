@@ -0,0 +1,703 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+use common::{
+    components::{
+        Armed, CameraDefinition, CurrentDraw, DepthMeasurement, DepthTarget, MeasuredVoltage,
+        Orientation, Robot,
+    },
+    ecs_sync::{SerializedChange, SerializedChangeInEvent},
+    error::ErrorEvent,
+    sync::{Peers, REPLAY_TOKEN},
+};
+use opencv::{
+    core::{Point, Rect, Scalar, Size},
+    imgproc,
+    prelude::*,
+    videoio::{self, VideoWriter},
+};
+use serde::{Deserialize, Serialize};
+use sysinfo::{DiskExt, System, SystemExt};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use super::VideoThread;
+use crate::{
+    replay::{RemoteState, TelemetryEntry, TelemetryRecord, TELEMETRY_LOG_FILE},
+    session::{artifact_directory, ActiveSession},
+    settings::config_path,
+    wall_clock::now,
+};
+
+pub const RECORDING_DIRECTORY: &str = "recordings";
+pub const RECORDING_OVERLAYS_FILE: &str = "recording_overlays.toml";
+
+/// Recording stops when the disk has less free space than this
+const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+/// The UI warns when the disk has less free space than this
+pub const LOW_FREE_SPACE: u64 = 10 * 1024 * 1024 * 1024;
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Telemetry is logged at 10hz alongside the video for syncing
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the burned in telemetry is refreshed, the timestamp is redrawn on every frame
+const OVERLAY_INTERVAL: Duration = Duration::from_millis(100);
+/// Burned in text is scaled from this size at 720p
+const OVERLAY_FONT_SCALE: f64 = 0.8;
+
+/// Used when the stream does not report a frame rate
+const DEFAULT_FPS: f64 = 30.0;
+
+/// Gstreamer H.264 encoders in order of preference, hardware encoders first
+const ENCODERS: &[&str] = &[
+    "nvh264enc",
+    "vaapih264enc",
+    "v4l2h264enc",
+    "x264enc tune=zerolatency speed-preset=veryfast",
+];
+
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SetRecording>()
+            .add_systems(PreStartup, load_overlays)
+            .add_systems(
+                Update,
+                (
+                    record_changes.before(handle_set_recording),
+                    handle_set_recording,
+                    record_telemetry.after(handle_set_recording),
+                    monitor_disk_space.after(handle_set_recording),
+                    end_session.after(monitor_disk_space),
+                    update_burn_in,
+                ),
+            )
+            .add_systems(Last, write_overlays);
+    }
+}
+
+/// Starts or stops recording a camera, `None` applies to every camera
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SetRecording {
+    pub camera: Option<Entity>,
+    pub record: bool,
+}
+
+/// Marks a camera that is being written to disk
+#[derive(Component, Debug, Clone)]
+pub struct Recording {
+    pub path: PathBuf,
+    pub started: Instant,
+}
+
+/// Commands sent to the video thread
+pub enum RecordingCommand {
+    Start(PathBuf),
+    Stop,
+    /// Text to burn into recorded frames, `None` records the stream untouched
+    Overlay(Option<BurnIn>),
+}
+
+/// Cameras, by name, whose recordings have the time and the robot's telemetry burned in
+///
+/// Only the file on disk is annotated, the live preview is left as is.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RecordingOverlays {
+    pub cameras: BTreeSet<String>,
+}
+
+/// Text burned into the top left of recorded frames, below the robot's name and the wall clock
+/// time the frame was written
+#[derive(Debug, Clone, Default)]
+pub struct BurnIn {
+    pub robot: String,
+    pub telemetry: String,
+}
+
+impl BurnIn {
+    fn draw(&self, frame: &mut Mat) -> anyhow::Result<()> {
+        let time = now();
+        let timestamp = format!(
+            "{} {:02}:{:02}:{:02}.{:03}",
+            time.date(),
+            time.hour(),
+            time.minute(),
+            time.second(),
+            time.millisecond()
+        );
+
+        // BGR
+        let background = Scalar::new(0.0, 0.0, 0.0, 0.0);
+        let foreground = Scalar::new(255.0, 255.0, 255.0, 0.0);
+
+        // Keeps the text the same fraction of the frame at any resolution
+        let scale = OVERLAY_FONT_SCALE * frame.rows() as f64 / 720.0;
+        let thickness = (scale * 2.0).round().max(1.0) as i32;
+
+        let mut top = 0;
+        for line in [
+            format!("{}  {timestamp}", self.robot),
+            self.telemetry.clone(),
+        ] {
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut baseline = 0;
+            let size = imgproc::get_text_size(
+                &line,
+                imgproc::FONT_HERSHEY_SIMPLEX,
+                scale,
+                thickness,
+                &mut baseline,
+            )?;
+            let margin = size.height / 2;
+            let height = size.height + baseline + margin * 2;
+
+            imgproc::rectangle(
+                frame,
+                Rect::new(0, top, size.width + margin * 2, height),
+                background,
+                imgproc::FILLED,
+                imgproc::LINE_8,
+                0,
+            )?;
+            imgproc::put_text(
+                frame,
+                &line,
+                Point::new(margin, top + margin + size.height),
+                imgproc::FONT_HERSHEY_SIMPLEX,
+                scale,
+                foreground,
+                thickness,
+                imgproc::LINE_AA,
+                false,
+            )?;
+
+            top += height;
+        }
+
+        Ok(())
+    }
+}
+
+/// The folder that the current recordings are written into, exists while any camera is recording
+#[derive(Resource)]
+pub struct RecordingSession {
+    pub folder: PathBuf,
+    pub started: Instant,
+    /// Free space on the disk holding `folder`
+    pub available_space: Option<u64>,
+
+    telemetry: BufWriter<File>,
+    /// Every replicated change, for replaying the session
+    telemetry_log: BufWriter<File>,
+    notes: BufWriter<File>,
+    last_telemetry: Option<Instant>,
+    last_disk_check: Option<Instant>,
+}
+
+impl RecordingSession {
+    fn new(directory: &Path, state: &RemoteState) -> anyhow::Result<Self> {
+        let started_at = now();
+        let name = started_at
+            .format(&Iso8601::DATE_TIME)
+            .context("Format time")?;
+        let folder = directory.join(format!("session_{name}"));
+
+        fs::create_dir_all(&folder).context("Create session folder")?;
+
+        let mut telemetry = BufWriter::new(
+            File::create(folder.join("telemetry.csv")).context("Create telemetry log")?,
+        );
+        writeln!(
+            telemetry,
+            "elapsed_s,unix_time_ms,armed,depth_m,depth_target_m,yaw_deg,pitch_deg,roll_deg,voltage_v,current_a"
+        )
+        .context("Write telemetry header")?;
+
+        let mut notes =
+            BufWriter::new(File::create(folder.join("session.txt")).context("Create notes")?);
+        writeln!(notes, "Session started {name}").context("Write notes")?;
+
+        let telemetry_log = BufWriter::new(
+            File::create(folder.join(TELEMETRY_LOG_FILE)).context("Create telemetry log")?,
+        );
+
+        info!("Started recording session in {folder:?}");
+
+        let mut session = Self {
+            folder,
+            started: Instant::now(),
+            available_space: None,
+            telemetry,
+            telemetry_log,
+            notes,
+            last_telemetry: None,
+            last_disk_check: None,
+        };
+
+        // Replays start from the state when the session started
+        for change in state.changes() {
+            session.log(TelemetryEntry::Change(change))?;
+        }
+
+        Ok(session)
+    }
+
+    fn log(&mut self, entry: TelemetryEntry) -> anyhow::Result<()> {
+        let record = TelemetryRecord {
+            elapsed: self.started.elapsed(),
+            entry,
+        };
+
+        bincode::serialize_into(&mut self.telemetry_log, &record).context("Write telemetry log")
+    }
+
+    fn note(&mut self, note: &str) {
+        let elapsed = self.started.elapsed().as_secs_f32();
+        let _ = writeln!(self.notes, "+{elapsed:.3}s {note}");
+        let _ = self.notes.flush();
+    }
+}
+
+/// Writes frames from a video thread to disk
+pub struct Recorder {
+    writer: VideoWriter,
+}
+
+impl Recorder {
+    /// Encodes with the first available encoder from `ENCODERS` into a matroska file, which stays
+    /// readable if the control station exits without finishing the file
+    pub fn open(path: &Path, size: Size, fps: f64) -> anyhow::Result<Self> {
+        let fps = if fps > 0.0 { fps } else { DEFAULT_FPS };
+
+        for encoder in ENCODERS {
+            let pipeline = format!(
+                "appsrc ! videoconvert ! {encoder} ! h264parse ! matroskamux ! filesink location=\"{}\"",
+                path.display()
+            );
+
+            let writer = VideoWriter::new_with_backend(
+                &pipeline,
+                videoio::CAP_GSTREAMER,
+                0,
+                fps,
+                size,
+                true,
+            );
+
+            if let Ok(writer) = writer {
+                if writer.is_opened().unwrap_or(false) {
+                    info!("Recording to {path:?} with {encoder}");
+                    return Ok(Self { writer });
+                }
+            }
+        }
+
+        bail!("No usable video encoder for {path:?}");
+    }
+
+    pub fn write(&mut self, frame: &Mat) -> anyhow::Result<()> {
+        self.writer.write(frame).context("Write video frame")
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.writer.release();
+    }
+}
+
+/// The state of recording on the video thread
+#[derive(Default)]
+pub struct ThreadRecording {
+    state: RecorderState,
+    overlay: Option<BurnIn>,
+    /// Reused for the burned in copy of each frame
+    annotated: Mat,
+}
+
+#[derive(Default)]
+enum RecorderState {
+    #[default]
+    Stopped,
+    /// Waiting for a frame to learn the resolution
+    Pending(PathBuf),
+    Running(Recorder),
+}
+
+impl ThreadRecording {
+    pub fn handle(&mut self, command: RecordingCommand) {
+        match command {
+            RecordingCommand::Start(path) => self.state = RecorderState::Pending(path),
+            RecordingCommand::Stop => self.state = RecorderState::Stopped,
+            RecordingCommand::Overlay(overlay) => self.overlay = overlay,
+        }
+    }
+
+    /// If frames are being written or will be once the next one arrives
+    pub fn is_active(&self) -> bool {
+        !matches!(self.state, RecorderState::Stopped)
+    }
+
+    pub fn write(&mut self, frame: &Mat, fps: f64) -> anyhow::Result<()> {
+        if let RecorderState::Pending(path) = &self.state {
+            let size = frame.size().context("Get size")?;
+            match Recorder::open(path, size, fps) {
+                Ok(recorder) => self.state = RecorderState::Running(recorder),
+                Err(err) => {
+                    self.state = RecorderState::Stopped;
+                    return Err(err);
+                }
+            }
+        }
+
+        if let RecorderState::Running(recorder) = &mut self.state {
+            let res: anyhow::Result<()> = try {
+                // The frame is shared with the live preview, only a copy is annotated
+                let frame = match &self.overlay {
+                    Some(overlay) => {
+                        frame.copy_to(&mut self.annotated).context("Copy frame")?;
+                        overlay.draw(&mut self.annotated).context("Burn in")?;
+
+                        &self.annotated
+                    }
+                    None => frame,
+                };
+
+                recorder.write(frame)?;
+            };
+
+            if let Err(err) = res {
+                self.state = RecorderState::Stopped;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_overlays(mut cmds: Commands) {
+    let res: anyhow::Result<RecordingOverlays> = try {
+        let overlays = fs::read_to_string(config_path(RECORDING_OVERLAYS_FILE))
+            .context("Read recording overlays")?;
+        toml::from_str(&overlays).context("Parse recording overlays")?
+    };
+
+    let overlays = match res {
+        Ok(overlays) => overlays,
+        Err(err) => {
+            warn!("No recording overlays: {err:?}");
+            RecordingOverlays::default()
+        }
+    };
+
+    cmds.insert_resource(overlays);
+}
+
+fn write_overlays(overlays: Res<RecordingOverlays>) {
+    if !overlays.is_changed() || overlays.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*overlays) else {
+        error!("Could not serialize recording overlays");
+        return;
+    };
+
+    let res = fs::write(config_path(RECORDING_OVERLAYS_FILE), &str);
+    if let Err(err) = res {
+        error!("Could not write recording overlays: {err:?}");
+    }
+}
+
+fn handle_set_recording(
+    mut cmds: Commands,
+    mut events: EventReader<SetRecording>,
+    mut session: Option<ResMut<RecordingSession>>,
+    cameras: Query<(Entity, &Name, &VideoThread, Option<&Recording>), With<CameraDefinition>>,
+    dive_session: Option<Res<ActiveSession>>,
+    remote_state: Res<RemoteState>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let mut new_session = None;
+    // Recordings go in the active dive session if there is one
+    let directory = artifact_directory(dive_session.as_deref(), RECORDING_DIRECTORY);
+
+    for &SetRecording { camera, record } in events.read() {
+        let targets = cameras
+            .iter()
+            .filter(|(entity, ..)| camera.is_none_or(|it| it == *entity))
+            .filter(|(.., recording)| recording.is_some() != record);
+
+        for (entity, name, thread, _) in targets {
+            if !record {
+                let _ = thread.4.send(RecordingCommand::Stop);
+                cmds.entity(entity).remove::<Recording>();
+
+                if let Some(session) = session.as_mut() {
+                    session.note(&format!("{name} stopped"));
+                }
+
+                continue;
+            }
+
+            let session = match (session.as_deref_mut(), &mut new_session) {
+                (Some(session), _) => session,
+                (None, Some(session)) => session,
+                (None, new_session @ None) => {
+                    match RecordingSession::new(&directory, &remote_state) {
+                        Ok(session) => new_session.insert(session),
+                        Err(err) => {
+                            errors.send(err.context("Start recording session").into());
+                            return;
+                        }
+                    }
+                }
+            };
+
+            if session
+                .available_space
+                .is_some_and(|it| it < MIN_FREE_SPACE)
+            {
+                errors.send(anyhow::anyhow!("Not enough disk space to record").into());
+                return;
+            }
+
+            let file_name = name.as_str().replace(['/', '\\', ' '], "_");
+            let elapsed = session.started.elapsed().as_secs();
+            let path = session.folder.join(format!("{file_name}_{elapsed}.mkv"));
+
+            if thread
+                .4
+                .send(RecordingCommand::Start(path.clone()))
+                .is_err()
+            {
+                errors.send(anyhow::anyhow!("Could not start recording {name}").into());
+                continue;
+            }
+
+            session.note(&format!("{name} started -> {}", path.display()));
+            let res = session.log(TelemetryEntry::VideoStarted {
+                camera: name.to_string(),
+                file: format!("{file_name}_{elapsed}.mkv"),
+            });
+            if let Err(err) = res {
+                errors.send(err.into());
+            }
+
+            cmds.entity(entity).insert(Recording {
+                path,
+                started: Instant::now(),
+            });
+        }
+    }
+
+    if let Some(session) = new_session {
+        cmds.insert_resource(session);
+    }
+}
+
+/// Logs the changes replicated from the robot, events aren't replayed so they are left out
+fn record_changes(
+    session: Option<ResMut<RecordingSession>>,
+    mut changes: EventReader<SerializedChangeInEvent>,
+    peers: Res<Peers>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    // Changes from before the session are already in its snapshot
+    let Some(mut session) = session else {
+        changes.clear();
+        return;
+    };
+
+    for SerializedChangeInEvent(change, token) in changes.read() {
+        if *token == REPLAY_TOKEN
+            || !peers.is_valid(*token)
+            || matches!(change, SerializedChange::EventEmitted(..))
+        {
+            continue;
+        }
+
+        if let Err(err) = session.log(TelemetryEntry::Change(change.clone())) {
+            errors.send(err.into());
+            return;
+        }
+    }
+}
+
+fn record_telemetry(
+    session: Option<ResMut<RecordingSession>>,
+    // TODO(low): Support multiple robots
+    robot: Query<
+        (
+            &Armed,
+            (Option<&DepthMeasurement>, Option<&DepthTarget>),
+            Option<&Orientation>,
+            (Option<&MeasuredVoltage>, Option<&CurrentDraw>),
+        ),
+        With<Robot>,
+    >,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+
+    if session
+        .last_telemetry
+        .is_some_and(|it| it.elapsed() < TELEMETRY_INTERVAL)
+    {
+        return;
+    }
+    session.last_telemetry = Some(Instant::now());
+
+    let Ok((armed, (depth, depth_target), orientation, (voltage, current))) = robot.get_single()
+    else {
+        return;
+    };
+
+    let elapsed = session.started.elapsed().as_secs_f64();
+    let unix_ms = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+    let (yaw, pitch, roll) = orientation
+        .map(|it| it.yaw_pitch_roll())
+        .map(|(yaw, pitch, roll)| (Some(yaw), Some(pitch), Some(roll)))
+        .unwrap_or_default();
+
+    let opt = |it: Option<f32>| it.map(|it| format!("{it:.3}")).unwrap_or_default();
+
+    let res = writeln!(
+        session.telemetry,
+        "{elapsed:.3},{unix_ms},{},{},{},{},{},{},{},{}",
+        *armed == Armed::Armed,
+        opt(depth.map(|it| it.depth.0)),
+        opt(depth_target.map(|it| it.0 .0)),
+        opt(yaw),
+        opt(pitch),
+        opt(roll),
+        opt(voltage.map(|it| it.0 .0)),
+        opt(current.map(|it| it.0 .0)),
+    );
+
+    if let Err(err) = res {
+        errors.send(anyhow::Error::from(err).context("Write telemetry").into());
+    }
+}
+
+/// Sends the burned in text to every camera, including ones not recording yet so their first frame
+/// is already annotated
+fn update_burn_in(
+    mut last_update: Local<Option<Instant>>,
+    cameras: Query<(&Name, &VideoThread), With<CameraDefinition>>,
+    // TODO(low): Support multiple robots
+    robot: Query<(&Name, Option<&DepthMeasurement>, Option<&Orientation>), With<Robot>>,
+    overlays: Res<RecordingOverlays>,
+) {
+    if !overlays.is_changed() && last_update.is_some_and(|it| it.elapsed() < OVERLAY_INTERVAL) {
+        return;
+    }
+    *last_update = Some(Instant::now());
+
+    let burn_in = match robot.get_single() {
+        Ok((name, depth, orientation)) => {
+            let depth = depth
+                .map(|it| format!("{:.2}m", it.depth.0))
+                .unwrap_or_else(|| "--".to_owned());
+            let heading = orientation
+                .map(|it| format!("{:03.0}", it.heading().0 .0))
+                .unwrap_or_else(|| "--".to_owned());
+
+            BurnIn {
+                robot: name.to_string(),
+                telemetry: format!("Depth {depth}  Heading {heading}"),
+            }
+        }
+        Err(_) => BurnIn::default(),
+    };
+
+    for (name, thread) in &cameras {
+        let overlay = overlays
+            .cameras
+            .contains(name.as_str())
+            .then(|| burn_in.clone());
+
+        // The next update retries if the thread is behind
+        let _ = thread.4.try_send(RecordingCommand::Overlay(overlay));
+    }
+}
+
+/// Stops every recording when the disk is almost full
+fn monitor_disk_space(
+    session: Option<ResMut<RecordingSession>>,
+    mut system: Local<System>,
+    mut set_recording: EventWriter<SetRecording>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+
+    if session
+        .last_disk_check
+        .is_some_and(|it| it.elapsed() < DISK_CHECK_INTERVAL)
+    {
+        return;
+    }
+    session.last_disk_check = Some(Instant::now());
+
+    let Ok(folder) = session.folder.canonicalize() else {
+        return;
+    };
+
+    system.refresh_disks_list();
+    let available_space = system
+        .disks()
+        .iter()
+        .filter(|it| folder.starts_with(it.mount_point()))
+        .max_by_key(|it| it.mount_point().as_os_str().len())
+        .map(|it| it.available_space());
+
+    if available_space.is_some_and(|it| it < MIN_FREE_SPACE) {
+        errors.send(anyhow::anyhow!("Disk almost full, stopping recordings").into());
+        set_recording.send(SetRecording {
+            camera: None,
+            record: false,
+        });
+    }
+
+    session.available_space = available_space;
+}
+
+/// Closes the session once nothing is recording
+fn end_session(
+    mut cmds: Commands,
+    session: Option<ResMut<RecordingSession>>,
+    recordings: Query<(), With<Recording>>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+
+    // Sessions are inserted with commands, give the first recording a chance to be inserted too
+    if session.is_added() || !recordings.is_empty() {
+        return;
+    }
+
+    session.note("Session ended");
+    let _ = session.telemetry.flush();
+    let _ = session.telemetry_log.flush();
+
+    info!("Ended recording session in {:?}", session.folder);
+    cmds.remove_resource::<RecordingSession>();
+}
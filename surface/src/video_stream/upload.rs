@@ -0,0 +1,95 @@
+//! Writes decoded camera frames straight into their existing GPU textures
+//!
+//! Bevy clones every modified image into the render world and creates a new texture for it, for a
+//! few 1080p streams that is most of the frame time. Camera frames are kept in the main world only
+//! and copied into the texture through the render queue's staging buffer during extract instead.
+//! A new texture is only created when a stream changes resolution.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{ImageDataLayout, TextureDataOrder},
+        renderer::{RenderDevice, RenderQueue},
+        texture::{DefaultImageSampler, GpuImage},
+        Extract, ExtractSchedule, RenderApp,
+    },
+};
+
+pub struct FrameUploadPlugin;
+
+impl Plugin for FrameUploadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameUploads>();
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.add_systems(ExtractSchedule, upload_frames);
+    }
+}
+
+/// Camera images that got a new frame this update
+///
+/// These images must only have `RenderAssetUsages::MAIN_WORLD` set so bevy doesn't also upload
+/// them.
+#[derive(Resource, Debug, Default)]
+pub struct FrameUploads(pub Vec<AssetId<Image>>);
+
+fn upload_frames(
+    uploads: Extract<Res<FrameUploads>>,
+    images: Extract<Res<Assets<Image>>>,
+    mut gpu_images: ResMut<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    default_sampler: Res<DefaultImageSampler>,
+) {
+    for &id in &uploads.0 {
+        let Some(image) = images.get(id) else {
+            continue;
+        };
+
+        let descriptor = &image.texture_descriptor;
+        let reusable = gpu_images
+            .get(id)
+            .filter(|it| it.size == image.size() && it.texture_format == descriptor.format);
+
+        if let Some(gpu_image) = reusable {
+            render_queue.write_texture(
+                gpu_image.texture.as_image_copy(),
+                &image.data,
+                ImageDataLayout {
+                    offset: 0,
+                    // Frames from `mat_to_image` are always RGBA8
+                    bytes_per_row: Some(descriptor.size.width * 4),
+                    rows_per_image: None,
+                },
+                descriptor.size,
+            );
+
+            continue;
+        }
+
+        let texture = render_device.create_texture_with_data(
+            &render_queue,
+            descriptor,
+            TextureDataOrder::default(),
+            &image.data,
+        );
+        let texture_view =
+            texture.create_view(image.texture_view_descriptor.as_ref().unwrap_or(&default()));
+
+        gpu_images.insert(
+            id,
+            GpuImage {
+                texture,
+                texture_view,
+                texture_format: descriptor.format,
+                sampler: (**default_sampler).clone(),
+                size: image.size(),
+                mip_level_count: descriptor.mip_level_count,
+            },
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use ahash::HashMap;
+use bevy::prelude::*;
+use common::{
+    components::{CameraCapabilities, CameraDefinition, Robot, RobotId},
+    events::RecoverCamera,
+};
+
+use super::{DecodeErrors, FrameDecodedAt, StreamStartedAt};
+
+/// How often the streams are checked
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+/// Frame age after which a stream is shown as stale
+const STALE_AFTER: Duration = Duration::from_secs(2);
+/// Frame age after which the robot is asked to recover the camera
+const RECOVER_AFTER: Duration = Duration::from_secs(5);
+/// Time the robot gets to bring a camera back before it is asked again
+const RECOVERY_RETRY: Duration = Duration::from_secs(20);
+/// Decode errors within one check that mean the stream is garbage even if frames still arrive
+const MAX_DECODE_ERRORS: u64 = 30;
+
+pub struct StreamWatchdogPlugin;
+
+impl Plugin for StreamWatchdogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (watch_streams, aggregate_health).chain());
+    }
+}
+
+/// How a camera's stream is doing, kept up to date by the watchdog
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct StreamHealth {
+    pub state: StreamState,
+    /// Time since the last decoded frame, or since the stream was started if none arrived yet
+    pub frame_age: Duration,
+    /// Frames the video thread failed to read since the stream was started
+    pub decode_errors: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamState {
+    Healthy,
+    /// No frames have arrived for a while, the display is frozen on the last frame
+    Stale,
+    /// The robot was asked to reset the camera and the new stream hasn't delivered a frame yet
+    Recovering,
+}
+
+impl StreamState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamState::Healthy => "Healthy",
+            StreamState::Stale => "Stale",
+            StreamState::Recovering => "Recovering",
+        }
+    }
+}
+
+/// Summary of the `StreamHealth` of every camera on a robot
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CameraHealth {
+    pub cameras: u32,
+    pub stale: u32,
+    pub recovering: u32,
+}
+
+impl CameraHealth {
+    pub fn healthy(&self) -> u32 {
+        self.cameras - self.stale - self.recovering
+    }
+}
+
+/// Watchdog state that outlives the camera's video thread
+#[derive(Default)]
+struct Watchdog {
+    last_recovery: Option<Instant>,
+    last_decode_errors: u64,
+}
+
+fn watch_streams(
+    mut cmds: Commands,
+    mut timer: Local<Option<Timer>>,
+    mut watchdogs: Local<HashMap<Entity, Watchdog>>,
+    cameras: Query<
+        (
+            Entity,
+            &Name,
+            &StreamStartedAt,
+            &DecodeErrors,
+            Option<&FrameDecodedAt>,
+            Option<&CameraCapabilities>,
+        ),
+        With<CameraDefinition>,
+    >,
+    mut recover: EventWriter<RecoverCamera>,
+    time: Res<Time<Real>>,
+) {
+    let timer = timer.get_or_insert_with(|| Timer::new(CHECK_INTERVAL, TimerMode::Repeating));
+    timer.tick(time.delta());
+
+    if !timer.just_finished() {
+        return;
+    }
+
+    let now = Instant::now();
+
+    watchdogs.retain(|entity, _| cameras.contains(*entity));
+
+    for (entity, name, started, errors, decoded, capabilities) in &cameras {
+        let watchdog = watchdogs.entry(entity).or_default();
+
+        // The frame may be left over from before the stream was restarted
+        let last_frame = decoded.map(|it| it.0).filter(|it| *it >= started.0);
+        let frame_age = now - last_frame.unwrap_or(started.0);
+
+        // The counter restarts along with the stream
+        let decode_errors = errors.0.load(Ordering::Relaxed);
+        let new_errors = decode_errors.saturating_sub(watchdog.last_decode_errors);
+        watchdog.last_decode_errors = decode_errors;
+
+        let recovering = watchdog
+            .last_recovery
+            .is_some_and(|it| now - it < RECOVERY_RETRY);
+        let corrupt = new_errors > MAX_DECODE_ERRORS;
+        let fresh = last_frame.is_some() && frame_age < STALE_AFTER && !corrupt;
+
+        let state = if (frame_age >= RECOVER_AFTER || corrupt) && !recovering {
+            match capabilities {
+                Some(capabilities) => {
+                    warn!("Stream of {name} stalled, asking the robot to recover it");
+
+                    recover.send(RecoverCamera {
+                        device: capabilities.device.clone(),
+                    });
+                    watchdog.last_recovery = Some(now);
+
+                    StreamState::Recovering
+                }
+                None => StreamState::Stale,
+            }
+        } else if recovering && !fresh {
+            StreamState::Recovering
+        } else if frame_age >= STALE_AFTER || corrupt {
+            StreamState::Stale
+        } else {
+            if recovering {
+                info!("Stream of {name} recovered");
+                watchdog.last_recovery = None;
+            }
+
+            StreamState::Healthy
+        };
+
+        cmds.entity(entity).insert(StreamHealth {
+            state,
+            frame_age,
+            decode_errors,
+        });
+    }
+}
+
+fn aggregate_health(
+    mut cmds: Commands,
+    robots: Query<(Entity, &RobotId, Option<&CameraHealth>), With<Robot>>,
+    cameras: Query<(&RobotId, &StreamHealth), With<CameraDefinition>>,
+) {
+    for (robot, robot_id, health) in &robots {
+        let mut new_health = CameraHealth::default();
+
+        for (_, stream) in cameras.iter().filter(|(it, _)| *it == robot_id) {
+            new_health.cameras += 1;
+
+            match stream.state {
+                StreamState::Healthy => {}
+                StreamState::Stale => new_health.stale += 1,
+                StreamState::Recovering => new_health.recovering += 1,
+            }
+        }
+
+        if health != Some(&new_health) {
+            cmds.entity(robot).insert(new_health);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Adds the cameras and the tags they see to the rerun stream from `common::visualization`
+
+use bevy::prelude::*;
+use common::{
+    components::{CameraDefinition, CameraInputRotation},
+    visualization::{transform, Rerun, RerunPlugin},
+};
+use rerun::Points3D;
+
+use crate::video_pipelines::tags::DetectedTag;
+
+pub struct VisualizationPlugin;
+
+impl Plugin for VisualizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RerunPlugin {
+            app_id: "control_station",
+        })
+        .add_systems(
+            PostUpdate,
+            (log_cameras, log_tags).run_if(resource_exists::<Rerun>),
+        );
+    }
+}
+
+fn camera_path(name: &Name) -> String {
+    format!(
+        "robot/cameras/{}",
+        name.as_str().replace(['/', '\\', ' '], "_")
+    )
+}
+
+fn log_cameras(
+    rerun: Res<Rerun>,
+    cameras: Query<(&Name, &CameraInputRotation), With<CameraDefinition>>,
+) {
+    // The mounting offsets aren't known, so cameras sit at the robot's origin
+    for (name, rotation) in &cameras {
+        rerun.log(camera_path(name), &transform(Vec3A::ZERO, rotation.0));
+    }
+}
+
+fn log_tags(
+    rerun: Res<Rerun>,
+    cameras: Query<(Entity, &Name), With<CameraDefinition>>,
+    tags: Query<&DetectedTag>,
+) {
+    for (camera, name) in &cameras {
+        let (positions, labels): (Vec<_>, Vec<_>) = tags
+            .iter()
+            .filter(|it| it.camera == camera)
+            .map(|tag| {
+                // From OpenCV's x right, y down, z forward to the robot's x right, y forward, z up
+                let position = tag.transform.translation;
+                let position = [position.x, position.z, -position.y];
+
+                (position, format!("Tag {}", tag.id))
+            })
+            .unzip();
+
+        // Logged even when empty so tags that went out of view disappear
+        rerun.log(
+            format!("{}/tags", camera_path(name)),
+            &Points3D::new(positions).with_labels(labels),
+        );
+    }
+}
@@ -0,0 +1,12 @@
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+/// The current local time, falls back to UTC if the local offset is unknown
+pub fn now() -> OffsetDateTime {
+    OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc())
+}
+
+/// Formats a timestamp for logs and exported reports
+pub fn format_time(time: OffsetDateTime) -> String {
+    time.format(&Iso8601::DATE_TIME)
+        .unwrap_or_else(|_| "Unknown".to_owned())
+}
@@ -0,0 +1,102 @@
+#!/usr/bin/env python3
+"""Renders the synthetic sample frames used by the video pipeline tests.
+
+Every frame is rendered through the pinhole + radial distortion model in golden.toml, so the
+expected results of the pipelines are known exactly. Only needs the python standard library.
+"""
+
+import math
+import struct
+import zlib
+
+WIDTH, HEIGHT = 640, 480
+FX, FY, CX, CY = 500.0, 500.0, 320.0, 240.0
+K1, K2 = -0.25, 0.08
+SUPERSAMPLE = 3
+
+
+def undistort(u, v):
+    """Maps a distorted pixel to its normalized undistorted coordinates."""
+    xd, yd = (u - CX) / FX, (v - CY) / FY
+    x, y = xd, yd
+    for _ in range(20):
+        r2 = x * x + y * y
+        factor = 1.0 + K1 * r2 + K2 * r2 * r2
+        x, y = xd / factor, yd / factor
+    return x, y
+
+
+def render(shade):
+    """shade(x, y) takes normalized undistorted coordinates and returns an rgb tuple."""
+    rows = []
+    for v in range(HEIGHT):
+        row = bytearray()
+        for u in range(WIDTH):
+            acc = [0.0, 0.0, 0.0]
+            for sv in range(SUPERSAMPLE):
+                for su in range(SUPERSAMPLE):
+                    x, y = undistort(
+                        u + (su + 0.5) / SUPERSAMPLE - 0.5, v + (sv + 0.5) / SUPERSAMPLE - 0.5
+                    )
+                    color = shade(x, y)
+                    for i in range(3):
+                        acc[i] += color[i]
+            row.extend(round(c / SUPERSAMPLE**2) for c in acc)
+        rows.append(bytes(row))
+    return rows
+
+
+def write_png(path, rows):
+    raw = b"".join(b"\x00" + row for row in rows)
+
+    def chunk(kind, data):
+        body = kind + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+    with open(path, "wb") as f:
+        f.write(b"\x89PNG\r\n\x1a\n")
+        f.write(chunk(b"IHDR", struct.pack(">IIBBBBB", WIDTH, HEIGHT, 8, 2, 0, 0, 0)))
+        f.write(chunk(b"IDAT", zlib.compress(raw, 9)))
+        f.write(chunk(b"IEND", b""))
+
+
+def grid(x, y):
+    """A 10x7 square chessboard (9x6 inner corners) of 48px squares, centered in the frame."""
+    u, v = x * FX + CX, y * FY + CY
+    left, top = CX - 5 * 48, CY - 3.5 * 48
+    col, row = math.floor((u - left) / 48), math.floor((v - top) / 48)
+    if 0 <= col < 10 and 0 <= row < 7 and (col + row) % 2 == 0:
+        return (0, 0, 0)
+    return (255, 255, 255)
+
+
+# A 0.47m x 0.9m red rectangle on a plane tilted 40 degrees away from the camera, the bottom edge
+# is the known width and the right edge is the length to measure
+SHIP_WIDTH, SHIP_LENGTH, TILT = 0.47, 0.9, math.radians(40)
+ORIGIN = (-SHIP_WIDTH / 2, 0.35, 1.5)
+AXIS_U = (1.0, 0.0, 0.0)
+AXIS_V = (0.0, -math.cos(TILT), math.sin(TILT))
+
+
+def shipwreck(x, y):
+    # Intersect the ray (x, y, 1) with the plane origin + s * AXIS_U + t * AXIS_V
+    normal = (
+        AXIS_U[1] * AXIS_V[2] - AXIS_U[2] * AXIS_V[1],
+        AXIS_U[2] * AXIS_V[0] - AXIS_U[0] * AXIS_V[2],
+        AXIS_U[0] * AXIS_V[1] - AXIS_U[1] * AXIS_V[0],
+    )
+    ray = (x, y, 1.0)
+    denom = sum(n * r for n, r in zip(normal, ray))
+    if abs(denom) > 1e-9:
+        depth = sum(n * o for n, o in zip(normal, ORIGIN)) / denom
+        point = [r * depth - o for r, o in zip(ray, ORIGIN)]
+        s = sum(p * a for p, a in zip(point, AXIS_U))
+        t = sum(p * a for p, a in zip(point, AXIS_V))
+        if depth > 0 and 0 <= s <= SHIP_WIDTH and 0 <= t <= SHIP_LENGTH:
+            return (200, 30, 30)
+    return (40, 90, 110)
+
+
+if __name__ == "__main__":
+    write_png("undistort_grid.png", render(grid))
+    write_png("shipwreck.png", render(shipwreck))
@@ -0,0 +1,161 @@
+//! Carries out the actions of behavior trees run by this app
+//!
+//! Motion actions drive the robot through the trajectory follower the same way missions do, only
+//! one of them can have the robot at a time
+
+use bevy::{
+    app::{Plugin, Update},
+    math::{vec2, vec3a, Quat},
+    prelude::{App, Commands, Entity, Has, IntoSystemConfigs, Local, Query, ResMut, With, Without},
+};
+use common::{
+    behavior::{BehaviorConditions, BehaviorPlugin, TickBehaviors},
+    components::{Armed, BehaviorStatus, BehaviorTree, DepthTarget, OrientationTarget, Robot},
+    ecs_sync::ForignOwned,
+    types::{
+        behavior::{BehaviorAction, NodeState},
+        units::Meters,
+    },
+};
+
+use crate::{
+    keep_out::RouteStatus,
+    mission::heading_quat,
+    trajectory::{CurrentPose, Pose, SpeedLimit, TargetPose},
+};
+
+pub struct AutonomyPlugin;
+
+impl Plugin for AutonomyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(BehaviorPlugin).add_systems(
+            Update,
+            (
+                update_conditions.before(TickBehaviors),
+                drive_actions.after(TickBehaviors),
+            ),
+        );
+    }
+}
+
+fn update_conditions(
+    mut conditions: ResMut<BehaviorConditions>,
+    robot: Query<(&Armed, Has<CurrentPose>), With<Robot>>,
+) {
+    let (armed, positioned) = robot
+        .get_single()
+        .map(|(armed, positioned)| (*armed == Armed::Armed, positioned))
+        .unwrap_or_default();
+
+    conditions.set("armed", armed);
+    conditions.set("positioned", positioned);
+}
+
+fn drive_actions(
+    mut active: Local<Option<(Entity, usize)>>,
+
+    mut cmds: Commands,
+    mut trees: Query<(Entity, &BehaviorTree, &mut BehaviorStatus), Without<ForignOwned>>,
+    robot: Query<
+        (
+            Entity,
+            Option<&CurrentPose>,
+            Option<&RouteStatus>,
+            Has<TargetPose>,
+        ),
+        With<Robot>,
+    >,
+) {
+    let robot = robot.get_single().ok();
+
+    // Let go of the robot once the action driving it is done or was halted
+    if let Some((tree, node)) = *active {
+        let running = trees
+            .get(tree)
+            .is_ok_and(|(_, _, status)| *status.state(node) == NodeState::Running);
+
+        if !running {
+            if let Some((robot, ..)) = robot {
+                cmds.entity(robot).remove::<(TargetPose, SpeedLimit)>();
+            }
+            *active = None;
+        }
+    }
+
+    for (entity, tree, mut status) in &mut trees {
+        let running = status
+            .running_actions(tree)
+            .map(|(node, action)| (node, action.clone()))
+            .collect::<Vec<_>>();
+
+        for (node, action) in running {
+            match action {
+                BehaviorAction::GoToPose { .. } | BehaviorAction::Hold { .. } => {}
+                BehaviorAction::RunPipeline { .. } => {
+                    status.finish(node, Err("Pipelines only run on the surface".to_owned()));
+                    continue;
+                }
+                // Finished by the tree itself
+                BehaviorAction::WaitFor { .. } => continue,
+            }
+
+            let Some((robot, pose, route, has_target)) = robot else {
+                status.finish(node, Err("No robot".to_owned()));
+                continue;
+            };
+
+            if active.is_some_and(|it| it != (entity, node)) {
+                status.finish(node, Err("Another action is driving the robot".to_owned()));
+                continue;
+            }
+            let starting = active.is_none();
+            *active = Some((entity, node));
+
+            match action {
+                BehaviorAction::GoToPose {
+                    x,
+                    y,
+                    depth,
+                    heading,
+                    speed,
+                    acceptance_radius,
+                } => {
+                    if starting {
+                        let mut robot = cmds.entity(robot);
+                        robot.insert((
+                            TargetPose(Pose {
+                                position: vec3a(x, y, 0.0),
+                                rotation: Quat::IDENTITY,
+                            }),
+                            SpeedLimit(speed),
+                        ));
+                        if let Some(depth) = depth {
+                            robot.insert(DepthTarget(Meters(depth)));
+                        }
+                        if let Some(heading) = heading {
+                            robot.insert(OrientationTarget(heading_quat(heading)));
+                        }
+
+                        continue;
+                    }
+
+                    // Rejected routes also clear the target
+                    if let (Some(RouteStatus::Rejected(reason)), false) = (route, has_target) {
+                        status.finish(node, Err(reason.to_string()));
+                    } else if pose.is_some_and(|it| {
+                        it.0.position.truncate().distance(vec2(x, y)) <= acceptance_radius
+                    }) {
+                        status.finish(node, Ok(()));
+                    }
+                }
+                BehaviorAction::Hold { .. } => {
+                    // Timed by the tree, the robot's own stabilization holds it without a pose
+                    if let Some(pose) = pose.filter(|_| starting) {
+                        cmds.entity(robot).insert(TargetPose(pose.0));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! Calibration between the acoustic frame and the robot's own frame
+//!
+//! The robot's orientation is relative to wherever its IMU's yaw started while acoustic positions
+//! are relative to the topside, so the two frames are rotated from each other. The locator is also
+//! mounted away from the robot's center. Both are solved for from patterns the pilot drives:
+//!
+//! - A straight surge leg: the direction the track moves in acoustically is the direction the
+//!   robot faced, which gives the rotation between the frames
+//! - A spin in place: the locator traces a circle around the robot's center, which gives where the
+//!   locator is mounted
+
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    fs,
+};
+
+use anyhow::{bail, Context};
+use bevy::{
+    app::{Plugin, PreStartup, Update},
+    math::{dvec4, vec2, DMat4, DVec4, Quat, Vec2, Vec3A},
+    prelude::{App, Commands, EventReader, Query, Res, ResMut, Resource, With},
+};
+use bevy_egui::EguiContexts;
+use common::{
+    attitude,
+    components::{Orientation, Robot},
+};
+use egui::DragValue;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    waterlinked::WaterlinkedLocationEvent,
+    waterlinked_api::{wl_to_mate_coords, Location},
+};
+
+pub const CALIBRATION_FILE: &str = "calibration.toml";
+
+/// Fewest acoustic fixes a pattern needs to be solved
+pub const MIN_SAMPLES: usize = 10;
+/// Shortest surge leg in meters
+pub const MIN_LEG: f32 = 2.0;
+/// Largest heading change in radians allowed during the surge leg
+pub const MAX_HEADING_DRIFT: f32 = 10.0 * PI / 180.0;
+/// How far off a straight line the surge leg's track may wander, as the ratio of its width to
+/// its length
+pub const MAX_LEG_WIDTH: f32 = 0.15;
+/// Smallest range of headings in radians the spin has to cover
+pub const MIN_SPIN: f32 = PI;
+
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CalibrationState>()
+            .add_systems(PreStartup, load_calibration)
+            .add_systems(Update, (record_samples, calibration_ui, save_calibration));
+    }
+}
+
+/// Transform from the robot's frame into the acoustic frame, applied to every fix
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct FrameCalibration {
+    /// Radians counter clockwise the acoustic frame's yaw is from the robot's
+    pub rotation: f32,
+    /// Where the locator is mounted in meters, in the robot's frame
+    pub lever_arm: Vec2,
+}
+
+impl FrameCalibration {
+    /// The robot's orientation in the acoustic frame
+    pub fn orientation(&self, orientation: Quat) -> Quat {
+        Quat::from_rotation_z(self.rotation) * orientation
+    }
+
+    /// Position of the robot's center given an acoustic fix and the calibrated orientation
+    pub fn robot_position(&self, fix: Vec3A, orientation: Quat) -> Vec3A {
+        fix - orientation * Vec3A::from(self.lever_arm.extend(0.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationPattern {
+    Surge,
+    Spin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationSample {
+    /// Uncalibrated acoustic position
+    pub position: Vec2,
+    /// Uncalibrated yaw from the robot's orientation
+    pub yaw: f32,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub enum CalibrationState {
+    #[default]
+    Idle,
+    Recording(CalibrationPattern, Vec<CalibrationSample>),
+    Solved(CalibrationPattern),
+    Failed(String),
+}
+
+fn load_calibration(mut cmds: Commands) {
+    let res: anyhow::Result<FrameCalibration> = try {
+        let calibration = fs::read_to_string(CALIBRATION_FILE).context("Read calibration")?;
+        toml::from_str(&calibration).context("Parse calibration")?
+    };
+
+    let calibration = match res {
+        Ok(calibration) => calibration,
+        Err(err) => {
+            warn!("Using uncalibrated frames: {err:?}");
+            FrameCalibration::default()
+        }
+    };
+
+    cmds.insert_resource(calibration);
+}
+
+fn save_calibration(calibration: Res<FrameCalibration>) {
+    if !calibration.is_changed() || calibration.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*calibration) else {
+        error!("Could not serialize calibration");
+        return;
+    };
+
+    let res = fs::write(CALIBRATION_FILE, &str);
+    if let Err(err) = res {
+        error!("Could not write calibration: {err:?}");
+    }
+}
+
+fn record_samples(
+    mut state: ResMut<CalibrationState>,
+    robot: Query<&Orientation, With<Robot>>,
+    mut reader: EventReader<WaterlinkedLocationEvent>,
+) {
+    let CalibrationState::Recording(_, samples) = &mut *state else {
+        reader.clear();
+        return;
+    };
+    let Ok(orientation) = robot.get_single() else {
+        reader.clear();
+        return;
+    };
+
+    let yaw = attitude::yaw(orientation.0);
+
+    for event in reader.read() {
+        let Location {
+            position_valid,
+            x,
+            y,
+            z,
+            ..
+        } = event.0;
+        if !position_valid {
+            continue;
+        }
+
+        let (x, y, _) = wl_to_mate_coords(x, y, z);
+        samples.push(CalibrationSample {
+            position: vec2(x, y),
+            yaw,
+        });
+    }
+}
+
+/// Wraps an angle into -PI..=PI
+fn wrap(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+fn circular_mean(angles: impl Iterator<Item = f32>) -> f32 {
+    let sum = angles.map(|it| vec2(it.cos(), it.sin())).sum::<Vec2>();
+    sum.y.atan2(sum.x)
+}
+
+/// Solves for the rotation between the frames from a straight leg driven forward
+pub fn solve_surge(samples: &[CalibrationSample]) -> anyhow::Result<f32> {
+    if samples.len() < MIN_SAMPLES {
+        bail!("Not enough fixes, got {} of {MIN_SAMPLES}", samples.len());
+    }
+
+    let first = samples[0].position;
+    let last = samples[samples.len() - 1].position;
+    if first.distance(last) < MIN_LEG {
+        bail!("Leg was shorter than {MIN_LEG}m");
+    }
+
+    let yaw = circular_mean(samples.iter().map(|it| it.yaw));
+    let drift = samples
+        .iter()
+        .map(|it| wrap(it.yaw - yaw).abs())
+        .fold(0.0, f32::max);
+    if drift > MAX_HEADING_DRIFT {
+        bail!(
+            "Heading changed by {:.0} deg during the leg",
+            drift.to_degrees()
+        );
+    }
+
+    // The track's principal axis is less sensitive to noisy end points than first to last
+    let mean = samples.iter().map(|it| it.position).sum::<Vec2>() / samples.len() as f32;
+    let (xx, xy, yy) = samples.iter().fold((0.0, 0.0, 0.0), |(xx, xy, yy), it| {
+        let delta = it.position - mean;
+        (
+            xx + delta.x * delta.x,
+            xy + delta.x * delta.y,
+            yy + delta.y * delta.y,
+        )
+    });
+    let axis_angle = 0.5 * (2.0 * xy).atan2(xx - yy);
+    let mut direction = Vec2::from_angle(axis_angle);
+    if direction.dot(last - first) < 0.0 {
+        direction = -direction;
+    }
+
+    let width = samples
+        .iter()
+        .map(|it| direction.perp_dot(it.position - mean).abs())
+        .fold(0.0, f32::max);
+    if width > MAX_LEG_WIDTH * first.distance(last) {
+        bail!("Track wasn't straight, drive the leg without strafing");
+    }
+
+    // The robot's forward is +Y, a quarter turn from the yaw's zero
+    let robot_direction = yaw + FRAC_PI_2;
+    let acoustic_direction = direction.y.atan2(direction.x);
+
+    Ok(wrap(acoustic_direction - robot_direction))
+}
+
+/// Solves for where the locator is mounted from a spin in place
+///
+/// Each fix is the robot's center plus the lever arm rotated by the robot's heading, which is
+/// linear in the center and the lever arm so they are found together by least squares
+pub fn solve_spin(samples: &[CalibrationSample], rotation: f32) -> anyhow::Result<Vec2> {
+    if samples.len() < MIN_SAMPLES {
+        bail!("Not enough fixes, got {} of {MIN_SAMPLES}", samples.len());
+    }
+
+    let mut sorted = samples.iter().map(|it| wrap(it.yaw)).collect::<Vec<_>>();
+    sorted.sort_by(f32::total_cmp);
+    let largest_gap = sorted
+        .windows(2)
+        .map(|it| it[1] - it[0])
+        .chain([sorted[0] + 2.0 * PI - sorted[sorted.len() - 1]])
+        .fold(0.0, f32::max);
+    if 2.0 * PI - largest_gap < MIN_SPIN {
+        bail!("Spin covered less than {:.0} deg", MIN_SPIN.to_degrees());
+    }
+
+    let mut normal = DMat4::ZERO;
+    let mut rhs = DVec4::ZERO;
+    for sample in samples {
+        let (sin, cos) = ((sample.yaw + rotation) as f64).sin_cos();
+        let position = sample.position.as_dvec2();
+
+        // Rows of the measurement matrix for the unknowns (center x, center y, arm x, arm y)
+        let rows = [dvec4(1.0, 0.0, cos, -sin), dvec4(0.0, 1.0, sin, cos)];
+        for (row, value) in rows.into_iter().zip([position.x, position.y]) {
+            normal += DMat4::from_cols(row * row.x, row * row.y, row * row.z, row * row.w);
+            rhs += row * value;
+        }
+    }
+
+    if normal.determinant().abs() < 1e-9 {
+        bail!("Spin was degenerate");
+    }
+    let solution = normal.inverse() * rhs;
+
+    Ok(vec2(solution.z as f32, solution.w as f32))
+}
+
+fn calibration_ui(
+    mut contexts: EguiContexts,
+    mut calibration: ResMut<FrameCalibration>,
+    mut state: ResMut<CalibrationState>,
+    robot: Query<(), With<Robot>>,
+) {
+    egui::Window::new("Frame Calibration").show(contexts.ctx_mut(), |ui| {
+        let mut new_calibration = *calibration;
+
+        ui.horizontal(|ui| {
+            ui.label("Rotation:");
+            let mut degrees = new_calibration.rotation.to_degrees();
+            ui.add(
+                DragValue::new(&mut degrees)
+                    .speed(0.5)
+                    .range(-180.0..=180.0)
+                    .suffix("°"),
+            );
+            new_calibration.rotation = degrees.to_radians();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Locator offset:");
+            ui.add(
+                DragValue::new(&mut new_calibration.lever_arm.x)
+                    .speed(0.01)
+                    .suffix("m"),
+            );
+            ui.add(
+                DragValue::new(&mut new_calibration.lever_arm.y)
+                    .speed(0.01)
+                    .suffix("m"),
+            );
+        });
+
+        // Degree conversion isn't exact so only write back real edits
+        if (new_calibration.rotation - calibration.rotation).abs() > 1e-4
+            || new_calibration.lever_arm != calibration.lever_arm
+        {
+            *calibration = new_calibration;
+        }
+
+        if ui.button("Reset").clicked() {
+            *calibration = FrameCalibration::default();
+        }
+
+        ui.separator();
+
+        match &*state {
+            CalibrationState::Idle => {
+                ui.label("Drive forward in a straight line for the rotation,");
+                ui.label("then spin in place for the locator offset");
+            }
+            CalibrationState::Recording(pattern, samples) => {
+                let pattern = match pattern {
+                    CalibrationPattern::Surge => "surge leg",
+                    CalibrationPattern::Spin => "spin",
+                };
+                ui.label(format!("Recording {pattern}, {} fixes", samples.len()));
+            }
+            CalibrationState::Solved(CalibrationPattern::Surge) => {
+                ui.label("Solved rotation");
+            }
+            CalibrationState::Solved(CalibrationPattern::Spin) => {
+                ui.label("Solved locator offset");
+            }
+            CalibrationState::Failed(reason) => {
+                ui.label(format!("Failed: {reason}"));
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if let CalibrationState::Recording(pattern, samples) = &*state {
+                let pattern = *pattern;
+
+                if ui.button("Finish").clicked() {
+                    let res = match pattern {
+                        CalibrationPattern::Surge => solve_surge(samples).map(|rotation| {
+                            calibration.rotation = rotation;
+                        }),
+                        CalibrationPattern::Spin => {
+                            solve_spin(samples, calibration.rotation).map(|lever_arm| {
+                                calibration.lever_arm = lever_arm;
+                            })
+                        }
+                    };
+
+                    *state = match res {
+                        Ok(()) => {
+                            info!("Calibrated frames: {:?}", *calibration);
+                            CalibrationState::Solved(pattern)
+                        }
+                        Err(err) => CalibrationState::Failed(format!("{err:#}")),
+                    };
+                } else if ui.button("Cancel").clicked() {
+                    *state = CalibrationState::Idle;
+                }
+            } else {
+                let connected = !robot.is_empty();
+
+                if ui
+                    .add_enabled(connected, egui::Button::new("Record Surge Leg"))
+                    .clicked()
+                {
+                    *state = CalibrationState::Recording(CalibrationPattern::Surge, Vec::new());
+                }
+                if ui
+                    .add_enabled(connected, egui::Button::new("Record Spin"))
+                    .clicked()
+                {
+                    *state = CalibrationState::Recording(CalibrationPattern::Spin, Vec::new());
+                }
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surge_finds_rotation() {
+        // The robot thinks it faces +Y but is actually driving along +X in the acoustic frame
+        let samples = (0..20)
+            .map(|it| CalibrationSample {
+                position: vec2(it as f32 * 0.2, 1.0),
+                yaw: 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        let rotation = solve_surge(&samples).unwrap();
+        assert!((rotation + FRAC_PI_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn surge_rejects_turns() {
+        let samples = (0..20)
+            .map(|it| CalibrationSample {
+                position: vec2(0.0, it as f32 * 0.2),
+                yaw: it as f32 * 0.05,
+            })
+            .collect::<Vec<_>>();
+
+        assert!(solve_surge(&samples).is_err());
+    }
+
+    #[test]
+    fn spin_finds_lever_arm() {
+        let center = vec2(3.0, -2.0);
+        let lever_arm = vec2(0.1, 0.4);
+        let rotation = 0.3;
+
+        let samples = (0..36)
+            .map(|it| {
+                let yaw = it as f32 * 10f32.to_radians();
+                CalibrationSample {
+                    position: center + Vec2::from_angle(yaw + rotation).rotate(lever_arm),
+                    yaw,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let solved = solve_spin(&samples, rotation).unwrap();
+        assert!(solved.distance(lever_arm) < 1e-3);
+    }
+}
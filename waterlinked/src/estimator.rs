@@ -0,0 +1,361 @@
+//! Fuses the acoustic position with dead reckoning into the robot's `CurrentPose`
+//!
+//! Each axis is filtered on its own with a position and velocity state. Between acoustic fixes
+//! the state is propagated with the IMU's acceleration, fixes correct the position and the depth
+//! sensor corrects the vertical axis. While acoustic positioning is lost, visual odometry
+//! velocities keep the dead reckoning from drifting, a DVL would be fused the same way. Fixes too
+//! far from the prediction to be plausible are rejected as acoustic spikes
+//!
+//! Everything is estimated in the acoustic frame, the robot's orientation is brought into it with
+//! the `FrameCalibration`
+
+use std::time::Duration;
+
+use bevy::{
+    app::{Plugin, PreUpdate},
+    math::{vec3a, Quat, Vec3A},
+    prelude::{
+        App, Commands, Component, Entity, EventReader, IntoSystemConfigs, Query, Ref, Res, ResMut,
+        Resource, With,
+    },
+    time::Time,
+};
+use common::{
+    components::{
+        AccelerometerMeasurement, DepthMeasurement, Orientation, PositionEstimate, Robot,
+        VisualOdometry,
+    },
+    types::units::Meters,
+};
+use tracing::warn;
+
+use crate::{
+    calibration::FrameCalibration,
+    trajectory::{CurrentPose, Pose},
+    waterlinked::WaterlinkedLocationEvent,
+    waterlinked_api::{wl_to_mate_coords, Location},
+};
+
+/// Acoustic positioning is considered lost once there hasn't been a valid fix for this long
+pub const ACOUSTIC_TIMEOUT: Duration = Duration::from_secs(3);
+pub const GRAVITY: f32 = 9.81;
+
+pub struct EstimatorPlugin;
+
+impl Plugin for EstimatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EstimatorSettings>()
+            .init_resource::<LastAcousticFix>()
+            .add_systems(
+                PreUpdate,
+                (
+                    predict,
+                    acoustic_update,
+                    depth_update,
+                    velocity_update,
+                    publish_pose,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct EstimatorSettings {
+    /// Standard deviation of the IMU's acceleration in m/s^2
+    pub acceleration_noise: f32,
+    /// Lower bound on the standard deviation of acoustic fixes in meters, the locator's own
+    /// estimate can be optimistic
+    pub min_acoustic_std: f32,
+    /// Standard deviation of depth measurements in meters
+    pub depth_std: f32,
+    /// Standard deviation of visual odometry velocities in m/s, scaled up when they aren't metric
+    pub velocity_std: f32,
+    /// Squared mahalanobis distance above which an acoustic fix is rejected
+    pub gate: f32,
+    /// After this many fixes in a row are rejected the filter trusts them and resets
+    pub max_rejections: u32,
+}
+
+impl Default for EstimatorSettings {
+    fn default() -> Self {
+        Self {
+            acceleration_noise: 0.5,
+            min_acoustic_std: 0.2,
+            depth_std: 0.05,
+            velocity_std: 0.05,
+            // 99% of a chi squared distribution with 3 degrees of freedom
+            gate: 11.34,
+            max_rejections: 5,
+        }
+    }
+}
+
+/// When the last valid acoustic position was accepted, measured from app startup
+#[derive(Resource, Debug, Default)]
+pub struct LastAcousticFix(pub Option<Duration>);
+
+/// State of the filter, lives on the robot
+///
+/// Covariances are stored per axis as the variance of the position, the variance of the velocity
+/// and the covariance between them
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct PoseEstimate {
+    pub position: Vec3A,
+    pub velocity: Vec3A,
+
+    pub position_variance: Vec3A,
+    pub velocity_variance: Vec3A,
+    pub cross_covariance: Vec3A,
+
+    /// Acoustic fixes rejected in a row
+    pub rejected: u32,
+}
+
+impl PoseEstimate {
+    fn new(position: Vec3A, position_variance: Vec3A) -> Self {
+        Self {
+            position,
+            velocity: Vec3A::ZERO,
+            position_variance,
+            velocity_variance: Vec3A::ONE,
+            cross_covariance: Vec3A::ZERO,
+            rejected: 0,
+        }
+    }
+
+    /// Horizontal standard deviation of the position in meters
+    pub fn horizontal_std(&self) -> f32 {
+        (self.position_variance.x + self.position_variance.y).sqrt()
+    }
+
+    /// Constant acceleration motion model
+    fn predict(&mut self, acceleration: Vec3A, variance: f32, dt: f32) {
+        self.position += self.velocity * dt + acceleration * dt * dt / 2.0;
+        self.velocity += acceleration * dt;
+
+        let (pp, pv, vv) = (
+            self.position_variance,
+            self.cross_covariance,
+            self.velocity_variance,
+        );
+        self.position_variance =
+            pp + 2.0 * dt * pv + dt * dt * vv + Vec3A::splat(variance * dt.powi(4) / 4.0);
+        self.cross_covariance = pv + dt * vv + Vec3A::splat(variance * dt.powi(3) / 2.0);
+        self.velocity_variance = vv + Vec3A::splat(variance * dt * dt);
+    }
+
+    /// Squared mahalanobis distance of a position measurement from the prediction
+    fn position_distance(&self, measurement: Vec3A, variance: Vec3A) -> f32 {
+        let innovation = measurement - self.position;
+        let innovation_variance = self.position_variance + variance;
+
+        (innovation * innovation / innovation_variance).element_sum()
+    }
+
+    /// Corrects the axes selected by `mask` with a position measurement
+    fn update_position(&mut self, measurement: Vec3A, variance: Vec3A, mask: Vec3A) {
+        let (pp, pv, vv) = (
+            self.position_variance,
+            self.cross_covariance,
+            self.velocity_variance,
+        );
+        let innovation = (measurement - self.position) * mask;
+        let innovation_variance = pp + variance;
+        let gain_position = pp / innovation_variance * mask;
+        let gain_velocity = pv / innovation_variance * mask;
+
+        self.position += gain_position * innovation;
+        self.velocity += gain_velocity * innovation;
+
+        self.position_variance = (Vec3A::ONE - gain_position) * pp;
+        self.cross_covariance = (Vec3A::ONE - gain_position) * pv;
+        self.velocity_variance = vv - gain_velocity * pv;
+    }
+
+    fn update_velocity(&mut self, measurement: Vec3A, variance: Vec3A) {
+        let (pp, pv, vv) = (
+            self.position_variance,
+            self.cross_covariance,
+            self.velocity_variance,
+        );
+        let innovation = measurement - self.velocity;
+        let innovation_variance = vv + variance;
+        let gain_position = pv / innovation_variance;
+        let gain_velocity = vv / innovation_variance;
+
+        self.position += gain_position * innovation;
+        self.velocity += gain_velocity * innovation;
+
+        self.position_variance = pp - gain_position * pv;
+        self.cross_covariance = (Vec3A::ONE - gain_velocity) * pv;
+        self.velocity_variance = (Vec3A::ONE - gain_velocity) * vv;
+    }
+}
+
+fn predict(
+    time: Res<Time>,
+    settings: Res<EstimatorSettings>,
+    calibration: Res<FrameCalibration>,
+    mut robot: Query<
+        (
+            &mut PoseEstimate,
+            Option<&Orientation>,
+            Option<&AccelerometerMeasurement>,
+        ),
+        With<Robot>,
+    >,
+) {
+    let Ok((mut estimate, orientation, accel)) = robot.get_single_mut() else {
+        return;
+    };
+
+    // The accelerometer measures gravity along with the robot's motion
+    let acceleration = orientation
+        .zip(accel)
+        .map(|(orientation, accel)| {
+            let body = vec3a(accel.x.0, accel.y.0, accel.z.0) * GRAVITY;
+            calibration.orientation(orientation.0) * body - vec3a(0.0, 0.0, GRAVITY)
+        })
+        .unwrap_or(Vec3A::ZERO);
+
+    let variance = settings.acceleration_noise * settings.acceleration_noise;
+    estimate.predict(acceleration, variance, time.delta_secs());
+}
+
+fn acoustic_update(
+    mut cmds: Commands,
+    time: Res<Time>,
+    settings: Res<EstimatorSettings>,
+    calibration: Res<FrameCalibration>,
+    mut last_fix: ResMut<LastAcousticFix>,
+    mut robot: Query<(Entity, Option<&mut PoseEstimate>, Option<&Orientation>), With<Robot>>,
+    mut reader: EventReader<WaterlinkedLocationEvent>,
+) {
+    let Ok((robot, mut estimate, orientation)) = robot.get_single_mut() else {
+        return;
+    };
+    let rotation = calibration.orientation(orientation.map(|it| it.0).unwrap_or_default());
+
+    for event in reader.read() {
+        let Location {
+            position_valid,
+            std,
+            x,
+            y,
+            z,
+            ..
+        } = event.0.clone();
+
+        if !position_valid {
+            warn!("Recieved bad UGPS update");
+            continue;
+        }
+
+        let (x, y, z) = wl_to_mate_coords(x, y, z);
+        // The locator isn't at the robot's center
+        let measurement = calibration.robot_position(vec3a(x, y, z), rotation);
+        let std = std.max(settings.min_acoustic_std);
+        let variance = Vec3A::splat(std * std);
+
+        let Some(estimate) = estimate.as_deref_mut() else {
+            cmds.entity(robot)
+                .insert(PoseEstimate::new(measurement, variance));
+            last_fix.0 = Some(time.elapsed());
+            continue;
+        };
+
+        if estimate.position_distance(measurement, variance) > settings.gate {
+            estimate.rejected += 1;
+
+            if estimate.rejected < settings.max_rejections {
+                warn!("Rejected acoustic fix as an outlier");
+                continue;
+            }
+
+            // The robot really is somewhere else, likely after a long stretch of dead reckoning
+            warn!("Resetting position estimate to the acoustic fix");
+            *estimate = PoseEstimate::new(measurement, variance);
+        } else {
+            estimate.rejected = 0;
+            estimate.update_position(measurement, variance, Vec3A::ONE);
+        }
+
+        last_fix.0 = Some(time.elapsed());
+    }
+}
+
+fn depth_update(
+    settings: Res<EstimatorSettings>,
+    mut robot: Query<(&mut PoseEstimate, Ref<DepthMeasurement>), With<Robot>>,
+) {
+    let Ok((mut estimate, depth)) = robot.get_single_mut() else {
+        return;
+    };
+    if !depth.is_changed() {
+        return;
+    }
+
+    let measurement = vec3a(0.0, 0.0, -depth.depth.0);
+    let variance = Vec3A::splat(settings.depth_std * settings.depth_std);
+    estimate.update_position(measurement, variance, Vec3A::Z);
+}
+
+fn velocity_update(
+    time: Res<Time>,
+    settings: Res<EstimatorSettings>,
+    calibration: Res<FrameCalibration>,
+    last_fix: Res<LastAcousticFix>,
+    mut robot: Query<(&mut PoseEstimate, Option<&Orientation>, Ref<VisualOdometry>), With<Robot>>,
+) {
+    let Ok((mut estimate, orientation, odometry)) = robot.get_single_mut() else {
+        return;
+    };
+
+    if !odometry.is_changed() {
+        return;
+    }
+
+    // Visual odometry's scale is only as good as the assumed scene distance, so it only stands in
+    // for acoustic positioning while that is lost
+    let acoustic_lost = last_fix
+        .0
+        .map(|it| time.elapsed().saturating_sub(it) > ACOUSTIC_TIMEOUT)
+        .unwrap_or(true);
+    if !acoustic_lost {
+        return;
+    }
+
+    let rotation = calibration.orientation(orientation.map(|it| it.0).unwrap_or_default());
+    let velocity = rotation * odometry.velocity;
+
+    let std = if odometry.metric {
+        settings.velocity_std
+    } else {
+        settings.velocity_std * 3.0
+    };
+    estimate.update_velocity(velocity, Vec3A::splat(std * std));
+}
+
+fn publish_pose(
+    mut cmds: Commands,
+    calibration: Res<FrameCalibration>,
+    robot: Query<(Entity, &PoseEstimate, Option<&Orientation>), With<Robot>>,
+) {
+    let Ok((robot, estimate, orientation)) = robot.get_single() else {
+        return;
+    };
+
+    cmds.entity(robot).insert((
+        CurrentPose(Pose {
+            position: estimate.position,
+            rotation: calibration.orientation(orientation.map(|it| it.0).unwrap_or_default()),
+        }),
+        // Shared with the surfaces for geotagging
+        PositionEstimate {
+            position: estimate.position,
+            horizontal_std: Meters(estimate.horizontal_std()),
+            frame_rotation: calibration.orientation(Quat::IDENTITY),
+        },
+    ));
+}
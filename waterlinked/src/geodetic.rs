@@ -0,0 +1,174 @@
+//! Conversions between geographic coordinates and the local frame positions are tracked in
+//!
+//! Geographic coordinates are on the WGS84 ellipsoid. The local frame is the topside's, the
+//! acoustic positions are relative to the receivers so the frame is centered on the topside and
+//! rotated with its heading
+
+use bevy::{
+    math::{dvec2, dvec3, DVec2, DVec3, Vec2},
+    prelude::Resource,
+};
+use serde::{Deserialize, Serialize};
+
+/// Semi major axis of the WGS84 ellipsoid in meters
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_223_563;
+const ECCENTRICITY_SQUARED: f64 = FLATTENING * (2.0 - FLATTENING);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    /// Degrees north
+    pub lat: f64,
+    /// Degrees east
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// Earth centered earth fixed coordinates in meters, `height` is above the ellipsoid
+    pub fn to_ecef(self, height: f64) -> DVec3 {
+        let (lat, lon) = (self.lat.to_radians(), self.lon.to_radians());
+        let prime_vertical =
+            SEMI_MAJOR_AXIS / (1.0 - ECCENTRICITY_SQUARED * lat.sin().powi(2)).sqrt();
+
+        dvec3(
+            (prime_vertical + height) * lat.cos() * lon.cos(),
+            (prime_vertical + height) * lat.cos() * lon.sin(),
+            (prime_vertical * (1.0 - ECCENTRICITY_SQUARED) + height) * lat.sin(),
+        )
+    }
+
+    /// Inverse of `to_ecef`, returns the point and its height above the ellipsoid
+    pub fn from_ecef(ecef: DVec3) -> (Self, f64) {
+        let lon = ecef.y.atan2(ecef.x);
+        let distance = ecef.truncate().length();
+
+        // Converges to well under a millimeter within a few iterations
+        let mut lat = ecef.z.atan2(distance * (1.0 - ECCENTRICITY_SQUARED));
+        let mut height = 0.0;
+        for _ in 0..5 {
+            let prime_vertical =
+                SEMI_MAJOR_AXIS / (1.0 - ECCENTRICITY_SQUARED * lat.sin().powi(2)).sqrt();
+            height = distance / lat.cos() - prime_vertical;
+            lat = ecef.z.atan2(
+                distance
+                    * (1.0 - ECCENTRICITY_SQUARED * prime_vertical / (prime_vertical + height)),
+            );
+        }
+
+        (Self::new(lat.to_degrees(), lon.to_degrees()), height)
+    }
+
+    /// East, north and up unit vectors at this point, in ecef
+    fn enu_axes(self) -> (DVec3, DVec3, DVec3) {
+        let (lat, lon) = (self.lat.to_radians(), self.lon.to_radians());
+
+        (
+            dvec3(-lon.sin(), lon.cos(), 0.0),
+            dvec3(-lat.sin() * lon.cos(), -lat.sin() * lon.sin(), lat.cos()),
+            dvec3(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()),
+        )
+    }
+}
+
+/// The topside's position and heading, which the acoustic positions are relative to
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct LocalFrame {
+    pub origin: GeoPoint,
+    /// Degrees clockwise from north the topside is facing
+    pub heading: f32,
+}
+
+impl LocalFrame {
+    /// East and north of the origin in meters
+    pub fn enu_from_geo(&self, point: GeoPoint) -> DVec2 {
+        let (east, north, _) = self.origin.enu_axes();
+        let delta = point.to_ecef(0.0) - self.origin.to_ecef(0.0);
+
+        dvec2(delta.dot(east), delta.dot(north))
+    }
+
+    pub fn geo_from_enu(&self, enu: DVec2) -> GeoPoint {
+        let (east, north, _) = self.origin.enu_axes();
+        let ecef = self.origin.to_ecef(0.0) + east * enu.x + north * enu.y;
+
+        GeoPoint::from_ecef(ecef).0
+    }
+
+    /// Position in the local frame, +X to the right of the topside and +Y ahead of it
+    pub fn local_from_geo(&self, point: GeoPoint) -> Vec2 {
+        let enu = self.enu_from_geo(point);
+        let (sin, cos) = (self.heading as f64).to_radians().sin_cos();
+
+        dvec2(enu.x * cos - enu.y * sin, enu.x * sin + enu.y * cos).as_vec2()
+    }
+
+    pub fn geo_from_local(&self, local: Vec2) -> GeoPoint {
+        let local = local.as_dvec2();
+        let (sin, cos) = (self.heading as f64).to_radians().sin_cos();
+
+        self.geo_from_enu(dvec2(
+            local.x * cos + local.y * sin,
+            -local.x * sin + local.y * cos,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::vec2;
+
+    use super::*;
+
+    const FRAME: LocalFrame = LocalFrame {
+        origin: GeoPoint {
+            lat: 63.4305,
+            lon: 10.3951,
+        },
+        heading: 0.0,
+    };
+
+    #[test]
+    fn ecef_round_trip() {
+        let point = GeoPoint::new(-33.8568, 151.2153);
+        let (back, height) = GeoPoint::from_ecef(point.to_ecef(12.0));
+
+        assert!((back.lat - point.lat).abs() < 1e-9);
+        assert!((back.lon - point.lon).abs() < 1e-9);
+        assert!((height - 12.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn enu_distances() {
+        // A thousandth of a degree of latitude is about 111m everywhere
+        let north = GeoPoint::new(FRAME.origin.lat + 0.001, FRAME.origin.lon);
+        let enu = FRAME.enu_from_geo(north);
+
+        assert!(enu.x.abs() < 0.01);
+        assert!((enu.y - 111.4).abs() < 0.5);
+    }
+
+    #[test]
+    fn local_frame_follows_heading() {
+        let frame = LocalFrame {
+            heading: 90.0,
+            ..FRAME
+        };
+
+        // Facing east, a point to the east is straight ahead
+        let east = frame.geo_from_enu(dvec2(10.0, 0.0));
+        let local = frame.local_from_geo(east);
+        assert!(local.distance(vec2(0.0, 10.0)) < 1e-3);
+
+        let point = vec2(-12.5, 40.0);
+        assert!(
+            frame
+                .local_from_geo(frame.geo_from_local(point))
+                .distance(point)
+                < 1e-3
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Polygonal zones the robot must stay out of
+//!
+//! Zones are saved with the mission and enforced when routing to the `TargetPose`. Targets inside
+//! a zone are moved to its edge and paths that would cross a zone are replaced by the shortest path
+//! around the zones' corners. Zones are grown by a margin first so the robot doesn't scrape along
+//! their edges
+
+use bevy::{
+    app::{Plugin, Update},
+    math::Vec2,
+    prelude::{App, Component, Res, ResMut, Resource},
+};
+use bevy_egui::EguiContexts;
+use egui::DragValue;
+use serde::{Deserialize, Serialize};
+
+use crate::mission::Mission;
+
+/// Paths are checked against zones grown by this fraction of the margin so routes along the
+/// margin's corners aren't treated as crossing it
+const CLEARANCE: f32 = 0.9;
+/// Limit on how far a sharp corner is grown, as a multiple of the margin
+const MAX_MITER: f32 = 3.0;
+
+pub struct KeepOutPlugin;
+
+impl Plugin for KeepOutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeepOutSettings>()
+            .init_resource::<ZoneEditor>()
+            .add_systems(Update, keep_out_ui);
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct KeepOutSettings {
+    /// Meters to stay away from zones
+    pub margin: f32,
+}
+
+impl Default for KeepOutSettings {
+    fn default() -> Self {
+        Self { margin: 0.5 }
+    }
+}
+
+/// Which zone right double clicks on the plot are adding corners to
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ZoneEditor {
+    pub drawing: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct KeepOutZone {
+    pub name: String,
+    /// Corners in the same frame as `CurrentPose`, in order around the zone
+    pub vertices: Vec<Vec2>,
+}
+
+impl KeepOutZone {
+    /// Zones still being drawn don't have an inside yet
+    pub fn is_closed(&self) -> bool {
+        self.vertices.len() >= 3
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.is_closed() && polygon_contains(&self.vertices, point)
+    }
+}
+
+/// How the route to the current `TargetPose` was planned, lives on the robot
+#[derive(Component, Debug, Clone, PartialEq)]
+pub enum RouteStatus {
+    Planned {
+        /// Corners driven through to get around zones
+        detours: usize,
+        /// Whether the target was inside a zone and moved to its edge
+        clipped: bool,
+    },
+    Rejected(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutePlan {
+    /// Points to drive through in order, ending at the possibly clipped target
+    pub points: Vec<Vec2>,
+    pub clipped: bool,
+}
+
+fn polygon_contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+
+    for (idx, a) in polygon.iter().enumerate() {
+        let b = polygon[(idx + 1) % polygon.len()];
+
+        if (a.y > point.y) != (b.y > point.y) {
+            let crossing = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if point.x < crossing {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+/// Whether the segments cross each other, touching doesn't count
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+
+    let (d1, d2) = (side(c, d, a), side(c, d, b));
+    let (d3, d4) = (side(a, b, c), side(a, b, d));
+
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+fn closest_on_segment(a: Vec2, b: Vec2, point: Vec2) -> Vec2 {
+    let delta = b - a;
+    let length_squared = delta.length_squared();
+    if length_squared == 0.0 {
+        return a;
+    }
+
+    a + delta * ((point - a).dot(delta) / length_squared).clamp(0.0, 1.0)
+}
+
+/// Moves every edge of the polygon outward by `margin`
+fn inflate(polygon: &[Vec2], margin: f32) -> Vec<Vec2> {
+    // Twice the signed area, positive when the vertices go counter clockwise
+    let area = polygon
+        .iter()
+        .enumerate()
+        .map(|(idx, a)| a.perp_dot(polygon[(idx + 1) % polygon.len()]))
+        .sum::<f32>();
+    let outward = |a: Vec2, b: Vec2| {
+        let normal = (b - a).perp().normalize_or_zero();
+        if area > 0.0 {
+            -normal
+        } else {
+            normal
+        }
+    };
+
+    let count = polygon.len();
+    (0..count)
+        .map(|idx| {
+            let prev = polygon[(idx + count - 1) % count];
+            let vertex = polygon[idx];
+            let next = polygon[(idx + 1) % count];
+
+            let (n1, n2) = (outward(prev, vertex), outward(vertex, next));
+            // Where the two moved edges meet
+            let miter = (n1 + n2) / (1.0 + n1.dot(n2)).max(1e-3);
+
+            vertex + miter.clamp_length_max(MAX_MITER) * margin
+        })
+        .collect()
+}
+
+fn visible(a: Vec2, b: Vec2, obstacles: &[Vec<Vec2>]) -> bool {
+    obstacles.iter().all(|polygon| {
+        let crosses = (0..polygon.len())
+            .any(|idx| segments_cross(a, b, polygon[idx], polygon[(idx + 1) % polygon.len()]));
+
+        !crosses && !polygon_contains(polygon, (a + b) / 2.0)
+    })
+}
+
+/// Nearest point on the edge of the grown zone containing `point`, `None` if it isn't in one
+fn clip(point: Vec2, grown: &[Vec<Vec2>]) -> Option<Vec2> {
+    let polygon = grown.iter().find(|it| polygon_contains(it, point))?;
+
+    (0..polygon.len())
+        .map(|idx| closest_on_segment(polygon[idx], polygon[(idx + 1) % polygon.len()], point))
+        .min_by(|a, b| a.distance(point).total_cmp(&b.distance(point)))
+}
+
+/// Shortest route from `start` to `goal` that stays `margin` away from every zone
+pub fn plan_route(
+    start: Vec2,
+    goal: Vec2,
+    zones: &[KeepOutZone],
+    margin: f32,
+) -> Result<RoutePlan, &'static str> {
+    let zones = zones.iter().filter(|it| it.is_closed());
+    let grown = zones
+        .clone()
+        .map(|it| inflate(&it.vertices, margin))
+        .collect::<Vec<_>>();
+    let obstacles = zones
+        .map(|it| inflate(&it.vertices, margin * CLEARANCE))
+        .collect::<Vec<_>>();
+
+    // A robot that ended up inside a zone leaves it the shortest way first
+    let exit = clip(start, &grown);
+    let start = exit.unwrap_or(start);
+
+    let clipped = clip(goal, &grown);
+    let goal = clipped.unwrap_or(goal);
+    // Clipped points are on the edge of the grown zones so they're checked against the obstacles
+    if obstacles.iter().any(|it| polygon_contains(it, goal)) {
+        return Err("Target is inside a keep-out zone");
+    }
+
+    let mut nodes = vec![start, goal];
+    nodes.extend(grown.iter().flatten().filter(|it| {
+        !obstacles
+            .iter()
+            .any(|polygon| polygon_contains(polygon, **it))
+    }));
+
+    // Dijkstra over the visibility graph, small enough that the quadratic version is fine
+    let mut distance = vec![f32::INFINITY; nodes.len()];
+    let mut previous = vec![None; nodes.len()];
+    let mut done = vec![false; nodes.len()];
+    distance[0] = 0.0;
+
+    while let Some(current) = (0..nodes.len())
+        .filter(|it| !done[*it] && distance[*it].is_finite())
+        .min_by(|a, b| distance[*a].total_cmp(&distance[*b]))
+    {
+        if current == 1 {
+            break;
+        }
+        done[current] = true;
+
+        for next in 0..nodes.len() {
+            if done[next] || !visible(nodes[current], nodes[next], &obstacles) {
+                continue;
+            }
+
+            let candidate = distance[current] + nodes[current].distance(nodes[next]);
+            if candidate < distance[next] {
+                distance[next] = candidate;
+                previous[next] = Some(current);
+            }
+        }
+    }
+
+    if !distance[1].is_finite() {
+        return Err("No path around the keep-out zones");
+    }
+
+    let mut points = vec![goal];
+    let mut node = 1;
+    while let Some(prev) = previous[node] {
+        if prev != 0 {
+            points.push(nodes[prev]);
+        }
+        node = prev;
+    }
+    if exit.is_some() {
+        points.push(start);
+    }
+    points.reverse();
+
+    Ok(RoutePlan {
+        points,
+        clipped: clipped.is_some(),
+    })
+}
+
+fn keep_out_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<KeepOutSettings>,
+    mut editor: ResMut<ZoneEditor>,
+    mut mission: ResMut<Mission>,
+) {
+    egui::Window::new("Keep-Out Zones").show(contexts.ctx_mut(), |ui| {
+        let mut margin = settings.margin;
+        ui.horizontal(|ui| {
+            ui.label("Margin:");
+            ui.add(
+                DragValue::new(&mut margin)
+                    .speed(0.05)
+                    .range(0.0..=5.0)
+                    .suffix("m"),
+            );
+        });
+        if margin != settings.margin {
+            settings.margin = margin;
+        }
+
+        ui.separator();
+
+        let mut remove = None;
+        for (idx, zone) in mission.keep_out.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}: {} corners", zone.name, zone.vertices.len()));
+                if editor.drawing != Some(idx) && ui.button("🗑").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+
+        if let Some(idx) = remove {
+            mission.keep_out.remove(idx);
+            if let Some(drawing) = &mut editor.drawing {
+                if *drawing > idx {
+                    *drawing -= 1;
+                }
+            }
+        }
+
+        if let Some(drawing) = editor.drawing {
+            ui.label("Right double click on the plot to add corners");
+
+            if ui.button("Done").clicked() {
+                if mission
+                    .keep_out
+                    .get(drawing)
+                    .is_some_and(|it| !it.is_closed())
+                {
+                    mission.keep_out.remove(drawing);
+                }
+                editor.drawing = None;
+            }
+        } else if ui.button("Draw Zone").clicked() {
+            let name = format!("Zone {}", mission.keep_out.len() + 1);
+            mission.keep_out.push(KeepOutZone {
+                name,
+                vertices: Vec::new(),
+            });
+            editor.drawing = Some(mission.keep_out.len() - 1);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::vec2;
+
+    use super::*;
+
+    fn square(center: Vec2, size: f32) -> KeepOutZone {
+        let half = size / 2.0;
+
+        KeepOutZone {
+            name: "Square".to_owned(),
+            vertices: vec![
+                center + vec2(-half, -half),
+                center + vec2(half, -half),
+                center + vec2(half, half),
+                center + vec2(-half, half),
+            ],
+        }
+    }
+
+    #[test]
+    fn clear_paths_are_direct() {
+        let zones = [square(vec2(0.0, 10.0), 2.0)];
+        let plan = plan_route(vec2(0.0, 0.0), vec2(5.0, 0.0), &zones, 0.5).unwrap();
+
+        assert_eq!(plan.points, vec![vec2(5.0, 0.0)]);
+        assert!(!plan.clipped);
+    }
+
+    #[test]
+    fn routes_around_zones() {
+        let zones = [square(vec2(0.0, 5.0), 2.0)];
+        let plan = plan_route(vec2(0.0, 0.0), vec2(0.0, 10.0), &zones, 0.5).unwrap();
+
+        assert!(plan.points.len() > 1);
+        assert_eq!(*plan.points.last().unwrap(), vec2(0.0, 10.0));
+
+        // No leg may pass through the zone
+        let mut from = vec2(0.0, 0.0);
+        for to in plan.points {
+            for step in 0..=20 {
+                let point = from.lerp(to, step as f32 / 20.0);
+                assert!(!zones[0].contains(point));
+            }
+            from = to;
+        }
+    }
+
+    #[test]
+    fn targets_inside_zones_are_clipped() {
+        let zones = [square(vec2(0.0, 5.0), 2.0)];
+        let plan = plan_route(vec2(0.0, 0.0), vec2(0.0, 4.2), &zones, 0.5).unwrap();
+
+        assert!(plan.clipped);
+        let end = *plan.points.last().unwrap();
+        assert!((end.y - 3.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,346 @@
+//! Places the local frame on the globe and draws map tiles under the position plot
+//!
+//! The local frame follows the topside's GPS fix and heading. Tiles are fetched from a slippy map
+//! tile server and cached for the lifetime of the app
+
+use std::{collections::HashMap, f64::consts::PI};
+
+use anyhow::Context;
+use bevy::{
+    app::{Plugin, Update},
+    math::DVec2,
+    prelude::{App, Commands, Res, ResMut, Resource},
+};
+use bevy_tokio_tasks::TokioTasksRuntime;
+use egui::{ColorImage, TextureHandle, TextureOptions};
+use egui_plot::{PlotImage, PlotPoint, PlotUi};
+use tracing::warn;
+
+use crate::{
+    geodetic::{GeoPoint, LocalFrame},
+    mission::Mission,
+    waterlinked::TopsideGps,
+};
+
+pub const USER_AGENT: &str = "robocode-waterlinked";
+/// Circumference of the earth at the equator in meters
+pub const EQUATOR: f64 = 40_075_016.686;
+pub const MAX_ZOOM: u8 = 19;
+/// Tiles requested at once
+pub const MAX_IN_FLIGHT: usize = 8;
+/// The cache is dropped once it holds this many tiles
+pub const MAX_CACHED: usize = 512;
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapSettings>()
+            .init_resource::<MapTiles>()
+            .add_systems(
+                Update,
+                (
+                    update_local_frame,
+                    resolve_geographic_waypoints,
+                    request_tiles,
+                ),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct MapSettings {
+    pub enabled: bool,
+    /// `{z}`, `{x}` and `{y}` are replaced with the tile's coordinates
+    pub tile_url: String,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            tile_url: "https://tile.openstreetmap.org/{z}/{x}/{y}.png".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileId {
+    pub x: u32,
+    pub y: u32,
+    pub zoom: u8,
+}
+
+impl TileId {
+    /// Fractional web mercator tile coordinates of a point
+    fn tile_coords(point: GeoPoint, zoom: u8) -> DVec2 {
+        let tiles = (1u32 << zoom) as f64;
+        let lat = point.lat.to_radians();
+
+        DVec2::new(
+            (point.lon + 180.0) / 360.0 * tiles,
+            (1.0 - lat.tan().asinh() / PI) / 2.0 * tiles,
+        )
+    }
+
+    fn geo_from_tile_coords(coords: DVec2, zoom: u8) -> GeoPoint {
+        let tiles = (1u32 << zoom) as f64;
+
+        GeoPoint::new(
+            (PI * (1.0 - 2.0 * coords.y / tiles))
+                .sinh()
+                .atan()
+                .to_degrees(),
+            coords.x / tiles * 360.0 - 180.0,
+        )
+    }
+
+    pub fn center(&self) -> GeoPoint {
+        Self::geo_from_tile_coords(
+            DVec2::new(self.x as f64 + 0.5, self.y as f64 + 0.5),
+            self.zoom,
+        )
+    }
+
+    /// Width of the tile in meters, tiles are square at the scales drawn here
+    pub fn size(&self) -> f64 {
+        EQUATOR * self.center().lat.to_radians().cos() / (1u32 << self.zoom) as f64
+    }
+
+    fn url(&self, template: &str) -> String {
+        template
+            .replace("{z}", &self.zoom.to_string())
+            .replace("{x}", &self.x.to_string())
+            .replace("{y}", &self.y.to_string())
+    }
+}
+
+enum Tile {
+    Loading,
+    Loaded(ColorImage),
+    Ready(TextureHandle),
+    Failed,
+}
+
+#[derive(Resource)]
+pub struct MapTiles {
+    tiles: HashMap<TileId, Tile>,
+    /// Tiles that were in view but not cached yet
+    wanted: Vec<TileId>,
+    client: reqwest::Client,
+}
+
+impl Default for MapTiles {
+    fn default() -> Self {
+        Self {
+            tiles: HashMap::new(),
+            wanted: Vec::new(),
+            client: reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl MapTiles {
+    /// Draws the tiles covering the plot's current bounds, queues the ones that are missing
+    pub fn draw(&mut self, ui: &mut PlotUi, frame: &LocalFrame) {
+        let bounds = *ui.transform().bounds();
+        let meters_per_pixel = bounds.width() / ui.transform().frame().width() as f64;
+
+        // Pick the zoom where a tile's pixels are about the size of the plot's
+        let tile_meters_per_pixel = EQUATOR * frame.origin.lat.to_radians().cos() / 256.0;
+        let zoom = (tile_meters_per_pixel / meters_per_pixel)
+            .log2()
+            .round()
+            .clamp(0.0, MAX_ZOOM as f64) as u8;
+
+        let corners = [
+            (bounds.min()[0], bounds.min()[1]),
+            (bounds.min()[0], bounds.max()[1]),
+            (bounds.max()[0], bounds.min()[1]),
+            (bounds.max()[0], bounds.max()[1]),
+        ]
+        .map(|(x, y)| {
+            let point = frame.geo_from_local(DVec2::new(x, y).as_vec2());
+            TileId::tile_coords(point, zoom)
+        });
+
+        let min = corners
+            .iter()
+            .copied()
+            .reduce(DVec2::min)
+            .unwrap_or_default();
+        let max = corners
+            .iter()
+            .copied()
+            .reduce(DVec2::max)
+            .unwrap_or_default();
+        let last = (1u32 << zoom) - 1;
+
+        for y in (min.y.floor().max(0.0) as u32)..=(max.y.floor() as u32).min(last) {
+            for x in (min.x.floor().max(0.0) as u32)..=(max.x.floor() as u32).min(last) {
+                let id = TileId { x, y, zoom };
+
+                let Some(tile) = self.tiles.get_mut(&id) else {
+                    self.wanted.push(id);
+                    continue;
+                };
+
+                if let Tile::Loaded(image) = tile {
+                    let texture = ui.ctx().load_texture(
+                        format!("Tile {zoom}/{x}/{y}"),
+                        std::mem::take(image),
+                        TextureOptions::LINEAR,
+                    );
+                    *tile = Tile::Ready(texture);
+                }
+                let Tile::Ready(texture) = tile else {
+                    continue;
+                };
+
+                let center = frame.local_from_geo(id.center());
+                let size = id.size() as f32;
+
+                // Tiles are drawn north up, the local frame is rotated with the topside
+                ui.image(
+                    PlotImage::new(
+                        "Map",
+                        texture.id(),
+                        PlotPoint::new(center.x, center.y),
+                        [size, size],
+                    )
+                    .rotate(frame.heading.to_radians() as f64),
+                );
+            }
+        }
+    }
+}
+
+fn update_local_frame(
+    mut cmds: Commands,
+    topside: Res<TopsideGps>,
+    frame: Option<Res<LocalFrame>>,
+) {
+    if !topside.is_changed() {
+        return;
+    }
+    let Some(fix) = topside.0.as_ref().filter(|it| it.has_fix()) else {
+        return;
+    };
+
+    // Without a compass the topside is assumed to face north
+    let new_frame = LocalFrame {
+        origin: GeoPoint::new(fix.lat, fix.lon),
+        heading: fix.heading().unwrap_or(0.0),
+    };
+
+    if frame.as_deref() != Some(&new_frame) {
+        cmds.insert_resource(new_frame);
+    }
+}
+
+/// Keeps the local position of waypoints entered as coordinates up to date as the topside moves
+fn resolve_geographic_waypoints(frame: Option<Res<LocalFrame>>, mut mission: ResMut<Mission>) {
+    let Some(frame) = frame else {
+        return;
+    };
+    if !frame.is_changed() && !mission.is_changed() {
+        return;
+    }
+
+    let mut changed = false;
+    for waypoint in &mut mission.bypass_change_detection().waypoints {
+        let Some(geo) = waypoint.geo else {
+            continue;
+        };
+
+        // Ignore GPS jitter so the mission isn't rewritten every fix
+        let local = frame.local_from_geo(geo);
+        if local.distance(waypoint.position()) > 0.05 {
+            waypoint.x = local.x;
+            waypoint.y = local.y;
+            changed = true;
+        }
+    }
+
+    if changed {
+        mission.set_changed();
+    }
+}
+
+fn request_tiles(
+    runtime: Res<TokioTasksRuntime>,
+    settings: Res<MapSettings>,
+    mut map: ResMut<MapTiles>,
+) {
+    let map = &mut *map;
+
+    if !settings.enabled {
+        map.wanted.clear();
+        return;
+    }
+
+    if map.tiles.len() > MAX_CACHED {
+        map.tiles.clear();
+    }
+
+    let mut in_flight = map
+        .tiles
+        .values()
+        .filter(|it| matches!(it, Tile::Loading))
+        .count();
+
+    for id in map.wanted.drain(..) {
+        if in_flight >= MAX_IN_FLIGHT {
+            break;
+        }
+        if map.tiles.contains_key(&id) {
+            continue;
+        }
+
+        map.tiles.insert(id, Tile::Loading);
+        in_flight += 1;
+
+        let client = map.client.clone();
+        let url = id.url(&settings.tile_url);
+        runtime.spawn_background_task(move |mut ctx| async move {
+            let res: anyhow::Result<ColorImage> = async {
+                let bytes = client
+                    .get(&url)
+                    .send()
+                    .await
+                    .context("Send request")?
+                    .error_for_status()
+                    .context("Bad status")?
+                    .bytes()
+                    .await
+                    .context("Read body")?;
+
+                let image = image::load_from_memory(&bytes)
+                    .context("Decode tile")?
+                    .to_rgba8();
+                let size = [image.width() as usize, image.height() as usize];
+
+                Ok(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+            }
+            .await;
+
+            let tile = match res {
+                Ok(image) => Tile::Loaded(image),
+                Err(err) => {
+                    warn!("Could not load map tile {url}: {err:?}");
+                    Tile::Failed
+                }
+            };
+
+            ctx.run_on_main_thread(move |ctx| {
+                ctx.world.resource_mut::<MapTiles>().tiles.insert(id, tile);
+            })
+            .await;
+        });
+    }
+
+    map.wanted.clear();
+}
@@ -0,0 +1,573 @@
+//! Ordered waypoint missions
+//!
+//! Each waypoint is reached by handing it to the trajectory follower as the `TargetPose`, once the
+//! robot is inside the waypoint's acceptance radius it holds there for the waypoint's hold time
+//! before moving on to the next one
+
+use std::{fs, ops::RangeInclusive, time::Duration};
+
+use anyhow::{bail, Context};
+use bevy::{
+    app::{Plugin, PreStartup, Update},
+    math::{vec2, vec3a, Quat, Vec2},
+    prelude::{
+        App, Commands, Entity, Event, EventReader, EventWriter, Query, Res, ResMut, Resource, With,
+    },
+    time::Time,
+};
+use bevy_egui::EguiContexts;
+use common::{
+    components::{DepthTarget, OrientationTarget, Robot},
+    types::units::Meters,
+};
+use egui::{DragValue, Grid, ProgressBar};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{
+    geodetic::{GeoPoint, LocalFrame},
+    keep_out::KeepOutZone,
+    trajectory::{CurrentPose, Pose, SpeedLimit, TargetPose},
+};
+
+pub const MISSION_FILE: &str = "mission.toml";
+
+/// Seconds a waypoint can be held for
+const HOLD_RANGE: RangeInclusive<f32> = 0.0..=600.0;
+/// Speed limits in m/s a leg can have
+const SPEED_RANGE: RangeInclusive<f32> = 0.05..=1.0;
+
+pub struct MissionPlugin;
+
+impl Plugin for MissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MissionState>()
+            .add_event::<MissionCommand>()
+            .add_systems(PreStartup, load_mission)
+            .add_systems(
+                Update,
+                (handle_commands, execute_mission, mission_ui, save_mission),
+            );
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Mission {
+    pub waypoints: Vec<Waypoint>,
+    #[serde(default)]
+    pub keep_out: Vec<KeepOutZone>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    /// Meters, in the same frame as `CurrentPose`
+    pub x: f32,
+    pub y: f32,
+    /// Set for waypoints entered as coordinates, `x` and `y` then follow it as the topside moves
+    pub geo: Option<GeoPoint>,
+    /// Meters below the surface
+    pub depth: f32,
+    /// Compass heading in degrees to hold while on the leg, unset leaves the heading alone
+    pub heading: Option<f32>,
+    /// Seconds to stay at the waypoint once it is reached
+    pub hold: f32,
+    /// Speed limit in m/s on the leg to this waypoint
+    pub speed: f32,
+    /// Meters from the waypoint the robot has to be to have reached it
+    pub acceptance_radius: f32,
+}
+
+impl Waypoint {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            x,
+            y,
+            geo: None,
+            depth: 1.0,
+            heading: None,
+            hold: 0.0,
+            speed: 0.3,
+            acceptance_radius: 0.5,
+        }
+    }
+
+    pub fn position(&self) -> Vec2 {
+        vec2(self.x, self.y)
+    }
+}
+
+impl Mission {
+    /// Checks the values the UI limits, saved missions can be edited by hand
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (idx, waypoint) in self.waypoints.iter().enumerate() {
+            if !HOLD_RANGE.contains(&waypoint.hold) {
+                bail!(
+                    "Waypoint {} holds for {}s, outside {HOLD_RANGE:?}",
+                    idx + 1,
+                    waypoint.hold
+                );
+            }
+
+            if !SPEED_RANGE.contains(&waypoint.speed) {
+                bail!(
+                    "Waypoint {} has a speed of {}m/s, outside {SPEED_RANGE:?}",
+                    idx + 1,
+                    waypoint.speed
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub enum MissionState {
+    #[default]
+    Idle,
+    Running(MissionProgress),
+    Paused(MissionProgress),
+    Complete,
+    Aborted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MissionProgress {
+    /// Index of the waypoint being driven to
+    pub leg: usize,
+    /// When the robot reached the current waypoint, set while it holds there
+    pub arrived: Option<Duration>,
+    /// Whether the current waypoint has been handed to the trajectory follower
+    applied: bool,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionCommand {
+    Start,
+    Pause,
+    Resume,
+    Abort,
+}
+
+fn load_mission(mut cmds: Commands) {
+    let res: anyhow::Result<Mission> = try {
+        let mission = fs::read_to_string(MISSION_FILE).context("Read mission")?;
+        let mission: Mission = toml::from_str(&mission).context("Parse mission")?;
+        mission.validate().context("Validate mission")?;
+
+        mission
+    };
+
+    let mission = match res {
+        Ok(mission) => mission,
+        Err(err) => {
+            warn!("Using empty mission: {err:?}");
+            Mission::default()
+        }
+    };
+
+    cmds.insert_resource(mission);
+}
+
+fn save_mission(mission: Res<Mission>) {
+    if !mission.is_changed() || mission.is_added() {
+        return;
+    }
+
+    let Ok(str) = toml::to_string_pretty(&*mission) else {
+        error!("Could not serialize mission");
+        return;
+    };
+
+    let res = fs::write(MISSION_FILE, &str);
+    if let Err(err) = res {
+        error!("Could not write mission: {err:?}");
+    }
+}
+
+fn handle_commands(
+    mut cmds: Commands,
+    mission: Res<Mission>,
+    mut state: ResMut<MissionState>,
+    robot: Query<Entity, With<Robot>>,
+    mut commands: EventReader<MissionCommand>,
+) {
+    for command in commands.read() {
+        let new_state = match (command, *state) {
+            (MissionCommand::Start, MissionState::Running(_) | MissionState::Paused(_)) => continue,
+            (MissionCommand::Start, _) if mission.waypoints.is_empty() => {
+                warn!("Cannot start an empty mission");
+                continue;
+            }
+            (MissionCommand::Start, _) => MissionState::Running(MissionProgress {
+                leg: 0,
+                arrived: None,
+                applied: false,
+            }),
+            (MissionCommand::Pause, MissionState::Running(progress)) => {
+                MissionState::Paused(progress)
+            }
+            (MissionCommand::Resume, MissionState::Paused(progress)) => {
+                // The hold restarts since the robot may have drifted while paused
+                MissionState::Running(MissionProgress {
+                    arrived: None,
+                    applied: false,
+                    ..progress
+                })
+            }
+            (MissionCommand::Abort, MissionState::Running(_) | MissionState::Paused(_)) => {
+                MissionState::Aborted
+            }
+            _ => continue,
+        };
+
+        // Stop driving toward the waypoint, the robot's own stabilization holds it in place
+        if !matches!(new_state, MissionState::Running(_)) {
+            if let Ok(robot) = robot.get_single() {
+                cmds.entity(robot).remove::<(TargetPose, SpeedLimit)>();
+            }
+        }
+
+        *state = new_state;
+    }
+}
+
+fn execute_mission(
+    mut cmds: Commands,
+    time: Res<Time>,
+    mission: Res<Mission>,
+    mut state: ResMut<MissionState>,
+    robot: Query<(Entity, Option<&CurrentPose>), With<Robot>>,
+) {
+    let MissionState::Running(progress) = &mut *state else {
+        return;
+    };
+    let Ok((robot, pose)) = robot.get_single() else {
+        return;
+    };
+
+    let Some(waypoint) = mission.waypoints.get(progress.leg) else {
+        cmds.entity(robot).remove::<(TargetPose, SpeedLimit)>();
+        *state = MissionState::Complete;
+        return;
+    };
+
+    if !progress.applied {
+        progress.applied = true;
+
+        let mut entity = cmds.entity(robot);
+        entity.insert((
+            TargetPose(Pose {
+                position: vec3a(waypoint.x, waypoint.y, 0.0),
+                rotation: Quat::IDENTITY,
+            }),
+            SpeedLimit(waypoint.speed),
+            DepthTarget(Meters(waypoint.depth)),
+        ));
+        if let Some(heading) = waypoint.heading {
+            entity.insert(OrientationTarget(heading_quat(heading)));
+        }
+    }
+
+    let Some(pose) = pose else {
+        return;
+    };
+
+    let now = time.elapsed();
+    let distance = pose.0.position.truncate().distance(waypoint.position());
+
+    match progress.arrived {
+        None if distance <= waypoint.acceptance_radius => {
+            progress.arrived = Some(now);
+        }
+        Some(arrived)
+            if now - arrived >= Duration::try_from_secs_f32(waypoint.hold).unwrap_or_default() =>
+        {
+            progress.leg += 1;
+            progress.arrived = None;
+            progress.applied = false;
+        }
+        _ => {}
+    }
+}
+
+/// Heading is clockwise from north while yaw is counter clockwise about +Z
+pub fn heading_quat(heading: f32) -> Quat {
+    Quat::from_rotation_z(-heading.to_radians())
+}
+
+/// Time left in the mission assuming each leg is driven in a straight line at its speed limit
+pub fn eta(
+    mission: &Mission,
+    progress: &MissionProgress,
+    position: Vec2,
+    now: Duration,
+) -> Duration {
+    let mut seconds = 0.0;
+    let mut from = position;
+
+    for (idx, waypoint) in mission.waypoints.iter().enumerate().skip(progress.leg) {
+        seconds += from.distance(waypoint.position()) / waypoint.speed.max(0.01);
+        seconds += waypoint.hold;
+        from = waypoint.position();
+
+        if idx == progress.leg {
+            if let Some(arrived) = progress.arrived {
+                seconds -= (now - arrived).as_secs_f32().min(waypoint.hold);
+            }
+        }
+    }
+
+    Duration::try_from_secs_f32(seconds.max(0.0)).unwrap_or_default()
+}
+
+fn mission_ui(
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+    mut mission: ResMut<Mission>,
+    state: Res<MissionState>,
+    frame: Option<Res<LocalFrame>>,
+    robot: Query<Option<&CurrentPose>, With<Robot>>,
+
+    mut commands: EventWriter<MissionCommand>,
+) {
+    let pose = robot.get_single().ok().flatten();
+
+    egui::Window::new("Mission").show(contexts.ctx_mut(), |ui| {
+        ui.label("Shift + right double click on the plot to add a waypoint");
+
+        let editable = !matches!(*state, MissionState::Running(_) | MissionState::Paused(_));
+        let mut new_mission = mission.clone();
+
+        ui.add_enabled_ui(editable, |ui| {
+            waypoint_grid(ui, &mut new_mission, frame.as_deref());
+        });
+
+        if new_mission != *mission {
+            *mission = new_mission;
+        }
+
+        ui.separator();
+
+        let legs = mission.waypoints.len();
+        match *state {
+            MissionState::Idle => {
+                ui.label("Status: Idle");
+            }
+            MissionState::Running(progress) | MissionState::Paused(progress) => {
+                let paused = matches!(*state, MissionState::Paused(_));
+                let status = match (paused, progress.arrived) {
+                    (true, _) => "Paused",
+                    (false, Some(_)) => "Holding",
+                    (false, None) => "Driving",
+                };
+                ui.label(format!(
+                    "Status: {status}, waypoint {} of {legs}",
+                    progress.leg + 1
+                ));
+                ui.add(
+                    ProgressBar::new(progress.leg as f32 / legs.max(1) as f32).show_percentage(),
+                );
+
+                if let Some(pose) = pose {
+                    let position = pose.0.position.truncate();
+                    let eta = eta(&mission, &progress, position, time.elapsed());
+                    ui.label(format!("ETA: {:.0}s", eta.as_secs_f32()));
+
+                    if let Some(waypoint) = mission.waypoints.get(progress.leg) {
+                        ui.label(format!(
+                            "{:.2}m from waypoint",
+                            position.distance(waypoint.position())
+                        ));
+                    }
+                } else {
+                    ui.label("ETA: No position");
+                }
+            }
+            MissionState::Complete => {
+                ui.label("Status: Complete");
+            }
+            MissionState::Aborted => {
+                ui.label("Status: Aborted");
+            }
+        }
+
+        ui.horizontal(|ui| match *state {
+            MissionState::Running(_) => {
+                if ui.button("Pause").clicked() {
+                    commands.send(MissionCommand::Pause);
+                }
+                if ui.button("Abort").clicked() {
+                    commands.send(MissionCommand::Abort);
+                }
+            }
+            MissionState::Paused(_) => {
+                if ui.button("Resume").clicked() {
+                    commands.send(MissionCommand::Resume);
+                }
+                if ui.button("Abort").clicked() {
+                    commands.send(MissionCommand::Abort);
+                }
+            }
+            _ => {
+                if ui
+                    .add_enabled(pose.is_some() && legs > 0, egui::Button::new("Start"))
+                    .clicked()
+                {
+                    commands.send(MissionCommand::Start);
+                }
+            }
+        });
+    });
+}
+
+fn waypoint_grid(ui: &mut egui::Ui, mission: &mut Mission, frame: Option<&LocalFrame>) {
+    if mission.waypoints.is_empty() {
+        ui.label("No waypoints");
+        return;
+    }
+
+    let mut remove = None;
+    let mut swap = None;
+
+    Grid::new("Waypoints").striped(true).show(ui, |ui| {
+        for label in [
+            "#", "X", "Y", "Lat/Lon", "Depth", "Heading", "Hold", "Speed", "Radius", "",
+        ] {
+            ui.label(label);
+        }
+        ui.end_row();
+
+        let count = mission.waypoints.len();
+        for (idx, waypoint) in mission.waypoints.iter_mut().enumerate() {
+            ui.label(format!("{}", idx + 1));
+            // Waypoints entered as coordinates are moved by editing the coordinates
+            let local = waypoint.geo.is_none();
+            ui.add_enabled(
+                local,
+                DragValue::new(&mut waypoint.x).speed(0.05).suffix("m"),
+            );
+            ui.add_enabled(
+                local,
+                DragValue::new(&mut waypoint.y).speed(0.05).suffix("m"),
+            );
+
+            ui.horizontal(|ui| {
+                let mut geographic = waypoint.geo.is_some();
+                ui.add_enabled(
+                    frame.is_some() || geographic,
+                    egui::Checkbox::without_text(&mut geographic),
+                );
+                if geographic != waypoint.geo.is_some() {
+                    waypoint.geo = frame
+                        .filter(|_| geographic)
+                        .map(|it| it.geo_from_local(waypoint.position()));
+                }
+
+                if let Some(geo) = &mut waypoint.geo {
+                    ui.add(
+                        DragValue::new(&mut geo.lat)
+                            .speed(0.00001)
+                            .min_decimals(6)
+                            .suffix("°"),
+                    );
+                    ui.add(
+                        DragValue::new(&mut geo.lon)
+                            .speed(0.00001)
+                            .min_decimals(6)
+                            .suffix("°"),
+                    );
+                }
+            });
+            ui.add(
+                DragValue::new(&mut waypoint.depth)
+                    .speed(0.05)
+                    .range(0.0..=100.0)
+                    .suffix("m"),
+            );
+
+            ui.horizontal(|ui| {
+                let mut hold_heading = waypoint.heading.is_some();
+                ui.checkbox(&mut hold_heading, "");
+                if hold_heading != waypoint.heading.is_some() {
+                    waypoint.heading = hold_heading.then_some(0.0);
+                }
+
+                if let Some(heading) = &mut waypoint.heading {
+                    ui.add(
+                        DragValue::new(heading)
+                            .speed(1.0)
+                            .range(0.0..=360.0)
+                            .suffix("°"),
+                    );
+                }
+            });
+
+            ui.add(
+                DragValue::new(&mut waypoint.hold)
+                    .speed(0.5)
+                    .range(HOLD_RANGE)
+                    .suffix("s"),
+            );
+            ui.add(
+                DragValue::new(&mut waypoint.speed)
+                    .speed(0.01)
+                    .range(SPEED_RANGE)
+                    .suffix("m/s"),
+            );
+            ui.add(
+                DragValue::new(&mut waypoint.acceptance_radius)
+                    .speed(0.05)
+                    .range(0.1..=10.0)
+                    .suffix("m"),
+            );
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(idx > 0, egui::Button::new("⏶")).clicked() {
+                    swap = Some((idx, idx - 1));
+                }
+                if ui
+                    .add_enabled(idx + 1 < count, egui::Button::new("⏷"))
+                    .clicked()
+                {
+                    swap = Some((idx, idx + 1));
+                }
+                if ui.button("🗑").clicked() {
+                    remove = Some(idx);
+                }
+            });
+            ui.end_row();
+        }
+    });
+
+    if let Some((a, b)) = swap {
+        mission.waypoints.swap(a, b);
+    }
+    if let Some(idx) = remove {
+        mission.waypoints.remove(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_rejects_bad_waypoints() {
+        let mut mission = Mission {
+            waypoints: vec![Waypoint::new(0.0, 0.0)],
+            keep_out: Vec::new(),
+        };
+        assert!(mission.validate().is_ok());
+
+        mission.waypoints[0].hold = -1.0;
+        assert!(mission.validate().is_err());
+
+        mission.waypoints[0].hold = f32::NAN;
+        assert!(mission.validate().is_err());
+
+        mission.waypoints[0].hold = 10.0;
+        mission.waypoints[0].speed = f32::INFINITY;
+        assert!(mission.validate().is_err());
+    }
+}
@@ -0,0 +1,461 @@
+//! Brings the robot back to where it was armed and surfaces it
+//!
+//! Triggered by the pilot or by a failsafe: a sagging battery or every camera dropping out. The
+//! robot drives home at the transit depth and then ascends. Any pilot input takes over from the
+//! behavior until it is resumed
+
+use std::time::Duration;
+
+use bevy::{
+    app::{Plugin, Update},
+    core::Name,
+    math::Vec2,
+    prelude::{
+        App, Commands, Entity, Event, EventReader, EventWriter, Has, IntoSystemConfigs, Local,
+        Query, Ref, Res, ResMut, Resource, With,
+    },
+    time::Time,
+};
+use bevy_egui::EguiContexts;
+use common::{
+    components::{
+        Armed, CameraDefinition, DepthMeasurement, DepthTarget, MeasuredVoltage,
+        MovementContribution, Robot,
+    },
+    types::units::Meters,
+};
+use egui::{Align2, Color32, DragValue, RichText};
+use tracing::{info, warn};
+
+use crate::{
+    mission::MissionCommand,
+    trajectory::{CurrentPose, Pose, SpeedLimit, TargetPose},
+    transect::AbortTransect,
+};
+
+/// Pilot input above this many newtons takes over
+pub const OVERRIDE_FORCE: f32 = 5.0;
+/// Pilot input above this many newton meters takes over
+pub const OVERRIDE_TORQUE: f32 = 1.0;
+/// Depth in meters at which the robot counts as surfaced
+pub const SURFACED_DEPTH: f32 = 0.2;
+
+pub struct ReturnHomePlugin;
+
+impl Plugin for ReturnHomePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReturnHomeSettings>()
+            .init_resource::<Home>()
+            .init_resource::<ReturnHomeState>()
+            .add_event::<ReturnHome>()
+            .add_event::<CancelReturnHome>()
+            .add_systems(
+                Update,
+                (
+                    record_home,
+                    check_failsafes,
+                    handle_commands,
+                    pilot_override,
+                    return_home,
+                    return_home_ui,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ReturnHomeSettings {
+    /// Meters below the surface to drive home at, shallow enough to clear anything on the bottom
+    pub transit_depth: f32,
+    /// Meters per second
+    pub speed: f32,
+    /// Meters from home at which the robot starts ascending
+    pub arrival_radius: f32,
+
+    pub low_battery_failsafe: bool,
+    /// Volts under which the battery is considered low
+    pub low_battery_voltage: f32,
+    /// How long the voltage has to stay low, so current spikes don't trigger it
+    pub low_battery_time: Duration,
+
+    pub lost_video_failsafe: bool,
+    /// How long every camera has to be gone
+    pub lost_video_time: Duration,
+}
+
+impl Default for ReturnHomeSettings {
+    fn default() -> Self {
+        Self {
+            transit_depth: 1.0,
+            speed: 0.3,
+            arrival_radius: 1.0,
+
+            low_battery_failsafe: true,
+            low_battery_voltage: 13.2,
+            low_battery_time: Duration::from_secs(5),
+
+            lost_video_failsafe: true,
+            lost_video_time: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Where the robot was when it was armed
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct Home(pub Option<Pose>);
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReturnHomeState {
+    #[default]
+    Idle,
+    Returning(&'static str),
+    Surfacing(&'static str),
+    Surfaced,
+    /// The pilot took over, holds the phase to resume
+    Overridden(ReturnPhase, &'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnPhase {
+    Returning,
+    Surfacing,
+}
+
+/// Starts returning home, the reason is shown to the pilot
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ReturnHome(pub &'static str);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CancelReturnHome;
+
+fn record_home(
+    mut home: ResMut<Home>,
+    robot: Query<(Ref<Armed>, Option<&CurrentPose>), With<Robot>>,
+) {
+    let Ok((armed, pose)) = robot.get_single() else {
+        return;
+    };
+
+    if *armed != Armed::Armed {
+        return;
+    }
+
+    // Also covers arming before there was a position
+    if armed.is_changed() || home.0.is_none() {
+        if let Some(pose) = pose {
+            info!("Recorded home at {:?}", pose.0.position);
+            home.0 = Some(pose.0);
+        }
+    }
+}
+
+fn check_failsafes(
+    mut low_since: Local<Option<Duration>>,
+    mut video_lost_since: Local<Option<Duration>>,
+    mut had_video: Local<bool>,
+
+    time: Res<Time>,
+    settings: Res<ReturnHomeSettings>,
+    state: Res<ReturnHomeState>,
+    robot: Query<(&Armed, Option<&MeasuredVoltage>), With<Robot>>,
+    cameras: Query<(), With<CameraDefinition>>,
+    mut trigger: EventWriter<ReturnHome>,
+) {
+    let now = time.elapsed();
+
+    let Ok((armed, voltage)) = robot.get_single() else {
+        *low_since = None;
+        *video_lost_since = None;
+        *had_video = false;
+        return;
+    };
+    if *armed != Armed::Armed {
+        *low_since = None;
+        *video_lost_since = None;
+        return;
+    }
+
+    let low = voltage.is_some_and(|it| it.0 .0 < settings.low_battery_voltage);
+    *low_since = low.then(|| low_since.unwrap_or(now));
+
+    // Only counts as lost once there has been video to lose
+    let has_video = !cameras.is_empty();
+    *had_video |= has_video;
+    *video_lost_since = (*had_video && !has_video).then(|| video_lost_since.unwrap_or(now));
+
+    // Failsafes only start the behavior, they don't take back control from the pilot
+    if *state != ReturnHomeState::Idle {
+        return;
+    }
+
+    if settings.low_battery_failsafe
+        && low_since.is_some_and(|it| now - it >= settings.low_battery_time)
+    {
+        warn!("Low battery failsafe triggered");
+        trigger.send(ReturnHome("Low battery"));
+    } else if settings.lost_video_failsafe
+        && video_lost_since.is_some_and(|it| now - it >= settings.lost_video_time)
+    {
+        warn!("Lost video failsafe triggered");
+        trigger.send(ReturnHome("Lost video"));
+    }
+}
+
+fn handle_commands(
+    mut cmds: Commands,
+    home: Res<Home>,
+    mut state: ResMut<ReturnHomeState>,
+    robot: Query<Entity, With<Robot>>,
+    mut start: EventReader<ReturnHome>,
+    mut cancel: EventReader<CancelReturnHome>,
+    mut mission: EventWriter<MissionCommand>,
+    mut transect: EventWriter<AbortTransect>,
+) {
+    if let Some(ReturnHome(reason)) = start.read().last() {
+        let already_returning = matches!(
+            *state,
+            ReturnHomeState::Returning(_) | ReturnHomeState::Surfacing(_)
+        );
+
+        if !already_returning {
+            // Other behaviors would fight over the target
+            mission.send(MissionCommand::Abort);
+            transect.send(AbortTransect);
+
+            // Without a home there is nowhere to drive to, surfacing is still safe
+            *state = if home.0.is_some() {
+                ReturnHomeState::Returning(reason)
+            } else {
+                warn!("No home recorded, surfacing in place");
+                ReturnHomeState::Surfacing(reason)
+            };
+        }
+    }
+
+    if cancel.read().count() > 0 && *state != ReturnHomeState::Idle {
+        if let Ok(robot) = robot.get_single() {
+            cmds.entity(robot)
+                .remove::<(TargetPose, SpeedLimit, DepthTarget)>();
+        }
+
+        *state = ReturnHomeState::Idle;
+    }
+}
+
+fn pilot_override(
+    mut cmds: Commands,
+    mut state: ResMut<ReturnHomeState>,
+    robot: Query<Entity, With<Robot>>,
+    contributions: Query<(&Name, &MovementContribution)>,
+) {
+    let (phase, reason) = match *state {
+        ReturnHomeState::Returning(reason) => (ReturnPhase::Returning, reason),
+        ReturnHomeState::Surfacing(reason) => (ReturnPhase::Surfacing, reason),
+        _ => return,
+    };
+
+    // The surface names the contributions from its gamepads after them
+    let piloting = contributions.iter().any(|(name, contribution)| {
+        name.as_str().starts_with("HID")
+            && (contribution.0.force.length() > OVERRIDE_FORCE
+                || contribution.0.torque.length() > OVERRIDE_TORQUE)
+    });
+
+    if piloting {
+        info!("Pilot took over from return to home");
+
+        if let Ok(robot) = robot.get_single() {
+            cmds.entity(robot)
+                .remove::<(TargetPose, SpeedLimit, DepthTarget)>();
+        }
+
+        *state = ReturnHomeState::Overridden(phase, reason);
+    }
+}
+
+fn return_home(
+    mut applied: Local<Option<ReturnHomeState>>,
+
+    mut cmds: Commands,
+    settings: Res<ReturnHomeSettings>,
+    home: Res<Home>,
+    mut state: ResMut<ReturnHomeState>,
+    robot: Query<
+        (
+            Entity,
+            Option<&CurrentPose>,
+            Option<&DepthMeasurement>,
+            Has<TargetPose>,
+        ),
+        With<Robot>,
+    >,
+) {
+    let Ok((robot, pose, depth, has_target)) = robot.get_single() else {
+        return;
+    };
+
+    // Changing the target replans the route, so it's only set when the phase changes
+    let phase_changed = *applied != Some(*state);
+    *applied = Some(*state);
+
+    match *state {
+        ReturnHomeState::Returning(reason) => {
+            let Some(home) = home.0 else {
+                *state = ReturnHomeState::Surfacing(reason);
+                return;
+            };
+
+            // Aborting a mission clears the target a frame later, so put it back
+            if phase_changed || !has_target {
+                cmds.entity(robot).insert((
+                    TargetPose(home),
+                    SpeedLimit(settings.speed),
+                    DepthTarget(Meters(settings.transit_depth)),
+                ));
+            }
+
+            let distance =
+                pose.map(|it| Vec2::distance(it.0.position.truncate(), home.position.truncate()));
+            if distance.is_some_and(|it| it <= settings.arrival_radius) {
+                *state = ReturnHomeState::Surfacing(reason);
+            }
+        }
+        ReturnHomeState::Surfacing(_) => {
+            if phase_changed {
+                cmds.entity(robot)
+                    .remove::<(TargetPose, SpeedLimit)>()
+                    .insert(DepthTarget(Meters(0.0)));
+            }
+
+            if depth.is_some_and(|it| it.depth.0 <= SURFACED_DEPTH) {
+                info!("Surfaced");
+                *state = ReturnHomeState::Surfaced;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn return_home_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<ReturnHomeSettings>,
+    mut home: ResMut<Home>,
+    mut state: ResMut<ReturnHomeState>,
+    robot: Query<Option<&CurrentPose>, With<Robot>>,
+    mut start: EventWriter<ReturnHome>,
+    mut cancel: EventWriter<CancelReturnHome>,
+) {
+    let ctx = contexts.ctx_mut();
+    let pose = robot.get_single().ok().flatten();
+
+    // Impossible to miss while the robot is driving itself
+    let banner = match *state {
+        ReturnHomeState::Idle => None,
+        ReturnHomeState::Returning(reason) => {
+            Some((format!("RETURNING HOME: {reason}"), Color32::RED))
+        }
+        ReturnHomeState::Surfacing(reason) => Some((format!("SURFACING: {reason}"), Color32::RED)),
+        ReturnHomeState::Surfaced => Some(("SURFACED".to_owned(), Color32::DARK_GREEN)),
+        ReturnHomeState::Overridden(_, reason) => Some((
+            format!("PILOT OVERRIDE, return home paused: {reason}"),
+            Color32::ORANGE,
+        )),
+    };
+    if let Some((text, color)) = banner {
+        egui::Window::new("Return Home Status")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_TOP, [0.0, 10.0])
+            .show(ctx, |ui| {
+                ui.label(RichText::new(text).size(28.0).strong().color(color));
+            });
+    }
+
+    egui::Window::new("Return Home").show(ctx, |ui| {
+        match home.0 {
+            Some(home) => {
+                let position = home.position;
+                ui.label(format!(
+                    "Home: x: {:.02}, y: {:.02}",
+                    position.x, position.y
+                ));
+            }
+            None => {
+                ui.label("Home: Not recorded, arm the robot with a position");
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(pose.is_some(), egui::Button::new("Set Home Here"))
+                .clicked()
+            {
+                home.0 = pose.map(|it| it.0);
+            }
+
+            match *state {
+                ReturnHomeState::Idle | ReturnHomeState::Surfaced => {
+                    let button = egui::Button::new(RichText::new("Return Home").strong())
+                        .fill(Color32::from_rgb(200, 40, 40));
+                    if ui.add(button).clicked() {
+                        start.send(ReturnHome("Pilot request"));
+                    }
+                }
+                ReturnHomeState::Overridden(phase, reason) => {
+                    if ui.button("Resume").clicked() {
+                        *state = match phase {
+                            ReturnPhase::Returning => ReturnHomeState::Returning(reason),
+                            ReturnPhase::Surfacing => ReturnHomeState::Surfacing(reason),
+                        };
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel.send(CancelReturnHome);
+                    }
+                }
+                _ => {
+                    if ui.button("Cancel").clicked() {
+                        cancel.send(CancelReturnHome);
+                    }
+                }
+            }
+        });
+
+        ui.separator();
+
+        let mut new_settings = *settings;
+
+        ui.horizontal(|ui| {
+            ui.label("Transit depth:");
+            ui.add(
+                DragValue::new(&mut new_settings.transit_depth)
+                    .speed(0.05)
+                    .range(0.0..=50.0)
+                    .suffix("m"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Speed:");
+            ui.add(
+                DragValue::new(&mut new_settings.speed)
+                    .speed(0.01)
+                    .range(0.05..=1.0)
+                    .suffix("m/s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut new_settings.low_battery_failsafe, "Low battery below");
+            ui.add(
+                DragValue::new(&mut new_settings.low_battery_voltage)
+                    .speed(0.05)
+                    .range(0.0..=30.0)
+                    .suffix("V"),
+            );
+        });
+        ui.checkbox(&mut new_settings.lost_video_failsafe, "Lost video");
+
+        if new_settings != *settings {
+            *settings = new_settings;
+        }
+    });
+}
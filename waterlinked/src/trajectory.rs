@@ -1,14 +1,26 @@
+pub mod profile;
+
+use std::{collections::VecDeque, time::Duration};
+
 use bevy::{
     app::{Plugin, Update},
     core::Name,
     math::{Quat, Vec3A},
-    prelude::{App, Commands, Component, Entity, Local, Query, With},
+    prelude::{App, Commands, Component, Entity, Local, Query, Ref, Res, With},
+    time::Time,
 };
 use common::{
     bundles::MovementContributionBundle,
     components::{MovementContribution, Robot, RobotId},
 };
 use motor_math::glam::MovementGlam;
+use profile::{Trajectory, TrajectoryLimits};
+use tracing::warn;
+
+use crate::{
+    keep_out::{plan_route, KeepOutSettings, KeepOutZone, RouteStatus},
+    mission::Mission,
+};
 
 pub const FORCE_GAIN: f32 = 0.01;
 pub const TORQUE_GAIN: f32 = 0.5;
@@ -17,12 +29,13 @@ pub struct TrajectoryPlugin;
 
 impl Plugin for TrajectoryPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, trajectory_follower);
+        app.init_resource::<TrajectoryLimits>()
+            .add_systems(Update, trajectory_follower);
     }
 }
 
 // Consider using Isometry3d in bevy 15
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Pose {
     pub position: Vec3A,
     pub rotation: Quat,
@@ -34,6 +47,10 @@ pub struct TargetPose(pub Pose);
 #[derive(Component, Debug)]
 pub struct CurrentPose(pub Pose);
 
+/// Caps the speed of trajectories toward the `TargetPose`, in m/s
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SpeedLimit(pub f32);
+
 // NOTE: Outputs are unscaled
 pub fn move_toward(current_pose: &Pose, target_pose: &Pose) -> MovementGlam {
     let mut translation =
@@ -50,33 +67,131 @@ pub fn move_toward(current_pose: &Pose, target_pose: &Pose) -> MovementGlam {
     }
 }
 
+/// Legs toward the target, each driven with its own trajectory
+struct Route {
+    trajectory: Trajectory,
+    started: Duration,
+    /// Where the following legs end
+    remaining: VecDeque<Pose>,
+    /// Zones the route was planned around
+    zones: Vec<KeepOutZone>,
+}
+
 // FIXME: Ideally, this would run on the rov
 fn trajectory_follower(
     mut movement_contributer: Local<Option<Entity>>,
+    mut route: Local<Option<Route>>,
 
     mut cmds: Commands,
-    robot: Query<(&CurrentPose, &TargetPose, &RobotId), With<Robot>>,
+    time: Res<Time>,
+    limits: Res<TrajectoryLimits>,
+    keep_out: Res<KeepOutSettings>,
+    mission: Res<Mission>,
+    robot: Query<
+        (
+            Entity,
+            &CurrentPose,
+            Ref<TargetPose>,
+            &RobotId,
+            Option<&SpeedLimit>,
+        ),
+        With<Robot>,
+    >,
 ) {
-    let Ok((current_pose, target_pose, robot_id)) = robot.get_single() else {
-        if let Some(entity) = *movement_contributer {
+    let despawn = |cmds: &mut Commands, movement_contributer: &mut Option<Entity>| {
+        if let Some(entity) = movement_contributer.take() {
             cmds.entity(entity).despawn();
-            *movement_contributer = None;
         }
+    };
+
+    let Ok((robot, current_pose, target_pose, robot_id, speed_limit)) = robot.get_single() else {
+        despawn(&mut cmds, &mut *movement_contributer);
+        *route = None;
 
         return;
     };
+    let speed_limit = speed_limit.map(|it| it.0);
+
+    // Plan a new route from wherever the robot is whenever the target or the zones move
+    let replan = target_pose.is_changed()
+        || limits.is_changed()
+        || keep_out.is_changed()
+        || route.as_ref().is_none_or(|it| it.zones != mission.keep_out);
+    if replan {
+        let target = target_pose.0;
+        let plan = plan_route(
+            current_pose.0.position.truncate(),
+            target.position.truncate(),
+            &mission.keep_out,
+            keep_out.margin,
+        );
+
+        let plan = match plan {
+            Ok(plan) => plan,
+            Err(reason) => {
+                warn!("Rejected target: {reason}");
+                cmds.entity(robot)
+                    .insert(RouteStatus::Rejected(reason))
+                    .remove::<TargetPose>();
+                despawn(&mut cmds, &mut *movement_contributer);
+                *route = None;
+
+                return;
+            }
+        };
+
+        cmds.entity(robot).insert(RouteStatus::Planned {
+            detours: plan.points.len() - 1,
+            clipped: plan.clipped,
+        });
+
+        let mut remaining = plan
+            .points
+            .iter()
+            .map(|it| Pose {
+                position: it.extend(target.position.z).into(),
+                rotation: target.rotation,
+            })
+            .collect::<VecDeque<_>>();
+        let first = remaining.pop_front().unwrap_or(target);
+
+        *route = Some(Route {
+            trajectory: Trajectory::new(current_pose.0, first, &limits, speed_limit),
+            started: time.elapsed(),
+            remaining,
+            zones: mission.keep_out.clone(),
+        });
+    }
+    let Some(route) = &mut *route else {
+        unreachable!();
+    };
+
+    // Move on to the next leg once this one is done
+    let mut elapsed = (time.elapsed() - route.started).as_secs_f32();
+    if elapsed >= route.trajectory.duration() {
+        if let Some(next) = route.remaining.pop_front() {
+            route.trajectory = Trajectory::new(route.trajectory.end, next, &limits, speed_limit);
+            route.started = time.elapsed();
+            elapsed = 0.0;
+        }
+    }
+
+    let (setpoint, _) = route.trajectory.sample(elapsed);
 
-    let mut movement = move_toward(&current_pose.0, &target_pose.0);
+    let mut movement = move_toward(&current_pose.0, &setpoint);
     movement.force *= FORCE_GAIN;
     movement.torque *= TORQUE_GAIN;
 
     if let Some(entity) = *movement_contributer {
         cmds.entity(entity).insert(MovementContribution(movement));
     } else {
-        cmds.spawn(MovementContributionBundle {
-            name: Name::new("Trajectory Follower"),
-            contribution: MovementContribution(movement),
-            robot: *robot_id,
-        });
+        *movement_contributer = Some(
+            cmds.spawn(MovementContributionBundle {
+                name: Name::new("Trajectory Follower"),
+                contribution: MovementContribution(movement),
+                robot: *robot_id,
+            })
+            .id(),
+        );
     }
 }
@@ -0,0 +1,236 @@
+//! Time parameterized motion between two poses
+//!
+//! The robot moves along the straight line between the poses with a trapezoidal velocity profile,
+//! accelerating up to its cruise speed and decelerating into the end point. The limits along the
+//! line are the tightest ones that keep every axis within its own limits
+
+use bevy::{math::Vec3A, prelude::Resource};
+
+use crate::trajectory::Pose;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct TrajectoryLimits {
+    /// Meters per second along each axis
+    pub max_velocity: Vec3A,
+    /// Meters per second squared along each axis
+    pub max_acceleration: Vec3A,
+}
+
+impl Default for TrajectoryLimits {
+    fn default() -> Self {
+        Self {
+            max_velocity: Vec3A::new(0.3, 0.3, 0.2),
+            max_acceleration: Vec3A::new(0.1, 0.1, 0.1),
+        }
+    }
+}
+
+/// Motion along a single axis from 0 to `distance`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrapezoidProfile {
+    pub distance: f32,
+    /// Peak velocity, lower than the limit when there isn't room to reach it
+    pub velocity: f32,
+    pub acceleration: f32,
+    accelerate_time: f32,
+    cruise_time: f32,
+}
+
+impl TrapezoidProfile {
+    pub fn new(distance: f32, max_velocity: f32, max_acceleration: f32) -> Self {
+        let distance = distance.abs();
+
+        if distance == 0.0 || max_velocity <= 0.0 || max_acceleration <= 0.0 {
+            return Self {
+                distance,
+                velocity: 0.0,
+                acceleration: 0.0,
+                accelerate_time: 0.0,
+                cruise_time: 0.0,
+            };
+        }
+
+        // Too short to reach full speed, the profile becomes a triangle
+        if distance <= max_velocity * max_velocity / max_acceleration {
+            let accelerate_time = (distance / max_acceleration).sqrt();
+
+            return Self {
+                distance,
+                velocity: max_acceleration * accelerate_time,
+                acceleration: max_acceleration,
+                accelerate_time,
+                cruise_time: 0.0,
+            };
+        }
+
+        let accelerate_time = max_velocity / max_acceleration;
+        let accelerate_distance = max_velocity * accelerate_time / 2.0;
+
+        Self {
+            distance,
+            velocity: max_velocity,
+            acceleration: max_acceleration,
+            accelerate_time,
+            cruise_time: (distance - 2.0 * accelerate_distance) / max_velocity,
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        2.0 * self.accelerate_time + self.cruise_time
+    }
+
+    /// Position and velocity `time` seconds after the start
+    pub fn sample(&self, time: f32) -> (f32, f32) {
+        let time = time.clamp(0.0, self.duration());
+        let decelerate_start = self.accelerate_time + self.cruise_time;
+
+        if time < self.accelerate_time {
+            (
+                self.acceleration * time * time / 2.0,
+                self.acceleration * time,
+            )
+        } else if time < decelerate_start {
+            let accelerate_distance = self.velocity * self.accelerate_time / 2.0;
+            let cruise = time - self.accelerate_time;
+
+            (accelerate_distance + self.velocity * cruise, self.velocity)
+        } else {
+            let remaining = self.duration() - time;
+
+            (
+                self.distance - self.acceleration * remaining * remaining / 2.0,
+                self.acceleration * remaining,
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Trajectory {
+    pub start: Pose,
+    pub end: Pose,
+    profile: TrapezoidProfile,
+}
+
+impl Trajectory {
+    /// `speed_limit` additionally caps the speed along the path, in m/s
+    pub fn new(
+        start: Pose,
+        end: Pose,
+        limits: &TrajectoryLimits,
+        speed_limit: Option<f32>,
+    ) -> Self {
+        let delta = end.position - start.position;
+        let direction = delta.normalize_or_zero();
+
+        // Moving along the line at speed `s` moves each axis at `s * |direction|`
+        let along_path = |limit: Vec3A| {
+            (0..3)
+                .filter(|axis| direction[*axis] != 0.0)
+                .map(|axis| limit[axis] / direction[axis].abs())
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        let max_velocity =
+            along_path(limits.max_velocity).min(speed_limit.unwrap_or(f32::INFINITY));
+        let max_acceleration = along_path(limits.max_acceleration);
+
+        Self {
+            start,
+            end,
+            profile: TrapezoidProfile::new(delta.length(), max_velocity, max_acceleration),
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.profile.duration()
+    }
+
+    /// Setpoint and velocity feed forward `time` seconds after the start
+    pub fn sample(&self, time: f32) -> (Pose, Vec3A) {
+        let direction = (self.end.position - self.start.position).normalize_or_zero();
+        let (distance, speed) = self.profile.sample(time);
+
+        let duration = self.duration();
+        let progress = if duration > 0.0 {
+            (time / duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        (
+            Pose {
+                position: self.start.position + direction * distance,
+                rotation: self.start.rotation.slerp(self.end.rotation, progress),
+            },
+            direction * speed,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{vec3a, Quat};
+
+    use super::*;
+
+    fn pose(x: f32, y: f32, z: f32) -> Pose {
+        Pose {
+            position: vec3a(x, y, z),
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    #[test]
+    fn trapezoid_reaches_cruise_speed() {
+        let profile = TrapezoidProfile::new(10.0, 1.0, 0.5);
+
+        assert_eq!(profile.velocity, 1.0);
+        // 2s to accelerate, 2s to decelerate and 8m at full speed
+        assert!((profile.duration() - 12.0).abs() < 1e-4);
+        assert!((profile.sample(6.0).1 - 1.0).abs() < 1e-4);
+        assert!((profile.sample(12.0).0 - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn short_moves_become_triangles() {
+        let profile = TrapezoidProfile::new(1.0, 1.0, 0.5);
+
+        assert!(profile.velocity < 1.0);
+        assert!((profile.duration() - 2.0 * 2f32.sqrt()).abs() < 1e-4);
+        assert!((profile.sample(profile.duration()).0 - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn axes_stay_within_limits() {
+        let limits = TrajectoryLimits::default();
+        let trajectory = Trajectory::new(pose(0.0, 0.0, 0.0), pose(4.0, -1.0, 0.5), &limits, None);
+        let duration = trajectory.duration();
+
+        for step in 0..=100 {
+            let (_, velocity) = trajectory.sample(duration * step as f32 / 100.0);
+            assert!(velocity.abs().cmple(limits.max_velocity + 1e-4).all());
+        }
+
+        let (end, velocity) = trajectory.sample(duration);
+        assert!(end.position.distance(vec3a(4.0, -1.0, 0.5)) < 1e-3);
+        assert!(velocity.length() < 1e-3);
+    }
+
+    #[test]
+    fn speed_limit_caps_path_speed() {
+        let limits = TrajectoryLimits {
+            max_velocity: Vec3A::splat(1.0),
+            max_acceleration: Vec3A::splat(10.0),
+        };
+        let trajectory = Trajectory::new(
+            pose(0.0, 0.0, 0.0),
+            pose(10.0, 10.0, 0.0),
+            &limits,
+            Some(0.2),
+        );
+
+        let (_, velocity) = trajectory.sample(trajectory.duration() / 2.0);
+        assert!(velocity.length() <= 0.2 + 1e-4);
+    }
+}
@@ -0,0 +1,318 @@
+//! Follows a transect line found by the surface's transect pipeline
+//!
+//! While engaged the robot drives forward at the set speed, strafes and yaws to keep the line
+//! centered and straight in the downward camera and holds its altitude over the seafloor
+
+use std::time::Duration;
+
+use bevy::{
+    app::{Plugin, Update},
+    core::Name,
+    math::Vec3A,
+    prelude::{
+        App, Commands, Entity, Event, EventReader, EventWriter, Local, Query, Ref, Res, ResMut,
+        Resource, With,
+    },
+    time::Time,
+};
+use bevy_egui::EguiContexts;
+use common::{
+    bundles::MovementContributionBundle,
+    components::{DepthMeasurement, MovementContribution, Robot, RobotId, TransectLine},
+    types::units::Degrees,
+};
+use egui::{DragValue, ProgressBar};
+use motor_math::glam::MovementGlam;
+
+use crate::trajectory::CurrentPose;
+
+/// Newtons per m/s of commanded speed
+pub const SPEED_GAIN: f32 = 40.0;
+/// Newtons per unit of line offset, an offset of 1 is the line at the edge of the image
+pub const CENTER_GAIN: f32 = 15.0;
+/// Newton meters per radian the line is rotated from straight ahead
+pub const YAW_GAIN: f32 = 3.0;
+/// Newtons per meter of altitude error
+pub const ALTITUDE_GAIN: f32 = 30.0;
+
+/// The run is aborted if the line isn't seen for this long
+pub const LINE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Line detections with less confidence are treated as not seeing the line
+pub const MIN_CONFIDENCE: f32 = 0.2;
+
+pub struct TransectPlugin;
+
+impl Plugin for TransectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TransectSettings>()
+            .init_resource::<TransectState>()
+            .add_event::<EngageTransect>()
+            .add_event::<AbortTransect>()
+            .add_systems(Update, (handle_commands, transect_follower, transect_ui));
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct TransectSettings {
+    /// Meters per second
+    pub speed: f32,
+    /// Meters above the seafloor
+    pub altitude: f32,
+    /// Distance in meters after which the run is complete
+    pub length: f32,
+}
+
+impl Default for TransectSettings {
+    fn default() -> Self {
+        Self {
+            speed: 0.2,
+            altitude: 1.0,
+            length: 10.0,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub enum TransectState {
+    #[default]
+    Idle,
+    Following(TransectRun),
+    Complete(TransectRun),
+    Aborted(TransectRun, &'static str),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TransectRun {
+    pub started: Duration,
+    pub elapsed: Duration,
+    /// Meters travelled along the line
+    pub distance: f32,
+    pub last_seen: Duration,
+    last_position: Option<Vec3A>,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EngageTransect;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AbortTransect;
+
+fn handle_commands(
+    time: Res<Time>,
+    mut state: ResMut<TransectState>,
+    mut engage: EventReader<EngageTransect>,
+    mut abort: EventReader<AbortTransect>,
+) {
+    let now = time.elapsed();
+
+    if engage.read().count() > 0 && !matches!(*state, TransectState::Following(_)) {
+        *state = TransectState::Following(TransectRun {
+            started: now,
+            elapsed: Duration::ZERO,
+            distance: 0.0,
+            last_seen: now,
+            last_position: None,
+        });
+    }
+
+    if abort.read().count() > 0 {
+        if let TransectState::Following(run) = *state {
+            *state = TransectState::Aborted(run, "Aborted by operator");
+        }
+    }
+}
+
+fn transect_follower(
+    mut movement_contributer: Local<Option<Entity>>,
+
+    mut cmds: Commands,
+    time: Res<Time>,
+    settings: Res<TransectSettings>,
+    mut state: ResMut<TransectState>,
+    robot: Query<
+        (
+            &RobotId,
+            Option<Ref<TransectLine>>,
+            Option<&DepthMeasurement>,
+            Option<&CurrentPose>,
+        ),
+        With<Robot>,
+    >,
+) {
+    let movement = 'movement: {
+        let TransectState::Following(run) = &mut *state else {
+            break 'movement None;
+        };
+        let Ok((robot_id, line, depth, pose)) = robot.get_single() else {
+            *state = TransectState::Aborted(*run, "Robot disconnected");
+            break 'movement None;
+        };
+
+        let now = time.elapsed();
+        run.elapsed = now - run.started;
+
+        let line = line.filter(|it| it.confidence >= MIN_CONFIDENCE);
+        if line.as_ref().is_some_and(|it| it.is_changed()) {
+            run.last_seen = now;
+        }
+        if now - run.last_seen > LINE_TIMEOUT {
+            *state = TransectState::Aborted(*run, "Lost the line");
+            break 'movement None;
+        }
+
+        // Measure progress with the position estimate when there is one, otherwise assume the
+        // commanded speed is being reached
+        if let Some(pose) = pose {
+            let position = pose.0.position;
+            if let Some(last_position) = run.last_position {
+                run.distance += (position - last_position).truncate().length();
+            }
+            run.last_position = Some(position);
+        } else {
+            run.distance += settings.speed * time.delta_secs();
+        }
+
+        if run.distance >= settings.length {
+            *state = TransectState::Complete(*run);
+            break 'movement None;
+        }
+
+        let Some(line) = line else {
+            // Keep the last command until the line is found again or the timeout is hit
+            break 'movement Some((*robot_id, None));
+        };
+
+        // Slow down while the line is off center so it isn't lost out the side of the image
+        let forward = settings.speed * SPEED_GAIN * (1.0 - line.offset.abs()).max(0.0);
+        let strafe = line.offset * CENTER_GAIN;
+        // The line leaning right means the robot needs to turn clockwise
+        let yaw = -line.angle.0 * YAW_GAIN;
+
+        // An altitude of zero means the robot has no altimeter reading
+        let heave = depth
+            .map(|it| it.altitude.0)
+            .filter(|it| *it > 0.0)
+            .map(|altitude| (settings.altitude - altitude) * ALTITUDE_GAIN)
+            .unwrap_or(0.0);
+
+        Some((
+            *robot_id,
+            Some(MovementGlam {
+                force: Vec3A::new(strafe, forward, heave),
+                torque: Vec3A::new(0.0, 0.0, yaw),
+            }),
+        ))
+    };
+
+    match movement {
+        Some((_, None)) => {}
+        Some((robot_id, Some(movement))) => {
+            if let Some(entity) = *movement_contributer {
+                cmds.entity(entity).insert(MovementContribution(movement));
+            } else {
+                *movement_contributer = Some(
+                    cmds.spawn(MovementContributionBundle {
+                        name: Name::new("Transect Follower"),
+                        contribution: MovementContribution(movement),
+                        robot: robot_id,
+                    })
+                    .id(),
+                );
+            }
+        }
+        None => {
+            if let Some(entity) = movement_contributer.take() {
+                cmds.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+fn transect_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<TransectSettings>,
+    state: Res<TransectState>,
+    robot: Query<Option<&TransectLine>, With<Robot>>,
+
+    mut engage: EventWriter<EngageTransect>,
+    mut abort: EventWriter<AbortTransect>,
+) {
+    let Ok(line) = robot.get_single() else {
+        return;
+    };
+
+    egui::Window::new("Transect").show(contexts.ctx_mut(), |ui| {
+        let mut new_settings = *settings;
+
+        ui.horizontal(|ui| {
+            ui.label("Speed:");
+            ui.add(
+                DragValue::new(&mut new_settings.speed)
+                    .speed(0.01)
+                    .range(0.0..=1.0)
+                    .suffix("m/s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Altitude:");
+            ui.add(
+                DragValue::new(&mut new_settings.altitude)
+                    .speed(0.05)
+                    .range(0.2..=10.0)
+                    .suffix("m"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Length:");
+            ui.add(
+                DragValue::new(&mut new_settings.length)
+                    .speed(0.1)
+                    .range(0.5..=100.0)
+                    .suffix("m"),
+            );
+        });
+
+        if new_settings != *settings {
+            *settings = new_settings;
+        }
+
+        ui.separator();
+
+        if let Some(line) = line {
+            ui.label(format!(
+                "Line: {:.1}, {:.0}% off center, {:.0}% confidence",
+                Degrees::from(line.angle),
+                line.offset * 100.0,
+                line.confidence * 100.0
+            ));
+        } else {
+            ui.label("Line: Not detected, start the transect pipeline on the downward camera");
+        }
+
+        let (status, run) = match &*state {
+            TransectState::Idle => ("Idle", None),
+            TransectState::Following(run) => ("Following", Some(run)),
+            TransectState::Complete(run) => ("Complete", Some(run)),
+            TransectState::Aborted(run, reason) => (*reason, Some(run)),
+        };
+        ui.label(format!("Status: {status}"));
+
+        if let Some(run) = run {
+            ui.add(
+                ProgressBar::new(run.distance / settings.length)
+                    .text(format!("{:.1}m / {:.1}m", run.distance, settings.length)),
+            );
+            ui.label(format!("Elapsed: {:.0}s", run.elapsed.as_secs_f32()));
+        }
+
+        ui.horizontal(|ui| {
+            if matches!(*state, TransectState::Following(_)) {
+                if ui.button("Abort").clicked() {
+                    abort.send(AbortTransect);
+                }
+            } else if ui.button("Engage").clicked() {
+                engage.send(EngageTransect);
+            }
+        });
+    });
+}
@@ -1,7 +1,7 @@
 use bevy::{
     app::{App, Plugin, Startup, Update},
     core::Name,
-    math::{vec3a, Quat},
+    math::{vec2, vec3a, Quat},
     prelude::{Commands, Entity, EventWriter, Local, Query, Res, ResMut, With, World},
     reflect::List,
 };
@@ -11,11 +11,16 @@ use common::{
     components::{Robot, RobotId},
     sync::{ConnectToPeer, DisconnectPeer, MdnsPeers, Peer},
 };
-use egui::{CentralPanel, Color32, PointerButton, Visuals};
-use egui_plot::{Line, MarkerShape, Plot, PlotItem, PlotPoint, PlotPoints, Points};
+use egui::{CentralPanel, Color32, PointerButton, Stroke, Visuals};
+use egui_plot::{Line, MarkerShape, Plot, PlotItem, PlotPoint, PlotPoints, Points, Polygon};
 use tracing::{error, info, warn};
 
 use crate::{
+    estimator::PoseEstimate,
+    geodetic::LocalFrame,
+    keep_out::{RouteStatus, ZoneEditor},
+    map::{MapSettings, MapTiles},
+    mission::{Mission, MissionState, Waypoint},
     trajectory::{CurrentPose, Pose, TargetPose},
     DARK_MODE,
 };
@@ -51,17 +56,27 @@ fn main_pane(
             &Name,
             Option<&CurrentPose>,
             Option<&TargetPose>,
+            Option<&PoseEstimate>,
+            Option<&RouteStatus>,
             &RobotId,
         ),
         With<Robot>,
     >,
     mdns_peers: Option<Res<MdnsPeers>>,
     peers: Query<&Peer>,
+    mut mission: ResMut<Mission>,
+    mission_state: Res<MissionState>,
+    frame: Option<Res<LocalFrame>>,
+    mut map: ResMut<MapTiles>,
+    mut map_settings: ResMut<MapSettings>,
+    zone_editor: Res<ZoneEditor>,
 
     mut disconnect: EventWriter<DisconnectPeer>,
 ) {
     CentralPanel::default().show(contexts.ctx_mut(), |ui| {
-        if let Ok((robot, name, current_pose, target_pose, robot_id)) = robots.get_single() {
+        if let Ok((robot, name, current_pose, target_pose, estimate, route, robot_id)) =
+            robots.get_single()
+        {
             ui.horizontal(|ui| {
                 ui.label(format!("Connected to {}", name.as_str()));
                 if ui.button("Disconnect").clicked() {
@@ -76,6 +91,26 @@ fn main_pane(
                     "Current Location: x: {:.02}, y: {:.02}, z: {:.02}",
                     pos.x, pos.y, pos.z,
                 ));
+                if let Some(frame) = &frame {
+                    let geo = frame.geo_from_local(pos.truncate());
+                    ui.label(format!("Global Location: {:.6}, {:.6}", geo.lat, geo.lon));
+                }
+                if let Some(estimate) = estimate {
+                    let velocity = estimate.velocity;
+                    ui.label(format!(
+                        "Uncertainty: ±{:.02}m, Velocity: ({:.02}, {:.02}, {:.02})",
+                        estimate.horizontal_std(),
+                        velocity.x,
+                        velocity.y,
+                        velocity.z
+                    ));
+                    if estimate.rejected > 0 {
+                        ui.colored_label(
+                            Color32::ORANGE,
+                            format!("Rejected {} acoustic fixes in a row", estimate.rejected),
+                        );
+                    }
+                }
             } else {
                 ui.label("Current Location: None");
             }
@@ -93,6 +128,23 @@ fn main_pane(
             } else {
                 ui.label("Target Location: None");
             }
+            match route {
+                Some(RouteStatus::Rejected(reason)) => {
+                    ui.colored_label(Color32::RED, format!("Target rejected: {reason}"));
+                }
+                Some(RouteStatus::Planned { detours, clipped }) if target_pose.is_some() => {
+                    if *clipped {
+                        ui.colored_label(
+                            Color32::ORANGE,
+                            "Target is inside a keep-out zone, driving to its edge instead",
+                        );
+                    }
+                    if *detours > 0 {
+                        ui.label(format!("Avoiding keep-out zones through {detours} points"));
+                    }
+                }
+                _ => {}
+            }
             if let (Some(current_pose), Some(target_pose)) = (current_pose, target_pose) {
                 let current_pos = current_pose.0.position;
                 let target_pos = target_pose.0.position;
@@ -112,6 +164,10 @@ fn main_pane(
                 let current_pos = current_pose.0.position;
                 position_history.push(PlotPoint::new(current_pos.x as f64, current_pos.y as f64));
 
+                if frame.is_some() {
+                    ui.checkbox(&mut map_settings.enabled, "Show map");
+                }
+
                 let response = Plot::new("Position Track")
                     .data_aspect(1.0)
                     .include_x(0.0)
@@ -119,6 +175,31 @@ fn main_pane(
                     .width(500.0)
                     .height(500.0)
                     .show(ui, |ui| {
+                        if let Some(frame) = frame.as_deref().filter(|_| map_settings.enabled) {
+                            map.draw(ui, frame);
+                        }
+
+                        for zone in &mission.keep_out {
+                            let vertices = zone
+                                .vertices
+                                .iter()
+                                .map(|it| [it.x as f64, it.y as f64])
+                                .collect::<Vec<_>>();
+
+                            if zone.is_closed() {
+                                ui.polygon(
+                                    Polygon::new(zone.name.clone(), PlotPoints::from(vertices))
+                                        .fill_color(Color32::from_rgba_unmultiplied(255, 0, 0, 40))
+                                        .stroke(Stroke::new(1.5, Color32::RED)),
+                                );
+                            } else {
+                                ui.line(
+                                    Line::new(zone.name.clone(), PlotPoints::from(vertices))
+                                        .color(Color32::RED),
+                                );
+                            }
+                        }
+
                         ui.line(Line::new("Track", position_history.as_slice()).name("Track"));
                         ui.points(
                             Points::new(
@@ -130,6 +211,25 @@ fn main_pane(
                             .radius(3.0),
                         );
 
+                        if !mission.waypoints.is_empty() {
+                            let path = mission
+                                .waypoints
+                                .iter()
+                                .map(|it| [it.x as f64, it.y as f64])
+                                .collect::<Vec<_>>();
+
+                            ui.line(
+                                Line::new("Mission", PlotPoints::from(path.clone()))
+                                    .color(Color32::GRAY),
+                            );
+                            ui.points(
+                                Points::new("Waypoints", path)
+                                    .shape(MarkerShape::Diamond)
+                                    .color(Color32::DARK_GRAY)
+                                    .radius(4.0),
+                            );
+                        }
+
                         if let Some(target_pose) = target_pose {
                             let target_pos = target_pose.0.position;
                             ui.points(
@@ -144,18 +244,55 @@ fn main_pane(
                         }
                     });
 
+                if let Some((frame, mouse)) = frame.as_deref().zip(response.response.hover_pos()) {
+                    let position = response.transform.value_from_position(mouse);
+                    let geo = frame.geo_from_local(vec2(position.x as f32, position.y as f32));
+                    ui.label(format!("Cursor: {:.6}, {:.6}", geo.lat, geo.lon));
+                }
+
                 if response
                     .response
                     .double_clicked_by(PointerButton::Secondary)
                 {
                     let mouse = response.response.hover_pos();
+                    let editing_mission = ui.input(|it| it.modifiers.shift);
+                    let mission_running = matches!(
+                        *mission_state,
+                        MissionState::Running(_) | MissionState::Paused(_)
+                    );
+
                     if let Some(mouse) = mouse {
                         let position = response.transform.value_from_position(mouse);
 
-                        cmds.entity(robot).insert(TargetPose(Pose {
-                            position: vec3a(position.x as f32, position.y as f32, 0.0),
-                            rotation: Quat::IDENTITY,
-                        }));
+                        let drawing = zone_editor
+                            .drawing
+                            .filter(|it| *it < mission.keep_out.len());
+
+                        if let Some(zone) = drawing {
+                            mission.keep_out[zone]
+                                .vertices
+                                .push(vec2(position.x as f32, position.y as f32));
+                        } else if editing_mission {
+                            if !mission_running {
+                                let waypoint = Waypoint {
+                                    x: position.x as f32,
+                                    y: position.y as f32,
+                                    geo: None,
+                                    // Continue with the settings of the previous leg
+                                    ..mission
+                                        .waypoints
+                                        .last()
+                                        .copied()
+                                        .unwrap_or(Waypoint::new(0.0, 0.0))
+                                };
+                                mission.waypoints.push(waypoint);
+                            }
+                        } else {
+                            cmds.entity(robot).insert(TargetPose(Pose {
+                                position: vec3a(position.x as f32, position.y as f32, 0.0),
+                                rotation: Quat::IDENTITY,
+                            }));
+                        }
                     }
                 }
             } else {
@@ -0,0 +1,84 @@
+//! Adds the position estimate and where the robot is headed to the rerun stream from
+//! `common::visualization`, in the positioning system's frame under `world/`
+
+use bevy::{
+    app::{App, Plugin, PostUpdate},
+    math::Vec3A,
+    prelude::{resource_exists, IntoSystemConfigs, Local, Query, Res, With},
+};
+use common::{
+    components::Robot,
+    visualization::{transform, Rerun, RerunPlugin},
+};
+use rerun::{LineStrips3D, Points3D};
+
+use crate::trajectory::{CurrentPose, TargetPose};
+
+/// The path only grows once the robot has moved this far, in meters
+const PATH_SPACING: f32 = 0.1;
+/// Keeps the logged path from growing forever over a long session
+const MAX_PATH_POINTS: usize = 10_000;
+
+pub struct VisualizationPlugin;
+
+impl Plugin for VisualizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RerunPlugin {
+            app_id: "autonomous_controller",
+        })
+        .add_systems(
+            PostUpdate,
+            (log_pose, log_target).run_if(resource_exists::<Rerun>),
+        );
+    }
+}
+
+fn log_pose(
+    mut path: Local<Vec<Vec3A>>,
+
+    rerun: Res<Rerun>,
+    // TODO(low): Support multiple robots
+    robot: Query<&CurrentPose, With<Robot>>,
+) {
+    let Ok(CurrentPose(pose)) = robot.get_single() else {
+        return;
+    };
+
+    rerun.log("world/robot", &transform(pose.position, pose.rotation));
+
+    if path
+        .last()
+        .is_some_and(|it| it.distance(pose.position) < PATH_SPACING)
+    {
+        return;
+    }
+
+    path.push(pose.position);
+    if path.len() > MAX_PATH_POINTS {
+        path.remove(0);
+    }
+
+    let strip = path.iter().map(|it| it.to_array()).collect::<Vec<_>>();
+    rerun.log("world/path", &LineStrips3D::new([strip]));
+}
+
+fn log_target(rerun: Res<Rerun>, robot: Query<(&CurrentPose, Option<&TargetPose>), With<Robot>>) {
+    let Ok((CurrentPose(current), target)) = robot.get_single() else {
+        return;
+    };
+
+    // Logged even without a target so the last one disappears
+    let (points, strips) = match target {
+        Some(TargetPose(target)) => (
+            vec![target.position.to_array()],
+            vec![vec![
+                current.position.to_array(),
+                target.position.to_array(),
+            ]],
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+
+    rerun.log("world/target", &Points3D::new(points));
+    rerun.log("world/route", &LineStrips3D::new(strips));
+}
@@ -1,84 +1,454 @@
-use std::time::Duration;
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use bevy::{
-    app::{Plugin, PreUpdate, Startup, Update},
-    math::vec3a,
-    prelude::{App, Commands, Entity, Event, EventReader, Query, ResMut, With},
+    app::{Plugin, PreStartup, Startup, Update},
+    prelude::{App, Commands, Event, Local, Res, ResMut, Resource},
+    time::Time,
 };
-use bevy_tokio_tasks::TokioTasksRuntime;
-use common::components::{Orientation, Robot};
-use tracing::{error, warn};
+use bevy_egui::EguiContexts;
+use bevy_tokio_tasks::{TaskContext, TokioTasksRuntime};
+use egui::{ComboBox, DragValue, Grid};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+use tracing::{error, info, warn};
 
-use crate::{
-    trajectory::{CurrentPose, Pose},
-    waterlinked_api::{wl_to_mate_coords, Location, WaterLinked},
+use crate::waterlinked_api::{
+    About, GenericConfig, GpsFix, Location, ReceiverConfig, ReceiverHealth, WaterLinked,
 };
 
+pub const SETTINGS_FILE: &str = "waterlinked.toml";
+/// How often the topside and locator GPS are polled, they change much slower than the position
+pub const GPS_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest wait between reconnection attempts
+pub const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
 pub struct WaterlinkedPlugin;
 
 impl Plugin for WaterlinkedPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<WaterlinkedLocationEvent>();
+        app.add_event::<WaterlinkedLocationEvent>()
+            .init_resource::<WaterlinkedStatus>()
+            .init_resource::<WaterlinkedConfig>()
+            .init_resource::<ReceiverStatus>()
+            .init_resource::<TopsideGps>()
+            .init_resource::<LocatorGps>();
 
-        app.add_systems(Startup, start_task);
-        app.add_systems(PreUpdate, pose_updater);
+        app.add_systems(PreStartup, load_settings)
+            .add_systems(Startup, start_task)
+            .add_systems(Update, waterlinked_ui);
     }
 }
 
 #[derive(Event, Debug)]
 pub struct WaterlinkedLocationEvent(pub Location);
 
-fn start_task(runtime: ResMut<TokioTasksRuntime>) {
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct WaterlinkedSettings {
+    pub url: String,
+    /// Simulate a locator instead of talking to the topside
+    pub mock: bool,
+    /// Seconds before a request is given up on
+    pub timeout: f32,
+    /// Position requests per second
+    pub poll_rate: f32,
+}
+
+impl Default for WaterlinkedSettings {
+    fn default() -> Self {
+        Self {
+            // url: "https://demo.waterlinked.com/".to_owned(),
+            url: "http://192.168.2.94".to_owned(),
+            mock: false,
+            timeout: 1.0,
+            poll_rate: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct WaterlinkedStatus {
+    pub state: ConnectionState,
+    pub mock: bool,
+    pub about: Option<About>,
+    /// Failed requests since the last successful one
+    pub failures: u32,
+    pub last_error: Option<String>,
+    /// When the last position was received, measured from app startup
+    pub last_update: Option<Duration>,
+}
+
+/// The topside's configuration as of the last connection
+#[derive(Resource, Debug, Clone, Default)]
+pub struct WaterlinkedConfig {
+    pub generic: Option<GenericConfig>,
+    pub receivers: Vec<ReceiverConfig>,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ReceiverStatus(pub Vec<ReceiverHealth>);
+
+/// GPS position and compass heading of the topside
+#[derive(Resource, Debug, Clone, Default)]
+pub struct TopsideGps(pub Option<GpsFix>);
+
+/// Global position of the locator, computed by the topside
+#[derive(Resource, Debug, Clone, Default)]
+pub struct LocatorGps(pub Option<GpsFix>);
+
+#[derive(Debug, Clone)]
+pub enum WaterlinkedCommand {
+    SetConfig(GenericConfig),
+    Reconnect,
+}
+
+/// Sends commands to the task talking to the topside
+#[derive(Resource, Debug, Clone)]
+pub struct WaterlinkedCommands(mpsc::UnboundedSender<WaterlinkedCommand>);
+
+impl WaterlinkedCommands {
+    pub fn send(&self, command: WaterlinkedCommand) {
+        if self.0.send(command).is_err() {
+            error!("Waterlinked task is not running");
+        }
+    }
+}
+
+fn load_settings(mut cmds: Commands) {
+    let res: anyhow::Result<WaterlinkedSettings> = try {
+        let settings = fs::read_to_string(SETTINGS_FILE).context("Read settings")?;
+        toml::from_str(&settings).context("Parse settings")?
+    };
+
+    let settings = match res {
+        Ok(settings) => settings,
+        Err(err) => {
+            warn!("Using default waterlinked settings: {err:?}");
+            WaterlinkedSettings::default()
+        }
+    };
+
+    cmds.insert_resource(settings);
+}
+
+fn start_task(
+    mut cmds: Commands,
+    runtime: ResMut<TokioTasksRuntime>,
+    settings: Res<WaterlinkedSettings>,
+    mut status: ResMut<WaterlinkedStatus>,
+) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    cmds.insert_resource(WaterlinkedCommands(tx));
+
+    status.mock = settings.mock;
+    let settings = settings.clone();
+
     runtime.spawn_background_task(|mut ctx| async move {
-        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / 4.0));
-        // let api = WaterLinked::new("https://demo.waterlinked.com/".try_into().unwrap());
-        let api = WaterLinked::new("192.168.2.94".try_into().unwrap());
+        let api = if settings.mock {
+            info!("Using mock waterlinked");
+            WaterLinked::mock()
+        } else {
+            let res: anyhow::Result<WaterLinked> = try {
+                let url = settings.url.parse().context("Parse url")?;
+                WaterLinked::new(url, Duration::from_secs_f32(settings.timeout))?
+            };
+
+            match res {
+                Ok(api) => api,
+                Err(err) => {
+                    error!("Could not create waterlinked client: {err:?}");
+                    report_failure(&mut ctx, err, 1).await;
+                    return;
+                }
+            }
+        };
+
+        let mut interval = tokio::time::interval(Duration::from_secs_f32(1.0 / settings.poll_rate));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut connected = false;
+        let mut failures = 0;
+        let mut last_gps: Option<Instant> = None;
 
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                Some(command) = rx.recv() => {
+                    match command {
+                        WaterlinkedCommand::Reconnect => connected = false,
+                        WaterlinkedCommand::SetConfig(config) => {
+                            match api.set_config(&config).await.context("Set config") {
+                                // Reconnecting reads back what the topside accepted
+                                Ok(()) => connected = false,
+                                Err(err) => error!("Waterlinked error: {err:?}"),
+                            }
+                        }
+                    }
 
-            let location = match api.get_location().await.context("Get Location") {
-                Ok(location) => location,
-                Err(err) => {
-                    error!("Waterlinked error: {err:?}");
                     continue;
                 }
+            }
+
+            let res = if connected {
+                let poll_gps = last_gps.is_none_or(|it| it.elapsed() >= GPS_INTERVAL);
+                if poll_gps {
+                    last_gps = Some(Instant::now());
+                }
+
+                poll(&mut ctx, &api, poll_gps).await
+            } else {
+                connect(&mut ctx, &api).await
             };
 
-            ctx.run_on_main_thread(|ctx| ctx.world.send_event(WaterlinkedLocationEvent(location)))
-                .await;
+            match res {
+                Ok(()) => {
+                    connected = true;
+                    failures = 0;
+                }
+                Err(err) => {
+                    if connected || failures == 0 {
+                        error!("Waterlinked error: {err:?}");
+                    }
+
+                    connected = false;
+                    failures += 1;
+                    report_failure(&mut ctx, err, failures).await;
+
+                    // Back off so an unplugged topside isn't hammered with requests
+                    let backoff = interval
+                        .period()
+                        .mul_f32(2f32.powi(failures.min(16) as i32));
+                    tokio::time::sleep(backoff.min(MAX_BACKOFF)).await;
+                }
+            }
         }
     });
 }
 
-fn pose_updater(
-    mut cmds: Commands,
-    robot: Query<(Entity, Option<&Orientation>), With<Robot>>,
-    mut reader: EventReader<WaterlinkedLocationEvent>,
-) {
-    let Ok((robot, orientation)) = robot.get_single() else {
-        return;
+/// Reads everything that only changes when the topside is reconfigured
+async fn connect(ctx: &mut TaskContext, api: &WaterLinked) -> anyhow::Result<()> {
+    let (about, generic, receivers) = tokio::try_join!(
+        async { api.get_about().await.context("Get about") },
+        async { api.get_config().await.context("Get config") },
+        async { api.get_receivers().await.context("Get receivers") },
+    )?;
+
+    info!(
+        "Connected to waterlinked topside, version {}",
+        about.version
+    );
+
+    ctx.run_on_main_thread(move |ctx| {
+        let mut status = ctx.world.resource_mut::<WaterlinkedStatus>();
+        status.state = ConnectionState::Connected;
+        status.about = Some(about);
+        status.failures = 0;
+        status.last_error = None;
+
+        ctx.world.insert_resource(WaterlinkedConfig {
+            generic: Some(generic),
+            receivers,
+        });
+    })
+    .await;
+
+    Ok(())
+}
+
+async fn poll(ctx: &mut TaskContext, api: &WaterLinked, poll_gps: bool) -> anyhow::Result<()> {
+    let location = api.get_location().await.context("Get location")?;
+
+    let gps = if poll_gps {
+        let (topside, locator) = tokio::try_join!(
+            async { api.get_surface_gps().await.context("Get topside gps") },
+            async { api.get_locator_gps().await.context("Get locator gps") },
+        )?;
+
+        Some((topside, locator))
+    } else {
+        None
     };
 
-    for event in reader.read() {
-        let Location {
-            position_valid,
-            x,
-            y,
-            z,
-            ..
-        } = event.0.clone();
-
-        let (x, y, z) = wl_to_mate_coords(x, y, z);
-
-        if position_valid {
-            cmds.entity(robot).insert(CurrentPose(Pose {
-                position: vec3a(x, y, z),
-                rotation: orientation.map(|it| it.0).unwrap_or_default(),
-            }));
-        } else {
-            warn!("Recieved bad UGPS update");
+    ctx.run_on_main_thread(move |ctx| {
+        let now = ctx.world.resource::<Time>().elapsed();
+
+        let mut status = ctx.world.resource_mut::<WaterlinkedStatus>();
+        status.state = ConnectionState::Connected;
+        status.failures = 0;
+        status.last_error = None;
+        status.last_update = Some(now);
+
+        ctx.world
+            .insert_resource(ReceiverStatus(location.receivers()));
+
+        if let Some((topside, locator)) = gps {
+            ctx.world.insert_resource(TopsideGps(Some(topside)));
+            ctx.world.insert_resource(LocatorGps(Some(locator)));
         }
+
+        ctx.world.send_event(WaterlinkedLocationEvent(location));
+    })
+    .await;
+
+    Ok(())
+}
+
+async fn report_failure(ctx: &mut TaskContext, err: anyhow::Error, failures: u32) {
+    ctx.run_on_main_thread(move |ctx| {
+        let mut status = ctx.world.resource_mut::<WaterlinkedStatus>();
+        status.state = ConnectionState::Disconnected;
+        status.failures = failures;
+        status.last_error = Some(format!("{err:#}"));
+    })
+    .await;
+}
+
+fn waterlinked_ui(
+    mut draft: Local<Option<GenericConfig>>,
+
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+    status: Res<WaterlinkedStatus>,
+    config: Res<WaterlinkedConfig>,
+    receivers: Res<ReceiverStatus>,
+    topside: Res<TopsideGps>,
+    locator: Res<LocatorGps>,
+    commands: Option<Res<WaterlinkedCommands>>,
+) {
+    if config.is_changed() {
+        *draft = config.generic.clone();
     }
+
+    egui::Window::new("Waterlinked").show(contexts.ctx_mut(), |ui| {
+        let state = match status.state {
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Connected => "Connected",
+            ConnectionState::Disconnected => "Disconnected",
+        };
+        let mock = if status.mock { " (mock)" } else { "" };
+        ui.label(format!("Status: {state}{mock}"));
+
+        if let Some(about) = &status.about {
+            ui.label(format!("Version: {}", about.version));
+        }
+        if let Some(last_update) = status.last_update {
+            ui.label(format!(
+                "Last position: {:.1}s ago",
+                (time.elapsed() - last_update).as_secs_f32()
+            ));
+        }
+        if let Some(err) = &status.last_error {
+            ui.label(format!("Error ({} failures): {err}", status.failures));
+        }
+
+        if let Some(commands) = &commands {
+            if ui.button("Reconnect").clicked() {
+                commands.send(WaterlinkedCommand::Reconnect);
+            }
+        }
+
+        ui.separator();
+
+        for (name, fix) in [("Topside", &topside.0), ("Locator", &locator.0)] {
+            match fix {
+                Some(fix) if fix.has_fix() => {
+                    ui.label(format!(
+                        "{name}: {:.6}, {:.6} ({} sats, {:.1} hdop)",
+                        fix.lat, fix.lon, fix.numsats, fix.hdop
+                    ));
+                }
+                _ => {
+                    ui.label(format!("{name}: No fix"));
+                }
+            }
+        }
+        if let Some(heading) = topside.0.as_ref().and_then(GpsFix::heading) {
+            ui.label(format!("Topside heading: {heading:.1} deg"));
+        }
+
+        ui.separator();
+
+        Grid::new("Receivers").striped(true).show(ui, |ui| {
+            ui.label("Receiver");
+            ui.label("Distance");
+            ui.label("RSSI");
+            ui.label("NSD");
+            ui.label("Valid");
+            ui.end_row();
+
+            for (idx, receiver) in receivers.0.iter().enumerate() {
+                let id = config
+                    .receivers
+                    .get(idx)
+                    .map(|it| it.id)
+                    .unwrap_or(idx as u32 + 1);
+
+                ui.label(format!("{id}"));
+                ui.label(format!("{:.2}m", receiver.distance));
+                ui.label(format!("{:.0}dBm", receiver.rssi));
+                ui.label(format!("{:.0}dBm", receiver.nsd));
+                ui.label(if receiver.valid { "Yes" } else { "No" });
+                ui.end_row();
+            }
+        });
+
+        let Some(new_config) = &mut *draft else {
+            return;
+        };
+
+        ui.collapsing("Configuration", |ui| {
+            for (label, source) in [
+                ("Compass", &mut new_config.compass),
+                ("GPS", &mut new_config.gps),
+            ] {
+                ComboBox::from_label(label)
+                    .selected_text(source.as_str())
+                    .show_ui(ui, |ui| {
+                        for option in ["static", "onboard", "external"] {
+                            ui.selectable_value(source, option.to_owned(), option);
+                        }
+                    });
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Static position:");
+                ui.add(DragValue::new(&mut new_config.static_lat).speed(0.00001));
+                ui.add(DragValue::new(&mut new_config.static_lon).speed(0.00001));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Static heading:");
+                ui.add(
+                    DragValue::new(&mut new_config.static_orientation)
+                        .range(0.0..=360.0)
+                        .suffix("deg"),
+                );
+            });
+
+            let modified = Some(&*new_config) != config.generic.as_ref();
+            ui.add_enabled_ui(modified, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Apply").clicked() {
+                        if let Some(commands) = &commands {
+                            commands.send(WaterlinkedCommand::SetConfig(new_config.clone()));
+                        }
+                    }
+                    if ui.button("Revert").clicked() {
+                        *new_config = config.generic.clone().unwrap_or_default();
+                    }
+                });
+            });
+        });
+    });
 }
@@ -1,35 +1,72 @@
 // https://demo.waterlinked.com/swagger/
 
+use std::{
+    f64::consts::TAU,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
 use anyhow::Context;
 use reqwest::Url;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+/// Client for the Water Linked Underwater GPS topside's REST api
+///
+/// Can also be backed by a simulated locator for development without the hardware
 pub struct WaterLinked {
-    api_endpoint: Url,
-    client: reqwest::Client,
+    backend: Backend,
+}
+
+enum Backend {
+    Http {
+        api_endpoint: Url,
+        client: reqwest::Client,
+    },
+    Mock(MockLocator),
 }
 
 impl WaterLinked {
-    pub fn new(api_endpoint: Url) -> Self {
-        let client = reqwest::Client::new();
+    /// Every request fails if it takes longer than `timeout`
+    pub fn new(api_endpoint: Url, timeout: Duration) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .build()
+            .context("Build client")?;
 
+        Ok(Self {
+            backend: Backend::Http {
+                api_endpoint,
+                client,
+            },
+        })
+    }
+
+    pub fn mock() -> Self {
         Self {
-            api_endpoint,
-            client,
+            backend: Backend::Mock(MockLocator::new()),
         }
     }
 
-    pub async fn get_location(&self) -> anyhow::Result<Location> {
-        let response: Location = self
-            .client
-            .get(
-                self.api_endpoint
-                    .join("/api/v1/position/acoustic/filtered")
-                    .context("Build url")?,
-            )
+    pub fn is_mock(&self) -> bool {
+        matches!(self.backend, Backend::Mock(_))
+    }
+
+    async fn get<T: DeserializeOwned>(
+        api_endpoint: &Url,
+        client: &reqwest::Client,
+        path: &str,
+    ) -> anyhow::Result<T> {
+        let response = client
+            .get(api_endpoint.join(path).context("Build url")?)
             .send()
             .await
             .context("Send request")?
+            .error_for_status()
+            .context("Bad status")?
             .json()
             .await
             .context("Await Json")?;
@@ -37,41 +74,121 @@ impl WaterLinked {
         Ok(response)
     }
 
-    pub async fn get_locator_gps(&self) -> anyhow::Result<GpsFix> {
-        let response: GpsFix = self
-            .client
-            .get(
-                self.api_endpoint
-                    .join("/api/v1/position/global")
-                    .context("Build url")?,
-            )
+    async fn put<T: Serialize>(
+        api_endpoint: &Url,
+        client: &reqwest::Client,
+        path: &str,
+        body: &T,
+    ) -> anyhow::Result<()> {
+        client
+            .put(api_endpoint.join(path).context("Build url")?)
+            .json(body)
             .send()
             .await
             .context("Send request")?
-            .json()
-            .await
-            .context("Await Json")?;
+            .error_for_status()
+            .context("Bad status")?;
 
-        Ok(response)
+        Ok(())
+    }
+
+    pub async fn get_about(&self) -> anyhow::Result<About> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/about").await,
+            Backend::Mock(_) => Ok(About {
+                version: "mock".to_owned(),
+                chipid: "mock".to_owned(),
+            }),
+        }
+    }
+
+    /// The locator's position relative to the topside, filtered by the topside
+    pub async fn get_location(&self) -> anyhow::Result<Location> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/position/acoustic/filtered").await,
+            Backend::Mock(mock) => Ok(mock.location(true)),
+        }
+    }
+
+    /// The locator's position relative to the topside, without any filtering
+    pub async fn get_raw_location(&self) -> anyhow::Result<Location> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/position/acoustic/raw").await,
+            Backend::Mock(mock) => Ok(mock.location(false)),
+        }
     }
 
+    pub async fn get_locator_gps(&self) -> anyhow::Result<GpsFix> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/position/global").await,
+            Backend::Mock(mock) => Ok(mock.locator_gps()),
+        }
+    }
+
+    /// GPS position and compass heading of the topside
     pub async fn get_surface_gps(&self) -> anyhow::Result<GpsFix> {
-        let response: GpsFix = self
-            .client
-            .get(
-                self.api_endpoint
-                    .join("/api/v1/position/master")
-                    .context("Build url")?,
-            )
-            .send()
-            .await
-            .context("Send request")?
-            .json()
-            .await
-            .context("Await Json")?;
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/position/master").await,
+            Backend::Mock(mock) => Ok(mock.surface_gps()),
+        }
+    }
 
-        Ok(response)
+    pub async fn get_config(&self) -> anyhow::Result<GenericConfig> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/config/generic").await,
+            Backend::Mock(mock) => Ok(mock.config()),
+        }
+    }
+
+    pub async fn set_config(&self, config: &GenericConfig) -> anyhow::Result<()> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::put(api_endpoint, client, "/api/v1/config/generic", config).await,
+            Backend::Mock(mock) => {
+                mock.set_config(config.clone());
+                Ok(())
+            }
+        }
     }
+
+    /// Where each receiver is mounted relative to the topside
+    pub async fn get_receivers(&self) -> anyhow::Result<Vec<ReceiverConfig>> {
+        match &self.backend {
+            Backend::Http {
+                api_endpoint,
+                client,
+            } => Self::get(api_endpoint, client, "/api/v1/config/receivers").await,
+            Backend::Mock(_) => Ok(MockLocator::RECEIVERS.to_vec()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct About {
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub chipid: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -90,6 +207,35 @@ pub struct Location {
     pub z: f32,
 }
 
+impl Location {
+    /// The per receiver fields zipped together
+    pub fn receivers(&self) -> Vec<ReceiverHealth> {
+        self.receiver_distance
+            .iter()
+            .zip(&self.receiver_nsd)
+            .zip(&self.receiver_rssi)
+            .zip(&self.receiver_valid)
+            .map(|(((distance, nsd), rssi), valid)| ReceiverHealth {
+                distance: *distance,
+                nsd: *nsd,
+                rssi: *rssi,
+                valid: *valid != 0.0,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReceiverHealth {
+    /// Meters to the locator
+    pub distance: f32,
+    /// Noise spectral density, dBm
+    pub nsd: f32,
+    /// Received signal strength, dBm
+    pub rssi: f32,
+    pub valid: bool,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct GpsFix {
     /// Course over ground, degrees (-1 for no data)
@@ -99,17 +245,204 @@ pub struct GpsFix {
     /// Horizontal dilution of precision. -1 means no data.
     pub hdop: f32,
     /// Current Latitude
-    pub lat: f32,
+    pub lat: f64,
     /// Current Longitude
-    pub lon: f32,
+    pub lon: f64,
     /// Number of satellites. -1 means no data.
-    pub numsats: u32,
+    pub numsats: i32,
     /// Current orientation/compass heading (degrees). -1 means no data.
     pub orientation: f32,
     /// Speed over ground (km/h). -1 means no data
     pub sog: f32,
 }
 
+impl GpsFix {
+    pub fn has_fix(&self) -> bool {
+        self.fix_quality > 0.0
+    }
+
+    pub fn heading(&self) -> Option<f32> {
+        (self.orientation >= 0.0).then_some(self.orientation)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct GenericConfig {
+    /// Where the topside heading comes from, `static`, `onboard` or `external`
+    pub compass: String,
+    /// Where the topside position comes from, `static`, `onboard` or `external`
+    pub gps: String,
+    pub static_lat: f64,
+    pub static_lon: f64,
+    /// Degrees clockwise from north
+    pub static_orientation: f32,
+    pub range_min_x: f32,
+    pub range_max_x: f32,
+    pub range_min_y: f32,
+    pub range_max_y: f32,
+    pub range_max_z: f32,
+
+    /// Fields not modeled above, kept so writing the config back doesn't reset them
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct ReceiverConfig {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Simulated locator circling in front of the topside
+struct MockLocator {
+    started: Instant,
+    fixes: AtomicU32,
+    config: Mutex<GenericConfig>,
+}
+
+impl MockLocator {
+    const RECEIVERS: [ReceiverConfig; 4] = [
+        ReceiverConfig {
+            id: 1,
+            x: -1.0,
+            y: -1.0,
+            z: 2.0,
+        },
+        ReceiverConfig {
+            id: 2,
+            x: -1.0,
+            y: 1.0,
+            z: 2.0,
+        },
+        ReceiverConfig {
+            id: 3,
+            x: 1.0,
+            y: 1.0,
+            z: 2.0,
+        },
+        ReceiverConfig {
+            id: 4,
+            x: 1.0,
+            y: -1.0,
+            z: 2.0,
+        },
+    ];
+    /// Seconds per lap of the circle
+    const PERIOD: f64 = 60.0;
+    /// Every this many fixes one is off by several meters, like a multipath spike
+    const SPIKE_INTERVAL: u32 = 40;
+
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            fixes: AtomicU32::new(0),
+            config: Mutex::new(GenericConfig {
+                compass: "static".to_owned(),
+                gps: "static".to_owned(),
+                static_lat: 63.4305,
+                static_lon: 10.3951,
+                static_orientation: 0.0,
+                range_min_x: -50.0,
+                range_max_x: 50.0,
+                range_min_y: -50.0,
+                range_max_y: 50.0,
+                range_max_z: 100.0,
+                other: Default::default(),
+            }),
+        }
+    }
+
+    fn config(&self) -> GenericConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    fn set_config(&self, config: GenericConfig) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    /// Where the locator is, in the waterlinked frame
+    fn true_position(&self) -> (f64, f64, f64) {
+        let time = self.started.elapsed().as_secs_f64();
+        let phase = time / Self::PERIOD * TAU;
+
+        (
+            10.0 + 5.0 * phase.cos(),
+            5.0 * phase.sin(),
+            2.0 + 0.5 * (phase * 2.0).sin(),
+        )
+    }
+
+    fn location(&self, filtered: bool) -> Location {
+        let fix = self.fixes.fetch_add(1, Ordering::Relaxed);
+        let (x, y, z) = self.true_position();
+
+        // Cheap deterministic noise, good enough to exercise the filtering
+        let noise = |seed: f64| (fix as f64 * seed).sin() * if filtered { 0.1 } else { 0.3 };
+        let spike = if fix % Self::SPIKE_INTERVAL == Self::SPIKE_INTERVAL - 1 {
+            6.0
+        } else {
+            0.0
+        };
+        let (x, y, z) = (
+            x + noise(12.9898) + spike,
+            y + noise(78.233),
+            z + noise(37.719),
+        );
+
+        let receivers = Self::RECEIVERS.map(|it| {
+            let (dx, dy, dz) = (x - it.x as f64, y - it.y as f64, z - it.z as f64);
+            (dx * dx + dy * dy + dz * dz).sqrt() as f32
+        });
+
+        Location {
+            position_valid: true,
+            receiver_distance: receivers.to_vec(),
+            receiver_nsd: vec![-60.0; receivers.len()],
+            receiver_rssi: vec![-30.0; receivers.len()],
+            receiver_valid: vec![1.0; receivers.len()],
+            std: if filtered { 0.2 } else { 0.4 },
+            x: x as f32,
+            y: y as f32,
+            z: z as f32,
+        }
+    }
+
+    fn surface_gps(&self) -> GpsFix {
+        let config = self.config();
+
+        GpsFix {
+            cog: -1.0,
+            fix_quality: 1.0,
+            hdop: 0.9,
+            lat: config.static_lat,
+            lon: config.static_lon,
+            numsats: 9,
+            orientation: config.static_orientation,
+            sog: 0.0,
+        }
+    }
+
+    fn locator_gps(&self) -> GpsFix {
+        const METERS_PER_DEGREE: f64 = 111_320.0;
+
+        let surface = self.surface_gps();
+        let (forward, right, _) = self.true_position();
+
+        let heading = (surface.orientation as f64).to_radians();
+        let north = forward * heading.cos() - right * heading.sin();
+        let east = forward * heading.sin() + right * heading.cos();
+
+        GpsFix {
+            lat: surface.lat + north / METERS_PER_DEGREE,
+            lon: surface.lon + east / (METERS_PER_DEGREE * surface.lat.to_radians().cos()),
+            ..surface
+        }
+    }
+}
+
 pub fn wl_to_mate_coords(x: f32, y: f32, z: f32) -> (f32, f32, f32) {
     // WL: +X: Forward, +Y: Right, +Z: Down
     // MATE: +X: Right, +Y: Forwards, +Z: Up